@@ -0,0 +1,919 @@
+//! Read-only queries over a project's parsed modules: where a name is
+//! defined, where a symbol is referred to, what a module declares and
+//! what type a name has.
+//!
+//! An [`Analysis`] holds the project's modules, the modules they are
+//! checked against (the stdlib and FFI stubs) and the function signatures
+//! of the last type check. The language server answers its requests from
+//! one, and tools such as code-mod scripts can [`load`](Analysis::load)
+//! their own. Positions are byte offsets into a module's source, and
+//! answers are [`Location`]s in the same terms.
+//!
+//! Names are found by lexing each module's source, since expressions in
+//! the AST carry no spans. A path is resolved the way the compiler does:
+//! against the current module, its `use` declarations, the package and
+//! the stdlib. Variables are told apart from items by where they are
+//! bound, and are identified by the function they belong to.
+
+use crate::compiler::{
+    Function, Item, Module, ModuleLoader, ModulePath, Pattern, PathPrefix, Stmt, Token, UseTree,
+    check_modules_with_levels, configure_module, format_declaration, format_type,
+    typeck::FnInfo,
+};
+use crate::config::{FeatureSelection, ProjectConfig};
+use logos::Logos;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+
+/// A span of a module's source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub module: String,
+    /// Byte range in the module's source
+    pub span: Range<usize>,
+}
+
+/// What a name refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    /// A module, by its full name
+    Module(String),
+    /// A top-level function, type or trait
+    Item { module: String, name: String },
+    /// A variable, scoped to the function it is bound in
+    Local {
+        module: String,
+        /// Span of the function
+        function: Range<usize>,
+        name: String,
+    },
+}
+
+/// What a [`DocumentSymbol`] declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Struct,
+    Enum,
+    Trait,
+    TypeAlias,
+}
+
+/// A declaration of a module, for outlines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSymbol {
+    /// The item's name, or `Type::method` for methods
+    pub name: String,
+    pub kind: SymbolKind,
+    pub span: Range<usize>,
+}
+
+/// A project's modules and what the type checker knows about them.
+#[derive(Default)]
+pub struct Analysis {
+    package: Option<String>,
+    /// Project modules by name
+    modules: HashMap<String, Module>,
+    /// Modules the project is checked against without being part of it
+    context: Vec<Module>,
+    /// Function signatures from the last check, by `module::name`
+    functions: HashMap<String, FnInfo>,
+}
+
+impl Analysis {
+    pub fn new(context: Vec<Module>) -> Self {
+        Self {
+            context,
+            ..Self::default()
+        }
+    }
+
+    /// Load and type check the project at `root`, against `context`.
+    /// Fails if there is no project there or a module does not parse.
+    pub fn load(root: &Path, context: Vec<Module>) -> Result<Self, String> {
+        let config = ProjectConfig::load(&root.join("dream.toml")).map_err(|e| e.to_string())?;
+        let src_dir = config.src_dir(root);
+        let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+        loader.add_bindings_dir(config.target_layout(root).bindings_dir());
+        loader.load_all_in_dir(&src_dir).map_err(|e| e.to_string())?;
+        let options = config
+            .compile_options(root, &FeatureSelection::default(), "dev", true)
+            .map_err(|e| e.to_string())?;
+
+        let mut analysis = Self::new(context);
+        analysis.set_package(Some(config.package.name.clone()));
+        for module in loader.into_modules() {
+            analysis.insert(module);
+        }
+        let mut modules = analysis.context.clone();
+        modules.extend(analysis.modules.values().cloned().map(|mut module| {
+            let _ = configure_module(&mut module, &options);
+            module
+        }));
+        let levels = config.warning_levels().unwrap_or_default();
+        analysis.set_functions(check_modules_with_levels(&modules, &levels).functions);
+        Ok(analysis)
+    }
+
+    pub fn package(&self) -> Option<&str> {
+        self.package.as_deref()
+    }
+
+    pub fn set_package(&mut self, package: Option<String>) {
+        self.package = package;
+    }
+
+    /// Add a project module, replacing any of the same name.
+    pub fn insert(&mut self, module: Module) {
+        self.modules.insert(module.name.clone(), module);
+    }
+
+    /// Take the function signatures of a type check.
+    pub fn set_functions(&mut self, functions: HashMap<String, FnInfo>) {
+        self.functions = functions;
+    }
+
+    /// The project's modules, in no particular order.
+    pub fn modules(&self) -> impl Iterator<Item = &Module> {
+        self.modules.values()
+    }
+
+    pub fn context(&self) -> &[Module] {
+        &self.context
+    }
+
+    /// A project or context module by its full name.
+    pub fn module(&self, name: &str) -> Option<&Module> {
+        self.modules
+            .get(name)
+            .or_else(|| self.context.iter().find(|m| m.name == name))
+    }
+
+    /// The checked signature of function `name` of `module`.
+    pub fn signature(&self, module: &str, name: &str) -> Option<&FnInfo> {
+        self.functions.get(&format!("{}::{}", module, name))
+    }
+
+    /// What the name at byte `offset` of `module` refers to.
+    pub fn symbol_at(&self, module: &str, offset: usize) -> Option<Symbol> {
+        let scanned = Scanned::new(self.modules.get(module)?)?;
+        let name = scanned
+            .names
+            .iter()
+            .find(|name| name.span.start <= offset && offset <= name.span.end)?;
+        self.classify(&scanned, name)
+    }
+
+    /// Where `symbol` is declared: the item's declaration, the start of
+    /// the module, or the first place the variable is written.
+    pub fn definition(&self, symbol: &Symbol) -> Option<Location> {
+        match symbol {
+            Symbol::Module(name) => Some(Location {
+                module: self.module(name)?.name.clone(),
+                span: 0..0,
+            }),
+            Symbol::Item { module, name } => Some(Location {
+                module: module.clone(),
+                span: item_span(self.module(module)?, name)?,
+            }),
+            Symbol::Local { module, .. } => self
+                .references(symbol)
+                .into_iter()
+                .find(|location| location.module == *module),
+        }
+    }
+
+    /// Every place the project refers to `symbol`, declarations included,
+    /// ordered by module and position. Only the last segment of a path is
+    /// included, so that each location is the name itself.
+    pub fn references(&self, symbol: &Symbol) -> Vec<Location> {
+        let mut modules: Vec<&Module> = match symbol {
+            Symbol::Local { module, .. } => self.modules.get(module).into_iter().collect(),
+            _ => self.modules.values().collect(),
+        };
+        modules.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut locations = Vec::new();
+        for scanned in modules.into_iter().filter_map(Scanned::new) {
+            for name in &scanned.names {
+                if self.classify(&scanned, name).as_ref() == Some(symbol) {
+                    locations.push(Location {
+                        module: scanned.module.name.clone(),
+                        span: name.span.clone(),
+                    });
+                }
+            }
+        }
+        locations
+    }
+
+    /// Whether the name at `location` is a struct field written without
+    /// its value, as `x` in `Point { x }`, where it is both the field and
+    /// the variable.
+    pub fn is_field_shorthand(&self, location: &Location) -> bool {
+        let Some(source) = self.module(&location.module).and_then(|m| m.source.as_deref()) else {
+            return false;
+        };
+        names(source)
+            .iter()
+            .any(|name| name.span == location.span && name.shorthand)
+    }
+
+    /// The functions, types, traits and methods `module` declares, in
+    /// source order.
+    pub fn document_symbols(&self, module: &str) -> Vec<DocumentSymbol> {
+        let Some(module) = self.module(module) else {
+            return Vec::new();
+        };
+        let source = module.source.as_deref().unwrap_or_default();
+        let mut symbols = Vec::new();
+        let mut add = |name: String, kind: SymbolKind, span: Option<Range<usize>>| {
+            if let Some(span) = span {
+                symbols.push(DocumentSymbol { name, kind, span });
+            }
+        };
+        for item in &module.items {
+            match item {
+                Item::Function(f) => add(f.name.clone(), SymbolKind::Function, Some(f.span.clone())),
+                Item::Struct(s) => add(
+                    s.name.clone(),
+                    SymbolKind::Struct,
+                    find_declaration(source, "struct", &s.name),
+                ),
+                Item::Enum(e) => add(
+                    e.name.clone(),
+                    SymbolKind::Enum,
+                    find_declaration(source, "enum", &e.name),
+                ),
+                Item::Trait(t) => add(
+                    t.name.clone(),
+                    SymbolKind::Trait,
+                    find_declaration(source, "trait", &t.name),
+                ),
+                Item::TypeAlias(t) => add(
+                    t.name.clone(),
+                    SymbolKind::TypeAlias,
+                    find_declaration(source, "type", &t.name),
+                ),
+                Item::Impl(block) => {
+                    for method in &block.methods {
+                        add(
+                            format!("{}::{}", block.type_name, method.name),
+                            SymbolKind::Method,
+                            Some(method.span.clone()),
+                        );
+                    }
+                }
+                Item::TraitImpl(block) => {
+                    for method in &block.methods {
+                        add(
+                            format!("{}::{}", block.type_name, method.name),
+                            SymbolKind::Method,
+                            Some(method.span.clone()),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        symbols
+    }
+
+    /// The type of the name at byte `offset` of `module`, as Dream source:
+    /// a function's signature, a type's declaration, `mod name` for a
+    /// module, or `name: type` for a variable whose type is written down.
+    pub fn type_at(&self, module: &str, offset: usize) -> Option<String> {
+        match self.symbol_at(module, offset)? {
+            Symbol::Module(name) => Some(format!("mod {}", name)),
+            Symbol::Item { module, name } => {
+                if let Some(info) = self.signature(&module, &name) {
+                    return Some(info.signature());
+                }
+                self.module(&module)?
+                    .items
+                    .iter()
+                    .find(|item| item_name(item) == Some(&name))
+                    .map(format_declaration)
+            }
+            Symbol::Local {
+                module,
+                function,
+                name,
+            } => {
+                let f = all_functions(self.module(&module)?).find(|f| f.span == function)?;
+                let is_name = |pattern: &Pattern| matches!(pattern, Pattern::Ident(n) if *n == name);
+                let ty = f
+                    .params
+                    .iter()
+                    .find(|p| is_name(&p.pattern))
+                    .map(|p| &p.ty)
+                    .or_else(|| {
+                        f.body.stmts.iter().find_map(|stmt| match stmt {
+                            Stmt::Let {
+                                pattern,
+                                ty: Some(ty),
+                                ..
+                            } if is_name(pattern) => Some(ty),
+                            _ => None,
+                        })
+                    })?;
+                Some(format!("{}: {}", name, format_type(ty)))
+            }
+        }
+    }
+
+    /// What `path`, written in module `current`, refers to.
+    pub fn resolve(&self, current: &str, path: &str) -> Option<Symbol> {
+        self.resolve_in(self.modules.get(current)?, path)
+    }
+
+    /// The module that `path`, written in module `current`, names.
+    pub fn find_module(&self, current: &str, path: &str) -> Option<&Module> {
+        let package = self.package.as_deref();
+        let candidates: Vec<String> = if let Some(rest) = path.strip_prefix("crate::") {
+            package
+                .map(|p| format!("{}::{}", p, rest))
+                .into_iter()
+                .collect()
+        } else if let Some(rest) = path.strip_prefix("self::") {
+            vec![format!("{}::{}", current, rest)]
+        } else if let Some(rest) = path.strip_prefix("super::") {
+            let parent = current
+                .rsplit_once("::")
+                .map_or(current, |(parent, _)| parent);
+            vec![format!("{}::{}", parent, rest)]
+        } else {
+            let mut candidates = vec![format!("{}::{}", current, path)];
+            candidates.extend(package.map(|p| format!("{}::{}", p, path)));
+            candidates.push(path.to_string());
+            candidates.push(format!("dream::{}", path));
+            candidates
+        };
+        candidates.iter().find_map(|name| self.module(name))
+    }
+
+    fn resolve_in(&self, current: &Module, path: &str) -> Option<Symbol> {
+        let item = |module: &Module, name: &str| Symbol::Item {
+            module: module.name.clone(),
+            name: name.to_string(),
+        };
+        if path.is_empty() {
+            return None;
+        }
+        if let Some((prefix, name)) = path.rsplit_once("::") {
+            if let Some(module) = self.find_module(&current.name, prefix) {
+                if item_span(module, name).is_some() {
+                    return Some(item(module, name));
+                }
+            }
+            return self
+                .find_module(&current.name, path)
+                .map(|module| Symbol::Module(module.name.clone()));
+        }
+
+        if item_span(current, path).is_some() {
+            return Some(item(current, path));
+        }
+        if let Some((module, name)) = self.imported(current, path) {
+            return Some(item(module, &name));
+        }
+        if let Some(module) = self.find_module(&current.name, path) {
+            return Some(Symbol::Module(module.name.clone()));
+        }
+        // A type brought in some other way, such as a re-export
+        if !path.starts_with(char::is_uppercase) {
+            return None;
+        }
+        let mut candidates: Vec<&Module> = self
+            .modules
+            .values()
+            .filter(|module| item_span(module, path).is_some())
+            .collect();
+        candidates.sort_by(|a, b| a.name.cmp(&b.name));
+        candidates.first().map(|module| item(module, path))
+    }
+
+    /// The item a `use` declaration of `current` brings in as `name`.
+    fn imported(&self, current: &Module, name: &str) -> Option<(&Module, String)> {
+        let lookup = |module: &ModulePath, item: &str| {
+            let module = self.find_module(&current.name, &module_path(module))?;
+            item_span(module, item).map(|_| (module, item.to_string()))
+        };
+        current.items.iter().find_map(|item| {
+            let Item::Use(decl) = item else {
+                return None;
+            };
+            match &decl.tree {
+                UseTree::Path {
+                    module,
+                    name: item,
+                    rename,
+                } if rename.as_deref().unwrap_or(item) == name => lookup(module, item),
+                UseTree::Group { module, items } => items
+                    .iter()
+                    .find(|i| i.rename.as_deref().unwrap_or(&i.name) == name)
+                    .and_then(|i| lookup(module, &i.name)),
+                UseTree::Glob { module } => lookup(module, name),
+                _ => None,
+            }
+        })
+    }
+
+    /// What `name`, written in the scanned module, refers to.
+    fn classify(&self, scanned: &Scanned, name: &Name) -> Option<Symbol> {
+        if name.role == Role::Member {
+            return None;
+        }
+        let module = scanned.module;
+        let function = scanned
+            .functions
+            .iter()
+            .find(|(span, _)| span.contains(&name.span.start));
+        if let Some((span, bound)) = function {
+            let variable = !name.prefix
+                && !name.in_type
+                && !name.path.contains("::")
+                && name.path.starts_with(|c: char| c.is_lowercase() || c == '_');
+            // A name nothing declares is bound by a pattern, unless it is
+            // called, which makes it a builtin
+            if variable
+                && (name.role == Role::Binding
+                    || bound.contains(&name.path)
+                    || (!name.call && self.resolve_in(module, &name.path).is_none()))
+            {
+                return Some(Symbol::Local {
+                    module: module.name.clone(),
+                    function: span.clone(),
+                    name: name.path.clone(),
+                });
+            }
+        }
+        if name.role == Role::Binding {
+            return None;
+        }
+        self.resolve_in(module, &name.path)
+    }
+}
+
+/// How a name is used where it is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    /// A path to a module, item or variable
+    Path,
+    /// A variable bound by `let`, a parameter or a closure parameter, or
+    /// an `as` alias
+    Binding,
+    /// A field or method after `.`, or a field name in a struct
+    Member,
+}
+
+/// A name written in a module's source.
+#[derive(Debug, Clone)]
+struct Name {
+    /// The path up to and including this segment, e.g. `io::print`
+    path: String,
+    /// Span of the segment
+    span: Range<usize>,
+    /// More segments follow
+    prefix: bool,
+    role: Role,
+    /// Followed by `(`
+    call: bool,
+    /// After `->`, or after `:` following a variable, where only types are
+    /// written
+    in_type: bool,
+    /// A field of a struct literal or pattern that is also its value
+    shorthand: bool,
+}
+
+/// The names of one module and the variables each of its functions binds.
+struct Scanned<'a> {
+    module: &'a Module,
+    names: Vec<Name>,
+    functions: Vec<(Range<usize>, HashSet<String>)>,
+}
+
+impl<'a> Scanned<'a> {
+    fn new(module: &'a Module) -> Option<Self> {
+        let names = names(module.source.as_deref()?);
+        let functions = all_functions(module)
+            .map(|f| {
+                let bound = names
+                    .iter()
+                    .filter(|n| n.role == Role::Binding && f.span.contains(&n.span.start))
+                    .map(|n| n.path.clone())
+                    .collect();
+                (f.span.clone(), bound)
+            })
+            .collect();
+        Some(Self {
+            module,
+            names,
+            functions,
+        })
+    }
+}
+
+/// Every name written in `source`, including inside string
+/// interpolations, in order.
+fn names(source: &str) -> Vec<Name> {
+    let mut names = Vec::new();
+    scan(source, 0, &mut names);
+    names
+}
+
+fn scan(source: &str, base: usize, names: &mut Vec<Name>) {
+    let tokens: Vec<(Token, Range<usize>)> = Token::lexer(source)
+        .spanned()
+        .filter_map(|(token, span)| Some((token.ok()?, base + span.start..base + span.end)))
+        .collect();
+    // Open brackets, each `{` with the prefix of the `use` group it opens
+    // and whether it follows a type name, opening a struct's fields
+    let mut brackets: Vec<(char, Option<String>, bool)> = Vec::new();
+    let mut path = String::new();
+    let mut in_closure = false;
+    for (i, (token, span)) in tokens.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|i| &tokens[i].0);
+        let next = tokens.get(i + 1).map(|(token, _)| token);
+        match token {
+            Token::String(raw) => {
+                for inner in interpolations(raw) {
+                    scan(&raw[inner.clone()], span.start + 1 + inner.start, names);
+                }
+            }
+            Token::LBrace => {
+                let group = (prev == Some(&Token::ColonColon)).then(|| path.clone());
+                brackets.push(('{', group, matches!(prev, Some(Token::TypeIdent(_)))));
+            }
+            Token::LParen => brackets.push(('(', None, false)),
+            Token::LBracket | Token::HashBracket => brackets.push(('[', None, false)),
+            Token::RBrace | Token::RParen | Token::RBracket => {
+                brackets.pop();
+            }
+            Token::Pipe if in_closure => in_closure = false,
+            Token::Pipe => {
+                in_closure = matches!(
+                    prev,
+                    None | Some(
+                        Token::LParen
+                            | Token::Comma
+                            | Token::Eq
+                            | Token::LBrace
+                            | Token::Semi
+                            | Token::FatArrow
+                            | Token::Return
+                    )
+                )
+            }
+            _ => {}
+        }
+        let Some(text) = segment(token) else {
+            continue;
+        };
+
+        let continues = prev == Some(&Token::ColonColon)
+            && i >= 2
+            && segment(&tokens[i - 2].0).is_some();
+        if continues {
+            path.push_str("::");
+        } else {
+            path = match (prev, brackets.last()) {
+                (Some(Token::LBrace | Token::Comma), Some(('{', Some(group), _))) => {
+                    format!("{}::", group)
+                }
+                _ => String::new(),
+            };
+        }
+        path.push_str(text);
+
+        let innermost = brackets.last().map(|(bracket, _, _)| *bracket);
+        let in_fields = matches!(brackets.last(), Some(('{', None, true)));
+        let role = if prev == Some(&Token::Dot) {
+            Role::Member
+        } else if matches!(prev, Some(Token::Let | Token::Mut | Token::As))
+            || (in_closure && matches!(prev, Some(Token::Pipe | Token::Comma)))
+        {
+            Role::Binding
+        } else if next == Some(&Token::Colon) && matches!(prev, Some(Token::LParen | Token::LBrace | Token::Comma)) {
+            match innermost {
+                Some('{') => Role::Member,
+                Some('(') => Role::Binding,
+                _ => Role::Path,
+            }
+        } else {
+            Role::Path
+        };
+        names.push(Name {
+            path: path.clone(),
+            span: span.clone(),
+            prefix: next == Some(&Token::ColonColon),
+            role,
+            call: next == Some(&Token::LParen),
+            shorthand: in_fields
+                && role == Role::Path
+                && matches!(prev, Some(Token::LBrace | Token::Comma))
+                && matches!(next, Some(Token::Comma | Token::RBrace)),
+            in_type: match prev {
+                Some(Token::Arrow) => true,
+                Some(Token::Colon) => names.last().is_some_and(|n| n.role != Role::Member),
+                _ => false,
+            },
+        });
+    }
+}
+
+/// A token that can be a segment of a path, as written.
+fn segment(token: &Token) -> Option<&str> {
+    match token {
+        Token::Ident(name) | Token::TypeIdent(name) => Some(name),
+        Token::SelfKw => Some("self"),
+        Token::Crate => Some("crate"),
+        Token::Super => Some("super"),
+        _ => None,
+    }
+}
+
+/// Byte ranges of the `{...}` interpolations in the raw text of a string
+/// literal, without the braces.
+fn interpolations(raw: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut chars = raw.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '{' if chars.peek().is_some_and(|&(_, c)| c == '{') => {
+                chars.next();
+            }
+            '{' => {
+                let mut depth = 1;
+                for (j, c) in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        ranges.push(i + 1..j);
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+fn module_path(path: &ModulePath) -> String {
+    let prefix = match path.prefix {
+        PathPrefix::Crate => "crate::",
+        PathPrefix::Super => "super::",
+        PathPrefix::SelfMod => "self::",
+        PathPrefix::None => "",
+    };
+    format!("{}{}", prefix, path.segments.join("::"))
+}
+
+/// The top-level functions of `module`.
+pub(crate) fn functions(module: &Module) -> impl Iterator<Item = &Function> {
+    module.items.iter().filter_map(|item| match item {
+        Item::Function(f) => Some(f),
+        _ => None,
+    })
+}
+
+/// The functions and methods of `module`.
+fn all_functions(module: &Module) -> impl Iterator<Item = &Function> {
+    module.items.iter().flat_map(|item| match item {
+        Item::Function(f) => std::slice::from_ref(f),
+        Item::Impl(block) => block.methods.as_slice(),
+        Item::TraitImpl(block) => block.methods.as_slice(),
+        _ => [].as_slice(),
+    })
+}
+
+fn item_name(item: &Item) -> Option<&String> {
+    match item {
+        Item::Function(f) => Some(&f.name),
+        Item::Struct(s) => Some(&s.name),
+        Item::Enum(e) => Some(&e.name),
+        Item::Trait(t) => Some(&t.name),
+        Item::TypeAlias(t) => Some(&t.name),
+        _ => None,
+    }
+}
+
+/// Where item `name` of `module` is declared. Only functions carry spans,
+/// so other items are found in the module's source.
+fn item_span(module: &Module, name: &str) -> Option<Range<usize>> {
+    if let Some(f) = functions(module).find(|f| f.name == name) {
+        return Some(f.span.clone());
+    }
+    let source = module.source.as_deref()?;
+    module.items.iter().find_map(|item| match item {
+        Item::Struct(s) if s.name == name => find_declaration(source, "struct", name),
+        Item::Enum(e) if e.name == name => find_declaration(source, "enum", name),
+        Item::Trait(t) if t.name == name => find_declaration(source, "trait", name),
+        Item::TypeAlias(t) if t.name == name => find_declaration(source, "type", name),
+        _ => None,
+    })
+}
+
+/// The span of `<keyword> <name>` in `source`, as a whole word.
+pub fn find_declaration(source: &str, keyword: &str, name: &str) -> Option<Range<usize>> {
+    find_word(source, 0..source.len(), &format!("{} {}", keyword, name))
+}
+
+/// The first whole-word occurrence of `word` within `span` of `source`.
+pub fn find_word(source: &str, span: Range<usize>, word: &str) -> Option<Range<usize>> {
+    let text = source.get(span.clone())?;
+    text.match_indices(word)
+        .find(|&(at, _)| {
+            let before = text[..at].chars().next_back();
+            let after = text[at + word.len()..].chars().next();
+            !before.is_some_and(is_ident_char) && !after.is_some_and(is_ident_char)
+        })
+        .map(|(at, _)| span.start + at..span.start + at + word.len())
+}
+
+pub(crate) fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The path (`a::b::c`) around byte `offset` of `text`, up to the end of
+/// the segment the offset is in.
+pub fn path_at(text: &str, offset: usize) -> &str {
+    let is_path_char = |c: char| is_ident_char(c) || c == ':';
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| !is_path_char(c))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let end = text[offset..]
+        .char_indices()
+        .find(|&(_, c)| !is_ident_char(c))
+        .map_or(text.len(), |(i, _)| offset + i);
+    text[start..end].trim_matches(':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Parser;
+
+    const SHAPES: &str = "use app::util::{double as twice};\n\npub struct Point {\n    x: int,\n}\n\npub fn area(w: int, h: int) -> int {\n    let size: int = w * h;\n    twice(size)\n}\n\nfn origin() -> Point {\n    let x = 0;\n    Point { x: x }\n}\n\nfn label(p: Point) -> string {\n    let total = area(p.x, 1);\n    \"{total} at {p.x}\"\n}\n";
+    const UTIL: &str = "pub fn double(n: int) -> int {\n    n * 2\n}\n\nfn quadruple(n: int) -> int {\n    util::double(double(n))\n}\n";
+
+    fn analysis() -> Analysis {
+        let mut analysis = Analysis::new(Vec::new());
+        analysis.set_package(Some("app".to_string()));
+        analysis.insert(Parser::new(SHAPES).parse_file("app::shapes").unwrap());
+        analysis.insert(Parser::new(UTIL).parse_file("app::util").unwrap());
+        analysis
+    }
+
+    fn offset(source: &str, needle: &str, nth: usize) -> usize {
+        source.match_indices(needle).nth(nth).unwrap().0
+    }
+
+    fn texts<'a>(analysis: &'a Analysis, locations: &[Location]) -> Vec<(&'a str, &'a str)> {
+        locations
+            .iter()
+            .map(|l| {
+                let module = analysis.module(&l.module).unwrap();
+                (module.name.as_str(), &module.source.as_deref().unwrap()[l.span.clone()])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_paths() {
+        assert_eq!(path_at("x = io::print(y)", 9), "io::print");
+        assert_eq!(path_at("x = io::print(y)", 5), "io");
+        assert_eq!(interpolations("a {x} {{b}} {f({y})}"), [3..4, 13..19]);
+
+        let names = names("use a::{b, c as d};\nfn f(x: int) { x.y + a::b(\"{x}\") }");
+        let paths: Vec<(&str, Role)> = names.iter().map(|n| (n.path.as_str(), n.role)).collect();
+        assert!(names.iter().all(|n| !n.shorthand));
+        assert_eq!(
+            paths,
+            [
+                ("a", Role::Path),
+                ("a::b", Role::Path),
+                ("a::c", Role::Path),
+                ("d", Role::Binding),
+                ("f", Role::Path),
+                ("x", Role::Binding),
+                ("int", Role::Path),
+                ("x", Role::Path),
+                ("y", Role::Member),
+                ("a", Role::Path),
+                ("a::b", Role::Path),
+                ("x", Role::Path),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_definition() {
+        let analysis = analysis();
+        let at = |needle, nth| analysis.symbol_at("app::shapes", offset(SHAPES, needle, nth));
+
+        let symbol = at("area", 1).unwrap();
+        assert_eq!(
+            symbol,
+            Symbol::Item {
+                module: "app::shapes".to_string(),
+                name: "area".to_string()
+            }
+        );
+        let definition = analysis.definition(&symbol).unwrap();
+        assert_eq!(definition.span.start, offset(SHAPES, "fn area", 0));
+
+        // Through a renaming `use`
+        let symbol = at("twice", 1).unwrap();
+        let definition = analysis.definition(&symbol).unwrap();
+        assert_eq!(definition.module, "app::util");
+        assert_eq!(definition.span.start, offset(UTIL, "fn double", 0));
+
+        let symbol = at("Point", 2).unwrap();
+        let definition = analysis.definition(&symbol).unwrap();
+        assert_eq!(&SHAPES[definition.span], "struct Point");
+
+        // A field is not a variable
+        assert_eq!(at("x:", 1), None);
+        let symbol = at("x }", 0).unwrap();
+        assert!(matches!(symbol, Symbol::Local { ref name, .. } if name == "x"));
+        let definition = analysis.definition(&symbol).unwrap();
+        assert_eq!(definition.span.start, offset(SHAPES, "x = 0", 0));
+        assert_eq!(at("x,", 0), None);
+    }
+
+    #[test]
+    fn test_references() {
+        let analysis = analysis();
+        let double = Symbol::Item {
+            module: "app::util".to_string(),
+            name: "double".to_string(),
+        };
+        let references = analysis.references(&double);
+        assert_eq!(
+            texts(&analysis, &references),
+            [
+                ("app::shapes", "double"),
+                ("app::shapes", "twice"),
+                ("app::util", "double"),
+                ("app::util", "double"),
+                ("app::util", "double"),
+            ]
+        );
+
+        // Variables are scoped to their function, and seen in strings
+        let total = analysis
+            .symbol_at("app::shapes", offset(SHAPES, "total", 0))
+            .unwrap();
+        let references = analysis.references(&total);
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[1].span.start, offset(SHAPES, "total}", 0));
+
+        let n = analysis
+            .symbol_at("app::util", offset(UTIL, "n * 2", 0))
+            .unwrap();
+        assert_eq!(analysis.references(&n).len(), 2);
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let analysis = analysis();
+        let symbols: Vec<(String, SymbolKind)> = analysis
+            .document_symbols("app::shapes")
+            .into_iter()
+            .map(|s| (s.name, s.kind))
+            .collect();
+        assert_eq!(
+            symbols,
+            [
+                ("Point".to_string(), SymbolKind::Struct),
+                ("area".to_string(), SymbolKind::Function),
+                ("origin".to_string(), SymbolKind::Function),
+                ("label".to_string(), SymbolKind::Function),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_at() {
+        let analysis = analysis();
+        let at = |needle, nth| analysis.type_at("app::shapes", offset(SHAPES, needle, nth));
+        assert_eq!(at("area", 1).as_deref(), Some("pub fn area(w: int, h: int) -> int"));
+        assert_eq!(at("size)", 0).as_deref(), Some("size: int"));
+        assert_eq!(at("p: Point", 0).as_deref(), Some("p: Point"));
+        assert_eq!(at("Point {", 1).as_deref(), Some("pub struct Point {\n    x: int,\n}"));
+        assert_eq!(at("util", 0).as_deref(), Some("mod app::util"));
+        assert_eq!(at("x = 0", 0), None);
+    }
+}
@@ -0,0 +1,286 @@
+//! Benchmarks: `#[bench]` functions run by `dream bench`.
+//!
+//! A benchmark is a public zero-argument function, found the same way as
+//! tests are. Each one runs alone in a fresh process: first a warmup that
+//! keeps doubling the iteration count until the warmup time is used up,
+//! which also estimates the cost of one call; then a fixed number of
+//! samples, each timing enough back-to-back calls to fill the sample time.
+//! The runner prints the raw sample times and [`BenchStats`] turns them
+//! into per-call figures.
+
+use crate::compiler::{cfg, Module};
+use crate::config::CompileOptions;
+use crate::testing::{self, TestCase};
+use std::time::Duration;
+
+/// Prefix of the bench runner's protocol lines.
+const MARKER: &str = "##dream-bench##";
+
+/// Find the `#[bench]` functions in `modules`, sorted by name.
+pub fn discover(modules: &[Module], options: &CompileOptions) -> Vec<TestCase> {
+    testing::discover_marked(modules, options, cfg::is_bench)
+}
+
+/// How long and how often each benchmark is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchOptions {
+    /// Time spent calling the function before measuring
+    pub warmup: Duration,
+    /// Number of timed samples
+    pub samples: usize,
+    /// Roughly how long each sample runs
+    pub sample_time: Duration,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            warmup: Duration::from_millis(500),
+            samples: 100,
+            sample_time: Duration::from_millis(10),
+        }
+    }
+}
+
+/// Erlang expression that runs `benches` one after another and halts.
+///
+/// Each result is one line: `<marker> ok <index> <iterations> [<ns>,...]`
+/// with the total time of every sample, or `<marker> failed <index> <message>`
+/// with the message hex-encoded.
+pub fn runner_expr(benches: &[TestCase], options: &BenchOptions) -> String {
+    let benches: Vec<String> = benches
+        .iter()
+        .enumerate()
+        .map(|(i, b)| format!("{{{}, '{}', '{}'}}", i, b.beam_module(), b.function))
+        .collect();
+
+    format!(
+        "Benches = [{benches}], \
+         WarmupNs = {warmup}, \
+         Samples = {samples}, \
+         SampleNs = {sample}, \
+         Repeat = fun Rep(_, _, 0) -> ok; Rep(M, F, K) -> M:F(), Rep(M, F, K - 1) end, \
+         Time = fun(M, F, Iters) -> \
+             T0 = erlang:monotonic_time(nanosecond), \
+             Repeat(M, F, Iters), \
+             erlang:monotonic_time(nanosecond) - T0 \
+         end, \
+         Measure = fun(M, F) -> \
+             Warm = fun W(Iters, Spent) -> \
+                 T = Time(M, F, Iters), \
+                 case Spent + T >= WarmupNs of \
+                     true -> max(1, T div Iters); \
+                     false -> W(Iters * 2, Spent + T) \
+                 end \
+             end, \
+             Iters = max(1, SampleNs div Warm(1, 0)), \
+             {{ok, Iters, [Time(M, F, Iters) || _ <- lists:seq(1, Samples)]}} \
+         end, \
+         lists:foreach(fun({{I, M, F}}) -> \
+             {{_, Ref}} = spawn_monitor(fun() -> \
+                 exit(try Measure(M, F) \
+                 catch Class:Reason:Stack -> \
+                     {{failed, unicode:characters_to_binary(io_lib:format(\"~p: ~tp~n~tp\", [Class, Reason, Stack]))}} \
+                 end) \
+             end), \
+             receive \
+                 {{'DOWN', Ref, process, _, {{ok, Iters, Times}}}} -> \
+                     io:format(\"{marker} ok ~b ~b ~w~n\", [I, Iters, Times]); \
+                 {{'DOWN', Ref, process, _, {{failed, Msg}}}} -> \
+                     io:format(\"{marker} failed ~b ~s~n\", [I, binary:encode_hex(Msg)]); \
+                 {{'DOWN', Ref, process, _, Other}} -> \
+                     io:format(\"{marker} failed ~b ~s~n\", \
+                         [I, binary:encode_hex(unicode:characters_to_binary(io_lib:format(\"exited: ~tp\", [Other])))]) \
+             end \
+         end, Benches), \
+         halt(0).",
+        benches = benches.join(", "),
+        warmup = options.warmup.as_nanos(),
+        samples = options.samples.max(1),
+        sample = options.sample_time.as_nanos().max(1),
+        marker = MARKER,
+    )
+}
+
+/// Per-call timings of one benchmark, in nanoseconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchStats {
+    /// Calls per sample
+    pub iterations: u64,
+    pub samples: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// 99th percentile (nearest rank)
+    pub p99: f64,
+    pub min: f64,
+    pub max: f64,
+    pub std_dev: f64,
+}
+
+impl BenchStats {
+    /// Compute statistics from the total time of each sample, each covering
+    /// `iterations` calls. Returns `None` without samples.
+    pub fn from_samples(iterations: u64, sample_ns: &[u64]) -> Option<Self> {
+        if sample_ns.is_empty() {
+            return None;
+        }
+        let iterations = iterations.max(1);
+        let mut per_call: Vec<f64> = sample_ns
+            .iter()
+            .map(|&ns| ns as f64 / iterations as f64)
+            .collect();
+        per_call.sort_by(f64::total_cmp);
+
+        let n = per_call.len();
+        let mean = per_call.iter().sum::<f64>() / n as f64;
+        let median = if n % 2 == 0 {
+            (per_call[n / 2 - 1] + per_call[n / 2]) / 2.0
+        } else {
+            per_call[n / 2]
+        };
+        let rank = (n as f64 * 0.99).ceil() as usize;
+        let variance = per_call.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64;
+
+        Some(Self {
+            iterations,
+            samples: n,
+            mean,
+            median,
+            p99: per_call[rank.clamp(1, n) - 1],
+            min: per_call[0],
+            max: per_call[n - 1],
+            std_dev: variance.sqrt(),
+        })
+    }
+}
+
+/// Something the bench runner reported.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BenchEvent {
+    Measured { bench: TestCase, stats: BenchStats },
+    Failed { bench: TestCase, message: String },
+    /// A line printed outside any benchmark.
+    Output(String),
+}
+
+/// Turn one line of runner output into an event. `benches` is the list
+/// passed to [`runner_expr`].
+pub fn parse_line(benches: &[TestCase], line: &str) -> Option<BenchEvent> {
+    let Some(result) = line.strip_prefix(MARKER) else {
+        return Some(BenchEvent::Output(line.to_string()));
+    };
+
+    let mut fields = result.split(' ').skip(1);
+    let status = fields.next()?;
+    let bench = benches.get(fields.next()?.parse::<usize>().ok()?)?.clone();
+    match status {
+        "ok" => {
+            let iterations = fields.next()?.parse().ok()?;
+            let times = fields.next()?.trim_start_matches('[').trim_end_matches(']');
+            let sample_ns: Vec<u64> = times
+                .split(',')
+                .filter(|t| !t.is_empty())
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .ok()?;
+            Some(BenchEvent::Measured {
+                bench,
+                stats: BenchStats::from_samples(iterations, &sample_ns)?,
+            })
+        }
+        _ => Some(BenchEvent::Failed {
+            bench,
+            message: testing::decode(fields.next().unwrap_or("")),
+        }),
+    }
+}
+
+/// A nanosecond figure with a readable unit, e.g. `1.52 µs`.
+pub fn format_ns(ns: f64) -> String {
+    if ns < 1_000.0 {
+        format!("{:.1} ns", ns)
+    } else if ns < 1_000_000.0 {
+        format!("{:.2} µs", ns / 1_000.0)
+    } else if ns < 1_000_000_000.0 {
+        format!("{:.2} ms", ns / 1_000_000.0)
+    } else {
+        format!("{:.2} s", ns / 1_000_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Parser;
+
+    #[test]
+    fn test_discover() {
+        let module = Parser::new(
+            "#[bench]\npub fn bench_add() -> int { 1 + 1 }\n\n#[test]\npub fn test_add() -> atom { :ok }\n",
+        )
+        .parse_file("app::benches")
+        .unwrap();
+        let benches = discover(&[module], &CompileOptions::for_testing());
+        assert_eq!(benches, vec![TestCase::new("app::benches", "bench_add")]);
+    }
+
+    #[test]
+    fn test_runner_expr() {
+        let benches = [TestCase::new("app::benches", "bench_add")];
+        let expr = runner_expr(&benches, &BenchOptions::default());
+        assert!(expr.starts_with(
+            "Benches = [{0, 'dream::app::benches', 'bench_add'}], WarmupNs = 500000000, Samples = 100, SampleNs = 10000000,"
+        ));
+        assert!(expr.ends_with("halt(0)."));
+        assert!(!expr.contains('%'), "the expression is passed on one line");
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats = BenchStats::from_samples(10, &[400, 100, 200, 300]).unwrap();
+        assert_eq!(stats.samples, 4);
+        assert_eq!(stats.mean, 25.0);
+        assert_eq!(stats.median, 25.0);
+        assert_eq!(stats.p99, 40.0);
+        assert_eq!((stats.min, stats.max), (10.0, 40.0));
+        assert!((stats.std_dev - 11.18).abs() < 0.01);
+
+        let samples: Vec<u64> = (1..=200).collect();
+        let stats = BenchStats::from_samples(1, &samples).unwrap();
+        assert_eq!(stats.median, 100.5);
+        assert_eq!(stats.p99, 198.0);
+
+        assert_eq!(BenchStats::from_samples(1, &[]), None);
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let benches = vec![TestCase::new("app::benches", "bench_a"), TestCase::new("app::benches", "bench_b")];
+
+        let event = parse_line(&benches, "##dream-bench## ok 1 1000 [2000,4000,3000]").unwrap();
+        let BenchEvent::Measured { bench, stats } = event else {
+            panic!("expected a measurement");
+        };
+        assert_eq!(bench.function, "bench_b");
+        assert_eq!((stats.iterations, stats.median), (1000, 3.0));
+
+        let message = testing::encode("error: badarith");
+        assert_eq!(
+            parse_line(&benches, &format!("##dream-bench## failed 0 {}", message)),
+            Some(BenchEvent::Failed {
+                bench: benches[0].clone(),
+                message: "error: badarith".to_string(),
+            })
+        );
+        assert_eq!(parse_line(&benches, "hello"), Some(BenchEvent::Output("hello".to_string())));
+        assert_eq!(parse_line(&benches, "##dream-bench## ok 7 1 [1]"), None);
+    }
+
+    #[test]
+    fn test_format_ns() {
+        assert_eq!(format_ns(12.345), "12.3 ns");
+        assert_eq!(format_ns(1_520.0), "1.52 µs");
+        assert_eq!(format_ns(2_500_000.0), "2.50 ms");
+        assert_eq!(format_ns(3e9), "3.00 s");
+    }
+}
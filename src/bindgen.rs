@@ -3,18 +3,43 @@
 //! Parses Erlang -spec/-type and Elixir @spec/@type declarations and converts them
 //! to Dream extern mod syntax. Detects Result/Option patterns and generates
 //! appropriate Dream types.
+//!
+//! Erlang `-record` definitions become `#[record]` structs, with a function
+//! reading each field by its tuple position, so records returned by OTP
+//! (`#file_info{}` from `file:read_file_info/1`, say) can be used with types.
 
 use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
-/// Main entry point for the bindgen command.
-pub fn cmd_bindgen(files: &[std::path::PathBuf], output: Option<&Path>, _module: Option<&str>) -> ExitCode {
+use crate::toolchain;
+
+/// Main entry point for the bindgen command. `include_libs` are headers
+/// named like `-include_lib` does (`kernel/include/file.hrl`); with
+/// `records_only`, only records are generated.
+pub fn cmd_bindgen(
+    files: &[PathBuf],
+    include_libs: &[String],
+    records_only: bool,
+    output: Option<&Path>,
+    _module: Option<&str>,
+) -> ExitCode {
     let mut all_modules: HashMap<String, ModuleInfo> = HashMap::new();
 
-    for file in files {
+    let mut files = files.to_vec();
+    for include in include_libs {
+        match resolve_include_lib(include) {
+            Ok(path) => files.push(path),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(1);
+            }
+        }
+    }
+
+    for file in &files {
         if !file.exists() {
             eprintln!("Error: file not found: {}", file.display());
             return ExitCode::from(1);
@@ -107,6 +132,14 @@ pub fn cmd_bindgen(files: &[std::path::PathBuf], output: Option<&Path>, _module:
         entry.structs.extend(structs);
     }
 
+    if records_only {
+        for info in all_modules.values_mut() {
+            info.specs.clear();
+            info.type_defs.clear();
+            info.structs.clear();
+        }
+    }
+
     // Generate output
     let output_content = generate_dreamt(&all_modules);
 
@@ -128,6 +161,34 @@ pub fn cmd_bindgen(files: &[std::path::PathBuf], output: Option<&Path>, _module:
     }
 }
 
+/// Find the header `-include_lib("app/include/name.hrl")` would: under the
+/// directory of the OTP application `app`, as `erl` reports it.
+fn resolve_include_lib(path: &str) -> Result<PathBuf, String> {
+    let (app, rest) = path
+        .split_once('/')
+        .ok_or_else(|| format!("`{}` should start with an application name, like `kernel/include/file.hrl`", path))?;
+    if app.is_empty() || !app.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("`{}` is not an OTP application name", app));
+    }
+    let eval = format!(
+        "case code:lib_dir('{}') of Dir when is_list(Dir) -> io:put_chars(Dir); _ -> ok end, halt().",
+        app
+    );
+    let output = toolchain::erl()
+        .args(["-noshell", "-eval", &eval])
+        .output()
+        .map_err(|e| format!("failed to run erl to find `{}`: {}", app, e))?;
+    let dir = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if dir.is_empty() {
+        return Err(format!("OTP application `{}` not found", app));
+    }
+    let header = Path::new(&dir).join(rest);
+    if !header.exists() {
+        return Err(format!("file not found: {}", header.display()));
+    }
+    Ok(header)
+}
+
 /// Module information including specs, type definitions, records, and structs.
 struct ModuleInfo {
     specs: Vec<ErlangSpec>,
@@ -2131,6 +2192,7 @@ fn generate_dreamt(modules: &HashMap<String, ModuleInfo>) -> String {
             }
 
             output.push_str("}\n\n");
+            output.push_str(&record_accessors(record, &type_name));
             generated_types.insert(type_name);
         }
 
@@ -2224,6 +2286,29 @@ fn generate_dreamt(modules: &HashMap<String, ModuleInfo>) -> String {
     output
 }
 
+/// A function reading each field of `record`, a `type_name` struct, from
+/// its tuple position. They work on records from Erlang code, which field
+/// access on a struct only does for records built in the same function.
+fn record_accessors(record: &ErlangRecord, type_name: &str) -> String {
+    let mut output = String::new();
+    for (index, (field_name, field_type)) in record.fields.iter().enumerate() {
+        // Element 1 is the record name
+        let position = index + 2;
+        output.push_str(&format!(
+            "/// The `{}` field of `#{}{{}}`, element {} of the tuple.\n",
+            field_name, record.name, position
+        ));
+        output.push_str(&format!(
+            "pub fn {}(record: {}) -> {} {{\n    :erlang::element({}, record)\n}}\n\n",
+            sanitize_identifier(&format!("{}_{}", record.name, field_name)),
+            type_name,
+            erlang_type_to_dream(field_type),
+            position
+        ));
+    }
+    output
+}
+
 /// Convert Erlang type to Dream type syntax.
 ///
 /// Type naming convention:
@@ -2769,6 +2854,10 @@ mod tests {
             "Expected name field, got:\n{}", output);
         assert!(output.contains("age: int,"),
             "Expected age field (int stays lowercase), got:\n{}", output);
+        assert!(output.contains("pub fn person_name(record: Person) -> String {\n    :erlang::element(2, record)\n}"),
+            "Expected accessor for name at element 2, got:\n{}", output);
+        assert!(output.contains("pub fn person_age(record: Person) -> int {\n    :erlang::element(3, record)\n}"),
+            "Expected accessor for age at element 3, got:\n{}", output);
     }
 
     #[test]
@@ -0,0 +1,134 @@
+//! The BEAM code path of the programs Dream starts.
+//!
+//! Every command that runs Dream code on the BEAM (`run`, `shell`, `test`,
+//! `debug`, `profile`, `bench`) gives `erl` the same directories, in the
+//! same order:
+//!
+//! 1. the program's own compiled modules;
+//! 2. the compiled stdlib;
+//! 3. the ebin directories of its dependencies;
+//! 4. the directories listed in `DREAM_PATH`, separated as in `PATH`.
+//!
+//! A [`CodePath`] collects them and turns them into `-pa` arguments. A
+//! directory listed twice keeps its first place, so a module in the
+//! project is never shadowed by a copy further down.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The environment variable listing extra directories for the code path.
+pub const DREAM_PATH: &str = "DREAM_PATH";
+
+/// Directories to add to the BEAM code path, in order of precedence.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodePath {
+    dirs: Vec<PathBuf>,
+}
+
+impl CodePath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The code path of a program compiled to `beam_dir`: its modules, the
+    /// stdlib, its dependencies, then `DREAM_PATH`.
+    pub fn project<P: AsRef<Path>>(
+        beam_dir: &Path,
+        stdlib: Option<&Path>,
+        deps: impl IntoIterator<Item = P>,
+    ) -> Self {
+        let mut code_path = Self::new();
+        code_path.push(beam_dir);
+        if let Some(stdlib) = stdlib {
+            code_path.push(stdlib);
+        }
+        code_path.extend(deps).push_user_paths();
+        code_path
+    }
+
+    /// Add `dir` after the directories already there, unless it is one.
+    pub fn push(&mut self, dir: impl AsRef<Path>) -> &mut Self {
+        let dir = dir.as_ref();
+        if !self.dirs.iter().any(|d| d == dir) {
+            self.dirs.push(dir.to_path_buf());
+        }
+        self
+    }
+
+    pub fn extend<P: AsRef<Path>>(&mut self, dirs: impl IntoIterator<Item = P>) -> &mut Self {
+        for dir in dirs {
+            self.push(dir);
+        }
+        self
+    }
+
+    /// Add the directories listed in `DREAM_PATH`.
+    pub fn push_user_paths(&mut self) -> &mut Self {
+        self.extend(user_paths(env::var_os(DREAM_PATH)))
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    /// The `-pa` arguments that put these directories on `erl`'s code path.
+    pub fn erl_args(&self) -> Vec<OsString> {
+        self.dirs
+            .iter()
+            .flat_map(|dir| [OsString::from("-pa"), dir.clone().into_os_string()])
+            .collect()
+    }
+
+    /// Add the `-pa` arguments to `cmd`.
+    pub fn apply(&self, cmd: &mut Command) {
+        cmd.args(self.erl_args());
+    }
+}
+
+/// The directories in a `DREAM_PATH` value, skipping empty entries.
+fn user_paths(value: Option<OsString>) -> Vec<PathBuf> {
+    let Some(value) = value else {
+        return Vec::new();
+    };
+    env::split_paths(&value)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_path() {
+        let mut code_path = CodePath::new();
+        code_path
+            .push("_build/dev/ebin")
+            .push("stdlib")
+            .extend(["deps/a/ebin", "stdlib", "deps/b/ebin"]);
+        assert_eq!(
+            code_path.dirs(),
+            [
+                PathBuf::from("_build/dev/ebin"),
+                PathBuf::from("stdlib"),
+                PathBuf::from("deps/a/ebin"),
+                PathBuf::from("deps/b/ebin"),
+            ]
+        );
+
+        let args = CodePath::project(Path::new("ebin"), None, ["dep"]).erl_args();
+        assert_eq!(&args[..4], ["-pa", "ebin", "-pa", "dep"]);
+    }
+
+    #[test]
+    fn test_user_paths() {
+        assert!(user_paths(None).is_empty());
+        let value = env::join_paths(["/opt/a", "", "/opt/b"]).unwrap();
+        assert_eq!(
+            user_paths(Some(value)),
+            [PathBuf::from("/opt/a"), PathBuf::from("/opt/b")]
+        );
+    }
+}
@@ -1,5 +1,7 @@
 //! Abstract Syntax Tree types.
 
+use std::sync::atomic::{AtomicU64, Ordering};
+
 use crate::compiler::lexer::Span;
 
 // =============================================================================
@@ -132,6 +134,10 @@ pub enum AttributeArg {
     KeyValue(String, String),
     /// Nested function-like: `not(test)` in `#[cfg(not(test))]`
     Nested(String, Vec<AttributeArg>),
+    /// Comparison: `otp_release >= "26"` in `#[cfg(otp_release >= "26")]`
+    Compare(String, BinOp, String),
+    /// String literal: `"slow"` in `#[tag("slow")]`
+    Str(String),
 }
 
 /// Context for module resolution during compilation.
@@ -316,6 +322,10 @@ pub enum Item {
     TraitDecl(TraitDecl),
     /// External module type declarations for FFI: `extern mod erlang { ... }`
     ExternMod(ExternMod),
+    /// Typed declarations of Erlang functions: `extern "erlang" { ... }`
+    ExternBlock(ExternBlock),
+    /// `compile_error!("message");` at module level
+    CompileError(CompileErrorItem),
 }
 
 /// Type alias definition.
@@ -348,6 +358,15 @@ pub struct ExternMod {
     pub items: Vec<ExternItem>,
 }
 
+/// A module-level `compile_error!("message");`. Unless a `#[cfg]` on it,
+/// or on the module, removes it, the module fails to compile with `message`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileErrorItem {
+    pub attrs: Vec<Attribute>,
+    pub message: String,
+    pub span: Span,
+}
+
 /// Items that can appear in an extern mod block.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ExternItem {
@@ -379,6 +398,59 @@ pub struct ExternType {
     pub type_params: Vec<TypeParam>,
 }
 
+/// Declarations of functions in other BEAM modules, each naming its module:
+///
+/// ```dream
+/// extern "erlang" {
+///     fn lists::reverse<T>(list: [T]) -> [T];
+/// }
+/// ```
+///
+/// Calls to a declared function are checked against its signature and
+/// call the module as written, without the `dream::` prefix, even where
+/// it shares a name with a Dream stdlib module. In `extern "elixir"`
+/// blocks, `fn Phoenix::PubSub::broadcast` is called as `PubSub::broadcast`
+/// and lives in `'Elixir.Phoenix.PubSub'`.
+///
+/// Functions of `extern "nif"` blocks are instead implemented natively,
+/// in a Rustler library loaded when the declaring module loads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternBlock {
+    pub attrs: Vec<Attribute>,
+    /// The calling convention: `"erlang"`, `"elixir"` or `"nif"`
+    pub abi: String,
+    /// Each function with the module path it was declared with (`lists`,
+    /// `Phoenix::PubSub`, or empty for NIFs)
+    pub functions: Vec<(String, ExternFn)>,
+}
+
+impl ExternBlock {
+    /// The name a module of this block is called by in Dream code: the
+    /// last segment of its path.
+    pub fn local_module_name(module: &str) -> &str {
+        module.rsplit("::").next().unwrap_or(module)
+    }
+
+    /// The BEAM module behind a module path of this block.
+    pub fn beam_module_name(&self, module: &str) -> String {
+        if self.abi == "elixir" {
+            elixir_module_name(module.split("::"))
+        } else {
+            module.to_string()
+        }
+    }
+}
+
+/// The atom naming an Elixir module: `Phoenix::PubSub` is `Elixir.Phoenix.PubSub`.
+pub fn elixir_module_name<'a>(segments: impl IntoIterator<Item = &'a str>) -> String {
+    let mut name = String::from("Elixir");
+    for segment in segments {
+        name.push('.');
+        name.push_str(segment);
+    }
+    name
+}
+
 /// Impl block for associated functions and methods.
 #[derive(Debug, Clone, PartialEq)]
 pub struct ImplBlock {
@@ -524,6 +596,8 @@ pub enum Stmt {
         value: Expr,
         /// Optional else block for `let else` syntax (must diverge)
         else_block: Option<Block>,
+        /// Source span for diagnostics
+        span: Option<Span>,
     },
     /// Expression statement (with semicolon).
     Expr {
@@ -531,6 +605,15 @@ pub enum Stmt {
         /// Source span for diagnostics
         span: Option<Span>,
     },
+    /// Statement under `#[cfg(...)]`. Conditional compilation replaces it
+    /// with `stmt` or removes it before type checking.
+    Cfg {
+        attrs: Vec<Attribute>,
+        stmt: Box<Stmt>,
+        /// An expression written without a `;`, which becomes the block's
+        /// value if nothing follows it once the block is configured
+        tail: bool,
+    },
 }
 
 /// Part of an interpolated string.
@@ -542,6 +625,45 @@ pub enum StringPart {
     Expr(Box<Expr>),
 }
 
+/// Part of a `format!` template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatPart {
+    /// Literal text, braces unescaped.
+    Literal(String),
+    /// `{}`: the argument as string interpolation shows it.
+    Display(Box<Expr>),
+    /// `{:?}`: the argument as `display::display_any` shows it, with
+    /// strings quoted.
+    Debug(Box<Expr>),
+}
+
+/// Names an expression for a pass that keeps facts about it on the side,
+/// as the type checker does for the operators it resolves to trait impls.
+/// Each node made takes a [`ExprId::fresh`] id, and its clones share it.
+///
+/// Ids play no part in comparing expressions: two parses of the same
+/// source are equal.
+#[derive(Debug, Clone, Copy)]
+pub struct ExprId(u64);
+
+impl ExprId {
+    pub fn fresh() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        ExprId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// The id as a number, to key a side table by.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl PartialEq for ExprId {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
 /// Expressions.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
@@ -566,6 +688,7 @@ pub enum Expr {
         op: BinOp,
         left: Box<Expr>,
         right: Box<Expr>,
+        id: ExprId,
     },
     /// Unary operation.
     Unary { op: UnaryOp, expr: Box<Expr> },
@@ -712,16 +835,74 @@ pub enum Expr {
         /// Whether this is a comprehension (returns list) or side-effect loop (returns :ok)
         is_comprehension: bool,
     },
+    /// `compile_error!("message")`: fails the build with `message` if
+    /// conditional compilation leaves it in a function body.
+    CompileError { message: String, span: Span },
+    /// `format!("{} has {:?}", name, items)`: builds a string from the
+    /// template, each placeholder holding the argument it takes.
+    Format { parts: Vec<FormatPart>, span: Span },
 }
 
 /// A match arm.
 #[derive(Debug, Clone, PartialEq)]
 pub struct MatchArm {
+    /// `#[cfg(...)]` attributes, applied before type checking
+    pub attrs: Vec<Attribute>,
     pub pattern: Pattern,
     pub guard: Option<Box<Expr>>,
     pub body: Expr,
 }
 
+/// The variable a `with` step binds a value that doesn't match to.
+///
+/// `with` has no node of its own: each step parses to a match whose second
+/// arm is `__with_failure => __with_failure`, which the type checker and
+/// the formatter recognise by this name.
+pub const WITH_FAILURE: &str = "__with_failure";
+
+/// Whether a match is a `with` step, returning its pattern and the block
+/// that runs when the value matches.
+pub fn with_step(arms: &[MatchArm]) -> Option<(&Pattern, &Block)> {
+    let [step, failure] = arms else {
+        return None;
+    };
+    let Expr::Block(rest) = &step.body else {
+        return None;
+    };
+    let is_failure = step.guard.is_none()
+        && failure.guard.is_none()
+        && matches!(&failure.pattern, Pattern::Ident(name) if name == WITH_FAILURE)
+        && matches!(&failure.body, Expr::Ident(name) if name == WITH_FAILURE);
+    is_failure.then_some((&step.pattern, rest))
+}
+
+/// The function a `gen` block yields its values through.
+///
+/// `gen` has no node of its own either: `gen { ... }` parses to
+/// `iterator::generate(|__yield| { ... })` and each `yield value` in it to
+/// `__yield(value)`, which the formatter recognises by this name.
+pub const GEN_YIELD: &str = "__yield";
+
+/// Whether a call is a `gen` block, returning its body.
+pub fn gen_block<'a>(func: &Expr, args: &'a [Expr]) -> Option<&'a Block> {
+    let Expr::Path { segments } = func else {
+        return None;
+    };
+    let [Expr::Closure { params, body }] = args else {
+        return None;
+    };
+    let is_gen = *segments == ["iterator", "generate"] && *params == [GEN_YIELD];
+    is_gen.then_some(body)
+}
+
+/// Whether a call is a `yield`, returning the value yielded.
+pub fn yielded<'a>(func: &Expr, args: &'a [Expr]) -> Option<&'a Expr> {
+    let [value] = args else {
+        return None;
+    };
+    matches!(func, Expr::Ident(name) if name == GEN_YIELD).then_some(value)
+}
+
 /// A clause in a for loop expression.
 #[derive(Debug, Clone, PartialEq)]
 pub enum ForClause {
@@ -773,6 +954,19 @@ impl BinOp {
             BinOp::Mul | BinOp::Div | BinOp::Mod => 5,
         }
     }
+
+    /// The `ops` trait and method a type implements for this operator to
+    /// work on it, if it can be overloaded.
+    pub fn trait_method(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            BinOp::Add => Some(("Add", "add")),
+            BinOp::Sub => Some(("Sub", "sub")),
+            BinOp::Mul => Some(("Mul", "mul")),
+            BinOp::Eq | BinOp::Ne => Some(("Eq", "eq")),
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => Some(("Ord", "cmp")),
+            BinOp::Div | BinOp::Mod | BinOp::And | BinOp::Or => None,
+        }
+    }
 }
 
 impl std::fmt::Display for BinOp {
@@ -979,10 +1173,19 @@ pub struct StructDef {
     pub name: String,
     /// Generic type parameters with optional bounds (e.g., `<T: Display>`)
     pub type_params: Vec<TypeParam>,
+    /// Fields in declaration order. The fields of a tuple struct
+    /// (`struct Meters(Float);`) are named by position: `0`, `1`, ...
     pub fields: Vec<(String, Type)>,
     pub is_pub: bool,
 }
 
+impl StructDef {
+    /// Whether this is a tuple struct, with positional fields.
+    pub fn is_tuple(&self) -> bool {
+        self.fields.first().is_some_and(|(name, _)| name == "0")
+    }
+}
+
 /// Enum definition.
 #[derive(Debug, Clone, PartialEq)]
 pub struct EnumDef {
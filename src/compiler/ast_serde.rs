@@ -82,7 +82,7 @@ pub fn expr_to_erlang_term(expr: &Expr) -> String {
             format!("{{path, [{}]}}", segs.join(", "))
         }
 
-        Expr::Binary { op, left, right } => {
+        Expr::Binary { op, left, right, .. } => {
             format!("{{binary_op, '{}', {}, {}}}",
                 binop_to_atom(op),
                 expr_to_erlang_term(left),
@@ -277,7 +277,11 @@ pub fn expr_to_erlang_term(expr: &Expr) -> String {
         }
         Expr::BitString(_) => "{bitstring}".to_string(), // Simplified
         Expr::StringInterpolation(_) => "{string_interpolation}".to_string(), // Simplified
+        Expr::Format { .. } => "{format}".to_string(), // Simplified
         Expr::For { .. } => "{for}".to_string(), // Simplified
+        Expr::CompileError { message, .. } => {
+            format!("{{compile_error, <<\"{}\">>}}", escape_binary_string(message))
+        }
     }
 }
 
@@ -322,7 +326,7 @@ pub fn block_to_erlang_term(block: &Block) -> String {
 /// Convert a statement to Erlang term format.
 pub fn stmt_to_erlang_term(stmt: &Stmt) -> String {
     match stmt {
-        Stmt::Let { pattern, ty, value, else_block } => {
+        Stmt::Let { pattern, ty, value, else_block, .. } => {
             let ty_str = ty.as_ref()
                 .map(|t| type_to_erlang_term(t))
                 .unwrap_or_else(|| "none".to_string());
@@ -338,6 +342,7 @@ pub fn stmt_to_erlang_term(stmt: &Stmt) -> String {
         Stmt::Expr { expr: e, .. } => {
             format!("{{expr, {}}}", expr_to_erlang_term(e))
         }
+        Stmt::Cfg { stmt, .. } => stmt_to_erlang_term(stmt),
     }
 }
 
@@ -732,7 +737,8 @@ pub fn struct_to_token_stream(s: &StructDef) -> String {
         tokens.push(format!("{{group, angle, [{}]}}", params.join(", ")));
     }
 
-    // Fields group
+    // Fields group: (T, U); for a tuple struct, { name: T, ... } otherwise
+    let tuple = s.is_tuple();
     let fields: Vec<String> = s.fields.iter()
         .enumerate()
         .flat_map(|(i, (name, ty))| {
@@ -740,14 +746,21 @@ pub fn struct_to_token_stream(s: &StructDef) -> String {
             if i > 0 {
                 field_tokens.push(punct_token(","));
             }
-            field_tokens.push(format!("{{ident, '{}'}}", escape_atom(name)));
-            field_tokens.push(punct_token(":"));
+            if !tuple {
+                field_tokens.push(format!("{{ident, '{}'}}", escape_atom(name)));
+                field_tokens.push(punct_token(":"));
+            }
             field_tokens.extend(type_to_tokens(ty));
             field_tokens
         })
         .collect();
 
-    tokens.push(format!("{{group, brace, [{}]}}", fields.join(", ")));
+    if tuple {
+        tokens.push(format!("{{group, paren, [{}]}}", fields.join(", ")));
+        tokens.push(punct_token(";"));
+    } else {
+        tokens.push(format!("{{group, brace, [{}]}}", fields.join(", ")));
+    }
 
     format!("[{}]", tokens.join(", "))
 }
@@ -1367,6 +1380,7 @@ pub fn term_to_expr(term: &Term) -> TermParseResult<Expr> {
                         op,
                         left: Box::new(left),
                         right: Box::new(right),
+                        id: ExprId::fresh(),
                     })
                 }
                 "unary_op" => {
@@ -1559,7 +1573,7 @@ fn term_to_stmt(term: &Term) -> TermParseResult<Stmt> {
             } else {
                 None
             };
-            Ok(Stmt::Let { pattern, ty, value, else_block })
+            Ok(Stmt::Let { pattern, ty, value, else_block, span: None })
         }
         "expr" => {
             let expr = term_to_expr(&tuple[1])?;
@@ -1945,6 +1959,7 @@ mod tests {
             op: BinOp::Add,
             left: Box::new(Expr::Int(1)),
             right: Box::new(Expr::Int(2)),
+            id: ExprId::fresh(),
         };
         assert_eq!(
             expr_to_erlang_term(&expr),
@@ -2082,6 +2097,7 @@ mod tests {
             op: BinOp::Add,
             left: Box::new(Expr::Int(1)),
             right: Box::new(Expr::Int(2)),
+            id: ExprId::fresh(),
         });
     }
 
@@ -2127,6 +2143,7 @@ mod tests {
             op: BinOp::Add,
             left: Box::new(Expr::Int(1)),
             right: Box::new(Expr::Int(2)),
+            id: ExprId::fresh(),
         };
         let term_str = expr_to_erlang_term(&original);
         let term = parse_term(&term_str).unwrap();
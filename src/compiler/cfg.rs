@@ -1,10 +1,20 @@
 //! Cfg attribute evaluation for conditional compilation.
 //!
 //! Evaluates `#[cfg(...)]` attributes to determine whether items should be
-//! included in the compiled output based on compile options (test mode, features).
+//! included in the compiled output based on compile options (test mode, features,
+//! `--cfg` options), and expands `#[cfg_attr(...)]` into the attributes it applies.
+//! Statements and match arms under `#[cfg]` are removed from function bodies by
+//! [`configure_module`], before type checking, which also reports the
+//! `compile_error!`s left in.
 
-use crate::compiler::ast::{Attribute, AttributeArg, AttributeArgs};
-use crate::config::CompileOptions;
+use crate::compiler::ast::{
+    Attribute, AttributeArg, AttributeArgs, BinOp, Block, EnumVariantArgs, Expr, ExternItem, ExternMod, ForClause,
+    FormatPart, Function, Item, MatchArm, Module, Stmt, StringPart,
+};
+use crate::compiler::lexer::Span;
+use crate::compiler::diagnostics::{did_you_mean, Annotate, ErrorCode};
+use crate::compiler::error::{ParseError, TypeError, Warning};
+use crate::config::{CompileOptions, RuntimeTarget, RESERVED_CFG_NAMES};
 
 /// Check if an item with the given attributes should be included in compilation.
 /// Returns `true` if the item should be included, `false` if it should be excluded.
@@ -28,6 +38,31 @@ pub fn is_test(attrs: &[Attribute]) -> bool {
     attrs.iter().any(|attr| attr.name == "test")
 }
 
+/// Check if an item has the `#[bench]` attribute.
+pub fn is_bench(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| attr.name == "bench")
+}
+
+/// Check if a test has the `#[ignore]` attribute.
+pub fn is_ignored(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| attr.name == "ignore")
+}
+
+/// Collect the tags of a test: `#[tag("slow")]` or `#[tag(slow, db)]`.
+pub fn test_tags(attrs: &[Attribute]) -> Vec<String> {
+    let mut tags = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.name == "tag") {
+        if let AttributeArgs::Parenthesized(args) = &attr.args {
+            for arg in args {
+                if let AttributeArg::Str(tag) | AttributeArg::Ident(tag) = arg {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+    }
+    tags
+}
+
 /// Check if an item has the `#[macro]` attribute.
 pub fn is_macro(attrs: &[Attribute]) -> bool {
     attrs.iter().any(|attr| attr.name == "macro")
@@ -113,6 +148,581 @@ pub fn is_cfg_test(attrs: &[Attribute]) -> bool {
     })
 }
 
+/// Check that a `#[cfg(...)]` attribute is shaped like a predicate, so that
+/// a slip such as `not(a, b)` is reported instead of quietly deciding whether
+/// the item is compiled. Names are not checked here.
+pub fn check_cfg_attr(attr: &Attribute) -> Result<(), ParseError> {
+    let problem = match &attr.args {
+        AttributeArgs::None => Some("`cfg` needs a predicate, as in `#[cfg(test)]`".to_string()),
+        AttributeArgs::Eq(_) => Some("`cfg` takes its predicate in parentheses".to_string()),
+        AttributeArgs::Parenthesized(args) => args.iter().find_map(malformed_predicate),
+    };
+    match problem {
+        None => Ok(()),
+        Some(message) => Err(ParseError::new(message, attr.span.clone())
+            .with_code(ErrorCode::MalformedAttribute)
+            .with_note(
+                "a predicate is a name such as `test`, `feature = \"name\"` or \
+                 `otp_release >= \"26\"`, or `not`, `all` or `any` applied to predicates",
+            )),
+    }
+}
+
+/// Check that a `#[cfg_attr(predicate, attr, ...)]` attribute has a
+/// well-formed predicate and at least one attribute, and that any `cfg` or
+/// `cfg_attr` among those attributes is well-formed too.
+pub fn check_conditional_attr(attr: &Attribute) -> Result<(), ParseError> {
+    let malformed = |message: String| {
+        ParseError::new(message, attr.span.clone())
+            .with_code(ErrorCode::MalformedAttribute)
+            .with_note("`cfg_attr` takes a cfg predicate, then the attributes to apply when it holds")
+    };
+    let args = match &attr.args {
+        AttributeArgs::Parenthesized(args) if args.len() >= 2 => args,
+        AttributeArgs::Parenthesized(args) if !args.is_empty() => {
+            return Err(malformed("`cfg_attr` needs an attribute after its predicate".to_string()));
+        }
+        _ => {
+            return Err(malformed(
+                "`cfg_attr` needs a predicate and an attribute, as in `#[cfg_attr(test, derive(Debug))]`"
+                    .to_string(),
+            ));
+        }
+    };
+    if let Some(message) = malformed_predicate(&args[0]) {
+        return Err(malformed(message));
+    }
+    for arg in &args[1..] {
+        let Some(inner) = inner_attribute(arg, attr) else {
+            return Err(malformed(match arg {
+                AttributeArg::Path(path) => format!("`{}` is not an attribute", path.join("::")),
+                AttributeArg::Compare(key, op, value) => {
+                    format!("`{} {} \"{}\"` is not an attribute", key, op, value)
+                }
+                _ => "a string is not an attribute".to_string(),
+            }));
+        };
+        match inner.name.as_str() {
+            "cfg" => check_cfg_attr(&inner)?,
+            "cfg_attr" => check_conditional_attr(&inner)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Configure `module` for `options` before anything reads it. Each
+/// `#[cfg_attr(predicate, attr, ...)]` on the module and its items is
+/// replaced with the attributes it holds when the predicate is true and
+/// dropped otherwise, so derives, tests and `#[cfg]` itself can be made
+/// conditional. In function bodies, statements and match arms whose `#[cfg]`
+/// is false are removed, so the type checker never sees them.
+///
+/// Conditions that test something the compiler doesn't know of are still
+/// false, but are returned as warnings, since they are most likely typos.
+/// Each `compile_error!` that configuring leaves in a compiled item is
+/// returned as an error.
+pub fn configure_module(module: &mut Module, options: &CompileOptions) -> Configured {
+    let mut configurer = Configurer {
+        options,
+        warnings: Vec::new(),
+        errors: Vec::new(),
+        included: true,
+    };
+    configurer.attrs(&mut module.attrs);
+    configurer.included = should_include(&module.attrs, options);
+    for item in &mut module.items {
+        match item {
+            Item::Function(func) => configurer.function(func),
+            Item::Struct(def) => configurer.attrs(&mut def.attrs),
+            Item::Enum(def) => configurer.attrs(&mut def.attrs),
+            Item::TypeAlias(alias) => configurer.attrs(&mut alias.attrs),
+            Item::Impl(block) => {
+                for method in &mut block.methods {
+                    configurer.function(method);
+                }
+            }
+            Item::TraitImpl(block) => {
+                for method in &mut block.methods {
+                    configurer.function(method);
+                }
+            }
+            Item::Trait(def) => {
+                for body in def.methods.iter_mut().filter_map(|method| method.body.as_mut()) {
+                    configurer.block(body);
+                }
+            }
+            Item::ExternMod(extern_mod) => configurer.extern_mod(extern_mod),
+            Item::ExternBlock(block) => {
+                configurer.attrs(&mut block.attrs);
+                for (_, func) in &mut block.functions {
+                    configurer.attrs(&mut func.attrs);
+                }
+            }
+            Item::CompileError(item) => {
+                configurer.attrs(&mut item.attrs);
+                if should_include(&item.attrs, options) {
+                    configurer.compile_error(&item.message, &item.span);
+                }
+            }
+            Item::ModDecl(_) | Item::Use(_) | Item::TraitDecl(_) => {}
+        }
+    }
+    Configured {
+        warnings: configurer
+            .warnings
+            .into_iter()
+            .map(|warning| warning.in_module(module.name.clone()))
+            .collect(),
+        errors: configurer.errors,
+    }
+}
+
+/// What [`configure_module`] found in a module.
+#[derive(Debug, Default)]
+pub struct Configured {
+    /// Conditions testing something unknown
+    pub warnings: Vec<Warning>,
+    /// The `compile_error!`s left in
+    pub errors: Vec<TypeError>,
+}
+
+/// Walks a module for [`configure_module`].
+struct Configurer<'a> {
+    options: &'a CompileOptions,
+    /// Unexpected conditions found so far
+    warnings: Vec<Warning>,
+    /// `compile_error!`s found so far
+    errors: Vec<TypeError>,
+    /// Whether the item being walked is compiled. Items that `#[cfg]`
+    /// leaves out are still checked for unexpected conditions.
+    included: bool,
+}
+
+impl Configurer<'_> {
+    /// Check the conditions in one attribute list, then expand its
+    /// `cfg_attr`s.
+    fn attrs(&mut self, attrs: &mut Vec<Attribute>) {
+        for attr in attrs.iter() {
+            self.check_attr(attr);
+        }
+        expand_attrs(attrs, self.options);
+    }
+
+    fn check_attr(&mut self, attr: &Attribute) {
+        let AttributeArgs::Parenthesized(args) = &attr.args else {
+            return;
+        };
+        match attr.name.as_str() {
+            "cfg" => {
+                for arg in args {
+                    self.check_predicate(arg, attr);
+                }
+            }
+            "cfg_attr" => {
+                let Some((predicate, inner)) = args.split_first() else {
+                    return;
+                };
+                self.check_predicate(predicate, attr);
+                for arg in inner {
+                    if let Some(inner) = inner_attribute(arg, attr) {
+                        self.check_attr(&inner);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_predicate(&mut self, arg: &AttributeArg, attr: &Attribute) {
+        let warning = match arg {
+            AttributeArg::Ident(name) => unexpected_condition(name, None, self.options),
+            AttributeArg::KeyValue(name, value) => unexpected_condition(name, Some(value), self.options),
+            AttributeArg::Nested(_, args) => {
+                for arg in args {
+                    self.check_predicate(arg, attr);
+                }
+                None
+            }
+            // `otp_release` comparisons are checked by the parser
+            AttributeArg::Compare(..) | AttributeArg::Path(_) | AttributeArg::Str(_) => None,
+        };
+        if let Some(mut warning) = warning {
+            warning.span = Some(attr.span.clone());
+            self.warnings.push(warning);
+        }
+    }
+
+    fn extern_mod(&mut self, extern_mod: &mut ExternMod) {
+        self.attrs(&mut extern_mod.attrs);
+        for item in &mut extern_mod.items {
+            match item {
+                ExternItem::Mod(inner) => self.extern_mod(inner),
+                ExternItem::Function(func) => self.attrs(&mut func.attrs),
+                ExternItem::Type(_) => {}
+            }
+        }
+    }
+
+    fn function(&mut self, func: &mut Function) {
+        self.attrs(&mut func.attrs);
+        let included = self.included;
+        self.included = included && should_include(&func.attrs, self.options);
+        if let Some(guard) = &mut func.guard {
+            self.expr(guard);
+        }
+        self.block(&mut func.body);
+        self.included = included;
+    }
+
+    fn compile_error(&mut self, message: &str, span: &Span) {
+        if self.included {
+            let error = TypeError::with_span(message, span.clone()).with_code(ErrorCode::CompileError);
+            self.errors.push(error);
+        }
+    }
+
+    /// Drop the statements of `block` that are configured out and unwrap
+    /// the rest. An expression written last without a `;` becomes the
+    /// block's value if it is still last.
+    fn block(&mut self, block: &mut Block) {
+        if block.stmts.iter().any(|stmt| matches!(stmt, Stmt::Cfg { .. })) {
+            let mut tail = false;
+            let mut stmts = Vec::with_capacity(block.stmts.len());
+            for stmt in std::mem::take(&mut block.stmts) {
+                match stmt {
+                    Stmt::Cfg { mut attrs, stmt, tail: is_tail } => {
+                        self.attrs(&mut attrs);
+                        if should_include(&attrs, self.options) {
+                            stmts.push(*stmt);
+                            tail = is_tail;
+                        }
+                    }
+                    stmt => {
+                        stmts.push(stmt);
+                        tail = false;
+                    }
+                }
+            }
+            block.stmts = stmts;
+            if tail && block.expr.is_none() {
+                if let Some(Stmt::Expr { expr, .. }) = block.stmts.pop() {
+                    block.expr = Some(Box::new(expr));
+                }
+            }
+        }
+
+        for stmt in &mut block.stmts {
+            match stmt {
+                Stmt::Let { value, else_block, .. } => {
+                    self.expr(value);
+                    if let Some(else_block) = else_block {
+                        self.block(else_block);
+                    }
+                }
+                Stmt::Expr { expr, .. } => self.expr(expr),
+                Stmt::Cfg { .. } => {}
+            }
+        }
+        if let Some(expr) = &mut block.expr {
+            self.expr(expr);
+        }
+    }
+
+    fn arms(&mut self, arms: &mut Vec<MatchArm>) {
+        arms.retain_mut(|arm| {
+            self.attrs(&mut arm.attrs);
+            should_include(&arm.attrs, self.options)
+        });
+        for arm in arms {
+            arm.attrs.clear();
+            if let Some(guard) = &mut arm.guard {
+                self.expr(guard);
+            }
+            self.expr(&mut arm.body);
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Binary { left, right, .. }
+            | Expr::Send { to: left, msg: right }
+            | Expr::Pipe { left, right }
+            | Expr::ListCons { head: left, tail: right } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Unary { expr: inner, .. }
+            | Expr::FieldAccess { expr: inner, .. }
+            | Expr::Try { expr: inner }
+            | Expr::Spawn(inner) => self.expr(inner),
+            Expr::UnquoteFieldAccess { expr: inner, field_expr } => {
+                self.expr(inner);
+                self.expr(field_expr);
+            }
+            Expr::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Expr::Call { func, args, .. } => {
+                self.expr(func);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::MethodCall { receiver, args, .. } => {
+                self.expr(receiver);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Expr::Tuple(elements) | Expr::List(elements) | Expr::ExternCall { args: elements, .. } => {
+                for element in elements {
+                    self.expr(element);
+                }
+            }
+            Expr::StructInit { fields, base, .. } => {
+                for (_, value) in fields {
+                    self.expr(value);
+                }
+                if let Some(base) = base {
+                    self.expr(base);
+                }
+            }
+            Expr::EnumVariant { args, .. } => match args {
+                EnumVariantArgs::Unit => {}
+                EnumVariantArgs::Tuple(values) => {
+                    for value in values {
+                        self.expr(value);
+                    }
+                }
+                EnumVariantArgs::Struct(fields) => {
+                    for (_, value) in fields {
+                        self.expr(value);
+                    }
+                }
+            },
+            Expr::MapLiteral(entries) => {
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            Expr::StringInterpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(inner) = part {
+                        self.expr(inner);
+                    }
+                }
+            }
+            Expr::Format { parts, .. } => {
+                for part in parts {
+                    if let FormatPart::Display(inner) | FormatPart::Debug(inner) = part {
+                        self.expr(inner);
+                    }
+                }
+            }
+            Expr::BitString(segments) => {
+                for segment in segments {
+                    self.expr(&mut segment.value);
+                }
+            }
+            Expr::If { cond, then_block, else_block } => {
+                self.expr(cond);
+                self.block(then_block);
+                if let Some(else_block) = else_block {
+                    self.block(else_block);
+                }
+            }
+            Expr::Match { expr: scrutinee, arms } => {
+                self.expr(scrutinee);
+                self.arms(arms);
+            }
+            Expr::Receive { arms, timeout } => {
+                self.arms(arms);
+                if let Some((after, body)) = timeout {
+                    self.expr(after);
+                    self.block(body);
+                }
+            }
+            Expr::Block(block) | Expr::SpawnClosure(block) | Expr::Closure { body: block, .. } => {
+                self.block(block);
+            }
+            Expr::For { clauses, body, .. } => {
+                for clause in clauses {
+                    match clause {
+                        ForClause::Generator { source, .. } => self.expr(source),
+                        ForClause::When(cond) => self.expr(cond),
+                    }
+                }
+                self.expr(body);
+            }
+            // Quoted code is data for macros and is left as it was written
+            Expr::Quote(_)
+            | Expr::QuoteItem(_)
+            | Expr::QuoteRepetition { .. }
+            | Expr::Unquote(_)
+            | Expr::UnquoteSplice(_)
+            | Expr::UnquoteAtom(_) => {}
+            Expr::CompileError { message, span } => self.compile_error(message, span),
+            Expr::Int(_)
+            | Expr::String(_)
+            | Expr::Charlist(_)
+            | Expr::Atom(_)
+            | Expr::Bool(_)
+            | Expr::Ident(_)
+            | Expr::Path { .. }
+            | Expr::Unit => {}
+        }
+    }
+}
+
+/// A warning if the condition `name` or `name = "value"` tests something
+/// the compiler doesn't know of: a name that is neither built in nor set or
+/// declared by the project, a feature the project doesn't declare, or a
+/// target that doesn't exist.
+fn unexpected_condition(name: &str, value: Option<&str>, options: &CompileOptions) -> Option<Warning> {
+    let expected = options.expected_cfg.as_ref();
+    let (message, help) = match (name, value) {
+        ("test" | "debug_assertions", None) | ("otp_release", Some(_)) => return None,
+        ("test" | "debug_assertions", Some(value)) => (
+            format!("unexpected `cfg` condition value: `{} = \"{}\"`", name, value),
+            format!("`{}` takes no value; write `cfg({})`", name, name),
+        ),
+        ("feature" | "target" | "otp_release", None) => (
+            format!("unexpected `cfg` condition: `{}` without a value", name),
+            format!("write `{} = \"...\"`", name),
+        ),
+        ("feature", Some(feature)) => {
+            // Without a manifest there is nothing to check features against
+            let declared = &expected?.features;
+            if declared.contains(feature) {
+                return None;
+            }
+            let help = match did_you_mean(feature, declared.iter().map(String::as_str)) {
+                Some(candidate) => format!("did you mean `feature = \"{}\"`?", candidate),
+                None => "features are declared under `[features]` in dream.toml".to_string(),
+            };
+            (format!("unexpected `cfg` condition value: `feature = \"{}\"`", feature), help)
+        }
+        ("target", Some(target)) => {
+            let targets = RuntimeTarget::ALL.map(RuntimeTarget::name);
+            if targets.contains(&target) {
+                return None;
+            }
+            let help = match did_you_mean(target, targets) {
+                Some(candidate) => format!("did you mean `target = \"{}\"`?", candidate),
+                None => {
+                    let targets = targets.map(|target| format!("`\"{}\"`", target));
+                    format!("expected one of {}", targets.join(", "))
+                }
+            };
+            (format!("unexpected `cfg` condition value: `target = \"{}\"`", target), help)
+        }
+        (name, _) => {
+            let set = options.cfg.iter().map(|(name, _)| name.as_str());
+            let declared = expected.into_iter().flat_map(|expected| expected.names.iter().map(String::as_str));
+            let mut known: Vec<&str> = RESERVED_CFG_NAMES.into_iter().chain(set).chain(declared).collect();
+            if known.contains(&name) {
+                return None;
+            }
+            known.sort_unstable();
+            known.dedup();
+            let help = match did_you_mean(name, known) {
+                Some(candidate) => format!("did you mean `{}`?", candidate),
+                None => format!(
+                    "set it with `--cfg {}` or under `[build.cfg]`, or list it in `check-cfg` under `[build]` in dream.toml",
+                    name
+                ),
+            };
+            (format!("unexpected `cfg` condition name: `{}`", name), help)
+        }
+    };
+    Some(Warning::with_help(message, help).with_code(ErrorCode::UnexpectedCfg))
+}
+
+/// Expand the `cfg_attr` attributes in one attribute list, in place.
+pub fn expand_attrs(attrs: &mut Vec<Attribute>, options: &CompileOptions) {
+    if !attrs.iter().any(|attr| attr.name == "cfg_attr") {
+        return;
+    }
+    let mut expanded = Vec::with_capacity(attrs.len());
+    for attr in attrs.drain(..) {
+        push_expanded(attr, options, &mut expanded);
+    }
+    *attrs = expanded;
+}
+
+fn push_expanded(attr: Attribute, options: &CompileOptions, out: &mut Vec<Attribute>) {
+    if attr.name != "cfg_attr" {
+        out.push(attr);
+        return;
+    }
+    // The parser has checked the shape, so anything else can't happen
+    let AttributeArgs::Parenthesized(args) = &attr.args else {
+        return;
+    };
+    let Some((predicate, inner)) = args.split_first() else {
+        return;
+    };
+    if !evaluate_cfg_arg(predicate, options) {
+        return;
+    }
+    for arg in inner {
+        if let Some(inner) = inner_attribute(arg, &attr) {
+            // `cfg_attr(a, cfg_attr(b, ...))` applies when both hold
+            push_expanded(inner, options, out);
+        }
+    }
+}
+
+/// One of the attributes in a `cfg_attr`, written as an attribute argument:
+/// `test`, `derive(Debug)` or `name = "value"`. It takes the span of the
+/// whole `cfg_attr`.
+fn inner_attribute(arg: &AttributeArg, cfg_attr: &Attribute) -> Option<Attribute> {
+    let (name, args) = match arg {
+        AttributeArg::Ident(name) => (name.clone(), AttributeArgs::None),
+        AttributeArg::Nested(name, args) => (name.clone(), AttributeArgs::Parenthesized(args.clone())),
+        AttributeArg::KeyValue(name, value) => (name.clone(), AttributeArgs::Eq(value.clone())),
+        AttributeArg::Path(_) | AttributeArg::Str(_) | AttributeArg::Compare(..) => return None,
+    };
+    Some(Attribute {
+        name,
+        args,
+        span: cfg_attr.span.clone(),
+    })
+}
+
+/// What is wrong with a cfg predicate, if anything.
+fn malformed_predicate(arg: &AttributeArg) -> Option<String> {
+    match arg {
+        AttributeArg::Ident(_) | AttributeArg::KeyValue(_, _) => None,
+        AttributeArg::Nested(name, args) if name == "not" && args.len() != 1 => Some(format!(
+            "`not` takes exactly one predicate, found {}",
+            args.len()
+        )),
+        AttributeArg::Nested(name, args) if matches!(name.as_str(), "not" | "all" | "any") => {
+            args.iter().find_map(malformed_predicate)
+        }
+        AttributeArg::Nested(name, _) => Some(format!(
+            "unknown cfg operator `{}`, expected `not`, `all` or `any`",
+            name
+        )),
+        AttributeArg::Compare(key, op, value) if key != "otp_release" => Some(format!(
+            "only `otp_release` can be compared with `{}`; did you mean `{} = \"{}\"`?",
+            op, key, value
+        )),
+        AttributeArg::Compare(_, _, value) if value.parse::<u32>().is_err() => Some(format!(
+            "`otp_release` is compared with a release number such as \"26\", not \"{}\"",
+            value
+        )),
+        AttributeArg::Compare(..) => None,
+        AttributeArg::Path(path) => Some(format!("`{}` is not a cfg predicate", path.join("::"))),
+        AttributeArg::Str(value) => Some(format!("\"{}\" is not a cfg predicate", value)),
+    }
+}
+
 /// Evaluate a single `#[cfg(...)]` attribute.
 fn evaluate_cfg_attr(attr: &Attribute, options: &CompileOptions) -> bool {
     match &attr.args {
@@ -139,16 +749,28 @@ fn evaluate_cfg_arg(arg: &AttributeArg, options: &CompileOptions) -> bool {
             if ident == "test" {
                 return options.test_mode;
             }
-            // Unknown identifier - treat as false
-            false
+            // `debug_assertions` - set by the build profile
+            if ident == "debug_assertions" {
+                return options.debug_assertions;
+            }
+            // Anything else is set by `--cfg name` or `[build.cfg]`
+            options.has_cfg(ident, None)
         }
         AttributeArg::KeyValue(key, value) => {
             // `feature = "name"` - checks if feature is enabled
             if key == "feature" {
                 return options.has_feature(value);
             }
-            // Unknown key - treat as false
-            false
+            // `target = "beam"` - the runtime being compiled for
+            if key == "target" {
+                return options.target.name() == value;
+            }
+            // `otp_release = "26"` - exactly this OTP release
+            if key == "otp_release" {
+                return value.parse().ok().is_some_and(|release: u32| options.otp_release == Some(release));
+            }
+            // Other keys are set by `--cfg key="value"` or `[build.cfg]`
+            options.has_cfg(key, Some(value))
         }
         AttributeArg::Nested(name, inner_args) => {
             match name.as_str() {
@@ -176,8 +798,21 @@ fn evaluate_cfg_arg(arg: &AttributeArg, options: &CompileOptions) -> bool {
                 }
             }
         }
-        AttributeArg::Path(_) => {
-            // Paths don't make sense in cfg context - treat as false
+        AttributeArg::Compare(key, op, value) => {
+            // `otp_release >= "26"` - false when the release isn't known
+            let (Some(release), Ok(value)) = (options.otp_release, value.parse::<u32>()) else {
+                return false;
+            };
+            match op {
+                BinOp::Ge if key == "otp_release" => release >= value,
+                BinOp::Gt if key == "otp_release" => release > value,
+                BinOp::Le if key == "otp_release" => release <= value,
+                BinOp::Lt if key == "otp_release" => release < value,
+                _ => false,
+            }
+        }
+        AttributeArg::Path(_) | AttributeArg::Str(_) => {
+            // Paths and bare strings don't make sense in cfg context - treat as false
             false
         }
     }
@@ -187,6 +822,7 @@ fn evaluate_cfg_arg(arg: &AttributeArg, options: &CompileOptions) -> bool {
 mod tests {
     use super::*;
     use crate::compiler::lexer::Span;
+    use crate::config::ExpectedCfg;
     use std::collections::HashSet;
 
     fn make_attr(name: &str, args: AttributeArgs) -> Attribute {
@@ -197,6 +833,298 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_check_cfg_attr() {
+        use AttributeArg::*;
+        let ok = make_attr(
+            "cfg",
+            AttributeArgs::Parenthesized(vec![Nested(
+                "all".to_string(),
+                vec![Ident("test".to_string()), Nested("not".to_string(), vec![Ident("unix".to_string())])],
+            )]),
+        );
+        assert!(check_cfg_attr(&ok).is_ok());
+
+        let not_two = make_attr(
+            "cfg",
+            AttributeArgs::Parenthesized(vec![Nested(
+                "not".to_string(),
+                vec![Ident("a".to_string()), Ident("b".to_string())],
+            )]),
+        );
+        let err = check_cfg_attr(&not_two).unwrap_err();
+        assert_eq!(err.message, "`not` takes exactly one predicate, found 2");
+        assert_eq!(err.code, ErrorCode::MalformedAttribute);
+        assert_eq!(err.annotations.notes.len(), 1);
+
+        let unknown_op = make_attr(
+            "cfg",
+            AttributeArgs::Parenthesized(vec![Nested("either".to_string(), vec![])]),
+        );
+        assert!(check_cfg_attr(&unknown_op).unwrap_err().message.contains("`either`"));
+        assert!(check_cfg_attr(&make_attr("cfg", AttributeArgs::None)).is_err());
+    }
+
+    #[test]
+    fn test_configure_module_attrs() {
+        let source = r#"
+#[cfg_attr(test, derive(Debug), tag("slow"))]
+#[cfg_attr(feature = "json", derive(Serialize))]
+pub struct Point {
+    x: int,
+    y: int,
+}
+
+#[cfg_attr(test, cfg_attr(not(feature = "db"), ignore))]
+#[cfg_attr(not(test), cfg(feature = "db"))]
+fn check() -> int {
+    1
+}
+"#;
+        let parsed = crate::compiler::Parser::new(source).parse_file("app").unwrap();
+        let attrs = |module: &Module| -> Vec<Vec<String>> {
+            module
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Struct(def) => Some(&def.attrs),
+                    Item::Function(func) => Some(&func.attrs),
+                    _ => None,
+                })
+                .map(|attrs| attrs.iter().map(|attr| attr.name.clone()).collect())
+                .collect()
+        };
+
+        let mut module = parsed.clone();
+        configure_module(&mut module, &CompileOptions::for_testing());
+        assert_eq!(attrs(&module), vec![vec!["derive", "tag"], vec!["ignore"]]);
+        let Some(Item::Struct(def)) = module.items.iter().find(|item| matches!(item, Item::Struct(_))) else {
+            panic!("expected a struct");
+        };
+        assert_eq!(
+            def.attrs[0].args,
+            AttributeArgs::Parenthesized(vec![AttributeArg::Ident("Debug".to_string())])
+        );
+
+        // Outside test mode the function gets a `cfg` that then excludes it
+        let mut module = parsed;
+        configure_module(&mut module, &CompileOptions::new());
+        assert_eq!(attrs(&module), vec![vec![], vec!["cfg"]]);
+        let Some(Item::Function(func)) = module.items.last() else {
+            panic!("expected a function");
+        };
+        assert!(!should_include(&func.attrs, &CompileOptions::new()));
+    }
+
+    #[test]
+    fn test_configure_bodies() {
+        let source = r#"
+fn check(x: int) -> int {
+    #[cfg(test)]
+    let y = 1;
+    #[cfg(not(test))]
+    let y = 2;
+    #[cfg(feature = "log")]
+    log(y);
+    match x {
+        #[cfg_attr(not(test), cfg(feature = "log"))]
+        0 => y,
+        _ => x,
+    }
+}
+
+fn mode() -> int {
+    #[cfg(test)]
+    1
+    #[cfg(not(test))]
+    2
+}
+"#;
+        let parsed = crate::compiler::Parser::new(source).parse_file("app").unwrap();
+        let function = |module: &Module, name: &str| -> Function {
+            module
+                .items
+                .iter()
+                .find_map(|item| match item {
+                    Item::Function(func) if func.name == name => Some(func.clone()),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let arms = |func: &Function| -> Vec<MatchArm> {
+            match func.body.expr.as_deref() {
+                Some(Expr::Match { arms, .. }) => arms.clone(),
+                other => panic!("expected a match, got {:?}", other),
+            }
+        };
+
+        let mut module = parsed.clone();
+        configure_module(&mut module, &CompileOptions::for_testing());
+        let check = function(&module, "check");
+        assert_eq!(check.body.stmts.len(), 1);
+        assert!(matches!(&check.body.stmts[0], Stmt::Let { value: Expr::Int(1), .. }));
+        let check_arms = arms(&check);
+        assert_eq!(check_arms.len(), 2);
+        assert!(check_arms.iter().all(|arm| arm.attrs.is_empty()));
+        let mode = function(&module, "mode");
+        assert!(mode.body.stmts.is_empty());
+        assert_eq!(mode.body.expr.as_deref(), Some(&Expr::Int(1)));
+
+        let mut module = parsed.clone();
+        configure_module(&mut module, &CompileOptions::new());
+        let check = function(&module, "check");
+        assert!(matches!(&check.body.stmts[..], [Stmt::Let { value: Expr::Int(2), .. }]));
+        assert_eq!(arms(&check).len(), 1);
+        assert_eq!(function(&module, "mode").body.expr.as_deref(), Some(&Expr::Int(2)));
+
+        let mut module = parsed;
+        let options = CompileOptions::with_features(HashSet::from(["log".to_string()]));
+        configure_module(&mut module, &options);
+        let check = function(&module, "check");
+        assert_eq!(check.body.stmts.len(), 2);
+        assert_eq!(arms(&check).len(), 2);
+    }
+
+    #[test]
+    fn test_unexpected_cfgs() {
+        let source = r#"
+#[cfg(fature = "json")]
+fn encode() -> int {
+    #[cfg(feature = "jsno")]
+    let x = 1;
+    match 2 {
+        #[cfg(target = "atomv")]
+        2 => 2,
+        _ => 0,
+    }
+}
+
+#[cfg_attr(all(test, not(otel)), cfg(debug_assertions = "yes"))]
+fn traced() -> int {
+    1
+}
+"#;
+        let parsed = crate::compiler::Parser::new(source).parse_file("app").unwrap();
+        let messages = |options: &CompileOptions| -> Vec<(String, String)> {
+            configure_module(&mut parsed.clone(), options)
+                .warnings
+                .into_iter()
+                .map(|warning| {
+                    assert_eq!(warning.code, Some(ErrorCode::UnexpectedCfg));
+                    assert_eq!(warning.module.as_deref(), Some("app"));
+                    (warning.message, warning.help.unwrap_or_default())
+                })
+                .collect()
+        };
+
+        // Without a manifest, features aren't checked
+        let warnings = messages(&CompileOptions::for_testing());
+        assert_eq!(warnings.len(), 4, "{:?}", warnings);
+        assert_eq!(
+            warnings[0],
+            (
+                "unexpected `cfg` condition name: `fature`".to_string(),
+                "did you mean `feature`?".to_string()
+            )
+        );
+        assert_eq!(warnings[1].1, "did you mean `target = \"atomvm\"`?");
+        assert!(warnings[2].0.contains("`otel`"), "{:?}", warnings);
+        assert_eq!(warnings[3].0, "unexpected `cfg` condition value: `debug_assertions = \"yes\"`");
+
+        let options = CompileOptions {
+            cfg: HashSet::from([("otel".to_string(), None)]),
+            expected_cfg: Some(ExpectedCfg {
+                names: HashSet::new(),
+                features: HashSet::from(["json".to_string()]),
+            }),
+            ..CompileOptions::for_testing()
+        };
+        let warnings = messages(&options);
+        assert_eq!(warnings.len(), 4, "{:?}", warnings);
+        assert_eq!(warnings[1].1, "did you mean `feature = \"json\"`?");
+        assert!(!warnings.iter().any(|(message, _)| message.contains("otel")));
+    }
+
+    #[test]
+    fn test_compile_error() {
+        let source = r#"
+#[cfg(all(feature = "sync", feature = "async"))]
+compile_error!("the `sync` and `async` features can't both be enabled");
+
+fn start() -> int {
+    #[cfg(not(any(feature = "sync", feature = "async")))]
+    compile_error!("enable the `sync` or `async` feature");
+    1
+}
+
+#[cfg(test)]
+fn fixture() -> int {
+    compile_error!("no fixtures yet")
+}
+"#;
+        let parsed = crate::compiler::Parser::new(source).parse_file("app").unwrap();
+        let errors = |features: &[&str], test_mode: bool| -> Vec<String> {
+            let options = CompileOptions {
+                features: features.iter().map(|feature| feature.to_string()).collect(),
+                test_mode,
+                ..CompileOptions::new()
+            };
+            configure_module(&mut parsed.clone(), &options)
+                .errors
+                .into_iter()
+                .map(|error| {
+                    assert_eq!(error.code, ErrorCode::CompileError);
+                    assert!(error.span.is_some());
+                    error.message
+                })
+                .collect()
+        };
+
+        assert!(errors(&["sync"], false).is_empty());
+        assert_eq!(
+            errors(&["sync", "async"], false),
+            ["the `sync` and `async` features can't both be enabled"]
+        );
+        assert_eq!(
+            errors(&[], true),
+            ["enable the `sync` or `async` feature", "no fixtures yet"]
+        );
+    }
+
+    #[test]
+    fn test_check_conditional_attr() {
+        use AttributeArg::*;
+        let ok = make_attr(
+            "cfg_attr",
+            AttributeArgs::Parenthesized(vec![
+                KeyValue("feature".to_string(), "json".to_string()),
+                Nested("derive".to_string(), vec![Ident("Serialize".to_string())]),
+            ]),
+        );
+        assert!(check_conditional_attr(&ok).is_ok());
+
+        let no_attr = make_attr("cfg_attr", AttributeArgs::Parenthesized(vec![Ident("test".to_string())]));
+        let err = check_conditional_attr(&no_attr).unwrap_err();
+        assert_eq!(err.message, "`cfg_attr` needs an attribute after its predicate");
+        assert_eq!(err.code, ErrorCode::MalformedAttribute);
+
+        let bad_inner_cfg = make_attr(
+            "cfg_attr",
+            AttributeArgs::Parenthesized(vec![
+                Ident("test".to_string()),
+                Nested("cfg".to_string(), vec![Nested("either".to_string(), vec![])]),
+            ]),
+        );
+        assert!(check_conditional_attr(&bad_inner_cfg).unwrap_err().message.contains("`either`"));
+
+        let string = make_attr(
+            "cfg_attr",
+            AttributeArgs::Parenthesized(vec![Ident("test".to_string()), Str("x".to_string())]),
+        );
+        assert!(check_conditional_attr(&string).is_err());
+        assert!(check_conditional_attr(&make_attr("cfg_attr", AttributeArgs::None)).is_err());
+    }
+
     #[test]
     fn test_no_attrs_included() {
         let options = CompileOptions::new();
@@ -337,6 +1265,28 @@ mod tests {
         assert!(!is_test(&attrs));
     }
 
+    #[test]
+    fn test_ignore_and_tags() {
+        let attrs = vec![
+            make_attr("test", AttributeArgs::None),
+            make_attr("ignore", AttributeArgs::None),
+            make_attr(
+                "tag",
+                AttributeArgs::Parenthesized(vec![AttributeArg::Str("slow".to_string())]),
+            ),
+            make_attr(
+                "tag",
+                AttributeArgs::Parenthesized(vec![AttributeArg::Ident("db".to_string())]),
+            ),
+        ];
+        assert!(is_ignored(&attrs));
+        assert_eq!(test_tags(&attrs), vec!["slow", "db"]);
+
+        let attrs = vec![make_attr("test", AttributeArgs::None)];
+        assert!(!is_ignored(&attrs));
+        assert!(test_tags(&attrs).is_empty());
+    }
+
     #[test]
     fn test_is_cfg_test() {
         let attrs = vec![make_attr(
@@ -355,6 +1305,76 @@ mod tests {
         assert!(!is_cfg_test(&attrs));
     }
 
+    #[test]
+    fn test_cfg_debug_assertions() {
+        let attrs = vec![make_attr(
+            "cfg",
+            AttributeArgs::Parenthesized(vec![AttributeArg::Ident("debug_assertions".to_string())]),
+        )];
+        assert!(!should_include(&attrs, &CompileOptions::new()));
+
+        let options = CompileOptions {
+            debug_assertions: true,
+            ..CompileOptions::new()
+        };
+        assert!(should_include(&attrs, &options));
+    }
+
+    #[test]
+    fn test_cfg_user_options() {
+        let mut options = CompileOptions::new();
+        options.cfg.insert(("tracing".to_string(), None));
+        options.cfg.insert(("log_level".to_string(), Some("debug".to_string())));
+
+        let cfg = |arg: AttributeArg| vec![make_attr("cfg", AttributeArgs::Parenthesized(vec![arg]))];
+        assert!(should_include(&cfg(AttributeArg::Ident("tracing".to_string())), &options));
+        assert!(!should_include(&cfg(AttributeArg::Ident("metrics".to_string())), &options));
+        assert!(!should_include(&cfg(AttributeArg::Ident("log_level".to_string())), &options));
+        let key_value = |value: &str| AttributeArg::KeyValue("log_level".to_string(), value.to_string());
+        assert!(should_include(&cfg(key_value("debug")), &options));
+        assert!(!should_include(&cfg(key_value("info")), &options));
+    }
+
+    #[test]
+    fn test_cfg_target_and_otp_release() {
+        let cfg = |arg: AttributeArg| vec![make_attr("cfg", AttributeArgs::Parenthesized(vec![arg]))];
+        let target = |name: &str| cfg(AttributeArg::KeyValue("target".to_string(), name.to_string()));
+        let at_least = |release: &str| {
+            cfg(AttributeArg::Compare("otp_release".to_string(), BinOp::Ge, release.to_string()))
+        };
+
+        let options = CompileOptions::new();
+        assert!(should_include(&target("beam"), &options));
+        assert!(!should_include(&target("native"), &options));
+        // Unknown release: no comparison holds
+        assert!(!should_include(&at_least("20"), &options));
+
+        let options = CompileOptions {
+            target: RuntimeTarget::AtomVm,
+            otp_release: Some(26),
+            ..CompileOptions::new()
+        };
+        assert!(should_include(&target("atomvm"), &options));
+        assert!(!should_include(&target("beam"), &options));
+        assert!(should_include(&at_least("26"), &options));
+        assert!(!should_include(&at_least("27"), &options));
+        let before = cfg(AttributeArg::Compare("otp_release".to_string(), BinOp::Lt, "27".to_string()));
+        assert!(should_include(&before, &options));
+        let exactly = cfg(AttributeArg::KeyValue("otp_release".to_string(), "26".to_string()));
+        assert!(should_include(&exactly, &options));
+
+        let source = "#[cfg(otp_release >= \"26\")]\nfn f() -> int {\n    1\n}\n";
+        let module = crate::compiler::Parser::new(source).parse_file("app").unwrap();
+        let Some(Item::Function(func)) = module.items.last() else {
+            panic!("expected a function");
+        };
+        assert_eq!(func.attrs[0].args, at_least("26")[0].args);
+        let err = crate::compiler::Parser::new("#[cfg(otp_release >= \"new\")]\nfn f() -> int {\n    1\n}\n")
+            .parse_file("app")
+            .unwrap_err();
+        assert!(err.message.contains("release number"), "{}", err.message);
+    }
+
     #[test]
     fn test_multiple_cfg_attrs() {
         // Both conditions must be true
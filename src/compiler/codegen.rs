@@ -7,8 +7,10 @@ use crate::compiler::ast::{
     EnumPatternFields, EnumVariantArgs, Expr, Function, Item, Module as AstModule,
     Pattern as AstPattern, Stmt, UnaryOp, UseDecl, UseTree,
 };
-use crate::instruction::{Instruction, Operand, Pattern as VmPattern, Register, Source};
-use crate::Module;
+use crate::instruction::{
+    Instruction, Operand, Pattern as VmPattern, Register, Source, SpawnOpts,
+};
+use crate::{Module, OverflowPolicy, Priority};
 
 /// Code generation error.
 #[derive(Debug, Clone)]
@@ -239,7 +241,7 @@ impl Codegen {
                 Item::Use(_) => {
                     // Already processed in first pass
                 }
-                Item::ExternMod(_) => {
+                Item::ExternMod(_) | Item::ExternBlock(_) => {
                     // External module declarations don't generate code
                     // They're used for type checking FFI calls
                 }
@@ -247,6 +249,9 @@ impl Codegen {
                     // Type aliases don't generate code
                     // They're resolved at compile time by the type checker
                 }
+                Item::CompileError(_) => {
+                    // Reported by the cfg pass if it was left in
+                }
             }
         }
 
@@ -339,12 +344,14 @@ impl Codegen {
                     || then_block.stmts.iter().any(|s| match s {
                         Stmt::Let { value, .. } => Self::contains_call(value),
                         Stmt::Expr { expr: e, .. } => Self::contains_call(e),
+                        Stmt::Cfg { .. } => true,
                     })
                     || then_block.expr.as_ref().map_or(false, |e| Self::contains_call(e))
                     || else_block.as_ref().map_or(false, |b| {
                         b.stmts.iter().any(|s| match s {
                             Stmt::Let { value, .. } => Self::contains_call(value),
                             Stmt::Expr { expr: e, .. } => Self::contains_call(e),
+                            Stmt::Cfg { .. } => true,
                         }) || b.expr.as_ref().map_or(false, |e| Self::contains_call(e))
                     })
             }
@@ -356,6 +363,7 @@ impl Codegen {
                 block.stmts.iter().any(|s| match s {
                     Stmt::Let { value, .. } => Self::contains_call(value),
                     Stmt::Expr { expr: e, .. } => Self::contains_call(e),
+                    Stmt::Cfg { .. } => true,
                 }) || block.expr.as_ref().map_or(false, |e| Self::contains_call(e))
             }
             Expr::Tuple(elems) | Expr::List(elems) => elems.iter().any(|e| Self::contains_call(e)),
@@ -388,6 +396,7 @@ impl Codegen {
                 self.compile_expr(expr)?;
                 Ok(())
             }
+            Stmt::Cfg { .. } => Err(CodegenError::new("`#[cfg]` statement left in a module that wasn't configured")),
         }
     }
 
@@ -467,7 +476,7 @@ impl Codegen {
                 }
             }
 
-            Expr::Binary { op, left, right } => {
+            Expr::Binary { op, left, right, .. } => {
                 let left_reg = self.compile_expr(left)?;
 
                 // If right side contains a call, save left to stack (calls clobber registers)
@@ -1000,6 +1009,10 @@ impl Codegen {
             }
 
             Expr::Call { func, args, .. } => {
+                if let Some(dest) = self.compile_intrinsic_call(func, args)? {
+                    return Ok(dest);
+                }
+
                 // Save current registers before call
                 let saved_next = self.regs.next_reg;
 
@@ -1475,7 +1488,282 @@ impl Codegen {
                     "for loops are not yet implemented for WASM target",
                 ))
             }
+
+            Expr::CompileError { message, .. } => Err(CodegenError::new(message.clone())),
+
+            Expr::Format { .. } => Err(CodegenError::new(
+                "format! is not yet implemented for WASM target",
+            )),
+        }
+    }
+
+    /// Compile a call the VM runs as instructions rather than through a
+    /// loaded module: `self()` and the `process` and `timer` functions the
+    /// scheduler implements itself. Returns `None` for any other call.
+    fn compile_intrinsic_call(
+        &mut self,
+        func: &Expr,
+        args: &[Expr],
+    ) -> CodegenResult<Option<Register>> {
+        let (module, function) = match func {
+            Expr::Ident(name) if name == "self" && args.is_empty() => {
+                let dest = self.regs.alloc();
+                self.emit(Instruction::SelfPid { dest });
+                return Ok(Some(dest));
+            }
+            Expr::Ident(name) => match self.imports.get(name) {
+                Some((module, function)) => (module.clone(), function.clone()),
+                None => return Ok(None),
+            },
+            Expr::Path { segments } if segments.len() == 2 => {
+                (segments[0].clone(), segments[1].clone())
+            }
+            _ => return Ok(None),
+        };
+        let call = format!("{}::{}", module, function);
+
+        let dest = match (module.as_str(), function.as_str(), args) {
+            ("process", "set_mailbox_limit", [max, policy]) => {
+                let max = literal_u32(&call, max)?;
+                let policy = match literal_atom(&call, policy)? {
+                    "drop_newest" => OverflowPolicy::DropNewest,
+                    "drop_oldest" => OverflowPolicy::DropOldest,
+                    "crash_sender" => OverflowPolicy::CrashSender,
+                    "suspend_sender" => OverflowPolicy::SuspendSender,
+                    other => {
+                        return Err(CodegenError::new(format!(
+                            "unknown mailbox overflow policy :{}",
+                            other
+                        )))
+                    }
+                };
+                self.emit(Instruction::SetMailboxLimit { max, policy });
+                self.load_atom("ok")
+            }
+            ("process", "message_queue_len" | "label" | "priority", [pid]) => {
+                let pid = self.compile_expr(pid)?;
+                self.compile_info_option(pid, &function)
+            }
+            ("process", "set_label", [label]) => {
+                let label = literal_string(&call, label)?;
+                self.emit(Instruction::SetLabel { label });
+                self.load_atom("ok")
+            }
+            ("process", "set_priority", [level]) => {
+                // Returns the priority it replaces
+                let priority = literal_priority(&call, level)?;
+                let pid = self.regs.alloc();
+                self.emit(Instruction::SelfPid { dest: pid });
+                let info = self.regs.alloc();
+                self.emit(Instruction::ProcessInfoItem {
+                    pid,
+                    item: "priority".to_string(),
+                    dest: info,
+                });
+                let old = self.regs.alloc();
+                self.emit(Instruction::TupleElement {
+                    tuple: info,
+                    index: 1,
+                    dest: old,
+                });
+                self.emit(Instruction::SetPriority { priority });
+                old
+            }
+            ("process", "spawn_link", [f]) => {
+                let opts = SpawnOpts {
+                    link: true,
+                    ..SpawnOpts::default()
+                };
+                self.compile_spawn_opt(&call, f, opts)?
+            }
+            ("process", "spawn_monitor", [f]) => {
+                let opts = SpawnOpts {
+                    monitor: true,
+                    ..SpawnOpts::default()
+                };
+                self.compile_spawn_opt(&call, f, opts)?
+            }
+            ("process", "spawn_with", [f, opts]) => {
+                let opts = spawn_options(&call, opts)?;
+                let monitor = opts.monitor;
+                let spawned = self.compile_spawn_opt(&call, f, opts)?;
+                // (Pid, Option<Ref>), where SpawnOpt gives the pid or {pid, ref}
+                if monitor {
+                    let pid = self.regs.alloc();
+                    self.emit(Instruction::TupleElement {
+                        tuple: spawned,
+                        index: 0,
+                        dest: pid,
+                    });
+                    let monitor_ref = self.regs.alloc();
+                    self.emit(Instruction::TupleElement {
+                        tuple: spawned,
+                        index: 1,
+                        dest: monitor_ref,
+                    });
+                    let some = self.load_atom("Some");
+                    let some = self.make_tuple(&[some, monitor_ref]);
+                    self.make_tuple(&[pid, some])
+                } else {
+                    let none = self.load_atom("None");
+                    self.make_tuple(&[spawned, none])
+                }
+            }
+            ("timer", "interval", [ms, to, msg]) => {
+                let interval = literal_u32(&call, ms)?;
+                let to = self.compile_expr(to)?;
+                let msg = self.compile_expr(msg)?;
+                let timer_ref = self.regs.alloc();
+                self.emit(Instruction::SendInterval {
+                    interval,
+                    to: Source::Reg(to),
+                    msg,
+                    dest: timer_ref,
+                });
+                let ok = self.load_atom("ok");
+                self.make_tuple(&[ok, timer_ref])
+            }
+            ("timer", "cancel_interval", [timer_ref]) => {
+                let timer_ref = self.compile_expr(timer_ref)?;
+                let remaining = self.regs.alloc();
+                self.emit(Instruction::CancelTimer {
+                    timer_ref,
+                    dest: remaining,
+                });
+                let ok = self.load_atom("ok");
+                let cancel = self.load_atom("cancel");
+                self.make_tuple(&[ok, cancel])
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(dest))
+    }
+
+    /// Look up one `process_info` item of `pid` as an `Option`: `Some(value)`
+    /// while the process is alive, `None` once it has exited.
+    fn compile_info_option(&mut self, pid: Register, item: &str) -> Register {
+        let info = self.regs.alloc();
+        self.emit(Instruction::ProcessInfoItem {
+            pid,
+            item: item.to_string(),
+            dest: info,
+        });
+        let alive = self.regs.alloc();
+        self.emit(Instruction::IsTuple {
+            source: info,
+            dest: alive,
+        });
+        let dest = self.regs.alloc();
+        let jump_none = self.code.len();
+        self.emit(Instruction::JumpUnless {
+            cond: Operand::Reg(alive),
+            target: 0,
+        });
+
+        let value = self.regs.alloc();
+        self.emit(Instruction::TupleElement {
+            tuple: info,
+            index: 1,
+            dest: value,
+        });
+        // An unlabeled process reports {label, undefined}
+        let jump_unlabeled = (item == "label").then(|| {
+            let labeled = self.regs.alloc();
+            self.emit(Instruction::IsString {
+                source: value,
+                dest: labeled,
+            });
+            let jump = self.code.len();
+            self.emit(Instruction::JumpUnless {
+                cond: Operand::Reg(labeled),
+                target: 0,
+            });
+            jump
+        });
+        let some = self.load_atom("Some");
+        let some = self.make_tuple(&[some, value]);
+        self.emit(Instruction::Move { source: some, dest });
+        let jump_end = self.code.len();
+        self.emit(Instruction::Jump { target: 0 });
+
+        let none_start = self.code.len();
+        self.patch_jump(jump_none, none_start);
+        if let Some(jump) = jump_unlabeled {
+            self.patch_jump(jump, none_start);
+        }
+        self.emit(Instruction::LoadAtom {
+            name: "None".to_string(),
+            dest,
+        });
+        let end = self.code.len();
+        self.patch_jump(jump_end, end);
+        dest
+    }
+
+    /// Spawn a process running the zero-argument function `f` with `opts`.
+    /// The result is the child's pid, or `{pid, ref}` when monitoring.
+    fn compile_spawn_opt(
+        &mut self,
+        call: &str,
+        f: &Expr,
+        opts: SpawnOpts,
+    ) -> CodegenResult<Register> {
+        let (module, function) = match f {
+            Expr::Ident(name) if self.regs.lookup(name).is_none() => {
+                match self.imports.get(name) {
+                    Some((module, function)) => (module.clone(), function.clone()),
+                    None => (self.module_name.clone(), name.clone()),
+                }
+            }
+            Expr::Path { segments } if segments.len() == 2 => {
+                (segments[0].clone(), segments[1].clone())
+            }
+            _ => {
+                return Err(CodegenError::new(format!(
+                    "{} needs a function name on the VM target",
+                    call
+                )))
+            }
+        };
+        let dest = self.regs.alloc();
+        self.emit(Instruction::SpawnOpt {
+            code: vec![
+                Instruction::CallMFA {
+                    module,
+                    function,
+                    arity: 0,
+                },
+                Instruction::End,
+            ],
+            opts,
+            dest,
+        });
+        Ok(dest)
+    }
+
+    /// Load an atom into a fresh register.
+    fn load_atom(&mut self, name: &str) -> Register {
+        let dest = self.regs.alloc();
+        self.emit(Instruction::LoadAtom {
+            name: name.to_string(),
+            dest,
+        });
+        dest
+    }
+
+    /// Build a tuple from registers into a fresh register.
+    fn make_tuple(&mut self, elements: &[Register]) -> Register {
+        for &element in elements {
+            self.emit(Instruction::Push {
+                source: Operand::Reg(element),
+            });
         }
+        let dest = self.regs.alloc();
+        self.emit(Instruction::MakeTuple {
+            arity: elements.len() as u8,
+            dest,
+        });
+        dest
     }
 
     /// Compile a pattern to VM pattern.
@@ -1810,15 +2098,105 @@ impl Default for Codegen {
     }
 }
 
+/// An argument the VM needs at compile time, such as a timer interval.
+fn literal_u32(call: &str, expr: &Expr) -> CodegenResult<u32> {
+    match expr {
+        Expr::Int(n) => u32::try_from(*n).map_err(|_| {
+            CodegenError::new(format!("{} takes a value from 0 to {}, got {}", call, u32::MAX, n))
+        }),
+        _ => Err(CodegenError::new(format!(
+            "{} needs an integer literal on the VM target",
+            call
+        ))),
+    }
+}
+
+/// An atom argument the VM needs at compile time, such as an overflow policy.
+fn literal_atom<'a>(call: &str, expr: &'a Expr) -> CodegenResult<&'a str> {
+    match expr {
+        Expr::Atom(atom) => Ok(atom),
+        _ => Err(CodegenError::new(format!(
+            "{} needs an atom literal on the VM target",
+            call
+        ))),
+    }
+}
+
+/// A string argument the VM needs at compile time, such as a label.
+fn literal_string(call: &str, expr: &Expr) -> CodegenResult<String> {
+    match expr {
+        Expr::String(s) => Ok(s.clone()),
+        _ => Err(CodegenError::new(format!(
+            "{} needs a string literal on the VM target",
+            call
+        ))),
+    }
+}
+
+/// A scheduling priority atom: `:low`, `:normal` or `:high`.
+fn literal_priority(call: &str, expr: &Expr) -> CodegenResult<Priority> {
+    match literal_atom(call, expr)? {
+        "low" => Ok(Priority::Low),
+        "normal" => Ok(Priority::Normal),
+        "high" => Ok(Priority::High),
+        other => Err(CodegenError::new(format!(
+            "priority :{} is not supported on the VM target",
+            other
+        ))),
+    }
+}
+
+/// The options of a `SpawnOptions { .. }` literal. Fields left out, or
+/// filled in from `..process::spawn_options()`, keep their defaults.
+fn spawn_options(call: &str, expr: &Expr) -> CodegenResult<SpawnOpts> {
+    let literal_error = || {
+        CodegenError::new(format!(
+            "{} needs a SpawnOptions literal on the VM target",
+            call
+        ))
+    };
+    let Expr::StructInit { fields, base, .. } = expr else {
+        return Err(literal_error());
+    };
+    if let Some(base) = base {
+        let defaults = match base.as_ref() {
+            Expr::Call { func, args, .. } if args.is_empty() => match func.as_ref() {
+                Expr::Ident(name) => name == "spawn_options",
+                Expr::Path { segments } => segments.last().is_some_and(|s| s == "spawn_options"),
+                _ => false,
+            },
+            _ => false,
+        };
+        if !defaults {
+            return Err(literal_error());
+        }
+    }
+
+    let mut opts = SpawnOpts::default();
+    for (field, value) in fields {
+        match (field.as_str(), value) {
+            ("link", Expr::Bool(link)) => opts.link = *link,
+            ("monitor", Expr::Bool(monitor)) => opts.monitor = *monitor,
+            ("label", Expr::String(label)) => {
+                opts.label = Some(label.clone()).filter(|l| !l.is_empty())
+            }
+            ("priority", priority) => opts.priority = literal_priority(call, priority)?,
+            _ => return Err(literal_error()),
+        }
+    }
+    Ok(opts)
+}
+
 /// Convenience function to compile source code to a VM module.
 /// Expects source with `mod name { ... }` wrapper.
 pub fn compile(source: &str) -> CodegenResult<Module> {
     use crate::compiler::Parser;
 
     let mut parser = Parser::new(source);
-    let ast = parser
+    let mut ast = parser
         .parse_module()
         .map_err(|e| CodegenError::new(e.to_string()))?;
+    configure_native(&mut ast)?;
 
     Codegen::compile_module(&ast)
 }
@@ -1828,13 +2206,40 @@ pub fn compile_file(source: &str, module_name: &str) -> CodegenResult<Module> {
     use crate::compiler::Parser;
 
     let mut parser = Parser::new(source);
-    let ast = parser
+    let mut ast = parser
         .parse_file(module_name)
         .map_err(|e| CodegenError::new(e.to_string()))?;
+    configure_native(&mut ast)?;
 
     Codegen::compile_module(&ast)
 }
 
+/// Drop the functions `#[cfg]` leaves out on the built-in VM, where
+/// `cfg(target = "native")` holds. Fails with the first `compile_error!`
+/// left in.
+fn configure_native(ast: &mut AstModule) -> CodegenResult<()> {
+    use crate::compiler::cfg::{configure_module, should_include};
+    use crate::config::{CompileOptions, RuntimeTarget};
+
+    let options = CompileOptions {
+        target: RuntimeTarget::Native,
+        ..CompileOptions::default()
+    };
+    if let Some(error) = configure_module(ast, &options).errors.into_iter().next() {
+        return Err(CodegenError::new(error.message));
+    }
+    ast.items.retain(|item| match item {
+        Item::Function(func) => should_include(&func.attrs, &options),
+        _ => true,
+    });
+    for item in &mut ast.items {
+        if let Item::Impl(block) = item {
+            block.methods.retain(|method| should_include(&method.attrs, &options));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -2188,4 +2593,171 @@ mod tests {
         let process = scheduler.processes.get(&crate::Pid(0)).unwrap();
         assert_eq!(process.registers[0], crate::Value::Int(1));
     }
+
+    #[test]
+    fn test_compile_timer_interval() {
+        let source = r#"
+            mod ticker {
+                pub fn main() -> int {
+                    match timer::interval(5, self(), :tick) {
+                        (:ok, timer) => {
+                            // Timer messages reach the VM mailbox as strings
+                            let first = receive { _ => 1, after 100 => { 0 } };
+                            let second = receive { _ => 1, after 100 => { 0 } };
+                            timer::cancel_interval(timer);
+                            first + second
+                        }
+                    }
+                }
+            }
+        "#;
+
+        let module = compile(source).unwrap();
+        assert!(module
+            .code
+            .iter()
+            .any(|i| matches!(i, Instruction::SendInterval { interval: 5, .. })));
+
+        let run = crate::native::run_function(vec![module], "ticker", "main", vec![]).unwrap();
+        assert!(run.success());
+        assert_eq!(run.result, crate::Value::Int(2));
+    }
+
+
+    #[test]
+    fn test_compile_mailbox_limit_and_queue_len() {
+        let source = r#"
+            mod inbox {
+                pub fn main() -> Option<int> {
+                    process::set_mailbox_limit(2, :drop_newest);
+                    match timer::interval(1, self(), :tick) {
+                        (:ok, timer) => {
+                            // Nothing matches, so the ticks stay queued
+                            receive { :never => 0, after 50 => { 0 } };
+                            timer::cancel_interval(timer);
+                            process::message_queue_len(self())
+                        }
+                    }
+                }
+            }
+        "#;
+
+        let module = compile(source).unwrap();
+        let run = crate::native::run_function(vec![module], "inbox", "main", vec![]).unwrap();
+        assert!(run.success());
+        assert_eq!(
+            run.result,
+            crate::Value::Tuple(vec![
+                crate::Value::Atom("Some".to_string()),
+                crate::Value::Int(2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_compile_spawn_options() {
+        let source = r#"
+            mod spawner {
+                pub fn worker() -> int {
+                    receive { _ => 1, after 100 => { 0 } }
+                }
+
+                pub fn watch() -> int {
+                    match process::spawn_monitor(worker) {
+                        (_, _) => receive {
+                            (:DOWN, _, :process, _, _) => 1,
+                            after 500 => { 0 }
+                        }
+                    }
+                }
+
+                pub fn start() -> (Pid, Option<Ref>) {
+                    process::spawn_with(worker, SpawnOptions {
+                        link: true,
+                        label: "worker",
+                        priority: :high,
+                        ..process::spawn_options()
+                    })
+                }
+            }
+        "#;
+
+        let module = compile(source).unwrap();
+        let run = crate::native::run_function(vec![module.clone()], "spawner", "watch", vec![])
+            .unwrap();
+        assert_eq!(run.result, crate::Value::Int(1));
+
+        let mut scheduler = Scheduler::new();
+        scheduler.load_module(module).unwrap();
+        let parent = scheduler.spawn(vec![
+            Instruction::CallMFA {
+                module: "spawner".to_string(),
+                function: "start".to_string(),
+                arity: 0,
+            },
+            // Stay alive, and so linked, while the child is inspected
+            Instruction::Receive { dest: Register(1) },
+            Instruction::End,
+        ]);
+        scheduler.step(10);
+
+        let crate::Value::Tuple(result) = &scheduler.processes[&parent].registers[0] else {
+            panic!("expected (pid, None)");
+        };
+        let crate::Value::Pid(child) = result[0] else {
+            panic!("expected a pid");
+        };
+        assert_eq!(result[1], crate::Value::Atom("None".to_string()));
+        let child = &scheduler.processes[&child];
+        assert_eq!(child.label.as_deref(), Some("worker"));
+        assert_eq!(child.priority, Priority::High);
+        assert!(child.links.contains(&parent));
+    }
+
+    #[test]
+    fn test_compile_intrinsic_needs_literals() {
+        let source = r#"
+            mod inbox {
+                pub fn main(n: int) -> Atom {
+                    process::set_mailbox_limit(n, :drop_newest)
+                }
+            }
+        "#;
+
+        let err = compile(source).unwrap_err();
+        assert!(err.message.contains("process::set_mailbox_limit needs an integer literal"));
+    }
+
+
+    #[test]
+    fn test_compile_label_and_priority() {
+        let source = r#"
+            mod labels {
+                pub fn main() -> (Option<String>, Atom, Option<String>, Option<Atom>) {
+                    let before = process::label(self());
+                    process::set_label("cache");
+                    let old = process::set_priority(:high);
+                    (before, old, process::label(self()), process::priority(self()))
+                }
+            }
+        "#;
+
+        let module = compile(source).unwrap();
+        let run = crate::native::run_function(vec![module], "labels", "main", vec![]).unwrap();
+        assert!(run.success());
+
+        let some = |value| {
+            crate::Value::Tuple(vec![crate::Value::Atom("Some".to_string()), value])
+        };
+        assert_eq!(
+            run.result,
+            crate::Value::Tuple(vec![
+                crate::Value::Atom("None".to_string()),
+                crate::Value::Atom("normal".to_string()),
+                some(crate::Value::String("cache".to_string())),
+                some(crate::Value::Atom("high".to_string())),
+            ])
+        );
+    }
+
 }
@@ -4,9 +4,11 @@
 //! which can then be compiled to BEAM bytecode using `erlc +from_core`.
 
 use crate::compiler::cfg;
+use crate::compiler::ice;
 use crate::config::CompileOptions;
+use crate::timings::Phase;
 use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, PoisonError, RwLock};
 
 /// Registry for cross-module generic functions.
 /// Stores generic function ASTs keyed by (module_name, function_name).
@@ -47,11 +49,12 @@ impl GenericFunctionRegistry {
 pub type SharedGenericRegistry = Arc<RwLock<GenericFunctionRegistry>>;
 
 use crate::compiler::ast::{
-    BinOp, BitEndianness, BitSegmentType, BitSignedness, BitStringSegment, Block,
+    AttributeArgs, BinOp, BitEndianness, BitSegmentType, BitSignedness, BitStringSegment, Block,
     EnumPatternFields, EnumVariant, EnumVariantArgs, Expr, ForClause, Function, Item, MatchArm,
-    Module, ModuleContext, ModulePath, PathPrefix, Pattern, Stmt, StringPart, TraitDef, TraitImpl,
-    Type, UnaryOp, UseDecl, UseTree, VariantKind,
+    FormatPart, Module, ModuleContext, ModulePath, PathPrefix, Pattern, Stmt, StringPart, TraitDef,
+    TraitImpl, Type, UnaryOp, UseDecl, UseTree, VariantKind,
 };
+use crate::compiler::lexer::Span;
 use crate::compiler::typeck::StructInfo;
 
 /// Core Erlang emitter error.
@@ -147,6 +150,16 @@ pub struct CoreErlangEmitter {
     struct_info: HashMap<String, StructInfo>,
     /// Variable type tracking for record field access (var_name -> struct_type_name)
     variable_types: HashMap<String, String>,
+    /// Byte offset of each line of the module's source, when instrumenting
+    /// for coverage. Empty otherwise.
+    line_starts: Vec<usize>,
+    /// Lines given a coverage counter so far
+    cover_lines: std::collections::BTreeSet<usize>,
+    /// Variables a debugger probe can show at the point being emitted,
+    /// innermost last. Tracks function parameters and `let` bindings.
+    debug_scope: Vec<String>,
+    /// Lines given a debugger probe so far
+    debug_lines: std::collections::BTreeSet<usize>,
 }
 
 impl CoreErlangEmitter {
@@ -178,6 +191,10 @@ impl CoreErlangEmitter {
             extern_function_names: HashMap::new(),
             struct_info: HashMap::new(),
             variable_types: HashMap::new(),
+            line_starts: Vec::new(),
+            cover_lines: std::collections::BTreeSet::new(),
+            debug_scope: Vec::new(),
+            debug_lines: std::collections::BTreeSet::new(),
         }
     }
 
@@ -270,6 +287,9 @@ impl CoreErlangEmitter {
         "io", "list", "enumerable", "iterator", "option", "result",
         "string", "map", "file", "timer", "display", "convert",
         "process", "genserver", "supervisor", "application", "logger",
+        "task", "ets", "port", "node", "code", "codec", "json", "regex",
+        "time", "random", "math", "agent", "stream", "crypto", "iodata",
+        "ops",
     ];
 
     /// Resolve a simple module name string, adding dream:: prefix for Dream modules.
@@ -333,8 +353,29 @@ impl CoreErlangEmitter {
         }
     }
 
+    /// Resolve the BEAM module backing a module-level trait declaration,
+    /// for use in the `-behaviour` attribute.
+    /// `impl genserver::GenServer;` -> `dream::genserver`. An unqualified
+    /// trait name is looked up in the module's imports; traits that cannot
+    /// be traced back to a module produce no attribute.
+    fn behaviour_module(&self, trait_name: &str) -> Option<String> {
+        let module = match trait_name.rsplit_once("::") {
+            Some((module, _)) => self.resolve_user_module_path(module),
+            None => self.imports.get(trait_name)?.0.clone(),
+        };
+        Some(Self::resolve_module_name(&module))
+    }
+
     /// Collect imports from a UseDecl into the imports map.
     fn collect_imports(&mut self, use_decl: &UseDecl) {
+        // `use elixir::Enum` names an Elixir module, which the type checker
+        // registers with the extern modules
+        let module = match &use_decl.tree {
+            UseTree::Path { module, .. } | UseTree::Glob { module } | UseTree::Group { module, .. } => module,
+        };
+        if module.prefix == PathPrefix::None && module.segments.first().is_some_and(|s| s == "elixir") {
+            return;
+        }
         match &use_decl.tree {
             UseTree::Path { module, name, rename } => {
                 let local_name = rename.as_ref().unwrap_or(name).clone();
@@ -355,6 +396,20 @@ impl CoreErlangEmitter {
         }
     }
 
+    /// The Elixir module a struct marked `#[elixir_struct]` stands for,
+    /// which is its `__struct__` value.
+    fn elixir_struct_module(&self, name: &str) -> Option<String> {
+        self.struct_info.get(name).and_then(|info| info.elixir_module.clone())
+    }
+
+    /// Whether `name` is a tuple struct of one field. Such a newtype costs
+    /// nothing at runtime: its value is the value it wraps.
+    fn is_newtype(&self, name: &str) -> bool {
+        self.struct_info
+            .get(name)
+            .is_some_and(|info| info.is_tuple() && info.fields.len() == 1)
+    }
+
     /// Generate a fresh variable name.
     #[allow(dead_code)]
     fn fresh_var(&mut self) -> String {
@@ -401,22 +456,29 @@ impl CoreErlangEmitter {
 
     /// Collect variable names from a pattern and add them to the variables set.
     fn collect_pattern_vars(&mut self, pattern: &Pattern) {
+        let mut names = Vec::new();
+        Self::pattern_bindings(pattern, &mut names);
+        self.variables.extend(names);
+    }
+
+    /// Variable names a pattern binds, in the order they appear.
+    fn pattern_bindings(pattern: &Pattern, names: &mut Vec<String>) {
         match pattern {
             Pattern::Ident(name) if name != "_" => {
-                self.variables.insert(name.clone());
+                names.push(name.clone());
             }
             Pattern::Tuple(patterns) | Pattern::List(patterns) => {
                 for p in patterns {
-                    self.collect_pattern_vars(p);
+                    Self::pattern_bindings(p, names);
                 }
             }
             Pattern::ListCons { head, tail } => {
-                self.collect_pattern_vars(head);
-                self.collect_pattern_vars(tail);
+                Self::pattern_bindings(head, names);
+                Self::pattern_bindings(tail, names);
             }
             Pattern::Struct { fields, .. } => {
                 for (_, field_pat) in fields {
-                    self.collect_pattern_vars(field_pat);
+                    Self::pattern_bindings(field_pat, names);
                 }
             }
             Pattern::Enum { fields, .. } => {
@@ -424,12 +486,12 @@ impl CoreErlangEmitter {
                     EnumPatternFields::Unit => {}
                     EnumPatternFields::Tuple(patterns) => {
                         for p in patterns {
-                            self.collect_pattern_vars(p);
+                            Self::pattern_bindings(p, names);
                         }
                     }
                     EnumPatternFields::Struct(field_patterns) => {
                         for (_, p) in field_patterns {
-                            self.collect_pattern_vars(p);
+                            Self::pattern_bindings(p, names);
                         }
                     }
                 }
@@ -459,6 +521,8 @@ impl CoreErlangEmitter {
         block.stmts.iter().any(|stmt| match stmt {
             Stmt::Expr { expr: e, .. } => Self::contains_return(e),
             Stmt::Let { value, .. } => Self::contains_return(value),
+            // Removed by conditional compilation before this runs
+            Stmt::Cfg { .. } => false,
         }) || block.expr.as_ref().is_some_and(|e| Self::contains_return(e))
     }
 
@@ -522,7 +586,7 @@ impl CoreErlangEmitter {
         }
 
         if matches.len() == 1 {
-            Some(matches.into_iter().next().unwrap())
+            matches.pop()
         } else {
             // Zero or multiple matches - can't disambiguate
             None
@@ -541,7 +605,7 @@ impl CoreErlangEmitter {
     }
 
     /// Check if a function name is a built-in function (BIF).
-    fn is_bif(name: &str) -> bool {
+    pub(crate) fn is_bif(name: &str) -> bool {
         matches!(
             name,
             "self"
@@ -663,7 +727,7 @@ impl CoreErlangEmitter {
     fn infer_expr_type(&self, expr: &Expr) -> String {
         match expr {
             Expr::Int(_) => "int".to_string(),
-            Expr::String(_) | Expr::StringInterpolation(_) => "string".to_string(),
+            Expr::String(_) | Expr::StringInterpolation(_) | Expr::Format { .. } => "string".to_string(),
             Expr::Atom(_) => "atom".to_string(),
             Expr::Bool(_) => "bool".to_string(),
             Expr::BitString(_) => "binary".to_string(),
@@ -952,7 +1016,10 @@ impl CoreErlangEmitter {
 
             // Match on fully qualified atom 'module::Type' (includes dream:: prefix)
             // Use actual_type for the pattern (not func_prefix which may include trait name)
-            self.emit(&format!("<'{}::{}'>", self.module_name, actual_type));
+            let tag = self
+                .elixir_struct_module(actual_type)
+                .unwrap_or_else(|| format!("{}::{}", self.module_name, actual_type));
+            self.emit(&format!("<'{}'>", tag));
             self.emit(" when 'true' ->");
             self.newline();
             self.indent += 1;
@@ -1283,6 +1350,7 @@ impl CoreErlangEmitter {
 
     /// Emit a complete Core Erlang module.
     pub fn emit_module(&mut self, module: &Module) -> CoreErlangResult<String> {
+        let _scope = ice::module(Phase::Codegen, module);
         // All Dream modules are prefixed with dream:: (like Elixir uses Elixir.)
         // This ensures Dream modules are properly namespaced on the BEAM
         // Unless skip_stdlib_prefix is set (for REPL modules)
@@ -1438,6 +1506,35 @@ impl CoreErlangEmitter {
             }
         }
 
+        // NIFs: functions of `extern "nif"` blocks, loaded from one library
+        let mut nif_library = None;
+        let mut nifs: Vec<(String, usize)> = Vec::new();
+        for item in &module.items {
+            if let Item::ExternBlock(block) = item {
+                if block.abi == "nif" && cfg::should_include(&block.attrs, &self.compile_options) {
+                    let library = block
+                        .attrs
+                        .iter()
+                        .find_map(|attr| match &attr.args {
+                            AttributeArgs::Eq(value) if attr.name == "name" => Some(value.clone()),
+                            _ => None,
+                        })
+                        .unwrap_or_else(|| module.name.rsplit("::").next().unwrap_or(&module.name).to_string());
+                    nif_library.get_or_insert(library);
+                    nifs.extend(block.functions.iter().map(|(_, f)| (f.name.clone(), f.params.len())));
+                }
+            }
+        }
+        self.local_functions.extend(nifs.iter().cloned());
+
+        if self.compile_options.coverage || self.compile_options.debug {
+            if let Some(source) = &module.source {
+                self.line_starts = std::iter::once(0)
+                    .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+                    .collect();
+            }
+        }
+
         // Module header (with Dream. prefix)
         self.emit(&format!("module '{}'", self.module_name));
 
@@ -1568,12 +1665,61 @@ impl CoreErlangEmitter {
             }
         }
 
+        // NIF stubs are exported like Rustler's, so other modules can call them
+        for (name, arity) in &nifs {
+            exports.push(format!("'{}'/{}", name, arity));
+        }
+
+        if self.compile_options.coverage && !self.line_starts.is_empty() {
+            exports.push(format!("'{}'/0", Self::COVER_FUNCTION));
+        }
+        if self.compile_options.debug && !self.line_starts.is_empty() {
+            exports.push(format!("'{}'/0", Self::DEBUG_LINES_FUNCTION));
+        }
+
+        // In test builds, a generator EUnit finds by its `_test_` suffix
+        // runs the module's tests under `rebar3 eunit` and `:eunit.test/1`.
+        let eunit_tests: Vec<String> = if self.compile_options.test_mode {
+            module
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    Item::Function(f)
+                        if f.params.is_empty()
+                            && cfg::is_test(&f.attrs)
+                            && !cfg::is_ignored(&f.attrs)
+                            && cfg::should_include(&f.attrs, &self.compile_options) =>
+                    {
+                        Some(f.name.clone())
+                    }
+                    _ => None,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if !eunit_tests.is_empty() {
+            exports.push(format!("'{}'/0", Self::EUNIT_GENERATOR));
+        }
+
         self.emit(" [");
         self.emit(&exports.join(", "));
         self.emit("]");
 
         self.newline();
-        self.emit("    attributes []");
+        let mut attributes: Vec<String> = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::TraitDecl(decl) => self.behaviour_module(&decl.trait_name),
+                _ => None,
+            })
+            .map(|m| format!("'behaviour' = ['{}']", m))
+            .collect();
+        if nif_library.is_some() {
+            attributes.push(format!("'on_load' = [{{'{}', 0}}]", Self::NIF_LOADER));
+        }
+        self.emit(&format!("    attributes [{}]", attributes.join(", ")));
         self.newline();
 
         // Emit grouped functions (supports multi-clause functions)
@@ -1639,8 +1785,24 @@ impl CoreErlangEmitter {
         // Emit monomorphized functions (local)
         self.emit_monomorphized_functions()?;
 
-        // Emit cross-module monomorphized functions
+        // Emit cross-module monomorphized functions. Their spans point into
+        // other modules' sources, so they are not instrumented here.
+        let line_starts = std::mem::take(&mut self.line_starts);
         self.emit_cross_module_monomorphized_functions()?;
+        self.line_starts = line_starts;
+
+        if self.compile_options.coverage && !self.line_starts.is_empty() {
+            self.emit_cover_function();
+        }
+        if self.compile_options.debug && !self.line_starts.is_empty() {
+            self.emit_debug_functions();
+        }
+        if !eunit_tests.is_empty() {
+            self.emit_eunit_generator(&eunit_tests);
+        }
+        if let Some(library) = &nif_library {
+            self.emit_nifs(library, &nifs);
+        }
 
         self.newline();
         self.emit("end");
@@ -1649,6 +1811,186 @@ impl CoreErlangEmitter {
         Ok(self.output.clone())
     }
 
+    /// Exported function listing the lines that have coverage counters.
+    const COVER_FUNCTION: &str = "__dream_cover__";
+
+    /// Name of the ETS table coverage counters are kept in.
+    const COVER_TABLE: &str = "dream_cover";
+
+    /// When instrumenting for coverage, count an execution of the line
+    /// `span` starts on. Emits `do <bump>` so that whatever is emitted
+    /// next becomes the expression's value.
+    fn emit_cover_point(&mut self, span: &Span) {
+        if !self.compile_options.coverage || self.line_starts.is_empty() || span.start >= span.end {
+            return;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= span.start);
+        self.cover_lines.insert(line);
+        let key = format!("{{'{}', {}}}", self.module_name, line);
+        self.emit(&format!(
+            "do call 'ets':'update_counter'('{}', {}, 1, {{{}, 0}})",
+            Self::COVER_TABLE,
+            key,
+            key
+        ));
+        self.newline();
+    }
+
+    /// Emit `__dream_cover__/0`, returning the instrumented lines.
+    fn emit_cover_function(&mut self) {
+        let lines: Vec<String> = self.cover_lines.iter().map(|l| l.to_string()).collect();
+        self.newline();
+        self.emit(&format!("'{}'/0 =", Self::COVER_FUNCTION));
+        self.newline();
+        self.emit(&format!("    fun () -> [{}]", lines.join(", ")));
+        self.newline();
+    }
+
+    /// Local function a debugger probe calls before each statement.
+    const DEBUG_PROBE: &str = "__dream_debug__";
+
+    /// Exported function listing the lines that have debugger probes.
+    const DEBUG_LINES_FUNCTION: &str = "__dream_debug_lines__";
+
+    /// When compiling for `dream debug`, give the debugger a chance to stop
+    /// on the line `span` starts on, passing it the variables in scope by
+    /// their Dream names. Emits `do <probe>` like `emit_cover_point`.
+    fn emit_debug_point(&mut self, span: &Span) {
+        if !self.compile_options.debug || self.line_starts.is_empty() || span.start >= span.end {
+            return;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= span.start);
+        self.debug_lines.insert(line);
+
+        // A shadowed variable shows its innermost binding, and `_`-prefixed
+        // names are ones the program itself ignores
+        let mut seen = HashSet::new();
+        let mut bindings: Vec<String> = self
+            .debug_scope
+            .iter()
+            .rev()
+            .filter(|name| !name.starts_with('_') && seen.insert(name.as_str()))
+            .map(|name| format!("{{'{}', {}}}", name, Self::var_name(name)))
+            .collect();
+        bindings.reverse();
+        self.emit(&format!("do apply '{}'/2({}, [{}])", Self::DEBUG_PROBE, line, bindings.join(", ")));
+        self.newline();
+    }
+
+    /// Emit the rest of a block after a `let`, with the variables `pattern`
+    /// binds visible to the debugger probes in it.
+    fn emit_block_after_let(
+        &mut self,
+        pattern: &Pattern,
+        rest: &[Stmt],
+        final_expr: &Option<Box<Expr>>,
+    ) -> CoreErlangResult<()> {
+        let depth = self.debug_scope.len();
+        Self::pattern_bindings(pattern, &mut self.debug_scope);
+        let result = self.emit_block_inner(rest, final_expr);
+        self.debug_scope.truncate(depth);
+        result
+    }
+
+    /// Emit the debugger probe and `__dream_debug_lines__/0`.
+    ///
+    /// The probe stops when the process is single-stepping or the line has
+    /// a breakpoint in the `dream_debug` table. It then sends the line and
+    /// bindings to the process registered as `dream_debugger` and waits to
+    /// be told to `continue` or `step`. Without the table, as when a debug
+    /// build runs outside `dream debug`, it does nothing.
+    fn emit_debug_functions(&mut self) {
+        let lines: Vec<String> = self.debug_lines.iter().map(|l| l.to_string()).collect();
+        let module = self.module_name.clone();
+        self.newline();
+        self.emit(&format!("'{}'/2 =", Self::DEBUG_PROBE));
+        self.newline();
+        self.emit(&format!(
+            "    fun (_@line, _@bindings) -> \
+             let <_@stop> = case call 'erlang':'get'('dream_debug_step') of \
+                 <'true'> when 'true' -> 'true' \
+                 <_> when 'true' -> \
+                     try call 'ets':'member'('dream_debug', {{'{module}', _@line}}) \
+                     of <_@hit> -> _@hit \
+                     catch <_@class, _@reason, _@stack> -> 'false' \
+             end in \
+             case _@stop of \
+                 <'true'> when 'true' -> \
+                     let <_@self> = call 'erlang':'self'() in \
+                     do call 'erlang':'send'('dream_debugger', {{'stopped', _@self, '{module}', _@line, _@bindings}}) \
+                     receive \
+                         <{{'dream_debug_resume', _@mode}}> when 'true' -> \
+                             let <_@step> = call 'erlang':'=:='(_@mode, 'step') in \
+                             do call 'erlang':'put'('dream_debug_step', _@step) 'ok' \
+                     after 'infinity' -> 'ok' \
+                 <_> when 'true' -> 'ok' \
+             end"
+        ));
+        self.newline();
+        self.newline();
+        self.emit(&format!("'{}'/0 =", Self::DEBUG_LINES_FUNCTION));
+        self.newline();
+        self.emit(&format!("    fun () -> [{}]", lines.join(", ")));
+        self.newline();
+    }
+
+    /// Exported EUnit test generator of test builds.
+    const EUNIT_GENERATOR: &str = "__dream_test_";
+
+    /// Emit `__dream_test_/0`, returning a `{Title, Fun}` pair for each
+    /// test. A test fails the way `dream test` fails it: by crashing, or by
+    /// returning `false` or `Err`.
+    fn emit_eunit_generator(&mut self, tests: &[String]) {
+        let pairs: Vec<String> = tests
+            .iter()
+            .map(|name| {
+                format!(
+                    "{{\"{}\", fun () -> case apply '{}'/0() of \
+                     <'false'> when 'true' -> call 'erlang':'error'({{'dream_test_failed', 'false'}}) \
+                     <{{'error', Reason}}> when 'true' -> call 'erlang':'error'({{'dream_test_failed', {{'error', Reason}}}}) \
+                     <_> when 'true' -> 'ok' end}}",
+                    name, name
+                )
+            })
+            .collect();
+        self.newline();
+        self.emit(&format!("'{}'/0 =", Self::EUNIT_GENERATOR));
+        self.newline();
+        self.emit(&format!("    fun () -> [{}]", pairs.join(", ")));
+        self.newline();
+    }
+
+    /// The module's `on_load` function, when it declares NIFs.
+    const NIF_LOADER: &str = "__dream_load_nif__";
+
+    /// Emit a stub for each NIF, raising `nif_not_loaded` until the native
+    /// library replaces it, and the `on_load` function loading the library
+    /// from `priv/native/lib<library>` of the module's application. A
+    /// failed load fails loading the module.
+    fn emit_nifs(&mut self, library: &str, nifs: &[(String, usize)]) {
+        for (name, arity) in nifs {
+            let params: Vec<String> = (0..*arity).map(|i| format!("_Nif{}", i)).collect();
+            self.newline();
+            self.emit(&format!("'{}'/{} =", name, arity));
+            self.newline();
+            self.emit(&format!(
+                "    fun ({}) -> call 'erlang':'nif_error'('nif_not_loaded')",
+                params.join(", ")
+            ));
+            self.newline();
+        }
+        self.newline();
+        self.emit(&format!("'{}'/0 =", Self::NIF_LOADER));
+        self.newline();
+        self.emit(&format!(
+            "    fun () -> let <Ebin> = call 'filename':'dirname'(call 'code':'which'('{}')) \
+             in let <Lib> = call 'filename':'join'([call 'filename':'dirname'(Ebin), \"priv\", \"native\", \"lib{}\"]) \
+             in call 'erlang':'load_nif'(Lib, 0)",
+            self.module_name, library
+        ));
+        self.newline();
+    }
+
     /// Emit monomorphized versions of generic functions.
     /// For each (func_name, [Type1, Type2, ...]) in pending_monomorphizations,
     /// generate a specialized function func_name_Type1_Type2.
@@ -1723,7 +2065,7 @@ impl CoreErlangEmitter {
                 break;
             }
 
-            let guard = registry.read().unwrap();
+            let guard = registry.read().unwrap_or_else(PoisonError::into_inner);
 
             for (source_module, func_name, type_names) in monos {
                 // Skip if already emitted
@@ -1789,6 +2131,7 @@ impl CoreErlangEmitter {
 
     /// Emit a function definition.
     fn emit_function(&mut self, func: &Function) -> CoreErlangResult<()> {
+        let _item = ice::item(&func.span);
         let arity = func.params.len();
         self.emit(&format!("'{}'/{} =", func.name, arity));
         self.newline();
@@ -1809,9 +2152,11 @@ impl CoreErlangEmitter {
 
         // Clear variables from previous function and add this function's parameters
         self.variables.clear();
+        self.debug_scope.clear();
         for p in &func.params {
             if let Pattern::Ident(name) = &p.pattern {
                 self.variables.insert(name.clone());
+                self.debug_scope.push(name.clone());
             }
         }
 
@@ -1846,6 +2191,8 @@ impl CoreErlangEmitter {
             self.indent += 1;
         }
 
+        self.emit_cover_point(&func.span);
+        self.emit_debug_point(&func.span);
         self.emit_block(&func.body)?;
 
         if needs_result_catch {
@@ -1969,7 +2316,12 @@ impl CoreErlangEmitter {
         clauses: &[&Function],
     ) -> CoreErlangResult<()> {
         // For single-clause functions without guards, use simpler direct emission
-        if clauses.len() == 1 && clauses[0].guard.is_none() {
+        // whose parameters are all plain names
+        let plain_params = clauses[0]
+            .params
+            .iter()
+            .all(|p| matches!(p.pattern, Pattern::Ident(_) | Pattern::Wildcard));
+        if clauses.len() == 1 && clauses[0].guard.is_none() && plain_params {
             return self.emit_function(clauses[0]);
         }
 
@@ -2015,8 +2367,10 @@ impl CoreErlangEmitter {
         for clause in clauses.iter() {
             // Collect pattern variables for this clause
             self.variables.clear();
+            self.debug_scope.clear();
             for p in &clause.params {
                 self.collect_pattern_vars(&p.pattern);
+                Self::pattern_bindings(&p.pattern, &mut self.debug_scope);
             }
 
             // Check for self parameter
@@ -2049,6 +2403,8 @@ impl CoreErlangEmitter {
             self.newline();
 
             self.indent += 1;
+            self.emit_cover_point(&clause.span);
+            self.emit_debug_point(&clause.span);
             self.emit_block(&clause.body)?;
             self.indent -= 1;
             self.newline();
@@ -2171,7 +2527,7 @@ impl CoreErlangEmitter {
         stmts: &[Stmt],
         final_expr: &Option<Box<Expr>>,
     ) -> CoreErlangResult<()> {
-        if stmts.is_empty() {
+        let Some((first, rest)) = stmts.split_first() else {
             // No more statements, emit the final expression
             if let Some(expr) = final_expr {
                 self.emit_expr(expr)?;
@@ -2180,9 +2536,12 @@ impl CoreErlangEmitter {
                 self.emit("'ok'");
             }
             return Ok(());
-        }
+        };
 
-        let (first, rest) = stmts.split_first().unwrap();
+        if let Stmt::Let { span: Some(span), .. } | Stmt::Expr { span: Some(span), .. } = first {
+            self.emit_cover_point(span);
+            self.emit_debug_point(span);
+        }
 
         match first {
             Stmt::Let { pattern, value, else_block, .. } => {
@@ -2207,7 +2566,7 @@ impl CoreErlangEmitter {
                     self.emit("> when 'true' ->");
                     self.newline();
                     self.indent += 1;
-                    self.emit_block_inner(rest, final_expr)?;
+                    self.emit_block_after_let(pattern, rest, final_expr)?;
                     self.indent -= 1;
                     self.newline();
                     // Wildcard arm - executes else block (must diverge)
@@ -2230,7 +2589,7 @@ impl CoreErlangEmitter {
                     self.indent -= 1;
                     self.newline();
                     self.emit("in ");
-                    self.emit_block_inner(rest, final_expr)?;
+                    self.emit_block_after_let(pattern, rest, final_expr)?;
                 } else {
                     // Use case for complex patterns (tuple, list, struct, enum)
                     self.emit("case ");
@@ -2243,7 +2602,7 @@ impl CoreErlangEmitter {
                     self.emit("> when 'true' ->");
                     self.newline();
                     self.indent += 1;
-                    self.emit_block_inner(rest, final_expr)?;
+                    self.emit_block_after_let(pattern, rest, final_expr)?;
                     self.indent -= 1;
                     self.newline();
                     self.indent -= 1;
@@ -2296,6 +2655,11 @@ impl CoreErlangEmitter {
                     self.emit_block_inner(rest, final_expr)?;
                 }
             }
+            Stmt::Cfg { .. } => {
+                return Err(CoreErlangError::new(
+                    "`#[cfg]` statement left in a module that wasn't configured",
+                ));
+            }
         }
 
         Ok(())
@@ -2552,6 +2916,38 @@ impl CoreErlangEmitter {
                 self.emit("])");
             }
 
+            Expr::Format { parts, .. } => {
+                // One iolist, made a binary once: literals as their UTF-8
+                // bytes, string literal arguments as they are, and other
+                // arguments rendered by the display module
+                self.emit("call 'erlang':'iolist_to_binary'([");
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        self.emit(", ");
+                    }
+                    match part {
+                        FormatPart::Literal(s) => {
+                            let bytes: Vec<String> = s.bytes().map(|b| b.to_string()).collect();
+                            self.emit(&format!("[{}]", bytes.join(", ")));
+                        }
+                        FormatPart::Display(e) if matches!(**e, Expr::String(_)) => {
+                            self.emit_expr(e)?;
+                        }
+                        FormatPart::Display(e) => {
+                            self.emit("call 'dream::display':'to_string'(");
+                            self.emit_expr(e)?;
+                            self.emit(")");
+                        }
+                        FormatPart::Debug(e) => {
+                            self.emit("call 'dream::display':'display_any'(");
+                            self.emit_expr(e)?;
+                            self.emit(")");
+                        }
+                    }
+                }
+                self.emit("])");
+            }
+
             Expr::Atom(a) => {
                 // Check if this atom references a local module
                 if let Some(resolved) = self.module_context.resolve_local_module(a) {
@@ -2580,7 +2976,7 @@ impl CoreErlangEmitter {
                 self.emit("'ok'");
             }
 
-            Expr::Binary { op, left, right } => {
+            Expr::Binary { op, left, right, .. } => {
                 self.emit_binary_op(*op, left, right)?;
             }
 
@@ -2667,7 +3063,7 @@ impl CoreErlangEmitter {
                                 .as_ref()
                                 .and_then(|source| {
                                     self.external_generics.as_ref().map(|reg| {
-                                        let guard = reg.read().unwrap();
+                                        let guard = reg.read().unwrap_or_else(PoisonError::into_inner);
                                         if guard.contains(source, name) {
                                             Some(source.clone())
                                         } else {
@@ -2863,7 +3259,7 @@ impl CoreErlangEmitter {
                                 let has_external_generic = self.external_generics
                                     .as_ref()
                                     .map(|reg| {
-                                        let guard = reg.read().unwrap();
+                                        let guard = reg.read().unwrap_or_else(PoisonError::into_inner);
                                         guard.contains(&source_module, &func_name)
                                     })
                                     .unwrap_or(false);
@@ -3332,8 +3728,12 @@ impl CoreErlangEmitter {
                     } else {
                         // Full struct init
                         self.emit("~{");
-                        // Add __struct__ tag with BEAM module name for method dispatch
-                        self.emit(&format!("'__struct__' => '{}::{}'", beam_module, type_name));
+                        // Add __struct__ tag with BEAM module name for method dispatch,
+                        // or the Elixir module of an #[elixir_struct]
+                        let tag = self
+                            .elixir_struct_module(name)
+                            .unwrap_or_else(|| format!("{}::{}", beam_module, type_name));
+                        self.emit(&format!("'__struct__' => '{}'", tag));
                         for (field_name, value) in fields.iter() {
                             self.emit(", ");
                             self.emit(&format!("'{}' => ", field_name));
@@ -3344,6 +3744,13 @@ impl CoreErlangEmitter {
                 }
             }
 
+            Expr::EnumVariant { type_name: None, variant, args: EnumVariantArgs::Tuple(exprs) }
+                if exprs.len() == 1 && self.is_newtype(variant) =>
+            {
+                // A newtype is the value it wraps: Meters(3) is just 3
+                self.emit_expr(&exprs[0])?;
+            }
+
             Expr::EnumVariant { type_name, variant, args } => {
                 // Special handling for Result type to match Erlang conventions:
                 // - Ok(()) → 'ok' (just the atom, for Result<(), E>)
@@ -3603,6 +4010,11 @@ impl CoreErlangEmitter {
                     "unquote/unquote-splice/quote-repetition can only be used inside quote blocks",
                 ));
             }
+
+            // The cfg pass reports these before code generation
+            Expr::CompileError { message, .. } => {
+                return Err(CoreErlangError::new(message.clone()));
+            }
         }
 
         Ok(())
@@ -3696,7 +4108,7 @@ impl CoreErlangEmitter {
                 self.emit("]}");
             }
 
-            Expr::Binary { op, left, right } => {
+            Expr::Binary { op, left, right, .. } => {
                 self.emit(&format!("{{'binary_op', '{}', ", self.binop_to_atom(*op)));
                 self.emit_quoted_expr(left)?;
                 self.emit(", ");
@@ -3928,7 +4340,7 @@ impl CoreErlangEmitter {
     /// Emit a quoted statement.
     fn emit_quoted_stmt(&mut self, stmt: &Stmt) -> CoreErlangResult<()> {
         match stmt {
-            Stmt::Let { pattern, ty: _, value, else_block } => {
+            Stmt::Let { pattern, ty: _, value, else_block, .. } => {
                 self.emit("{'let', ");
                 self.emit_quoted_pattern(pattern)?;
                 self.emit(", ");
@@ -3944,6 +4356,11 @@ impl CoreErlangEmitter {
                 self.emit_quoted_expr(expr)?;
                 self.emit("}");
             }
+            Stmt::Cfg { .. } => {
+                return Err(CoreErlangError::new(
+                    "`#[cfg]` statement left in a module that wasn't configured",
+                ));
+            }
         }
         Ok(())
     }
@@ -5112,7 +5529,10 @@ impl CoreErlangEmitter {
 
                     self.emit("~{");
                     // Match __struct__ tag as fully qualified atom 'module::Type'
-                    self.emit(&format!("'__struct__' := '{}::{}'", module_name, type_name));
+                    let tag = self
+                        .elixir_struct_module(name)
+                        .unwrap_or_else(|| format!("{}::{}", module_name, type_name));
+                    self.emit(&format!("'__struct__' := '{}'", tag));
                     for (field_name, pat) in fields.iter() {
                         self.emit(", ");
                         self.emit(&format!("'{}' := ", field_name));
@@ -5122,6 +5542,13 @@ impl CoreErlangEmitter {
                 }
             }
 
+            Pattern::Enum { name, variant, fields: EnumPatternFields::Tuple(patterns) }
+                if name.is_empty() && patterns.len() == 1 && self.is_newtype(variant) =>
+            {
+                // A newtype pattern matches the wrapped value itself
+                self.emit_pattern(&patterns[0])?;
+            }
+
             Pattern::Enum { name, variant, fields } => {
                 // Special handling for Result type patterns to match Erlang conventions:
                 // - Ok() or Ok(()) → 'ok'
@@ -5217,12 +5644,15 @@ impl CoreErlangEmitter {
         if let Some(size) = &seg.size {
             self.emit_expr(size)?;
         } else {
-            self.emit("8"); // default size
+            match seg.segment_type {
+                BitSegmentType::Binary => self.emit("'all'"),
+                _ => self.emit("8"), // default size
+            }
         }
 
         // Type specifiers
         self.emit(", ");
-        self.emit("1, "); // unit = 1 bit
+        self.emit(Self::bitstring_unit(seg.segment_type));
 
         let type_spec = match seg.segment_type {
             BitSegmentType::Integer => "'integer'",
@@ -5251,6 +5681,16 @@ impl CoreErlangEmitter {
         &mut self,
         seg: &BitStringSegment<Box<Pattern>>,
     ) -> CoreErlangResult<()> {
+        // A string literal matches its UTF-8 bytes, one segment each
+        if let Pattern::String(s) = seg.value.as_ref() {
+            let bytes: Vec<String> = s
+                .bytes()
+                .map(|b| format!("#<{}>(8, 1, 'integer', ['unsigned', 'big'])", b))
+                .collect();
+            self.emit(&bytes.join(", "));
+            return Ok(());
+        }
+
         self.emit("#<");
         self.emit_pattern(&seg.value)?;
         self.emit(">(");
@@ -5267,7 +5707,7 @@ impl CoreErlangEmitter {
 
         // Type specifiers
         self.emit(", ");
-        self.emit("1, "); // unit = 1 bit
+        self.emit(Self::bitstring_unit(seg.segment_type));
 
         let type_spec = match seg.segment_type {
             BitSegmentType::Integer => "'integer'",
@@ -5292,6 +5732,15 @@ impl CoreErlangEmitter {
 
         Ok(())
     }
+
+    /// The unit of a segment's size, as in Erlang: `body:len/binary` is
+    /// `len` bytes, `n:16` is 16 bits.
+    fn bitstring_unit(segment_type: BitSegmentType) -> &'static str {
+        match segment_type {
+            BitSegmentType::Binary => "8, ",
+            _ => "1, ",
+        }
+    }
 }
 
 impl Default for CoreErlangEmitter {
@@ -5316,20 +5765,30 @@ pub fn emit_core_erlang_with_typecheck(source: &str, typecheck: bool) -> CoreErl
         .parse_module()
         .map_err(|e| CoreErlangError::new(format!("Parse error: {}", e.message)))?;
 
-    let (module, extern_module_names, extern_function_names) = if typecheck {
+    let (module, extern_module_names, extern_function_names, struct_info) = if typecheck {
         use crate::compiler::typeck::check_modules_with_metadata;
         // Type check and annotate the module, extract extern module/function name mappings
         let result = check_modules_with_metadata(&[module]);
-        let (_, type_result) = result.modules.into_iter().next().unwrap();
+        let (_, type_result) = result
+            .modules
+            .into_iter()
+            .next()
+            .ok_or_else(|| CoreErlangError::new("Type error: no module was checked"))?;
         let module = type_result.map_err(|e| CoreErlangError::new(format!("Type error: {}", e.message)))?;
-        (module, result.extern_module_names, result.extern_function_names)
+        (
+            module,
+            result.extern_module_names,
+            result.extern_function_names,
+            result.struct_info,
+        )
     } else {
-        (module, HashMap::new(), HashMap::new())
+        (module, HashMap::new(), HashMap::new(), HashMap::new())
     };
 
     let mut emitter = CoreErlangEmitter::new();
     emitter.set_extern_module_names(extern_module_names);
     emitter.set_extern_function_names(extern_function_names);
+    emitter.set_struct_info(struct_info);
     emitter.emit_module(&module)
 }
 
@@ -5354,6 +5813,158 @@ mod tests {
         assert!(result.contains("call 'erlang':'+'"));
     }
 
+    #[test]
+    fn test_binary_function_heads() {
+        let source = r#"
+            mod test {
+                pub fn parse(<<"PING", _/binary>>) -> Binary {
+                    "pong"
+                }
+                pub fn parse(<<len:16, body:len/binary, _rest/binary>>) -> Binary {
+                    body
+                }
+            }
+        "#;
+
+        let result = emit_core_erlang(source).unwrap();
+        assert_eq!(result.matches("'parse'/1 =").count(), 1);
+        assert!(result.contains("#<80>(8, 1, 'integer', ['unsigned', 'big']), #<73>"));
+        assert!(result.contains("#<Len>(16, 1, 'integer'"));
+        assert!(result.contains("#<Body>(Len, 8, 'binary'"));
+        assert!(result.contains("#<_rest>('all', 8, 'binary'"));
+    }
+
+    #[test]
+    fn test_tuple_structs() {
+        let source = r#"
+            mod test {
+                struct Meters(float);
+                struct Pair(int, int);
+
+                pub fn wrap(x: float) -> Meters {
+                    Meters(x)
+                }
+                pub fn unwrap(Meters(x): Meters) -> float {
+                    x
+                }
+                pub fn pair(a: int, b: int) -> Pair {
+                    Pair(a, b)
+                }
+            }
+        "#;
+
+        let result = emit_core_erlang_with_typecheck(source, true).unwrap();
+        assert!(!result.contains("{'meters'"));
+        assert!(result.contains("{'pair', A, B}"));
+    }
+
+    #[test]
+    fn test_overloaded_operators() {
+        let source = r#"
+            mod test {
+                trait Add { fn add(self, other: Self) -> Self; }
+                struct Vec2 { x: int, y: int }
+
+                impl Add for Vec2 {
+                    fn add(self, other: Vec2) -> Vec2 {
+                        Vec2 { x: self.x + other.x, y: self.y + other.y }
+                    }
+                }
+
+                pub fn shift(a: Vec2, b: Vec2) -> Vec2 {
+                    a + b
+                }
+
+                pub fn sum<T: Add>(a: T, b: T) -> T {
+                    a + b
+                }
+            }
+        "#;
+
+        let result = emit_core_erlang_with_typecheck(source, true).unwrap();
+        assert!(result.contains("apply 'Vec2_add'/2(A, B)"));
+        let sum = &result[result.find("'sum'/2 =").unwrap()..];
+        assert!(sum.contains("case call 'maps':'get'('__struct__'"));
+    }
+
+    #[test]
+    fn test_coverage_instrumentation() {
+        use crate::compiler::parser::Parser;
+
+        let source = "mod test {\n    pub fn double(x: int) -> int {\n        let y = x + x;\n        y\n    }\n}\n";
+        let module = Parser::new(source).parse_module().unwrap();
+        let options = CompileOptions {
+            coverage: true,
+            ..CompileOptions::default()
+        };
+        let result = CoreErlangEmitter::with_options(options).emit_module(&module).unwrap();
+        assert!(result.contains("'__dream_cover__'/0"));
+        assert!(result.contains("do call 'ets':'update_counter'('dream_cover', {'dream::test', 2}, 1, {{'dream::test', 2}, 0})"));
+        assert!(result.contains("{'dream::test', 3}"));
+        assert!(result.contains("fun () -> [2, 3]"));
+
+        // Off by default
+        let result = emit_core_erlang(source).unwrap();
+        assert!(!result.contains("update_counter"));
+        assert!(!result.contains("__dream_cover__"));
+    }
+
+    #[test]
+    fn test_debug_instrumentation() {
+        use crate::compiler::parser::Parser;
+
+        let source = "mod test {\n    pub fn double(x: int) -> int {\n        let y = x + x;\n        let z = y;\n        z\n    }\n}\n";
+        let module = Parser::new(source).parse_module().unwrap();
+        let options = CompileOptions {
+            debug: true,
+            ..CompileOptions::default()
+        };
+        let result = CoreErlangEmitter::with_options(options).emit_module(&module).unwrap();
+        assert!(result.contains("do apply '__dream_debug__'/2(2, [{'x', X}])"));
+        assert!(result.contains("do apply '__dream_debug__'/2(3, [{'x', X}])"));
+        assert!(result.contains("do apply '__dream_debug__'/2(4, [{'x', X}, {'y', Y}])"));
+        assert!(result.contains("'__dream_debug__'/2 ="));
+        assert!(result.contains("'__dream_debug_lines__'/0"));
+        assert!(result.contains("fun () -> [2, 3, 4]"));
+        assert!(!result.contains("__dream_cover__"));
+
+        // Off by default
+        let result = emit_core_erlang(source).unwrap();
+        assert!(!result.contains("__dream_debug"));
+    }
+
+    #[test]
+    fn test_eunit_generator() {
+        use crate::compiler::parser::Parser;
+
+        let source = r#"
+            mod math {
+                pub fn double(x: int) -> int { x + x }
+
+                #[test]
+                fn test_double() -> bool { double(2) == 4 }
+
+                #[test]
+                #[ignore]
+                fn test_slow() -> bool { true }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().unwrap();
+        let options = CompileOptions {
+            test_mode: true,
+            ..CompileOptions::default()
+        };
+        let result = CoreErlangEmitter::with_options(options).emit_module(&module).unwrap();
+        assert!(result.contains("'__dream_test_'/0"));
+        assert!(result.contains("{\"test_double\", fun () -> case apply 'test_double'/0() of"));
+        assert!(result.contains("call 'erlang':'error'({'dream_test_failed', 'false'})"));
+        assert!(!result.contains("\"test_slow\""));
+
+        // Only in test builds
+        let result = CoreErlangEmitter::new().emit_module(&module).unwrap();
+        assert!(!result.contains("__dream_test_"));
+    }
+
     #[test]
     fn test_let_binding() {
         let source = r#"
@@ -5684,6 +6295,116 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_extern_erlang_block_calls() {
+        let source = r#"
+            mod test {
+                extern "erlang" {
+                    fn lists::reverse<T>(list: [T]) -> [T];
+                    fn timer::sleep(ms: int) -> atom;
+                    #[name = "is_process_alive"]
+                    fn erlang::alive(pid: pid) -> bool;
+                }
+
+                pub fn run(p: pid) -> bool {
+                    lists::reverse([1, 2]);
+                    timer::sleep(10);
+                    erlang::alive(p)
+                }
+            }
+        "#;
+
+        let result = emit_core_erlang_with_typecheck(source, true).unwrap();
+        assert!(result.contains("call 'lists':'reverse'("), "{}", result);
+        // Declared functions bypass the stdlib module of the same name
+        assert!(result.contains("call 'timer':'sleep'("), "{}", result);
+        assert!(!result.contains("'dream::timer'"), "{}", result);
+        assert!(result.contains("call 'erlang':'is_process_alive'("), "{}", result);
+    }
+
+    #[test]
+    fn test_elixir_interop() {
+        use crate::compiler::parser::Parser;
+        use crate::compiler::typeck::check_modules_with_metadata;
+
+        let source = r#"
+            mod test {
+                use elixir::Jason;
+                use elixir::Phoenix::PubSub as Bus;
+
+                extern "elixir" {
+                    fn Enum::sum(list: [int]) -> int;
+                }
+
+                #[elixir_struct = "URI"]
+                pub struct Uri {
+                    host: string,
+                    port: int,
+                }
+
+                pub fn run(data: any) -> int {
+                    Jason::encode(data);
+                    Bus::broadcast(:app, "topic", data);
+                    Enum::sum([1, 2])
+                }
+
+                pub fn localhost() -> Uri {
+                    Uri { host: "localhost", port: 80 }
+                }
+
+                pub fn port(uri: Uri) -> int {
+                    match uri {
+                        Uri { port, .. } => port,
+                    }
+                }
+            }
+        "#;
+
+        let module = Parser::new(source).parse_module().unwrap();
+        let result = check_modules_with_metadata(&[module]);
+        let (_, module) = result.modules.into_iter().next().unwrap();
+        let mut emitter = CoreErlangEmitter::new();
+        emitter.set_extern_module_names(result.extern_module_names);
+        emitter.set_struct_info(result.struct_info);
+        let output = emitter.emit_module(&module.unwrap()).unwrap();
+
+        assert!(output.contains("call 'Elixir.Jason':'encode'("), "{}", output);
+        assert!(output.contains("call 'Elixir.Phoenix.PubSub':'broadcast'("), "{}", output);
+        assert!(output.contains("call 'Elixir.Enum':'sum'("), "{}", output);
+        assert!(output.contains("'__struct__' => 'Elixir.URI'"), "{}", output);
+        assert!(output.contains("'__struct__' := 'Elixir.URI'"), "{}", output);
+        assert!(!output.contains("dream::elixir"), "{}", output);
+    }
+
+    #[test]
+    fn test_nif_stubs() {
+        use crate::compiler::parser::Parser;
+
+        let source = r#"
+            mod fast_math {
+                #[name = "fast_math_nif"]
+                extern "nif" {
+                    fn add(a: int, b: int) -> int;
+                }
+
+                pub fn twice(x: int) -> int {
+                    add(x, x)
+                }
+            }
+        "#;
+
+        let module = Parser::new(source).parse_module().unwrap();
+        let mut emitter = CoreErlangEmitter::new();
+        let output = emitter.emit_module(&module).unwrap();
+
+        assert!(output.contains("'twice'/1, 'add'/2"), "{}", output);
+        assert!(output.contains("'on_load' = [{'__dream_load_nif__', 0}]"), "{}", output);
+        assert!(output.contains("apply 'add'/2("), "{}", output);
+        assert!(output.contains("'add'/2 =\n    fun (_Nif0, _Nif1) -> call 'erlang':'nif_error'('nif_not_loaded')"), "{}", output);
+        assert!(output.contains("call 'code':'which'('dream::fast_math')"), "{}", output);
+        assert!(output.contains("\"priv\", \"native\", \"libfast_math_nif\"]"), "{}", output);
+    }
+
     #[test]
     fn test_imported_extern_function() {
         // Test that `use jason::encode; encode(data)` works
@@ -6060,4 +6781,130 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn test_trait_decl_emits_behaviour_attribute() {
+        let source = r#"
+            mod counter {
+                impl genserver::GenServer;
+
+                pub fn init(n: int) -> (Atom, int) {
+                    (:ok, n)
+                }
+            }
+        "#;
+
+        let result = emit_core_erlang(source).unwrap();
+        assert!(
+            result.contains("attributes ['behaviour' = ['dream::genserver']]"),
+            "Expected behaviour attribute for GenServer, got:\n{}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_imported_trait_decl_emits_behaviour_attribute() {
+        let source = r#"
+            mod counter {
+                use genserver::GenServer;
+
+                impl GenServer;
+            }
+        "#;
+
+        let result = emit_core_erlang(source).unwrap();
+        assert!(
+            result.contains("'behaviour' = ['dream::genserver']"),
+            "Expected behaviour attribute for imported GenServer, got:\n{}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_module_without_trait_decl_has_no_attributes() {
+        let source = r#"
+            mod test {
+                pub fn answer() -> int { 42 }
+            }
+        "#;
+
+        let result = emit_core_erlang(source).unwrap();
+        assert!(result.contains("attributes []"));
+    }
+
+    #[test]
+    fn test_ets_calls_go_through_dream_ets() {
+        let source = r#"
+            mod cache {
+                pub fn put(tab: Any, key: Atom, value: int) -> bool {
+                    ets::insert(tab, key, value)
+                }
+            }
+        "#;
+
+        let result = emit_core_erlang(source).unwrap();
+        assert!(
+            result.contains("call 'dream::ets':'insert'"),
+            "Expected 'dream::ets':'insert' but got:\n{}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_ets_extern_binding_emits_ets_bif() {
+        // dream::ets itself wraps the ets BIFs through the extern binding
+        let source = r#"
+            mod wrapper {
+                #[name = "ets"]
+                extern mod erl_ets {
+                    fn lookup(tab: Any, key: Any) -> [Any];
+                    #[name = "match"]
+                    fn match_(tab: Any, pattern: Any) -> [Any];
+                }
+
+                pub fn get(tab: Any, key: Any) -> [Any] {
+                    erl_ets::lookup(tab, key)
+                }
+
+                pub fn find(tab: Any, pattern: Any) -> [Any] {
+                    erl_ets::match_(tab, pattern)
+                }
+            }
+        "#;
+
+        let result = emit_core_erlang_with_typecheck(source, true).unwrap();
+        assert!(
+            result.contains("call 'ets':'lookup'"),
+            "Expected 'ets':'lookup' but got:\n{}",
+            result
+        );
+        assert!(
+            result.contains("call 'ets':'match'"),
+            "Expected 'ets':'match' but got:\n{}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_port_calls_go_through_dream_port() {
+        let source = r#"
+            mod shell {
+                pub fn ls() -> Any {
+                    port::open("ls", port::options())
+                }
+            }
+        "#;
+
+        let result = emit_core_erlang(source).unwrap();
+        assert!(
+            result.contains("call 'dream::port':'open'"),
+            "Expected 'dream::port':'open' but got:\n{}",
+            result
+        );
+        assert!(
+            result.contains("call 'dream::port':'options'"),
+            "Expected 'dream::port':'options' but got:\n{}",
+            result
+        );
+    }
 }
@@ -1,7 +1,8 @@
 //! Derive macro expansion for Dream.
 //!
-//! Supports `#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]` on structs and enums.
-//! Generates impl blocks with the corresponding methods at compile time.
+//! Supports `#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Encode, Decode, Display,
+//! Inspect)]` on
+//! structs and enums. Generates impl blocks with the corresponding methods at compile time.
 //!
 //! Also supports user-defined macros that execute on BEAM via the MacroRegistry.
 
@@ -43,6 +44,10 @@ enum DeriveKind {
     Default,
     Eq,
     Hash,
+    Encode,
+    Decode,
+    Display,
+    Inspect,
 }
 
 impl DeriveKind {
@@ -54,6 +59,10 @@ impl DeriveKind {
             "Default" => Some(DeriveKind::Default),
             "PartialEq" | "Eq" => Some(DeriveKind::Eq),
             "Hash" => Some(DeriveKind::Hash),
+            "Encode" => Some(DeriveKind::Encode),
+            "Decode" => Some(DeriveKind::Decode),
+            "Display" => Some(DeriveKind::Display),
+            "Inspect" => Some(DeriveKind::Inspect),
             _ => None,
         }
     }
@@ -372,10 +381,8 @@ impl MacroRegistry {
 
     /// Get or create the macro expander.
     fn get_expander(&mut self) -> Result<&mut MacroExpander, DeriveError> {
-        if self.expander.is_none() {
-            self.expander = Some(MacroExpander::new(self.beam_paths.clone()));
-        }
-        Ok(self.expander.as_mut().unwrap())
+        let beam_paths = &self.beam_paths;
+        Ok(self.expander.get_or_insert_with(|| MacroExpander::new(beam_paths.clone())))
     }
 
     /// Shutdown the macro expander if running.
@@ -625,6 +632,22 @@ fn generate_struct_derives(
         // Check if it's a built-in derive (only for unqualified names)
         if let DeriveRef::Name(ref name) = derive_ref {
             if let Some(kind) = DeriveKind::from_name(name) {
+                if struct_def.is_tuple()
+                    && !matches!(
+                        kind,
+                        DeriveKind::Clone
+                            | DeriveKind::Eq
+                            | DeriveKind::Hash
+                            | DeriveKind::Display
+                            | DeriveKind::Inspect
+                    )
+                {
+                    errors.push(DeriveError::new(
+                        format!("`derive({})` is not supported on tuple structs", name),
+                        span,
+                    ));
+                    continue;
+                }
                 if let Some(impl_block) = generate_struct_derive(struct_def, kind) {
                     impls.push(Item::Impl(impl_block));
                 }
@@ -717,6 +740,9 @@ fn generate_struct_derive(struct_def: &StructDef, kind: DeriveKind) -> Option<Im
         DeriveKind::Default => generate_struct_default(struct_def),
         DeriveKind::Eq => generate_struct_eq(struct_def),
         DeriveKind::Hash => generate_hash(),
+        DeriveKind::Encode => generate_struct_encode(struct_def),
+        DeriveKind::Decode => generate_struct_decode(struct_def),
+        DeriveKind::Display | DeriveKind::Inspect => generate_struct_show(struct_def, kind),
     };
 
     Some(ImplBlock {
@@ -733,6 +759,9 @@ fn generate_enum_derive(enum_def: &EnumDef, kind: DeriveKind) -> Option<ImplBloc
         DeriveKind::Default => generate_enum_default(enum_def)?,
         DeriveKind::Eq => generate_enum_eq(enum_def),
         DeriveKind::Hash => generate_hash(),
+        DeriveKind::Encode => generate_enum_encode(),
+        DeriveKind::Decode => generate_enum_decode(enum_def),
+        DeriveKind::Display | DeriveKind::Inspect => generate_enum_show(enum_def, kind),
     };
 
     Some(ImplBlock {
@@ -846,24 +875,30 @@ fn generate_struct_clone(struct_def: &StructDef) -> Function {
     let name = &struct_def.name;
     let fields = &struct_def.fields;
 
-    // Build struct init with field accesses
-    let field_inits: Vec<(String, Expr)> = fields
-        .iter()
-        .map(|(field_name, _)| {
-            (
-                field_name.clone(),
-                Expr::FieldAccess {
-                    expr: Box::new(Expr::Ident("self".to_string())),
-                    field: field_name.clone(),
-                },
-            )
-        })
-        .collect();
+    let body_expr = if struct_def.is_tuple() {
+        // Tuple struct fields have no names to copy by; BEAM data is
+        // immutable, so clone is identity
+        Expr::Ident("self".to_string())
+    } else {
+        // Build struct init with field accesses
+        let field_inits: Vec<(String, Expr)> = fields
+            .iter()
+            .map(|(field_name, _)| {
+                (
+                    field_name.clone(),
+                    Expr::FieldAccess {
+                        expr: Box::new(Expr::Ident("self".to_string())),
+                        field: field_name.clone(),
+                    },
+                )
+            })
+            .collect();
 
-    let body_expr = Expr::StructInit {
-        name: name.clone(),
-        fields: field_inits,
-        base: None,
+        Expr::StructInit {
+            name: name.clone(),
+            fields: field_inits,
+            base: None,
+        }
     };
 
     let return_type = Type::Named {
@@ -1015,6 +1050,14 @@ fn generate_struct_eq(struct_def: &StructDef) -> Function {
     let body_expr = if fields.is_empty() {
         // Empty struct - always equal
         Expr::Bool(true)
+    } else if struct_def.is_tuple() {
+        // Tuple struct - use Erlang's == operator for structural equality
+        Expr::Binary {
+            op: BinOp::Eq,
+            left: Box::new(Expr::Ident("self".to_string())),
+            right: Box::new(Expr::Ident("other".to_string())),
+            id: ExprId::fresh(),
+        }
     } else {
         // Build chain of field comparisons: self.x == other.x && self.y == other.y
         let comparisons: Vec<Expr> = fields
@@ -1030,6 +1073,7 @@ fn generate_struct_eq(struct_def: &StructDef) -> Function {
                         expr: Box::new(Expr::Ident("other".to_string())),
                         field: field_name.clone(),
                     }),
+                    id: ExprId::fresh(),
                 }
             })
             .collect();
@@ -1041,6 +1085,7 @@ fn generate_struct_eq(struct_def: &StructDef) -> Function {
                 op: BinOp::And,
                 left: Box::new(acc),
                 right: Box::new(cmp),
+                id: ExprId::fresh(),
             })
             .unwrap_or(Expr::Bool(true))
     };
@@ -1069,6 +1114,7 @@ fn generate_enum_eq(enum_def: &EnumDef) -> Function {
         op: BinOp::Eq,
         left: Box::new(Expr::Ident("self".to_string())),
         right: Box::new(Expr::Ident("other".to_string())),
+        id: ExprId::fresh(),
     };
 
     let other_type = Type::Named {
@@ -1111,6 +1157,493 @@ fn generate_hash() -> Function {
     )
 }
 
+// =============================================================================
+// Encode / Decode derive
+// =============================================================================
+
+/// The stdlib module the generated code calls into.
+const CODEC_MODULE: &str = "dream::codec";
+
+/// A call to a `dream::codec` function.
+fn codec_call(function: &str, args: Vec<Expr>) -> Expr {
+    Expr::ExternCall {
+        module: CODEC_MODULE.to_string(),
+        function: function.to_string(),
+        args,
+    }
+}
+
+/// Generate `fn encode(self) -> any` for a struct.
+///
+/// Produces code like:
+/// ```text
+/// fn encode(self) -> any {
+///     :maps::from_list([(:name, :dream::codec::encode(self.name)), ...])
+/// }
+/// ```
+fn generate_struct_encode(struct_def: &StructDef) -> Function {
+    let entries: Vec<Expr> = struct_def
+        .fields
+        .iter()
+        .map(|(field_name, field_type)| {
+            let field = Expr::FieldAccess {
+                expr: Box::new(Expr::Ident("self".to_string())),
+                field: field_name.clone(),
+            };
+            let function = if is_option(field_type) { "encode_option" } else { "encode" };
+            Expr::Tuple(vec![
+                Expr::Atom(field_name.clone()),
+                codec_call(function, vec![field]),
+            ])
+        })
+        .collect();
+
+    let body_expr = Expr::ExternCall {
+        module: "maps".to_string(),
+        function: "from_list".to_string(),
+        args: vec![Expr::List(entries)],
+    };
+
+    make_method("encode", vec![make_self_param()], Some(Type::Any), body_expr)
+}
+
+/// Generate `fn encode(self) -> any` for an enum.
+///
+/// Variants are already plain terms, so this only encodes their payloads.
+fn generate_enum_encode() -> Function {
+    let body_expr = codec_call("encode", vec![Expr::Ident("self".to_string())]);
+
+    make_method("encode", vec![make_self_param()], Some(Type::Any), body_expr)
+}
+
+/// Generate `fn decode(value: any) -> Result<Self, any>` for a struct.
+///
+/// Produces code like:
+/// ```text
+/// fn decode(value: any) -> Result<User, any> {
+///     match :dream::codec::field(value, :name, |v| :dream::codec::string(v)) {
+///         (:ok, field_name) => Ok(User { name: field_name }),
+///         error => error,
+///     }
+/// }
+/// ```
+fn generate_struct_decode(struct_def: &StructDef) -> Function {
+    let steps: Vec<(String, Expr)> = struct_def
+        .fields
+        .iter()
+        .map(|(field_name, field_type)| {
+            (format!("field_{}", field_name), field_decoder(field_name, field_type))
+        })
+        .collect();
+
+    let field_inits: Vec<(String, Expr)> = struct_def
+        .fields
+        .iter()
+        .map(|(field_name, _)| (field_name.clone(), Expr::Ident(format!("field_{}", field_name))))
+        .collect();
+
+    let decoded = make_ok(Expr::StructInit {
+        name: struct_def.name.clone(),
+        fields: field_inits,
+        base: None,
+    });
+
+    make_method(
+        "decode",
+        vec![make_param("value", Type::Any)],
+        Some(decode_result_type(&struct_def.name)),
+        decode_chain(steps, decoded),
+    )
+}
+
+/// Generate `fn decode(value: any) -> Result<Self, any>` for an enum.
+///
+/// Accepts the terms the variants compile to: `:red` for a unit variant,
+/// `(:circle, radius)` for a tuple variant and `(:move, #{x => .., y => ..})`
+/// for a struct variant.
+fn generate_enum_decode(enum_def: &EnumDef) -> Function {
+    let name = &enum_def.name;
+
+    let mut arms: Vec<MatchArm> = enum_def
+        .variants
+        .iter()
+        .map(|variant| {
+            let tag = Pattern::Atom(variant.name.to_lowercase());
+            let (pattern, body) = match &variant.kind {
+                VariantKind::Unit => (
+                    tag,
+                    make_ok(Expr::EnumVariant {
+                        type_name: Some(name.clone()),
+                        variant: variant.name.clone(),
+                        args: EnumVariantArgs::Unit,
+                    }),
+                ),
+                VariantKind::Tuple(types) => {
+                    let mut patterns = vec![tag];
+                    let mut steps = Vec::new();
+                    let mut args = Vec::new();
+                    for (i, ty) in types.iter().enumerate() {
+                        patterns.push(Pattern::Ident(format!("arg{}", i)));
+                        steps.push((
+                            format!("field{}", i),
+                            decoder_call(ty, Expr::Ident(format!("arg{}", i)), 0),
+                        ));
+                        args.push(Expr::Ident(format!("field{}", i)));
+                    }
+                    let decoded = make_ok(Expr::EnumVariant {
+                        type_name: Some(name.clone()),
+                        variant: variant.name.clone(),
+                        args: EnumVariantArgs::Tuple(args),
+                    });
+                    (Pattern::Tuple(patterns), decode_chain(steps, decoded))
+                }
+                VariantKind::Struct(fields) => {
+                    let steps = fields
+                        .iter()
+                        .map(|(field_name, field_type)| {
+                            let decoder = field_decoder(field_name, field_type);
+                            (format!("field_{}", field_name), decoder)
+                        })
+                        .collect();
+                    let field_inits = fields
+                        .iter()
+                        .map(|(field_name, _)| {
+                            (field_name.clone(), Expr::Ident(format!("field_{}", field_name)))
+                        })
+                        .collect();
+                    let decoded = make_ok(Expr::EnumVariant {
+                        type_name: Some(name.clone()),
+                        variant: variant.name.clone(),
+                        args: EnumVariantArgs::Struct(field_inits),
+                    });
+                    let pattern = Pattern::Tuple(vec![tag, Pattern::Ident("value".to_string())]);
+                    (pattern, decode_chain(steps, decoded))
+                }
+            };
+            MatchArm {
+                attrs: vec![],
+                pattern,
+                guard: None,
+                body,
+            }
+        })
+        .collect();
+
+    // Anything else isn't one of the variants
+    arms.push(MatchArm {
+        attrs: vec![],
+        pattern: Pattern::Ident("other".to_string()),
+        guard: None,
+        body: codec_call(
+            "mismatch",
+            vec![Expr::String(name.clone()), Expr::Ident("other".to_string())],
+        ),
+    });
+
+    let body_expr = Expr::Match {
+        expr: Box::new(Expr::Ident("value".to_string())),
+        arms,
+    };
+
+    make_method(
+        "decode",
+        vec![make_param("value", Type::Any)],
+        Some(decode_result_type(name)),
+        body_expr,
+    )
+}
+
+/// `Result<Name, any>`, the return type of a generated `decode`.
+fn decode_result_type(name: &str) -> Type {
+    Type::Named {
+        name: "Result".to_string(),
+        type_args: vec![
+            Type::Named {
+                name: name.to_string(),
+                type_args: vec![],
+            },
+            Type::Any,
+        ],
+    }
+}
+
+/// Decode the field `field_name` of the map bound to `value`. `Option`
+/// fields may be missing.
+fn field_decoder(field_name: &str, field_type: &Type) -> Expr {
+    let (function, decoded) = match option_inner(field_type) {
+        Some(inner) => ("optional_field", inner),
+        None => ("field", field_type),
+    };
+    codec_call(
+        function,
+        vec![
+            Expr::Ident("value".to_string()),
+            Expr::Atom(field_name.to_string()),
+            decoder_closure(decoded, 0),
+        ],
+    )
+}
+
+/// A closure decoding its argument as `ty`. `depth` keeps the parameter
+/// names of nested closures apart.
+fn decoder_closure(ty: &Type, depth: usize) -> Expr {
+    let param = format!("v{}", depth);
+    Expr::Closure {
+        params: vec![param.clone()],
+        body: Block {
+            stmts: vec![],
+            expr: Some(Box::new(decoder_call(ty, Expr::Ident(param), depth + 1))),
+        },
+    }
+}
+
+/// Decode `value` as `ty`, returning a `Result`.
+fn decoder_call(ty: &Type, value: Expr, depth: usize) -> Expr {
+    let primitive = match ty {
+        Type::Int => Some("int"),
+        Type::Float => Some("float"),
+        Type::String | Type::Binary => Some("string"),
+        Type::Bool => Some("bool"),
+        Type::Atom => Some("atom"),
+        Type::Map => Some("map"),
+        _ => None,
+    };
+    if let Some(function) = primitive {
+        return codec_call(function, vec![value]);
+    }
+    if let Some(inner) = option_inner(ty) {
+        return codec_call("option", vec![value, decoder_closure(inner, depth)]);
+    }
+
+    match ty {
+        Type::List(elem) => codec_call("list", vec![value, decoder_closure(elem, depth)]),
+        // Other named types decode through their own `decode`
+        Type::Named { name, type_args } if type_args.is_empty() => Expr::Call {
+            func: Box::new(Expr::Path {
+                segments: vec![name.clone(), "decode".to_string()],
+            }),
+            type_args: vec![],
+            inferred_type_args: vec![],
+            args: vec![value],
+        },
+        // Type variables, tuples, unions and the like are passed through
+        _ => codec_call("any", vec![value]),
+    }
+}
+
+fn is_option(ty: &Type) -> bool {
+    option_inner(ty).is_some()
+}
+
+/// The `T` of `Option<T>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Named { name, type_args } if name == "Option" && type_args.len() == 1 => {
+            Some(&type_args[0])
+        }
+        _ => None,
+    }
+}
+
+/// Run each `(binding, decode)` step in turn, binding its value on success
+/// and returning its error otherwise, then evaluate `done`.
+fn decode_chain(steps: Vec<(String, Expr)>, done: Expr) -> Expr {
+    steps.into_iter().rev().fold(done, |rest, (binding, decode)| Expr::Match {
+        expr: Box::new(decode),
+        arms: vec![
+            MatchArm {
+                attrs: vec![],
+                pattern: Pattern::Tuple(vec![
+                    Pattern::Atom("ok".to_string()),
+                    Pattern::Ident(binding),
+                ]),
+                guard: None,
+                body: rest,
+            },
+            MatchArm {
+                attrs: vec![],
+                pattern: Pattern::Ident("error".to_string()),
+                guard: None,
+                body: Expr::Ident("error".to_string()),
+            },
+        ],
+    })
+}
+
+/// `Ok(expr)`
+fn make_ok(expr: Expr) -> Expr {
+    Expr::EnumVariant {
+        type_name: None,
+        variant: "Ok".to_string(),
+        args: EnumVariantArgs::Tuple(vec![expr]),
+    }
+}
+
+// =============================================================================
+// Display and Inspect derives
+// =============================================================================
+
+/// Generate `fn display(self) -> String` or `fn inspect(self) -> String`
+/// for a struct, writing it the way it is built.
+///
+/// Produces code like:
+/// ```text
+/// fn inspect(self) -> String {
+///     format!("Point { x: {:?}, y: {:?} }", self.x, self.y)
+/// }
+/// ```
+/// `display` shows the fields with `{}`, `inspect` with `{:?}`.
+fn generate_struct_show(struct_def: &StructDef, kind: DeriveKind) -> Function {
+    let name = &struct_def.name;
+    let self_expr = Expr::Ident("self".to_string());
+
+    let body_expr = if !struct_def.is_tuple() {
+        let fields = struct_def
+            .fields
+            .iter()
+            .map(|(field_name, _)| {
+                let value = Expr::FieldAccess {
+                    expr: Box::new(self_expr.clone()),
+                    field: field_name.clone(),
+                };
+                (field_name.clone(), value)
+            })
+            .collect();
+        show_fields(name, fields, kind)
+    } else if struct_def.fields.len() == 1 {
+        // A newtype is its field at runtime
+        show_args(name, vec![self_expr], kind)
+    } else {
+        let (pattern, args) = tuple_pattern(name, struct_def.fields.len());
+        Expr::Match {
+            expr: Box::new(self_expr),
+            arms: vec![MatchArm {
+                attrs: vec![],
+                pattern,
+                guard: None,
+                body: show_args(name, args, kind),
+            }],
+        }
+    };
+
+    make_show_method(kind, body_expr)
+}
+
+/// Generate `fn display(self) -> String` or `fn inspect(self) -> String`
+/// for an enum: each variant shown the way it is built, as with structs.
+fn generate_enum_show(enum_def: &EnumDef, kind: DeriveKind) -> Function {
+    let arms = enum_def
+        .variants
+        .iter()
+        .map(|variant| {
+            let tag = Pattern::Atom(variant.name.to_lowercase());
+            let (pattern, body) = match &variant.kind {
+                VariantKind::Unit => (tag, Expr::String(variant.name.clone())),
+                VariantKind::Tuple(types) => {
+                    let (pattern, args) = tuple_pattern(&variant.name, types.len());
+                    (pattern, show_args(&variant.name, args, kind))
+                }
+                VariantKind::Struct(fields) => {
+                    // A struct variant is its tag and a map of its fields
+                    let fields = fields
+                        .iter()
+                        .map(|(field_name, _)| {
+                            let value = Expr::ExternCall {
+                                module: "maps".to_string(),
+                                function: "get".to_string(),
+                                args: vec![
+                                    Expr::Atom(field_name.clone()),
+                                    Expr::Ident("value".to_string()),
+                                ],
+                            };
+                            (field_name.clone(), value)
+                        })
+                        .collect();
+                    let pattern = Pattern::Tuple(vec![tag, Pattern::Ident("value".to_string())]);
+                    (pattern, show_fields(&variant.name, fields, kind))
+                }
+            };
+            MatchArm {
+                attrs: vec![],
+                pattern,
+                guard: None,
+                body,
+            }
+        })
+        .collect();
+
+    let body_expr = Expr::Match {
+        expr: Box::new(Expr::Ident("self".to_string())),
+        arms,
+    };
+
+    make_show_method(kind, body_expr)
+}
+
+/// The tuple `{'name', arg0, arg1, ...}` a tuple struct or variant is at
+/// runtime, as a pattern, with the bound arguments.
+fn tuple_pattern(name: &str, len: usize) -> (Pattern, Vec<Expr>) {
+    let mut patterns = vec![Pattern::Atom(name.to_lowercase())];
+    let mut args = Vec::new();
+    for i in 0..len {
+        patterns.push(Pattern::Ident(format!("arg{}", i)));
+        args.push(Expr::Ident(format!("arg{}", i)));
+    }
+    (Pattern::Tuple(patterns), args)
+}
+
+/// `Name(a, b)`, each argument shown as `kind` shows values.
+fn show_args(name: &str, args: Vec<Expr>, kind: DeriveKind) -> Expr {
+    let mut parts = vec![FormatPart::Literal(format!("{}(", name))];
+    for (i, arg) in args.into_iter().enumerate() {
+        if i > 0 {
+            parts.push(FormatPart::Literal(", ".to_string()));
+        }
+        parts.push(show_part(arg, kind));
+    }
+    parts.push(FormatPart::Literal(")".to_string()));
+    Expr::Format {
+        parts,
+        span: Span::default(),
+    }
+}
+
+/// `Name { a: x, b: y }`, each value shown as `kind` shows values.
+fn show_fields(name: &str, fields: Vec<(String, Expr)>, kind: DeriveKind) -> Expr {
+    if fields.is_empty() {
+        return Expr::String(format!("{} {{}}", name));
+    }
+    let mut parts = Vec::new();
+    let mut before = format!("{} {{ ", name);
+    for (field_name, value) in fields {
+        parts.push(FormatPart::Literal(format!("{}{}: ", before, field_name)));
+        parts.push(show_part(value, kind));
+        before = ", ".to_string();
+    }
+    parts.push(FormatPart::Literal(" }".to_string()));
+    Expr::Format {
+        parts,
+        span: Span::default(),
+    }
+}
+
+/// `{}` of `value` for `Display`, `{:?}` for `Inspect`.
+fn show_part(value: Expr, kind: DeriveKind) -> FormatPart {
+    match kind {
+        DeriveKind::Display => FormatPart::Display(Box::new(value)),
+        _ => FormatPart::Debug(Box::new(value)),
+    }
+}
+
+/// The `display` or `inspect` method with `body_expr` as its body.
+fn make_show_method(kind: DeriveKind, body_expr: Expr) -> Function {
+    let name = match kind {
+        DeriveKind::Display => "display",
+        _ => "inspect",
+    };
+    make_method(name, vec![make_self_param()], Some(Type::String), body_expr)
+}
+
 // =============================================================================
 // AST construction helpers
 // =============================================================================
@@ -1227,6 +1760,10 @@ mod tests {
         assert_eq!(DeriveKind::from_name("PartialEq"), Some(DeriveKind::Eq));
         assert_eq!(DeriveKind::from_name("Eq"), Some(DeriveKind::Eq));
         assert_eq!(DeriveKind::from_name("Hash"), Some(DeriveKind::Hash));
+        assert_eq!(DeriveKind::from_name("Encode"), Some(DeriveKind::Encode));
+        assert_eq!(DeriveKind::from_name("Decode"), Some(DeriveKind::Decode));
+        assert_eq!(DeriveKind::from_name("Display"), Some(DeriveKind::Display));
+        assert_eq!(DeriveKind::from_name("Inspect"), Some(DeriveKind::Inspect));
         assert_eq!(DeriveKind::from_name("Unknown"), None);
     }
 
@@ -1277,6 +1814,162 @@ mod tests {
         assert!(matches!(method.return_type, Some(Type::Int)));
     }
 
+    #[test]
+    fn test_generate_struct_encode() {
+        let struct_def = make_test_struct(
+            "User",
+            vec![
+                ("name", Type::String),
+                ("email", Type::Named { name: "Option".to_string(), type_args: vec![Type::String] }),
+            ],
+        );
+        let method = generate_struct_encode(&struct_def);
+
+        assert_eq!(method.name, "encode");
+        assert_eq!(method.params.len(), 1);
+        let Some(Expr::ExternCall { module, function, args }) = method.body.expr.as_deref() else {
+            panic!("expected a call to maps:from_list");
+        };
+        assert_eq!((module.as_str(), function.as_str()), ("maps", "from_list"));
+        let [Expr::List(entries)] = args.as_slice() else {
+            panic!("expected a list of entries");
+        };
+        let encoders: Vec<&str> = entries
+            .iter()
+            .map(|entry| match entry {
+                Expr::Tuple(pair) => match &pair[1] {
+                    Expr::ExternCall { function, .. } => function.as_str(),
+                    other => panic!("unexpected value {:?}", other),
+                },
+                other => panic!("unexpected entry {:?}", other),
+            })
+            .collect();
+        assert_eq!(encoders, vec!["encode", "encode_option"]);
+    }
+
+    #[test]
+    fn test_generate_struct_decode() {
+        let struct_def = make_test_struct(
+            "User",
+            vec![
+                ("name", Type::String),
+                ("tags", Type::List(Box::new(Type::Atom))),
+                ("email", Type::Named { name: "Option".to_string(), type_args: vec![Type::String] }),
+            ],
+        );
+        let method = generate_struct_decode(&struct_def);
+
+        assert_eq!(method.name, "decode");
+        assert_eq!(method.params.len(), 1); // value, no self
+        assert!(matches!(
+            &method.return_type,
+            Some(Type::Named { name, type_args }) if name == "Result" && type_args.len() == 2
+        ));
+
+        // One match per field, innermost returning Ok(User { .. })
+        let mut expr = method.body.expr.as_deref().unwrap();
+        let mut decoders = Vec::new();
+        while let Expr::Match { expr: scrutinee, arms } = expr {
+            let Expr::ExternCall { function, .. } = scrutinee.as_ref() else {
+                panic!("expected a codec call");
+            };
+            decoders.push(function.as_str());
+            assert_eq!(arms.len(), 2);
+            expr = &arms[0].body;
+        }
+        assert_eq!(decoders, vec!["field", "field", "optional_field"]);
+        assert!(matches!(
+            expr,
+            Expr::EnumVariant { variant, args: EnumVariantArgs::Tuple(args), .. }
+                if variant == "Ok" && matches!(&args[0], Expr::StructInit { name, .. } if name == "User")
+        ));
+    }
+
+    #[test]
+    fn test_decoder_call_for_nested_types() {
+        let value = Expr::Ident("v".to_string());
+
+        let call = decoder_call(&Type::List(Box::new(Type::Int)), value.clone(), 0);
+        assert!(matches!(&call, Expr::ExternCall { function, args, .. }
+            if function == "list" && matches!(&args[1], Expr::Closure { params, .. } if params == &["v0"])));
+
+        let address = Type::Named { name: "Address".to_string(), type_args: vec![] };
+        let call = decoder_call(&address, value, 0);
+        assert!(matches!(&call, Expr::Call { func, .. }
+            if matches!(func.as_ref(), Expr::Path { segments } if segments == &["Address", "decode"])));
+    }
+
+    #[test]
+    fn test_generate_enum_decode() {
+        let enum_def = EnumDef {
+            attrs: vec![],
+            name: "Shape".to_string(),
+            type_params: vec![],
+            variants: vec![
+                EnumVariant { name: "Empty".to_string(), kind: VariantKind::Unit },
+                EnumVariant { name: "Circle".to_string(), kind: VariantKind::Tuple(vec![Type::Int]) },
+                EnumVariant {
+                    name: "Rect".to_string(),
+                    kind: VariantKind::Struct(vec![("w".to_string(), Type::Int), ("h".to_string(), Type::Int)]),
+                },
+            ],
+            is_pub: true,
+        };
+        let method = generate_enum_decode(&enum_def);
+
+        assert_eq!(method.name, "decode");
+        let Some(Expr::Match { arms, .. }) = method.body.expr.as_deref() else {
+            panic!("expected a match on the value");
+        };
+        // One arm per variant plus the fallback
+        assert_eq!(arms.len(), 4);
+        assert_eq!(arms[0].pattern, Pattern::Atom("empty".to_string()));
+        assert!(matches!(&arms[1].pattern, Pattern::Tuple(p)
+            if p.len() == 2 && p[0] == Pattern::Atom("circle".to_string())));
+        assert!(matches!(&arms[2].pattern, Pattern::Tuple(p)
+            if p.len() == 2 && p[0] == Pattern::Atom("rect".to_string())));
+        assert!(matches!(&arms[3].body, Expr::ExternCall { module, function, .. }
+            if module == "dream::codec" && function == "mismatch"));
+    }
+
+    #[test]
+    fn test_generate_show() {
+        let struct_def = make_test_struct("Point", vec![("x", Type::Int), ("y", Type::Int)]);
+        let method = generate_struct_show(&struct_def, DeriveKind::Inspect);
+
+        assert_eq!(method.name, "inspect");
+        let Some(Expr::Format { parts, .. }) = method.body.expr.as_deref() else {
+            panic!("expected a format");
+        };
+        assert_eq!(parts[0], FormatPart::Literal("Point { x: ".to_string()));
+        assert!(matches!(&parts[1], FormatPart::Debug(e) if matches!(e.as_ref(), Expr::FieldAccess { field, .. } if field == "x")));
+        assert_eq!(parts[2], FormatPart::Literal(", y: ".to_string()));
+        assert_eq!(parts[4], FormatPart::Literal(" }".to_string()));
+
+        let enum_def = EnumDef {
+            attrs: vec![],
+            name: "Shape".to_string(),
+            type_params: vec![],
+            variants: vec![
+                EnumVariant { name: "Empty".to_string(), kind: VariantKind::Unit },
+                EnumVariant { name: "Circle".to_string(), kind: VariantKind::Tuple(vec![Type::Int]) },
+            ],
+            is_pub: true,
+        };
+        let method = generate_enum_show(&enum_def, DeriveKind::Display);
+
+        assert_eq!(method.name, "display");
+        let Some(Expr::Match { arms, .. }) = method.body.expr.as_deref() else {
+            panic!("expected a match on self");
+        };
+        assert_eq!(arms[0].body, Expr::String("Empty".to_string()));
+        let Expr::Format { parts, .. } = &arms[1].body else {
+            panic!("expected a format");
+        };
+        assert_eq!(parts[0], FormatPart::Literal("Circle(".to_string()));
+        assert_eq!(parts[1], FormatPart::Display(Box::new(Expr::Ident("arg0".to_string()))));
+    }
+
     #[test]
     fn test_unknown_derive_error() {
         let struct_def = StructDef {
@@ -0,0 +1,751 @@
+//! Stable codes for compiler diagnostics, what `dream explain` says about
+//! each, and the secondary spans, notes and suggestions a diagnostic can
+//! carry besides its message, including the "did you mean" suggestions for
+//! misspelt names.
+//!
+//! Every parse, load, type, expansion and code generation error carries
+//! an [`ErrorCode`], as do warnings. A code keeps its meaning once released, so it can be
+//! searched for and linked to; new kinds of diagnostic get new codes. The
+//! explanations are data here rather than prose in the reporting code, so
+//! `dream explain`, the docs and editors all print the same thing.
+
+use crate::compiler::lexer::Span;
+use std::fmt;
+
+/// A kind of diagnostic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    // Syntax
+    UnexpectedToken,
+    UnexpectedEof,
+    MalformedAttribute,
+    MissingTypeAnnotation,
+    ClauseArity,
+    // Modules
+    ModuleNotFound,
+    CircularDependency,
+    UnreadableSource,
+    // Names
+    UndefinedVariable,
+    UndefinedStruct,
+    UndefinedVariant,
+    UnknownField,
+    UndefinedTrait,
+    // Types
+    TypeMismatch,
+    ArgumentCount,
+    InvalidOperand,
+    NonBoolCondition,
+    InvalidTry,
+    VariantShape,
+    MissingField,
+    TypeArgumentCount,
+    UnsatisfiedBound,
+    NonExhaustiveMatch,
+    IncompleteTraitImpl,
+    InvalidRegex,
+    // Expansion and code generation
+    MacroExpansion,
+    CompileError,
+    CodegenFailed,
+    // Bugs in the compiler
+    InternalError,
+    // Warnings
+    UnusedValue,
+    UnknownFunction,
+    Deprecated,
+    DynamicCoercion,
+    UnexpectedCfg,
+    UnreachableClause,
+}
+
+/// The explanation of one code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Explanation {
+    /// `E0001`, or `W0001` for warnings
+    pub code: &'static str,
+    /// What the diagnostic is about, in a few words
+    pub title: &'static str,
+    /// Why the compiler reports it, and how to fix it
+    pub description: &'static str,
+    /// Source that gets the diagnostic
+    pub erroneous: &'static str,
+    /// The same source, fixed
+    pub fixed: &'static str,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 35] = [
+        ErrorCode::UnexpectedToken,
+        ErrorCode::UnexpectedEof,
+        ErrorCode::MalformedAttribute,
+        ErrorCode::MissingTypeAnnotation,
+        ErrorCode::ClauseArity,
+        ErrorCode::ModuleNotFound,
+        ErrorCode::CircularDependency,
+        ErrorCode::UnreadableSource,
+        ErrorCode::UndefinedVariable,
+        ErrorCode::UndefinedStruct,
+        ErrorCode::UndefinedVariant,
+        ErrorCode::UnknownField,
+        ErrorCode::UndefinedTrait,
+        ErrorCode::TypeMismatch,
+        ErrorCode::ArgumentCount,
+        ErrorCode::InvalidOperand,
+        ErrorCode::NonBoolCondition,
+        ErrorCode::InvalidTry,
+        ErrorCode::VariantShape,
+        ErrorCode::MissingField,
+        ErrorCode::TypeArgumentCount,
+        ErrorCode::UnsatisfiedBound,
+        ErrorCode::NonExhaustiveMatch,
+        ErrorCode::IncompleteTraitImpl,
+        ErrorCode::InvalidRegex,
+        ErrorCode::MacroExpansion,
+        ErrorCode::CompileError,
+        ErrorCode::CodegenFailed,
+        ErrorCode::InternalError,
+        ErrorCode::UnusedValue,
+        ErrorCode::UnknownFunction,
+        ErrorCode::Deprecated,
+        ErrorCode::DynamicCoercion,
+        ErrorCode::UnexpectedCfg,
+        ErrorCode::UnreachableClause,
+    ];
+
+    /// The code as printed, e.g. `E0030`.
+    pub fn as_str(self) -> &'static str {
+        self.explanation().code
+    }
+
+    /// Look a code up by its printed form. Case-insensitive, and the
+    /// leading zeros may be left out: `e30` finds `E0030`.
+    pub fn from_code(code: &str) -> Option<ErrorCode> {
+        let code = code.trim();
+        let (letter, number) = code.split_at_checked(1)?;
+        let number: u32 = number.parse().ok()?;
+        let wanted = format!("{}{:04}", letter.to_ascii_uppercase(), number);
+        ErrorCode::ALL.into_iter().find(|c| c.as_str() == wanted)
+    }
+
+    pub fn is_warning(self) -> bool {
+        self.as_str().starts_with('W')
+    }
+
+    pub fn explanation(self) -> &'static Explanation {
+        match self {
+            ErrorCode::UnexpectedToken => &Explanation {
+                code: "E0001",
+                title: "unexpected token",
+                description: "The parser found a token that cannot appear at this point. The \
+                    message says what was expected instead. Often the cause is a little \
+                    earlier: a missing comma, semicolon or closing bracket.",
+                erroneous: "fn add(a: int b: int) -> int {\n    a + b\n}",
+                fixed: "fn add(a: int, b: int) -> int {\n    a + b\n}",
+            },
+            ErrorCode::UnexpectedEof => &Explanation {
+                code: "E0002",
+                title: "unexpected end of file",
+                description: "The file ended in the middle of an item. Usually a `}` or `)` \
+                    was never closed; the message says what the parser was still waiting for.",
+                erroneous: "fn answer() -> int {\n    42",
+                fixed: "fn answer() -> int {\n    42\n}",
+            },
+            ErrorCode::MalformedAttribute => &Explanation {
+                code: "E0003",
+                title: "malformed attribute",
+                description: "An attribute's arguments are not in a form attributes take. \
+                    Values after `=` must be string literals, as in `#[name = \"value\"]` or \
+                    `#[cfg(feature = \"json\")]`.",
+                erroneous: "#[cfg(feature = json)]\nfn encode() -> String {\n    \"{}\"\n}",
+                fixed: "#[cfg(feature = \"json\")]\nfn encode() -> String {\n    \"{}\"\n}",
+            },
+            ErrorCode::MissingTypeAnnotation => &Explanation {
+                code: "E0004",
+                title: "missing parameter type",
+                description: "Function parameters need a type. Only literal, enum \
+                    variant and binary patterns, whose type is obvious, may leave it out, \
+                    and so may the later clauses of a function, which take the types of \
+                    its first clause.",
+                erroneous: "fn double(x) -> int {\n    x * 2\n}",
+                fixed: "fn double(x: int) -> int {\n    x * 2\n}",
+            },
+            ErrorCode::ClauseArity => &Explanation {
+                code: "E0005",
+                title: "clause with no first clause of its arity",
+                description: "A function defined more than once with the same number of \
+                    arguments is one function with several clauses, tried in order, and a \
+                    later clause takes the types it leaves out from the first. Definitions \
+                    with another number of arguments are another function, so a clause \
+                    leaving out its types has no first clause to take them from.",
+                erroneous: "fn area(side: int) -> int {\n    side * side\n}\n\nfn area(w, h) {\n    w * h\n}",
+                fixed: "fn area(side: int) -> int {\n    side * side\n}\n\nfn area(w: int, h: int) -> int {\n    w * h\n}",
+            },
+            ErrorCode::ModuleNotFound => &Explanation {
+                code: "E0010",
+                title: "module not found",
+                description: "A `mod` or `use` names a module with no source file. The \
+                    message lists the paths that were searched: `mod users;` in \
+                    `src/app.dream` looks for `src/app/users.dream` and \
+                    `src/app/users/mod.dream`.",
+                erroneous: "// src/app.dream, with no src/app/users.dream\nmod users;",
+                fixed: "// src/app.dream, after creating src/app/users.dream\nmod users;",
+            },
+            ErrorCode::CircularDependency => &Explanation {
+                code: "E0011",
+                title: "circular module dependency",
+                description: "Modules declare each other with `mod` in a cycle, so there is \
+                    no order to load them in. Declare each module from one parent only; \
+                    modules can still `use` each other in both directions.",
+                erroneous: "// a.dream\nmod b;\n\n// b.dream\nmod a;",
+                fixed: "// main.dream\nmod a;\nmod b;\n\n// a.dream\nuse crate::b;",
+            },
+            ErrorCode::UnreadableSource => &Explanation {
+                code: "E0012",
+                title: "source file cannot be read",
+                description: "A source file or directory exists but could not be read, \
+                    usually because of its permissions or because it is not UTF-8. The \
+                    message includes the error from the operating system.",
+                erroneous: "$ chmod 000 src/users.dream\n$ dream build",
+                fixed: "$ chmod 644 src/users.dream\n$ dream build",
+            },
+            ErrorCode::UndefinedVariable => &Explanation {
+                code: "E0020",
+                title: "undefined variable",
+                description: "A name is used that no `let`, parameter or pattern in scope \
+                    binds. Check the spelling, and that the binding is not inside a block \
+                    that has already ended.",
+                erroneous: "fn greet(name: String) -> String {\n    \"hello {nme}\"\n}",
+                fixed: "fn greet(name: String) -> String {\n    \"hello {name}\"\n}",
+            },
+            ErrorCode::UndefinedStruct => &Explanation {
+                code: "E0021",
+                title: "undefined struct",
+                description: "A struct literal names a struct that is not defined or \
+                    imported into this module. Define it, or bring it in with `use`.",
+                erroneous: "fn origin() -> Point {\n    Point { x: 0, y: 0 }\n}",
+                fixed: "struct Point {\n    x: int,\n    y: int,\n}\n\nfn origin() -> Point {\n    Point { x: 0, y: 0 }\n}",
+            },
+            ErrorCode::UndefinedVariant => &Explanation {
+                code: "E0022",
+                title: "undefined enum variant",
+                description: "An enum has no variant with this name, or a bare variant \
+                    name doesn't belong to any enum in scope.",
+                erroneous: "enum Color {\n    Red,\n    Green,\n}\n\nfn sky() -> Color {\n    Color::Blue\n}",
+                fixed: "enum Color {\n    Red,\n    Green,\n    Blue,\n}\n\nfn sky() -> Color {\n    Color::Blue\n}",
+            },
+            ErrorCode::UnknownField => &Explanation {
+                code: "E0023",
+                title: "unknown field",
+                description: "A struct, or a struct-like enum variant, has no field with this \
+                    name. Check the spelling against the definition.",
+                erroneous: "struct User {\n    name: String,\n}\n\nfn name(user: User) -> String {\n    user.nmae\n}",
+                fixed: "struct User {\n    name: String,\n}\n\nfn name(user: User) -> String {\n    user.name\n}",
+            },
+            ErrorCode::UndefinedTrait => &Explanation {
+                code: "E0024",
+                title: "undefined trait",
+                description: "An `impl Trait for Type` names a trait that is not defined or \
+                    imported into this module.",
+                erroneous: "struct Point {\n    x: int,\n}\n\nimpl Show for Point {\n    fn show(self) -> String {\n        \"point\"\n    }\n}",
+                fixed: "trait Show {\n    fn show(self) -> String;\n}\n\nstruct Point {\n    x: int,\n}\n\nimpl Show for Point {\n    fn show(self) -> String {\n        \"point\"\n    }\n}",
+            },
+            ErrorCode::TypeMismatch => &Explanation {
+                code: "E0030",
+                title: "mismatched types",
+                description: "A value's type differs from the type its context requires: a \
+                    declared return type, a parameter, a `let` annotation, a field, or the \
+                    other arms of a `match`. The help says which type was expected and \
+                    which was found.",
+                erroneous: "fn count() -> int {\n    \"three\"\n}",
+                fixed: "fn count() -> int {\n    3\n}",
+            },
+            ErrorCode::ArgumentCount => &Explanation {
+                code: "E0031",
+                title: "wrong number of arguments",
+                description: "A function, method or enum variant is called with more or \
+                    fewer arguments than it declares. Dream has no default or variadic \
+                    arguments; functions with different arities are different functions.",
+                erroneous: "fn add(a: int, b: int) -> int {\n    a + b\n}\n\nfn three() -> int {\n    add(1, 1, 1)\n}",
+                fixed: "fn add(a: int, b: int) -> int {\n    a + b\n}\n\nfn three() -> int {\n    add(add(1, 1), 1)\n}",
+            },
+            ErrorCode::InvalidOperand => &Explanation {
+                code: "E0032",
+                title: "invalid operand type",
+                description: "An operator is applied to a type it doesn't work on. \
+                    Arithmetic and `-x` take ints, `&&`, `||` and `!` take bools, and both \
+                    sides of a comparison must have the same type. Strings are joined with \
+                    interpolation rather than `+`.",
+                erroneous: "fn label(n: int) -> String {\n    \"item \" + n\n}",
+                fixed: "fn label(n: int) -> String {\n    \"item {n}\"\n}",
+            },
+            ErrorCode::NonBoolCondition => &Explanation {
+                code: "E0033",
+                title: "condition is not a bool",
+                description: "Conditions of `if`, guards of functions, `match` and \
+                    `receive` arms, and `when` filters of `for` must be bools. There is \
+                    no truthiness: compare explicitly.",
+                erroneous: "fn describe(n: int) -> String {\n    if n {\n        \"nonzero\"\n    } else {\n        \"zero\"\n    }\n}",
+                fixed: "fn describe(n: int) -> String {\n    if n != 0 {\n        \"nonzero\"\n    } else {\n        \"zero\"\n    }\n}",
+            },
+            ErrorCode::InvalidTry => &Explanation {
+                code: "E0034",
+                title: "`?` applied to a value that is not a Result or Option",
+                description: "The `?` operator returns early on `Err` or `None`, so it only \
+                    applies to `Result` and `Option` values, in functions returning the \
+                    same kind.",
+                erroneous: "fn half(n: int) -> Result<int, String> {\n    Ok(n? / 2)\n}",
+                fixed: "fn half(n: int) -> Result<int, String> {\n    Ok(n / 2)\n}",
+            },
+            ErrorCode::VariantShape => &Explanation {
+                code: "E0035",
+                title: "enum variant used with the wrong syntax",
+                description: "Unit variants take no arguments, tuple variants are built \
+                    with parentheses and struct variants with braces, as declared.",
+                erroneous: "enum Shape {\n    Circle(int),\n    Rect { w: int, h: int },\n}\n\nfn square(side: int) -> Shape {\n    Shape::Rect(side, side)\n}",
+                fixed: "enum Shape {\n    Circle(int),\n    Rect { w: int, h: int },\n}\n\nfn square(side: int) -> Shape {\n    Shape::Rect { w: side, h: side }\n}",
+            },
+            ErrorCode::MissingField => &Explanation {
+                code: "E0036",
+                title: "missing field",
+                description: "A struct variant is built without one of its fields. Every \
+                    field must be given a value.",
+                erroneous: "enum Event {\n    Move { x: int, y: int },\n}\n\nfn right() -> Event {\n    Event::Move { x: 1 }\n}",
+                fixed: "enum Event {\n    Move { x: int, y: int },\n}\n\nfn right() -> Event {\n    Event::Move { x: 1, y: 0 }\n}",
+            },
+            ErrorCode::TypeArgumentCount => &Explanation {
+                code: "E0037",
+                title: "wrong number of type arguments",
+                description: "A generic function is called with explicit type arguments, \
+                    but not as many as it has type parameters.",
+                erroneous: "fn first<T>(items: [T]) -> Option<T> {\n    list::head(items)\n}\n\nfn main() -> Option<int> {\n    first::<int, int>([1, 2])\n}",
+                fixed: "fn first<T>(items: [T]) -> Option<T> {\n    list::head(items)\n}\n\nfn main() -> Option<int> {\n    first::<int>([1, 2])\n}",
+            },
+            ErrorCode::UnsatisfiedBound => &Explanation {
+                code: "E0038",
+                title: "trait bound not satisfied",
+                description: "A generic function requires its type argument to implement a \
+                    trait, and the type it was called with does not. Implement the trait \
+                    for the type, or call the function with a type that has it.",
+                erroneous: "trait Show {\n    fn show(self) -> String;\n}\n\nstruct Point {\n    x: int,\n}\n\nfn print<T: Show>(value: T) -> String {\n    value.show()\n}\n\nfn main() -> String {\n    print::<Point>(Point { x: 1 })\n}",
+                fixed: "trait Show {\n    fn show(self) -> String;\n}\n\nstruct Point {\n    x: int,\n}\n\nimpl Show for Point {\n    fn show(self) -> String {\n        \"point\"\n    }\n}\n\nfn print<T: Show>(value: T) -> String {\n    value.show()\n}\n\nfn main() -> String {\n    print::<Point>(Point { x: 1 })\n}",
+            },
+            ErrorCode::NonExhaustiveMatch => &Explanation {
+                code: "E0040",
+                title: "non-exhaustive match",
+                description: "A `match` on an enum, bool or similar closed type has no arm \
+                    for some of its values, which would crash at runtime. Add arms for the \
+                    missing patterns listed in the help, or a `_` arm.",
+                erroneous: "fn name(n: Option<String>) -> String {\n    match n {\n        Some(s) => s,\n    }\n}",
+                fixed: "fn name(n: Option<String>) -> String {\n    match n {\n        Some(s) => s,\n        None => \"anonymous\",\n    }\n}",
+            },
+            ErrorCode::IncompleteTraitImpl => &Explanation {
+                code: "E0041",
+                title: "trait implementation is missing items",
+                description: "An `impl Trait for Type` leaves out a method or associated \
+                    type the trait requires and has no default for. The help lists what \
+                    is missing.",
+                erroneous: "trait Shape {\n    fn area(self) -> int;\n    fn sides(self) -> int;\n}\n\nstruct Square {\n    side: int,\n}\n\nimpl Shape for Square {\n    fn area(self) -> int {\n        self.side * self.side\n    }\n}",
+                fixed: "trait Shape {\n    fn area(self) -> int;\n    fn sides(self) -> int;\n}\n\nstruct Square {\n    side: int,\n}\n\nimpl Shape for Square {\n    fn area(self) -> int {\n        self.side * self.side\n    }\n\n    fn sides(self) -> int {\n        4\n    }\n}",
+            },
+            ErrorCode::InvalidRegex => &Explanation {
+                code: "E0042",
+                title: "invalid regular expression",
+                description: "A pattern written as a string literal in a call to \
+                    `regex::new`, `regex::compile` or `regex::compile_with` is not a valid \
+                    regular expression, so it could never be compiled at runtime. The help \
+                    gives the byte of the pattern where the problem is: typically a group \
+                    or bracket that is never closed, or a `*`, `+`, `?` or `{n,m}` with \
+                    nothing before it to repeat.",
+                erroneous: "fn number(text: String) -> Option<String> {\n    regex::find(regex::new(\"[0-9+\"), text)\n}",
+                fixed: "fn number(text: String) -> Option<String> {\n    regex::find(regex::new(\"[0-9]+\"), text)\n}",
+            },
+            ErrorCode::MacroExpansion => &Explanation {
+                code: "E0050",
+                title: "macro expansion failed",
+                description: "A `#[derive]` names a macro that is neither built in nor \
+                    defined with `#[proc_macro_derive]` in the package, or a user-defined \
+                    macro failed or returned something that is not valid Dream.",
+                erroneous: "#[derive(Frobnicate)]\nstruct Point {\n    x: int,\n}",
+                fixed: "#[derive(Debug, Clone)]\nstruct Point {\n    x: int,\n}",
+            },
+            ErrorCode::CompileError => &Explanation {
+                code: "E0051",
+                title: "`compile_error!` reached",
+                description: "Conditional compilation left a `compile_error!` in the module, \
+                    so the build stops with its message. Packages guard it with `#[cfg]` to \
+                    reject configurations they don't support, such as two features that \
+                    exclude each other; change the features or `--cfg` options the build \
+                    uses, as the message says.",
+                erroneous: "compile_error!(\"the `sync` and `async` features can't both be enabled\");",
+                fixed: "#[cfg(all(feature = \"sync\", feature = \"async\"))]\ncompile_error!(\"the `sync` and `async` features can't both be enabled\");",
+            },
+            ErrorCode::CodegenFailed => &Explanation {
+                code: "E0060",
+                title: "code generation failed",
+                description: "The program type checked but could not be translated to Core \
+                    Erlang, or `erlc` rejected the result. This is usually a construct the \
+                    backend does not support yet; the message names it. If the code looks \
+                    valid, please report it as a compiler bug.",
+                erroneous: "$ dream build\nCompile error in app: pipe right-hand side must be a function call or identifier",
+                fixed: "$ dream build --emit core\n# the .core files are written next to the .beam files",
+            },
+            ErrorCode::InternalError => &Explanation {
+                code: "E0070",
+                title: "internal compiler error",
+                description: "The compiler panicked: it met a case its own code assumed could \
+                    not happen. This is a bug in Dream, not in the program being compiled. \
+                    The error names the compiler version, the phase that failed and the \
+                    item it was working on; please report it at \
+                    https://github.com/scrogson/dream/issues with that item's code, cut \
+                    down as far as it still fails. Until it is fixed, writing the item \
+                    another way usually avoids it.",
+                erroneous: "$ dream build\nerror[E0070]: internal compiler error: index out of bounds",
+                fixed: "$ dream build\n# after rewriting the item the error pointed at",
+            },
+            ErrorCode::UnusedValue => &Explanation {
+                code: "W0001",
+                title: "unused value",
+                description: "A statement computes a value that is then thrown away, which \
+                    usually means a result that should have been checked was ignored. \
+                    Bind it with `let _ = ...` when dropping it is intended.",
+                erroneous: "fn save(path: String) -> atom {\n    file::write(path, \"data\");\n    :ok\n}",
+                fixed: "fn save(path: String) -> atom {\n    let _ = file::write(path, \"data\");\n    :ok\n}",
+            },
+            ErrorCode::UnknownFunction => &Explanation {
+                code: "W0002",
+                title: "unknown function",
+                description: "A call names a function that is not defined in the module, \
+                    imported, or built in, but is close to the name of one that is. The \
+                    call is compiled as a local call and fails when the module is \
+                    assembled, so this is most likely a typo. Calls with no similar name \
+                    nearby are left alone.",
+                erroneous: "fn double(x: int) -> int {\n    x * 2\n}\n\nfn quadruple(x: int) -> int {\n    doubel(doubel(x))\n}",
+                fixed: "fn double(x: int) -> int {\n    x * 2\n}\n\nfn quadruple(x: int) -> int {\n    double(double(x))\n}",
+            },
+            ErrorCode::Deprecated => &Explanation {
+                code: "W0003",
+                title: "use of deprecated item",
+                description: "A function, struct or enum marked `#[deprecated]` is used. \
+                    It still works, but is expected to go away; the note on the attribute \
+                    usually says what to use instead. Set `deprecated = \"allow\"` under \
+                    `[warnings]` in dream.toml to silence these while migrating.",
+                erroneous: "#[deprecated(note = \"use `area` instead\")]\nfn size(w: int, h: int) -> int {\n    w * h\n}\n\nfn area(w: int, h: int) -> int {\n    w * h\n}\n\nfn main() -> int {\n    size(2, 3)\n}",
+                fixed: "#[deprecated(note = \"use `area` instead\")]\nfn size(w: int, h: int) -> int {\n    w * h\n}\n\nfn area(w: int, h: int) -> int {\n    w * h\n}\n\nfn main() -> int {\n    area(2, 3)\n}",
+            },
+            ErrorCode::DynamicCoercion => &Explanation {
+                code: "W0004",
+                title: "implicit coercion from `any`",
+                description: "A value the type checker knows nothing about, such as the \
+                    result of an untyped Erlang call, is used where a concrete type is \
+                    expected. It is accepted without a check, so a wrong value fails \
+                    later and further away. These are allowed by default; set \
+                    `dynamic = \"warn\"` under `[warnings]` to find them.",
+                erroneous: "fn now() -> int {\n    let t: int = :legacy::timestamp();\n    t\n}",
+                fixed: "extern mod legacy {\n    fn timestamp() -> int;\n}\n\nfn now() -> int {\n    let t: int = legacy::timestamp();\n    t\n}",
+            },
+            ErrorCode::UnexpectedCfg => &Explanation {
+                code: "W0005",
+                title: "unexpected cfg condition",
+                description: "A `#[cfg]` or `#[cfg_attr]` condition tests a name the compiler \
+                    doesn't set, a feature dream.toml doesn't declare, or a target that \
+                    doesn't exist. The condition is false, so the code it guards is quietly \
+                    left out, which is rarely what a misspelt name meant. Names set only \
+                    now and then with `--cfg` can be listed in `check-cfg` under `[build]`.",
+                erroneous: "#[cfg(fature = \"json\")]\nfn encode(term: any) -> String {\n    :jason::encode(term)\n}",
+                fixed: "#[cfg(feature = \"json\")]\nfn encode(term: any) -> String {\n    :jason::encode(term)\n}",
+            },
+            ErrorCode::UnreachableClause => &Explanation {
+                code: "W0006",
+                title: "unreachable clause",
+                description: "Clauses of a function are tried in order, and an earlier \
+                    clause without a guard matches every argument this one would, so this \
+                    one never runs. Usually a catch-all clause comes too early: move it \
+                    after the clauses for particular values.",
+                erroneous: "fn fact(n: int) -> int {\n    n * fact(n - 1)\n}\n\nfn fact(0) {\n    1\n}",
+                fixed: "fn fact(0) -> int {\n    1\n}\n\nfn fact(n) {\n    n * fact(n - 1)\n}",
+            },
+        }
+    }
+
+    /// The full explanation, as Markdown.
+    pub fn explain(self) -> String {
+        let e = self.explanation();
+        let kind = if self.is_warning() {
+            "warning"
+        } else {
+            "error"
+        };
+        format!(
+            "# {}: {}\n\n{}\n\nExample of code with this {}:\n\n```\n{}\n```\n\nFixed:\n\n```\n{}\n```\n",
+            e.code, e.title, e.description, kind, e.erroneous, e.fixed
+        )
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A span a diagnostic points at besides its primary one, such as where
+/// an unclosed delimiter was opened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// How sure a [`Suggestion`] is to be right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Applicability {
+    /// Applying it keeps the program's meaning, so `dream fix` may apply it
+    /// without asking, e.g. removing an unused import.
+    MachineApplicable,
+    /// A guess a person should look at first, e.g. a "did you mean" name.
+    MaybeIncorrect,
+}
+
+impl Applicability {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Applicability::MachineApplicable => "machine-applicable",
+            Applicability::MaybeIncorrect => "maybe-incorrect",
+        }
+    }
+}
+
+/// A proposed fix: replace the text at `span` with `replacement`. An empty
+/// span inserts, and an empty replacement deletes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    /// What the edit does, e.g. "add a semicolon"
+    pub message: String,
+    pub applicability: Applicability,
+}
+
+impl Suggestion {
+    pub fn is_machine_applicable(&self) -> bool {
+        self.applicability == Applicability::MachineApplicable
+    }
+}
+
+/// Everything a diagnostic says besides its message, primary span and help.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotations {
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl Annotations {
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.notes.is_empty() && self.suggestions.is_empty()
+    }
+
+    /// `help` followed by the notes, one per line, for renderers that only
+    /// have room for a single block of text under the source.
+    pub fn footer(&self, help: Option<&str>) -> Option<String> {
+        let lines: Vec<String> = help
+            .map(str::to_string)
+            .into_iter()
+            .chain(self.notes.iter().map(|note| format!("note: {}", note)))
+            .collect();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
+/// Builders for the [`Annotations`] of a diagnostic.
+pub trait Annotate: Sized {
+    fn annotations_mut(&mut self) -> &mut Annotations;
+
+    /// Point at `span` too, saying `message` there.
+    fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.annotations_mut().labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.annotations_mut().notes.push(note.into());
+        self
+    }
+
+    /// Suggest an edit a person should check before applying.
+    fn with_suggestion(
+        mut self,
+        span: Span,
+        replacement: impl Into<String>,
+        message: impl Into<String>,
+    ) -> Self {
+        self.annotations_mut().suggestions.push(Suggestion {
+            span,
+            replacement: replacement.into(),
+            message: message.into(),
+            applicability: Applicability::MaybeIncorrect,
+        });
+        self
+    }
+
+    /// Suggest an edit that is certainly right, which `dream fix` applies.
+    fn with_fix(mut self, span: Span, replacement: impl Into<String>, message: impl Into<String>) -> Self {
+        self.annotations_mut().suggestions.push(Suggestion {
+            span,
+            replacement: replacement.into(),
+            message: message.into(),
+            applicability: Applicability::MachineApplicable,
+        });
+        self
+    }
+}
+
+/// The name among `candidates` that `name` is most likely a misspelling
+/// of: one differing only in case, or else the closest by edit distance,
+/// if that is at most a third of the name's length. Ties go to the first
+/// in alphabetical order, so the answer doesn't depend on iteration order.
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max = (name.chars().count() / 3).max(1);
+    let mut best: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        if candidate == name {
+            continue;
+        }
+        let distance = if candidate.eq_ignore_ascii_case(name) {
+            0
+        } else {
+            edit_distance(name, candidate)
+        };
+        let better = match best {
+            Some((d, b)) => distance < d || (distance == d && candidate < b),
+            None => true,
+        };
+        if distance <= max && better {
+            best = Some((distance, candidate));
+        }
+    }
+    best.map(|(_, candidate)| candidate)
+}
+
+/// The edit distance between `a` and `b`: how many characters have to be
+/// inserted, deleted or replaced, or adjacent pairs swapped, to turn one
+/// into the other. Swaps count once since they are such a common typo.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // d[i][j] is the distance between the first i of `a` and first j of `b`
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes_are_unique() {
+        let codes: HashSet<_> = ErrorCode::ALL.iter().map(|c| c.as_str()).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+    }
+
+    #[test]
+    fn test_from_code() {
+        assert_eq!(ErrorCode::from_code("E0030"), Some(ErrorCode::TypeMismatch));
+        assert_eq!(ErrorCode::from_code("e30"), Some(ErrorCode::TypeMismatch));
+        assert_eq!(ErrorCode::from_code("W0001"), Some(ErrorCode::UnusedValue));
+        assert_eq!(ErrorCode::from_code("E9999"), None);
+        assert_eq!(ErrorCode::from_code("E"), None);
+        assert_eq!(ErrorCode::from_code(""), None);
+    }
+
+    #[test]
+    fn test_explain() {
+        let text = ErrorCode::UndefinedVariable.explain();
+        assert!(text.starts_with("# E0020: undefined variable\n\n"));
+        assert!(text.contains("Example of code with this error:\n\n```\nfn greet"));
+        assert!(
+            ErrorCode::UnusedValue
+                .explain()
+                .contains("with this warning")
+        );
+    }
+
+    #[test]
+    fn test_footer() {
+        let mut annotations = Annotations::default();
+        assert_eq!(annotations.footer(None), None);
+        assert_eq!(
+            annotations.footer(Some("try this")).as_deref(),
+            Some("try this")
+        );
+        annotations.notes.push("types must match".to_string());
+        assert_eq!(
+            annotations.footer(Some("try this")).as_deref(),
+            Some("try this\nnote: types must match")
+        );
+        assert_eq!(
+            annotations.footer(None).as_deref(),
+            Some("note: types must match")
+        );
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("count", "count"), 0);
+        assert_eq!(edit_distance("jsno", "json"), 1);
+
+        let names = ["count", "counter", "total", "Point"];
+        assert_eq!(did_you_mean("cout", names), Some("count"));
+        assert_eq!(did_you_mean("countr", names), Some("count"));
+        assert_eq!(did_you_mean("point", names), Some("Point"));
+        assert_eq!(did_you_mean("x", names), None);
+        assert_eq!(did_you_mean("count", names), None);
+        assert_eq!(did_you_mean("ab", ["ac", "aa"]), Some("aa"));
+    }
+
+    #[test]
+    fn test_examples_parse() {
+        use crate::compiler::Parser;
+        for code in ErrorCode::ALL {
+            let e = code.explanation();
+            // Examples of syntax and file errors can't be Dream that parses
+            if matches!(
+                e.code,
+                "E0001" | "E0002" | "E0003" | "E0004" | "E0005" | "E0010" | "E0011" | "E0012"
+                    | "E0060" | "E0070"
+            ) {
+                continue;
+            }
+            for source in [e.erroneous, e.fixed] {
+                assert!(
+                    Parser::new(source).parse_file("example").is_ok(),
+                    "{} example:\n{}",
+                    e.code,
+                    source
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,329 @@
+//! Driving the compiler from other programs.
+//!
+//! [`compile_str`] and [`Compiler`] take Dream source through type checking
+//! and code generation to Core Erlang, and optionally through `erlc` to a
+//! `.beam`, without shelling out to the `dream` CLI. Build tool plugins,
+//! the language server and tests call them directly, and get problems back
+//! as structured [`Diagnostic`]s rather than printed text.
+
+use super::{
+    check_modules_with_levels, configure_module, expand_derives_with_registry, expand_quotes, resolve_stdlib_methods,
+    CoreErlangEmitter, ErrorCode, GenericFunctionRegistry, MacroRegistry, Module, ModuleContext, Parser,
+};
+use crate::config::CompileOptions;
+use crate::output::{Diagnostic, Level};
+use crate::toolchain;
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Compile one module from source to Core Erlang. `name` is the module
+/// name, unless the source wraps itself in `mod <name> { ... }`.
+pub fn compile_str(name: &str, source: &str, options: &CompileOptions) -> Result<CompiledModule, Diagnostics> {
+    Compiler::new(options.clone()).compile_str(name, source)
+}
+
+/// The output of compiling one module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledModule {
+    /// Dream module name, e.g. `app::math`
+    pub name: String,
+    /// BEAM module name, e.g. `dream::app::math`
+    pub beam_module: String,
+    pub core_erlang: String,
+    /// The assembled module, if [`Compiler::with_beam`] asked for it
+    pub beam: Option<Vec<u8>>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Why a compilation failed: at least one error, plus any warnings
+/// reported before it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter().filter(|d| d.level == Level::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter().filter(|d| d.level == Level::Warning)
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, diagnostic) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", diagnostic.level, diagnostic.message)?;
+            if let Some(span) = &diagnostic.span {
+                write!(f, " at {}:{}", span.line_start, span.column_start)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// A configured compiler, for when [`compile_str`]'s defaults are not
+/// enough.
+#[derive(Debug, Clone, Default)]
+pub struct Compiler {
+    options: CompileOptions,
+    package: Option<String>,
+    /// Modules type checked alongside the one being compiled
+    context: Vec<Module>,
+    beam: bool,
+}
+
+impl Compiler {
+    pub fn new(options: CompileOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Compile modules as members of `package`, so that `crate::` and
+    /// `super::` paths resolve.
+    pub fn with_package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
+
+    /// Type check against `modules` as well, without compiling them: the
+    /// stdlib, FFI stubs or the rest of a project.
+    pub fn with_context(mut self, modules: impl IntoIterator<Item = Module>) -> Self {
+        self.context.extend(modules);
+        self
+    }
+
+    /// Also assemble a `.beam`, which needs `erlc` on the `PATH`.
+    pub fn with_beam(mut self) -> Self {
+        self.beam = true;
+        self
+    }
+
+    /// Parse and compile one module. `name` is as for [`compile_str`].
+    pub fn compile_str(&self, name: &str, source: &str) -> Result<CompiledModule, Diagnostics> {
+        let mut parser = Parser::new(source);
+        let module = parser.parse_file(name).map_err(|_| {
+            let errors = parser.errors().iter();
+            Diagnostics(errors.map(|e| Diagnostic::from_parse_error(name, e, source)).collect())
+        })?;
+        self.compile_module(module)
+    }
+
+    /// Compile an already parsed module.
+    pub fn compile_module(&self, mut module: Module) -> Result<CompiledModule, Diagnostics> {
+        let configured = configure_module(&mut module, &self.options);
+        let name = module.name.clone();
+        let source = module.source.clone();
+        let (cfg_warnings, mut denied) = self.options.warnings.apply(configured.warnings);
+        denied.extend(configured.errors);
+        let mut diagnostics: Vec<Diagnostic> = cfg_warnings
+            .iter()
+            .map(|w| Diagnostic::from_warning(w, source.as_deref()))
+            .collect();
+        if !denied.is_empty() {
+            diagnostics.extend(
+                denied
+                    .iter()
+                    .map(|e| Diagnostic::from_type_error(&name, e, source.as_deref())),
+            );
+            return Err(Diagnostics(diagnostics));
+        }
+
+        let mut modules = self.context.clone();
+        modules.push(module);
+        let checked = check_modules_with_levels(&modules, &self.options.warnings);
+
+        diagnostics.extend(
+            checked
+                .warnings
+                .iter()
+                .filter(|w| w.module.as_ref().is_none_or(|m| *m == name))
+                .map(|w| Diagnostic::from_warning(w, source.as_deref())),
+        );
+        let error = |diagnostics: &mut Vec<Diagnostic>, diagnostic: Diagnostic| {
+            diagnostics.push(diagnostic);
+            Diagnostics(std::mem::take(diagnostics))
+        };
+
+        let mut module = match checked.modules.into_iter().find(|(n, _)| *n == name) {
+            Some((_, Ok(module))) => module,
+            Some((_, Err(e))) => {
+                let diagnostic = Diagnostic::from_type_error(&name, &e, source.as_deref());
+                return Err(error(&mut diagnostics, diagnostic));
+            }
+            None => {
+                let diagnostic = Diagnostic::error("module was not type checked").in_module(&name);
+                return Err(error(&mut diagnostics, diagnostic));
+            }
+        };
+
+        // Only the built-in derives: user macros need a compiled macro module
+        if let Err(errors) = expand_derives_with_registry(&mut module, &mut MacroRegistry::new()) {
+            diagnostics.extend(errors.into_iter().map(|e| {
+                let diagnostic = Diagnostic::error(e.message)
+                    .with_code(ErrorCode::MacroExpansion.as_str())
+                    .in_module(&name);
+                match &source {
+                    Some(source) => diagnostic.with_span(source, Some(e.span)),
+                    None => diagnostic,
+                }
+            }));
+            return Err(Diagnostics(diagnostics));
+        }
+        expand_quotes(&mut module);
+        resolve_stdlib_methods(&mut module);
+
+        let context = match &self.package {
+            Some(package) => ModuleContext::for_module(package, &name),
+            None => ModuleContext::default(),
+        };
+        let registry = Arc::new(RwLock::new(GenericFunctionRegistry::new()));
+        let mut emitter = CoreErlangEmitter::with_all(registry, context, self.options.clone());
+        emitter.set_extern_module_names(checked.extern_module_names);
+        emitter.set_struct_info(checked.struct_info);
+        let core_erlang = match emitter.emit_module(&module) {
+            Ok(core_erlang) => core_erlang,
+            Err(e) => {
+                let diagnostic = Diagnostic::error(e.message)
+                    .with_code(ErrorCode::CodegenFailed.as_str())
+                    .in_module(&name);
+                return Err(error(&mut diagnostics, diagnostic));
+            }
+        };
+
+        // All Dream modules are prefixed with dream:: (like Elixir uses Elixir.)
+        let beam_module = if name.starts_with("dream::") {
+            name.clone()
+        } else {
+            format!("dream::{}", name)
+        };
+        let beam = if self.beam {
+            match assemble(&beam_module, &core_erlang, &self.options.erlc_flags) {
+                Ok(beam) => Some(beam),
+                Err(message) => {
+                    let diagnostic = Diagnostic::error(message)
+                        .with_code(ErrorCode::CodegenFailed.as_str())
+                        .in_module(&name);
+                    return Err(error(&mut diagnostics, diagnostic));
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(CompiledModule {
+            name,
+            beam_module,
+            core_erlang,
+            beam,
+            warnings: diagnostics,
+        })
+    }
+}
+
+/// Turn Core Erlang into `.beam` bytes with `erlc`, in a scratch directory.
+fn assemble(beam_module: &str, core_erlang: &str, erlc_flags: &[String]) -> Result<Vec<u8>, String> {
+    static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "dream_compile_{}_{}",
+        std::process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+
+    let core_file = dir.join(format!("{}.core", beam_module));
+    let result = fs::write(&core_file, core_erlang)
+        .map_err(|e| format!("cannot write {}: {}", core_file.display(), e))
+        .and_then(|()| {
+            toolchain::erlc()
+                .arg("+from_core")
+                .args(erlc_flags)
+                .arg("-o")
+                .arg(&dir)
+                .arg(&core_file)
+                .output()
+                .map_err(|e| format!("cannot run erlc: {}", e))
+        })
+        .and_then(|output| {
+            if output.status.success() {
+                fs::read(dir.join(format!("{}.beam", beam_module))).map_err(|e| e.to_string())
+            } else {
+                // erlc reports errors on stdout
+                Err(format!("erlc failed: {}", String::from_utf8_lossy(&output.stdout).trim()))
+            }
+        });
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::LintLevel;
+
+    #[test]
+    fn test_compile_str() {
+        let compiled = compile_str(
+            "app::math",
+            "pub fn add(a: int, b: int) -> int {\n    a + b\n}\n",
+            &CompileOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(compiled.name, "app::math");
+        assert_eq!(compiled.beam_module, "dream::app::math");
+        assert!(compiled.core_erlang.contains("'add'/2"), "{}", compiled.core_erlang);
+        assert_eq!(compiled.beam, None);
+        assert!(compiled.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let errors = compile_str("app", "pub fn broken( {\n}\n", &CompileOptions::default()).unwrap_err();
+        let error = errors.errors().next().unwrap();
+        assert_eq!(error.module.as_deref(), Some("app"));
+        assert_eq!(error.span.as_ref().map(|s| s.line_start), Some(1));
+    }
+
+    #[test]
+    fn test_type_error() {
+        let errors = compile_str("app", "fn bad() -> int {\n    \"hello\"\n}\n", &CompileOptions::default())
+            .unwrap_err();
+        assert_eq!(errors.errors().count(), 1);
+        assert!(errors.to_string().starts_with("error: "), "{}", errors);
+    }
+
+    #[test]
+    fn test_unexpected_cfg() {
+        let source = "#[cfg(tset)]\nfn helper() -> int {\n    1\n}\n\npub fn answer() -> int {\n    42\n}\n";
+        let compiled = compile_str("app", source, &CompileOptions::default()).unwrap();
+        assert_eq!(compiled.warnings.len(), 1);
+        assert_eq!(compiled.warnings[0].code.as_deref(), Some("W0005"));
+        assert_eq!(compiled.warnings[0].help.as_deref(), Some("did you mean `test`?"));
+
+        let mut options = CompileOptions::default();
+        options.warnings.set("unexpected_cfgs", LintLevel::Deny).unwrap();
+        let errors = compile_str("app", source, &options).unwrap_err();
+        assert_eq!(errors.errors().count(), 1);
+    }
+
+    #[test]
+    fn test_compile_error() {
+        let source = "#[cfg(not(feature = \"json\"))]\ncompile_error!(\"build with the `json` feature\");\n\npub fn answer() -> int {\n    42\n}\n";
+        let errors = compile_str("app", source, &CompileOptions::default()).unwrap_err();
+        let error = errors.errors().next().unwrap();
+        assert_eq!(error.message, "build with the `json` feature");
+        assert_eq!(error.code.as_deref(), Some("E0051"));
+
+        let options = CompileOptions::with_features(["json".to_string()].into());
+        assert!(compile_str("app", source, &options).is_ok());
+    }
+}
@@ -0,0 +1,114 @@
+//! Intermediate representations the compiler can write out with `--emit`.
+//!
+//! Each [`EmitKind`] is one stage of the pipeline. The dumps are meant for
+//! debugging the compiler and for tools built on it, so they print the
+//! compiler's own data structures rather than a stable format.
+
+use super::ast::Module;
+use super::token::Token;
+use crate::output::line_column;
+use logos::Logos;
+use std::fmt::Write;
+
+/// A stage of compilation whose output can be written to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmitKind {
+    /// The token stream of each source file
+    Tokens,
+    /// The parsed syntax tree, before type checking
+    Ast,
+    /// The syntax tree handed to code generation: annotated with inferred
+    /// types, with derives and quotes expanded
+    TypedAst,
+    /// Core Erlang
+    Core,
+    /// BEAM bytecode
+    Beam,
+}
+
+impl EmitKind {
+    pub const ALL: [EmitKind; 5] = [
+        EmitKind::Tokens,
+        EmitKind::Ast,
+        EmitKind::TypedAst,
+        EmitKind::Core,
+        EmitKind::Beam,
+    ];
+
+    /// The name used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            EmitKind::Tokens => "tokens",
+            EmitKind::Ast => "ast",
+            EmitKind::TypedAst => "typed-ast",
+            EmitKind::Core => "core",
+            EmitKind::Beam => "beam",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EmitKind> {
+        EmitKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Extension of the files this stage is written to.
+    pub fn extension(self) -> &'static str {
+        match self {
+            EmitKind::Tokens => "tokens",
+            EmitKind::Ast => "ast",
+            EmitKind::TypedAst => "typed-ast",
+            EmitKind::Core => "core",
+            EmitKind::Beam => "beam",
+        }
+    }
+}
+
+/// The tokens of `source`, one per line as `line:column token`. Text the
+/// lexer rejects is shown as `<error>`.
+pub fn dump_tokens(source: &str) -> String {
+    let mut out = String::new();
+    for (token, span) in Token::lexer(source).spanned() {
+        let (line, column) = line_column(source, span.start);
+        let written = match token {
+            Ok(token) => writeln!(out, "{}:{}\t{:?}", line, column, token),
+            Err(()) => writeln!(out, "{}:{}\t<error> {:?}", line, column, &source[span]),
+        };
+        written.expect("writing to a String cannot fail");
+    }
+    out
+}
+
+/// The items of `module` as a tree.
+pub fn dump_ast(module: &Module) -> String {
+    format!("{:#?}\n", module.items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Parser;
+
+    #[test]
+    fn test_names_round_trip() {
+        for kind in EmitKind::ALL {
+            assert_eq!(EmitKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(EmitKind::from_name("asm"), None);
+    }
+
+    #[test]
+    fn test_dump_tokens() {
+        let dump = dump_tokens("fn f() {\n    x\n}");
+        assert_eq!(
+            dump,
+            "1:1\tFn\n1:4\tIdent(\"f\")\n1:5\tLParen\n1:6\tRParen\n1:8\tLBrace\n2:5\tIdent(\"x\")\n3:1\tRBrace\n"
+        );
+    }
+
+    #[test]
+    fn test_dump_ast() {
+        let module = Parser::new("fn answer() -> int { 42 }").parse_file("app").unwrap();
+        let dump = dump_ast(&module);
+        assert!(dump.contains("name: \"answer\""));
+        assert!(dump.contains("Int(\n"));
+    }
+}
@@ -1,22 +1,33 @@
 //! Compiler errors with rich diagnostics.
-
+//!
+//! Each error carries an [`ErrorCode`], shown next to the message and
+//! explained by `dream explain`, and [`Annotations`]: secondary spans,
+//! notes and suggested fixes. The `Diagnostic` impls are written out rather
+//! than derived so that the code and the number of labels can vary per
+//! error.
+
+use crate::compiler::diagnostics::{Annotate, Annotations, ErrorCode};
 use crate::compiler::lexer::Span;
+use crate::compiler::lint::LintLevel;
 use crate::compiler::token::Token;
-use miette::{Diagnostic, NamedSource, SourceSpan};
+use crate::compiler::warnings::WarningCategory;
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceCode, SourceSpan};
+use std::fmt;
 use thiserror::Error;
 
 /// A parse error with source location.
-#[derive(Error, Debug, Diagnostic, Clone)]
+#[derive(Error, Debug, Clone)]
 #[error("{message}")]
-#[diagnostic()]
 pub struct ParseError {
     pub message: String,
 
-    #[label("here")]
     pub span: SourceSpan,
 
-    #[help]
     pub help: Option<String>,
+
+    pub code: ErrorCode,
+
+    pub annotations: Box<Annotations>,
 }
 
 impl ParseError {
@@ -25,6 +36,8 @@ impl ParseError {
             message: message.into(),
             span: span.into(),
             help: None,
+            code: ErrorCode::UnexpectedToken,
+            annotations: Box::default(),
         }
     }
 
@@ -33,6 +46,8 @@ impl ParseError {
             message: message.into(),
             span: span.into(),
             help: Some(help.into()),
+            code: ErrorCode::UnexpectedToken,
+            annotations: Box::default(),
         }
     }
 
@@ -45,6 +60,12 @@ impl ParseError {
             format!("unexpected end of input, expected {}", expected),
             0..0,
         )
+        .with_code(ErrorCode::UnexpectedEof)
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
     }
 }
 
@@ -56,21 +77,42 @@ impl PartialEq for ParseError {
     }
 }
 
+impl Diagnostic for ParseError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        footer(&self.help, &self.annotations)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        labels("here", Some(self.span), &self.annotations)
+    }
+}
+
+impl Annotate for ParseError {
+    fn annotations_mut(&mut self) -> &mut Annotations {
+        &mut self.annotations
+    }
+}
+
 /// Result type for parsing.
 pub type ParseResult<T> = Result<T, ParseError>;
 
 /// A type error with source location.
-#[derive(Error, Debug, Diagnostic, Clone)]
+#[derive(Error, Debug, Clone)]
 #[error("{message}")]
-#[diagnostic(code(dream::type_error))]
 pub struct TypeError {
     pub message: String,
 
-    #[label("type mismatch here")]
     pub span: Option<SourceSpan>,
 
-    #[help]
     pub help: Option<String>,
+
+    pub code: ErrorCode,
+
+    pub annotations: Box<Annotations>,
 }
 
 impl TypeError {
@@ -79,6 +121,8 @@ impl TypeError {
             message: message.into(),
             span: None,
             help: None,
+            code: ErrorCode::TypeMismatch,
+            annotations: Box::default(),
         }
     }
 
@@ -87,6 +131,8 @@ impl TypeError {
             message: message.into(),
             span: Some(span.into()),
             help: None,
+            code: ErrorCode::TypeMismatch,
+            annotations: Box::default(),
         }
     }
 
@@ -95,8 +141,35 @@ impl TypeError {
             message: message.into(),
             span: None,
             help: Some(help.into()),
+            code: ErrorCode::TypeMismatch,
+            annotations: Box::default(),
         }
     }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+}
+
+impl Diagnostic for TypeError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        footer(&self.help, &self.annotations)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        labels(self.code.explanation().title, self.span, &self.annotations)
+    }
+}
+
+impl Annotate for TypeError {
+    fn annotations_mut(&mut self) -> &mut Annotations {
+        &mut self.annotations
+    }
 }
 
 /// Result type for type checking.
@@ -111,6 +184,8 @@ pub struct Warning {
     pub module: Option<String>,
     /// Source span for the warning
     pub span: Option<Span>,
+    pub code: Option<ErrorCode>,
+    pub annotations: Box<Annotations>,
 }
 
 impl Warning {
@@ -120,6 +195,8 @@ impl Warning {
             help: None,
             module: None,
             span: None,
+            code: None,
+            annotations: Box::default(),
         }
     }
 
@@ -129,6 +206,8 @@ impl Warning {
             help: Some(help.into()),
             module: None,
             span: None,
+            code: None,
+            annotations: Box::default(),
         }
     }
 
@@ -138,6 +217,8 @@ impl Warning {
             help: None,
             module: None,
             span: Some(span),
+            code: None,
+            annotations: Box::default(),
         }
     }
 
@@ -147,6 +228,8 @@ impl Warning {
             help: Some(help.into()),
             module: None,
             span: Some(span),
+            code: None,
+            annotations: Box::default(),
         }
     }
 
@@ -154,23 +237,50 @@ impl Warning {
         self.module = Some(module.into());
         self
     }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = Some(code);
+        self
+    }
+
+    /// The error this warning is reported as when its category is denied.
+    pub fn into_error(self) -> TypeError {
+        let code = self.code.unwrap_or(ErrorCode::TypeMismatch);
+        let mut err = TypeError::new(self.message).with_code(code);
+        err.span = self.span.map(Into::into);
+        err.help = self.help;
+        err.annotations = self.annotations;
+        // Say why a warning stopped the build, unless it always does
+        if let Some(category) = WarningCategory::of(code) {
+            if category.default_level() != LintLevel::Deny {
+                err = err.with_note(format!("`{}` warnings are denied", category));
+            }
+        }
+        err
+    }
+}
+
+impl Annotate for Warning {
+    fn annotations_mut(&mut self) -> &mut Annotations {
+        &mut self.annotations
+    }
 }
 
 /// A compiler warning with source context for rich diagnostics.
-#[derive(Error, Debug, Diagnostic)]
+#[derive(Error, Debug)]
 #[error("{message}")]
-#[diagnostic(severity(warning), code(dream::warning))]
 pub struct CompilerWarning {
     pub message: String,
 
-    #[source_code]
     pub src: NamedSource<String>,
 
-    #[label("this value is unused")]
     pub span: Option<SourceSpan>,
 
-    #[help]
     pub help: Option<String>,
+
+    pub code: Option<ErrorCode>,
+
+    pub annotations: Box<Annotations>,
 }
 
 impl CompilerWarning {
@@ -186,27 +296,52 @@ impl CompilerWarning {
             src: NamedSource::new(filename, source.into()),
             span: warning.span.map(|s| s.into()),
             help: warning.help,
+            code: warning.code,
+            annotations: warning.annotations,
         }
     }
 }
 
+impl Diagnostic for CompilerWarning {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.code.map(|code| Box::new(code) as Box<dyn fmt::Display>)
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(miette::Severity::Warning)
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        footer(&self.help, &self.annotations)
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.src)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let text = self.code.map_or("here", |code| code.explanation().title);
+        labels(text, self.span, &self.annotations)
+    }
+}
+
 /// A compiler error that can include source code context.
-#[derive(Error, Debug, Diagnostic)]
+#[derive(Error, Debug)]
 #[error("{message}")]
-#[diagnostic(code(dream::compiler_error))]
 pub struct CompilerError {
     pub message: String,
 
-    #[source_code]
     pub src: NamedSource<String>,
 
-    #[label("{label}")]
     pub span: Option<SourceSpan>,
 
     pub label: String,
 
-    #[help]
     pub help: Option<String>,
+
+    pub code: ErrorCode,
+
+    pub annotations: Box<Annotations>,
 }
 
 impl CompilerError {
@@ -219,6 +354,8 @@ impl CompilerError {
             span: Some(err.span),
             label: "here".to_string(),
             help: err.help,
+            code: err.code,
+            annotations: err.annotations,
         }
     }
 
@@ -233,8 +370,56 @@ impl CompilerError {
             message: err.message.clone(),
             src: NamedSource::new(filename, source.into()),
             span: err.span,
-            label: "type mismatch here".to_string(),
+            label: err.code.explanation().title.to_string(),
             help: err.help,
+            code: err.code,
+            annotations: err.annotations,
         }
     }
 }
+
+impl Diagnostic for CompilerError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.code))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        footer(&self.help, &self.annotations)
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        Some(&self.src)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        labels(&self.label, self.span, &self.annotations)
+    }
+}
+
+/// The help, followed by the notes.
+fn footer<'a>(help: &Option<String>, annotations: &Annotations) -> Option<Box<dyn fmt::Display + 'a>> {
+    let footer = annotations.footer(help.as_deref())?;
+    Some(Box::new(footer))
+}
+
+/// The primary label, `text` on `span` if there is one, then the secondary
+/// labels and one label per suggestion, showing where it would edit.
+fn labels<'a>(
+    text: &str,
+    span: Option<SourceSpan>,
+    annotations: &Annotations,
+) -> Option<Box<dyn Iterator<Item = LabeledSpan> + 'a>> {
+    let primary = span.map(|span| LabeledSpan::new_primary_with_span(Some(text.to_string()), span));
+    let secondary = annotations
+        .labels
+        .iter()
+        .map(|label| LabeledSpan::new_with_span(Some(label.message.clone()), label.span.clone()));
+    let suggestions = annotations.suggestions.iter().map(|suggestion| {
+        LabeledSpan::new_with_span(Some(suggestion.message.clone()), suggestion.span.clone())
+    });
+    let labels: Vec<LabeledSpan> = primary.into_iter().chain(secondary).chain(suggestions).collect();
+    if labels.is_empty() {
+        return None;
+    }
+    Some(Box::new(labels.into_iter()))
+}
@@ -0,0 +1,3188 @@
+//! The Dream formatter, behind `dream fmt` and the language server's
+//! formatting request.
+//!
+//! [`format_source`] parses a file and prints the AST back out in one
+//! canonical style: four-space indents, lines broken to fit in 100
+//! columns, trailing commas wherever the grammar allows them. The AST has
+//! no room for comments, so they are woven back in afterwards: the tokens
+//! of the source are lined up with the tokens of the printed file, and each
+//! comment goes back next to the token it was next to before.
+
+use super::ast::*;
+use super::error::ParseError;
+use super::parser::{Parser, SourceFile, SourceItem, SyntaxHints};
+use super::token::{
+    LexStringPart, Token, has_interpolation, parse_interpolated_string, process_escapes,
+};
+use logos::Logos;
+use std::fmt;
+use std::ops::Range;
+
+/// Lines are kept within this many columns where the code allows.
+const WIDTH: usize = 100;
+const INDENT: usize = 4;
+
+// How tightly each kind of expression binds, loosest first. Binary
+// operators sit between `PIPE` and `UNARY`, at `BinOp::precedence() + 1`.
+const PIPE: u8 = 1;
+const COMPARISON: u8 = 4;
+const UNARY: u8 = 7;
+const POSTFIX: u8 = 8;
+const PRIMARY: u8 = 9;
+
+/// Why a file could not be formatted.
+#[derive(Debug)]
+pub enum FormatError {
+    /// The source does not parse.
+    Parse(ParseError),
+    /// The formatted file does not read back as what was printed. This is
+    /// a formatter bug; the source should be left as it is.
+    Unstable,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Parse(e) => write!(f, "{}", e),
+            FormatError::Unstable => {
+                write!(
+                    f,
+                    "formatting changed the meaning of the file; this is a bug in the formatter"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+/// Format a Dream source file, keeping its comments.
+pub fn format_source(source: &str) -> Result<String, FormatError> {
+    let file = Parser::new(source)
+        .parse_source_file()
+        .map_err(FormatError::Parse)?;
+    let printed = Printer::new(&file.hints).file(&file);
+    let formatted = weave_comments(source, &printed);
+
+    // Reading the result back must give what was printed, or the printer
+    // lost something the parser needed
+    let reparsed = Parser::new(&formatted)
+        .parse_source_file()
+        .map_err(|_| FormatError::Unstable)?;
+    if Printer::new(&reparsed.hints).file(&reparsed) != printed {
+        return Err(FormatError::Unstable);
+    }
+    Ok(formatted)
+}
+
+/// How `item` is declared, formatted the way [`format_source`] would,
+/// without its attributes: a function's signature without its body, a
+/// trait's method signatures without default bodies, and anything else in
+/// full.
+pub fn format_declaration(item: &Item) -> String {
+    let hints = SyntaxHints::default();
+    let mut printer = Printer::new(&hints);
+    match item {
+        Item::Function(f) => printer.signature(f, 0),
+        Item::Trait(t) => {
+            let methods = t
+                .methods
+                .iter()
+                .map(|m| TraitMethod { body: None, ..m.clone() })
+                .collect();
+            printer.trait_def(&TraitDef { methods, ..t.clone() }, false, 0)
+        }
+        Item::Struct(s) => printer.item(&Item::Struct(StructDef { attrs: Vec::new(), ..s.clone() }), &[], false, 0),
+        Item::Enum(e) => printer.item(&Item::Enum(EnumDef { attrs: Vec::new(), ..e.clone() }), &[], false, 0),
+        Item::TypeAlias(t) => printer.item(&Item::TypeAlias(TypeAlias { attrs: Vec::new(), ..t.clone() }), &[], false, 0),
+        _ => printer.item(item, &[], false, 0),
+    }
+}
+
+/// `t` as it would be written in a declaration.
+pub fn format_type(t: &Type) -> String {
+    ty(t)
+}
+
+/// How far through each list of [`SyntaxHints`] the printer has got.
+#[derive(Debug, Clone, Copy, Default)]
+struct Cursor {
+    if_let: usize,
+    pattern_rest: usize,
+    map_shorthand: usize,
+    with_try: usize,
+    clause_param: usize,
+    clause_return: usize,
+}
+
+/// Prints an AST in the canonical style, without comments.
+///
+/// Layout is decided by trying a construct on one line and breaking it
+/// if that doesn't fit. A failed attempt may already have used up hints,
+/// so every attempt saves the cursor and restores it on failure.
+struct Printer<'a> {
+    hints: &'a SyntaxHints,
+    cursor: Cursor,
+    /// Inside `{...}` in a string, whose hints the parser doesn't keep.
+    in_interpolation: bool,
+}
+
+/// An element of a comma-separated list.
+enum Elem<'a> {
+    Expr(&'a Expr),
+    /// A struct literal field: `name: value`, or `name` for a variable of
+    /// the same name
+    Field(&'a str, &'a Expr),
+    /// `..base` in a struct update
+    Base(&'a Expr),
+    /// A map entry: `key: value` or `key => value`
+    Entry(&'a Expr, &'a Expr),
+    Segment(&'a BitStringSegment<Box<Expr>>),
+    Pattern(&'a Pattern),
+    /// A struct pattern field: `name: pattern` or `name`
+    PatternField(&'a str, &'a Pattern),
+    /// The pattern of an `assert_match!`, with its guard
+    Guarded(&'a Pattern, Option<&'a Expr>),
+    Param(&'a Param),
+    /// The `..` ending a struct pattern
+    Rest,
+    Text(String),
+}
+
+/// The assertion macro an expression was desugared from.
+#[derive(Clone, Copy)]
+enum Assertion<'a> {
+    /// `assert!(cond)`
+    True(&'a Expr),
+    /// `assert_eq!(left, right)` or `assert_ne!(left, right)`
+    Compare(&'static str, &'a Expr, &'a Expr),
+    /// `assert_match!(value, pattern if guard)`
+    Match(&'a Expr, &'a Pattern, Option<&'a Expr>),
+}
+
+impl<'a> Printer<'a> {
+    fn new(hints: &'a SyntaxHints) -> Self {
+        Self {
+            hints,
+            cursor: Cursor::default(),
+            in_interpolation: false,
+        }
+    }
+
+    // === Hints ===
+
+    /// Whether the `Expr::Match` with these arms was written as `if let`,
+    /// without using up its hint.
+    fn peek_if_let(&self, arms: &[MatchArm]) -> bool {
+        if_let_shape(arms)
+            && (self.in_interpolation || self.hints.if_let.get(self.cursor.if_let) == Some(&true))
+    }
+
+    fn take_if_let(&mut self, arms: &[MatchArm]) -> bool {
+        let if_let = self.peek_if_let(arms);
+        if !self.in_interpolation {
+            self.cursor.if_let += 1;
+        }
+        if_let
+    }
+
+    fn take_pattern_rest(&mut self) -> bool {
+        if self.in_interpolation {
+            return false;
+        }
+        self.cursor.pattern_rest += 1;
+        self.hints.pattern_rest.get(self.cursor.pattern_rest - 1) == Some(&true)
+    }
+
+    /// Whether the map entry with this key was written `key: value`.
+    fn take_map_shorthand(&mut self, key: &Expr) -> bool {
+        if self.in_interpolation {
+            return matches!(key, Expr::Atom(atom) if is_plain_ident(atom));
+        }
+        self.cursor.map_shorthand += 1;
+        self.hints.map_shorthand.get(self.cursor.map_shorthand - 1) == Some(&true)
+            && matches!(key, Expr::Atom(_) | Expr::String(_))
+    }
+
+    /// Whether the `with` step matching `pattern` was written
+    /// `inner <- value?`.
+    fn take_with_try(&mut self, pattern: &Pattern) -> bool {
+        if self.in_interpolation {
+            return false;
+        }
+        self.cursor.with_try += 1;
+        try_step_shape(pattern)
+            && self.hints.with_try.get(self.cursor.with_try - 1) == Some(&true)
+    }
+
+    /// Whether the next parameter of a `fn` was written without its type,
+    /// taking it from the function's first clause.
+    fn take_clause_param(&mut self) -> bool {
+        self.cursor.clause_param += 1;
+        self.hints.clause_param.get(self.cursor.clause_param - 1) == Some(&true)
+    }
+
+    /// Whether the next `fn` was written without its return type, taking
+    /// it from the function's first clause.
+    fn take_clause_return(&mut self) -> bool {
+        self.cursor.clause_return += 1;
+        self.hints.clause_return.get(self.cursor.clause_return - 1) == Some(&true)
+    }
+
+    // === Items ===
+
+    fn file(&mut self, file: &SourceFile) -> String {
+        let mut parts = Vec::new();
+        if !file.items.is_empty() {
+            parts.push(self.items(&file.items, 0));
+        }
+        for (module, items) in &file.modules {
+            parts.push(if items.is_empty() {
+                format!("mod {} {{}}", module)
+            } else {
+                format!("mod {} {{\n{}\n}}", module, self.items(items, INDENT))
+            });
+        }
+        if let Some(script) = &file.script {
+            let stmts = self.stmts(script, 0);
+            if !stmts.is_empty() {
+                parts.push(stmts);
+            }
+        }
+        let mut out = parts.join("\n\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+
+    fn items(&mut self, items: &[SourceItem], indent: usize) -> String {
+        let mut out = String::new();
+        for (i, source) in items.iter().enumerate() {
+            if i > 0 {
+                // Runs of `use` and `mod` declarations stay together
+                let tight = matches!(
+                    (&items[i - 1].item, &source.item),
+                    (Item::Use(_), Item::Use(_)) | (Item::ModDecl(_), Item::ModDecl(_))
+                );
+                out.push_str(if tight { "\n" } else { "\n\n" });
+            }
+            out.push_str(&pad(indent));
+            out.push_str(&self.item(&source.item, &source.attrs, source.is_pub, indent));
+        }
+        out
+    }
+
+    /// An item, starting at column `indent`. `attrs` and `is_pub` are what
+    /// the item itself doesn't keep.
+    fn item(&mut self, item: &Item, attrs: &[Attribute], is_pub: bool, indent: usize) -> String {
+        let own_attrs: &[Attribute] = match item {
+            Item::Function(f) => &f.attrs,
+            Item::Struct(s) => &s.attrs,
+            Item::Enum(e) => &e.attrs,
+            Item::TypeAlias(t) => &t.attrs,
+            Item::ExternMod(m) => &m.attrs,
+            Item::ExternBlock(b) => &b.attrs,
+            Item::CompileError(e) => &e.attrs,
+            _ => &[],
+        };
+        let mut out = String::new();
+        for attr in attrs.iter().chain(own_attrs) {
+            out.push_str(&attribute(attr));
+            out.push('\n');
+            out.push_str(&pad(indent));
+        }
+        let body = match item {
+            Item::Function(f) => self.function(f, indent),
+            Item::Struct(s) => {
+                let head = format!(
+                    "{}struct {}{}",
+                    vis(s.is_pub),
+                    name(&s.name),
+                    type_params(&s.type_params)
+                );
+                if s.is_tuple() {
+                    let types: Vec<Type> = s.fields.iter().map(|(_, t)| t.clone()).collect();
+                    format!("{}({});", head, type_list(&types))
+                } else {
+                    let fields: Vec<String> = s
+                        .fields
+                        .iter()
+                        .map(|(field, t)| format!("{}: {},", name(field), ty(t)))
+                        .collect();
+                    members(head, &fields, indent)
+                }
+            }
+            Item::Enum(e) => {
+                let head = format!(
+                    "{}enum {}{}",
+                    vis(e.is_pub),
+                    name(&e.name),
+                    type_params(&e.type_params)
+                );
+                let variants: Vec<String> = e
+                    .variants
+                    .iter()
+                    .map(|v| format!("{},", variant(v)))
+                    .collect();
+                members(head, &variants, indent)
+            }
+            Item::TypeAlias(t) => format!(
+                "{}type {}{} = {};",
+                vis(t.is_pub),
+                name(&t.name),
+                type_params(&t.type_params),
+                ty(&t.ty)
+            ),
+            Item::ModDecl(m) => format!("{}mod {};", vis(m.is_pub), m.name),
+            Item::Use(u) => self.use_decl(&u.tree, indent),
+            Item::Impl(block) => {
+                let methods: Vec<String> = block
+                    .methods
+                    .iter()
+                    .map(|m| self.method(m, indent + INDENT))
+                    .collect();
+                let body = methods.join(&format!("\n\n{}", pad(indent + INDENT)));
+                members(format!("impl {}", name(&block.type_name)), &[body], indent)
+            }
+            Item::Trait(t) => self.trait_def(t, is_pub, indent),
+            Item::TraitImpl(t) => {
+                let head = format!(
+                    "impl {}{} for {}",
+                    name(&t.trait_name),
+                    type_args(&t.trait_type_args),
+                    name(&t.type_name)
+                );
+                let mut body = Vec::new();
+                if !t.type_bindings.is_empty() {
+                    body.push(type_bindings(&t.type_bindings, indent + INDENT));
+                }
+                body.extend(t.methods.iter().map(|m| self.method(m, indent + INDENT)));
+                members(
+                    head,
+                    &[body.join(&format!("\n\n{}", pad(indent + INDENT)))],
+                    indent,
+                )
+            }
+            Item::TraitDecl(t) if t.type_bindings.is_empty() => {
+                format!("impl {};", name(&t.trait_name))
+            }
+            Item::TraitDecl(t) => members(
+                format!("impl {}", name(&t.trait_name)),
+                &[type_bindings(&t.type_bindings, indent + INDENT)],
+                indent,
+            ),
+            Item::ExternMod(m) => format!("extern {}", extern_mod(m, indent)),
+            Item::ExternBlock(b) => extern_block(b, indent),
+            Item::CompileError(e) => format!("compile_error!(\"{}\");", escape_string(&e.message)),
+        };
+        out.push_str(&body);
+        out
+    }
+
+    /// A method in an impl block, with its attributes.
+    fn method(&mut self, f: &Function, indent: usize) -> String {
+        let mut out = String::new();
+        for attr in &f.attrs {
+            out.push_str(&attribute(attr));
+            out.push('\n');
+            out.push_str(&pad(indent));
+        }
+        out.push_str(&self.function(f, indent));
+        out
+    }
+
+    fn function(&mut self, f: &Function, indent: usize) -> String {
+        let signature = self.signature(f, indent);
+        self.block_after(signature, &f.body, indent)
+    }
+
+    /// Everything of a function up to its body.
+    fn signature(&mut self, f: &Function, indent: usize) -> String {
+        let head = format!(
+            "{}fn {}{}",
+            vis(f.is_pub),
+            name(&f.name),
+            type_params(&f.type_params)
+        );
+        let params: Vec<Elem> = f
+            .params
+            .iter()
+            .map(|param| {
+                if self.take_clause_param() {
+                    Elem::Pattern(&param.pattern)
+                } else {
+                    Elem::Param(param)
+                }
+            })
+            .collect();
+        let col = indent + width(&head);
+        let mut signature = head + &self.list("(", &params, ")", false, false, indent, col);
+        if let Some(guard) = &f.guard {
+            signature.push_str(" when ");
+            let col = end_col(indent, &signature);
+            signature.push_str(&self.cond(guard, indent, col));
+        }
+        let inherited_return = self.take_clause_return();
+        if let Some(ret) = f.return_type.as_ref().filter(|_| !inherited_return) {
+            signature.push_str(" -> ");
+            signature.push_str(&ty(ret));
+        }
+        signature
+    }
+
+    fn trait_def(&mut self, t: &TraitDef, is_pub: bool, indent: usize) -> String {
+        let head = format!(
+            "{}trait {}{}",
+            vis(is_pub),
+            name(&t.name),
+            type_params(&t.type_params)
+        );
+        let inner = indent + INDENT;
+        let mut body = String::new();
+        if !t.associated_types.is_empty() {
+            let types: Vec<String> = t
+                .associated_types
+                .iter()
+                .map(|a| format!("type {};", a))
+                .collect();
+            body.push_str(&types.join(&format!("\n{}", pad(inner))));
+        }
+        let mut previous_had_body = true;
+        for method in &t.methods {
+            if !body.is_empty() {
+                // Bare signatures may sit together; anything with a body
+                // gets a blank line around it
+                let blank = previous_had_body || method.body.is_some();
+                body.push_str(if blank { "\n\n" } else { "\n" });
+                body.push_str(&pad(inner));
+            }
+            let head = format!(
+                "fn {}{}",
+                name(&method.name),
+                type_params(&method.type_params)
+            );
+            let params: Vec<Elem> = method.params.iter().map(Elem::Param).collect();
+            let col = inner + width(&head);
+            let mut signature = head + &self.list("(", &params, ")", false, false, inner, col);
+            if let Some(ret) = &method.return_type {
+                signature.push_str(" -> ");
+                signature.push_str(&ty(ret));
+            }
+            match &method.body {
+                Some(block) => body.push_str(&self.block_after(signature, block, inner)),
+                None => {
+                    body.push_str(&signature);
+                    body.push(';');
+                }
+            }
+            previous_had_body = method.body.is_some();
+        }
+        members(head, &[body], indent)
+    }
+
+    fn use_decl(&mut self, tree: &UseTree, indent: usize) -> String {
+        match tree {
+            UseTree::Path {
+                module,
+                name,
+                rename,
+            } => {
+                format!(
+                    "use {}::{};",
+                    module_path(module),
+                    renamed(name, rename.as_deref())
+                )
+            }
+            UseTree::Glob { module } => format!("use {}::*;", module_path(module)),
+            UseTree::Group { module, items } => {
+                let head = format!("use {}::", module_path(module));
+                let items: Vec<Elem> = items
+                    .iter()
+                    .map(|item| Elem::Text(renamed(&item.name, item.rename.as_deref())))
+                    .collect();
+                let col = indent + width(&head);
+                format!(
+                    "{}{};",
+                    head,
+                    self.list("{", &items, "}", false, false, indent, col)
+                )
+            }
+        }
+    }
+
+    // === Statements ===
+
+    /// A block's statements and final expression, each line indented to
+    /// `indent`.
+    fn stmts(&mut self, block: &Block, indent: usize) -> String {
+        // Each statement, and whether it is a block-like expression that
+        // may go without a semicolon
+        let mut stmts = Vec::new();
+        for stmt in &block.stmts {
+            stmts.push(match stmt {
+                Stmt::Let {
+                    pattern,
+                    ty,
+                    value,
+                    else_block,
+                    ..
+                } => (
+                    self.let_stmt(pattern, ty.as_ref(), value, else_block.as_ref(), indent),
+                    false,
+                ),
+                Stmt::Expr { expr, .. } if is_block_like(expr) => {
+                    (self.broken(expr, indent, indent), true)
+                }
+                Stmt::Expr { expr, .. } => (format!("{};", self.expr(expr, indent, indent)), false),
+                Stmt::Cfg { attrs, stmt, tail } => (self.cfg_stmt(attrs, stmt, *tail, indent), false),
+            });
+        }
+        // Control flow at the end of a block is laid out like a statement
+        let tail = block.expr.as_deref().map(|e| {
+            if is_block_like(e) {
+                self.broken(e, indent, indent)
+            } else {
+                self.expr(e, indent, indent)
+            }
+        });
+
+        let mut lines = Vec::new();
+        for (i, (stmt, block_like)) in stmts.iter().enumerate() {
+            let mut stmt = stmt.clone();
+            if *block_like {
+                // Without a semicolon, a block-like statement at the end
+                // would be the block's value, and one followed by `(`, `-`
+                // or `||` would be called, subtracted from or or-ed
+                let next = stmts
+                    .get(i + 1)
+                    .map(|(s, _)| s.as_str())
+                    .or(tail.as_deref());
+                if next.is_none_or(|next| next.starts_with(['(', '-']) || next.starts_with("||")) {
+                    stmt.push(';');
+                }
+            }
+            lines.push(format!("{}{}", pad(indent), stmt));
+        }
+        if let Some(tail) = tail {
+            lines.push(format!("{}{}", pad(indent), tail));
+        }
+        lines.join("\n")
+    }
+
+    /// A statement under `#[cfg]`, its attributes on lines of their own.
+    /// Only a possible tail goes without a `;`: anything else would read
+    /// back as one.
+    fn cfg_stmt(&mut self, attrs: &[Attribute], stmt: &Stmt, tail: bool, indent: usize) -> String {
+        let mut out = String::new();
+        for attr in attrs {
+            out.push_str(&attribute(attr));
+            out.push('\n');
+            out.push_str(&pad(indent));
+        }
+        match stmt {
+            Stmt::Let {
+                pattern,
+                ty,
+                value,
+                else_block,
+                ..
+            } => out.push_str(&self.let_stmt(pattern, ty.as_ref(), value, else_block.as_ref(), indent)),
+            Stmt::Expr { expr, .. } => {
+                if is_block_like(expr) {
+                    out.push_str(&self.broken(expr, indent, indent));
+                } else {
+                    out.push_str(&self.expr(expr, indent, indent));
+                }
+                if !tail {
+                    out.push(';');
+                }
+            }
+            Stmt::Cfg { attrs, stmt, tail } => out.push_str(&self.cfg_stmt(attrs, stmt, *tail, indent)),
+        }
+        out
+    }
+
+    fn let_stmt(
+        &mut self,
+        pattern: &Pattern,
+        annotation: Option<&Type>,
+        value: &Expr,
+        else_block: Option<&Block>,
+        indent: usize,
+    ) -> String {
+        let mut out = format!("let {}", self.pattern(pattern, indent, indent + 4));
+        if let Some(t) = annotation {
+            out.push_str(": ");
+            out.push_str(&ty(t));
+        }
+        out.push_str(" = ");
+        let col = end_col(indent, &out);
+        // An `if` without an `else` would take the `let`'s `else` as its own
+        if else_block.is_some()
+            && matches!(
+                value,
+                Expr::If {
+                    else_block: None,
+                    ..
+                }
+            )
+        {
+            out.push_str(&format!("({})", self.expr(value, indent, col + 1)));
+        } else {
+            out.push_str(&self.expr(value, indent, col));
+        }
+        if let Some(block) = else_block {
+            out = self.block_after(out + " else", block, indent);
+        }
+        out.push(';');
+        out
+    }
+
+    /// `head {`, the block's contents, then `}` at `indent`.
+    fn block_after(&mut self, head: String, block: &Block, indent: usize) -> String {
+        let mut out = open_brace(head, indent);
+        if block.stmts.is_empty() && block.expr.is_none() {
+            out.push('}');
+            return out;
+        }
+        out.push('\n');
+        out.push_str(&self.stmts(block, indent + INDENT));
+        out.push('\n');
+        out.push_str(&pad(indent));
+        out.push('}');
+        out
+    }
+
+    /// `{ value }` for a block that is just a value, if it fits in `col`.
+    fn short_block(&mut self, block: &Block, col: usize) -> Option<String> {
+        let saved = self.cursor;
+        let short = self.flat_block(block).filter(|s| col + width(s) < WIDTH);
+        if short.is_none() {
+            self.cursor = saved;
+        }
+        short
+    }
+
+    fn flat_block(&mut self, block: &Block) -> Option<String> {
+        if !block.stmts.is_empty() {
+            return None;
+        }
+        match block.expr.as_deref() {
+            None => Some("{}".to_string()),
+            Some(e) => Some(format!("{{ {} }}", self.flat(e)?)),
+        }
+    }
+
+    // === Expressions ===
+
+    /// An expression starting at column `col`, with its continuation lines
+    /// indented from `indent`.
+    fn expr(&mut self, e: &Expr, indent: usize, col: usize) -> String {
+        let saved = self.cursor;
+        if let Some(flat) = self.flat(e) {
+            if col + width(&flat) < WIDTH {
+                return flat;
+            }
+        }
+        self.cursor = saved;
+        self.broken(e, indent, col)
+    }
+
+    /// An expression in the head of an `if`, `match` or `for`, where a
+    /// trailing type name would take the `{` as the start of a struct.
+    fn cond(&mut self, e: &Expr, indent: usize, col: usize) -> String {
+        if ends_in_type_name(e) {
+            format!("({})", self.expr(e, indent, col + 1))
+        } else {
+            self.expr(e, indent, col)
+        }
+    }
+
+    fn flat_cond(&mut self, e: &Expr) -> Option<String> {
+        let flat = self.flat(e)?;
+        Some(if ends_in_type_name(e) {
+            format!("({})", flat)
+        } else {
+            flat
+        })
+    }
+
+    /// `e` as an operand that needs at least `precedence`, in parentheses
+    /// if it binds more loosely.
+    fn operand(&mut self, e: &Expr, precedence: u8, indent: usize, col: usize) -> String {
+        if expr_precedence(e) < precedence {
+            format!("({})", self.expr(e, indent, col + 1))
+        } else {
+            self.expr(e, indent, col)
+        }
+    }
+
+    fn flat_operand(&mut self, e: &Expr, precedence: u8) -> Option<String> {
+        let flat = self.flat(e)?;
+        Some(if expr_precedence(e) < precedence {
+            format!("({})", flat)
+        } else {
+            flat
+        })
+    }
+
+    fn callee(&mut self, func: &Expr, indent: usize, col: usize) -> String {
+        if needs_parens_as_callee(func) {
+            format!("({})", self.expr(func, indent, col + 1))
+        } else {
+            self.expr(func, indent, col)
+        }
+    }
+
+    fn flat_callee(&mut self, func: &Expr) -> Option<String> {
+        let flat = self.flat(func)?;
+        Some(if needs_parens_as_callee(func) {
+            format!("({})", flat)
+        } else {
+            flat
+        })
+    }
+
+    /// An expression on one line, or `None` if it can't be.
+    fn flat(&mut self, e: &Expr) -> Option<String> {
+        Some(match e {
+            Expr::Int(n) => n.to_string(),
+            Expr::String(s) => format!("\"{}\"", escape_string(s)),
+            Expr::Charlist(s) => charlist_literal(s),
+            Expr::StringInterpolation(parts) => self.interpolation(parts),
+            Expr::Format { parts, .. } => self.flat_list("format!(", &format_items(parts), ")", false)?,
+            Expr::Atom(a) => atom(a),
+            Expr::Bool(b) => b.to_string(),
+            Expr::Ident(n) => n.clone(),
+            Expr::Unit => "()".to_string(),
+            Expr::Path { segments } => segments.join("::"),
+            Expr::CompileError { message, .. } => {
+                format!("compile_error!(\"{}\")", escape_string(message))
+            }
+            Expr::Binary { op, left, right, .. } => {
+                let precedence = expr_precedence(e);
+                let left_precedence = if precedence == COMPARISON {
+                    precedence + 1
+                } else {
+                    precedence
+                };
+                format!(
+                    "{} {} {}",
+                    self.flat_operand(left, left_precedence)?,
+                    op,
+                    self.flat_operand(right, precedence + 1)?
+                )
+            }
+            Expr::Pipe { left, right } => {
+                format!(
+                    "{} |> {}",
+                    self.flat_operand(left, PIPE)?,
+                    self.flat_operand(right, PIPE + 1)?
+                )
+            }
+            Expr::Send { to, msg } => {
+                format!(
+                    "{} ! {}",
+                    self.flat_operand(to, POSTFIX)?,
+                    self.flat_operand(msg, POSTFIX)?
+                )
+            }
+            Expr::Unary { op, expr } => format!("{}{}", op, self.flat_operand(expr, UNARY)?),
+            Expr::Call {
+                func,
+                type_args,
+                args,
+                ..
+            } => {
+                if let Some(body) = gen_block(func, args) {
+                    return Some(format!("gen {}", self.flat_block(body)?));
+                }
+                if let Some(value) = yielded(func, args) {
+                    return Some(format!("yield {}", self.flat(value)?));
+                }
+                let callee = self.flat_callee(func)?;
+                format!(
+                    "{}{}({})",
+                    callee,
+                    turbofish(type_args),
+                    self.flat_exprs(args)?
+                )
+            }
+            Expr::MethodCall {
+                receiver,
+                method,
+                type_args,
+                args,
+                ..
+            } => format!(
+                "{}.{}{}({})",
+                self.flat_operand(receiver, POSTFIX)?,
+                method,
+                turbofish(type_args),
+                self.flat_exprs(args)?
+            ),
+            Expr::FieldAccess { expr, field } => {
+                format!("{}.{}", self.flat_operand(expr, POSTFIX)?, field)
+            }
+            Expr::UnquoteFieldAccess { expr, field_expr } => {
+                format!(
+                    "{}.#{}",
+                    self.flat_operand(expr, POSTFIX)?,
+                    self.flat_operand(field_expr, PRIMARY)?
+                )
+            }
+            Expr::Try { expr } => format!("{}?", self.flat_operand(expr, POSTFIX)?),
+            Expr::Spawn(inner) => {
+                let inner_flat = self.flat(inner)?;
+                if needs_parens_after_spawn(inner) {
+                    format!("spawn ({})", inner_flat)
+                } else {
+                    format!("spawn {}", inner_flat)
+                }
+            }
+            Expr::SpawnClosure(body) => format!("spawn || {}", self.flat_block(body)?),
+            Expr::Closure { params, body } => {
+                format!("{} {}", closure_params(params), self.flat_block(body)?)
+            }
+            Expr::Return(None) => "return".to_string(),
+            Expr::Return(Some(value)) => format!("return {}", self.flat(value)?),
+            Expr::Tuple(items) if items.len() == 1 => format!("({},)", self.flat(&items[0])?),
+            Expr::Tuple(items) => format!("({})", self.flat_exprs(items)?),
+            Expr::List(items) => format!("[{}]", self.flat_exprs(items)?),
+            Expr::ListCons { head, tail } => {
+                format!("[{} | {}]", self.flat(head)?, self.flat(tail)?)
+            }
+            Expr::If {
+                cond,
+                then_block,
+                else_block,
+            } => match assertion(e) {
+                Some((assertion, message)) => self.flat_assertion(&assertion, message)?,
+                None => {
+                    let mut out = format!(
+                        "if {} {}",
+                        self.flat_cond(cond)?,
+                        self.flat_block(then_block)?
+                    );
+                    if let Some(block) = else_block {
+                        out.push_str(" else ");
+                        match self.else_if(block) {
+                            Some(else_if) => out.push_str(&self.flat(else_if)?),
+                            None => out.push_str(&self.flat_block(block)?),
+                        }
+                    }
+                    out
+                }
+            },
+            Expr::Match { expr, arms } => {
+                if let Some((assertion, message)) = assertion(e) {
+                    return self.flat_assertion(&assertion, message);
+                }
+                if with_step(arms).is_some() || !self.take_if_let(arms) {
+                    return None;
+                }
+                let Expr::Block(then_block) = &arms[0].body else {
+                    return None;
+                };
+                let pattern = self.flat_pattern(&arms[0].pattern);
+                let mut out = format!(
+                    "if let {} = {} {}",
+                    pattern,
+                    self.flat_cond(expr)?,
+                    self.flat_block(then_block)?
+                );
+                match &arms[1].body {
+                    Expr::Tuple(items) if items.is_empty() => {}
+                    Expr::Block(block) => {
+                        out.push_str(" else ");
+                        out.push_str(&self.flat_block(block)?);
+                    }
+                    else_if => {
+                        out.push_str(" else ");
+                        out.push_str(&self.flat(else_if)?);
+                    }
+                }
+                out
+            }
+            Expr::StructInit {
+                name: n,
+                fields,
+                base,
+            } => {
+                let items = struct_fields(fields, base.as_deref());
+                self.flat_list(&format!("{} {{", name(n)), &items, "}", true)?
+            }
+            Expr::EnumVariant {
+                type_name,
+                variant,
+                args,
+            } => {
+                let head = match type_name {
+                    Some(t) => format!("{}::{}", name(t), name(variant)),
+                    None => name(variant),
+                };
+                match args {
+                    EnumVariantArgs::Unit => head,
+                    EnumVariantArgs::Tuple(items) => {
+                        format!("{}({})", head, self.flat_exprs(items)?)
+                    }
+                    EnumVariantArgs::Struct(fields) => {
+                        let items = struct_fields(fields, None);
+                        self.flat_list(&format!("{} {{", head), &items, "}", true)?
+                    }
+                }
+            }
+            Expr::MapLiteral(entries) => {
+                let items: Vec<Elem> = entries.iter().map(|(k, v)| Elem::Entry(k, v)).collect();
+                self.flat_list("{", &items, "}", true)?
+            }
+            Expr::BitString(segments) => {
+                let items: Vec<Elem> = segments.iter().map(Elem::Segment).collect();
+                self.flat_list("<<", &items, ">>", false)?
+            }
+            Expr::ExternCall {
+                module,
+                function,
+                args,
+            } => {
+                format!("{}::{}({})", atom(module), function, self.flat_exprs(args)?)
+            }
+            Expr::Quote(inner) => match &**inner {
+                Expr::Block(block) => format!("quote {}", self.flat_block(block)?),
+                other => format!("quote {{ {} }}", self.flat(other)?),
+            },
+            Expr::Unquote(inner) => format!("#{}", self.flat_operand(inner, PRIMARY)?),
+            Expr::UnquoteSplice(inner) => format!("#..{}", self.flat_operand(inner, PRIMARY)?),
+            Expr::UnquoteAtom(inner) => format!(":#{}", self.flat_operand(inner, PRIMARY)?),
+            Expr::QuoteRepetition { pattern, separator } => {
+                format!(
+                    "#({}){}*",
+                    self.flat(pattern)?,
+                    separator.as_deref().unwrap_or("")
+                )
+            }
+            Expr::For {
+                clauses,
+                body,
+                is_comprehension: true,
+            } => {
+                let clauses = clauses
+                    .iter()
+                    .map(|clause| self.flat_clause(clause))
+                    .collect::<Option<Vec<_>>>()?;
+                format!("for {} {{ {} }}", clauses.join(", "), self.flat(body)?)
+            }
+            Expr::Block(_) | Expr::Receive { .. } | Expr::For { .. } | Expr::QuoteItem(_) => {
+                return None;
+            }
+        })
+    }
+
+    fn flat_exprs(&mut self, items: &[Expr]) -> Option<String> {
+        let items = items
+            .iter()
+            .map(|e| self.flat(e))
+            .collect::<Option<Vec<_>>>()?;
+        Some(items.join(", "))
+    }
+
+    /// An expression over several lines, for when it doesn't fit on one.
+    fn broken(&mut self, e: &Expr, indent: usize, col: usize) -> String {
+        match e {
+            Expr::Binary { .. } | Expr::Pipe { .. } => self.operator_chain(e, indent, col),
+            Expr::Unary { op, expr } => {
+                format!("{}{}", op, self.operand(expr, UNARY, indent, col + 1))
+            }
+            Expr::Call {
+                func,
+                type_args,
+                args,
+                ..
+            } => {
+                if let Some(body) = gen_block(func, args) {
+                    return self.block_after("gen".to_string(), body, indent);
+                }
+                if let Some(value) = yielded(func, args) {
+                    return format!("yield {}", self.expr(value, indent, col + 6));
+                }
+                let callee = self.callee(func, indent, col) + &turbofish(type_args);
+                let col = end_col(col, &callee);
+                let args: Vec<Elem> = args.iter().map(Elem::Expr).collect();
+                callee + &self.list("(", &args, ")", false, false, indent, col)
+            }
+            Expr::Format { parts, .. } => {
+                self.list("format!(", &format_items(parts), ")", false, true, indent, col)
+            }
+            Expr::MethodCall { .. }
+            | Expr::FieldAccess { .. }
+            | Expr::UnquoteFieldAccess { .. }
+            | Expr::Try { .. } => self.postfix_chain(e, indent, col),
+            Expr::Spawn(inner) if needs_parens_after_spawn(inner) => {
+                format!("spawn ({})", self.expr(inner, indent, col + 7))
+            }
+            Expr::Spawn(inner) => format!("spawn {}", self.expr(inner, indent, col + 6)),
+            Expr::SpawnClosure(body) => self.block_after("spawn ||".to_string(), body, indent),
+            Expr::Closure { params, body } => {
+                self.block_after(closure_params(params), body, indent)
+            }
+            Expr::Return(Some(value)) => format!("return {}", self.expr(value, indent, col + 7)),
+            Expr::Tuple(items) if items.len() == 1 => {
+                format!("({},)", self.expr(&items[0], indent, col + 1))
+            }
+            Expr::Tuple(items) => {
+                let items: Vec<Elem> = items.iter().map(Elem::Expr).collect();
+                self.list("(", &items, ")", false, true, indent, col)
+            }
+            Expr::List(items) => {
+                let items: Vec<Elem> = items.iter().map(Elem::Expr).collect();
+                self.list("[", &items, "]", false, true, indent, col)
+            }
+            Expr::ListCons { head, tail } => {
+                let head = self.expr(head, indent, col + 1);
+                let tail_col = end_col(col + 1, &head) + 3;
+                format!("[{} | {}]", head, self.expr(tail, indent, tail_col))
+            }
+            Expr::If {
+                cond,
+                then_block,
+                else_block,
+            } => match assertion(e) {
+                Some((assertion, message)) => self.assertion_call(&assertion, message, indent, col),
+                None => self.if_expr(cond, then_block, else_block.as_ref(), indent, col),
+            },
+            Expr::Match { expr, arms } => {
+                if let Some((assertion, message)) = assertion(e) {
+                    return self.assertion_call(&assertion, message, indent, col);
+                }
+                if with_step(arms).is_some() {
+                    self.with_block(e, indent)
+                } else if self.take_if_let(arms) {
+                    self.if_let(expr, arms, indent, col)
+                } else {
+                    self.match_expr(expr, arms, indent, col)
+                }
+            }
+            Expr::Block(block) => self.block_after(String::new(), block, indent),
+            Expr::Receive { arms, timeout } => self.receive(arms, timeout.as_ref(), indent),
+            Expr::For {
+                clauses,
+                body,
+                is_comprehension,
+            } => self.for_expr(clauses, body, *is_comprehension, indent, col),
+            Expr::StructInit {
+                name: n,
+                fields,
+                base,
+            } => {
+                let items = struct_fields(fields, base.as_deref());
+                self.list(
+                    &format!("{} {{", name(n)),
+                    &items,
+                    "}",
+                    true,
+                    base.is_none(),
+                    indent,
+                    col,
+                )
+            }
+            Expr::EnumVariant {
+                type_name,
+                variant,
+                args,
+            } => {
+                let head = match type_name {
+                    Some(t) => format!("{}::{}", name(t), name(variant)),
+                    None => name(variant),
+                };
+                match args {
+                    EnumVariantArgs::Unit => head,
+                    EnumVariantArgs::Tuple(items) => {
+                        let items: Vec<Elem> = items.iter().map(Elem::Expr).collect();
+                        self.list(
+                            &format!("{}(", head),
+                            &items,
+                            ")",
+                            false,
+                            false,
+                            indent,
+                            col,
+                        )
+                    }
+                    EnumVariantArgs::Struct(fields) => {
+                        let items = struct_fields(fields, None);
+                        self.list(
+                            &format!("{} {{", head),
+                            &items,
+                            "}",
+                            true,
+                            false,
+                            indent,
+                            col,
+                        )
+                    }
+                }
+            }
+            Expr::MapLiteral(entries) => {
+                let items: Vec<Elem> = entries.iter().map(|(k, v)| Elem::Entry(k, v)).collect();
+                self.list("{", &items, "}", true, true, indent, col)
+            }
+            Expr::BitString(segments) => {
+                let items: Vec<Elem> = segments.iter().map(Elem::Segment).collect();
+                self.list("<<", &items, ">>", false, false, indent, col)
+            }
+            Expr::ExternCall {
+                module,
+                function,
+                args,
+            } => {
+                let items: Vec<Elem> = args.iter().map(Elem::Expr).collect();
+                self.list(
+                    &format!("{}::{}(", atom(module), function),
+                    &items,
+                    ")",
+                    false,
+                    false,
+                    indent,
+                    col,
+                )
+            }
+            Expr::Quote(inner) => match &**inner {
+                Expr::Block(block) => self.block_after("quote".to_string(), block, indent),
+                other => format!("quote {{ {} }}", self.expr(other, indent, col + 8)),
+            },
+            Expr::QuoteItem(item) => {
+                let inner = indent + INDENT;
+                format!(
+                    "quote {{\n{}{}\n{}}}",
+                    pad(inner),
+                    self.item(item, &[], false, inner),
+                    pad(indent)
+                )
+            }
+            Expr::QuoteRepetition { pattern, separator } => {
+                format!(
+                    "#({}){}*",
+                    self.expr(pattern, indent, col + 2),
+                    separator.as_deref().unwrap_or("")
+                )
+            }
+            _ => self.flat(e).unwrap_or_default(),
+        }
+    }
+
+    /// A run of binary operators of one precedence, or of pipes, with each
+    /// operator starting a line.
+    fn operator_chain(&mut self, e: &Expr, indent: usize, col: usize) -> String {
+        let precedence = expr_precedence(e);
+        let mut rest = Vec::new();
+        let mut first = e;
+        loop {
+            match first {
+                Expr::Binary { op, left, right, .. } if expr_precedence(first) == precedence => {
+                    rest.push((op.to_string(), &**right));
+                    first = left;
+                    // Comparisons don't chain
+                    if precedence == COMPARISON {
+                        break;
+                    }
+                }
+                Expr::Pipe { left, right } if precedence == PIPE => {
+                    rest.push(("|>".to_string(), &**right));
+                    first = left;
+                }
+                _ => break,
+            }
+        }
+        rest.reverse();
+
+        let first_precedence = if precedence == COMPARISON {
+            precedence + 1
+        } else {
+            precedence
+        };
+        let inner = indent + INDENT;
+        let mut out = self.operand(first, first_precedence, indent, col);
+        for (op, operand) in rest {
+            out.push('\n');
+            out.push_str(&pad(inner));
+            out.push_str(&op);
+            out.push(' ');
+            let col = inner + op.len() + 1;
+            out.push_str(&self.operand(operand, precedence + 1, inner, col));
+        }
+        out
+    }
+
+    /// Method calls, field accesses and `?`s. Two or more method calls put
+    /// each call on its own line.
+    fn postfix_chain(&mut self, e: &Expr, indent: usize, col: usize) -> String {
+        let mut links = Vec::new();
+        let mut root = e;
+        loop {
+            match root {
+                Expr::MethodCall {
+                    receiver: inner, ..
+                }
+                | Expr::FieldAccess { expr: inner, .. }
+                | Expr::UnquoteFieldAccess { expr: inner, .. }
+                | Expr::Try { expr: inner } => {
+                    links.push(root);
+                    root = inner;
+                }
+                _ => break,
+            }
+        }
+        links.reverse();
+        let calls = links
+            .iter()
+            .filter(|link| matches!(link, Expr::MethodCall { .. }))
+            .count();
+
+        let inner = indent + INDENT;
+        let mut out = self.operand(root, POSTFIX, indent, col);
+        let mut seen_call = false;
+        for link in links {
+            match link {
+                Expr::MethodCall {
+                    method,
+                    type_args,
+                    args,
+                    ..
+                } => {
+                    if calls > 1 {
+                        out.push('\n');
+                        out.push_str(&pad(inner));
+                    }
+                    out.push('.');
+                    out.push_str(method);
+                    out.push_str(&turbofish(type_args));
+                    let (indent, col) = if calls > 1 {
+                        (inner, end_col(inner, &out))
+                    } else {
+                        (indent, end_col(col, &out))
+                    };
+                    let args: Vec<Elem> = args.iter().map(Elem::Expr).collect();
+                    out.push_str(&self.list("(", &args, ")", false, false, indent, col));
+                    seen_call = true;
+                }
+                Expr::FieldAccess { field, .. } => {
+                    if calls > 1 && seen_call {
+                        out.push('\n');
+                        out.push_str(&pad(inner));
+                    }
+                    out.push('.');
+                    out.push_str(field);
+                }
+                Expr::UnquoteFieldAccess { field_expr, .. } => {
+                    if calls > 1 && seen_call {
+                        out.push('\n');
+                        out.push_str(&pad(inner));
+                    }
+                    let field = self.flat_operand(field_expr, PRIMARY).unwrap_or_default();
+                    out.push_str(&format!(".#{}", field));
+                }
+                _ => out.push('?'),
+            }
+        }
+        out
+    }
+
+    fn if_expr(
+        &mut self,
+        cond: &Expr,
+        then_block: &Block,
+        else_block: Option<&Block>,
+        indent: usize,
+        col: usize,
+    ) -> String {
+        let head = format!("if {}", self.cond(cond, indent, col + 3));
+        let out = self.block_after(head, then_block, indent);
+        match else_block {
+            Some(block) => self.else_branch(out, block, indent),
+            None => out,
+        }
+    }
+
+    /// The `if` or `if let` that an `else` block consists of, to print as
+    /// `else if`.
+    fn else_if<'e>(&self, block: &'e Block) -> Option<&'e Expr> {
+        if !block.stmts.is_empty() {
+            return None;
+        }
+        let e = block.expr.as_deref()?;
+        match e {
+            Expr::If { .. } if assertion(e).is_none() => Some(e),
+            Expr::Match { arms, .. } if assertion(e).is_none() && self.peek_if_let(arms) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn else_branch(&mut self, out: String, block: &Block, indent: usize) -> String {
+        match self.else_if(block) {
+            Some(else_if) => {
+                let head = out + " else ";
+                let col = end_col(indent, &head);
+                head + &self.broken(else_if, indent, col)
+            }
+            None => self.block_after(out + " else", block, indent),
+        }
+    }
+
+    fn if_let(&mut self, scrutinee: &Expr, arms: &[MatchArm], indent: usize, col: usize) -> String {
+        let pattern = self.pattern(&arms[0].pattern, indent, col + 7);
+        let head = format!("if let {} = ", pattern);
+        let value_col = end_col(col, &head);
+        let head = head + &self.cond(scrutinee, indent, value_col);
+        let out = match &arms[0].body {
+            Expr::Block(block) => self.block_after(head, block, indent),
+            body => format!("{} {{ {} }}", head, self.expr(body, indent, col)),
+        };
+        match &arms[1].body {
+            Expr::Tuple(items) if items.is_empty() => out,
+            Expr::Block(block) => self.block_after(out + " else", block, indent),
+            else_if => {
+                let head = out + " else ";
+                let col = end_col(indent, &head);
+                head + &self.broken(else_if, indent, col)
+            }
+        }
+    }
+
+    /// The `with` block whose first step parsed to the match `e`.
+    fn with_block(&mut self, e: &Expr, indent: usize) -> String {
+        let inner = indent + INDENT;
+        let mut lines = Vec::new();
+        let mut current = e;
+        while let Expr::Match { expr, arms } = current {
+            let Some((pattern, rest)) = with_step(arms) else {
+                break;
+            };
+            let is_try = self.take_with_try(pattern);
+            let pattern = match pattern {
+                Pattern::Enum {
+                    fields: EnumPatternFields::Tuple(fields),
+                    ..
+                } if is_try => &fields[0],
+                _ => pattern,
+            };
+            let head = format!("{} <- ", self.pattern(pattern, inner, inner));
+            let col = end_col(inner, &head);
+            let step = if is_try {
+                format!("{}{}?;", head, self.operand(expr, POSTFIX, inner, col))
+            } else {
+                format!("{}{};", head, self.expr(expr, inner, col))
+            };
+            lines.push(format!("{}{}", pad(inner), step));
+            if !rest.stmts.is_empty() {
+                let stmts = Block {
+                    stmts: rest.stmts.clone(),
+                    expr: None,
+                };
+                lines.push(self.stmts(&stmts, inner));
+            }
+            match rest.expr.as_deref() {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+        let value = Block {
+            stmts: vec![],
+            expr: Some(Box::new(current.clone())),
+        };
+        lines.push(self.stmts(&value, inner));
+        format!("with {{\n{}\n{}}}", lines.join("\n"), pad(indent))
+    }
+
+    fn match_expr(
+        &mut self,
+        scrutinee: &Expr,
+        arms: &[MatchArm],
+        indent: usize,
+        col: usize,
+    ) -> String {
+        let head = format!("match {}", self.cond(scrutinee, indent, col + 6));
+        let mut out = open_brace(head, indent);
+        if arms.is_empty() {
+            out.push('}');
+            return out;
+        }
+        out.push('\n');
+        out.push_str(&self.arms(arms, indent + INDENT));
+        out.push('\n');
+        out.push_str(&pad(indent));
+        out.push('}');
+        out
+    }
+
+    fn receive(
+        &mut self,
+        arms: &[MatchArm],
+        timeout: Option<&(Box<Expr>, Block)>,
+        indent: usize,
+    ) -> String {
+        if arms.is_empty() && timeout.is_none() {
+            return "receive {}".to_string();
+        }
+        let inner = indent + INDENT;
+        let mut out = String::from("receive {\n");
+        out.push_str(&self.arms(arms, inner));
+        if let Some((after, block)) = timeout {
+            if !arms.is_empty() {
+                out.push('\n');
+            }
+            let head = format!("after {} =>", self.expr(after, inner, inner + 6));
+            let col = end_col(inner, &head) + 1;
+            out.push_str(&pad(inner));
+            out.push_str(&head);
+            out.push(' ');
+            out.push_str(&self.arm_block(block, inner, col));
+        }
+        out.push('\n');
+        out.push_str(&pad(indent));
+        out.push('}');
+        out
+    }
+
+    /// Match or receive arms, one per line at `indent`.
+    fn arms(&mut self, arms: &[MatchArm], indent: usize) -> String {
+        let mut lines = Vec::new();
+        for (i, arm) in arms.iter().enumerate() {
+            for attr in &arm.attrs {
+                lines.push(format!("{}{}", pad(indent), attribute(attr)));
+            }
+            let mut line = self.pattern(&arm.pattern, indent, indent);
+            if let Some(guard) = &arm.guard {
+                line.push_str(" if ");
+                let col = end_col(indent, &line);
+                line.push_str(&self.expr(guard, indent, col));
+            }
+            line.push_str(" => ");
+            let col = end_col(indent, &line);
+            match &arm.body {
+                Expr::Block(block) => {
+                    line.push_str(&self.arm_block(block, indent, col));
+                    // A block ends the arm by itself, unless the next
+                    // pattern would read as a call on it
+                    if matches!(
+                        arms.get(i + 1),
+                        Some(MatchArm {
+                            pattern: Pattern::Tuple(_),
+                            ..
+                        })
+                    ) {
+                        line.push(',');
+                    }
+                }
+                body => {
+                    line.push_str(&self.expr(body, indent, col));
+                    line.push(',');
+                }
+            }
+            lines.push(format!("{}{}", pad(indent), line));
+        }
+        lines.join("\n")
+    }
+
+    fn arm_block(&mut self, block: &Block, indent: usize, col: usize) -> String {
+        match self.short_block(block, col) {
+            Some(short) => short,
+            None => self.block_after(String::new(), block, indent),
+        }
+    }
+
+    fn for_expr(
+        &mut self,
+        clauses: &[ForClause],
+        body: &Expr,
+        is_comprehension: bool,
+        indent: usize,
+        col: usize,
+    ) -> String {
+        let mut head = String::from("for ");
+        for (i, clause) in clauses.iter().enumerate() {
+            if i > 0 {
+                head.push_str(", ");
+            }
+            let col = end_col(col, &head);
+            head.push_str(&self.clause(clause, indent, col));
+        }
+        match body {
+            Expr::Block(block) if !is_comprehension => self.block_after(head, block, indent),
+            body => {
+                let inner = indent + INDENT;
+                let mut out = open_brace(head, indent);
+                out.push('\n');
+                out.push_str(&pad(inner));
+                out.push_str(&self.expr(body, inner, inner));
+                out.push('\n');
+                out.push_str(&pad(indent));
+                out.push('}');
+                out
+            }
+        }
+    }
+
+    fn clause(&mut self, clause: &ForClause, indent: usize, col: usize) -> String {
+        match clause {
+            ForClause::Generator {
+                pattern,
+                source,
+                style,
+            } => {
+                let head = format!(
+                    "{}{}",
+                    self.pattern(pattern, indent, col),
+                    generator_arrow(*style)
+                );
+                let col = end_col(col, &head);
+                head + &self.cond(source, indent, col)
+            }
+            ForClause::When(cond) => format!("when {}", self.cond(cond, indent, col + 5)),
+        }
+    }
+
+    fn flat_clause(&mut self, clause: &ForClause) -> Option<String> {
+        Some(match clause {
+            ForClause::Generator {
+                pattern,
+                source,
+                style,
+            } => format!(
+                "{}{}{}",
+                self.flat_pattern(pattern),
+                generator_arrow(*style),
+                self.flat_cond(source)?
+            ),
+            ForClause::When(cond) => format!("when {}", self.flat_cond(cond)?),
+        })
+    }
+
+    fn flat_assertion(&mut self, assertion: &Assertion, message: Option<&Expr>) -> Option<String> {
+        let (open, items) = assertion_items(assertion, message);
+        self.flat_list(&open, &items, ")", false)
+    }
+
+    fn assertion_call(
+        &mut self,
+        assertion: &Assertion,
+        message: Option<&Expr>,
+        indent: usize,
+        col: usize,
+    ) -> String {
+        let (open, items) = assertion_items(assertion, message);
+        // The parser takes a trailing comma only where a message could go
+        self.list(&open, &items, ")", false, message.is_none(), indent, col)
+    }
+
+    fn interpolation(&mut self, parts: &[StringPart]) -> String {
+        let outer = std::mem::replace(&mut self.in_interpolation, true);
+        let mut out = String::from("\"");
+        for part in parts {
+            match part {
+                StringPart::Literal(text) => out.push_str(&escape_string(text)),
+                StringPart::Expr(e) => {
+                    let e = self.flat(e).unwrap_or_else(|| self.broken(e, 0, 0));
+                    // `{{` would be an escaped brace
+                    if e.starts_with('{') {
+                        out.push_str(&format!("{{ {}}}", e));
+                    } else {
+                        out.push_str(&format!("{{{}}}", e));
+                    }
+                }
+            }
+        }
+        out.push('"');
+        self.in_interpolation = outer;
+        out
+    }
+
+    // === Lists ===
+
+    /// `open items close`: on one line if it fits, else with a multi-line
+    /// last item hugged by the delimiters, else one item per line. A
+    /// `spaced` list has spaces inside its delimiters on one line, as in
+    /// `Point { x: 1 }`; a `trailing` one gets a comma after its last item
+    /// when broken, where the grammar allows it.
+    #[allow(clippy::too_many_arguments)]
+    fn list(
+        &mut self,
+        open: &str,
+        items: &[Elem],
+        close: &str,
+        spaced: bool,
+        trailing: bool,
+        indent: usize,
+        col: usize,
+    ) -> String {
+        if items.is_empty() {
+            return format!("{}{}", open, close);
+        }
+        let saved = self.cursor;
+        if let Some(flat) = self.flat_list(open, items, close, spaced) {
+            if col + width(&flat) < WIDTH {
+                return flat;
+            }
+        }
+        self.cursor = saved;
+
+        if !spaced && items.last().is_some_and(hugs) {
+            if let Some(hugged) = self.hugged_list(open, items, close, indent, col) {
+                return hugged;
+            }
+            self.cursor = saved;
+        }
+
+        let inner = indent + INDENT;
+        let mut out = format!("{}\n", open);
+        for (i, item) in items.iter().enumerate() {
+            out.push_str(&pad(inner));
+            out.push_str(&self.elem(item, inner, inner));
+            if trailing || i + 1 < items.len() {
+                out.push(',');
+            }
+            out.push('\n');
+        }
+        out.push_str(&pad(indent));
+        out.push_str(close);
+        out
+    }
+
+    fn flat_list(
+        &mut self,
+        open: &str,
+        items: &[Elem],
+        close: &str,
+        spaced: bool,
+    ) -> Option<String> {
+        let items = items
+            .iter()
+            .map(|item| self.flat_elem(item))
+            .collect::<Option<Vec<_>>>()?;
+        let space = if spaced && !items.is_empty() { " " } else { "" };
+        Some(format!(
+            "{}{}{}{}{}",
+            open,
+            space,
+            items.join(", "),
+            space,
+            close
+        ))
+    }
+
+    fn hugged_list(
+        &mut self,
+        open: &str,
+        items: &[Elem],
+        close: &str,
+        indent: usize,
+        col: usize,
+    ) -> Option<String> {
+        let (last, rest) = items.split_last()?;
+        let mut out = open.to_string();
+        for item in rest {
+            out.push_str(&self.flat_elem(item)?);
+            out.push_str(", ");
+        }
+        let col = col + width(&out);
+        if col >= WIDTH {
+            return None;
+        }
+        let last = self.elem(last, indent, col);
+        let first_line = last.lines().next().unwrap_or_default();
+        (last.contains('\n') && col + width(first_line) <= WIDTH).then(|| out + &last + close)
+    }
+
+    fn flat_elem(&mut self, elem: &Elem) -> Option<String> {
+        Some(match elem {
+            Elem::Expr(e) => self.flat(e)?,
+            Elem::Field(field, Expr::Ident(value)) if value == field => name(field),
+            Elem::Field(field, value) => format!("{}: {}", name(field), self.flat(value)?),
+            Elem::Base(base) => format!("..{}", self.flat(base)?),
+            Elem::Entry(key, value) => {
+                let key = match self.shorthand_key(key) {
+                    Some(key) => key,
+                    None => format!("{} =>", self.flat(key)?),
+                };
+                format!("{} {}", key, self.flat(value)?)
+            }
+            Elem::Segment(segment) => {
+                let value = self.flat_operand(&segment.value, UNARY)?;
+                value + &self.segment_suffix(segment)?
+            }
+            Elem::Pattern(pattern) => self.flat_pattern(pattern),
+            Elem::PatternField(field, pattern) => self.flat_pattern_field(field, pattern),
+            Elem::Guarded(pattern, guard) => {
+                let pattern = self.flat_pattern(pattern);
+                match guard {
+                    Some(guard) => format!("{} if {}", pattern, self.flat(guard)?),
+                    None => pattern,
+                }
+            }
+            Elem::Param(param) => {
+                let pattern = self.flat_pattern(&param.pattern);
+                typed_param(param, pattern)
+            }
+            Elem::Rest => "..".to_string(),
+            Elem::Text(text) => text.clone(),
+        })
+    }
+
+    fn elem(&mut self, elem: &Elem, indent: usize, col: usize) -> String {
+        match elem {
+            Elem::Expr(e) => self.expr(e, indent, col),
+            Elem::Field(field, Expr::Ident(value)) if value == field => name(field),
+            Elem::Field(field, value) => {
+                let head = format!("{}: ", name(field));
+                let col = col + width(&head);
+                head + &self.expr(value, indent, col)
+            }
+            Elem::Base(base) => format!("..{}", self.expr(base, indent, col + 2)),
+            Elem::Entry(key, value) => {
+                let key = match self.shorthand_key(key) {
+                    Some(key) => key,
+                    None => format!("{} =>", self.expr(key, indent, col)),
+                };
+                let col = end_col(col, &key) + 1;
+                format!("{} {}", key, self.expr(value, indent, col))
+            }
+            Elem::PatternField(field, pattern) => match pattern {
+                Pattern::Ident(binding) if binding == field => binding.clone(),
+                _ => {
+                    let head = format!("{}: ", field);
+                    let col = col + width(&head);
+                    head + &self.pattern(pattern, indent, col)
+                }
+            },
+            Elem::Pattern(pattern) => self.pattern(pattern, indent, col),
+            Elem::Param(param) => {
+                let pattern = self.pattern(&param.pattern, indent, col);
+                typed_param(param, pattern)
+            }
+            _ => self.flat_elem(elem).unwrap_or_default(),
+        }
+    }
+
+    /// `key:` for a map entry written with the shorthand.
+    fn shorthand_key(&mut self, key: &Expr) -> Option<String> {
+        if !self.take_map_shorthand(key) {
+            return None;
+        }
+        match key {
+            Expr::Atom(atom) => Some(format!("{}:", atom)),
+            // Kept as written: the parser doesn't process escapes here
+            Expr::String(raw) => Some(format!("\"{}\":", raw)),
+            _ => None,
+        }
+    }
+
+    /// `:size/specifiers` after a bit string segment's value, leaving out
+    /// the defaults.
+    fn segment_suffix<T>(&mut self, segment: &BitStringSegment<T>) -> Option<String> {
+        let mut out = String::new();
+        if let Some(size) = &segment.size {
+            let size = self.flat_operand(size, UNARY)?;
+            out.push(':');
+            // `x:n` lexes as `x` and the atom `:n`, which the parser takes
+            // back as the variable, but `x:n.len` would not come back whole
+            let variable = size.chars().all(|c| c.is_alphanumeric() || c == '_');
+            if size.starts_with(|c: char| c.is_alphabetic() || c == '_') && !variable {
+                out.push(' ');
+            }
+            out.push_str(&size);
+        }
+        let mut specifiers = Vec::new();
+        match segment.segment_type {
+            BitSegmentType::Integer => {}
+            BitSegmentType::Float => specifiers.push("float"),
+            BitSegmentType::Binary => specifiers.push("binary"),
+            BitSegmentType::Utf8 => specifiers.push("utf8"),
+        }
+        if segment.signedness == BitSignedness::Signed {
+            specifiers.push("signed");
+        }
+        if segment.endianness == BitEndianness::Little {
+            specifiers.push("little");
+        }
+        if !specifiers.is_empty() {
+            out.push('/');
+            out.push_str(&specifiers.join("-"));
+        }
+        Some(out)
+    }
+
+    // === Patterns ===
+
+    fn pattern(&mut self, pattern: &Pattern, indent: usize, col: usize) -> String {
+        let saved = self.cursor;
+        let flat = self.flat_pattern(pattern);
+        if col + width(&flat) < WIDTH {
+            return flat;
+        }
+        self.cursor = saved;
+        match pattern {
+            Pattern::Tuple(items) if items.len() > 1 => {
+                let items: Vec<Elem> = items.iter().map(Elem::Pattern).collect();
+                self.list("(", &items, ")", false, true, indent, col)
+            }
+            Pattern::List(items) => {
+                let items: Vec<Elem> = items.iter().map(Elem::Pattern).collect();
+                self.list("[", &items, "]", false, true, indent, col)
+            }
+            Pattern::Struct { name: n, fields } => {
+                self.struct_pattern(&name(n), fields, indent, col)
+            }
+            Pattern::Enum {
+                name: n,
+                variant,
+                fields: EnumPatternFields::Tuple(items),
+            } => {
+                let items: Vec<Elem> = items.iter().map(Elem::Pattern).collect();
+                let open = format!("{}(", variant_path(n, variant));
+                self.list(&open, &items, ")", false, false, indent, col)
+            }
+            Pattern::Enum {
+                name: n,
+                variant,
+                fields: EnumPatternFields::Struct(fields),
+            } => self.struct_pattern(&variant_path(n, variant), fields, indent, col),
+            _ => self.flat_pattern(pattern),
+        }
+    }
+
+    fn struct_pattern(
+        &mut self,
+        head: &str,
+        fields: &[(String, Pattern)],
+        indent: usize,
+        col: usize,
+    ) -> String {
+        let rest = self.take_pattern_rest();
+        let mut items: Vec<Elem> = fields
+            .iter()
+            .map(|(field, p)| Elem::PatternField(field, p))
+            .collect();
+        if rest {
+            items.push(Elem::Rest);
+        }
+        self.list(
+            &format!("{} {{", head),
+            &items,
+            "}",
+            true,
+            !rest,
+            indent,
+            col,
+        )
+    }
+
+    fn flat_pattern(&mut self, pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Wildcard => "_".to_string(),
+            Pattern::Ident(n) => n.clone(),
+            Pattern::Int(n) => n.to_string(),
+            Pattern::String(s) => format!("\"{}\"", escape_string(s)),
+            Pattern::Charlist(s) => charlist_literal(s),
+            Pattern::Atom(a) => atom(a),
+            Pattern::Bool(b) => b.to_string(),
+            Pattern::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|p| self.flat_pattern(p)).collect();
+                match items.as_slice() {
+                    [item] => format!("({},)", item),
+                    _ => format!("({})", items.join(", ")),
+                }
+            }
+            Pattern::List(items) => {
+                let items: Vec<String> = items.iter().map(|p| self.flat_pattern(p)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            Pattern::ListCons { head, tail } => {
+                format!(
+                    "[{} | {}]",
+                    self.flat_pattern(head),
+                    self.flat_pattern(tail)
+                )
+            }
+            Pattern::Struct { name: n, fields } => self.flat_struct_pattern(&name(n), fields),
+            Pattern::Enum {
+                name: n,
+                variant,
+                fields,
+            } => {
+                let head = variant_path(n, variant);
+                match fields {
+                    EnumPatternFields::Unit => head,
+                    EnumPatternFields::Tuple(items) => {
+                        let items: Vec<String> =
+                            items.iter().map(|p| self.flat_pattern(p)).collect();
+                        format!("{}({})", head, items.join(", "))
+                    }
+                    EnumPatternFields::Struct(fields) => self.flat_struct_pattern(&head, fields),
+                }
+            }
+            Pattern::BitString(segments) => {
+                let segments: Vec<String> = segments
+                    .iter()
+                    .map(|segment| {
+                        let value = self.flat_pattern(&segment.value);
+                        value + &self.segment_suffix(segment).unwrap_or_default()
+                    })
+                    .collect();
+                format!("<<{}>>", segments.join(", "))
+            }
+        }
+    }
+
+    fn flat_struct_pattern(&mut self, head: &str, fields: &[(String, Pattern)]) -> String {
+        let rest = self.take_pattern_rest();
+        let mut items: Vec<String> = fields
+            .iter()
+            .map(|(field, p)| self.flat_pattern_field(field, p))
+            .collect();
+        if rest {
+            items.push("..".to_string());
+        }
+        if items.is_empty() {
+            format!("{} {{}}", head)
+        } else {
+            format!("{} {{ {} }}", head, items.join(", "))
+        }
+    }
+
+    fn flat_pattern_field(&mut self, field: &str, pattern: &Pattern) -> String {
+        match pattern {
+            Pattern::Ident(binding) if binding == field => binding.clone(),
+            _ => format!("{}: {}", field, self.flat_pattern(pattern)),
+        }
+    }
+}
+
+/// `head {`, or `head` then `{` on its own line when the head spans lines
+/// and doesn't end with a closing delimiter at `indent`, so the brace
+/// stands out from the continuation lines above it.
+fn open_brace(head: String, indent: usize) -> String {
+    if head.is_empty() {
+        return "{".to_string();
+    }
+    let last = head.rsplit('\n').next().unwrap_or_default();
+    let trimmed = last.trim_start();
+    let ends_in_closer =
+        last.len() - trimmed.len() == indent && trimmed.starts_with([')', ']', '}']);
+    if head.contains('\n') && !ends_in_closer {
+        format!("{}\n{}{{", head, pad(indent))
+    } else {
+        head + " {"
+    }
+}
+
+/// `head { ... }` with one member per line, or `head {}` without any.
+fn members(head: String, members: &[String], indent: usize) -> String {
+    let members: Vec<&String> = members.iter().filter(|m| !m.is_empty()).collect();
+    if members.is_empty() {
+        return format!("{} {{}}", head);
+    }
+    let inner = pad(indent + INDENT);
+    let body: Vec<String> = members.iter().map(|m| format!("{}{}", inner, m)).collect();
+    format!("{} {{\n{}\n{}}}", head, body.join("\n"), pad(indent))
+}
+
+fn type_bindings(bindings: &[(String, Type)], indent: usize) -> String {
+    let bindings: Vec<String> = bindings
+        .iter()
+        .map(|(n, t)| format!("type {} = {};", n, ty(t)))
+        .collect();
+    bindings.join(&format!("\n{}", pad(indent)))
+}
+
+/// An `extern mod` block, after the `extern`.
+fn extern_mod(module: &ExternMod, indent: usize) -> String {
+    let head = format!("mod {}", module.name);
+    if module.items.is_empty() {
+        return format!("{} {{}}", head);
+    }
+    let inner = indent + INDENT;
+    let mut out = format!("{} {{\n", head);
+    for (i, item) in module.items.iter().enumerate() {
+        if i > 0 {
+            // Nested modules get a blank line around them
+            let nested = matches!(item, ExternItem::Mod(_))
+                || matches!(module.items[i - 1], ExternItem::Mod(_));
+            out.push_str(if nested { "\n\n" } else { "\n" });
+        }
+        out.push_str(&pad(inner));
+        let attrs = match item {
+            ExternItem::Mod(m) => m.attrs.as_slice(),
+            ExternItem::Function(f) => f.attrs.as_slice(),
+            ExternItem::Type(_) => &[],
+        };
+        for attr in attrs {
+            out.push_str(&attribute(attr));
+            out.push('\n');
+            out.push_str(&pad(inner));
+        }
+        match item {
+            ExternItem::Mod(m) => out.push_str(&extern_mod(m, inner)),
+            ExternItem::Function(f) => out.push_str(&extern_fn(f, &f.name, inner)),
+            ExternItem::Type(t) => out.push_str(&format!(
+                "type {}{};",
+                name(&t.name),
+                type_params(&t.type_params)
+            )),
+        }
+    }
+    out.push('\n');
+    out.push_str(&pad(indent));
+    out.push('}');
+    out
+}
+
+/// An `extern "erlang"`, `extern "elixir"` or `extern "nif"` block.
+fn extern_block(block: &ExternBlock, indent: usize) -> String {
+    let head = format!("extern \"{}\"", escape_string(&block.abi));
+    if block.functions.is_empty() {
+        return format!("{} {{}}", head);
+    }
+    let inner = indent + INDENT;
+    let mut out = format!("{} {{\n", head);
+    for (module, f) in &block.functions {
+        out.push_str(&pad(inner));
+        for attr in &f.attrs {
+            out.push_str(&attribute(attr));
+            out.push('\n');
+            out.push_str(&pad(inner));
+        }
+        let name = if module.is_empty() {
+            f.name.clone()
+        } else {
+            format!("{}::{}", module, f.name)
+        };
+        out.push_str(&extern_fn(f, &name, inner));
+        out.push('\n');
+    }
+    out.push_str(&pad(indent));
+    out.push('}');
+    out
+}
+
+/// An extern function's signature, called `name`, starting at column `indent`.
+fn extern_fn(f: &ExternFn, name: &str, indent: usize) -> String {
+    let params: Vec<String> = f
+        .params
+        .iter()
+        .map(|(n, t)| format!("{}: {}", n, ty(t)))
+        .collect();
+    let head = format!("fn {}{}(", name, type_params(&f.type_params));
+    let params = if indent + width(&head) + width(&params.join(", ")) + 1 < WIDTH {
+        params.join(", ")
+    } else {
+        let param_pad = pad(indent + INDENT);
+        let params: Vec<String> = params
+            .iter()
+            .map(|p| format!("{}{},\n", param_pad, p))
+            .collect();
+        format!("\n{}{}", params.concat(), pad(indent))
+    };
+    let mut out = head;
+    out.push_str(&params);
+    out.push(')');
+    if f.return_type != Type::Unit {
+        out.push_str(" -> ");
+        out.push_str(&ty(&f.return_type));
+    }
+    out.push(';');
+    out
+}
+
+fn attribute(attr: &Attribute) -> String {
+    match &attr.args {
+        AttributeArgs::None => format!("#[{}]", attr.name),
+        AttributeArgs::Parenthesized(args) => format!("#[{}({})]", attr.name, attribute_args(args)),
+        // Attribute strings are kept as written
+        AttributeArgs::Eq(value) => format!("#[{} = \"{}\"]", attr.name, value),
+    }
+}
+
+fn attribute_args(args: &[AttributeArg]) -> String {
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| match arg {
+            AttributeArg::Ident(n) => n.clone(),
+            AttributeArg::Path(segments) => segments.join("::"),
+            AttributeArg::KeyValue(key, value) => format!("{} = \"{}\"", key, value),
+            AttributeArg::Compare(key, op, value) => format!("{} {} \"{}\"", key, op, value),
+            AttributeArg::Nested(n, args) => format!("{}({})", n, attribute_args(args)),
+            AttributeArg::Str(s) => format!("\"{}\"", s),
+        })
+        .collect();
+    args.join(", ")
+}
+
+fn variant(v: &EnumVariant) -> String {
+    match &v.kind {
+        VariantKind::Unit => name(&v.name),
+        VariantKind::Tuple(types) => format!("{}({})", name(&v.name), type_list(types)),
+        VariantKind::Struct(fields) if fields.is_empty() => format!("{} {{}}", name(&v.name)),
+        VariantKind::Struct(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(n, t)| format!("{}: {}", name(n), ty(t)))
+                .collect();
+            format!("{} {{ {} }}", name(&v.name), fields.join(", "))
+        }
+    }
+}
+
+fn module_path(module: &ModulePath) -> String {
+    let prefix = match module.prefix {
+        PathPrefix::Crate => Some("crate"),
+        PathPrefix::Super => Some("super"),
+        PathPrefix::SelfMod => Some("self"),
+        PathPrefix::None => None,
+    };
+    let segments: Vec<&str> = prefix
+        .into_iter()
+        .chain(module.segments.iter().map(String::as_str))
+        .collect();
+    segments.join("::")
+}
+
+fn renamed(name: &str, rename: Option<&str>) -> String {
+    match rename {
+        Some(rename) => format!("{} as {}", name, rename),
+        None => name.to_string(),
+    }
+}
+
+fn vis(is_pub: bool) -> &'static str {
+    if is_pub { "pub " } else { "" }
+}
+
+/// An identifier, with the parser's marker for `#var` in a quoted item
+/// turned back into `#var`.
+fn name(name: &str) -> String {
+    match name.strip_prefix("$UNQUOTE:") {
+        Some(var) => format!("#{}", var),
+        None => name.to_string(),
+    }
+}
+
+fn type_params(params: &[TypeParam]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = params
+        .iter()
+        .map(|p| {
+            if p.bounds.is_empty() {
+                p.name.clone()
+            } else {
+                format!("{}: {}", p.name, p.bounds.join(" + "))
+            }
+        })
+        .collect();
+    format!("<{}>", params.join(", "))
+}
+
+fn type_args(args: &[Type]) -> String {
+    if args.is_empty() {
+        String::new()
+    } else {
+        format!("<{}>", type_list(args))
+    }
+}
+
+fn turbofish(args: &[Type]) -> String {
+    if args.is_empty() {
+        String::new()
+    } else {
+        format!("::<{}>", type_list(args))
+    }
+}
+
+fn type_list(types: &[Type]) -> String {
+    let types: Vec<String> = types.iter().map(ty).collect();
+    types.join(", ")
+}
+
+fn ty(t: &Type) -> String {
+    match t {
+        Type::Named {
+            name: n,
+            type_args: args,
+        } => format!("{}{}", name(n), type_args(args)),
+        Type::TypeVar(n) => n.clone(),
+        Type::Tuple(items) if items.len() == 1 => format!("({},)", ty(&items[0])),
+        Type::Tuple(items) => format!("({})", type_list(items)),
+        Type::List(item) => format!("[{}]", ty(item)),
+        Type::Pid => "pid".to_string(),
+        Type::Ref => "ref".to_string(),
+        Type::Int => "int".to_string(),
+        Type::String => "string".to_string(),
+        Type::Atom => "atom".to_string(),
+        Type::AtomLiteral(a) => atom(a),
+        Type::Bool => "bool".to_string(),
+        Type::Float => "float".to_string(),
+        Type::Unit => "()".to_string(),
+        Type::Binary => "binary".to_string(),
+        Type::Map => "map".to_string(),
+        Type::Any => "any".to_string(),
+        Type::Union(members) => {
+            let members: Vec<String> = members
+                .iter()
+                .map(|member| match member {
+                    Type::Fn { .. } | Type::Union(_) => format!("({})", ty(member)),
+                    _ => ty(member),
+                })
+                .collect();
+            members.join(" | ")
+        }
+        Type::Fn { params, ret } => format!("fn({}) -> {}", type_list(params), ty(ret)),
+        Type::AssociatedType { base, name } => format!("{}::{}", base, name),
+    }
+}
+
+/// A parameter with its type, unless the type is the one the parser
+/// would give it anyway: `self`, and literal, variant and binary patterns.
+fn typed_param(param: &Param, pattern: String) -> String {
+    let implied = match &param.pattern {
+        Pattern::Ident(n) if n == "self" => "Self",
+        Pattern::Int(_) => "int",
+        Pattern::Atom(_) => "atom",
+        Pattern::Bool(_) => "bool",
+        Pattern::String(_) | Pattern::Charlist(_) => "string",
+        Pattern::Wildcard => "any",
+        Pattern::Enum { name, .. } => name.as_str(),
+        Pattern::BitString(_) => "Binary",
+        _ => return format!("{}: {}", pattern, ty(&param.ty)),
+    };
+    match &param.ty {
+        Type::Named { name, type_args } if name == implied && type_args.is_empty() => pattern,
+        t => format!("{}: {}", pattern, ty(t)),
+    }
+}
+
+fn variant_path(type_name: &str, variant: &str) -> String {
+    if type_name.is_empty() {
+        variant.to_string()
+    } else {
+        format!("{}::{}", type_name, variant)
+    }
+}
+
+fn closure_params(params: &[String]) -> String {
+    format!("|{}|", params.join(", "))
+}
+
+fn generator_arrow(style: GeneratorStyle) -> &'static str {
+    match style {
+        GeneratorStyle::In => " in ",
+        GeneratorStyle::Arrow => " <- ",
+    }
+}
+
+fn struct_fields<'e>(fields: &'e [(String, Expr)], base: Option<&'e Expr>) -> Vec<Elem<'e>> {
+    let mut items: Vec<Elem> = fields
+        .iter()
+        .map(|(field, value)| Elem::Field(field, value))
+        .collect();
+    items.extend(base.map(Elem::Base));
+    items
+}
+
+fn assertion_items<'e>(
+    assertion: &Assertion<'e>,
+    message: Option<&'e Expr>,
+) -> (String, Vec<Elem<'e>>) {
+    let (open, mut items) = match *assertion {
+        Assertion::True(cond) => ("assert!(".to_string(), vec![Elem::Expr(cond)]),
+        Assertion::Compare(macro_name, left, right) => (
+            format!("{}!(", macro_name),
+            vec![Elem::Expr(left), Elem::Expr(right)],
+        ),
+        Assertion::Match(value, pattern, guard) => (
+            "assert_match!(".to_string(),
+            vec![Elem::Expr(value), Elem::Guarded(pattern, guard)],
+        ),
+    };
+    items.extend(message.map(Elem::Expr));
+    (open, items)
+}
+
+/// The template and arguments of a `format!`.
+fn format_items(parts: &[FormatPart]) -> Vec<Elem<'_>> {
+    let mut template = String::from("\"");
+    let mut args = Vec::new();
+    for part in parts {
+        match part {
+            FormatPart::Literal(text) => template.push_str(&escape_string(text)),
+            FormatPart::Display(e) => {
+                template.push_str("{}");
+                args.push(Elem::Expr(e));
+            }
+            FormatPart::Debug(e) => {
+                template.push_str("{:?}");
+                args.push(Elem::Expr(e));
+            }
+        }
+    }
+    template.push('"');
+    let mut items = vec![Elem::Text(template)];
+    items.extend(args);
+    items
+}
+
+/// Whether a list may hug this, its last item, when the item spans lines:
+/// `foo(|x| {` ... `})`.
+fn hugs(elem: &Elem) -> bool {
+    matches!(
+        elem,
+        Elem::Expr(
+            Expr::Closure { .. }
+                | Expr::SpawnClosure(_)
+                | Expr::StructInit { .. }
+                | Expr::MapLiteral(_)
+                | Expr::List(_)
+                | Expr::Block(_)
+                | Expr::Receive { .. }
+                | Expr::For { .. }
+                | Expr::Quote(_)
+                | Expr::QuoteItem(_)
+                | Expr::EnumVariant {
+                    args: EnumVariantArgs::Struct(_),
+                    ..
+                }
+        )
+    )
+}
+
+fn expr_precedence(e: &Expr) -> u8 {
+    match e {
+        Expr::Return(_) => 0,
+        Expr::Call { func, args, .. } if yielded(func, args).is_some() => 0,
+        Expr::Pipe { .. } | Expr::Send { .. } => PIPE,
+        Expr::Binary { op, .. } => op.precedence() + 1,
+        Expr::Unary { .. } | Expr::Spawn(_) => UNARY,
+        Expr::Int(n) if *n < 0 => UNARY,
+        Expr::Call { .. }
+        | Expr::MethodCall { .. }
+        | Expr::FieldAccess { .. }
+        | Expr::UnquoteFieldAccess { .. }
+        | Expr::Try { .. } => POSTFIX,
+        _ => PRIMARY,
+    }
+}
+
+/// Whether a statement of this expression can go without a semicolon.
+fn is_block_like(e: &Expr) -> bool {
+    matches!(
+        e,
+        Expr::If { .. }
+            | Expr::Match { .. }
+            | Expr::Block(_)
+            | Expr::Receive { .. }
+            | Expr::For { .. }
+    ) && assertion(e).is_none()
+}
+
+/// Whether a called expression needs parentheses: `(x.f)(1)` isn't a
+/// method call, and `(Some)(1)` isn't a variant with arguments.
+fn needs_parens_as_callee(func: &Expr) -> bool {
+    matches!(
+        func,
+        Expr::FieldAccess { .. }
+            | Expr::EnumVariant {
+                args: EnumVariantArgs::Unit,
+                ..
+            }
+    ) || expr_precedence(func) < POSTFIX
+}
+
+/// Whether `spawn` needs its operand parenthesised: `spawn || { ... }` is
+/// a spawned closure body rather than a spawned closure value.
+fn needs_parens_after_spawn(e: &Expr) -> bool {
+    matches!(e, Expr::Closure { params, .. } if params.is_empty()) || expr_precedence(e) < POSTFIX
+}
+
+/// Whether an expression ends in a type name, which a following `{`
+/// would turn into a struct literal.
+fn ends_in_type_name(e: &Expr) -> bool {
+    match e {
+        Expr::EnumVariant {
+            args: EnumVariantArgs::Unit,
+            ..
+        } => true,
+        Expr::Path { segments } => segments
+            .last()
+            .is_some_and(|s| s.starts_with(char::is_uppercase)),
+        Expr::Binary { right, .. } | Expr::Pipe { right, .. } | Expr::Send { msg: right, .. } => {
+            ends_in_type_name(right)
+        }
+        Expr::Unary { expr, .. }
+        | Expr::Spawn(expr)
+        | Expr::Return(Some(expr))
+        | Expr::Unquote(expr)
+        | Expr::UnquoteSplice(expr)
+        | Expr::UnquoteAtom(expr) => ends_in_type_name(expr),
+        _ => false,
+    }
+}
+
+/// Whether a `with` step's pattern could have been written `inner <- value?`.
+fn try_step_shape(pattern: &Pattern) -> bool {
+    matches!(
+        pattern,
+        Pattern::Enum { name, variant, fields: EnumPatternFields::Tuple(fields) }
+            if name.is_empty() && variant == "Ok" && fields.len() == 1
+    )
+}
+
+/// Whether match arms have the shape the parser gives `if let`.
+fn if_let_shape(arms: &[MatchArm]) -> bool {
+    let [then, otherwise] = arms else {
+        return false;
+    };
+    then.guard.is_none()
+        && matches!(then.body, Expr::Block(_))
+        && otherwise.guard.is_none()
+        && otherwise.pattern == Pattern::Wildcard
+        && match &otherwise.body {
+            Expr::Block(_) | Expr::If { .. } => true,
+            Expr::Tuple(items) => items.is_empty(),
+            Expr::Match { arms, .. } => if_let_shape(arms),
+            _ => false,
+        }
+}
+
+/// The assertion macro `e` was desugared from, and its message.
+fn assertion(e: &Expr) -> Option<(Assertion<'_>, Option<&Expr>)> {
+    match e {
+        Expr::If { .. } => {
+            let (cond, info) = assert_check(e)?;
+            Some((Assertion::True(cond), message(info)))
+        }
+        Expr::Match { expr, arms } => match arms.as_slice() {
+            [arm] => {
+                let Expr::Tuple(operands) = &**expr else {
+                    return None;
+                };
+                let [left, right] = operands.as_slice() else {
+                    return None;
+                };
+                let bound = Pattern::Tuple(vec![
+                    Pattern::Ident("__assert_left".to_string()),
+                    Pattern::Ident("__assert_right".to_string()),
+                ]);
+                if arm.pattern != bound || arm.guard.is_some() {
+                    return None;
+                }
+                let (cond, info) = assert_check(&arm.body)?;
+                let macro_name = match cond {
+                    Expr::Binary { op: BinOp::Eq, .. } => "assert_eq",
+                    Expr::Binary { op: BinOp::Ne, .. } => "assert_ne",
+                    _ => return None,
+                };
+                Some((Assertion::Compare(macro_name, left, right), message(info)))
+            }
+            [matched, fallback] => {
+                if fallback.pattern != Pattern::Ident("__assert_value".to_string())
+                    || fallback.guard.is_some()
+                    || !matches!(&matched.body, Expr::Atom(ok) if ok == "ok")
+                {
+                    return None;
+                }
+                let info = assertion_info(&fallback.body)?;
+                let assertion = Assertion::Match(expr, &matched.pattern, matched.guard.as_deref());
+                Some((assertion, message(info)))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// `if cond { :ok } else { <assertion failure> }`, as `assert!` expands.
+fn assert_check(e: &Expr) -> Option<(&Expr, &[(Expr, Expr)])> {
+    let Expr::If {
+        cond,
+        then_block,
+        else_block: Some(else_block),
+    } = e
+    else {
+        return None;
+    };
+    let ok = then_block.stmts.is_empty()
+        && matches!(then_block.expr.as_deref(), Some(Expr::Atom(a)) if a == "ok");
+    if !ok || !else_block.stmts.is_empty() {
+        return None;
+    }
+    Some((cond, assertion_info(else_block.expr.as_deref()?)?))
+}
+
+/// The info map of `:erlang::error((:assertion_failed, info))`, which
+/// every assertion macro raises on failure.
+fn assertion_info(e: &Expr) -> Option<&[(Expr, Expr)]> {
+    let Expr::ExternCall {
+        module,
+        function,
+        args,
+    } = e
+    else {
+        return None;
+    };
+    if module != "erlang" || function != "error" {
+        return None;
+    }
+    let [Expr::Tuple(items)] = args.as_slice() else {
+        return None;
+    };
+    let [Expr::Atom(tag), Expr::MapLiteral(info)] = items.as_slice() else {
+        return None;
+    };
+    let from_macro = tag == "assertion_failed"
+        && matches!(info.first(), Some((Expr::Atom(key), _)) if key == "source");
+    from_macro.then_some(info.as_slice())
+}
+
+fn message(info: &[(Expr, Expr)]) -> Option<&Expr> {
+    info.iter()
+        .find(|(key, _)| matches!(key, Expr::Atom(a) if a == "message"))
+        .map(|(_, value)| value)
+}
+
+fn atom(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if plain {
+        format!(":{}", name)
+    } else if name.contains('\'') {
+        format!(":\"{}\"", name)
+    } else {
+        format!(":'{}'", name)
+    }
+}
+
+/// Whether an atom can be written as a bare `key:` in a map.
+fn is_plain_ident(name: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "fn", "let", "mut", "if", "else", "match", "struct", "enum", "mod", "pub", "self", "crate",
+        "super", "spawn", "receive", "after", "return", "use", "as", "impl", "trait", "for", "in",
+        "when", "true", "false", "extern", "type", "quote", "big", "little", "signed", "unsigned",
+        "integer", "float", "utf8",
+    ];
+    name != "_"
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
+
+/// The inside of a string literal. Braces are doubled so they don't start
+/// an interpolation.
+fn escape_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            '{' => out.push_str("{{"),
+            '}' => out.push_str("}}"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn charlist_literal(s: &str) -> String {
+    let mut out = String::from("'");
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // The lexer ends a charlist at any `'` not after a backslash,
+            // and escape processing leaves `\'` alone
+            '\\' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                out.push_str("\\'");
+            }
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            '{' => out.push_str("{{"),
+            '}' => out.push_str("}}"),
+            c => out.push(c),
+        }
+    }
+    out.push('\'');
+    out
+}
+
+fn pad(indent: usize) -> String {
+    " ".repeat(indent)
+}
+
+fn width(s: &str) -> usize {
+    s.chars().count()
+}
+
+/// The column after `s`, printed starting at `col`.
+fn end_col(col: usize, s: &str) -> usize {
+    match s.rsplit_once('\n') {
+        Some((_, last)) => width(last),
+        None => col + width(s),
+    }
+}
+
+// === Comments ===
+
+/// A comment from the source.
+struct Comment<'s> {
+    text: &'s str,
+    /// Whether a blank line came before it
+    blank_before: bool,
+}
+
+/// The comments around a source token.
+#[derive(Default)]
+struct Trivia<'s> {
+    /// Comments on lines of their own before the token
+    leading: Vec<Comment<'s>>,
+    /// Comments after the token on its line
+    trailing: Vec<&'s str>,
+    /// Whether a blank line separates the token from what comes before it
+    blank_before: bool,
+}
+
+fn lex(text: &str) -> Vec<(Token, Range<usize>)> {
+    Token::lexer(text)
+        .spanned()
+        .filter_map(|(token, span)| Some((token.ok()?, span)))
+        .collect()
+}
+
+/// Split the comments of `source` among its tokens. The extra last entry
+/// holds comments after the last token.
+fn trivia<'s>(source: &'s str, tokens: &[(Token, Range<usize>)]) -> Vec<Trivia<'s>> {
+    let mut trivia: Vec<Trivia> = (0..=tokens.len()).map(|_| Trivia::default()).collect();
+    let mut start = 0;
+    for i in 0..=tokens.len() {
+        let end = tokens.get(i).map_or(source.len(), |(_, span)| span.start);
+        let gap = &source[start..end];
+        let mut newlines = 0;
+        // Nothing comes before the first token, so its comments lead it
+        let mut own_line = i == 0;
+        let mut pos = 0;
+        while pos < gap.len() {
+            let rest = &gap[pos..];
+            let len = if rest.starts_with("//") {
+                rest.find('\n').unwrap_or(rest.len())
+            } else if rest.starts_with("/*") {
+                rest.find("*/").map_or(rest.len(), |end| end + 2)
+            } else {
+                let c = rest.chars().next().unwrap_or_default();
+                if c == '\n' {
+                    newlines += 1;
+                }
+                pos += c.len_utf8();
+                continue;
+            };
+            let text = rest[..len].trim_end();
+            own_line = own_line || newlines > 0;
+            if own_line {
+                trivia[i].leading.push(Comment {
+                    text,
+                    blank_before: newlines > 1,
+                });
+            } else {
+                trivia[i - 1].trailing.push(text);
+            }
+            newlines = 0;
+            pos += len;
+        }
+        trivia[i].blank_before = newlines > 1;
+        start = tokens.get(i).map_or(source.len(), |(_, span)| span.end);
+    }
+    trivia
+}
+
+/// Whether two tokens say the same thing, however they are spelled.
+fn same_token(a: &Token, b: &Token) -> bool {
+    match (a, b) {
+        (Token::String(a), Token::String(b)) => string_meaning(a) == string_meaning(b),
+        (Token::Charlist(a), Token::Charlist(b)) => process_escapes(a) == process_escapes(b),
+        (Token::Atom(a) | Token::QuotedAtom(a), Token::Atom(b) | Token::QuotedAtom(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn string_meaning(raw: &str) -> Vec<LexStringPart> {
+    if !has_interpolation(raw) {
+        return vec![LexStringPart::Literal(process_escapes(raw))];
+    }
+    parse_interpolated_string(raw)
+        .into_iter()
+        .map(|part| match part {
+            LexStringPart::Interpolation(e) => {
+                LexStringPart::Interpolation(e.chars().filter(|c| !c.is_whitespace()).collect())
+            }
+            literal => literal,
+        })
+        .collect()
+}
+
+/// For each source token, the printed token it became, if any. The two
+/// streams differ only in punctuation the printer adds or drops, so a
+/// short look-ahead finds where they agree again.
+fn align(
+    source: &[(Token, Range<usize>)],
+    printed: &[(Token, Range<usize>)],
+) -> Vec<Option<usize>> {
+    const LOOKAHEAD: usize = 8;
+    let agree = |i: usize, j: usize| {
+        i < source.len() && j < printed.len() && same_token(&source[i].0, &printed[j].0)
+    };
+
+    let mut aligned = vec![None; source.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < source.len() && j < printed.len() {
+        if agree(i, j) {
+            aligned[i] = Some(j);
+            i += 1;
+            j += 1;
+            continue;
+        }
+        // The nearest point where both streams agree for two tokens
+        let resync = (1..=LOOKAHEAD).find_map(|distance| {
+            (0..=distance)
+                .map(|skip| (skip, distance - skip))
+                .find(|&(a, b)| {
+                    agree(i + a, j + b)
+                        && (i + a + 1 >= source.len()
+                            || j + b + 1 >= printed.len()
+                            || agree(i + a + 1, j + b + 1))
+                })
+        });
+        let (a, b) = resync.unwrap_or((1, 1));
+        i += a;
+        j += b;
+    }
+    aligned
+}
+
+/// Put the comments of `source` into `printed`, its comment-free
+/// formatting, and keep its single blank lines between statements.
+fn weave_comments(source: &str, printed: &str) -> String {
+    let source_tokens = lex(source);
+    let printed_tokens = lex(printed);
+    let mut trivia = trivia(source, &source_tokens);
+    let after_last = trivia.pop().unwrap_or_default();
+    let aligned = align(&source_tokens, &printed_tokens);
+
+    let lines: Vec<&str> = printed.lines().collect();
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(printed.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    let line_of = |offset: usize| line_starts.partition_point(|&start| start <= offset) - 1;
+    let mut first_on_line = vec![None; lines.len()];
+    for (j, (_, span)) in printed_tokens.iter().enumerate() {
+        first_on_line[line_of(span.start)].get_or_insert(j);
+    }
+
+    let mut leading: Vec<Vec<Comment>> = lines.iter().map(|_| Vec::new()).collect();
+    let mut trailing: Vec<Vec<&str>> = lines.iter().map(|_| Vec::new()).collect();
+    let mut blank_before = vec![false; lines.len()];
+    // Source spellings of strings to keep, by line: the byte range in the
+    // printed line and the source text
+    let mut spellings: Vec<Vec<(Range<usize>, &str)>> = lines.iter().map(|_| Vec::new()).collect();
+    // Comments of tokens that were dropped, for the next token that wasn't
+    let mut pending = Vec::new();
+    let mut last_line: Option<usize> = None;
+    for (i, token_trivia) in trivia.into_iter().enumerate() {
+        let Some(j) = aligned[i] else {
+            pending.extend(token_trivia.leading);
+            match last_line {
+                Some(line) => trailing[line].extend(token_trivia.trailing),
+                None => pending.extend(token_trivia.trailing.into_iter().map(|text| Comment {
+                    text,
+                    blank_before: false,
+                })),
+            }
+            continue;
+        };
+        let span = &printed_tokens[j].1;
+        let line = line_of(span.start);
+        leading[line].append(&mut pending);
+        leading[line].extend(token_trivia.leading);
+        trailing[line].extend(token_trivia.trailing);
+        if token_trivia.blank_before && first_on_line[line] == Some(j) {
+            blank_before[line] = true;
+        }
+        let (token, source_span) = &source_tokens[i];
+        if matches!(token, Token::String(_) | Token::Charlist(_))
+            && source[source_span.clone()] != printed[span.clone()]
+        {
+            let start = span.start - line_starts[line];
+            spellings[line].push((start..start + span.len(), &source[source_span.clone()]));
+        }
+        last_line = Some(line);
+    }
+    pending.extend(after_last.leading);
+
+    let mut out = String::new();
+    for (n, line) in lines.iter().enumerate() {
+        if line.is_empty() {
+            blank_line(&mut out);
+            continue;
+        }
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+        let closer = trimmed.starts_with([')', ']', '}']);
+        // Comments before a closing delimiter belong to the body it closes
+        let comment_indent = if closer { indent + INDENT } else { indent };
+        for comment in &leading[n] {
+            if comment.blank_before {
+                blank_line(&mut out);
+            }
+            out.push_str(&pad(comment_indent));
+            out.push_str(comment.text);
+            out.push('\n');
+        }
+        if blank_before[n] && !closer {
+            blank_line(&mut out);
+        }
+        let mut line = line.to_string();
+        for (range, spelling) in spellings[n].iter().rev() {
+            line.replace_range(range.clone(), spelling);
+        }
+        for comment in &trailing[n] {
+            line.push(' ');
+            line.push_str(comment);
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    for comment in pending {
+        if comment.blank_before {
+            blank_line(&mut out);
+        }
+        out.push_str(comment.text);
+        out.push('\n');
+    }
+    out
+}
+
+/// Add a blank line, unless it would be the first line, follow another
+/// blank line or follow an opening delimiter.
+fn blank_line(out: &mut String) {
+    let Some(text) = out.strip_suffix('\n') else {
+        return;
+    };
+    let last = text.rsplit('\n').next().unwrap_or_default();
+    if !last.trim().is_empty() && !last.ends_with(['{', '(', '[']) {
+        out.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            format_source(&formatted).unwrap(),
+            formatted,
+            "formatting is not idempotent"
+        );
+        formatted
+    }
+
+    #[test]
+    fn test_canonical_layout() {
+        let source = "pub fn add(a: int,b: int)->int{a+b}\nfn main(){let x=add(1,2);x}";
+        assert_eq!(
+            format(source),
+            "pub fn add(a: int, b: int) -> int {\n    a + b\n}\n\nfn main() {\n    let x = add(1, 2);\n    x\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_comments_are_kept() {
+        let source = "\
+// The entry point
+fn main() {
+    // Say hello
+    let x = 1; // one
+
+    /* twice */
+    x + x
+}
+// The end
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_extern_block() {
+        let source = "extern \"erlang\" {\n    fn lists::reverse<T>(list: [T]) -> [T];\n    #[name = \"is_list\"]\n    fn erlang::is_list(x: any) -> bool;\n}\n";
+        assert_eq!(format(source), source);
+        assert_eq!(
+            format("extern \"erlang\"{fn timer::sleep(ms: int);}"),
+            "extern \"erlang\" {\n    fn timer::sleep(ms: int);\n}\n"
+        );
+        let source = "extern \"nif\" {\n    fn add(a: int, b: int) -> int;\n}\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_uses_stay_together() {
+        let source =
+            "use std::io;\nuse app::{a, b as c};\n\nfn main() {\n    io::println(\"hi\")\n}\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_if_let_and_else_if() {
+        let source = "\
+fn f(x: int) -> int {
+    if let Some(y) = lookup(x) {
+        y
+    } else if x > 0 {
+        1
+    } else {
+        0
+    }
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_with_block() {
+        let source = "\
+fn f(id: int) -> Result<String, Atom> {
+    with {
+        user <- fetch(id)?;
+        Ok(team) <- find_team(user);
+        let name = team.name;
+        log(name);
+        (a, b) <- split(name)?;
+        Ok(a)
+    }
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_gen_block() {
+        let source = "\
+fn numbers(rest: [int]) -> Iter {
+    gen {
+        yield 0;
+        for n in rest {
+            yield n * 2;
+        }
+        yield -1
+    }
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_match_arms() {
+        let source = "\
+fn f(m: Msg) -> int {
+    match m {
+        Msg::Move { x, .. } => x,
+        Msg::Quit => {
+            stop();
+            0
+        },
+        (a, b) if a > b => a,
+        _ => 0,
+    }
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_assertion_macros() {
+        let source = "\
+fn t() {
+    assert!(x > 1);
+    assert_eq!(f(1), 2, \"f of one\");
+    assert_match!(g(), Some(y) if y > 0);
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_map_keys_keep_their_style() {
+        let source =
+            "fn m() -> map {\n    { name: \"x\", \"content-type\": \"json\", 1 => :one }\n}\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_long_lines_are_broken() {
+        let source = "fn f() { let total = first_function_name(alpha, beta) + second_function_name(gamma, delta) + third(epsilon); total }";
+        assert_eq!(
+            format(source),
+            "\
+fn f() {
+    let total = first_function_name(alpha, beta)
+        + second_function_name(gamma, delta)
+        + third(epsilon);
+    total
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_pipes_and_method_chains() {
+        let source = "\
+fn f(items: [int]) -> [int] {
+    items
+        |> list::filter(|x| { x > 0 })
+        |> list::map(|x| { x * 2 })
+        |> list::reverse()
+        |> list::take(100000000000)
+        |> list::drop(1)
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_precedence_parentheses() {
+        assert_eq!(
+            format("fn f() -> int { (a + b) * -(c - d) - (e - f) }"),
+            "fn f() -> int {\n    (a + b) * -(c - d) - (e - f)\n}\n"
+        );
+        assert_eq!(
+            format("fn f() -> int { ((a - b)) - (c) }"),
+            "fn f() -> int {\n    a - b - c\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_macro() {
+        let source = "fn f(name: string) -> string {\n    format!(\"{} has {:?} {{points}}\", name, 1 + 2)\n}\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_function_clauses() {
+        // Later clauses keep leaving out the types they take from the first
+        let source = "\
+fn fact(0) -> int {
+    1
+}
+
+fn fact(n) when n > 0 {
+    n * fact(n - 1)
+}
+
+fn parse(<<len:8, body:len/binary>>) -> Binary {
+    body
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_tuple_structs() {
+        let source = "\
+pub struct Meters(float);
+
+struct Pair<T>(T, T);
+
+fn add(Meters(a): Meters, Meters(b): Meters) -> Meters {
+    Meters(a + b)
+}
+";
+        assert_eq!(format(source), source);
+        assert_eq!(format("struct Id( int ) ;\n"), "struct Id(int);\n");
+    }
+
+    #[test]
+    fn test_string_spelling_is_kept() {
+        let source = "fn s() -> string {\n    \"tab\\there {{braces}}\"\n}\n";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_block_statement_semicolons() {
+        let source = "\
+fn f(x: bool) {
+    if x {
+        a();
+    };
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_cfg_statements_and_arms() {
+        let source = "\
+fn target() -> string {
+    #[cfg(test)]
+    log(\"checking\");
+    #[cfg(target = \"beam\")]
+    \"beam\"
+    #[cfg(not(target = \"beam\"))]
+    \"other\"
+}
+
+fn f(m: Msg) -> int {
+    match m {
+        #[cfg(feature = \"debug\")]
+        Msg::Dump => 1,
+        _ => 0,
+    }
+}
+";
+        assert_eq!(format(source), source);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(matches!(
+            format_source("fn broken( {"),
+            Err(FormatError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn test_format_declaration() {
+        let module = Parser::new(
+            "#[derive(Debug)]\npub struct Point { x: int, y: int }\n\
+             pub fn norm<T: Num>(p: Point, scale: T) -> int { p.x * p.x }\n\
+             trait Shape { fn area(self) -> float; fn name(self) -> string { \"shape\" } }\n",
+        )
+        .parse_file("shapes")
+        .unwrap();
+        // The prelude's `Option` and `Result` come first
+        let declarations: Vec<String> =
+            module.items.iter().skip(2).map(format_declaration).collect();
+        assert_eq!(
+            declarations,
+            [
+                "pub struct Point {\n    x: int,\n    y: int,\n}",
+                "pub fn norm<T: Num>(p: Point, scale: T) -> int",
+                "trait Shape {\n    fn area(self) -> float;\n    fn name(self) -> string;\n}",
+            ]
+        );
+    }
+}
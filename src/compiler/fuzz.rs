@@ -0,0 +1,109 @@
+//! Entry points for fuzzing the lexer and parser.
+//!
+//! Each takes arbitrary bytes and throws away what it computes: all that
+//! matters is that no input makes it panic or overflow the stack. The
+//! cargo-fuzz targets in `fuzz/` call these; see the README for running
+//! them.
+
+use crate::compiler::lexer::Lexer;
+use crate::compiler::parser::Parser;
+
+/// Lex `data`, if it is UTF-8.
+pub fn fuzz_lex(data: &[u8]) {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    Lexer::new(source).for_each(drop);
+}
+
+/// Parse `data`, if it is UTF-8, the ways the compiler and the formatter
+/// do: as the modules of a file, recovering from errors, and as a source
+/// file keeping its comments and spelling.
+pub fn fuzz_parse(data: &[u8]) {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = Parser::new(source).parse_file_modules("fuzz");
+    let _ = Parser::new(source).parse_source_file();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_input() {
+        let inputs: &[&[u8]] = &[
+            b"",
+            b"\xff\xfe",
+            b"fn",
+            b"fn main(",
+            b"fn main() -> { let = ; }",
+            b"\"unterminated",
+            b"\"{\"",
+            b"\"{}\"",
+            b"\"{1 2}\"",
+            b"'",
+            b":'",
+            b"99999999999999999999999999",
+            b"#[",
+            b"#[a(",
+            b"use a::{",
+            b"mod m { mod",
+            b"impl",
+            b"match x { Some(",
+            b"<<1:",
+            b"}}}}",
+        ];
+        for input in inputs {
+            fuzz_lex(input);
+            fuzz_parse(input);
+        }
+    }
+
+    #[test]
+    fn test_every_prefix() {
+        let source = "use std::io;\n\n\
+                      #[derive(Debug)]\n\
+                      struct Point { x: int, y: int }\n\n\
+                      enum Shape { Circle(int), Square { side: int } }\n\n\
+                      /// Area\n\
+                      pub fn area(shape: Shape) -> int {\n    \
+                          match shape {\n        \
+                              Shape::Circle(r) if r > 0 => 3 * r * r,\n        \
+                              Shape::Square { side } => side * side,\n        \
+                              _ => 0,\n    \
+                          }\n\
+                      }\n\n\
+                      fn main() {\n    \
+                          let p = Point { x: 1, y: -2 };\n    \
+                          let bits = <<1:8, 2:16/little>>;\n    \
+                          let xs = [x * 2 for x in [1, 2, 3]];\n    \
+                          io::format(\"{p.x} and {xs}\\n\", []) |> drop();\n\
+                      }\n";
+        for end in (0..=source.len()).filter(|&end| source.is_char_boundary(end)) {
+            fuzz_parse(source[..end].as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // Run on a stack the size of the main thread's, which the compiler
+        // runs on, rather than the test harness's smaller one
+        let parse = |source: String| {
+            std::thread::Builder::new()
+                .stack_size(8 * 1024 * 1024)
+                .spawn(move || {
+                    fuzz_parse(source.as_bytes());
+                    Parser::new(&source).parse_expr().map(drop)
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        for open in ["(", "[", "{", "!", "-"] {
+            assert!(parse(open.repeat(10_000)).is_err(), "{}", open);
+        }
+        assert!(parse(format!("{}1{}", "(".repeat(20), ")".repeat(20))).is_ok());
+    }
+}
@@ -0,0 +1,303 @@
+//! Internal compiler errors: a panic in the compiler reported as a
+//! diagnostic asking for a bug report, rather than a crash.
+//!
+//! The phases keep a note of where they are: [`phase`] and [`module`] as
+//! a phase starts on a module, [`item`] as it starts on a function. The
+//! note lives in a thread-local and each returns a [`Scope`] that puts the
+//! previous one back when dropped, so it costs nothing unless something
+//! goes wrong. [`catch`] runs the CLI, a REPL line or a language server
+//! request, and turns a panic inside into an [`InternalCompilerError`]
+//! carrying the compiler version, the phase and the source of the item
+//! being compiled when it happened.
+
+use crate::compiler::diagnostics::ErrorCode;
+use crate::compiler::lexer::Span;
+use crate::compiler::Module;
+use crate::timings::Phase;
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceCode};
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Once;
+use thiserror::Error;
+
+/// Where the repository takes bug reports.
+const ISSUES: &str = "https://github.com/scrogson/dream/issues";
+
+/// A panic in the compiler, with what it was doing at the time.
+#[derive(Error, Debug)]
+#[error("internal compiler error: {message}")]
+pub struct InternalCompilerError {
+    /// What the panic said
+    pub message: String,
+    /// Where in the compiler's own source it panicked
+    pub location: Option<String>,
+    pub phase: Option<Phase>,
+    pub module: Option<String>,
+    pub file: Option<PathBuf>,
+    /// The module's source, when the phase had it
+    pub src: Option<NamedSource<String>>,
+    /// The item being compiled, within `src`
+    pub span: Option<Span>,
+}
+
+impl InternalCompilerError {
+    fn new(message: String, location: Option<String>, context: Context) -> Self {
+        let name = context
+            .file
+            .as_ref()
+            .map(|file| file.display().to_string())
+            .or_else(|| context.module.clone())
+            .unwrap_or_default();
+        // A span from another file, such as the prelude's, is no use here
+        let span = context
+            .span
+            .filter(|span| context.source.as_ref().is_some_and(|source| span.end <= source.len()));
+        Self {
+            message,
+            location,
+            phase: context.phase,
+            module: context.module,
+            file: context.file,
+            src: context.source.map(|source| NamedSource::new(name, source.to_string())),
+            span,
+        }
+    }
+
+    /// The source of the module, if known.
+    pub fn source(&self) -> Option<&str> {
+        self.src.as_ref().map(|src| src.inner().as_str())
+    }
+
+    /// What the compiler was doing: "type checking `app`".
+    pub fn doing(&self) -> Option<String> {
+        let doing = match self.phase? {
+            Phase::Lex => "lexing",
+            Phase::Parse => "parsing",
+            Phase::Resolve => "resolving names in",
+            Phase::Typecheck => "type checking",
+            Phase::Codegen => "generating code for",
+            Phase::Erlc => "compiling",
+        };
+        Some(match &self.module {
+            Some(module) => format!("{} `{}`", doing, module),
+            None => doing.to_string(),
+        })
+    }
+
+    /// The notes printed under the help: the version, the phase and where
+    /// the compiler panicked.
+    pub fn notes(&self) -> Vec<String> {
+        let mut version = format!("dream {}", env!("CARGO_PKG_VERSION"));
+        if let Some(doing) = self.doing() {
+            version.push_str(&format!(", while {}", doing));
+        }
+        let mut notes = vec![version];
+        if let Some(location) = &self.location {
+            notes.push(format!("panicked at {}", location));
+        }
+        notes
+    }
+
+    pub fn help(&self) -> String {
+        format!(
+            "this is a bug in the Dream compiler, not in your code. Please report it at {}, \
+             with the code shown cut down as far as it still fails",
+            ISSUES
+        )
+    }
+}
+
+impl Diagnostic for InternalCompilerError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(ErrorCode::InternalError))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let mut footer = self.help();
+        for note in self.notes() {
+            footer.push_str("\nnote: ");
+            footer.push_str(&note);
+        }
+        Some(Box::new(footer))
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.src.as_ref().map(|src| src as &dyn SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let span = self.span.clone()?;
+        let label = LabeledSpan::new_primary_with_span(Some("while compiling this".to_string()), span);
+        Some(Box::new(std::iter::once(label)))
+    }
+}
+
+/// What the compiler is doing on this thread.
+#[derive(Debug, Clone, Default)]
+struct Context {
+    phase: Option<Phase>,
+    module: Option<String>,
+    file: Option<PathBuf>,
+    source: Option<Rc<str>>,
+    span: Option<Span>,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Context> = RefCell::default();
+    /// How many [`catch`]es are running, so panics outside one are left
+    /// to the default hook
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    /// The error for the panic being unwound to a [`catch`]
+    static PANIC: RefCell<Option<InternalCompilerError>> = const { RefCell::new(None) };
+}
+
+/// Restores the context from before it was made when dropped.
+#[must_use]
+pub struct Scope {
+    previous: Option<Context>,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            CONTEXT.with(|context| *context.borrow_mut() = previous);
+        }
+    }
+}
+
+fn scope(update: impl FnOnce(&mut Context)) -> Scope {
+    CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        let previous = context.clone();
+        update(&mut context);
+        Scope { previous: Some(previous) }
+    })
+}
+
+/// Note that `phase` is running, over `module` or over every module.
+pub fn phase(phase: Phase, module: Option<&str>) -> Scope {
+    scope(|context| {
+        if context.module.as_deref() != module {
+            *context = Context {
+                module: module.map(str::to_string),
+                ..Context::default()
+            };
+        }
+        context.phase = Some(phase);
+    })
+}
+
+/// Note that `phase` is running over `module`, whose source is at hand.
+pub fn module(phase: Phase, module: &Module) -> Scope {
+    scope(|context| {
+        *context = Context {
+            phase: Some(phase),
+            module: Some(module.name.clone()),
+            file: module.source_path.clone(),
+            source: module.source.as_deref().map(Rc::from),
+            span: None,
+        };
+    })
+}
+
+/// Note that the phase is on the item at `span` of the current module.
+pub fn item(span: &Span) -> Scope {
+    scope(|context| context.span = Some(span.clone()))
+}
+
+/// Run `f`, turning a panic into an [`InternalCompilerError`].
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Box<InternalCompilerError>> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(install_hook);
+
+    CATCHING.with(|catching| catching.set(catching.get() + 1));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|catching| catching.set(catching.get() - 1));
+    result.map_err(|payload| {
+        Box::new(
+            PANIC
+                .with(|error| error.borrow_mut().take())
+                .unwrap_or_else(|| InternalCompilerError::new(message(&*payload), None, Context::default())),
+        )
+    })
+}
+
+/// Replace the panic hook with one that, inside a [`catch`], records the
+/// panic with its context instead of printing it. The context has to be
+/// taken here: by the time `catch` sees the panic, unwinding has dropped
+/// every [`Scope`].
+fn install_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if CATCHING.with(Cell::get) == 0 {
+            return default(info);
+        }
+        let context = CONTEXT
+            .with(|context| context.try_borrow().map(|context| context.clone()))
+            .unwrap_or_default();
+        let location = info.location().map(ToString::to_string);
+        let error = InternalCompilerError::new(message(info.payload()), location, context);
+        PANIC.with(|panic| *panic.borrow_mut() = Some(error));
+    }));
+}
+
+/// The message a panic was raised with.
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch() {
+        assert_eq!(catch(|| 42).unwrap(), 42);
+
+        let source = "fn main() -> int {\n    1\n}\n";
+        let mut module = crate::compiler::Parser::new(source).parse_file("app").unwrap();
+        module.source = Some(source.to_string());
+        let error = catch(|| {
+            let _module = self::module(Phase::Typecheck, &module);
+            let _item = item(&(0..source.len() - 1));
+            let items: Vec<u8> = Vec::new();
+            items[1]
+        })
+        .unwrap_err();
+
+        assert!(error.message.contains("index out of bounds"), "{}", error.message);
+        assert!(error.location.as_deref().is_some_and(|l| l.contains("ice.rs")));
+        assert_eq!(error.doing().as_deref(), Some("type checking `app`"));
+        assert_eq!(error.source(), Some(source));
+        assert_eq!(error.span, Some(0..source.len() - 1));
+        assert!(error.notes()[0].starts_with("dream "));
+
+        // The scopes were dropped while unwinding
+        let error = catch(|| panic!("again")).unwrap_err();
+        assert_eq!(error.message, "again");
+        assert_eq!(error.phase, None);
+        assert_eq!(error.src.map(|src| src.name().to_string()), None);
+    }
+
+    #[test]
+    fn test_phase_scope() {
+        let error = catch(|| {
+            let _phase = phase(Phase::Codegen, Some("app"));
+            let _other = phase(Phase::Erlc, None);
+            panic!("{}", 1)
+        })
+        .unwrap_err();
+        assert_eq!(error.message, "1");
+        assert_eq!(error.doing().as_deref(), Some("compiling"));
+    }
+}
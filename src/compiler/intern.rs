@@ -0,0 +1,79 @@
+//! Interned strings.
+//!
+//! A [`Symbol`] stands for a string in an [`Interner`], so copying one is
+//! copying an integer and comparing two is comparing integers. The type
+//! checker keys its scopes by them: a scope is copied for every block and
+//! function it checks, and copying `String` keys each time was much of
+//! what it allocated.
+//!
+//! Each run of the type checker has an interner of its own, shared by all
+//! of its scopes and dropped with them, so the language server, which
+//! checks what is typed into it for as long as it runs, doesn't keep every
+//! name it has ever seen. A symbol only means something to the interner
+//! that made it.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// An interned string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// The strings behind a set of [`Symbol`]s.
+#[derive(Debug, Default)]
+pub struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The symbol for `string`, interning it if it is new.
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(string) {
+            return symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let string: Rc<str> = Rc::from(string);
+        self.strings.push(Rc::clone(&string));
+        self.symbols.insert(string, symbol);
+        symbol
+    }
+
+    /// The symbol for `string` if it has been interned. Nothing can be
+    /// keyed by a string that never was, so lookups use this rather than
+    /// [`Interner::intern`].
+    pub fn lookup(&self, string: &str) -> Option<Symbol> {
+        self.symbols.get(string).copied()
+    }
+
+    /// The string `symbol` stands for.
+    ///
+    /// # Panics
+    ///
+    /// If `symbol` was made by another interner with more strings.
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.strings[symbol.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let mut interner = Interner::new();
+        let a = interner.intern("a");
+        assert_eq!(a, interner.intern("a"));
+        assert_ne!(a, interner.intern("b"));
+        assert_eq!(interner.resolve(a), "a");
+
+        assert_eq!(interner.lookup("a"), Some(a));
+        assert_eq!(interner.lookup("never"), None);
+        assert_eq!(Interner::new().lookup("a"), None);
+    }
+}
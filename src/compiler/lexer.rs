@@ -1,7 +1,11 @@
 //! Lexer wrapper with span tracking.
+//!
+//! [`TokenCache`] keeps the tokens of a [`Text`] that is being edited,
+//! re-lexing only the stretch each edit touches.
 
 use logos::{Logos, SpannedIter};
 
+use crate::compiler::text::Text;
 use crate::compiler::token::Token;
 
 /// A span in the source code.
@@ -44,6 +48,115 @@ impl<'source> Lexer<'source> {
     }
 }
 
+/// How many bytes the first re-lex after an edit reads past it. Each
+/// time that is not enough to find where the tokens line up with the old
+/// ones again, it doubles.
+const RELEX_WINDOW: usize = 1024;
+
+/// How close to the end of the stretch being re-lexed a token may end and
+/// still be trusted. One that ends closer might have been longer had the
+/// lexer seen more.
+const RELEX_MARGIN: usize = 64;
+
+/// The tokens of a [`Text`], kept up to date through edits.
+///
+/// Logos lexes each token afresh from where the last one ended, so once a
+/// token after an edit starts where one did before it, shifted by the
+/// edit, every token from there on is the old one shifted. An edit is
+/// re-lexed from a token before it until that happens. Tokens just before
+/// an edit can depend on what follows them, such as `x` becoming `xy`,
+/// so re-lexing starts a token earlier than the edit needs. Invalid input,
+/// such as an unterminated string, can depend on everything after it, so
+/// while there is any the whole text is lexed again.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenCache {
+    tokens: Vec<SpannedToken>,
+    /// Whether the text has input that does not lex
+    invalid: bool,
+}
+
+impl TokenCache {
+    pub fn new(text: &Text) -> Self {
+        let source = text.to_string();
+        let mut cache = Self::default();
+        for (result, span) in Token::lexer(&source).spanned() {
+            match result {
+                Ok(token) => cache.tokens.push(SpannedToken { token, span }),
+                Err(()) => cache.invalid = true,
+            }
+        }
+        cache
+    }
+
+    pub fn tokens(&self) -> &[SpannedToken] {
+        &self.tokens
+    }
+
+    /// Update the tokens for the bytes `old` of the text having been
+    /// replaced by `inserted` bytes, making it `text`.
+    pub fn edit(&mut self, text: &Text, old: Span, inserted: usize) {
+        if self.invalid {
+            *self = Self::new(text);
+            return;
+        }
+        let shift = |offset: usize| offset + inserted - old.len();
+        // Where the edited text ends, in the new text
+        let edit_end = old.start + inserted;
+
+        let first = self
+            .tokens
+            .partition_point(|t| t.span.end < old.start)
+            .saturating_sub(1);
+        let start = self.tokens.get(first).map_or(0, |t| t.span.start).min(old.start);
+        // The old tokens the new ones might line up with
+        let after = self.tokens.partition_point(|t| t.span.start < old.end);
+
+        let mut window = RELEX_WINDOW;
+        loop {
+            let mut end = (edit_end + window).min(text.len());
+            while !text.is_char_boundary(end) {
+                end += 1;
+            }
+            let at_end = end == text.len();
+            let source = text.slice(start..end);
+
+            let mut relexed = Vec::new();
+            let mut invalid = false;
+            let mut synced = None;
+            for (result, span) in Token::lexer(&source).spanned() {
+                let span = start + span.start..start + span.end;
+                if !at_end && span.end + RELEX_MARGIN > end {
+                    break;
+                }
+                if span.start >= edit_end {
+                    let found = self.tokens[after..].binary_search_by_key(&span.start, |t| shift(t.span.start));
+                    if let Ok(i) = found {
+                        synced = Some(after + i);
+                        break;
+                    }
+                }
+                match result {
+                    Ok(token) => relexed.push(SpannedToken { token, span }),
+                    Err(()) => invalid = true,
+                }
+            }
+
+            // Invalid input may only be a string the window cut short
+            if (synced.is_none() || invalid) && !at_end {
+                window *= 2;
+                continue;
+            }
+            let synced = synced.unwrap_or(self.tokens.len());
+            for token in &mut self.tokens[synced..] {
+                token.span = shift(token.span.start)..shift(token.span.end);
+            }
+            self.tokens.splice(first..synced, relexed);
+            self.invalid = invalid;
+            return;
+        }
+    }
+}
+
 impl Iterator for Lexer<'_> {
     type Item = SpannedToken;
 
@@ -82,6 +195,30 @@ mod tests {
         assert_eq!(tok.span, 3..6);
     }
 
+    #[test]
+    fn test_token_cache() {
+        let mut source = "fn main() {\n    let x = 1;\n}\n".repeat(200);
+        let mut text = Text::new(&source);
+        let mut cache = TokenCache::new(&text);
+
+        let edits: [(Span, &str); 8] = [
+            (3..7, "start"),
+            (20..20, "y"),
+            (0..0, "/// docs\n"),
+            (500..500, " \"a string\" "),
+            (1000..1012, ""),
+            (2000..2000, "\""),
+            (2100..2100, "\""),
+            (4000..4003, "fn z() -> int { 2 }"),
+        ];
+        for (old, inserted) in edits {
+            source.replace_range(old.clone(), inserted);
+            text.replace(old.clone(), inserted);
+            cache.edit(&text, old, inserted.len());
+            assert_eq!(cache, TokenCache::new(&text), "after inserting {:?}", inserted);
+        }
+    }
+
     #[test]
     fn test_collect_tokens() {
         let source = "let x = 42;";
@@ -0,0 +1,1136 @@
+//! Lints: checks for code that compiles but is probably not what was meant.
+//!
+//! `dream lint` runs every [`Lint`] over the type-checked modules. Each lint
+//! has a default [`LintLevel`]; the `[lints]` table of dream.toml and the
+//! `-A`/`-W`/`-D` flags change levels for the whole run, and `#[allow(..)]`,
+//! `#[warn(..)]` and `#[deny(..)]` on a function change them inside it.
+
+use super::ast::*;
+use super::diagnostics::{Applicability, Suggestion};
+use super::lexer::Span;
+use super::token::Token;
+use crate::analysis::find_word;
+use logos::Logos;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// A lint rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Lint {
+    UnusedVariables,
+    UnusedImports,
+    Shadowing,
+    NonTailRecursion,
+    SingleWildcardMatch,
+    DivisionByZero,
+    UnknownLints,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 7] = [
+        Lint::UnusedVariables,
+        Lint::UnusedImports,
+        Lint::Shadowing,
+        Lint::NonTailRecursion,
+        Lint::SingleWildcardMatch,
+        Lint::DivisionByZero,
+        Lint::UnknownLints,
+    ];
+
+    /// The name used in attributes, flags and dream.toml.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedImports => "unused_imports",
+            Lint::Shadowing => "shadowing",
+            Lint::NonTailRecursion => "non_tail_recursion",
+            Lint::SingleWildcardMatch => "single_wildcard_match",
+            Lint::DivisionByZero => "division_by_zero",
+            Lint::UnknownLints => "unknown_lints",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+
+    pub fn default_level(self) -> LintLevel {
+        match self {
+            Lint::Shadowing => LintLevel::Allow,
+            Lint::DivisionByZero => LintLevel::Deny,
+            _ => LintLevel::Warn,
+        }
+    }
+
+    /// One line on what the lint looks for, for `dream lint --list`.
+    pub fn description(self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "variables that are bound but never used",
+            Lint::UnusedImports => "`use` declarations whose names are never used",
+            Lint::Shadowing => "`let` bindings that hide a variable of the same name",
+            Lint::NonTailRecursion => {
+                "recursive calls whose result is discarded, so the recursion is not a tail call"
+            }
+            Lint::SingleWildcardMatch => "`match` expressions with a single catch-all arm",
+            Lint::DivisionByZero => "division or remainder by a literal zero",
+            Lint::UnknownLints => "lint attributes naming a lint that does not exist",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What to do when a lint fires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl LintLevel {
+    pub fn from_name(name: &str) -> Option<LintLevel> {
+        match name {
+            "allow" => Some(LintLevel::Allow),
+            "warn" => Some(LintLevel::Warn),
+            "deny" => Some(LintLevel::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LintLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LintLevel::Allow => "allow",
+            LintLevel::Warn => "warn",
+            LintLevel::Deny => "deny",
+        })
+    }
+}
+
+/// The level of each lint, starting from the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels {
+    levels: HashMap<Lint, LintLevel>,
+    /// Level for every lint that would otherwise warn, from `-D warnings`
+    warnings: Option<LintLevel>,
+}
+
+impl LintLevels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the level of the lint called `name`, or with `warnings`, of
+    /// every lint that would warn.
+    pub fn set(&mut self, name: &str, level: LintLevel) -> Result<(), String> {
+        if name == "warnings" {
+            self.warnings = Some(level);
+            return Ok(());
+        }
+        let lint = Lint::from_name(name).ok_or_else(|| format!("unknown lint `{}`", name))?;
+        self.levels.insert(lint, level);
+        Ok(())
+    }
+
+    pub fn level(&self, lint: Lint) -> LintLevel {
+        let level = self
+            .levels
+            .get(&lint)
+            .copied()
+            .unwrap_or(lint.default_level());
+        match (level, self.warnings) {
+            (LintLevel::Warn, Some(warnings)) => warnings,
+            _ => level,
+        }
+    }
+
+    /// These levels with the lint attributes among `attrs` applied, and
+    /// the names in them that are not lints.
+    fn with_attrs<'a>(&self, attrs: &'a [Attribute]) -> (LintLevels, Vec<(&'a str, Span)>) {
+        let mut levels = self.clone();
+        let mut unknown = Vec::new();
+        for attr in attrs {
+            let Some(level) = LintLevel::from_name(&attr.name) else {
+                continue;
+            };
+            let AttributeArgs::Parenthesized(args) = &attr.args else {
+                continue;
+            };
+            for arg in args {
+                if let AttributeArg::Ident(name) = arg {
+                    if levels.set(name, level).is_err() {
+                        unknown.push((name.as_str(), attr.span.clone()));
+                    }
+                }
+            }
+        }
+        (levels, unknown)
+    }
+}
+
+/// A lint that fired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintDiagnostic {
+    pub lint: Lint,
+    /// `Warn` or `Deny`
+    pub level: LintLevel,
+    pub message: String,
+    pub help: Option<String>,
+    pub module: String,
+    /// Byte range in the module's source
+    pub span: Option<Span>,
+    /// Edits to the module's source that fix it
+    pub suggestions: Vec<Suggestion>,
+}
+
+/// Runs the lints over modules.
+pub struct Linter<'a> {
+    levels: &'a LintLevels,
+    /// Traits of every module seen, whose imports are used by method calls
+    /// rather than by name
+    traits: HashSet<String>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(levels: &'a LintLevels) -> Self {
+        Self {
+            levels,
+            traits: HashSet::new(),
+        }
+    }
+
+    /// Learn the traits of modules that are not linted themselves, such as
+    /// the stdlib.
+    pub fn with_context(mut self, modules: &[Module]) -> Self {
+        self.learn_traits(modules);
+        self
+    }
+
+    /// Lint `modules`, in order.
+    pub fn lint_modules(mut self, modules: &[Module]) -> Vec<LintDiagnostic> {
+        self.learn_traits(modules);
+        modules
+            .iter()
+            .flat_map(|module| self.lint_module(module))
+            .collect()
+    }
+
+    fn learn_traits(&mut self, modules: &[Module]) {
+        for item in modules.iter().flat_map(|module| &module.items) {
+            if let Item::Trait(t) = item {
+                self.traits.insert(t.name.clone());
+            }
+        }
+    }
+
+    pub fn lint_module(&self, module: &Module) -> Vec<LintDiagnostic> {
+        let (levels, unknown) = self.levels.with_attrs(&module.attrs);
+        let mut walker = Walker::new(module, levels);
+        walker.unknown_lints(unknown);
+        walker.unused_imports(module, &self.traits);
+
+        for item in &module.items {
+            match item {
+                Item::Function(f) => walker.function(f),
+                Item::Impl(block) => block.methods.iter().for_each(|f| walker.function(f)),
+                Item::TraitImpl(block) => block.methods.iter().for_each(|f| walker.function(f)),
+                Item::Trait(t) => {
+                    for method in &t.methods {
+                        if let Some(body) = &method.body {
+                            walker.body(&method.name, &method.params, None, body, None);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Generic functions are checked once per instantiation, which can
+        // repeat a finding
+        let mut seen = HashSet::new();
+        walker
+            .diagnostics
+            .into_iter()
+            .filter(|d| seen.insert((d.lint, d.span.clone(), d.message.clone())))
+            .collect()
+    }
+}
+
+/// A variable in scope.
+struct Binding {
+    name: String,
+    span: Option<Span>,
+    used: bool,
+}
+
+/// Walks one module, tracking the variables in scope.
+struct Walker<'m> {
+    module: &'m str,
+    source: Option<&'m str>,
+    /// Levels for the module
+    module_levels: LintLevels,
+    /// Levels inside the current function
+    levels: LintLevels,
+    function: String,
+    scopes: Vec<Vec<Binding>>,
+    /// The statement being walked. Expressions have no spans, so their
+    /// lints point at it.
+    span: Option<Span>,
+    diagnostics: Vec<LintDiagnostic>,
+}
+
+impl<'m> Walker<'m> {
+    fn new(module: &'m Module, levels: LintLevels) -> Self {
+        Self {
+            module: &module.name,
+            source: module.source.as_deref(),
+            module_levels: levels.clone(),
+            levels,
+            function: String::new(),
+            scopes: Vec::new(),
+            span: None,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, lint: Lint, span: Option<Span>, message: String, help: Option<String>) {
+        self.report_fixable(lint, span, message, help, Vec::new());
+    }
+
+    fn report_fixable(
+        &mut self,
+        lint: Lint,
+        span: Option<Span>,
+        message: String,
+        help: Option<String>,
+        suggestions: Vec<Suggestion>,
+    ) {
+        let level = self.levels.level(lint);
+        if level == LintLevel::Allow {
+            return;
+        }
+        self.diagnostics.push(LintDiagnostic {
+            lint,
+            level,
+            message,
+            help,
+            module: self.module.to_string(),
+            span,
+            suggestions,
+        });
+    }
+
+    fn unknown_lints(&mut self, unknown: Vec<(&str, Span)>) {
+        for (name, span) in unknown {
+            self.report(
+                Lint::UnknownLints,
+                Some(span),
+                format!("unknown lint `{}`", name),
+                Some("`dream lint --list` shows every lint".to_string()),
+            );
+        }
+    }
+
+    /// Report imported names that appear nowhere else in the module.
+    fn unused_imports(&mut self, module: &Module, traits: &HashSet<String>) {
+        let Some(source) = self.source else {
+            return;
+        };
+        let (names, import_spans) = source_names(source);
+        for item in &module.items {
+            let Item::Use(decl) = item else { continue };
+            let imported: Vec<(&str, &str)> = match &decl.tree {
+                UseTree::Path { name, rename, .. } => {
+                    vec![(name.as_str(), rename.as_deref().unwrap_or(name))]
+                }
+                UseTree::Group { items, .. } => items
+                    .iter()
+                    .map(|item| {
+                        (
+                            item.name.as_str(),
+                            item.rename.as_deref().unwrap_or(&item.name),
+                        )
+                    })
+                    .collect(),
+                UseTree::Glob { .. } => Vec::new(),
+            };
+            // A trait is used by calling its methods
+            let unused: Vec<&str> = imported
+                .iter()
+                .filter(|(name, local)| !names.contains(*local) && !traits.contains(*name) && *name != "self")
+                .map(|(_, local)| *local)
+                .collect();
+            // Nothing is left of the declaration without them
+            let whole = unused.len() == imported.len();
+            for local in unused {
+                let span = import_spans.get(local).cloned();
+                let suggestions = span
+                    .as_ref()
+                    .and_then(|span| import_removal(source, span, whole))
+                    .map(|removal| Suggestion {
+                        span: removal,
+                        replacement: String::new(),
+                        message: if whole {
+                            "remove the `use` declaration".to_string()
+                        } else {
+                            format!("remove `{}`", local)
+                        },
+                        applicability: Applicability::MachineApplicable,
+                    })
+                    .into_iter()
+                    .collect();
+                self.report_fixable(
+                    Lint::UnusedImports,
+                    span,
+                    format!("unused import `{}`", local),
+                    Some("remove it from the `use` declaration".to_string()),
+                    suggestions,
+                );
+            }
+        }
+    }
+
+    fn function(&mut self, f: &Function) {
+        let (levels, unknown) = self.module_levels.with_attrs(&f.attrs);
+        self.levels = levels;
+        self.unknown_lints(unknown);
+        self.body(
+            &f.name,
+            &f.params,
+            f.guard.as_deref(),
+            &f.body,
+            Some(f.span.clone()),
+        );
+        self.levels = self.module_levels.clone();
+    }
+
+    fn body(
+        &mut self,
+        name: &str,
+        params: &[Param],
+        guard: Option<&Expr>,
+        body: &Block,
+        span: Option<Span>,
+    ) {
+        self.function = name.to_string();
+        self.span = span;
+        self.scopes.push(Vec::new());
+        for param in params {
+            self.bind(&param.pattern, false);
+        }
+        if let Some(guard) = guard {
+            self.expr(guard, false);
+        }
+        self.block(body, false);
+        self.pop_scope();
+    }
+
+    fn block(&mut self, block: &Block, discarded: bool) {
+        let outer = self.span.clone();
+        self.scopes.push(Vec::new());
+        for stmt in &block.stmts {
+            match stmt {
+                Stmt::Let {
+                    pattern,
+                    value,
+                    else_block,
+                    span,
+                    ..
+                } => {
+                    self.span = span.clone().or_else(|| outer.clone());
+                    self.expr(value, false);
+                    if let Some(else_block) = else_block {
+                        self.block(else_block, false);
+                    }
+                    self.bind(pattern, true);
+                }
+                Stmt::Expr { expr, span } => {
+                    self.span = span.clone().or_else(|| outer.clone());
+                    self.expr(expr, true);
+                }
+                // Removed or unwrapped before linting
+                Stmt::Cfg { .. } => {}
+            }
+        }
+        if let Some(tail) = &block.expr {
+            self.expr(tail, discarded);
+        }
+        self.pop_scope();
+        self.span = outer;
+    }
+
+    /// Bring the variables of `pattern` into scope. `let` bindings may
+    /// shadow; match arms and parameters start fresh names by design.
+    fn bind(&mut self, pattern: &Pattern, is_let: bool) {
+        let mut names = Vec::new();
+        pattern_names(pattern, &mut names);
+        for name in names {
+            if is_let && !name.starts_with('_') && self.lookup(name).is_some() {
+                self.report(
+                    Lint::Shadowing,
+                    self.word_span(name),
+                    format!("`{}` shadows an earlier binding", name),
+                    Some("give one of them a different name".to_string()),
+                );
+            }
+            let span = self.word_span(name);
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.push(Binding {
+                    name: name.to_string(),
+                    span,
+                    used: false,
+                });
+            }
+        }
+        // Sizes in bit string patterns can refer to earlier segments
+        if let Pattern::BitString(segments) = pattern {
+            for size in segments
+                .iter()
+                .filter_map(|segment| segment.size.as_deref())
+            {
+                self.expr(size, false);
+            }
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|binding| binding.name == name)
+    }
+
+    fn use_name(&mut self, name: &str) {
+        if let Some(binding) = self.lookup(name) {
+            binding.used = true;
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for binding in scope {
+            if binding.used || binding.name.starts_with('_') || binding.name == "self" {
+                continue;
+            }
+            self.report(
+                Lint::UnusedVariables,
+                binding.span,
+                format!("unused variable `{}`", binding.name),
+                Some(format!(
+                    "if this is intentional, name it `_{}`",
+                    binding.name
+                )),
+            );
+        }
+    }
+
+    /// `word` in the current statement's source.
+    fn word_span(&self, word: &str) -> Option<Span> {
+        let source = self.source?;
+        let span = self.span.clone()?;
+        find_word(source, span, word)
+    }
+
+    fn is_self_call(&self, func: &Expr) -> bool {
+        match func {
+            Expr::Ident(name) => *name == self.function,
+            Expr::Path { segments } => {
+                matches!(segments.as_slice(), [ty, name] if ty == "Self" && *name == self.function)
+            }
+            _ => false,
+        }
+    }
+
+    fn non_tail_call(&mut self) {
+        let name = self.function.clone();
+        self.report(
+            Lint::NonTailRecursion,
+            self.span.clone(),
+            format!("recursive call to `{}` is not in tail position", name),
+            Some(
+                "its result is discarded, so every iteration keeps a stack frame; \
+                 make the call the last expression"
+                    .to_string(),
+            ),
+        );
+    }
+
+    /// Walk an expression. `discarded` is set where its value is thrown
+    /// away: statements, and the tails of blocks that are.
+    fn expr(&mut self, e: &Expr, discarded: bool) {
+        match e {
+            Expr::Ident(name) => self.use_name(name),
+            Expr::Binary { op, left, right, .. } => {
+                if matches!(op, BinOp::Div | BinOp::Mod) && is_zero(right) {
+                    self.division_by_zero();
+                }
+                self.expr(left, false);
+                self.expr(right, false);
+            }
+            Expr::ExternCall {
+                module,
+                function,
+                args,
+            } => {
+                let divides =
+                    module == "erlang" && matches!(function.as_str(), "div" | "rem" | "/");
+                if divides && args.get(1).is_some_and(is_zero) {
+                    self.division_by_zero();
+                }
+                self.exprs(args);
+            }
+            Expr::Call { func, args, .. } => {
+                if discarded && self.is_self_call(func) {
+                    self.non_tail_call();
+                }
+                self.expr(func, false);
+                self.exprs(args);
+            }
+            Expr::Pipe { left, right } => {
+                if discarded
+                    && matches!(&**right, Expr::Call { func, .. } if self.is_self_call(func))
+                {
+                    self.non_tail_call();
+                }
+                self.expr(left, false);
+                self.expr(right, false);
+            }
+            Expr::If {
+                cond,
+                then_block,
+                else_block,
+            } => {
+                self.expr(cond, false);
+                self.block(then_block, discarded);
+                if let Some(else_block) = else_block {
+                    self.block(else_block, discarded);
+                }
+            }
+            Expr::Match { expr, arms } => {
+                if let [arm] = arms.as_slice() {
+                    if arm.guard.is_none()
+                        && matches!(arm.pattern, Pattern::Wildcard | Pattern::Ident(_))
+                    {
+                        self.single_wildcard_match(&arm.pattern);
+                    }
+                }
+                self.expr(expr, false);
+                for arm in arms {
+                    self.arm(arm, discarded);
+                }
+            }
+            Expr::Receive { arms, timeout } => {
+                for arm in arms {
+                    self.arm(arm, discarded);
+                }
+                if let Some((after, block)) = timeout {
+                    self.expr(after, false);
+                    self.block(block, discarded);
+                }
+            }
+            Expr::Block(block) => self.block(block, discarded),
+            Expr::Closure { params, body } => {
+                let scope = params
+                    .iter()
+                    .map(|param| Binding {
+                        name: param.clone(),
+                        span: self.word_span(param),
+                        used: false,
+                    })
+                    .collect();
+                self.scopes.push(scope);
+                self.block(body, false);
+                self.pop_scope();
+            }
+            Expr::SpawnClosure(body) => self.block(body, false),
+            Expr::For {
+                clauses,
+                body,
+                is_comprehension,
+            } => {
+                self.scopes.push(Vec::new());
+                for clause in clauses {
+                    match clause {
+                        ForClause::Generator {
+                            pattern, source, ..
+                        } => {
+                            self.expr(source, false);
+                            self.bind(pattern, false);
+                        }
+                        ForClause::When(cond) => self.expr(cond, false),
+                    }
+                }
+                // A loop throws its body's value away; a comprehension keeps it
+                self.expr(body, !is_comprehension);
+                self.pop_scope();
+            }
+            // A quote's variables are unquoted when the macro expands, so
+            // count everything in scope as used
+            Expr::Quote(_) | Expr::QuoteItem(_) => {
+                for binding in self.scopes.iter_mut().flatten() {
+                    binding.used = true;
+                }
+            }
+            Expr::StringInterpolation(parts) => {
+                for part in parts {
+                    if let StringPart::Expr(e) = part {
+                        self.expr(e, false);
+                    }
+                }
+            }
+            Expr::Format { parts, .. } => {
+                for part in parts {
+                    if let FormatPart::Display(e) | FormatPart::Debug(e) = part {
+                        self.expr(e, false);
+                    }
+                }
+            }
+            Expr::Unary { expr, .. }
+            | Expr::FieldAccess { expr, .. }
+            | Expr::Try { expr }
+            | Expr::Spawn(expr)
+            | Expr::Unquote(expr)
+            | Expr::UnquoteSplice(expr)
+            | Expr::UnquoteAtom(expr) => self.expr(expr, false),
+            Expr::UnquoteFieldAccess { expr, field_expr } => {
+                self.expr(expr, false);
+                self.expr(field_expr, false);
+            }
+            Expr::MethodCall { receiver, args, .. } => {
+                self.expr(receiver, false);
+                self.exprs(args);
+            }
+            Expr::Send { to, msg } => {
+                self.expr(to, false);
+                self.expr(msg, false);
+            }
+            Expr::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value, false);
+                }
+            }
+            Expr::Tuple(items) | Expr::List(items) => self.exprs(items),
+            Expr::ListCons { head, tail } => {
+                self.expr(head, false);
+                self.expr(tail, false);
+            }
+            Expr::StructInit { fields, base, .. } => {
+                for (_, value) in fields {
+                    self.expr(value, false);
+                }
+                if let Some(base) = base {
+                    self.expr(base, false);
+                }
+            }
+            Expr::EnumVariant { args, .. } => match args {
+                EnumVariantArgs::Unit => {}
+                EnumVariantArgs::Tuple(items) => self.exprs(items),
+                EnumVariantArgs::Struct(fields) => {
+                    for (_, value) in fields {
+                        self.expr(value, false);
+                    }
+                }
+            },
+            Expr::MapLiteral(entries) => {
+                for (key, value) in entries {
+                    self.expr(key, false);
+                    self.expr(value, false);
+                }
+            }
+            Expr::BitString(segments) => {
+                for segment in segments {
+                    self.expr(&segment.value, false);
+                    if let Some(size) = &segment.size {
+                        self.expr(size, false);
+                    }
+                }
+            }
+            Expr::QuoteRepetition { pattern, .. } => self.expr(pattern, false),
+            Expr::Int(_)
+            | Expr::String(_)
+            | Expr::Charlist(_)
+            | Expr::Atom(_)
+            | Expr::Bool(_)
+            | Expr::Unit
+            | Expr::Path { .. }
+            | Expr::CompileError { .. } => {}
+        }
+    }
+
+    fn exprs(&mut self, exprs: &[Expr]) {
+        for e in exprs {
+            self.expr(e, false);
+        }
+    }
+
+    fn arm(&mut self, arm: &MatchArm, discarded: bool) {
+        self.scopes.push(Vec::new());
+        self.bind(&arm.pattern, false);
+        if let Some(guard) = &arm.guard {
+            self.expr(guard, false);
+        }
+        self.expr(&arm.body, discarded);
+        self.pop_scope();
+    }
+
+    fn division_by_zero(&mut self) {
+        self.report(
+            Lint::DivisionByZero,
+            self.span.clone(),
+            "division by zero".to_string(),
+            Some("this always fails with `badarith` at runtime".to_string()),
+        );
+    }
+
+    fn single_wildcard_match(&mut self, pattern: &Pattern) {
+        let help = match pattern {
+            Pattern::Ident(name) => format!(
+                "the arm always matches; bind the value with `let {} = ...` instead",
+                name
+            ),
+            _ => "the arm always matches; use its body directly".to_string(),
+        };
+        self.report(
+            Lint::SingleWildcardMatch,
+            self.word_span("match"),
+            "this `match` has a single catch-all arm".to_string(),
+            Some(help),
+        );
+    }
+}
+
+/// The variables a pattern binds, in order.
+fn pattern_names<'p>(pattern: &'p Pattern, names: &mut Vec<&'p str>) {
+    match pattern {
+        Pattern::Ident(name) => names.push(name),
+        Pattern::Tuple(items) | Pattern::List(items) => {
+            items.iter().for_each(|p| pattern_names(p, names))
+        }
+        Pattern::ListCons { head, tail } => {
+            pattern_names(head, names);
+            pattern_names(tail, names);
+        }
+        Pattern::Struct { fields, .. }
+        | Pattern::Enum {
+            fields: EnumPatternFields::Struct(fields),
+            ..
+        } => fields.iter().for_each(|(_, p)| pattern_names(p, names)),
+        Pattern::Enum {
+            fields: EnumPatternFields::Tuple(items),
+            ..
+        } => items.iter().for_each(|p| pattern_names(p, names)),
+        Pattern::BitString(segments) => {
+            segments.iter().for_each(|s| pattern_names(&s.value, names))
+        }
+        _ => {}
+    }
+}
+
+fn is_zero(e: &Expr) -> bool {
+    match e {
+        Expr::Int(0) => true,
+        Expr::Unary {
+            op: UnaryOp::Neg,
+            expr,
+        } => is_zero(expr),
+        _ => false,
+    }
+}
+
+/// The names a module's source mentions outside `use` declarations, and
+/// where each name a `use` declaration brings in is written.
+fn source_names(source: &str) -> (HashSet<String>, HashMap<String, Span>) {
+    let mut names = HashSet::new();
+    let mut imports = HashMap::new();
+    let mut in_use = false;
+    for (token, span) in Token::lexer(source).spanned() {
+        match token {
+            Ok(Token::Use) => in_use = true,
+            Ok(Token::Semi) => in_use = false,
+            Ok(Token::Ident(name) | Token::TypeIdent(name)) if in_use => {
+                imports.insert(name, span);
+            }
+            Ok(Token::Ident(name) | Token::TypeIdent(name)) => {
+                names.insert(name);
+            }
+            _ => {}
+        }
+    }
+    (names, imports)
+}
+
+/// What to delete to remove the import at `span`: with `whole`, its
+/// entire `use` declaration, and the line it is on if it has nothing else;
+/// otherwise just its item in the group, and the comma separating it from
+/// the next item, or from the previous one if it is last.
+fn import_removal(source: &str, span: &Span, whole: bool) -> Option<Span> {
+    let mut tokens = Token::lexer(source).spanned().filter_map(|(token, span)| Some((token.ok()?, span)));
+    let decl: Vec<(Token, Span)> = loop {
+        let (token, start) = tokens.next()?;
+        if token != Token::Use {
+            continue;
+        }
+        let mut decl = vec![(token, start)];
+        for (token, span) in tokens.by_ref() {
+            let end = token == Token::Semi;
+            decl.push((token, span));
+            if end {
+                break;
+            }
+        }
+        if decl[0].1.start <= span.start && span.end <= decl[decl.len() - 1].1.end {
+            break decl;
+        }
+    };
+
+    let group = decl.iter().position(|(token, _)| *token == Token::LBrace);
+    if whole || group.is_none() {
+        let start = decl[0].1.start;
+        let end = decl[decl.len() - 1].1.end;
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[end..].find('\n').map_or(source.len(), |i| end + i + 1);
+        let before = source[line_start..start].trim_end();
+        if before.is_empty() && source[end..line_end].trim().is_empty() {
+            // A blank line left at the top of the file, or after another,
+            // goes too
+            let after_blank = line_start == 0 || source[..line_start].ends_with("\n\n");
+            if after_blank && source[line_end..].starts_with('\n') {
+                return Some(line_start..line_end + 1);
+            }
+            return Some(line_start..line_end);
+        }
+        return Some(line_start + before.len()..end);
+    }
+
+    // The items of the group, as the spans from their first token to their
+    // last
+    let mut items: Vec<Span> = Vec::new();
+    let mut item: Option<Span> = None;
+    for (token, token_span) in &decl[group? + 1..] {
+        match token {
+            Token::Comma | Token::RBrace => items.extend(item.take()),
+            _ => {
+                item = Some(match item {
+                    Some(item) => item.start..token_span.end,
+                    None => token_span.clone(),
+                })
+            }
+        }
+    }
+    let i = items.iter().position(|item| item.start <= span.start && span.end <= item.end)?;
+    Some(match (items.get(i + 1), i.checked_sub(1)) {
+        (Some(next), _) => items[i].start..next.start,
+        (None, Some(previous)) => items[previous].end..items[i].end,
+        (None, None) => items[i].clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Parser;
+
+    fn lint_with(source: &str, levels: &LintLevels) -> Vec<(Lint, LintLevel, String)> {
+        let module = Parser::new(source).parse_file("app").unwrap();
+        Linter::new(levels)
+            .lint_modules(&[module])
+            .into_iter()
+            .map(|d| (d.lint, d.level, d.message))
+            .collect()
+    }
+
+    fn lint(source: &str) -> Vec<(Lint, LintLevel, String)> {
+        lint_with(source, &LintLevels::new())
+    }
+
+    #[test]
+    fn test_unused_variables() {
+        let found =
+            lint("fn f(a: int, _b: int) -> int {\n    let c = 1;\n    let d = 2;\n    d\n}\n");
+        assert_eq!(
+            found,
+            [
+                (
+                    Lint::UnusedVariables,
+                    LintLevel::Warn,
+                    "unused variable `c`".to_string()
+                ),
+                (
+                    Lint::UnusedVariables,
+                    LintLevel::Warn,
+                    "unused variable `a`".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_variables_used_in_closures_and_strings() {
+        let source = "fn f(xs: [int], n: int) -> string {\n    let m = list::map(xs, |x| { x * n });\n    \"{m}\"\n}\n";
+        assert!(lint(source).is_empty());
+    }
+
+    #[test]
+    fn test_unused_variable_span() {
+        let source = "fn f() -> int {\n    let unused = 1;\n    2\n}\n";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let found = Linter::new(&LintLevels::new()).lint_modules(&[module]);
+        let span = found[0].span.clone().unwrap();
+        assert_eq!(&source[span], "unused");
+    }
+
+    #[test]
+    fn test_unused_imports() {
+        let source = "use std::list::{map, filter};\nuse std::io as console;\n\nfn f(xs: [int]) -> [int] {\n    map(xs, |x| { x })\n}\n";
+        let found = lint(source);
+        assert_eq!(
+            found,
+            [
+                (
+                    Lint::UnusedImports,
+                    LintLevel::Warn,
+                    "unused import `filter`".to_string()
+                ),
+                (
+                    Lint::UnusedImports,
+                    LintLevel::Warn,
+                    "unused import `console`".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unused_import_fixes() {
+        let fixed = |source: &str| {
+            let module = Parser::new(source).parse_file("app").unwrap();
+            let mut fixes: Vec<Suggestion> = Linter::new(&LintLevels::new())
+                .lint_modules(&[module])
+                .into_iter()
+                .flat_map(|d| d.suggestions)
+                .collect();
+            assert!(fixes.iter().all(Suggestion::is_machine_applicable));
+            fixes.sort_by_key(|fix| std::cmp::Reverse(fix.span.start));
+            // Every import of a declaration that goes removes all of it
+            fixes.dedup_by(|a, b| a.span == b.span);
+            let mut source = source.to_string();
+            for fix in fixes {
+                source.replace_range(fix.span, &fix.replacement);
+            }
+            source
+        };
+        let body = "\nfn f(xs: [int]) -> [int] {\n    map(xs, |x| { x })\n}\n";
+        assert_eq!(
+            fixed(&format!("use std::list::{{map, filter}};\nuse std::io as console;\n{}", body)),
+            format!("use std::list::{{map}};\n{}", body)
+        );
+        assert_eq!(
+            fixed(&format!("use std::list::{{filter, map, sort}};\n{}", body)),
+            format!("use std::list::{{map}};\n{}", body)
+        );
+        assert_eq!(
+            fixed(&format!("use std::list::{{map}}; use std::list::{{filter, sort}};\n{}", body)),
+            format!("use std::list::{{map}};\n{}", body)
+        );
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let source = "fn f(x: int) -> int {\n    let x = x + 1;\n    x\n}\n";
+        assert!(lint(source).is_empty());
+
+        let mut levels = LintLevels::new();
+        levels.set("shadowing", LintLevel::Warn).unwrap();
+        assert_eq!(
+            lint_with(source, &levels),
+            [(
+                Lint::Shadowing,
+                LintLevel::Warn,
+                "`x` shadows an earlier binding".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_non_tail_recursion() {
+        let source = "fn loop(n: int) -> int {\n    if n > 0 {\n        loop(n - 1);\n    }\n    n\n}\n\nfn count(n: int) -> int {\n    if n > 0 { count(n - 1) } else { 0 }\n}\n";
+        assert_eq!(
+            lint(source),
+            [(
+                Lint::NonTailRecursion,
+                LintLevel::Warn,
+                "recursive call to `loop` is not in tail position".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_single_wildcard_match() {
+        let source = "fn f(x: int) -> int {\n    match x {\n        _ => 1,\n    }\n}\n";
+        assert_eq!(
+            lint(source),
+            [(
+                Lint::SingleWildcardMatch,
+                LintLevel::Warn,
+                "this `match` has a single catch-all arm".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        let source = "fn f(x: int) -> int {\n    x / 0 + x % 2\n}\n";
+        assert_eq!(
+            lint(source),
+            [(
+                Lint::DivisionByZero,
+                LintLevel::Deny,
+                "division by zero".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        let source = "#[allow(unused_variables)]\nfn f(a: int) -> int {\n    1\n}\n\n#[deny(unused_variables, no_such_lint)]\nfn g(b: int) -> int {\n    1\n}\n";
+        assert_eq!(
+            lint(source),
+            [
+                (
+                    Lint::UnknownLints,
+                    LintLevel::Warn,
+                    "unknown lint `no_such_lint`".to_string()
+                ),
+                (
+                    Lint::UnusedVariables,
+                    LintLevel::Deny,
+                    "unused variable `b`".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_levels() {
+        let mut levels = LintLevels::new();
+        levels.set("warnings", LintLevel::Deny).unwrap();
+        assert_eq!(levels.level(Lint::UnusedVariables), LintLevel::Deny);
+        assert_eq!(levels.level(Lint::Shadowing), LintLevel::Allow);
+
+        levels.set("unused_variables", LintLevel::Allow).unwrap();
+        assert_eq!(levels.level(Lint::UnusedVariables), LintLevel::Allow);
+        assert_eq!(
+            levels.set("nope", LintLevel::Warn),
+            Err("unknown lint `nope`".to_string())
+        );
+    }
+}
@@ -6,15 +6,24 @@ use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use crate::compiler::ast::{Item, ModDecl, Module};
+use crate::compiler::ast::{Item, ModDecl, Module, ModuleContext, UseTree};
+use crate::compiler::diagnostics::{did_you_mean, ErrorCode};
+use crate::compiler::error::ParseError;
 use crate::compiler::parser::Parser;
 use crate::config::ProjectConfig;
+use crate::timings::{self, Phase};
 
 /// Error during module loading.
 #[derive(Debug, Clone)]
 pub struct LoadError {
     pub message: String,
     pub path: Option<PathBuf>,
+    pub code: ErrorCode,
+    /// What to do about it, such as the module that was likely meant
+    pub help: Option<String>,
+    /// For a file that failed to parse, its syntax errors and its source,
+    /// so they can be shown in context
+    pub parse_errors: Option<Box<(Vec<ParseError>, String)>>,
 }
 
 impl std::fmt::Display for LoadError {
@@ -34,6 +43,9 @@ impl LoadError {
         Self {
             message: message.into(),
             path: None,
+            code: ErrorCode::UnreadableSource,
+            help: None,
+            parse_errors: None,
         }
     }
 
@@ -41,10 +53,15 @@ impl LoadError {
         Self {
             message: message.into(),
             path: Some(path),
+            code: ErrorCode::UnreadableSource,
+            help: None,
+            parse_errors: None,
         }
     }
 
-    pub fn module_not_found(name: &str, searched: &[PathBuf]) -> Self {
+    /// The error for a `mod name;` with no source file, listing the paths
+    /// searched. `available` are the modules that could have been meant.
+    pub fn module_not_found(name: &str, searched: &[PathBuf], available: &[String]) -> Self {
         let paths = searched
             .iter()
             .map(|p| format!("  {}", p.display()))
@@ -53,6 +70,10 @@ impl LoadError {
         Self {
             message: format!("cannot find module `{}`\nsearched:\n{}", name, paths),
             path: None,
+            code: ErrorCode::ModuleNotFound,
+            help: did_you_mean(name, available.iter().map(String::as_str))
+                .map(|module| format!("did you mean `{}`?", module)),
+            parse_errors: None,
         }
     }
 
@@ -60,10 +81,53 @@ impl LoadError {
         Self {
             message: format!("circular module dependency: {}", chain.join(" -> ")),
             path: None,
+            code: ErrorCode::CircularDependency,
+            help: None,
+            parse_errors: None,
+        }
+    }
+
+    pub fn with_code(mut self, code: ErrorCode) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// The error for `path` failing to parse, with every syntax error the
+    /// parser found in it. Its message and code are the first error's.
+    pub fn parse(errors: Vec<ParseError>, source: &str, path: PathBuf) -> Self {
+        let (message, code) = match errors.first() {
+            Some(error) => (error.message.clone(), error.code),
+            None => ("failed to parse".to_string(), ErrorCode::UnexpectedToken),
+        };
+        Self {
+            message,
+            path: Some(path),
+            code,
+            help: None,
+            parse_errors: Some(Box::new((errors, source.to_string()))),
         }
     }
 }
 
+/// The modules a `mod` declaration could name from `dirs`: their `.dream`
+/// and `.dreamt` files, and subdirectories with a `mod.dream`.
+fn modules_in(dirs: &[PathBuf]) -> Vec<String> {
+    let mut names = Vec::new();
+    for entry in dirs.iter().filter_map(|dir| fs::read_dir(dir).ok()).flatten().flatten() {
+        let path = entry.path();
+        let is_module = if path.is_dir() {
+            path.join("mod.dream").exists()
+        } else {
+            matches!(path.extension().and_then(|e| e.to_str()), Some("dream" | "dreamt"))
+        };
+        let stem = path.file_stem().and_then(|s| s.to_str());
+        if let Some(stem) = stem.filter(|_| is_module) {
+            names.push(stem.to_string());
+        }
+    }
+    names
+}
+
 /// Result type for module loading operations.
 pub type LoadResult<T> = Result<T, LoadError>;
 
@@ -123,7 +187,7 @@ impl ModuleLoader {
     /// For lib.dream at src root: `src/lib.dream` -> `my_app`
     /// For bindings files: `_build/bindings/cowboy.dream` -> `cowboy`
     /// For standalone files (no package): uses just the filename stem
-    fn derive_module_name(&self, path: &Path) -> String {
+    pub fn derive_module_name(&self, path: &Path) -> String {
         // Check if this is a bindings file - use just the filename
         let canonical_path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
         for bindings_dir in &self.bindings_dirs {
@@ -242,7 +306,9 @@ impl ModuleLoader {
             searched.push(binding_mod_path);
         }
 
-        Err(LoadError::module_not_found(name, &searched))
+        let mut dirs = vec![parent.to_path_buf()];
+        dirs.extend(self.bindings_dirs.iter().cloned());
+        Err(LoadError::module_not_found(name, &searched, &modules_in(&dirs)))
     }
 
     /// Load a module and all its dependencies.
@@ -281,7 +347,7 @@ impl ModuleLoader {
             .values()
             .next()
             .cloned()
-            .ok_or_else(|| LoadError::new(format!("module '{}' not found in file", module_name)))
+            .ok_or_else(|| LoadError::new(format!("module '{}' not found in file", module_name)).with_code(ErrorCode::ModuleNotFound))
     }
 
     /// Load all modules from a file.
@@ -316,10 +382,9 @@ impl ModuleLoader {
         let source = fs::read_to_string(&canonical)
             .map_err(|e| LoadError::with_path(format!("cannot read file: {}", e), canonical.clone()))?;
 
-        let mut parser = Parser::new(&source);
-        let modules = parser
-            .parse_file_modules(fallback_name)
-            .map_err(|e| LoadError::with_path(e.to_string(), canonical.clone()))?;
+        let mut parser = timings::time(Phase::Lex, Some(fallback_name), || Parser::new(&source));
+        let modules = timings::time(Phase::Parse, Some(fallback_name), || parser.parse_file_modules(fallback_name))
+            .map_err(|_| LoadError::parse(parser.errors().to_vec(), &source, canonical.clone()))?;
 
         // Load dependencies for each module (recursive)
         for module in &modules {
@@ -396,7 +461,8 @@ impl ModuleLoader {
                 return Err(LoadError::with_path(
                     "no main.dream or lib.dream found in directory",
                     src_dir,
-                ));
+                )
+                .with_code(ErrorCode::ModuleNotFound));
             }
         } else {
             path.to_path_buf()
@@ -483,6 +549,132 @@ impl Default for ModuleLoader {
     }
 }
 
+/// Order modules so each one comes after the modules it depends on.
+///
+/// A module depends on the submodules it declares with `mod` and on the
+/// project modules it imports with `use`. Dependency cycles are allowed,
+/// since BEAM modules may call each other freely; a cycle is broken at the
+/// module visited first. Ties are broken by name so builds are deterministic.
+pub fn order_by_dependency(mut modules: Vec<Module>, package_name: Option<&str>) -> Vec<Module> {
+    modules.sort_by(|a, b| a.name.cmp(&b.name));
+    let index: HashMap<String, usize> = modules
+        .iter()
+        .enumerate()
+        .map(|(i, m)| (m.name.clone(), i))
+        .collect();
+
+    let deps: Vec<Vec<usize>> = modules
+        .iter()
+        .map(|m| {
+            let mut deps: Vec<usize> = module_dependencies(m, package_name)
+                .iter()
+                .filter_map(|name| index.get(name).copied())
+                .filter(|&i| modules[i].name != m.name)
+                .collect();
+            deps.sort_unstable();
+            deps.dedup();
+            deps
+        })
+        .collect();
+
+    // Depth-first post-order; `visited` is set on entry so cycles terminate
+    fn visit(i: usize, deps: &[Vec<usize>], visited: &mut [bool], order: &mut Vec<usize>) {
+        if visited[i] {
+            return;
+        }
+        visited[i] = true;
+        for &dep in &deps[i] {
+            visit(dep, deps, visited, order);
+        }
+        order.push(i);
+    }
+
+    let mut visited = vec![false; modules.len()];
+    let mut order = Vec::with_capacity(modules.len());
+    for i in 0..modules.len() {
+        visit(i, &deps, &mut visited, &mut order);
+    }
+
+    let mut slots: Vec<Option<Module>> = modules.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|i| slots[i].take()).collect()
+}
+
+/// The modules in `changed` plus every module that depends on one of them,
+/// directly or through other modules. These are the modules a build has to
+/// recompile when `changed` were edited.
+pub fn affected_modules(
+    modules: &[Module],
+    changed: &HashSet<String>,
+    package_name: Option<&str>,
+) -> HashSet<String> {
+    // Reverse edges: module name -> modules that depend on it
+    let mut dependents: HashMap<String, Vec<&str>> = HashMap::new();
+    for module in modules {
+        for dep in module_dependencies(module, package_name) {
+            if dep != module.name {
+                dependents.entry(dep).or_default().push(&module.name);
+            }
+        }
+    }
+
+    let mut affected = changed.clone();
+    let mut queue: Vec<String> = changed.iter().cloned().collect();
+    while let Some(name) = queue.pop() {
+        for &dependent in dependents.get(&name).into_iter().flatten() {
+            if affected.insert(dependent.to_string()) {
+                queue.push(dependent.to_string());
+            }
+        }
+    }
+    affected
+}
+
+/// Names of the modules `module` may depend on, fully qualified.
+/// Includes candidates that might not exist (`use a::b` may import the
+/// function `b` rather than the module `a::b`); callers filter them.
+fn module_dependencies(module: &Module, package_name: Option<&str>) -> Vec<String> {
+    let ctx = package_name
+        .map(|pkg| ModuleContext::for_module(pkg, &module.name))
+        .unwrap_or_default();
+    let mut names = Vec::new();
+    let add = |names: &mut Vec<String>, name: String| {
+        // Unprefixed paths may name a module of this package
+        if let Some(pkg) = package_name {
+            names.push(format!("{}::{}", pkg, name));
+        }
+        names.push(name);
+    };
+
+    for item in &module.items {
+        match item {
+            Item::ModDecl(decl) => names.push(format!("{}::{}", module.name, decl.name)),
+            Item::Use(use_decl) => match &use_decl.tree {
+                UseTree::Path { module: path, name, .. } => {
+                    if let Some(resolved) = ctx.resolve(path) {
+                        add(&mut names, format!("{}::{}", resolved, name));
+                        add(&mut names, resolved);
+                    }
+                }
+                UseTree::Glob { module: path } => {
+                    if let Some(resolved) = ctx.resolve(path) {
+                        add(&mut names, resolved);
+                    }
+                }
+                UseTree::Group { module: path, items } => {
+                    if let Some(resolved) = ctx.resolve(path) {
+                        for item in items {
+                            add(&mut names, format!("{}::{}", resolved, item.name));
+                        }
+                        add(&mut names, resolved);
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+    names
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -591,5 +783,67 @@ mod tests {
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert!(err.message.contains("cannot find module"));
+        assert_eq!(err.help, None);
+
+        // A module with a similar name is suggested
+        create_temp_file(dir.path(), "users.dream", "pub fn all() -> int { 1 }");
+        create_temp_file(dir.path(), "app.dream", "mod user;");
+        let err = ModuleLoader::new().load(&dir.path().join("app.dream")).unwrap_err();
+        assert_eq!(err.help.as_deref(), Some("did you mean `users`?"));
+    }
+
+    fn parse(name: &str, source: &str) -> Module {
+        Parser::new(source).parse_file(name).unwrap()
+    }
+
+    fn names(modules: &[Module]) -> Vec<&str> {
+        modules.iter().map(|m| m.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_order_by_dependency_use_and_mod() {
+        let modules = vec![
+            parse("app", "mod db;\nmod web;\npub fn main() -> int { 1 }"),
+            parse("app::web", "use crate::db::query;\npub fn handle() -> int { query() }"),
+            parse("app::db", "pub fn query() -> int { 1 }"),
+            parse("app::util", "pub fn id(x: int) -> int { x }"),
+        ];
+
+        let ordered = order_by_dependency(modules, Some("app"));
+        let names = names(&ordered);
+        let pos = |n: &str| names.iter().position(|m| *m == n).unwrap();
+        assert!(pos("app::db") < pos("app::web"));
+        assert!(pos("app::web") < pos("app"));
+        assert!(pos("app::db") < pos("app"));
+        assert_eq!(names.len(), 4);
+    }
+
+    #[test]
+    fn test_affected_modules() {
+        let modules = vec![
+            parse("app", "mod db;\nmod web;\npub fn main() -> int { 1 }"),
+            parse("app::web", "use crate::db::query;\npub fn handle() -> int { query() }"),
+            parse("app::db", "pub fn query() -> int { 1 }"),
+            parse("app::util", "pub fn id(x: int) -> int { x }"),
+        ];
+        let changed: HashSet<String> = ["app::db".to_string()].into();
+
+        let mut affected: Vec<String> = affected_modules(&modules, &changed, Some("app")).into_iter().collect();
+        affected.sort();
+        assert_eq!(affected, vec!["app", "app::db", "app::web"]);
+
+        let changed: HashSet<String> = ["app::util".to_string()].into();
+        assert_eq!(affected_modules(&modules, &changed, Some("app")), changed);
+    }
+
+    #[test]
+    fn test_order_by_dependency_is_deterministic_with_cycles() {
+        let a = parse("app::a", "use crate::b::g;\npub fn f() -> int { g() }");
+        let b = parse("app::b", "use crate::a::f;\npub fn g() -> int { f() }");
+
+        let one = order_by_dependency(vec![a.clone(), b.clone()], Some("app"));
+        let two = order_by_dependency(vec![b, a], Some("app"));
+        assert_eq!(names(&one), names(&two));
+        assert_eq!(one.len(), 2);
     }
 }
@@ -19,11 +19,12 @@
 
 use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
-use std::process::{Child, Command, Stdio};
+use std::process::{Child, Stdio};
 use std::sync::mpsc::{self, Receiver};
 use std::thread;
 
 use crate::compiler::lexer::Span;
+use crate::toolchain;
 
 /// Error type for macro expansion failures.
 #[derive(Debug)]
@@ -181,7 +182,7 @@ impl MacroExpander {
             .join(" ");
         let eval_code = format!("{}.", eval_code);
 
-        let mut cmd = Command::new("erl");
+        let mut cmd = toolchain::erl();
         cmd.arg("-noshell");
 
         // Add paths for loading macro modules
@@ -8,30 +8,56 @@ pub mod cfg;
 mod codegen;
 pub mod core_erlang;
 pub mod derive;
+mod diagnostics;
+mod driver;
+mod emit;
 pub mod ast_serde;
 pub mod macro_expander;
 mod error;
+mod fmt;
+mod fuzz;
+pub mod ice;
+mod intern;
 mod lexer;
+pub mod lint;
 mod loader;
 mod parser;
 mod prelude;
+mod query;
 pub mod quote_expand;
+mod regex;
+mod text;
 mod token;
 pub mod typeck;
+mod warnings;
 
 pub use ast::*;
-pub use cfg::{get_derive_macro_name, get_proc_macro_derive_name, is_derive_macro, is_macro, is_proc_macro_derive, is_test, should_include};
+pub use cfg::{configure_module, get_derive_macro_name, get_proc_macro_derive_name, is_derive_macro, is_macro, is_proc_macro_derive, is_test, should_include};
 pub use codegen::{compile, compile_file, Codegen, CodegenError, CodegenResult};
 pub use core_erlang::{
     emit_core_erlang, CoreErlangEmitter, CoreErlangError, GenericFunctionRegistry,
     SharedGenericRegistry,
 };
+pub use diagnostics::{did_you_mean, Annotate, Annotations, Applicability, ErrorCode, Explanation, Label, Suggestion};
+pub use emit::{dump_ast, dump_tokens, EmitKind};
+pub use fmt::{format_declaration, format_source, format_type, FormatError};
+pub use fuzz::{fuzz_lex, fuzz_parse};
+pub use ice::InternalCompilerError;
+pub use intern::{Interner, Symbol};
 pub use error::{CompilerError, CompilerWarning, ParseError, ParseResult, TypeError, TypeResult, Warning};
-pub use lexer::Lexer;
-pub use loader::{LoadError, LoadResult, ModuleLoader};
-pub use parser::Parser;
+pub use lexer::{Lexer, SpannedToken, TokenCache};
+pub use lint::{Lint, LintDiagnostic, LintLevel, LintLevels, Linter};
+pub use loader::{affected_modules, order_by_dependency, LoadError, LoadResult, ModuleLoader};
+pub use parser::{Parser, SourceFile, SourceItem, SyntaxHints};
+pub use query::{Database, ModuleCheck, Revision};
+pub use text::Text;
 pub use token::Token;
+pub use driver::{compile_str, CompiledModule, Compiler, Diagnostics};
 pub use derive::{expand_derives, expand_derives_with_registry, DeriveError, MacroRegistry};
 pub use macro_expander::{MacroExpander, MacroError, MacroResult};
 pub use quote_expand::expand_quotes;
-pub use typeck::{check_module, check_modules, check_modules_with_metadata, resolve_stdlib_methods, TypeCheckResult};
+pub use typeck::{
+    check_module, check_modules, check_modules_where, check_modules_with_levels, check_modules_with_metadata,
+    resolve_stdlib_methods, TypeCheckResult,
+};
+pub use warnings::{WarningCategory, WarningLevels};
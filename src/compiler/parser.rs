@@ -1,12 +1,16 @@
 //! Recursive descent parser.
 
 use crate::compiler::ast::*;
+use crate::compiler::cfg;
+use crate::compiler::diagnostics::{Annotate, ErrorCode};
 use crate::compiler::error::{ParseError, ParseResult};
 use crate::compiler::lexer::{Lexer, Span, SpannedToken};
 use crate::compiler::prelude::prelude_items_for_module;
 use crate::compiler::token::{
-    has_interpolation, parse_interpolated_string, process_escapes, LexStringPart, Token,
+    has_interpolation, parse_format_string, parse_interpolated_string, process_escapes,
+    FormatSegment, LexStringPart, Token,
 };
+use std::collections::HashMap;
 
 /// Recursive descent parser.
 pub struct Parser<'source> {
@@ -19,6 +23,105 @@ pub struct Parser<'source> {
     /// Tracks if we're currently parsing inside a quote block.
     /// When true, allows `#ident` (unquote) in type name positions.
     in_quote: bool,
+    /// How the source spelled constructs the AST doesn't distinguish.
+    hints: SyntaxHints,
+    /// Errors recovered from so far, in source order.
+    errors: Vec<ParseError>,
+    /// Whether a statement that fails to parse is recorded and skipped
+    /// rather than failing its block. Set by the module entry points; the
+    /// formatter and single expressions still stop at the first error.
+    recovering: bool,
+    /// How many expressions, patterns and types the one being parsed is
+    /// nested in, so that deeply nested input is an error rather than a
+    /// stack overflow.
+    depth: usize,
+    /// The first clause of each function of the module or impl block
+    /// being parsed, whose types later clauses may leave out.
+    clauses: HashMap<(String, usize), FirstClause>,
+    /// How many `gen` blocks the expression being parsed is in; `yield`
+    /// is a keyword only inside one.
+    gen_depth: usize,
+}
+
+/// The signature of a function's first clause.
+#[derive(Debug, Clone)]
+struct FirstClause {
+    params: Vec<Type>,
+    return_type: Option<Type>,
+    /// The clause's parameter list, to point at
+    params_span: Span,
+}
+
+/// The deepest expressions, patterns and types may nest. Far past what
+/// anyone writes, well short of what the stack holds.
+const MAX_DEPTH: usize = 64;
+
+/// Choices between equivalent spellings that parsing throws away, kept so
+/// the formatter can print them back the way they were written. Each list
+/// is in source order, which is also the order a printer walking the AST
+/// front to back meets the constructs in.
+#[derive(Debug, Clone, Default)]
+pub struct SyntaxHints {
+    /// For each `if let` or `match`, whether it was an `if let`. Both parse
+    /// to `Expr::Match`.
+    pub if_let: Vec<bool>,
+    /// For each struct or struct variant pattern, whether it ended in `..`
+    pub pattern_rest: Vec<bool>,
+    /// For each map literal entry, whether it used `key: value` rather than
+    /// `key => value`
+    pub map_shorthand: Vec<bool>,
+    /// For each `with` step, whether it was written `pattern <- value?`
+    /// rather than `Ok(pattern) <- value`. Both parse to the same match.
+    pub with_try: Vec<bool>,
+    /// For each parameter of a `fn`, whether its type was left out and
+    /// taken from the function's first clause
+    pub clause_param: Vec<bool>,
+    /// For each `fn`, whether its return type was left out and taken from
+    /// the function's first clause
+    pub clause_return: Vec<bool>,
+}
+
+/// A file as written, for the formatter: no prelude items are added, and
+/// the attributes and `pub` that some items parse but don't keep are kept
+/// alongside them.
+#[derive(Debug, Clone, Default)]
+pub struct SourceFile {
+    /// Items of a file-based module
+    pub items: Vec<SourceItem>,
+    /// `mod name { ... }` blocks, for files that wrap their modules
+    pub modules: Vec<(String, Vec<SourceItem>)>,
+    /// Statements after the wrapped modules of a script
+    pub script: Option<Block>,
+    pub hints: SyntaxHints,
+}
+
+/// A top-level item with what [`Item`] has no room for.
+#[derive(Debug, Clone)]
+pub struct SourceItem {
+    pub item: Item,
+    /// Attributes on a `use`, `impl`, `trait` or `mod` declaration
+    pub attrs: Vec<Attribute>,
+    /// Whether a trait was declared `pub trait`
+    pub is_pub: bool,
+}
+
+/// The token that closes the delimiter `open`.
+fn closer_of(open: &Token) -> Option<Token> {
+    match open {
+        Token::LParen => Some(Token::RParen),
+        Token::LBrace => Some(Token::RBrace),
+        Token::LBracket | Token::HashBracket => Some(Token::RBracket),
+        _ => None,
+    }
+}
+
+/// Update `open`, the closers of the delimiters open so far, for `token`.
+fn track_delimiter(open: &mut Vec<Token>, token: &Token) {
+    if let Some(closer) = closer_of(token) {
+        open.push(closer);
+    } else if let Some(i) = open.iter().rposition(|closer| closer == token) {
+        open.truncate(i);
+    }
 }
 
 impl<'source> Parser<'source> {
@@ -26,25 +129,54 @@ impl<'source> Parser<'source> {
     pub fn new(source: &'source str) -> Self {
         let mut lexer = Lexer::new(source);
         let tokens = lexer.collect_tokens();
+        Self::with_tokens(source, tokens)
+    }
+
+    /// Create a parser for `source` from its tokens, such as those a
+    /// [`TokenCache`](crate::compiler::TokenCache) keeps, rather than
+    /// lexing it again.
+    pub fn with_tokens(source: &'source str, tokens: Vec<SpannedToken>) -> Self {
         Self {
             tokens,
             pos: 0,
             source,
             pending_gt: false,
             in_quote: false,
+            hints: SyntaxHints::default(),
+            errors: Vec::new(),
+            recovering: false,
+            depth: 0,
+            clauses: HashMap::new(),
+            gen_depth: 0,
         }
     }
 
+    /// Every error the module entry points have found, in source order.
+    ///
+    /// The entry points return the first of these. After an error the
+    /// parser skips to the next item or statement and carries on, so one
+    /// pass reports every syntax error in a file.
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
+
     /// Parse a complete module.
     /// Supports fully qualified module paths like `mod my_app::users::auth { }`.
     pub fn parse_module(&mut self) -> ParseResult<Module> {
+        self.recovering = true;
+        let result = self.parse_wrapped_module();
+        self.finish(result)
+    }
+
+    fn parse_wrapped_module(&mut self) -> ParseResult<Module> {
         self.expect(&Token::Mod)?;
         let name = self.parse_module_path()?;
         self.expect(&Token::LBrace)?;
+        self.clauses.clear();
 
         let mut items = Vec::new();
         while !self.check(&Token::RBrace) && !self.is_at_end() {
-            items.push(self.parse_item()?);
+            self.parse_item_into(&mut items);
         }
 
         self.expect(&Token::RBrace)?;
@@ -69,11 +201,17 @@ impl<'source> Parser<'source> {
     /// For file-based modules, the name is derived from the filename.
     /// Note: For files with multiple wrapped modules, use parse_file_modules instead.
     pub fn parse_file(&mut self, module_name: &str) -> ParseResult<Module> {
+        self.recovering = true;
+        let result = self.parse_file_module(module_name);
+        self.finish(result)
+    }
+
+    fn parse_file_module(&mut self, module_name: &str) -> ParseResult<Module> {
         // Check if this is a wrapped module: `mod name { ... }`
         if self.check(&Token::Mod) {
             // Peek ahead to see if it's `mod name {` (wrapped) or `mod name;` (declaration)
             if self.peek_is_wrapped_module() {
-                return self.parse_module();
+                return self.parse_wrapped_module();
             }
         }
 
@@ -81,7 +219,7 @@ impl<'source> Parser<'source> {
         let mut items = Vec::new();
 
         while !self.is_at_end() {
-            items.push(self.parse_item()?);
+            self.parse_item_into(&mut items);
         }
 
         // Inject prelude items (Option, Result) at the beginning
@@ -103,6 +241,12 @@ impl<'source> Parser<'source> {
     /// If the file contains file-based items (not wrapped in mod {}), returns a single module.
     /// If the file contains top-level expressions after modules, creates a `__script__` module.
     pub fn parse_file_modules(&mut self, fallback_name: &str) -> ParseResult<Vec<Module>> {
+        self.recovering = true;
+        let result = self.parse_modules(fallback_name);
+        self.finish(result)
+    }
+
+    fn parse_modules(&mut self, fallback_name: &str) -> ParseResult<Vec<Module>> {
         let mut modules = Vec::new();
 
         // Check if this file has wrapped modules
@@ -110,7 +254,7 @@ impl<'source> Parser<'source> {
             // Parse all wrapped modules in the file
             while !self.is_at_end() {
                 if self.check(&Token::Mod) && self.peek_is_wrapped_module() {
-                    modules.push(self.parse_module()?);
+                    modules.push(self.parse_wrapped_module()?);
                 } else {
                     // Non-module tokens - parse as script expressions
                     break;
@@ -124,13 +268,47 @@ impl<'source> Parser<'source> {
             }
         } else {
             // File-based module: parse items directly
-            let module = self.parse_file(fallback_name)?;
+            let module = self.parse_file_module(fallback_name)?;
             modules.push(module);
         }
 
         Ok(modules)
     }
 
+    /// Parse a whole file for the formatter, as [`parse_file_modules`]
+    /// would but keeping it as written.
+    ///
+    /// [`parse_file_modules`]: Parser::parse_file_modules
+    pub fn parse_source_file(&mut self) -> ParseResult<SourceFile> {
+        let mut file = SourceFile::default();
+        if self.check(&Token::Mod) && self.peek_is_wrapped_module() {
+            while self.check(&Token::Mod) && self.peek_is_wrapped_module() {
+                self.advance();
+                let name = self.parse_module_path()?;
+                self.expect(&Token::LBrace)?;
+                self.clauses.clear();
+                let mut items = Vec::new();
+                while !self.check(&Token::RBrace) && !self.is_at_end() {
+                    items.push(self.parse_source_item()?);
+                }
+                self.expect(&Token::RBrace)?;
+                file.modules.push((name, items));
+            }
+            if !self.is_at_end() {
+                let mut script = self.parse_script_module()?;
+                if let Some(Item::Function(main)) = script.items.pop() {
+                    file.script = Some(main.body);
+                }
+            }
+        } else {
+            while !self.is_at_end() {
+                file.items.push(self.parse_source_item()?);
+            }
+        }
+        file.hints = std::mem::take(&mut self.hints);
+        Ok(file)
+    }
+
     /// Parse top-level statements/expressions into a synthetic `__script__` module.
     /// This enables script-style execution: the `__main__` function is called when the file runs.
     fn parse_script_module(&mut self) -> ParseResult<Module> {
@@ -139,37 +317,9 @@ impl<'source> Parser<'source> {
         let mut expr = None;
 
         while !self.is_at_end() {
-            // Check if this is a let statement
-            if self.check(&Token::Let) {
-                stmts.push(self.parse_let_stmt()?);
-            } else {
-                // Parse expression, tracking span
-                let start = self.current_span().start;
-                let e = self.parse_expr()?;
-                // Use current position (after parsing) to get end of expression
-                let end = if self.pos > 0 {
-                    self.tokens.get(self.pos - 1).map(|t| t.span.end).unwrap_or(start)
-                } else {
-                    start
-                };
-                let span = start..end;
-
-                // Check if followed by semicolon (statement) or not (trailing expr)
-                if self.check(&Token::Semi) {
-                    self.advance();
-                    stmts.push(Stmt::Expr { expr: e, span: Some(span) });
-                } else if self.is_at_end() {
-                    // Trailing expression (final result)
-                    expr = Some(Box::new(e));
-                } else {
-                    // Expression statements that don't need semicolons
-                    if Self::is_block_expr(&e) {
-                        stmts.push(Stmt::Expr { expr: e, span: Some(span) });
-                    } else {
-                        let span = self.current_span();
-                        return Err(ParseError::new("expected `;` or end of file", span));
-                    }
-                }
+            let start = self.pos;
+            if let Err(error) = self.parse_stmt_into(&mut stmts, &mut expr, true) {
+                self.recover_statement(error, start)?;
             }
         }
 
@@ -260,11 +410,17 @@ impl<'source> Parser<'source> {
         let end = self.current_span().end;
         self.expect(&Token::RBracket)?;
 
-        Ok(Attribute {
+        let attr = Attribute {
             name,
             args,
             span: start..end,
-        })
+        };
+        if attr.name == "cfg" {
+            cfg::check_cfg_attr(&attr)?;
+        } else if attr.name == "cfg_attr" {
+            cfg::check_conditional_attr(&attr)?;
+        }
+        Ok(attr)
     }
 
     /// Parse attribute arguments after the name: `(args)`, `= "value"`, or nothing
@@ -289,7 +445,8 @@ impl<'source> Parser<'source> {
                 Err(ParseError::new(
                     "expected string literal after `=` in attribute",
                     self.current_span(),
-                ))
+                )
+                .with_code(ErrorCode::MalformedAttribute))
             }
         } else {
             Ok(AttributeArgs::None)
@@ -306,7 +463,7 @@ impl<'source> Parser<'source> {
         }
 
         loop {
-            args.push(self.parse_attribute_arg()?);
+            args.push(self.nested(Self::parse_attribute_arg)?);
 
             if self.check(&Token::Comma) {
                 self.advance();
@@ -322,8 +479,19 @@ impl<'source> Parser<'source> {
         Ok(args)
     }
 
-    /// Parse a single attribute argument: `ident`, `path::to::ident`, `key = "value"`, or `func(args)`
+    /// Parse a single attribute argument: `ident`, `path::to::ident`, `key = "value"`,
+    /// `key >= "value"`, or `func(args)`
     fn parse_attribute_arg(&mut self) -> ParseResult<AttributeArg> {
+        if let Some(SpannedToken {
+            token: Token::String(s),
+            ..
+        }) = self.tokens.get(self.pos)
+        {
+            let value = s.clone();
+            self.advance();
+            return Ok(AttributeArg::Str(value));
+        }
+
         // Accept both lowercase identifiers and type identifiers (for derives like Debug, Clone)
         let name = self.expect_ident_or_type_ident()?;
 
@@ -337,6 +505,32 @@ impl<'source> Parser<'source> {
             return Ok(AttributeArg::Path(segments));
         }
 
+        let comparison = match self.tokens.get(self.pos).map(|t| &t.token) {
+            Some(Token::GtEq) => Some(BinOp::Ge),
+            Some(Token::Gt) => Some(BinOp::Gt),
+            Some(Token::LtEq) => Some(BinOp::Le),
+            Some(Token::Lt) => Some(BinOp::Lt),
+            _ => None,
+        };
+        if let Some(op) = comparison {
+            // Comparison: `otp_release >= "26"`
+            self.advance();
+            if let Some(SpannedToken {
+                token: Token::String(s),
+                ..
+            }) = self.tokens.get(self.pos)
+            {
+                let value = s.clone();
+                self.advance();
+                return Ok(AttributeArg::Compare(name, op, value));
+            }
+            return Err(ParseError::new(
+                format!("expected string literal after `{}` in attribute argument", op),
+                self.current_span(),
+            )
+            .with_code(ErrorCode::MalformedAttribute));
+        }
+
         if self.check(&Token::Eq) {
             // Key-value: `feature = "json"`
             self.advance();
@@ -352,7 +546,8 @@ impl<'source> Parser<'source> {
                 Err(ParseError::new(
                     "expected string literal after `=` in attribute argument",
                     self.current_span(),
-                ))
+                )
+                .with_code(ErrorCode::MalformedAttribute))
             }
         } else if self.check(&Token::LParen) {
             // Nested: `not(test)` or `all(feature = "a", feature = "b")`
@@ -368,22 +563,32 @@ impl<'source> Parser<'source> {
 
     /// Parse a top-level item.
     pub fn parse_item(&mut self) -> ParseResult<Item> {
+        Ok(self.parse_source_item()?.item)
+    }
+
+    /// Parse a top-level item, keeping the attributes of `use`, `impl`,
+    /// `trait` and `mod` items and the `pub` of traits, which their AST nodes
+    /// don't record.
+    fn parse_source_item(&mut self) -> ParseResult<SourceItem> {
         // Parse any attributes before the item
         let attrs = self.parse_attributes()?;
 
         // Use statements don't have pub modifier (and don't support attributes currently)
         if self.check(&Token::Use) {
-            return self.parse_use_decl();
+            let item = self.parse_use_decl()?;
+            return Ok(SourceItem { item, attrs, is_pub: false });
         }
 
         // Impl blocks don't have pub modifier (methods inside can be pub)
         if self.check(&Token::Impl) {
-            return self.parse_impl_or_trait_impl();
+            let item = self.parse_impl_or_trait_impl()?;
+            return Ok(SourceItem { item, attrs, is_pub: false });
         }
 
         // Trait definitions (with optional pub modifier)
         if self.check(&Token::Trait) {
-            return self.parse_trait_def();
+            let item = self.parse_trait_def()?;
+            return Ok(SourceItem { item, attrs, is_pub: false });
         }
 
         let is_pub = self.check(&Token::Pub);
@@ -395,28 +600,37 @@ impl<'source> Parser<'source> {
         if self.check(&Token::Trait) {
             // pub trait - parse trait definition
             // Note: Currently traits don't track pub visibility, but we accept the syntax
-            return self.parse_trait_def();
+            let item = self.parse_trait_def()?;
+            return Ok(SourceItem { item, attrs, is_pub });
         }
 
-        if self.check(&Token::Extern) {
-            self.parse_extern_mod(attrs)
+        let item = if self.check(&Token::Extern) {
+            self.parse_extern_mod(attrs)?
         } else if self.check(&Token::Fn) {
-            Ok(Item::Function(self.parse_function(is_pub, attrs)?))
+            Item::Function(self.parse_function(is_pub, attrs)?)
         } else if self.check(&Token::Struct) {
-            Ok(Item::Struct(self.parse_struct(is_pub, attrs)?))
+            Item::Struct(self.parse_struct(is_pub, attrs)?)
         } else if self.check(&Token::Enum) {
-            Ok(Item::Enum(self.parse_enum(is_pub, attrs)?))
+            Item::Enum(self.parse_enum(is_pub, attrs)?)
         } else if self.check(&Token::Type) {
-            Ok(Item::TypeAlias(self.parse_type_alias(is_pub, attrs)?))
+            Item::TypeAlias(self.parse_type_alias(is_pub, attrs)?)
         } else if self.check(&Token::Mod) {
-            self.parse_mod_decl(is_pub)
+            let item = self.parse_mod_decl(is_pub)?;
+            return Ok(SourceItem { item, attrs, is_pub: false });
+        } else if !is_pub && self.peek_is_compile_error() {
+            let (message, span) = self.parse_compile_error()?;
+            if self.check(&Token::Semi) {
+                self.advance();
+            }
+            Item::CompileError(CompileErrorItem { attrs, message, span })
         } else {
             let span = self.current_span();
-            Err(ParseError::new(
+            return Err(ParseError::new(
                 "expected `fn`, `struct`, `enum`, `type`, `mod`, `impl`, `trait`, `extern`, or `use`",
                 span,
-            ))
-        }
+            ));
+        };
+        Ok(SourceItem { item, attrs: Vec::new(), is_pub: false })
     }
 
     /// Parse a module declaration: `mod foo;`
@@ -455,8 +669,15 @@ impl<'source> Parser<'source> {
     /// Parse an external module declaration: `extern mod erlang { ... }`
     /// Used in .dreamt files to declare types for FFI modules.
     /// Supports `#[name = "Actual.Module.Name"]` attribute for module name mapping.
+    /// `extern "erlang" { ... }` is parsed by [`Self::parse_extern_block`].
     fn parse_extern_mod(&mut self, attrs: Vec<Attribute>) -> ParseResult<Item> {
         self.expect(&Token::Extern)?;
+        if let Some(Token::String(abi)) = self.peek() {
+            let abi = abi.clone();
+            let span = self.current_span();
+            self.advance();
+            return self.parse_extern_block(attrs, abi, span);
+        }
         self.expect(&Token::Mod)?;
         let name = self.expect_ident()?;
         self.expect(&Token::LBrace)?;
@@ -470,6 +691,69 @@ impl<'source> Parser<'source> {
         Ok(Item::ExternMod(ExternMod { attrs, name, items }))
     }
 
+    /// Parse the rest of `extern "erlang" { fn lists::reverse<T>(list: [T]) -> [T]; }`
+    /// or `extern "elixir" { fn Enum::sum(list: [int]) -> int; }`, after the ABI
+    /// string. Elixir module paths may have several segments: `fn Phoenix::PubSub::broadcast`.
+    /// Functions of `extern "nif"` blocks belong to the declaring module, so
+    /// they have no path: `fn add(a: int, b: int) -> int;`.
+    fn parse_extern_block(&mut self, attrs: Vec<Attribute>, abi: String, abi_span: Span) -> ParseResult<Item> {
+        if abi != "erlang" && abi != "elixir" && abi != "nif" {
+            return Err(ParseError::new(format!("unsupported ABI \"{}\"", abi), abi_span)
+                .with_note("functions of other BEAM modules are declared in `extern \"erlang\"` or `extern \"elixir\"` blocks, and native functions in `extern \"nif\"` blocks"));
+        }
+        self.expect(&Token::LBrace)?;
+
+        let mut functions = Vec::new();
+        while !self.check(&Token::RBrace) && !self.is_at_end() {
+            let attrs = self.parse_attributes()?;
+            self.expect(&Token::Fn)?;
+            let start = self.current_span();
+            let mut path = Vec::new();
+            loop {
+                let segment = match self.peek().cloned() {
+                    Some(Token::TypeIdent(name)) => {
+                        self.advance();
+                        name
+                    }
+                    _ => self.expect_ident_or_keyword()?,
+                };
+                path.push(segment);
+                if !self.check(&Token::ColonColon) {
+                    break;
+                }
+                self.advance();
+            }
+            let name = path.pop().unwrap_or_default();
+            if abi == "nif" {
+                if !path.is_empty() {
+                    return Err(ParseError::with_help(
+                        "NIF functions can't name a module",
+                        start.start..self.current_span().start,
+                        format!("a NIF belongs to the module declaring it: `fn {}`", name),
+                    ));
+                }
+            } else if path.is_empty() {
+                let example = if abi == "elixir" { "Enum" } else { "lists" };
+                return Err(ParseError::with_help(
+                    "expected `::` after the module name",
+                    self.current_span(),
+                    format!("functions in `extern \"{}\"` blocks name their module: `fn {}::{}`", abi, example, name),
+                ));
+            }
+            if abi == "erlang" && path.len() > 1 {
+                return Err(ParseError::new(
+                    format!("Erlang module `{}` has more than one segment", path.join("::")),
+                    start.start..self.current_span().start,
+                )
+                .with_note("Erlang modules are single atoms, like `lists` or `gen_server`"));
+            }
+            functions.push((path.join("::"), self.parse_extern_fn(attrs, name)?));
+        }
+        self.expect(&Token::RBrace)?;
+
+        Ok(Item::ExternBlock(ExternBlock { attrs, abi, functions }))
+    }
+
     /// Parse an item inside an extern mod block.
     /// Supports attributes on nested modules and functions.
     fn parse_extern_item(&mut self) -> ParseResult<ExternItem> {
@@ -506,42 +790,7 @@ impl<'source> Parser<'source> {
             // Function declaration: `fn get<K, V>(key: K, map: Map<K, V>) -> V;`
             self.expect(&Token::Fn)?;
             let name = self.expect_ident_or_keyword()?;
-
-            let type_params = if self.check(&Token::Lt) {
-                self.parse_type_params()?
-            } else {
-                vec![]
-            };
-
-            self.expect(&Token::LParen)?;
-            let mut params = Vec::new();
-            while !self.check(&Token::RParen) {
-                let param_name = self.expect_ident()?;
-                self.expect(&Token::Colon)?;
-                let param_type = self.parse_type()?;
-                params.push((param_name, param_type));
-                if !self.check(&Token::RParen) {
-                    self.expect(&Token::Comma)?;
-                }
-            }
-            self.expect(&Token::RParen)?;
-
-            let return_type = if self.check(&Token::Arrow) {
-                self.advance();
-                self.parse_type()?
-            } else {
-                Type::Unit
-            };
-
-            self.expect(&Token::Semi)?;
-
-            Ok(ExternItem::Function(ExternFn {
-                attrs,
-                name,
-                type_params,
-                params,
-                return_type,
-            }))
+            Ok(ExternItem::Function(self.parse_extern_fn(attrs, name)?))
         } else {
             let span = self.current_span();
             Err(ParseError::new(
@@ -551,6 +800,46 @@ impl<'source> Parser<'source> {
         }
     }
 
+    /// Parse the signature of an extern function after its name:
+    /// `<K, V>(key: K, map: Map<K, V>) -> V;`
+    fn parse_extern_fn(&mut self, attrs: Vec<Attribute>, name: String) -> ParseResult<ExternFn> {
+        let type_params = if self.check(&Token::Lt) {
+            self.parse_type_params()?
+        } else {
+            vec![]
+        };
+
+        self.expect(&Token::LParen)?;
+        let mut params = Vec::new();
+        while !self.check(&Token::RParen) {
+            let param_name = self.expect_ident()?;
+            self.expect(&Token::Colon)?;
+            let param_type = self.parse_type()?;
+            params.push((param_name, param_type));
+            if !self.check(&Token::RParen) {
+                self.expect(&Token::Comma)?;
+            }
+        }
+        self.expect(&Token::RParen)?;
+
+        let return_type = if self.check(&Token::Arrow) {
+            self.advance();
+            self.parse_type()?
+        } else {
+            Type::Unit
+        };
+
+        self.expect(&Token::Semi)?;
+
+        Ok(ExternFn {
+            attrs,
+            name,
+            type_params,
+            params,
+            return_type,
+        })
+    }
+
     /// Parse an impl block, trait implementation, or module-level trait declaration.
     /// `impl Point { ... }` or `impl Display for Point { ... }` or `impl mod::Trait;`
     fn parse_impl_or_trait_impl(&mut self) -> ParseResult<Item> {
@@ -580,6 +869,7 @@ impl<'source> Parser<'source> {
             // Parse type bindings and methods inside the trait impl
             let mut type_bindings = Vec::new();
             let mut methods = Vec::new();
+            let outer_clauses = std::mem::take(&mut self.clauses);
             while !self.check(&Token::RBrace) && !self.is_at_end() {
                 // Check for type binding: `type Name = ConcreteType;`
                 if self.check(&Token::Type) {
@@ -603,6 +893,7 @@ impl<'source> Parser<'source> {
                     return Err(ParseError::new("expected `fn` or `type` in trait impl", span));
                 }
             }
+            self.clauses = outer_clauses;
 
             self.expect(&Token::RBrace)?;
 
@@ -645,6 +936,7 @@ impl<'source> Parser<'source> {
             } else {
                 // Regular impl block
                 let mut methods = Vec::new();
+                let outer_clauses = std::mem::take(&mut self.clauses);
                 while !self.check(&Token::RBrace) && !self.is_at_end() {
                     // Parse attributes for methods
                     let attrs = self.parse_attributes()?;
@@ -662,6 +954,7 @@ impl<'source> Parser<'source> {
                         return Err(ParseError::new("expected `fn` in impl block", span));
                     }
                 }
+                self.clauses = outer_clauses;
 
                 self.expect(&Token::RBrace)?;
 
@@ -829,7 +1122,7 @@ impl<'source> Parser<'source> {
             // ident is a segment (followed by ::) or the final item (followed by ; or as or {)
             while self.check(&Token::ColonColon) && self.has_more_path_after_colon() {
                 self.advance(); // consume ::
-                segments.push(self.expect_ident()?);
+                segments.push(self.expect_ident_or_type_ident()?);
             }
         }
 
@@ -969,18 +1262,25 @@ impl<'source> Parser<'source> {
             let var_name = self.expect_ident()?;
             format!("$UNQUOTE:{}", var_name)
         } else {
-            self.expect_ident()?
+            self.expect_ident_or_keyword()?
         };
 
         // Parse optional type parameters: <T, U>
         let type_params = self.parse_type_params()?;
 
+        // A later clause of a function takes the types it leaves out from
+        // the first clause with the same name and arity. Clauses under
+        // `#[cfg]` may be alternatives rather than clauses, so they stand
+        // alone.
+        let is_clause = !self.in_quote && !attrs.iter().any(|attr| attr.name == "cfg");
+
+        let params_start = self.current_span().start;
         self.expect(&Token::LParen)?;
 
-        let mut params = Vec::new();
+        let mut written = Vec::new();
         if !self.check(&Token::RParen) {
             loop {
-                params.push(self.parse_param()?);
+                written.push(self.parse_written_param()?);
                 if !self.check(&Token::Comma) {
                     break;
                 }
@@ -988,6 +1288,33 @@ impl<'source> Parser<'source> {
             }
         }
         self.expect(&Token::RParen)?;
+        let params_end = self.tokens.get(self.pos.saturating_sub(1))
+            .map(|t| t.span.end)
+            .unwrap_or(params_start);
+        let params_span = params_start..params_end;
+
+        let first_clause = if is_clause {
+            self.clauses.get(&(name.clone(), written.len())).cloned()
+        } else {
+            None
+        };
+
+        let arity = written.len();
+        let mut params = Vec::new();
+        for (pattern, ty, span) in written {
+            let inherited = first_clause
+                .as_ref()
+                .and_then(|clause| clause.params.get(params.len()));
+            let (param, was_inherited) = match self.clause_param(pattern, ty, inherited, span) {
+                Ok(param) => param,
+                Err(err) if is_clause && first_clause.is_none() => {
+                    return Err(self.clause_arity_error(&name, arity, params_span, err));
+                }
+                Err(err) => return Err(err),
+            };
+            self.hints.clause_param.push(was_inherited);
+            params.push(param);
+        }
 
         // Parse optional guard clause: `when <expr>`
         let guard = if self.check(&Token::When) {
@@ -997,13 +1324,29 @@ impl<'source> Parser<'source> {
             None
         };
 
+        let inherited_return = first_clause
+            .as_ref()
+            .and_then(|clause| clause.return_type.clone());
         let return_type = if self.check(&Token::Arrow) {
             self.advance();
+            self.hints.clause_return.push(false);
             Some(self.parse_type()?)
         } else {
-            None
+            self.hints.clause_return.push(inherited_return.is_some());
+            inherited_return
         };
 
+        if is_clause && first_clause.is_none() {
+            self.clauses.insert(
+                (name.clone(), params.len()),
+                FirstClause {
+                    params: params.iter().map(|param| param.ty.clone()).collect(),
+                    return_type: return_type.clone(),
+                    params_span,
+                },
+            );
+        }
+
         let body = self.parse_block()?;
         let end = self.tokens.get(self.pos.saturating_sub(1))
             .map(|t| t.span.end)
@@ -1022,8 +1365,50 @@ impl<'source> Parser<'source> {
         })
     }
 
+    /// The error for a clause of `name` taking `arity` arguments, one of
+    /// which `err` says has no type. When the function has a clause of
+    /// another arity, this one most likely meant to be one of its clauses.
+    fn clause_arity_error(&self, name: &str, arity: usize, params_span: Span, err: ParseError) -> ParseError {
+        let first = self
+            .clauses
+            .iter()
+            .filter(|((clause, _), _)| clause == name)
+            .min_by_key(|(_, clause)| clause.params_span.start);
+        let Some(((_, first_arity), clause)) = first else {
+            return err;
+        };
+        ParseError::with_help(
+            format!(
+                "this clause of `{}` takes {} argument{} but its first clause takes {}",
+                name,
+                arity,
+                if arity == 1 { "" } else { "s" },
+                first_arity
+            ),
+            params_span,
+            "a clause takes the types it leaves out from the first clause with the same \
+             number of arguments; write them out to define a function of another arity",
+        )
+        .with_label(clause.params_span.clone(), "first clause")
+        .with_code(ErrorCode::ClauseArity)
+    }
+
     /// Parse a function parameter.
     fn parse_param(&mut self) -> ParseResult<Param> {
+        self.parse_clause_param(None).map(|(param, _)| param)
+    }
+
+    /// Parse a parameter of a function clause, which takes `inherited`,
+    /// the type of the same parameter in the function's first clause, if
+    /// it leaves its own out. Also returns whether it did.
+    fn parse_clause_param(&mut self, inherited: Option<&Type>) -> ParseResult<(Param, bool)> {
+        let (pattern, ty, span) = self.parse_written_param()?;
+        self.clause_param(pattern, ty, inherited, span)
+    }
+
+    /// Parse a parameter as written: its pattern, its type if it has one,
+    /// and where a missing type would go.
+    fn parse_written_param(&mut self) -> ParseResult<(Pattern, Option<Type>, Span)> {
         // Special handling for `self` without type annotation
         if self.check(&Token::SelfKw) {
             self.advance();
@@ -1041,58 +1426,82 @@ impl<'source> Parser<'source> {
                 }
             };
 
-            return Ok(Param { pattern, ty });
+            return Ok((pattern, Some(ty), self.current_span()));
         }
 
         let pattern = self.parse_pattern()?;
-
-        // Type is optional for literal patterns (type can be inferred)
+        let span = self.current_span();
         let ty = if self.check(&Token::Colon) {
             self.advance();
-            self.parse_type()?
+            Some(self.parse_type()?)
         } else {
-            // Infer type from pattern for literals
-            match &pattern {
-                Pattern::Int(_) => Type::Named {
-                    name: "int".to_string(),
-                    type_args: vec![],
-                },
-                Pattern::Atom(_) => Type::Named {
-                    name: "atom".to_string(),
-                    type_args: vec![],
-                },
-                Pattern::Bool(_) => Type::Named {
-                    name: "bool".to_string(),
-                    type_args: vec![],
-                },
-                Pattern::String(_) => Type::Named {
-                    name: "string".to_string(),
-                    type_args: vec![],
-                },
-                Pattern::Charlist(_) => Type::Named {
-                    name: "string".to_string(),
-                    type_args: vec![],
-                },
-                Pattern::Wildcard => Type::Named {
-                    name: "any".to_string(),
-                    type_args: vec![],
-                },
-                // Infer type from enum variant pattern (e.g., Msg::Get → Msg)
-                Pattern::Enum { name, .. } => Type::Named {
-                    name: name.clone(),
-                    type_args: vec![],
-                },
-                _ => {
-                    let span = self.current_span();
-                    return Err(ParseError::new(
-                        "type annotation required for this pattern",
-                        span,
-                    ));
-                }
+            None
+        };
+        Ok((pattern, ty, span))
+    }
+
+    /// The parameter a written one makes: with its own type, the
+    /// `inherited` one, or, for a literal pattern, the type it is obviously
+    /// of. Also returns whether it took the inherited type.
+    fn clause_param(
+        &self,
+        pattern: Pattern,
+        ty: Option<Type>,
+        inherited: Option<&Type>,
+        span: Span,
+    ) -> ParseResult<(Param, bool)> {
+        if let Some(ty) = ty {
+            return Ok((Param { pattern, ty }, false));
+        }
+        if let Some(ty) = inherited {
+            return Ok((Param { pattern, ty: ty.clone() }, true));
+        }
+        // Type is optional for literal patterns (type can be inferred)
+        let ty = match &pattern {
+            Pattern::Int(_) => Type::Named {
+                name: "int".to_string(),
+                type_args: vec![],
+            },
+            Pattern::Atom(_) => Type::Named {
+                name: "atom".to_string(),
+                type_args: vec![],
+            },
+            Pattern::Bool(_) => Type::Named {
+                name: "bool".to_string(),
+                type_args: vec![],
+            },
+            Pattern::String(_) => Type::Named {
+                name: "string".to_string(),
+                type_args: vec![],
+            },
+            Pattern::Charlist(_) => Type::Named {
+                name: "string".to_string(),
+                type_args: vec![],
+            },
+            Pattern::Wildcard => Type::Named {
+                name: "any".to_string(),
+                type_args: vec![],
+            },
+            // Infer type from enum variant pattern (e.g., Msg::Get → Msg)
+            Pattern::Enum { name, .. } => Type::Named {
+                name: name.clone(),
+                type_args: vec![],
+            },
+            // A binary pattern only matches a binary
+            Pattern::BitString(_) => Type::Named {
+                name: "Binary".to_string(),
+                type_args: vec![],
+            },
+            _ => {
+                return Err(ParseError::new(
+                    "type annotation required for this pattern",
+                    span,
+                )
+                .with_code(ErrorCode::MissingTypeAnnotation));
             }
         };
 
-        Ok(Param { pattern, ty })
+        Ok((Param { pattern, ty }, false))
     }
 
     /// Parse a struct definition.
@@ -1111,6 +1520,39 @@ impl<'source> Parser<'source> {
         // Parse optional type parameters: <T, U>
         let type_params = self.parse_type_params()?;
 
+        if self.check(&Token::LParen) {
+            // Tuple struct: struct Meters(Float);
+            let start = self.current_span().start;
+            self.advance();
+            let mut fields = Vec::new();
+            while !self.check(&Token::RParen) && !self.is_at_end() {
+                let field_type = self.parse_type()?;
+                fields.push((fields.len().to_string(), field_type));
+                if self.check(&Token::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+            let end = self.current_span().end;
+            self.expect(&Token::RParen)?;
+            if fields.is_empty() {
+                return Err(ParseError::with_help(
+                    format!("tuple struct `{}` has no fields", name),
+                    start..end,
+                    format!("write `struct {} {{}}` for a struct without fields", name),
+                ));
+            }
+            self.expect(&Token::Semi)?;
+            return Ok(StructDef {
+                attrs,
+                name,
+                type_params,
+                fields,
+                is_pub,
+            });
+        }
+
         self.expect(&Token::LBrace)?;
 
         let mut fields = Vec::new();
@@ -1226,48 +1668,129 @@ impl<'source> Parser<'source> {
         Ok(block)
     }
 
-    /// Parse block contents (statements and optional trailing expression).
-    /// Does not consume the surrounding braces.
-    fn parse_block_contents(&mut self) -> ParseResult<Block> {
-        let mut stmts = Vec::new();
-        let mut expr = None;
+    /// Parse block contents (statements and optional trailing expression).
+    /// Does not consume the surrounding braces.
+    fn parse_block_contents(&mut self) -> ParseResult<Block> {
+        let mut stmts = Vec::new();
+        let mut expr = None;
+
+        while !self.check(&Token::RBrace) && !self.is_at_end() {
+            let start = self.pos;
+            if let Err(error) = self.parse_stmt_into(&mut stmts, &mut expr, false) {
+                self.recover_statement(error, start)?;
+            }
+        }
+
+        Ok(Block { stmts, expr })
+    }
+
+    /// Parse a statement onto `stmts`, or the trailing expression of the
+    /// block (of the file, in a `script`) into `expr`.
+    fn parse_stmt_into(
+        &mut self,
+        stmts: &mut Vec<Stmt>,
+        expr: &mut Option<Box<Expr>>,
+        script: bool,
+    ) -> ParseResult<()> {
+        // `#[cfg(...)]` on a statement or on the trailing expression
+        if self.check(&Token::HashBracket) {
+            let attrs = self.parse_cfg_attributes("statements")?;
+            let (stmt, tail) = self.parse_conditional_stmt(script)?;
+            stmts.push(Stmt::Cfg {
+                attrs,
+                stmt: Box::new(stmt),
+                tail,
+            });
+            return Ok(());
+        }
+
+        // Check if this is a let statement
+        if self.check(&Token::Let) {
+            stmts.push(self.parse_let_stmt()?);
+            return Ok(());
+        }
+
+        // Parse expression, tracking span
+        let start = self.current_span().start;
+        let e = self.parse_expr()?;
+        // Use current position (after parsing) to get end of expression
+        let end = if self.pos > 0 {
+            self.tokens.get(self.pos - 1).map(|t| t.span.end).unwrap_or(start)
+        } else {
+            start
+        };
+        let span = start..end;
+        let at_end = if script { self.is_at_end() } else { self.check(&Token::RBrace) };
+
+        // Check if followed by semicolon (statement) or not (trailing expr)
+        if self.check(&Token::Semi) {
+            self.advance();
+            stmts.push(Stmt::Expr { expr: e, span: Some(span) });
+        } else if at_end {
+            *expr = Some(Box::new(e));
+        } else if Self::is_block_expr(&e) {
+            // Expression statements that don't need semicolons
+            // (if, match, block, etc.)
+            stmts.push(Stmt::Expr { expr: e, span: Some(span) });
+        } else {
+            let message = if script {
+                "expected `;` or end of file"
+            } else {
+                "expected `;` or `}`"
+            };
+            return Err(ParseError::new(message, self.current_span()));
+        }
+        Ok(())
+    }
+
+    /// Parse the statement after `#[cfg(...)]`. An expression without a `;`
+    /// at the end of the block, or before another attribute, may be the
+    /// block's value, so it comes back with `tail` set.
+    fn parse_conditional_stmt(&mut self, script: bool) -> ParseResult<(Stmt, bool)> {
+        if self.check(&Token::Let) {
+            return Ok((self.parse_let_stmt()?, false));
+        }
+
+        let start = self.current_span().start;
+        let expr = self.parse_expr()?;
+        let end = self.tokens.get(self.pos - 1).map(|t| t.span.end).unwrap_or(start);
+        let block_like = Self::is_block_expr(&expr);
+        let stmt = Stmt::Expr {
+            expr,
+            span: Some(start..end),
+        };
+        let at_end = if script { self.is_at_end() } else { self.check(&Token::RBrace) };
 
-        while !self.check(&Token::RBrace) && !self.is_at_end() {
-            // Check if this is a let statement
-            if self.check(&Token::Let) {
-                stmts.push(self.parse_let_stmt()?);
+        if self.check(&Token::Semi) {
+            self.advance();
+            Ok((stmt, false))
+        } else if at_end || self.check(&Token::HashBracket) {
+            Ok((stmt, true))
+        } else if block_like {
+            Ok((stmt, false))
+        } else {
+            let message = if script {
+                "expected `;` or end of file"
             } else {
-                // Parse expression, tracking span
-                let start = self.current_span().start;
-                let e = self.parse_expr()?;
-                let end = if self.pos > 0 {
-                    self.tokens.get(self.pos - 1).map(|t| t.span.end).unwrap_or(start)
-                } else {
-                    start
-                };
-                let span = start..end;
-
-                // Check if followed by semicolon (statement) or not (trailing expr)
-                if self.check(&Token::Semi) {
-                    self.advance();
-                    stmts.push(Stmt::Expr { expr: e, span: Some(span) });
-                } else if self.check(&Token::RBrace) {
-                    // Trailing expression
-                    expr = Some(Box::new(e));
-                } else {
-                    // Expression statements that don't need semicolons
-                    // (if, match, block, etc.)
-                    if Self::is_block_expr(&e) {
-                        stmts.push(Stmt::Expr { expr: e, span: Some(span) });
-                    } else {
-                        let span = self.current_span();
-                        return Err(ParseError::new("expected `;` or `}`", span));
-                    }
-                }
-            }
+                "expected `;` or `}`"
+            };
+            Err(ParseError::new(message, self.current_span()))
         }
+    }
 
-        Ok(Block { stmts, expr })
+    /// Parse the attributes on a statement or match arm, where only
+    /// `cfg` and `cfg_attr` mean anything.
+    fn parse_cfg_attributes(&mut self, what: &str) -> ParseResult<Vec<Attribute>> {
+        let attrs = self.parse_attributes()?;
+        if let Some(attr) = attrs.iter().find(|attr| attr.name != "cfg" && attr.name != "cfg_attr") {
+            return Err(ParseError::new(
+                format!("`#[{}]` can't be used on {}", attr.name, what),
+                attr.span.clone(),
+            )
+            .with_code(ErrorCode::MalformedAttribute)
+            .with_note(format!("only `#[cfg]` and `#[cfg_attr]` apply to {}", what)));
+        }
+        Ok(attrs)
     }
 
     /// Parse block contents when we've already parsed the first expression.
@@ -1292,30 +1815,9 @@ impl<'source> Parser<'source> {
 
         // Continue parsing remaining block contents
         while !self.check(&Token::RBrace) && !self.is_at_end() {
-            if self.check(&Token::Let) {
-                stmts.push(self.parse_let_stmt()?);
-            } else {
-                // Parse expression, tracking span
-                let start = self.current_span().start;
-                let e = self.parse_expr()?;
-                let end = if self.pos > 0 {
-                    self.tokens.get(self.pos - 1).map(|t| t.span.end).unwrap_or(start)
-                } else {
-                    start
-                };
-                let span = start..end;
-
-                if self.check(&Token::Semi) {
-                    self.advance();
-                    stmts.push(Stmt::Expr { expr: e, span: Some(span) });
-                } else if self.check(&Token::RBrace) {
-                    expr = Some(Box::new(e));
-                } else if Self::is_block_expr(&e) {
-                    stmts.push(Stmt::Expr { expr: e, span: Some(span) });
-                } else {
-                    let span = self.current_span();
-                    return Err(ParseError::new("expected `;` or `}`", span));
-                }
+            let start = self.pos;
+            if let Err(error) = self.parse_stmt_into(&mut stmts, &mut expr, false) {
+                self.recover_statement(error, start)?;
             }
         }
 
@@ -1365,10 +1867,12 @@ impl<'source> Parser<'source> {
     fn parse_map_entry(&mut self) -> ParseResult<(Expr, Expr)> {
         // Try shorthand syntax first: ident: or "string":
         if let Some((key, true)) = self.try_parse_map_key_shorthand()? {
+            self.hints.map_shorthand.push(true);
             let value = self.parse_expr()?;
             return Ok((key, value));
         }
         // Otherwise parse expr => value
+        self.hints.map_shorthand.push(false);
         let key = self.parse_expr()?;
         self.expect(&Token::FatArrow)?;
         let value = self.parse_expr()?;
@@ -1378,6 +1882,7 @@ impl<'source> Parser<'source> {
     /// Parse a let statement.
     /// Supports both `let pattern = value;` and `let pattern = value else { ... };`
     fn parse_let_stmt(&mut self) -> ParseResult<Stmt> {
+        let start = self.current_span().start;
         self.expect(&Token::Let)?;
         let pattern = self.parse_pattern()?;
 
@@ -1400,13 +1905,20 @@ impl<'source> Parser<'source> {
         };
 
         self.expect(&Token::Semi)?;
+        let end = self.tokens[self.pos - 1].span.end;
 
-        Ok(Stmt::Let { pattern, ty, value, else_block })
+        Ok(Stmt::Let {
+            pattern,
+            ty,
+            value,
+            else_block,
+            span: Some(start..end),
+        })
     }
 
     /// Parse an expression.
     pub fn parse_expr(&mut self) -> ParseResult<Expr> {
-        self.parse_pipe_expr()
+        self.nested(Self::parse_pipe_expr)
     }
 
     /// Parse pipe expressions: `expr |> func(args)`.
@@ -1437,6 +1949,7 @@ impl<'source> Parser<'source> {
                 op: BinOp::Or,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: ExprId::fresh(),
             };
         }
 
@@ -1454,6 +1967,7 @@ impl<'source> Parser<'source> {
                 op: BinOp::And,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: ExprId::fresh(),
             };
         }
 
@@ -1487,6 +2001,7 @@ impl<'source> Parser<'source> {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: ExprId::fresh(),
             })
         } else {
             Ok(left)
@@ -1512,6 +2027,7 @@ impl<'source> Parser<'source> {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: ExprId::fresh(),
             };
         }
 
@@ -1539,6 +2055,7 @@ impl<'source> Parser<'source> {
                 op,
                 left: Box::new(left),
                 right: Box::new(right),
+                id: ExprId::fresh(),
             };
         }
 
@@ -1549,7 +2066,7 @@ impl<'source> Parser<'source> {
     fn parse_unary_expr(&mut self) -> ParseResult<Expr> {
         if self.check(&Token::Bang) {
             self.advance();
-            let expr = self.parse_unary_expr()?;
+            let expr = self.nested(Self::parse_unary_expr)?;
             return Ok(Expr::Unary {
                 op: UnaryOp::Not,
                 expr: Box::new(expr),
@@ -1558,7 +2075,7 @@ impl<'source> Parser<'source> {
 
         if self.check(&Token::Minus) {
             self.advance();
-            let expr = self.parse_unary_expr()?;
+            let expr = self.nested(Self::parse_unary_expr)?;
             return Ok(Expr::Unary {
                 op: UnaryOp::Neg,
                 expr: Box::new(expr),
@@ -1670,7 +2187,13 @@ impl<'source> Parser<'source> {
             } else if self.check(&Token::ColonColon) {
                 // Path access - only valid if expr is Ident or Path
                 self.advance();
-                let segment = self.expect_ident_or_type_ident()?;
+                let segment = match self.peek().cloned() {
+                    Some(Token::TypeIdent(name)) => {
+                        self.advance();
+                        name
+                    }
+                    _ => self.expect_ident_or_keyword()?,
+                };
                 let is_type_segment = segment.chars().next().map(|c| c.is_uppercase()).unwrap_or(false);
 
                 expr = match expr {
@@ -1898,8 +2421,52 @@ impl<'source> Parser<'source> {
             return Ok(Expr::Ident("self".to_string()));
         }
 
+        // With block: `with` is only a keyword before `{`, so it stays
+        // usable as a name, e.g. for `maps:with/2`
+        if self.check_ident("with") && self.check_ahead(1, &Token::LBrace) {
+            return self.parse_with_expr();
+        }
+
+        // Generator block: `gen` is only a keyword before `{`, and `yield`
+        // only inside one
+        if self.check_ident("gen") && self.check_ahead(1, &Token::LBrace) {
+            return self.parse_gen_expr();
+        }
+        if self.check_ident("yield") {
+            if self.gen_depth > 0 {
+                self.advance(); // consume `yield`
+                let value = self.parse_expr()?;
+                return Ok(Expr::Call {
+                    func: Box::new(Expr::Ident(GEN_YIELD.to_string())),
+                    type_args: vec![],
+                    inferred_type_args: vec![],
+                    args: vec![value],
+                });
+            }
+            if matches!(
+                self.tokens.get(self.pos + 1).map(|t| &t.token),
+                Some(Token::Ident(_) | Token::Int(_) | Token::String(_))
+            ) {
+                return Err(ParseError::with_help(
+                    "`yield` outside a `gen` block",
+                    self.current_span(),
+                    "wrap the code that yields in `gen { ... }`",
+                ));
+            }
+        }
+
         // Identifier or type identifier (for struct init or enum)
         if let Some(Token::Ident(name)) = self.peek().cloned() {
+            if self.peek_is_assert_macro(&name) {
+                return self.parse_assert_macro(&name);
+            }
+            if self.peek_is_compile_error() {
+                let (message, span) = self.parse_compile_error()?;
+                return Ok(Expr::CompileError { message, span });
+            }
+            if name == "format" && self.peek_is_macro_call() {
+                return self.parse_format_macro();
+            }
             self.advance();
             return Ok(Expr::Ident(name));
         }
@@ -2190,8 +2757,8 @@ impl<'source> Parser<'source> {
                 return Ok(Expr::MapLiteral(vec![]));
             }
 
-            // If it starts with 'let', it's definitely a block
-            if self.check(&Token::Let) {
+            // If it starts with 'let' or an attribute, it's definitely a block
+            if self.check(&Token::Let) || self.check(&Token::HashBracket) {
                 let block = self.parse_block_contents()?;
                 self.expect(&Token::RBrace)?;
                 return Ok(Expr::Block(block));
@@ -2202,6 +2769,7 @@ impl<'source> Parser<'source> {
             if let Some((key_expr, is_shorthand)) = self.try_parse_map_key_shorthand()? {
                 if is_shorthand {
                     // It's a map with shorthand syntax
+                    self.hints.map_shorthand.push(true);
                     let value = self.parse_expr()?;
                     let mut pairs = vec![(key_expr, value)];
 
@@ -2220,6 +2788,8 @@ impl<'source> Parser<'source> {
 
             // Try to determine if this is a map or block
             // Parse first expression and check for '=>'
+            let entry_hint = self.hints.map_shorthand.len();
+            self.hints.map_shorthand.push(false);
             let first = self.parse_expr()?;
 
             if self.check(&Token::FatArrow) {
@@ -2241,6 +2811,7 @@ impl<'source> Parser<'source> {
             } else {
                 // It's a block - continue parsing as block contents
                 // The first expression we parsed is either the return expr or first statement
+                self.hints.map_shorthand.remove(entry_hint);
                 let block = self.parse_block_contents_with_first(first)?;
                 self.expect(&Token::RBrace)?;
                 return Ok(Expr::Block(block));
@@ -2390,6 +2961,8 @@ impl<'source> Parser<'source> {
         if self.check(&Token::Colon) {
             self.advance();
             segment.size = Some(Box::new(self.parse_unary_expr()?));
+        } else if let Some(name) = self.bitstring_size_var() {
+            segment.size = Some(Box::new(Expr::Ident(name)));
         }
 
         // Parse optional type specifiers: `/specifier-specifier-...`
@@ -2401,6 +2974,17 @@ impl<'source> Parser<'source> {
         Ok(segment)
     }
 
+    /// A variable size written straight after the colon, as in
+    /// `body:len/binary`, where the lexer sees the atom `:len`. No atom can
+    /// follow a segment's value otherwise.
+    fn bitstring_size_var(&mut self) -> Option<String> {
+        let Some(Token::Atom(name)) = self.peek().cloned() else {
+            return None;
+        };
+        self.advance();
+        Some(name)
+    }
+
     /// Parse bit string type specifiers: `big-signed-integer`
     fn parse_bitstring_specifiers<T>(&mut self, segment: &mut BitStringSegment<T>) -> ParseResult<()> {
         loop {
@@ -2529,6 +3113,7 @@ impl<'source> Parser<'source> {
 
         // Check for `if let` pattern matching
         if self.check(&Token::Let) {
+            self.hints.if_let.push(true);
             return self.parse_if_let_expr();
         }
 
@@ -2569,6 +3154,7 @@ impl<'source> Parser<'source> {
 
         // Build the match arm for the pattern
         let then_arm = MatchArm {
+            attrs: vec![],
             pattern,
             guard: None,
             body: Expr::Block(then_block),
@@ -2584,6 +3170,7 @@ impl<'source> Parser<'source> {
                 Expr::Block(self.parse_block()?)
             };
             MatchArm {
+                attrs: vec![],
                 pattern: Pattern::Wildcard,
                 guard: None,
                 body: else_body,
@@ -2591,6 +3178,7 @@ impl<'source> Parser<'source> {
         } else {
             // No else block - return unit (empty tuple)
             MatchArm {
+                attrs: vec![],
                 pattern: Pattern::Wildcard,
                 guard: None,
                 body: Expr::Tuple(vec![]),
@@ -2603,9 +3191,266 @@ impl<'source> Parser<'source> {
         })
     }
 
+    /// Whether the current identifier starts a built-in assertion such as
+    /// `assert!(...)`, with the `!` written directly after the name.
+    fn peek_is_assert_macro(&self, name: &str) -> bool {
+        matches!(name, "assert" | "assert_eq" | "assert_ne" | "assert_match") && self.peek_is_macro_call()
+    }
+
+    /// Whether the current token starts `compile_error!(...)`.
+    fn peek_is_compile_error(&self) -> bool {
+        matches!(self.peek(), Some(Token::Ident(name)) if name == "compile_error") && self.peek_is_macro_call()
+    }
+
+    /// Whether the current identifier is followed directly by `!` and `(`.
+    fn peek_is_macro_call(&self) -> bool {
+        match (self.tokens.get(self.pos), self.tokens.get(self.pos + 1)) {
+            (Some(ident), Some(bang)) => {
+                bang.token == Token::Bang
+                    && ident.span.end == bang.span.start
+                    && self.check_ahead(2, &Token::LParen)
+            }
+            _ => false,
+        }
+    }
+
+    /// Parse `compile_error!("message")`, returning the message and the span
+    /// of the whole call. The message must be a plain string literal.
+    fn parse_compile_error(&mut self) -> ParseResult<(String, Span)> {
+        let start = self.current_span().start;
+        self.advance(); // compile_error
+        self.advance(); // !
+        self.expect(&Token::LParen)?;
+        let message = match self.peek().cloned() {
+            Some(Token::String(raw)) if !has_interpolation(&raw) => {
+                self.advance();
+                process_escapes(&raw)
+            }
+            _ => {
+                return Err(ParseError::new(
+                    "`compile_error!` takes a string literal",
+                    self.current_span(),
+                )
+                .with_note("the message is reported at compile time, so it can't interpolate values"));
+            }
+        };
+        self.expect(&Token::RParen)?;
+        let end = self.tokens[self.pos - 1].span.end;
+        Ok((message, start..end))
+    }
+
+    /// Parse `format!("template", args...)`. The template must be a string
+    /// literal; each `{}` or `{:?}` in it takes the next argument, and the
+    /// placeholders and arguments must match up.
+    fn parse_format_macro(&mut self) -> ParseResult<Expr> {
+        let start = self.current_span().start;
+        self.advance(); // format
+        self.advance(); // !
+        self.expect(&Token::LParen)?;
+        let template = match self.peek().cloned() {
+            Some(Token::String(raw)) => {
+                let span = self.current_span();
+                self.advance();
+                parse_format_string(&raw).map_err(|message| {
+                    ParseError::with_help(
+                        message,
+                        span,
+                        "placeholders are `{}` and `{:?}`; write `{{` and `}}` for literal braces",
+                    )
+                })?
+            }
+            _ => {
+                return Err(ParseError::new(
+                    "`format!` takes a string literal",
+                    self.current_span(),
+                )
+                .with_note("the template is checked against the arguments at compile time"));
+            }
+        };
+
+        let mut args = Vec::new();
+        while self.check(&Token::Comma) {
+            self.advance();
+            if self.check(&Token::RParen) {
+                break;
+            }
+            args.push(self.parse_expr()?);
+        }
+        self.expect(&Token::RParen)?;
+        let end = self.tokens[self.pos - 1].span.end;
+
+        let placeholders = template
+            .iter()
+            .filter(|segment| !matches!(segment, FormatSegment::Literal(_)))
+            .count();
+        if placeholders != args.len() {
+            return Err(ParseError::new(
+                format!(
+                    "`format!` template has {} placeholder{} but {} argument{} {} given",
+                    placeholders,
+                    if placeholders == 1 { "" } else { "s" },
+                    args.len(),
+                    if args.len() == 1 { "" } else { "s" },
+                    if args.len() == 1 { "was" } else { "were" },
+                ),
+                start..end,
+            ));
+        }
+
+        let mut args = args.into_iter().map(Box::new);
+        let parts = template
+            .into_iter()
+            .map(|segment| match segment {
+                FormatSegment::Literal(text) => FormatPart::Literal(text),
+                FormatSegment::Display => FormatPart::Display(args.next().unwrap()),
+                FormatSegment::Debug => FormatPart::Debug(args.next().unwrap()),
+            })
+            .collect();
+        Ok(Expr::Format {
+            parts,
+            span: start..end,
+        })
+    }
+
+    /// Parse a built-in assertion: `assert!(cond)`, `assert_eq!(left, right)`,
+    /// `assert_ne!(left, right)` or `assert_match!(expr, pattern)`, each
+    /// with an optional trailing message.
+    ///
+    /// Desugars to a check that evaluates to `:ok`, or on failure calls
+    /// `erlang:error({assertion_failed, Info})`. `Info` is a map with the
+    /// assertion's source text, line and column, the values involved and
+    /// the message, which `dream test` renders as a failure report.
+    fn parse_assert_macro(&mut self, name: &str) -> ParseResult<Expr> {
+        let start = self.current_span().start;
+        let (line, column) = self.line_col(start);
+        self.advance(); // name
+        self.advance(); // !
+        self.expect(&Token::LParen)?;
+
+        enum Against {
+            Nothing,
+            Value(Expr),
+            Pattern(Pattern, Option<Box<Expr>>),
+        }
+
+        let first = self.parse_expr()?;
+        let against = if name == "assert" {
+            Against::Nothing
+        } else {
+            self.expect(&Token::Comma)?;
+            if name == "assert_match" {
+                let pattern = self.parse_pattern()?;
+                let guard = if self.check(&Token::If) {
+                    self.advance();
+                    Some(Box::new(self.parse_expr()?))
+                } else {
+                    None
+                };
+                Against::Pattern(pattern, guard)
+            } else {
+                Against::Value(self.parse_expr()?)
+            }
+        };
+        let message = if self.check(&Token::Comma) {
+            self.advance();
+            if self.check(&Token::RParen) {
+                None
+            } else {
+                Some(self.parse_expr()?)
+            }
+        } else {
+            None
+        };
+        self.expect(&Token::RParen)?;
+        let end = self.tokens[self.pos - 1].span.end;
+
+        let mut info = vec![
+            (Expr::Atom("source".to_string()), Expr::String(self.source[start..end].to_string())),
+            (Expr::Atom("line".to_string()), Expr::Int(line as i64)),
+            (Expr::Atom("column".to_string()), Expr::Int(column as i64)),
+        ];
+        if let Some(message) = message {
+            info.push((Expr::Atom("message".to_string()), message));
+        }
+        let fail = |mut info: Vec<(Expr, Expr)>, values: &[&str]| {
+            for value in values {
+                info.push((
+                    Expr::Atom(value.to_string()),
+                    Expr::Ident(format!("__assert_{}", value)),
+                ));
+            }
+            Expr::ExternCall {
+                module: "erlang".to_string(),
+                function: "error".to_string(),
+                args: vec![Expr::Tuple(vec![
+                    Expr::Atom("assertion_failed".to_string()),
+                    Expr::MapLiteral(info),
+                ])],
+            }
+        };
+        let ok = || Block {
+            stmts: vec![],
+            expr: Some(Box::new(Expr::Atom("ok".to_string()))),
+        };
+        let check = |cond: Expr, fail: Expr| Expr::If {
+            cond: Box::new(cond),
+            then_block: ok(),
+            else_block: Some(Block {
+                stmts: vec![],
+                expr: Some(Box::new(fail)),
+            }),
+        };
+
+        Ok(match against {
+            // assert!(cond)
+            Against::Nothing => check(first, fail(info, &[])),
+            // assert_eq!(left, right) / assert_ne!(left, right)
+            Against::Value(right) => {
+                let op = if name == "assert_eq" { BinOp::Eq } else { BinOp::Ne };
+                let cond = Expr::Binary {
+                    op,
+                    left: Box::new(Expr::Ident("__assert_left".to_string())),
+                    right: Box::new(Expr::Ident("__assert_right".to_string())),
+                    id: ExprId::fresh(),
+                };
+                Expr::Match {
+                    expr: Box::new(Expr::Tuple(vec![first, right])),
+                    arms: vec![MatchArm {
+                        attrs: vec![],
+                        pattern: Pattern::Tuple(vec![
+                            Pattern::Ident("__assert_left".to_string()),
+                            Pattern::Ident("__assert_right".to_string()),
+                        ]),
+                        guard: None,
+                        body: check(cond, fail(info, &["left", "right"])),
+                    }],
+                }
+            }
+            // assert_match!(expr, pattern)
+            Against::Pattern(pattern, guard) => Expr::Match {
+                expr: Box::new(first),
+                arms: vec![
+                    MatchArm {
+                        attrs: vec![],
+                        pattern,
+                        guard,
+                        body: Expr::Atom("ok".to_string()),
+                    },
+                    MatchArm {
+                        attrs: vec![],
+                        pattern: Pattern::Ident("__assert_value".to_string()),
+                        guard: None,
+                        body: fail(info, &["value"]),
+                    },
+                ],
+            },
+        })
+    }
+
     /// Parse a match expression.
     fn parse_match_expr(&mut self) -> ParseResult<Expr> {
         self.expect(&Token::Match)?;
+        self.hints.if_let.push(false);
         let expr = self.parse_expr()?;
         self.expect(&Token::LBrace)?;
 
@@ -2704,6 +3549,122 @@ impl<'source> Parser<'source> {
         })
     }
 
+    /// Parse a `with` block.
+    /// `with { user <- fetch(id)?; Some(team) <- user.team; Ok(team) }`
+    ///
+    /// Each step `pattern <- value;` goes on with the rest of the block
+    /// when the value matches, and otherwise ends the block with the value
+    /// as it is. `pattern <- value?` is `Ok(pattern) <- value`. The steps
+    /// desugar to nested matches (see [`WITH_FAILURE`]); statements between
+    /// them go at the start of the arm that matched.
+    fn parse_with_expr(&mut self) -> ParseResult<Expr> {
+        self.advance(); // consume `with`
+        self.expect(&Token::LBrace)?;
+
+        // Each step, with the statements that follow it
+        let mut steps: Vec<(Pattern, Expr, Vec<Stmt>)> = Vec::new();
+        let mut value = None;
+        while !self.check(&Token::RBrace) && !self.is_at_end() {
+            if let Some((pattern, expr)) = self.parse_with_step()? {
+                steps.push((pattern, expr, Vec::new()));
+                continue;
+            }
+            let Some((_, _, stmts)) = steps.last_mut() else {
+                return Err(ParseError::new(
+                    "expected `pattern <- value;` to start a `with` block",
+                    self.current_span(),
+                ));
+            };
+            self.parse_stmt_into(stmts, &mut value, false)?;
+        }
+        let end = self.current_span();
+        self.expect(&Token::RBrace)?;
+
+        let Some(mut rest) = value else {
+            let message = if steps.is_empty() {
+                "expected `pattern <- value;` to start a `with` block"
+            } else {
+                "a `with` block must end with its value"
+            };
+            return Err(ParseError::new(message, end));
+        };
+        for (pattern, expr, stmts) in steps.into_iter().rev() {
+            rest = Box::new(Expr::Match {
+                expr: Box::new(expr),
+                arms: vec![
+                    MatchArm {
+                        attrs: vec![],
+                        pattern,
+                        guard: None,
+                        body: Expr::Block(Block { stmts, expr: Some(rest) }),
+                    },
+                    MatchArm {
+                        attrs: vec![],
+                        pattern: Pattern::Ident(WITH_FAILURE.to_string()),
+                        guard: None,
+                        body: Expr::Ident(WITH_FAILURE.to_string()),
+                    },
+                ],
+            });
+        }
+        Ok(*rest)
+    }
+
+    /// Parse a `gen` block, `gen { yield 1; yield 2; }`: a lazy iterator
+    /// over the values it yields. It desugars to
+    /// `iterator::generate(|__yield| { ... })`, with each `yield value`
+    /// a call `__yield(value)` (see [`GEN_YIELD`]).
+    fn parse_gen_expr(&mut self) -> ParseResult<Expr> {
+        self.advance(); // consume `gen`
+        self.gen_depth += 1;
+        let body = self.parse_block();
+        self.gen_depth -= 1;
+        Ok(Expr::Call {
+            func: Box::new(Expr::Path {
+                segments: vec!["iterator".to_string(), "generate".to_string()],
+            }),
+            type_args: vec![],
+            inferred_type_args: vec![],
+            args: vec![Expr::Closure {
+                params: vec![GEN_YIELD.to_string()],
+                body: body?,
+            }],
+        })
+    }
+
+    /// Parse a `with` step, `pattern <- value;` or `pattern <- value?;`.
+    /// Anything else is left for the caller to parse as a statement.
+    fn parse_with_step(&mut self) -> ParseResult<Option<(Pattern, Expr)>> {
+        let start = self.pos;
+        let pattern_rest = self.hints.pattern_rest.len();
+        let pattern = match self.parse_pattern() {
+            Ok(pattern) if self.check(&Token::LArrow) => pattern,
+            _ => {
+                self.pos = start;
+                self.hints.pattern_rest.truncate(pattern_rest);
+                return Ok(None);
+            }
+        };
+        self.advance(); // consume '<-'
+
+        let hint = self.hints.with_try.len();
+        self.hints.with_try.push(false);
+        let value = self.parse_expr()?;
+        self.expect(&Token::Semi)?;
+        match value {
+            Expr::Try { expr } => {
+                self.hints.with_try[hint] = true;
+                let pattern = Pattern::Enum {
+                    name: String::new(),
+                    variant: "Ok".to_string(),
+                    fields: EnumPatternFields::Tuple(vec![pattern]),
+                };
+                Ok(Some((pattern, *expr)))
+            }
+            value => Ok(Some((pattern, value))),
+        }
+    }
+
     /// Parse a single clause in a for loop.
     /// Either a generator (pattern in/into source) or a filter (when expr).
     fn parse_for_clause(&mut self) -> ParseResult<ForClause> {
@@ -2762,6 +3723,7 @@ impl<'source> Parser<'source> {
 
     /// Parse a match arm.
     fn parse_match_arm(&mut self) -> ParseResult<MatchArm> {
+        let attrs = self.parse_cfg_attributes("match arms")?;
         let pattern = self.parse_pattern()?;
 
         let guard = if self.check(&Token::If) {
@@ -2775,6 +3737,7 @@ impl<'source> Parser<'source> {
         let body = self.parse_expr()?;
 
         Ok(MatchArm {
+            attrs,
             pattern,
             guard,
             body,
@@ -2783,6 +3746,10 @@ impl<'source> Parser<'source> {
 
     /// Parse a pattern.
     fn parse_pattern(&mut self) -> ParseResult<Pattern> {
+        self.nested(Self::parse_pattern_inner)
+    }
+
+    fn parse_pattern_inner(&mut self) -> ParseResult<Pattern> {
         // Wildcard
         if self.check(&Token::Underscore) {
             self.advance();
@@ -2858,10 +3825,12 @@ impl<'source> Parser<'source> {
                     } else if self.check(&Token::LBrace) {
                         // Struct variant pattern: mod::Type::Variant { field, field: pattern, .. }
                         self.advance();
+                        let rest = self.push_pattern_rest_hint();
                         let mut fs = Vec::new();
                         while !self.check(&Token::RBrace) && !self.is_at_end() {
                             if self.check(&Token::DotDot) {
                                 self.advance();
+                                self.hints.pattern_rest[rest] = true;
                                 break;
                             }
                             let field_name = self.expect_ident()?;
@@ -2895,10 +3864,12 @@ impl<'source> Parser<'source> {
                     if self.check(&Token::LBrace) {
                         // Module-qualified struct pattern
                         self.advance();
+                        let rest = self.push_pattern_rest_hint();
                         let mut fields = Vec::new();
                         while !self.check(&Token::RBrace) && !self.is_at_end() {
                             if self.check(&Token::DotDot) {
                                 self.advance();
+                                self.hints.pattern_rest[rest] = true;
                                 break;
                             }
                             let field_name = self.expect_ident()?;
@@ -2960,10 +3931,12 @@ impl<'source> Parser<'source> {
                 } else if self.check(&Token::LBrace) {
                     // Struct variant pattern: Type::Variant { field, field: pattern, .. }
                     self.advance();
+                    let rest = self.push_pattern_rest_hint();
                     let mut fs = Vec::new();
                     while !self.check(&Token::RBrace) && !self.is_at_end() {
                         if self.check(&Token::DotDot) {
                             self.advance();
+                            self.hints.pattern_rest[rest] = true;
                             break;
                         }
                         let field_name = self.expect_ident()?;
@@ -2996,11 +3969,13 @@ impl<'source> Parser<'source> {
             // Struct pattern: Name { field: pattern, ... } or Name { field, ... } or Name { field, .. }
             if self.check(&Token::LBrace) {
                 self.advance();
+                let rest = self.push_pattern_rest_hint();
                 let mut fields = Vec::new();
                 while !self.check(&Token::RBrace) && !self.is_at_end() {
                     // Check for rest pattern `..` (ignores remaining fields)
                     if self.check(&Token::DotDot) {
                         self.advance();
+                        self.hints.pattern_rest[rest] = true;
                         // `..` must be last, skip to closing brace
                         break;
                     }
@@ -3156,6 +4131,11 @@ impl<'source> Parser<'source> {
         } else if let Some(Token::Int(n)) = self.peek().cloned() {
             self.advance();
             Pattern::Int(n)
+        } else if let Some(Token::String(raw)) = self.peek().cloned() {
+            // A string literal matches its bytes: `<<"GET ", path/binary>>`
+            self.advance();
+            let pattern = Pattern::String(process_escapes(&raw));
+            return Ok(BitStringSegment::new(Box::new(pattern)));
         } else if let Some(Token::Ident(name)) = self.peek().cloned() {
             self.advance();
             Pattern::Ident(name)
@@ -3180,6 +4160,8 @@ impl<'source> Parser<'source> {
                 let span = self.current_span();
                 return Err(ParseError::new("expected size in bit string segment", span));
             }
+        } else if let Some(name) = self.bitstring_size_var() {
+            segment.size = Some(Box::new(Expr::Ident(name)));
         }
 
         // Parse optional type specifiers: `/specifier-specifier-...`
@@ -3255,6 +4237,10 @@ impl<'source> Parser<'source> {
 
     /// Parse a type.
     fn parse_type(&mut self) -> ParseResult<Type> {
+        self.nested(Self::parse_type_inner)
+    }
+
+    fn parse_type_inner(&mut self) -> ParseResult<Type> {
         // Parse primary type, then check for union (|)
         let first = self.parse_primary_type()?;
 
@@ -3435,6 +4421,13 @@ impl<'source> Parser<'source> {
 
     // === Helper methods ===
 
+    /// Record a struct pattern that may end in `..`, returning its index in
+    /// `hints.pattern_rest`.
+    fn push_pattern_rest_hint(&mut self) -> usize {
+        self.hints.pattern_rest.push(false);
+        self.hints.pattern_rest.len() - 1
+    }
+
     fn peek(&self) -> Option<&Token> {
         self.tokens.get(self.pos).map(|t| &t.token)
     }
@@ -3483,7 +4476,7 @@ impl<'source> Parser<'source> {
             self.pending_gt = true; // We've used one `>`, save the other
             Ok(())
         } else if self.is_at_end() {
-            Err(ParseError::unexpected_eof(">"))
+            Err(self.eof_error(">"))
         } else {
             let span = self.current_span();
             Err(ParseError::unexpected_token(
@@ -3496,7 +4489,6 @@ impl<'source> Parser<'source> {
 
     // TODO: Use these for parsing contextual keywords like `type` in type aliases
     /// Check if current token is an identifier with specific value (for contextual keywords like `type`)
-    #[allow(dead_code)]
     fn check_ident(&self, value: &str) -> bool {
         matches!(self.peek(), Some(Token::Ident(s)) if s == value)
     }
@@ -3517,6 +4509,25 @@ impl<'source> Parser<'source> {
         self.pos >= self.tokens.len()
     }
 
+    /// 1-based line and column of a byte offset in the source.
+    fn line_col(&self, offset: usize) -> (usize, usize) {
+        let before = &self.source[..offset.min(self.source.len())];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+    }
+
+    /// Run `parse` one level deeper, failing if that is deeper than
+    /// [`MAX_DEPTH`].
+    fn nested<T>(&mut self, parse: impl FnOnce(&mut Self) -> ParseResult<T>) -> ParseResult<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(ParseError::new("nested too deeply", self.current_span()));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
     fn current_span(&self) -> Span {
         self.tokens
             .get(self.pos)
@@ -3529,15 +4540,192 @@ impl<'source> Parser<'source> {
             self.advance();
             Ok(())
         } else if self.is_at_end() {
-            Err(ParseError::unexpected_eof(&expected.to_string()))
+            Err(self.eof_error(&expected.to_string()))
         } else {
             let span = self.current_span();
-            Err(ParseError::unexpected_token(
+            let error = ParseError::unexpected_token(
                 self.peek().unwrap(),
                 &expected.to_string(),
                 span,
-            ))
+            );
+            Err(self.annotate_missing(error, expected))
+        }
+    }
+
+    /// The outcome of an entry point: the first error found, whether it was
+    /// recovered from or ended parsing, if there was one.
+    fn finish<T>(&mut self, result: ParseResult<T>) -> ParseResult<T> {
+        if let Err(error) = &result {
+            self.record(error.clone());
+        }
+        match self.errors.first() {
+            Some(error) => Err(error.clone()),
+            None => result,
+        }
+    }
+
+    /// Keep an error to report once parsing is done. An error at the same
+    /// place as the one before it is a knock-on of it, as when every
+    /// enclosing block finds the end of the file, and is dropped.
+    fn record(&mut self, error: ParseError) {
+        if self.errors.last().is_some_and(|last| last.span.offset() == error.span.offset()) {
+            return;
+        }
+        self.errors.push(error);
+    }
+
+    /// Parse an item onto `items`, or record why it couldn't be parsed and
+    /// skip past it.
+    fn parse_item_into(&mut self, items: &mut Vec<Item>) {
+        let start = self.pos;
+        match self.parse_item() {
+            Ok(item) => items.push(item),
+            Err(error) => {
+                self.record(error);
+                self.recover_item(start);
+            }
+        }
+    }
+
+    /// Skip the rest of the item starting at token `start`: up to the next
+    /// line that begins with an item keyword and is indented no deeper than
+    /// the item, or with a `}` indented less, which closes its module.
+    fn recover_item(&mut self, start: usize) {
+        let Some(first) = self.tokens.get(start) else {
+            return;
+        };
+        let (_, column) = self.line_col(first.span.start);
+        if self.pos == start {
+            self.advance();
+        }
+        while let Some(t) = self.tokens.get(self.pos) {
+            if self.starts_line(self.pos) {
+                let (_, col) = self.line_col(t.span.start);
+                let item = matches!(
+                    t.token,
+                    Token::Fn
+                        | Token::Pub
+                        | Token::Struct
+                        | Token::Enum
+                        | Token::Type
+                        | Token::Mod
+                        | Token::Impl
+                        | Token::Trait
+                        | Token::Extern
+                        | Token::Use
+                        | Token::HashBracket
+                );
+                if (item && col <= column) || (t.token == Token::RBrace && col < column) {
+                    return;
+                }
+            }
+            self.advance();
+        }
+    }
+
+    /// Record `error` and skip the rest of the statement starting at token
+    /// `start`: through the next `;` outside brackets, or up to the `}`
+    /// closing the block or a new line once its brackets are closed. A
+    /// statement that failed at the start of a line is most likely missing
+    /// its `;`, so parsing resumes there. When not recovering, `error` is
+    /// returned instead.
+    fn recover_statement(&mut self, error: ParseError, start: usize) -> ParseResult<()> {
+        if !self.recovering {
+            return Err(error);
+        }
+        self.record(error);
+        if self.pos > start && self.starts_line(self.pos) {
+            return Ok(());
+        }
+
+        // Closers of the brackets the statement has left open
+        let mut open = Vec::new();
+        for t in &self.tokens[start..self.pos] {
+            track_delimiter(&mut open, &t.token);
+        }
+        while let Some(token) = self.peek().cloned() {
+            if open.is_empty() && self.pos > start && self.starts_line(self.pos) {
+                break;
+            }
+            match token {
+                Token::RBrace if !open.contains(&Token::RBrace) => break,
+                Token::Semi if open.is_empty() => {
+                    self.advance();
+                    break;
+                }
+                _ => track_delimiter(&mut open, &token),
+            }
+            self.advance();
+        }
+
+        // Only a stray `}` in a script stops the skip where it started
+        if self.pos == start {
+            self.advance();
+        }
+        Ok(())
+    }
+
+    /// Whether token `pos` is the first on its line.
+    fn starts_line(&self, pos: usize) -> bool {
+        let Some(t) = self.tokens.get(pos) else {
+            return false;
+        };
+        let line_start = self.source[..t.span.start].rfind('\n').map_or(0, |i| i + 1);
+        self.source[line_start..t.span.start].trim().is_empty()
+    }
+
+    /// The error for reaching the end of the file while expecting
+    /// `expected`. It points at the end of the file, and at the innermost
+    /// delimiter still open.
+    fn eof_error(&self, expected: &str) -> ParseError {
+        let end = self.source.len();
+        let mut error = ParseError::unexpected_eof(expected);
+        error.span = (end..end).into();
+        match self.unclosed_delimiter() {
+            Some((_, open)) => error.with_label(open, "unclosed delimiter"),
+            None => error,
+        }
+    }
+
+    /// Add what helps with a missing `expected` token to `error`: the
+    /// delimiter a missing closing one would close, or where a missing
+    /// separator goes.
+    fn annotate_missing(&self, error: ParseError, expected: &Token) -> ParseError {
+        match expected {
+            Token::RParen | Token::RBrace | Token::RBracket => match self.unclosed_delimiter() {
+                Some((open, span)) if closer_of(&open) == Some(expected.clone()) => {
+                    error.with_label(span, "unclosed delimiter")
+                }
+                _ => error,
+            },
+            Token::Semi | Token::Comma => match self.pos.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+                Some(previous) => {
+                    let at = previous.span.end..previous.span.end;
+                    error.with_suggestion(at, expected.to_string(), format!("add `{}` here", expected))
+                }
+                None => error,
+            },
+            _ => error,
+        }
+    }
+
+    /// The innermost delimiter opened before the current token and not
+    /// closed by then, with its span.
+    fn unclosed_delimiter(&self) -> Option<(Token, Span)> {
+        let mut depth = 0usize;
+        for t in self.tokens[..self.pos.min(self.tokens.len())].iter().rev() {
+            match t.token {
+                Token::RParen | Token::RBrace | Token::RBracket => depth += 1,
+                Token::LParen | Token::LBrace | Token::LBracket | Token::HashBracket => {
+                    if depth == 0 {
+                        return Some((t.token.clone(), t.span.clone()));
+                    }
+                    depth -= 1;
+                }
+                _ => {}
+            }
         }
+        None
     }
 
     fn expect_ident(&mut self) -> ParseResult<String> {
@@ -3545,7 +4733,7 @@ impl<'source> Parser<'source> {
             self.advance();
             Ok(name)
         } else if self.is_at_end() {
-            Err(ParseError::unexpected_eof("identifier"))
+            Err(self.eof_error("identifier"))
         } else {
             let span = self.current_span();
             Err(ParseError::unexpected_token(
@@ -3580,7 +4768,7 @@ impl<'source> Parser<'source> {
             self.advance();
             Ok(name)
         } else if self.is_at_end() {
-            Err(ParseError::unexpected_eof("type identifier"))
+            Err(self.eof_error("type identifier"))
         } else {
             let span = self.current_span();
             Err(ParseError::unexpected_token(
@@ -3599,7 +4787,7 @@ impl<'source> Parser<'source> {
             self.advance();
             Ok(name)
         } else if self.is_at_end() {
-            Err(ParseError::unexpected_eof("identifier"))
+            Err(self.eof_error("identifier"))
         } else {
             let span = self.current_span();
             Err(ParseError::unexpected_token(
@@ -3611,8 +4799,9 @@ impl<'source> Parser<'source> {
     }
 
     /// Expect an identifier or a keyword that can be used as a function name.
-    /// This is needed for extern blocks where Erlang/Elixir function names may
-    /// be Dream keywords (spawn, receive, self, etc.)
+    /// Erlang/Elixir function names may be Dream keywords (spawn, receive,
+    /// self, etc.), and so may module functions called by a qualified path,
+    /// like `process::spawn`.
     fn expect_ident_or_keyword(&mut self) -> ParseResult<String> {
         let name = match self.peek().cloned() {
             Some(Token::Ident(name)) => name,
@@ -3631,7 +4820,7 @@ impl<'source> Parser<'source> {
             // Bit segment type keywords that might be function names
             Some(Token::Float) => "float".to_string(),
             Some(Token::Integer) => "integer".to_string(),
-            None => return Err(ParseError::unexpected_eof("identifier")),
+            None => return Err(self.eof_error("identifier")),
             _ => {
                 let span = self.current_span();
                 return Err(ParseError::unexpected_token(
@@ -3661,6 +4850,7 @@ impl<'source> Parser<'source> {
                 LexStringPart::Interpolation(expr_str) => {
                     // Create a new parser for the expression string
                     let mut sub_parser = Parser::new(&expr_str);
+                    sub_parser.depth = self.depth;
                     let expr = sub_parser.parse_expr()?;
 
                     // Ensure the entire expression was consumed
@@ -3790,6 +4980,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_tuple_struct() {
+        let source = r#"
+            mod test {
+                pub struct Meters(float);
+                struct Pair<T>(T, T);
+            }
+        "#;
+        let mut parser = Parser::new(source);
+        let module = parser.parse_module().unwrap();
+        let items = user_items(&module);
+
+        if let Item::Struct(s) = &items[0] {
+            assert_eq!(s.name, "Meters");
+            assert_eq!(s.fields, vec![("0".to_string(), Type::Float)]);
+            assert!(s.is_tuple());
+            assert!(s.is_pub);
+        } else {
+            panic!("expected struct");
+        }
+        if let Item::Struct(s) = &items[1] {
+            assert_eq!(s.type_params.len(), 1);
+            assert_eq!(s.fields.len(), 2);
+            assert_eq!(s.fields[1].0, "1");
+        } else {
+            panic!("expected struct");
+        }
+
+        let mut parser = Parser::new("mod test { struct Nothing(); }");
+        let err = parser.parse_module().unwrap_err();
+        assert!(err.message.contains("has no fields"));
+    }
+
     #[test]
     fn test_parse_enum() {
         let source = r#"
@@ -4152,6 +5375,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_keyword_function_names() {
+        let source = r#"
+            mod process {
+                pub fn spawn(f: fn() -> Any) -> Pid {
+                    erl::spawn(f)
+                }
+
+                fn start() -> float {
+                    process::spawn(|| { :ok });
+                    codec::float(1)
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().unwrap();
+        let names: Vec<_> = user_items(&module)
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(f) => Some(f.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["spawn", "start"]);
+
+        let Item::Function(start) = &user_items(&module)[1] else {
+            panic!("expected function");
+        };
+        match start.body.stmts.first() {
+            Some(Stmt::Expr { expr: Expr::Call { func, .. }, .. }) => assert_eq!(
+                **func,
+                Expr::Path { segments: vec!["process".into(), "spawn".into()] }
+            ),
+            other => panic!("expected call, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_parse_list_cons_pattern() {
         let source = r#"
@@ -4679,6 +5938,138 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_parse_binary_param() {
+        let source = r#"
+        mod test {
+            fn parse(<<"GET ", path/binary>>) -> Binary {
+                path
+            }
+        }
+        "#;
+        let mut parser = Parser::new(source);
+        let module = parser.parse_module().unwrap();
+
+        let Item::Function(f) = first_user_item(&module) else {
+            panic!("expected function");
+        };
+        let Pattern::BitString(segments) = &f.params[0].pattern else {
+            panic!("expected BitString pattern");
+        };
+        assert_eq!(*segments[0].value, Pattern::String("GET ".to_string()));
+        assert_eq!(segments[1].segment_type, BitSegmentType::Binary);
+        assert_eq!(
+            f.params[0].ty,
+            Type::Named {
+                name: "Binary".to_string(),
+                type_args: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_binary_pattern_escapes() {
+        let source = r#"
+        mod test {
+            fn first_line(<<"a\n", rest/binary>>) -> Binary {
+                rest
+            }
+        }
+        "#;
+        let mut parser = Parser::new(source);
+        let module = parser.parse_module().unwrap();
+
+        let Item::Function(f) = first_user_item(&module) else {
+            panic!("expected function");
+        };
+        let Pattern::BitString(segments) = &f.params[0].pattern else {
+            panic!("expected BitString pattern");
+        };
+        assert_eq!(*segments[0].value, Pattern::String("a\n".to_string()));
+    }
+
+    #[test]
+    fn test_parse_binary_variable_size() {
+        let source = r#"
+        mod test {
+            fn body(<<len:16, body:len/binary, rest/binary>>) -> Binary {
+                <<len:16, body:len/binary>>
+            }
+        }
+        "#;
+        let mut parser = Parser::new(source);
+        let module = parser.parse_module().unwrap();
+
+        let Item::Function(f) = first_user_item(&module) else {
+            panic!("expected function");
+        };
+        let Pattern::BitString(segments) = &f.params[0].pattern else {
+            panic!("expected BitString pattern");
+        };
+        assert_eq!(*segments[1].value, Pattern::Ident("body".to_string()));
+        assert_eq!(segments[1].size.as_deref(), Some(&Expr::Ident("len".to_string())));
+        assert_eq!(segments[1].segment_type, BitSegmentType::Binary);
+
+        let Some(Expr::BitString(segments)) = f.body.expr.as_deref() else {
+            panic!("expected BitString expression");
+        };
+        assert_eq!(segments[1].size.as_deref(), Some(&Expr::Ident("len".to_string())));
+        assert_eq!(segments[1].segment_type, BitSegmentType::Binary);
+    }
+
+    #[test]
+    fn test_parse_function_clauses() {
+        let source = "fn fact(0) -> int {\n    1\n}\n\nfn fact(n) {\n    n * fact(n - 1)\n}\n";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let clauses: Vec<&Function> = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(f) if f.name == "fact" => Some(f),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(clauses.len(), 2);
+        assert_eq!(clauses[1].params[0].ty, clauses[0].params[0].ty);
+        assert_eq!(clauses[1].return_type, clauses[0].return_type);
+
+        // Clauses are grouped by arity, so another arity is another function
+        let source = "fn greet(name: String) -> String {\n    greet(name, \"Hello\")\n}\n\nfn greet(name: String, greeting: String) -> String {\n    greeting\n}\n";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let arities: Vec<usize> = module
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(f) if f.name == "greet" => Some(f.params.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(arities, [1, 2]);
+        let source = "fn pad(s: String) -> String {\n    pad(s, 1)\n}\n\nfn pad(s: String, n: int) -> String {\n    s\n}\n\nfn pad(s, 0) {\n    s\n}\n";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let Some(Item::Function(last)) = module.items.last() else {
+            panic!("expected function");
+        };
+        assert_eq!(last.params[0].ty, Type::Named { name: "String".to_string(), type_args: vec![] });
+        assert_eq!(last.return_type, Some(Type::Named { name: "String".to_string(), type_args: vec![] }));
+
+        // A clause of another arity leaving its types out meant to be a clause
+        let source = "fn area(side: int) -> int {\n    side * side\n}\n\nfn area(w, h) {\n    w * h\n}\n";
+        let err = Parser::new(source).parse_file("app").unwrap_err();
+        assert_eq!(err.code, ErrorCode::ClauseArity);
+        assert_eq!(
+            err.message,
+            "this clause of `area` takes 2 arguments but its first clause takes 1"
+        );
+        assert_eq!(err.annotations.labels[0].span, 7..18);
+
+        // Clauses under `#[cfg]` are alternatives, and other modules start afresh
+        let source = "#[cfg(test)]\nfn now() -> int {\n    0\n}\n\nfn now(clock: atom) -> int {\n    1\n}\n";
+        assert!(Parser::new(source).parse_file("app").is_ok());
+        let source = "mod a {\n    fn f(x: int) -> int {\n        x\n    }\n}\nmod b {\n    fn f(x) -> int {\n        x\n    }\n}\n";
+        assert!(Parser::new(source).parse_file_modules("app").is_err());
+    }
+
     #[test]
     fn test_parse_empty_binary() {
         let source = r#"
@@ -4871,6 +6262,55 @@ mod tests {
         assert_eq!(nested_mod.unwrap().items.len(), 2);
     }
 
+    #[test]
+    fn test_parse_extern_block() {
+        let source = r#"
+            mod test {
+                extern "erlang" {
+                    fn lists::reverse<T>(list: [T]) -> [T];
+                    #[name = "is_process_alive"]
+                    fn erlang::alive(pid: pid) -> bool;
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("extern block should parse");
+        let Some(Item::ExternBlock(block)) = user_items(&module).iter().find(|item| matches!(item, Item::ExternBlock(_))) else {
+            panic!("should have extern block");
+        };
+        assert_eq!(block.abi, "erlang");
+        let names: Vec<(&str, &str)> = block.functions.iter().map(|(m, f)| (m.as_str(), f.name.as_str())).collect();
+        assert_eq!(names, [("lists", "reverse"), ("erlang", "alive")]);
+        assert_eq!(block.functions[0].1.type_params.len(), 1);
+        assert_eq!(block.functions[1].1.attrs[0].name, "name");
+
+        let err = Parser::new("mod test { extern \"C\" { fn puts(s: string); } }").parse_module().unwrap_err();
+        assert_eq!(err.message, "unsupported ABI \"C\"");
+        let err = Parser::new("mod test { extern \"erlang\" { fn reverse(l: any) -> any; } }").parse_module().unwrap_err();
+        assert_eq!(err.message, "expected `::` after the module name");
+
+        let source = "mod test { extern \"elixir\" { fn Enum::sum(l: [int]) -> int; fn Phoenix::PubSub::broadcast(p: atom, t: string, m: any) -> atom; } }";
+        let module = Parser::new(source).parse_module().unwrap();
+        let Some(Item::ExternBlock(block)) = user_items(&module).iter().find(|item| matches!(item, Item::ExternBlock(_))) else {
+            panic!("should have extern block");
+        };
+        assert_eq!(block.abi, "elixir");
+        assert_eq!(block.functions[1].0, "Phoenix::PubSub");
+        assert_eq!(block.beam_module_name(&block.functions[1].0), "Elixir.Phoenix.PubSub");
+        assert_eq!(ExternBlock::local_module_name(&block.functions[1].0), "PubSub");
+        let err = Parser::new("mod test { extern \"erlang\" { fn a::b::c(); } }").parse_module().unwrap_err();
+        assert_eq!(err.message, "Erlang module `a::b` has more than one segment");
+
+        let module = Parser::new("mod test { extern \"nif\" { fn add(a: int, b: int) -> int; } }").parse_module().unwrap();
+        let Some(Item::ExternBlock(block)) = user_items(&module).iter().find(|item| matches!(item, Item::ExternBlock(_))) else {
+            panic!("should have extern block");
+        };
+        assert_eq!(block.abi, "nif");
+        assert_eq!(block.functions[0].0, "");
+        assert_eq!(block.functions[0].1.name, "add");
+        let err = Parser::new("mod test { extern \"nif\" { fn math::add(a: int) -> int; } }").parse_module().unwrap_err();
+        assert_eq!(err.message, "NIF functions can't name a module");
+    }
+
     #[test]
     fn test_parse_stdlib_binding_file() {
         // Test parsing an stdlib binding file (lists module)
@@ -4904,6 +6344,50 @@ mod tests {
         assert!(fn_count >= 20, "lists mod should have at least 20 functions, got {}", fn_count);
     }
 
+    #[test]
+    fn test_parse_every_stdlib_module() {
+        // The stdlib is loaded file by file, and one that fails to parse
+        // stops the modules after it from loading at all
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("stdlib");
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "dream") {
+                let source = std::fs::read_to_string(&path).unwrap();
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                if let Err(err) = Parser::new(&source).parse_file(&name) {
+                    panic!("{} should parse: {} at {:?}", path.display(), err.message, err.span);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_stdlib_json_is_feature_gated() {
+        use crate::compiler::cfg::should_include;
+        use crate::config::CompileOptions;
+
+        let source = include_str!("../../stdlib/json.dream");
+        let module = Parser::new(source).parse_file("json").expect("json.dream should parse");
+        let enabled = CompileOptions::with_features(["json".to_string()].into());
+        let disabled = CompileOptions::new();
+
+        let public: Vec<_> = user_items(&module)
+            .iter()
+            .filter_map(|item| match item {
+                Item::Function(f) if f.is_pub => Some((f.name.as_str(), &f.attrs)),
+                Item::Enum(e) if e.is_pub => Some((e.name.as_str(), &e.attrs)),
+                _ => None,
+            })
+            .collect();
+        for name in ["Json", "Error", "encode", "decode", "decode_as"] {
+            assert!(public.iter().any(|(n, _)| *n == name), "missing {}", name);
+        }
+        for (name, attrs) in public {
+            assert!(should_include(attrs, &enabled), "{} should be in with the feature", name);
+            assert!(!should_include(attrs, &disabled), "{} should be out without the feature", name);
+        }
+    }
+
     // ========== Union Type Parser Tests ==========
 
     #[test]
@@ -5581,6 +7065,132 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_cfg_on_statements() {
+        let source = "fn f(x: int) -> int {\n    #[cfg(test)]\n    log(x);\n    #[cfg(test)]\n    1\n    #[cfg(not(test))]\n    match x {\n        #[cfg(test)]\n        0 => 0,\n        _ => x,\n    }\n}";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let Some(Item::Function(func)) = module.items.last() else {
+            panic!("expected a function");
+        };
+        assert!(func.body.expr.is_none());
+        let tails: Vec<bool> = func
+            .body
+            .stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Cfg { attrs, tail, .. } => {
+                    assert_eq!(attrs[0].name, "cfg");
+                    *tail
+                }
+                other => panic!("expected a cfg statement, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(tails, vec![false, true, true]);
+        if let Stmt::Cfg { stmt, .. } = &func.body.stmts[2] {
+            if let Stmt::Expr { expr: Expr::Match { arms, .. }, .. } = stmt.as_ref() {
+                assert_eq!(arms[0].attrs.len(), 1);
+                assert!(arms[1].attrs.is_empty());
+            }
+        }
+
+        let err = Parser::new("fn f() {\n    #[test]\n    g();\n}").parse_file("app").unwrap_err();
+        assert_eq!(err.code, ErrorCode::MalformedAttribute);
+        assert!(err.message.contains("can't be used on statements"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_parse_compile_error() {
+        let source = "#[cfg(feature = \"a\")]\ncompile_error!(\"no \\\"a\\\"\");\n\nfn f() -> int {\n    compile_error!(\"f\")\n}";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let Some(Item::CompileError(item)) = module.items.iter().find(|item| matches!(item, Item::CompileError(_))) else {
+            panic!("expected compile_error!");
+        };
+        assert_eq!(item.attrs[0].name, "cfg");
+        assert_eq!(item.message, "no \"a\"");
+        assert_eq!(&source[item.span.clone()], "compile_error!(\"no \\\"a\\\"\")");
+        let Some(Item::Function(func)) = module.items.last() else {
+            panic!("expected a function");
+        };
+        assert!(matches!(
+            func.body.expr.as_deref(),
+            Some(Expr::CompileError { message, .. }) if message == "f"
+        ));
+
+        let err = Parser::new("fn f() {\n    compile_error!(\"{x}\")\n}").parse_file("app").unwrap_err();
+        assert!(err.message.contains("takes a string literal"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let source = "fn f() -> string {\n    format!(\"{} is {:?}\", name, 1 + 2)\n}";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let Some(Item::Function(func)) = module.items.last() else {
+            panic!("expected a function");
+        };
+        let Some(Expr::Format { parts, span }) = func.body.expr.as_deref() else {
+            panic!("expected format!");
+        };
+        assert_eq!(&source[span.clone()], "format!(\"{} is {:?}\", name, 1 + 2)");
+        assert!(matches!(&parts[..], [
+            FormatPart::Display(name),
+            FormatPart::Literal(is),
+            FormatPart::Debug(_),
+        ] if **name == Expr::Ident("name".to_string()) && is == " is "));
+
+        let err = Parser::new("fn f() {\n    format!(\"{} and {}\", a)\n}").parse_file("app").unwrap_err();
+        assert!(err.message.contains("2 placeholders but 1 argument was given"), "{}", err.message);
+        let err = Parser::new("fn f() {\n    format!(\"{name}\", a)\n}").parse_file("app").unwrap_err();
+        assert!(err.message.contains("unknown placeholder"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_error_annotations() {
+        // A missing `;` is suggested right after the statement
+        let err = Parser::new("fn f() -> int {\n    let x = 1\n    x\n}").parse_file("app").unwrap_err();
+        assert_eq!(err.annotations.suggestions.len(), 1);
+        assert_eq!(err.annotations.suggestions[0].span, 29..29);
+        assert_eq!(err.annotations.suggestions[0].replacement, ";");
+
+        // Running out of input points at the end and at what was left open
+        let err = Parser::new("fn f() {\n    g(1").parse_file("app").unwrap_err();
+        assert_eq!(err.code, ErrorCode::UnexpectedEof);
+        assert_eq!(err.span.offset(), 16);
+        assert_eq!(err.annotations.labels[0].span, 14..15);
+        assert_eq!(err.annotations.labels[0].message, "unclosed delimiter");
+
+        let err = Parser::new("#[cfg(not(a, b))]\nfn f() {}").parse_file("app").unwrap_err();
+        assert_eq!(err.code, ErrorCode::MalformedAttribute);
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let source = "fn a() {\n    let x = ;\n    let y = 1\n    y\n}\n\nstruct S {\n    x: int\n    y: int\n}\n\nfn b() -> int {\n    foo(1 2);\n    3\n}\n\nfn c() -> int { 1 }\n";
+        let mut parser = Parser::new(source);
+        let err = parser.parse_file("app").unwrap_err();
+        let offsets: Vec<usize> = parser.errors().iter().map(|e| e.span.offset()).collect();
+        assert_eq!(
+            offsets,
+            vec![
+                source.find("= ;").unwrap() + 2,
+                source.find("    y\n}").unwrap() + 4,
+                source.find("    y: int").unwrap() + 4,
+                source.find("2)").unwrap(),
+            ]
+        );
+        assert_eq!(err, parser.errors()[0]);
+
+        // Errors in one wrapped module don't hide those in the next
+        let source = "mod a {\n    fn f() { 1 2 }\n}\n\nmod b {\n    fn g( {}\n}\n";
+        let mut parser = Parser::new(source);
+        assert!(parser.parse_file_modules("app").is_err());
+        assert_eq!(parser.errors().len(), 2);
+
+        // An unclosed block is reported once, not once per enclosing block
+        let mut parser = Parser::new("fn f() {\n    if x {\n        1\n");
+        assert!(parser.parse_file("app").is_err());
+        assert_eq!(parser.errors().len(), 1);
+    }
+
     #[test]
     fn test_parse_wrapped_module_with_leading_comments() {
         let source = r#"// Define modules here. All code must be inside mod blocks.
@@ -5866,6 +7476,104 @@ mod repl_edit {
         }
     }
 
+    #[test]
+    fn test_parse_with_block() {
+        let source = r#"
+            mod test {
+                fn load(id: int) {
+                    with {
+                        user <- fetch(id)?;
+                        Some(team) <- user.team;
+                        let name = team.name;
+                        Ok(name)
+                    }
+                }
+            }
+        "#;
+        let mut parser = Parser::new(source);
+        let module = parser.parse_module().unwrap();
+        assert_eq!(parser.hints.with_try, vec![true, false]);
+
+        let Item::Function(f) = first_user_item(&module) else {
+            panic!("expected function");
+        };
+        let Some(Expr::Match { expr, arms }) = f.body.expr.as_deref() else {
+            panic!("expected match, got {:?}", f.body.expr);
+        };
+        // `user <- fetch(id)?` matches `Ok(user)` against `fetch(id)`
+        assert!(matches!(expr.as_ref(), Expr::Call { .. }));
+        let (pattern, rest) = with_step(arms).expect("first step");
+        assert!(matches!(pattern, Pattern::Enum { variant, .. } if variant == "Ok"));
+        assert!(rest.stmts.is_empty());
+
+        let Some(Expr::Match { arms, .. }) = rest.expr.as_deref() else {
+            panic!("expected second step");
+        };
+        let (pattern, rest) = with_step(arms).expect("second step");
+        assert!(matches!(pattern, Pattern::Enum { variant, .. } if variant == "Some"));
+        assert_eq!(rest.stmts.len(), 1);
+        assert!(matches!(rest.expr.as_deref(), Some(Expr::EnumVariant { .. })));
+    }
+
+    #[test]
+    fn test_parse_with_errors() {
+        let parse = |body: &str| {
+            Parser::new(&format!("mod test {{ fn f() {{ {} }} }}", body)).parse_module()
+        };
+        assert!(parse("with { x <- f(); x }").is_ok());
+        // `with` is still a name anywhere else
+        assert!(parse("let with = 1; with").is_ok());
+        assert!(parse("with { let x = 1; x }").is_err());
+        assert!(parse("with { x <- f(); }").is_err());
+    }
+
+    #[test]
+    fn test_parse_gen_block() {
+        let source = r#"
+            mod test {
+                fn pair(a: int) -> Iter {
+                    gen {
+                        yield a;
+                        yield a + 1
+                    }
+                }
+            }
+        "#;
+        let mut parser = Parser::new(source);
+        let module = parser.parse_module().unwrap();
+
+        let Item::Function(f) = first_user_item(&module) else {
+            panic!("expected function");
+        };
+        let Some(Expr::Call { func, args, .. }) = f.body.expr.as_deref() else {
+            panic!("expected call, got {:?}", f.body.expr);
+        };
+        let body = gen_block(func, args).expect("gen block");
+        let Stmt::Expr {
+            expr: Expr::Call { func, args, .. },
+            ..
+        } = &body.stmts[0]
+        else {
+            panic!("expected yield, got {:?}", body.stmts[0]);
+        };
+        assert!(matches!(yielded(func, args), Some(Expr::Ident(name)) if name == "a"));
+        let Some(Expr::Call { func, args, .. }) = body.expr.as_deref() else {
+            panic!("expected yield, got {:?}", body.expr);
+        };
+        assert!(matches!(yielded(func, args), Some(Expr::Binary { .. })));
+    }
+
+    #[test]
+    fn test_parse_gen_errors() {
+        let parse = |body: &str| {
+            Parser::new(&format!("mod test {{ fn f() {{ {} }} }}", body)).parse_module()
+        };
+        assert!(parse("gen { yield 1; yield 2 }").is_ok());
+        // `gen` and `yield` are still names anywhere else
+        assert!(parse("let gen = 1; let yield = gen; yield").is_ok());
+        assert!(parse("yield 1").is_err());
+    }
+
     #[test]
     fn test_parse_empty_map_literal() {
         // Test that empty map literal {} works
@@ -6061,4 +7769,119 @@ mod repl_edit {
             panic!("expected function");
         }
     }
+
+    /// Body expression of the first user function in a wrapped module.
+    fn first_body(source: &str) -> Expr {
+        let module = Parser::new(source).parse_module().unwrap();
+        match first_user_item(&module) {
+            Item::Function(f) => *f.body.expr.clone().unwrap(),
+            _ => panic!("expected function"),
+        }
+    }
+
+    fn assertion_info(expr: &Expr) -> &[(Expr, Expr)] {
+        match expr {
+            Expr::ExternCall { module, function, args } if module == "erlang" && function == "error" => {
+                match &args[0] {
+                    Expr::Tuple(parts) => match (&parts[0], &parts[1]) {
+                        (Expr::Atom(tag), Expr::MapLiteral(info)) if tag == "assertion_failed" => info,
+                        _ => panic!("expected assertion_failed tuple"),
+                    },
+                    _ => panic!("expected tuple"),
+                }
+            }
+            _ => panic!("expected erlang:error call, got {:?}", expr),
+        }
+    }
+
+    fn info_keys(info: &[(Expr, Expr)]) -> Vec<&str> {
+        info.iter()
+            .map(|(k, _)| match k {
+                Expr::Atom(a) => a.as_str(),
+                _ => panic!("expected atom key"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_assert_macro() {
+        let expr = first_body(
+            r#"
+            mod test {
+                fn check(x: int) {
+                    assert!(x > 0, "x must be positive")
+                }
+            }
+        "#,
+        );
+        let Expr::If { cond, then_block, else_block } = expr else {
+            panic!("expected if");
+        };
+        assert!(matches!(*cond, Expr::Binary { op: BinOp::Gt, .. }));
+        assert_eq!(then_block.expr.as_deref(), Some(&Expr::Atom("ok".to_string())));
+
+        let fail = else_block.unwrap().expr.unwrap();
+        let info = assertion_info(&fail);
+        assert_eq!(info_keys(info), vec!["source", "line", "column", "message"]);
+        assert_eq!(info[0].1, Expr::String(r#"assert!(x > 0, "x must be positive")"#.to_string()));
+        assert_eq!(info[1].1, Expr::Int(4));
+        assert_eq!(info[2].1, Expr::Int(21));
+    }
+
+    #[test]
+    fn test_parse_assert_eq_macro() {
+        let expr = first_body(
+            r#"
+            mod test {
+                fn check(x: int) {
+                    assert_eq!(x + 1, 2)
+                }
+            }
+        "#,
+        );
+        let Expr::Match { expr, arms } = expr else {
+            panic!("expected match");
+        };
+        assert!(matches!(*expr, Expr::Tuple(ref parts) if parts.len() == 2));
+        let Expr::If { cond, else_block, .. } = &arms[0].body else {
+            panic!("expected if");
+        };
+        assert!(matches!(**cond, Expr::Binary { op: BinOp::Eq, .. }));
+        let fail = else_block.as_ref().unwrap().expr.as_deref().unwrap();
+        assert_eq!(info_keys(assertion_info(fail)), vec!["source", "line", "column", "left", "right"]);
+    }
+
+    #[test]
+    fn test_parse_assert_match_macro() {
+        let expr = first_body(
+            r#"
+            mod test {
+                fn check(r: Result<int, Atom>) {
+                    assert_match!(r, Ok(n) if n > 1)
+                }
+            }
+        "#,
+        );
+        let Expr::Match { arms, .. } = expr else {
+            panic!("expected match");
+        };
+        assert_eq!(arms.len(), 2);
+        assert!(arms[0].guard.is_some());
+        assert_eq!(arms[0].body, Expr::Atom("ok".to_string()));
+        assert_eq!(info_keys(assertion_info(&arms[1].body)), vec!["source", "line", "column", "value"]);
+    }
+
+    #[test]
+    fn test_assert_without_bang_is_ident() {
+        let expr = first_body(
+            r#"
+            mod test {
+                fn check(assert: bool) -> bool {
+                    assert
+                }
+            }
+        "#,
+        );
+        assert_eq!(expr, Expr::Ident("assert".to_string()));
+    }
 }
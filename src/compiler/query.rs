@@ -0,0 +1,300 @@
+//! Memoized queries over the modules of a project, for the language server.
+//!
+//! A [`Database`] holds each module of the project as an input, stamped
+//! with the [`Revision`] it last changed at, and remembers what the queries
+//! computed from it. When a module changes, only the results that read it
+//! are recomputed: its own `#[cfg]` evaluation, and the type checks of it
+//! and of the modules that depend on it, found through the same dependency
+//! graph that incremental builds use. Setting a module equal to the one it
+//! replaces, as a save without edits does, changes nothing.
+//!
+//! Checks are computed on demand, for the modules asked about, which in the
+//! language server are the open files. The definitions and signatures of
+//! every module are collected for each check, which is cheap; function
+//! bodies, which are not, are checked only in the modules asked about
+//! whose results are out of date. The context modules, such as the stdlib,
+//! never change and are never checked.
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::compiler::ast::Module;
+use crate::compiler::cfg::{configure_module, Configured};
+use crate::compiler::error::{TypeError, Warning};
+use crate::compiler::loader::affected_modules;
+use crate::compiler::typeck::{check_modules_where, FnInfo};
+use crate::compiler::warnings::WarningLevels;
+use crate::config::CompileOptions;
+
+/// A point in the history of a [`Database`]'s inputs, counting up from 0
+/// with each change.
+pub type Revision = u64;
+
+/// A module as last set, and when.
+struct Input {
+    module: Module,
+    changed_at: Revision,
+}
+
+/// A module with its `#[cfg]`s evaluated, and what evaluating them found.
+struct Configuration {
+    module: Module,
+    warnings: Vec<Warning>,
+    errors: Vec<TypeError>,
+}
+
+/// A remembered result and the revision it was computed at.
+struct Memo<T> {
+    value: Rc<T>,
+    computed_at: Revision,
+}
+
+/// The diagnostics of type checking one module.
+#[derive(Debug, Default)]
+pub struct ModuleCheck {
+    pub warnings: Vec<Warning>,
+    /// Type errors, `compile_error!`s and denied warnings
+    pub errors: Vec<TypeError>,
+}
+
+/// The modules of a project and what has been computed from them.
+pub struct Database {
+    revision: Revision,
+    /// Modules checked against but never changed or checked themselves
+    context: Vec<Module>,
+    package: Option<String>,
+    options: CompileOptions,
+    levels: WarningLevels,
+    inputs: HashMap<String, Input>,
+    /// Modules removed since the last check, whose dependents it must redo
+    removed: HashSet<String>,
+    configured: HashMap<String, Memo<Configuration>>,
+    /// The check of each module, kept until a module it reads changes
+    checks: HashMap<String, Rc<ModuleCheck>>,
+    /// The revision of the last check
+    checked_at: Revision,
+    /// Every module's public function signatures, as of the last check
+    functions: HashMap<String, FnInfo>,
+}
+
+impl Database {
+    pub fn new(context: Vec<Module>) -> Self {
+        Self {
+            revision: 0,
+            context,
+            package: None,
+            options: CompileOptions::for_testing(),
+            levels: WarningLevels::default(),
+            inputs: HashMap::new(),
+            removed: HashSet::new(),
+            configured: HashMap::new(),
+            checks: HashMap::new(),
+            checked_at: 0,
+            functions: HashMap::new(),
+        }
+    }
+
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// Set the package the modules belong to, which `use` paths resolve in.
+    pub fn set_package(&mut self, package: Option<String>) {
+        if self.package != package {
+            self.package = package;
+            self.invalidate();
+        }
+    }
+
+    /// Set what `#[cfg]` is evaluated against.
+    pub fn set_options(&mut self, options: CompileOptions) {
+        self.options = options;
+        self.invalidate();
+    }
+
+    pub fn set_warning_levels(&mut self, levels: WarningLevels) {
+        self.levels = levels;
+        self.invalidate();
+    }
+
+    /// Forget every result, for a change that every query reads.
+    fn invalidate(&mut self) {
+        self.revision += 1;
+        self.configured.clear();
+        self.checks.clear();
+    }
+
+    /// Set `module`, replacing the module of the same name.
+    pub fn set_module(&mut self, module: Module) {
+        if self.inputs.get(&module.name).is_some_and(|input| input.module == module) {
+            return;
+        }
+        self.revision += 1;
+        self.removed.remove(&module.name);
+        let input = Input {
+            module,
+            changed_at: self.revision,
+        };
+        self.inputs.insert(input.module.name.clone(), input);
+    }
+
+    pub fn remove_module(&mut self, name: &str) {
+        if self.inputs.remove(name).is_some() {
+            self.revision += 1;
+            self.removed.insert(name.to_string());
+            self.configured.remove(name);
+            self.checks.remove(name);
+        }
+    }
+
+    pub fn module(&self, name: &str) -> Option<&Module> {
+        self.inputs.get(name).map(|input| &input.module)
+    }
+
+    /// The public function signatures of every module, by qualified name,
+    /// as of the last [`check`](Self::check) that had anything to do.
+    pub fn functions(&self) -> &HashMap<String, FnInfo> {
+        &self.functions
+    }
+
+    /// The check of `name` as last computed, if it is still current.
+    pub fn diagnostics(&self, name: &str) -> Option<Rc<ModuleCheck>> {
+        self.checks.get(name).cloned()
+    }
+
+    /// Module `name` with its `#[cfg]`s evaluated.
+    fn configured(&mut self, name: &str) -> Option<Rc<Configuration>> {
+        let input = self.inputs.get(name)?;
+        if let Some(memo) = self.configured.get(name).filter(|memo| memo.computed_at >= input.changed_at) {
+            return Some(Rc::clone(&memo.value));
+        }
+        let mut module = input.module.clone();
+        let Configured { warnings, errors } = configure_module(&mut module, &self.options);
+        let value = Rc::new(Configuration { module, warnings, errors });
+        let memo = Memo {
+            value: Rc::clone(&value),
+            computed_at: self.revision,
+        };
+        self.configured.insert(name.to_string(), memo);
+        Some(value)
+    }
+
+    /// Bring the checks of the modules `names` up to date. Returns those
+    /// that were out of date, with their new checks; the others have
+    /// nothing new to report, and [`diagnostics`](Self::diagnostics) has
+    /// them.
+    pub fn check<'a>(&mut self, names: impl IntoIterator<Item = &'a str>) -> Vec<(String, Rc<ModuleCheck>)> {
+        let mut project_names: Vec<String> = self.inputs.keys().cloned().collect();
+        project_names.sort();
+        let project: Vec<Rc<Configuration>> =
+            project_names.iter().filter_map(|name| self.configured(name)).collect();
+        let mut modules = self.context.clone();
+        modules.extend(project.iter().map(|configuration| configuration.module.clone()));
+
+        // Whatever reads a module changed since the last check is stale
+        let mut changed: HashSet<String> = self
+            .inputs
+            .iter()
+            .filter(|(_, input)| input.changed_at > self.checked_at)
+            .map(|(name, _)| name.clone())
+            .collect();
+        changed.extend(self.removed.drain());
+        if !changed.is_empty() {
+            let project_modules = &modules[self.context.len()..];
+            for name in affected_modules(project_modules, &changed, self.package.as_deref()) {
+                self.checks.remove(&name);
+            }
+        }
+        self.checked_at = self.revision;
+
+        let stale: HashSet<&str> = names
+            .into_iter()
+            .filter(|name| self.inputs.contains_key(*name) && !self.checks.contains_key(*name))
+            .collect();
+        if stale.is_empty() && changed.is_empty() {
+            return Vec::new();
+        }
+        let result = check_modules_where(&modules, &self.levels, |module| {
+            stale.contains(module.name.as_str())
+        });
+        self.functions = result.functions;
+
+        let mut fresh = Vec::new();
+        for (name, outcome) in result.modules {
+            // A context module of the same name is not the project's
+            let Some(configuration) = project.iter().find(|c| c.module.name == name) else {
+                continue;
+            };
+            let mut warnings: Vec<Warning> = result
+                .warnings
+                .iter()
+                .filter(|w| w.module.as_deref() == Some(name.as_str()))
+                .cloned()
+                .collect();
+            let (cfg_warnings, mut errors) = self.levels.apply(configuration.warnings.iter().cloned());
+            warnings.extend(cfg_warnings);
+            errors.extend(configuration.errors.iter().cloned());
+            if let Err(error) = outcome {
+                errors.push(error);
+            }
+            let check = Rc::new(ModuleCheck { warnings, errors });
+            self.checks.insert(name.clone(), Rc::clone(&check));
+            fresh.push((name, check));
+        }
+        fresh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::parser::Parser;
+
+    fn module(name: &str, source: &str) -> Module {
+        Parser::new(source).parse_file(name).unwrap()
+    }
+
+    fn checked(fresh: &[(String, Rc<ModuleCheck>)]) -> Vec<&str> {
+        let mut names: Vec<&str> = fresh.iter().map(|(name, _)| name.as_str()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_check() {
+        let mut db = Database::new(Vec::new());
+        db.set_module(module("util", "pub fn double(n: int) -> int {\n    n * 2\n}\n"));
+        db.set_module(module("app", "use util::double;\n\nfn main() -> int {\n    double(2)\n}\n"));
+        db.set_module(module("other", "fn one() -> int {\n    1\n}\n"));
+        let open = ["util", "app", "other"];
+
+        let fresh = db.check(open);
+        assert_eq!(checked(&fresh), ["app", "other", "util"]);
+        assert!(fresh.iter().all(|(_, check)| check.errors.is_empty()));
+        assert!(db.functions().contains_key("util::double"));
+
+        // Nothing changed, so there is nothing to redo
+        assert!(db.check(open).is_empty());
+        let revision = db.revision();
+        db.set_module(module("other", "fn one() -> int {\n    1\n}\n"));
+        assert_eq!(db.revision(), revision);
+        assert!(db.check(open).is_empty());
+
+        // A change is seen by the module and its dependents only
+        db.set_module(module("util", "pub fn double(n: int) -> int {\n    \"two\"\n}\n"));
+        let fresh = db.check(open);
+        assert_eq!(checked(&fresh), ["app", "util"]);
+        assert!(!db.diagnostics("util").unwrap().errors.is_empty());
+        assert!(db.diagnostics("other").unwrap().errors.is_empty());
+
+        // Only what is asked about is checked; the rest waits until it is
+        db.set_module(module("util", "pub fn double(n: int) -> int {\n    n + n\n}\n"));
+        assert_eq!(checked(&db.check(["util"])), ["util"]);
+        assert!(db.diagnostics("app").is_none());
+        assert_eq!(checked(&db.check(open)), ["app"]);
+
+        db.remove_module("util");
+        assert_eq!(checked(&db.check(open)), ["app"]);
+        assert!(db.module("util").is_none());
+    }
+}
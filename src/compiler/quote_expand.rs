@@ -66,6 +66,7 @@ fn expand_stmt_quotes(stmt: &mut Stmt) {
         Stmt::Expr { expr, .. } => {
             *expr = expand_expr_quotes(expr.clone());
         }
+        Stmt::Cfg { stmt, .. } => expand_stmt_quotes(stmt),
     }
 }
 
@@ -79,10 +80,11 @@ fn expand_expr_quotes(expr: Expr) -> Expr {
         Expr::QuoteItem(item) => quote_item_to_tuple(&item),
 
         // Recursively expand in all expression types
-        Expr::Binary { op, left, right } => Expr::Binary {
+        Expr::Binary { op, left, right, id } => Expr::Binary {
             op,
             left: Box::new(expand_expr_quotes(*left)),
             right: Box::new(expand_expr_quotes(*right)),
+            id,
         },
         Expr::Unary { op, expr } => Expr::Unary {
             op,
@@ -120,6 +122,7 @@ fn expand_expr_quotes(expr: Expr) -> Expr {
             arms: arms
                 .into_iter()
                 .map(|arm| MatchArm {
+                    attrs: arm.attrs,
                     pattern: arm.pattern,
                     guard: arm.guard,
                     body: expand_expr_quotes(arm.body),
@@ -198,7 +201,7 @@ fn quote_expr_to_tuple(expr: &Expr) -> Expr {
         }
 
         // Binary operation
-        Expr::Binary { op, left, right } => make_tuple(vec![
+        Expr::Binary { op, left, right, .. } => make_tuple(vec![
             make_atom("binary_op"),
             make_atom(&binop_to_string(op)),
             quote_expr_to_tuple(left),
@@ -369,10 +372,8 @@ fn quote_block_with_splice(block: &Block, expr_tuple: Expr) -> Expr {
     }
 
     // Build the concatenation expression using :lists::append([list1, list2, ...])
-    let stmts_expr = if concat_parts.is_empty() {
-        Expr::List(vec![])
-    } else if concat_parts.len() == 1 {
-        concat_parts.pop().unwrap()
+    let stmts_expr = if concat_parts.len() <= 1 {
+        concat_parts.pop().unwrap_or(Expr::List(vec![]))
     } else {
         // Use :lists::append/1 which takes a list of lists
         Expr::ExternCall {
@@ -388,6 +389,7 @@ fn quote_block_with_splice(block: &Block, expr_tuple: Expr) -> Expr {
         ty: None,
         value: stmts_expr,
         else_block: None,
+        span: None,
     });
 
     // Generate: (_stmts, final_expr)
@@ -447,10 +449,10 @@ fn quote_list_with_splice(elems: &[Expr]) -> Expr {
     }
 
     // Build concatenation
-    if concat_parts.is_empty() {
-        make_tuple(vec![make_atom("list"), Expr::List(vec![])])
-    } else if concat_parts.len() == 1 {
-        concat_parts.pop().unwrap()
+    if concat_parts.len() <= 1 {
+        concat_parts
+            .pop()
+            .unwrap_or_else(|| make_tuple(vec![make_atom("list"), Expr::List(vec![])]))
     } else {
         // Use :lists::append/1 to concatenate all parts
         // But first we need to generate code that extracts the inner lists
@@ -470,6 +472,7 @@ fn quote_list_with_splice(elems: &[Expr]) -> Expr {
                 ty: None,
                 value: part,
                 else_block: None,
+                span: None,
             });
             list_vars.push(Expr::Ident(var_name));
         }
@@ -625,6 +628,7 @@ fn find_unquoted_vars_recursive(expr: &Expr, vars: &mut Vec<String>) {
                 match stmt {
                     Stmt::Let { value, .. } => find_unquoted_vars_recursive(value, vars),
                     Stmt::Expr { expr: e, .. } => find_unquoted_vars_recursive(e, vars),
+                    Stmt::Cfg { .. } => {}
                 }
             }
             if let Some(e) = &block.expr {
@@ -670,10 +674,11 @@ fn substitute_var_in_expr(expr: &Expr, var_name: &str, replacement: &str) -> Exp
             }
             expr.clone()
         }
-        Expr::Binary { op, left, right } => Expr::Binary {
+        Expr::Binary { op, left, right, .. } => Expr::Binary {
             op: op.clone(),
             left: Box::new(substitute_var_in_expr(left, var_name, replacement)),
             right: Box::new(substitute_var_in_expr(right, var_name, replacement)),
+            id: ExprId::fresh(),
         },
         Expr::Unary { op, expr: inner } => Expr::Unary {
             op: op.clone(),
@@ -709,13 +714,15 @@ fn substitute_var_in_expr(expr: &Expr, var_name: &str, replacement: &str) -> Exp
         }
         Expr::Block(block) => {
             let stmts = block.stmts.iter().map(|s| match s {
-                Stmt::Let { pattern, ty, value, else_block } => Stmt::Let {
+                Stmt::Let { pattern, ty, value, else_block, span } => Stmt::Let {
                     pattern: pattern.clone(),
                     ty: ty.clone(),
                     value: substitute_var_in_expr(value, var_name, replacement),
                     else_block: else_block.clone(),
+                    span: span.clone(),
                 },
                 Stmt::Expr { expr: e, span } => Stmt::Expr { expr: substitute_var_in_expr(e, var_name, replacement), span: span.clone() },
+                Stmt::Cfg { .. } => s.clone(),
             }).collect();
             let expr_opt = block.expr.as_ref().map(|e| {
                 Box::new(substitute_var_in_expr(e, var_name, replacement))
@@ -735,7 +742,7 @@ fn substitute_var_in_expr(expr: &Expr, var_name: &str, replacement: &str) -> Exp
 /// Convert a quoted statement to tuple construction code.
 fn quote_stmt_to_tuple(stmt: &Stmt) -> Expr {
     match stmt {
-        Stmt::Let { pattern, ty, value, else_block } => {
+        Stmt::Let { pattern, ty, value, else_block, .. } => {
             let pattern_tuple = quote_pattern_to_tuple(pattern);
             let type_tuple = ty
                 .as_ref()
@@ -755,6 +762,9 @@ fn quote_stmt_to_tuple(stmt: &Stmt) -> Expr {
             ])
         }
         Stmt::Expr { expr, .. } => quote_expr_to_tuple(expr),
+        // Quoted code is configured with the macro, so this is the
+        // statement as written
+        Stmt::Cfg { stmt, .. } => quote_stmt_to_tuple(stmt),
     }
 }
 
@@ -1028,6 +1038,7 @@ mod tests {
             op: BinOp::Add,
             left: Box::new(Expr::Unquote(Box::new(Expr::Ident("x".to_string())))),
             right: Box::new(Expr::Unquote(Box::new(Expr::Ident("y".to_string())))),
+            id: ExprId::fresh(),
         };
         let vars = find_unquoted_vars(&expr);
         assert_eq!(vars.len(), 2);
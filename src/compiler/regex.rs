@@ -0,0 +1,441 @@
+//! Checking regular expressions written as string literals.
+//!
+//! `regex::new("...")` and `regex::compile("...")` hand their pattern to
+//! Erlang's `re` module at run time, which is too late to learn that a
+//! bracket was never closed. When the pattern is a literal the type checker
+//! runs it through [`check_pattern`] first. This is not a PCRE
+//! implementation: it follows the syntax closely enough to find the
+//! mistakes `re:compile/2` would reject (unbalanced groups and classes,
+//! quantifiers with nothing to repeat, malformed group syntax and repetition
+//! counts, reversed ranges, reused group names) and accepts anything else.
+
+/// Why a pattern is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    pub message: String,
+    /// Byte offset in the pattern where the problem was found
+    pub offset: usize,
+}
+
+impl PatternError {
+    fn new(message: impl Into<String>, offset: usize) -> Self {
+        Self {
+            message: message.into(),
+            offset,
+        }
+    }
+}
+
+/// Largest count PCRE accepts in a `{n,m}` quantifier.
+const MAX_REPEAT: u32 = 65535;
+
+/// Check `pattern`, returning the names of its named groups in the order
+/// they are opened.
+pub fn check_pattern(pattern: &str) -> Result<Vec<String>, PatternError> {
+    let chars: Vec<(usize, char)> = pattern.char_indices().collect();
+    let mut names: Vec<String> = Vec::new();
+    let mut groups: Vec<usize> = Vec::new();
+    // Whether the last item can take a quantifier
+    let mut repeatable = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (offset, c) = chars[i];
+        match c {
+            '\\' => {
+                if i + 1 == chars.len() {
+                    return Err(PatternError::new("`\\` at end of pattern", offset));
+                }
+                i += 2;
+                repeatable = true;
+            }
+            '[' => {
+                i = class(&chars, i)?;
+                repeatable = true;
+            }
+            '(' => {
+                match group(&chars, i, &mut names)? {
+                    Group::Open(next) => {
+                        groups.push(offset);
+                        repeatable = false;
+                        i = next;
+                    }
+                    // Options, comments and verbs match nothing themselves
+                    Group::Closed(next) => {
+                        repeatable = false;
+                        i = next;
+                    }
+                    Group::Reference(next) => {
+                        repeatable = true;
+                        i = next;
+                    }
+                }
+            }
+            ')' => {
+                if groups.pop().is_none() {
+                    return Err(PatternError::new("unmatched `)`", offset));
+                }
+                repeatable = true;
+                i += 1;
+            }
+            '*' | '+' | '?' => {
+                if !repeatable {
+                    return Err(PatternError::new(
+                        format!("`{}` has nothing to repeat", c),
+                        offset,
+                    ));
+                }
+                i = lazy_or_possessive(&chars, i + 1);
+                repeatable = false;
+            }
+            '{' => match repetition(&chars, i)? {
+                Some(next) => {
+                    if !repeatable {
+                        return Err(PatternError::new("`{` quantifier has nothing to repeat", offset));
+                    }
+                    i = lazy_or_possessive(&chars, next);
+                    repeatable = false;
+                }
+                // Not a quantifier, so a literal `{`
+                None => {
+                    repeatable = true;
+                    i += 1;
+                }
+            },
+            '|' => {
+                repeatable = false;
+                i += 1;
+            }
+            _ => {
+                repeatable = true;
+                i += 1;
+            }
+        }
+    }
+
+    match groups.last() {
+        Some(&open) => Err(PatternError::new("missing `)` to close this group", open)),
+        None => Ok(names),
+    }
+}
+
+enum Group {
+    /// A group whose contents follow; the index after its opening syntax
+    Open(usize),
+    /// Syntax that ends at its own `)`; the index after it
+    Closed(usize),
+    /// A back-reference or recursion, which can be repeated
+    Reference(usize),
+}
+
+/// Parse the opening of a group at `chars[start]`, which is `(`.
+fn group(chars: &[(usize, char)], start: usize, names: &mut Vec<String>) -> Result<Group, PatternError> {
+    let offset = chars[start].0;
+    let at = |i: usize| chars.get(i).map(|&(_, c)| c);
+
+    // (*UTF8), (*UCP) and other verbs
+    if at(start + 1) == Some('*') {
+        return Ok(Group::Closed(close_paren(chars, start)?));
+    }
+    if at(start + 1) != Some('?') {
+        return Ok(Group::Open(start + 1));
+    }
+
+    let i = start + 2;
+    match at(i) {
+        Some(':' | '=' | '!' | '>' | '|') => Ok(Group::Open(i + 1)),
+        Some('<') if matches!(at(i + 1), Some('=' | '!')) => Ok(Group::Open(i + 2)),
+        Some('<') => named_group(chars, i + 1, '>', names),
+        Some('\'') => named_group(chars, i + 1, '\'', names),
+        Some('P') => match at(i + 1) {
+            Some('<') => named_group(chars, i + 2, '>', names),
+            Some('=' | '>') => Ok(Group::Reference(close_paren(chars, start)?)),
+            _ => Err(PatternError::new("unknown group syntax after `(?P`", offset)),
+        },
+        Some('#') => Ok(Group::Closed(close_paren(chars, start)?)),
+        Some('R' | '&' | '+' | '0'..='9') => Ok(Group::Reference(close_paren(chars, start)?)),
+        Some('-') if at(i + 1).is_some_and(|c| c.is_ascii_digit()) => {
+            Ok(Group::Reference(close_paren(chars, start)?))
+        }
+        Some(_) => {
+            // Option settings: (?i), (?x-s) or (?i:...)
+            let mut j = i;
+            while let Some(c) = at(j) {
+                match c {
+                    'i' | 'm' | 's' | 'x' | 'X' | 'J' | 'U' | '-' => j += 1,
+                    ')' => return Ok(Group::Closed(j + 1)),
+                    ':' => return Ok(Group::Open(j + 1)),
+                    _ => {
+                        return Err(PatternError::new(
+                            format!("unknown group syntax `(?{}`", c),
+                            offset,
+                        ));
+                    }
+                }
+            }
+            Err(PatternError::new("missing `)` to close this group", offset))
+        }
+        None => Err(PatternError::new("missing `)` to close this group", offset)),
+    }
+}
+
+/// Parse a group name starting at `chars[start]` and ending with `end`.
+fn named_group(
+    chars: &[(usize, char)],
+    start: usize,
+    end: char,
+    names: &mut Vec<String>,
+) -> Result<Group, PatternError> {
+    let offset = chars.get(start).map_or_else(|| chars[start - 1].0 + 1, |&(o, _)| o);
+    let mut name = String::new();
+    let mut i = start;
+    loop {
+        match chars.get(i) {
+            Some(&(_, c)) if c == end => break,
+            Some(&(_, c)) if c.is_ascii_alphanumeric() || c == '_' => name.push(c),
+            Some(&(o, c)) => {
+                return Err(PatternError::new(
+                    format!("`{}` can't appear in a group name", c),
+                    o,
+                ));
+            }
+            None => return Err(PatternError::new(format!("group name is missing its closing `{}`", end), offset)),
+        }
+        i += 1;
+    }
+    if name.is_empty() {
+        return Err(PatternError::new("group name is empty", offset));
+    }
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        return Err(PatternError::new(
+            format!("group name `{}` starts with a digit", name),
+            offset,
+        ));
+    }
+    if names.contains(&name) {
+        return Err(PatternError::new(
+            format!("group name `{}` is used twice", name),
+            offset,
+        ));
+    }
+    names.push(name);
+    Ok(Group::Open(i + 1))
+}
+
+/// The index after the `)` that ends the group opened at `chars[start]`,
+/// for group syntax that can't contain other groups.
+fn close_paren(chars: &[(usize, char)], start: usize) -> Result<usize, PatternError> {
+    chars[start..]
+        .iter()
+        .position(|&(_, c)| c == ')')
+        .map(|n| start + n + 1)
+        .ok_or_else(|| PatternError::new("missing `)` to close this group", chars[start].0))
+}
+
+/// Parse the character class at `chars[start]`, which is `[`, returning
+/// the index after its closing `]`.
+fn class(chars: &[(usize, char)], start: usize) -> Result<usize, PatternError> {
+    let offset = chars[start].0;
+    let mut i = start + 1;
+    if chars.get(i).map(|&(_, c)| c) == Some('^') {
+        i += 1;
+    }
+    // A `]` straight after the `[` or `[^` is a literal
+    if chars.get(i).map(|&(_, c)| c) == Some(']') {
+        i += 1;
+    }
+
+    // The last literal character, which may start a range
+    let mut previous: Option<char> = None;
+    while let Some(&(at, c)) = chars.get(i) {
+        match c {
+            ']' => return Ok(i + 1),
+            '\\' => {
+                if i + 1 == chars.len() {
+                    return Err(PatternError::new("`\\` at end of pattern", at));
+                }
+                previous = None;
+                i += 2;
+            }
+            '[' if chars.get(i + 1).map(|&(_, c)| c) == Some(':') => {
+                // [:alpha:] and friends
+                let end = chars[i + 2..]
+                    .windows(2)
+                    .position(|w| w[0].1 == ':' && w[1].1 == ']')
+                    .ok_or_else(|| PatternError::new("missing `:]` to close this class name", at))?;
+                previous = None;
+                i += 2 + end + 2;
+            }
+            '-' if previous.is_some() => {
+                match chars.get(i + 1) {
+                    Some(&(_, ']')) | None => {
+                        previous = Some('-');
+                        i += 1;
+                    }
+                    Some(&(_, '\\' | '[')) => {
+                        previous = None;
+                        i += 1;
+                    }
+                    Some(&(_, last)) => {
+                        let first = previous.unwrap_or('-');
+                        if last < first {
+                            return Err(PatternError::new(
+                                format!("range `{}-{}` is out of order", first, last),
+                                at,
+                            ));
+                        }
+                        previous = None;
+                        i += 2;
+                    }
+                }
+            }
+            _ => {
+                previous = Some(c);
+                i += 1;
+            }
+        }
+    }
+    Err(PatternError::new("missing `]` to close this class", offset))
+}
+
+/// Parse `{n}`, `{n,}` or `{n,m}` at `chars[start]`, returning the index
+/// after it, or `None` if the brace doesn't start a quantifier.
+fn repetition(chars: &[(usize, char)], start: usize) -> Result<Option<usize>, PatternError> {
+    let offset = chars[start].0;
+    let number = |i: &mut usize| -> Option<u64> {
+        let begin = *i;
+        while chars.get(*i).is_some_and(|&(_, c)| c.is_ascii_digit()) {
+            *i += 1;
+        }
+        if *i == begin {
+            return None;
+        }
+        chars[begin..*i]
+            .iter()
+            .map(|&(_, c)| c)
+            .collect::<String>()
+            .parse()
+            .ok()
+            .or(Some(u64::MAX))
+    };
+
+    let mut i = start + 1;
+    let Some(min) = number(&mut i) else {
+        return Ok(None);
+    };
+    let max = match chars.get(i).map(|&(_, c)| c) {
+        Some('}') => Some(min),
+        Some(',') => {
+            i += 1;
+            number(&mut i)
+        }
+        _ => return Ok(None),
+    };
+    if chars.get(i).map(|&(_, c)| c) != Some('}') {
+        return Ok(None);
+    }
+
+    if min.max(max.unwrap_or(0)) > u64::from(MAX_REPEAT) {
+        return Err(PatternError::new(
+            format!("repetition count is larger than {}", MAX_REPEAT),
+            offset,
+        ));
+    }
+    if let Some(max) = max {
+        if max < min {
+            return Err(PatternError::new(
+                format!("repetition counts `{{{},{}}}` are out of order", min, max),
+                offset,
+            ));
+        }
+    }
+    Ok(Some(i + 1))
+}
+
+/// Skip the `?` or `+` that makes a quantifier lazy or possessive.
+fn lazy_or_possessive(chars: &[(usize, char)], i: usize) -> usize {
+    match chars.get(i) {
+        Some(&(_, '?' | '+')) => i + 1,
+        _ => i,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(pattern: &str) -> PatternError {
+        check_pattern(pattern).expect_err(pattern)
+    }
+
+    #[test]
+    fn test_valid_patterns() {
+        for pattern in [
+            "",
+            "abc",
+            "^\\d{3}-\\d{4}$",
+            "[a-z]+@[a-z]+\\.com",
+            "[]a]",
+            "[^]a-]",
+            "[[:alpha:]_][[:alnum:]_]*",
+            "(a|b)*?c++",
+            "(?:x)(?=y)(?!z)(?<=w)(?<!v)(?>u)",
+            "(?i)hello(?-i:World)",
+            "(*UCP)\\w+",
+            "a{2}b{2,}c{2,5}?",
+            "a{,5}",
+            "x{",
+            "\\p{L}+",
+            "(?<year>\\d{4})-(?'month'\\d\\d)-(?P<day>\\d\\d)(?P=day)",
+            "(a)(?1)(?R)",
+            "a(?#comment)b",
+        ] {
+            assert!(check_pattern(pattern).is_ok(), "{}: {:?}", pattern, check_pattern(pattern));
+        }
+    }
+
+    #[test]
+    fn test_named_groups() {
+        assert_eq!(
+            check_pattern("(?<year>\\d{4})-(?<month>\\d\\d)(x)").unwrap(),
+            vec!["year".to_string(), "month".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_unbalanced() {
+        assert_eq!(error("(ab"), PatternError::new("missing `)` to close this group", 0));
+        assert_eq!(error("a(b(c)"), PatternError::new("missing `)` to close this group", 1));
+        assert_eq!(error("ab)"), PatternError::new("unmatched `)`", 2));
+        assert_eq!(error("x[a-z"), PatternError::new("missing `]` to close this class", 1));
+        assert_eq!(error("ab\\"), PatternError::new("`\\` at end of pattern", 2));
+    }
+
+    #[test]
+    fn test_nothing_to_repeat() {
+        assert_eq!(error("*a").offset, 0);
+        assert_eq!(error("a|+b").offset, 2);
+        assert_eq!(error("(?i)*").offset, 4);
+        assert_eq!(error("a**").message, "`*` has nothing to repeat");
+        assert_eq!(error("({2})").message, "`{` quantifier has nothing to repeat");
+    }
+
+    #[test]
+    fn test_bad_counts_and_ranges() {
+        assert_eq!(error("a{5,2}").message, "repetition counts `{5,2}` are out of order");
+        assert_eq!(error("a{70000}").message, "repetition count is larger than 65535");
+        assert_eq!(error("[z-a]"), PatternError::new("range `z-a` is out of order", 2));
+    }
+
+    #[test]
+    fn test_bad_groups() {
+        assert_eq!(error("(?Q)").message, "unknown group syntax `(?Q`");
+        assert_eq!(error("(?<>x)").message, "group name is empty");
+        assert_eq!(error("(?<1a>x)").message, "group name `1a` starts with a digit");
+        assert_eq!(error("(?<a-b>x)").message, "`-` can't appear in a group name");
+        assert_eq!(
+            error("(?<x>a)|(?<x>b)"),
+            PatternError::new("group name `x` is used twice", 11)
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Source text held in chunks, for files that are edited in place.
+//!
+//! Replacing a range of a [`Text`] copies only the chunks the range
+//! touches, where a `String` would move everything after it. The language
+//! server keeps open files this way, and [`TokenCache`] re-lexes just the
+//! stretch around each edit, reading it with [`Text::slice`].
+//!
+//! [`TokenCache`]: crate::compiler::TokenCache
+
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+
+/// How long chunks are made, in bytes. One can run a few bytes longer to
+/// end on a character boundary, or shorter after an edit.
+const CHUNK: usize = 4096;
+
+/// Text in chunks of up to about [`CHUNK`] bytes, none of them empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Text {
+    chunks: Vec<String>,
+    len: usize,
+}
+
+impl Text {
+    pub fn new(text: &str) -> Self {
+        Self {
+            chunks: split(text),
+            len: text.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = &str> + Clone {
+        self.chunks.iter().map(String::as_str)
+    }
+
+    /// The chunk holding byte `offset` and where in it the offset is. The
+    /// end of the text is in the last chunk.
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        let mut start = 0;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if offset < start + chunk.len() || i + 1 == self.chunks.len() {
+                return (i, offset - start);
+            }
+            start += chunk.len();
+        }
+        (0, 0)
+    }
+
+    /// The chunk that byte `offset` ends a range in, and where in it. An
+    /// offset between two chunks ends the first.
+    fn locate_end(&self, offset: usize) -> (usize, usize) {
+        let mut start = 0;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if offset <= start + chunk.len() {
+                return (i, offset - start);
+            }
+            start += chunk.len();
+        }
+        (0, 0)
+    }
+
+    pub fn is_char_boundary(&self, offset: usize) -> bool {
+        if offset >= self.len {
+            return offset == self.len;
+        }
+        let (i, at) = self.locate(offset);
+        self.chunks[i].is_char_boundary(at)
+    }
+
+    /// The bytes `range`, borrowed when they are all in one chunk.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds or not on character boundaries.
+    pub fn slice(&self, range: Range<usize>) -> Cow<'_, str> {
+        assert!(range.start <= range.end && range.end <= self.len, "range out of bounds");
+        if range.is_empty() {
+            return Cow::Borrowed("");
+        }
+        let (first, from) = self.locate(range.start);
+        let (last, to) = self.locate_end(range.end);
+        if first == last {
+            return Cow::Borrowed(&self.chunks[first][from..to]);
+        }
+        let mut text = String::with_capacity(range.len());
+        text.push_str(&self.chunks[first][from..]);
+        for chunk in &self.chunks[first + 1..last] {
+            text.push_str(chunk);
+        }
+        text.push_str(&self.chunks[last][..to]);
+        Cow::Owned(text)
+    }
+
+    /// Replace the bytes `range` with `text`.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds or not on character boundaries.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        assert!(range.start <= range.end && range.end <= self.len, "range out of bounds");
+        if self.chunks.is_empty() {
+            *self = Self::new(text);
+            return;
+        }
+        let (first, from) = self.locate(range.start);
+        let (last, to) = self.locate(range.end);
+        let mut edited = String::with_capacity(from + text.len() + self.chunks[last].len() - to);
+        edited.push_str(&self.chunks[first][..from]);
+        edited.push_str(text);
+        edited.push_str(&self.chunks[last][to..]);
+        self.chunks.splice(first..=last, split(&edited));
+        self.len = self.len - range.len() + text.len();
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+/// `text` in chunks of about [`CHUNK`] bytes.
+fn split(mut text: &str) -> Vec<String> {
+    let mut chunks = Vec::with_capacity(text.len() / CHUNK + 1);
+    while !text.is_empty() {
+        let mut end = CHUNK.min(text.len());
+        while !text.is_char_boundary(end) {
+            end += 1;
+        }
+        let (chunk, rest) = text.split_at(end);
+        chunks.push(chunk.to_string());
+        text = rest;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace() {
+        let source = "fn main() {\n    héllo\n}\n".repeat(1000);
+        let mut text = Text::new(&source);
+        assert!(text.chunks().count() > 1);
+        assert_eq!(text.to_string(), source);
+
+        let mut expected = source.clone();
+        for (range, insert) in [
+            (0..2, "pub fn"),
+            (4000..4100, ""),
+            (8190..8190, "x\ny"),
+            (100..9000, "é"),
+            (expected.len() - 2..expected.len() - 2, "end"),
+        ] {
+            let mut range = range.start.min(expected.len())..range.end.min(expected.len());
+            while !expected.is_char_boundary(range.start) {
+                range.start -= 1;
+            }
+            while !expected.is_char_boundary(range.end) {
+                range.end += 1;
+            }
+            expected.replace_range(range.clone(), insert);
+            text.replace(range, insert);
+            assert_eq!(text.len(), expected.len());
+            assert_eq!(text.to_string(), expected);
+            assert!(text.chunks().all(|chunk| !chunk.is_empty()));
+        }
+
+        let mut empty = Text::default();
+        empty.replace(0..0, "fn");
+        assert_eq!(empty.to_string(), "fn");
+        empty.replace(0..2, "");
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_slice() {
+        let source = "héllo wörld\n".repeat(800);
+        let text = Text::new(&source);
+        for range in [0..0, 0..6, 4090..4110, 100..9000, 0..source.len()] {
+            if source.is_char_boundary(range.start) && source.is_char_boundary(range.end) {
+                assert_eq!(text.slice(range.clone()), &source[range]);
+            }
+        }
+        assert!(matches!(text.slice(0..6), Cow::Borrowed(_)));
+        assert!(text.is_char_boundary(source.len()));
+        assert!(!text.is_char_boundary(2));
+    }
+}
@@ -165,6 +165,75 @@ pub fn parse_interpolated_string(s: &str) -> Vec<LexStringPart> {
     parts
 }
 
+/// A segment of a `format!` template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatSegment {
+    /// A literal string segment (escapes already processed)
+    Literal(String),
+    /// `{}`
+    Display,
+    /// `{:?}`
+    Debug,
+}
+
+/// Parse a `format!` template into literal text and placeholders. Unlike
+/// an interpolated string, braces hold no expressions: `{}` and `{:?}`
+/// take the next argument, `{{` and `}}` are literal braces, and anything
+/// else between braces is an error.
+pub fn parse_format_string(s: &str) -> Result<Vec<FormatSegment>, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) => literal.push_str(&process_escapes(&format!("\\{}", escaped))),
+                None => literal.push('\\'),
+            },
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => spec.push(c),
+                        None => return Err("unclosed `{` in `format!` string".to_string()),
+                    }
+                }
+                let segment = match spec.as_str() {
+                    "" => FormatSegment::Display,
+                    ":?" => FormatSegment::Debug,
+                    _ => {
+                        return Err(format!(
+                            "unknown placeholder `{{{}}}` in `format!` string",
+                            spec
+                        ))
+                    }
+                };
+                if !literal.is_empty() {
+                    segments.push(FormatSegment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(segment);
+            }
+            '}' => return Err("unmatched `}` in `format!` string".to_string()),
+            _ => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        segments.push(FormatSegment::Literal(literal));
+    }
+    Ok(segments)
+}
+
 /// Tokens produced by the lexer.
 #[derive(Logos, Debug, Clone, PartialEq)]
 #[logos(skip r"[ \t\n\r]+")]
@@ -632,4 +701,26 @@ mod tests {
         assert_eq!(lex.next(), Some(Ok(Token::Hash)));
         assert_eq!(lex.next(), Some(Ok(Token::Ident("foo".to_string()))));
     }
+
+    #[test]
+    fn test_parse_format_string() {
+        assert_eq!(
+            parse_format_string("User {} has {:?} points\\n"),
+            Ok(vec![
+                FormatSegment::Literal("User ".to_string()),
+                FormatSegment::Display,
+                FormatSegment::Literal(" has ".to_string()),
+                FormatSegment::Debug,
+                FormatSegment::Literal(" points\n".to_string()),
+            ])
+        );
+        assert_eq!(
+            parse_format_string("{{}} {}"),
+            Ok(vec![FormatSegment::Literal("{} ".to_string()), FormatSegment::Display])
+        );
+        assert_eq!(parse_format_string(""), Ok(vec![]));
+        assert!(parse_format_string("{name}").is_err());
+        assert!(parse_format_string("{").is_err());
+        assert!(parse_format_string("}").is_err());
+    }
 }
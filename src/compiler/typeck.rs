@@ -3,14 +3,25 @@
 //! Performs semantic analysis to validate types across the program.
 //! The type checker runs after parsing and before code generation.
 
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
 use crate::compiler::ast::{
-    self, Attribute, AttributeArgs, BinOp, Block, EnumPatternFields, EnumVariantArgs, Expr,
-    ExternItem, ExternMod, ForClause, Function, ImplBlock, Item, MatchArm, Module, PathPrefix,
-    Pattern, Stmt, StringPart, TypeParam, UnaryOp, UseDecl, UseTree, VariantKind,
+    self, Attribute, AttributeArgs, BinOp, BitSegmentType, Block, EnumPatternFields, EnumVariantArgs, Expr, ExprId,
+    ExternBlock, ExternFn, ExternItem, ExternMod, ForClause, Function, ImplBlock, Item, MatchArm, Module, PathPrefix,
+    FormatPart, Pattern, Stmt, StringPart, TypeParam, UnaryOp, UseDecl, UseTree, VariantKind,
 };
+use crate::compiler::core_erlang::CoreErlangEmitter;
+use crate::compiler::diagnostics::{did_you_mean, Annotate, ErrorCode};
 use crate::compiler::error::{TypeError, TypeResult, Warning};
+use crate::compiler::ice;
+use crate::compiler::intern::{Interner, Symbol};
+use crate::compiler::lint::LintLevel;
+use crate::compiler::regex;
+use crate::compiler::token::Token;
+use crate::compiler::warnings::WarningLevels;
+use crate::timings::Phase;
 
 /// Extract Erlang record name from #[record = "name"] attribute.
 fn get_record_name(attrs: &[Attribute]) -> Option<String> {
@@ -24,6 +35,165 @@ fn get_record_name(attrs: &[Attribute]) -> Option<String> {
     None
 }
 
+/// The Elixir module of a struct marked `#[elixir_struct = "URI"]`:
+/// `Elixir.URI`, the value of its `__struct__` key.
+fn get_elixir_struct(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| match &attr.args {
+        AttributeArgs::Eq(name) if attr.name == "elixir_struct" => {
+            let name = name.strip_prefix("Elixir.").unwrap_or(name);
+            Some(ast::elixir_module_name(name.split('.')))
+        }
+        _ => None,
+    })
+}
+
+/// Whether `path` starts with `elixir::`, which imports Elixir modules.
+fn is_elixir_path(path: &ast::ModulePath) -> bool {
+    path.prefix == PathPrefix::None && path.segments.first().is_some_and(|s| s == "elixir")
+}
+
+/// What a `#[deprecated]` attribute says about the item it marks.
+#[derive(Debug, Clone)]
+struct Deprecation {
+    /// Why, or what to use instead
+    note: Option<String>,
+    /// The name to replace uses with, which `dream fix` does
+    suggestion: Option<String>,
+}
+
+/// The `#[deprecated]` attribute among `attrs`, if the item is deprecated.
+/// The note comes from `#[deprecated = "note"]` or `#[deprecated(note =
+/// "note")]`, and the replacement from `#[deprecated(suggestion = "name")]`.
+fn get_deprecation(attrs: &[Attribute]) -> Option<Deprecation> {
+    let attr = attrs.iter().find(|attr| attr.name == "deprecated")?;
+    let arg = |name: &str| match &attr.args {
+        AttributeArgs::Parenthesized(args) => args.iter().find_map(|arg| match arg {
+            ast::AttributeArg::KeyValue(key, value) if key == name => Some(value.clone()),
+            _ => None,
+        }),
+        _ => None,
+    };
+    Some(match &attr.args {
+        AttributeArgs::Eq(note) => Deprecation {
+            note: Some(note.clone()),
+            suggestion: None,
+        },
+        _ => Deprecation {
+            note: arg("note"),
+            suggestion: arg("suggestion"),
+        },
+    })
+}
+
+/// The `ops` trait impl an operator calls.
+#[derive(Debug, Clone)]
+enum OperatorImpl {
+    /// The impl of this type.
+    Type(String),
+    /// The impl of the type the left operand has when the program runs,
+    /// for operands of a type parameter bounded by the trait.
+    Bound,
+}
+
+/// `left op right` as a call to the `ops` trait impl it resolved to:
+/// `a + b` is `Money::add(a, b)`, `a != b` is `!Money::eq(a, b)` and
+/// `a < b` is `Money::cmp(a, b) == :less`. On a `T: Add`, `a + b` is
+/// `a.add(b)`, which dispatches on the struct `a` is.
+fn operator_call(op: BinOp, operator_impl: &OperatorImpl, left: Expr, right: Expr) -> Expr {
+    let (_, method) = op.trait_method().expect("operator is overloadable");
+    let call = match operator_impl {
+        OperatorImpl::Type(type_name) => Expr::Call {
+            func: Box::new(Expr::Path {
+                segments: vec![type_name.clone(), method.to_string()],
+            }),
+            type_args: vec![],
+            inferred_type_args: vec![],
+            args: vec![left, right],
+        },
+        OperatorImpl::Bound => Expr::MethodCall {
+            receiver: Box::new(left),
+            method: method.to_string(),
+            type_args: vec![],
+            args: vec![right],
+            resolved_module: None,
+            inferred_type_args: vec![],
+        },
+    };
+    match op {
+        BinOp::Ne => Expr::Unary {
+            op: UnaryOp::Not,
+            expr: Box::new(call),
+        },
+        BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => {
+            let (op, ordering) = match op {
+                BinOp::Lt => (BinOp::Eq, "less"),
+                BinOp::Le => (BinOp::Ne, "greater"),
+                BinOp::Gt => (BinOp::Eq, "greater"),
+                _ => (BinOp::Ne, "less"),
+            };
+            Expr::Binary {
+                op,
+                left: Box::new(call),
+                right: Box::new(Expr::Atom(ordering.to_string())),
+                id: ExprId::fresh(),
+            }
+        }
+        _ => call,
+    }
+}
+
+/// Whether two type names name the same type: `iterator::Iter` in a
+/// signature is the `Iter` the iterator module defines. Types are looked up
+/// by their last segment, so two qualified names only match in full.
+fn same_type_name(a: &str, b: &str) -> bool {
+    match (a.rsplit_once("::"), b.rsplit_once("::")) {
+        (Some((_, a)), None) => a == b,
+        (None, Some((_, b))) => a == b,
+        _ => a == b,
+    }
+}
+
+/// `IoData`: a binary or an iolist.
+fn iodata() -> Ty {
+    Ty::Union(vec![Ty::Binary, Ty::List(Box::new(Ty::Any))])
+}
+
+/// `text` as a binary of its UTF-8 bytes, built byte by byte so it is a
+/// literal in the output.
+fn utf8_binary(text: &str) -> Expr {
+    Expr::BitString(
+        text.bytes()
+            .map(|b| ast::BitStringSegment::new(Box::new(Expr::Int(b as i64))))
+            .collect(),
+    )
+}
+
+/// A call to `display::function(value)`.
+fn display_call(function: &str, value: Expr) -> Expr {
+    Expr::Call {
+        func: Box::new(Expr::Path {
+            segments: vec!["display".to_string(), function.to_string()],
+        }),
+        type_args: vec![],
+        inferred_type_args: vec![],
+        args: vec![value],
+    }
+}
+
+/// Whether `general` matches every value `specific` does. Conservative:
+/// patterns that differ in anything but their variable names don't cover
+/// each other, unless `general` binds everything.
+fn pattern_covers(general: &Pattern, specific: &Pattern) -> bool {
+    match (general, specific) {
+        (Pattern::Wildcard | Pattern::Ident(_), _) => true,
+        (Pattern::Tuple(general), Pattern::Tuple(specific)) => {
+            general.len() == specific.len()
+                && general.iter().zip(specific).all(|(g, s)| pattern_covers(g, s))
+        }
+        _ => general == specific,
+    }
+}
+
 /// Internal type representation for type checking.
 /// This is separate from ast::Type to allow for inference variables.
 #[derive(Debug, Clone, PartialEq)]
@@ -126,8 +296,9 @@ impl Ty {
             Ty::List(t) => Ty::List(Box::new(t.substitute(subst))),
             Ty::Named { name, module, args } => {
                 // Check if this is a type parameter (Named with no args, matching a subst key)
-                if args.is_empty() && module.is_none() && subst.contains_key(name) {
-                    subst.get(name).cloned().unwrap()
+                let param = subst.get(name).filter(|_| args.is_empty() && module.is_none());
+                if let Some(ty) = param {
+                    ty.clone()
                 } else {
                     Ty::Named {
                         name: name.clone(),
@@ -257,6 +428,16 @@ pub struct StructInfo {
     pub fields: Vec<(String, Ty)>,
     /// Erlang record name if this struct is marked with #[record = "name"]
     pub record_name: Option<String>,
+    /// Elixir struct module if this struct is marked with
+    /// #[elixir_struct = "URI"]: its `__struct__` is `'Elixir.URI'`
+    pub elixir_module: Option<String>,
+}
+
+impl StructInfo {
+    /// Whether this is a tuple struct, with fields named `0`, `1`, ...
+    pub fn is_tuple(&self) -> bool {
+        self.fields.first().is_some_and(|(name, _)| name == "0")
+    }
 }
 
 /// The kind of an enum variant (type-checked version).
@@ -287,6 +468,32 @@ pub struct FnInfo {
     pub ret: Ty,
 }
 
+impl FnInfo {
+    /// The signature as it would be declared, e.g.
+    /// `fn map<T, U>(list: [T], f: fn(T) -> U) -> [U]`.
+    pub fn signature(&self) -> String {
+        let type_params = if self.type_params.is_empty() {
+            String::new()
+        } else {
+            let names: Vec<String> = self
+                .type_params
+                .iter()
+                .map(|p| match p.bounds.as_slice() {
+                    [] => p.name.clone(),
+                    bounds => format!("{}: {}", p.name, bounds.join(" + ")),
+                })
+                .collect();
+            format!("<{}>", names.join(", "))
+        };
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect();
+        format!("fn {}{}({}) -> {}", self.name, type_params, params.join(", "), self.ret)
+    }
+}
+
 /// Information about a trait definition.
 #[derive(Debug, Clone)]
 pub struct TraitInfo {
@@ -460,46 +667,56 @@ impl Default for PatternMatrix {
 // ============================================================================
 
 /// Type environment for a scope.
+///
+/// A scope is made for every function, block and clause checked, so the
+/// definitions, which are collected up front and only read after, are
+/// shared between a scope and its children; only the variables are copied.
 #[derive(Debug, Clone, Default)]
 pub struct TypeEnv {
     /// Variable bindings: name -> type
-    vars: HashMap<String, Ty>,
+    vars: HashMap<Symbol, Ty>,
+    /// The names of the variables, shared by the scopes of one checker
+    names: Rc<RefCell<Interner>>,
     /// Struct definitions: name -> info
-    structs: HashMap<String, StructInfo>,
+    structs: Rc<HashMap<String, StructInfo>>,
     /// Enum definitions: name -> info
-    enums: HashMap<String, EnumInfo>,
+    enums: Rc<HashMap<String, EnumInfo>>,
     /// Function signatures: name -> info
-    functions: HashMap<String, FnInfo>,
+    functions: Rc<HashMap<String, FnInfo>>,
     /// Impl methods: (type_name, method_name) -> FnInfo
-    methods: HashMap<(String, String), FnInfo>,
+    methods: Rc<HashMap<(String, String), FnInfo>>,
     /// Type aliases: name -> info (includes type parameters for generic aliases)
-    type_aliases: HashMap<String, TypeAliasInfo>,
+    type_aliases: Rc<HashMap<String, TypeAliasInfo>>,
     /// Trait definitions: trait_name -> TraitInfo
-    traits: HashMap<String, TraitInfo>,
+    traits: Rc<HashMap<String, TraitInfo>>,
     /// Trait implementations: (trait_name, type_name) -> TraitImplInfo
-    trait_impls: HashMap<(String, String), TraitImplInfo>,
+    trait_impls: Rc<HashMap<(String, String), TraitImplInfo>>,
     /// Module-level trait declarations: trait names this module implements
-    module_traits: Vec<String>,
+    module_traits: Rc<Vec<String>>,
     /// Associated type bindings from module-level trait declarations: "State" -> Ty::Int
-    associated_types: HashMap<String, Ty>,
+    associated_types: Rc<HashMap<String, Ty>>,
     /// External function signatures: (module, function, arity) -> FnInfo
     /// Used for type-checking FFI calls to Erlang/Elixir/etc
-    extern_functions: HashMap<(String, String, usize), FnInfo>,
+    extern_functions: Rc<HashMap<(String, String, usize), FnInfo>>,
     /// Maps Dream extern module name -> BEAM module name
     /// Used for #[name = "Elixir.Enum"] attribute support
-    extern_module_names: HashMap<String, String>,
+    extern_module_names: Rc<HashMap<String, String>>,
     /// Set of known extern module names (Dream names)
     /// Used to resolve `module::fn()` calls as extern calls
-    extern_modules: HashSet<String>,
+    extern_modules: Rc<HashSet<String>>,
     /// Extern function imports: local_name -> (module, function_name)
     /// Used for `use jason::encode; encode(data)` syntax
-    extern_imports: HashMap<String, (String, String)>,
+    extern_imports: Rc<HashMap<String, (String, String)>>,
     /// Maps extern function (module, dream_name, arity) -> beam_name
     /// Used for #[name = "encode!"] attribute support on functions
-    extern_function_names: HashMap<(String, String, usize), String>,
+    extern_function_names: Rc<HashMap<(String, String, usize), String>>,
     /// Module aliases: alias_name -> full_module_path
     /// Used for `use erlang::std::application as erl_app` syntax
-    extern_module_aliases: HashMap<String, String>,
+    extern_module_aliases: Rc<HashMap<String, String>>,
+    /// Functions declared in `extern "erlang"` and `extern "elixir"` blocks:
+    /// (module, function, arity). Calls to them go to the declared module even
+    /// if a stdlib module has its name
+    declared_functions: Rc<HashSet<(String, String, usize)>>,
 }
 
 impl TypeEnv {
@@ -511,32 +728,36 @@ impl TypeEnv {
     pub fn child(&self) -> Self {
         Self {
             vars: self.vars.clone(),
-            structs: self.structs.clone(),
-            enums: self.enums.clone(),
-            functions: self.functions.clone(),
-            methods: self.methods.clone(),
-            type_aliases: self.type_aliases.clone(),
-            traits: self.traits.clone(),
-            trait_impls: self.trait_impls.clone(),
-            module_traits: self.module_traits.clone(),
-            associated_types: self.associated_types.clone(),
-            extern_functions: self.extern_functions.clone(),
-            extern_module_names: self.extern_module_names.clone(),
-            extern_modules: self.extern_modules.clone(),
-            extern_imports: self.extern_imports.clone(),
-            extern_function_names: self.extern_function_names.clone(),
-            extern_module_aliases: self.extern_module_aliases.clone(),
+            names: Rc::clone(&self.names),
+            structs: Rc::clone(&self.structs),
+            enums: Rc::clone(&self.enums),
+            functions: Rc::clone(&self.functions),
+            methods: Rc::clone(&self.methods),
+            type_aliases: Rc::clone(&self.type_aliases),
+            traits: Rc::clone(&self.traits),
+            trait_impls: Rc::clone(&self.trait_impls),
+            module_traits: Rc::clone(&self.module_traits),
+            associated_types: Rc::clone(&self.associated_types),
+            extern_functions: Rc::clone(&self.extern_functions),
+            extern_module_names: Rc::clone(&self.extern_module_names),
+            extern_modules: Rc::clone(&self.extern_modules),
+            extern_imports: Rc::clone(&self.extern_imports),
+            extern_function_names: Rc::clone(&self.extern_function_names),
+            extern_module_aliases: Rc::clone(&self.extern_module_aliases),
+            declared_functions: Rc::clone(&self.declared_functions),
         }
     }
 
     /// Look up a variable in this scope and parent scopes.
     pub fn get_var(&self, name: &str) -> Option<&Ty> {
-        self.vars.get(name)
+        let symbol = self.names.borrow().lookup(name)?;
+        self.vars.get(&symbol)
     }
 
     /// Bind a variable in this scope.
     pub fn bind_var(&mut self, name: String, ty: Ty) {
-        self.vars.insert(name, ty);
+        let symbol = self.names.borrow_mut().intern(&name);
+        self.vars.insert(symbol, ty);
     }
 
     /// Get struct info.
@@ -632,7 +853,7 @@ impl TypeEnv {
     /// Register an extern function import: `use jason::encode;`
     /// Maps local name "encode" -> ("jason", "encode")
     pub fn add_extern_import(&mut self, local_name: String, module: String, function: String) {
-        self.extern_imports.insert(local_name, (module, function));
+        Rc::make_mut(&mut self.extern_imports).insert(local_name, (module, function));
     }
 
     /// Check if a name is an imported extern function.
@@ -648,9 +869,9 @@ impl TypeEnv {
         // If the module has a #[name = "..."] attribute, use that; otherwise use the module path
         if let Some(beam_name) = self.extern_module_names.get(&module_path).cloned() {
             // Also register the alias -> BEAM name mapping for code generation
-            self.extern_module_names.insert(alias.clone(), beam_name);
+            Rc::make_mut(&mut self.extern_module_names).insert(alias.clone(), beam_name);
         }
-        self.extern_module_aliases.insert(alias, module_path);
+        Rc::make_mut(&mut self.extern_module_aliases).insert(alias, module_path);
     }
 
     /// Resolve a module name, checking aliases first.
@@ -666,6 +887,25 @@ impl TypeEnv {
     pub fn is_module_alias(&self, name: &str) -> bool {
         self.extern_module_aliases.contains_key(name)
     }
+
+    /// Whether `module::function/arity` is declared in an `extern` block.
+    pub fn is_declared_function(&self, module: &str, function: &str, arity: usize) -> bool {
+        self.declared_functions
+            .contains(&(module.to_string(), function.to_string(), arity))
+    }
+
+    /// The arities `module::function` is declared with in `extern` blocks,
+    /// smallest first.
+    pub fn declared_arities(&self, module: &str, function: &str) -> Vec<usize> {
+        let mut arities: Vec<usize> = self
+            .declared_functions
+            .iter()
+            .filter(|(m, f, _)| m == module && f == function)
+            .map(|(_, _, arity)| *arity)
+            .collect();
+        arities.sort_unstable();
+        arities
+    }
 }
 
 /// The type checker.
@@ -682,6 +922,11 @@ pub struct TypeChecker {
     current_return_type: Option<Ty>,
     /// Current function's span for error reporting
     current_function_span: Option<crate::compiler::lexer::Span>,
+    /// The type of the impl block being checked, which `Self` stands for
+    current_impl_type: Option<Ty>,
+    /// How many uses of each deprecated name the current function has had
+    /// reported, so each is pointed at where it is written
+    deprecated_uses: HashMap<String, usize>,
     /// Type variable substitutions from unification (reserved for future use)
     #[allow(dead_code)]
     substitutions: HashMap<u32, Ty>,
@@ -690,6 +935,28 @@ pub struct TypeChecker {
     current_type_param_bounds: HashMap<String, Vec<String>>,
     /// Current module being type-checked (for resolving local function calls)
     current_module: Option<String>,
+    /// Source of the module being checked, to find misspelt names in
+    current_source: Option<String>,
+    /// What to do with each category of warning
+    warning_levels: WarningLevels,
+    /// The items marked `#[deprecated]`, by the name they are looked up
+    /// with: `module::function` for functions, the plain name for structs
+    /// and enums
+    deprecated: HashMap<String, Deprecation>,
+    /// The operators that resolved to a trait impl, by the id of their
+    /// `Expr::Binary`, with the impl. Annotation turns these into calls.
+    operator_impls: HashMap<u64, OperatorImpl>,
+    /// Whether a guard is being checked, where an operator can't call an
+    /// impl
+    in_guard: bool,
+    /// The values shown through their type's own `display` or `inspect`
+    /// method, by the address of their `Expr`, with the type and the
+    /// method. Annotation wraps these in calls to it.
+    shown_impls: HashMap<*const Expr, (String, &'static str)>,
+    /// The interpolated strings and `format!` calls whose value goes
+    /// where IoData does, by the address of their `Expr`. Annotation
+    /// leaves these as a list of their pieces instead of one String.
+    iodata_templates: HashSet<*const Expr>,
 }
 
 impl TypeChecker {
@@ -701,9 +968,27 @@ impl TypeChecker {
             warnings: Vec::new(),
             current_return_type: None,
             current_function_span: None,
+            current_impl_type: None,
+            deprecated_uses: HashMap::new(),
             substitutions: HashMap::new(),
             current_type_param_bounds: HashMap::new(),
             current_module: None,
+            current_source: None,
+            warning_levels: WarningLevels::default(),
+            deprecated: HashMap::new(),
+            operator_impls: HashMap::new(),
+            in_guard: false,
+            shown_impls: HashMap::new(),
+            iodata_templates: HashSet::new(),
+        }
+    }
+
+    /// A type checker that reports warnings at `levels` rather than the
+    /// default levels.
+    pub fn with_warning_levels(levels: WarningLevels) -> Self {
+        Self {
+            warning_levels: levels,
+            ..Self::new()
         }
     }
 
@@ -716,30 +1001,200 @@ impl TypeChecker {
 
     /// Record an error and continue (for error recovery).
     fn error(&mut self, err: TypeError) {
+        let err = self.in_function(err);
         self.errors.push(err);
     }
 
+    /// Point an error that has no span of its own at the `fn` keyword of
+    /// the function being checked, so it still shows where it happened.
+    fn in_function(&self, err: TypeError) -> TypeError {
+        match &self.current_function_span {
+            Some(span) if err.span.is_none() && err.annotations.labels.is_empty() && span.end > span.start => {
+                let keyword = span.start..(span.start + 2).min(span.end);
+                err.with_label(keyword, "in this function")
+            }
+            _ => err,
+        }
+    }
+
+    /// Where `name` is first written in the function being checked, right
+    /// after a `.` if `after_dot`. Expressions carry no spans, so a
+    /// misspelt name is looked for in the source instead.
+    fn find_in_function(&self, name: &str, after_dot: bool) -> Option<crate::compiler::lexer::Span> {
+        self.find_nth_in_function(name, after_dot, 0)
+    }
+
+    /// Where `name` is written for the `nth` time (from 0) in the function
+    /// being checked, right after a `.` if `after_dot`. The function is
+    /// lexed, so a name inside a string or a comment is not counted.
+    fn find_nth_in_function(
+        &self,
+        name: &str,
+        after_dot: bool,
+        nth: usize,
+    ) -> Option<crate::compiler::lexer::Span> {
+        let source = self.current_source.as_deref()?;
+        let function = self.current_function_span.clone()?;
+        let tokens = crate::compiler::lexer::Lexer::new(source.get(function.clone())?).collect_tokens();
+        tokens
+            .iter()
+            .enumerate()
+            .filter(|(i, tok)| {
+                let is_name = matches!(&tok.token, Token::Ident(s) | Token::TypeIdent(s) if s == name);
+                let before_dot = *i > 0 && tokens[i - 1].token == Token::Dot;
+                is_name && before_dot == after_dot
+            })
+            .nth(nth)
+            .map(|(_, tok)| function.start + tok.span.start..function.start + tok.span.end)
+    }
+
+    /// Add a "did you mean" to an error about `name`, if it looks like a
+    /// misspelling of one of `candidates`: help naming the closest, and a
+    /// fix replacing `name` with it where it is written in the function.
+    fn suggest_name<'a>(
+        &self,
+        mut err: TypeError,
+        name: &str,
+        candidates: impl IntoIterator<Item = &'a str>,
+        after_dot: bool,
+    ) -> TypeError {
+        let Some(candidate) = did_you_mean(name, candidates) else {
+            return err;
+        };
+        err.help = Some(format!("did you mean `{}`?", candidate));
+        match self.find_in_function(name, after_dot) {
+            Some(span) => {
+                err.span.get_or_insert_with(|| span.clone().into());
+                err.with_suggestion(span, candidate, format!("replace with `{}`", candidate))
+            }
+            None => err,
+        }
+    }
+
+    /// Names a misspelt variable or function could have meant: the
+    /// variables in scope and the functions callable by their simple name.
+    fn value_names(&self) -> Vec<String> {
+        let names = self.env.names.borrow();
+        let functions = self.env.functions.keys().filter(|name| !name.contains("::"));
+        let vars = self.env.vars.keys().map(|&name| names.resolve(name).to_string());
+        vars.chain(functions.cloned()).collect()
+    }
+
+    /// Warn about a call to `name` that resolves to nothing, when it looks
+    /// like a misspelling of a function or variable that is in scope.
+    /// Calls to unknown names are otherwise left to the code generator.
+    fn check_unknown_function(&mut self, name: &str) {
+        if self.env.get_var(name).is_some() || CoreErlangEmitter::is_bif(name) {
+            return;
+        }
+        let names = self.value_names();
+        let Some(candidate) = did_you_mean(name, names.iter().map(String::as_str)).map(str::to_string) else {
+            return;
+        };
+        let mut warning = Warning::with_help(
+            format!("unknown function `{}`", name),
+            format!("did you mean `{}`?", candidate),
+        )
+        .with_code(ErrorCode::UnknownFunction);
+        if let Some(span) = self.find_in_function(name, false) {
+            warning.span = Some(span.clone());
+            let message = format!("replace with `{}`", candidate);
+            warning = warning.with_suggestion(span, candidate, message);
+        }
+        self.warn(warning);
+    }
+
+    /// Reject a pattern passed as a literal to `regex::new`, `compile` or
+    /// `compile_with` that `re` could never compile.
+    fn check_regex_literal(&mut self, func_name: &str, args: &[Expr]) {
+        if !matches!(func_name, "new" | "compile" | "compile_with") {
+            return;
+        }
+        let Some(Expr::String(pattern)) = args.first() else {
+            return;
+        };
+        let Err(e) = regex::check_pattern(pattern) else {
+            return;
+        };
+        let message = format!("invalid regular expression: {}", e.message);
+        let help = format!("at byte {} of the pattern", e.offset);
+        match self.find_in_function(func_name, false) {
+            Some(span) => self.error_with_span(message, help, span, ErrorCode::InvalidRegex),
+            None => self.error(TypeError::with_help(message, help).with_code(ErrorCode::InvalidRegex)),
+        }
+    }
+
     /// Record an error with a source span.
     fn error_with_span(
         &mut self,
         message: impl Into<String>,
         help: impl Into<String>,
         span: crate::compiler::lexer::Span,
+        code: ErrorCode,
     ) {
-        let mut err = TypeError::with_span(message, span);
+        let mut err = TypeError::with_span(message, span).with_code(code);
         err.help = Some(help.into());
         self.errors.push(err);
     }
 
-    /// Record a warning.
+    /// Record a warning at the level of its category: dropped if allowed,
+    /// and recorded as an error if denied.
     fn warn(&mut self, warning: Warning) {
-        // Add module context if available
-        let warning = if let Some(module) = &self.current_module {
-            warning.in_module(module.clone())
-        } else {
-            warning
+        match self.warning_levels.level_of(warning.code) {
+            LintLevel::Allow => {}
+            LintLevel::Warn => {
+                // Add module context if available
+                let warning = if let Some(module) = &self.current_module {
+                    warning.in_module(module.clone())
+                } else {
+                    warning
+                };
+                self.warnings.push(warning);
+            }
+            LintLevel::Deny => self.error(warning.into_error()),
+        }
+    }
+
+    /// Warn about a use of `key` if it is marked `#[deprecated]`. `name` is
+    /// how the use is written, to point at it, and to replace with the
+    /// suggested name if the use names the item itself rather than, say,
+    /// one of its variants.
+    fn check_deprecated(&mut self, kind: &str, key: &str, name: &str) {
+        let Some(deprecation) = self.deprecated.get(key).cloned() else {
+            return;
         };
-        self.warnings.push(warning);
+        let mut message = format!("use of deprecated {} `{}`", kind, name);
+        if let Some(note) = &deprecation.note {
+            message = format!("{}: {}", message, note);
+        }
+        let mut warning = Warning::new(message).with_code(ErrorCode::Deprecated);
+        let nth = self.deprecated_uses.entry(name.to_string()).or_insert(0);
+        *nth += 1;
+        let nth = *nth - 1;
+        warning.span = self.find_nth_in_function(name, false, nth);
+        if let (Some(suggestion), Some(span)) = (deprecation.suggestion, warning.span.clone()) {
+            if key.rsplit("::").next() == Some(name) {
+                warning = warning.with_fix(span, suggestion.clone(), format!("use `{}`", suggestion));
+            }
+        }
+        self.warn(warning);
+    }
+
+    /// Warn about a value of type `any` used where `expected` is: it is let
+    /// through unchecked, so a wrong value only fails at runtime.
+    fn check_coercion(&mut self, found: &Ty, expected: &Ty, span: Option<crate::compiler::lexer::Span>) {
+        let found = self.apply_substitutions(found);
+        let expected = self.apply_substitutions(expected);
+        if found != Ty::Any || matches!(expected, Ty::Any | Ty::Var(_) | Ty::Infer(_) | Ty::Error) {
+            return;
+        }
+        let mut warning = Warning::with_help(
+            format!("implicit coercion from `any` to `{}`", expected),
+            "declare the types of the Erlang functions this value comes from in an `extern mod`",
+        )
+        .with_code(ErrorCode::DynamicCoercion);
+        warning.span = span;
+        self.warn(warning);
     }
 
     /// Unify two types, recording substitutions for inference variables.
@@ -772,6 +1227,10 @@ impl TypeChecker {
             // AtomLiteral is a subtype of Atom
             (Ty::AtomLiteral(_), Ty::Atom) | (Ty::Atom, Ty::AtomLiteral(_)) => Ok(()),
 
+            // An untyped map is a `Map<K, V>` of unknown keys and values
+            (Ty::RawMap, Ty::Named { name, .. }) | (Ty::Named { name, .. }, Ty::RawMap)
+                if name == "Map" => Ok(()),
+
             // AtomLiterals with the same value unify
             (Ty::AtomLiteral(a), Ty::AtomLiteral(b)) if a == b => Ok(()),
 
@@ -808,7 +1267,7 @@ impl TypeChecker {
                 Ty::Named { name: n1, args: a1, .. },
                 Ty::Named { name: n2, args: a2, .. },
             ) => {
-                if n1 != n2 {
+                if !same_type_name(n1, n2) {
                     return Err(TypeError::new(format!(
                         "type mismatch: {} vs {}",
                         ty1, ty2
@@ -979,6 +1438,65 @@ impl TypeChecker {
         }
     }
 
+    /// Infer the type of a call argument passed where `expected` is wanted.
+    ///
+    /// A closure passed for a function parameter is checked with its
+    /// parameters bound to the parameter types already known from earlier
+    /// arguments (`T` in `map(items, |x| ..)` once `items` is checked), so
+    /// its body is type checked and its result type flows into the call.
+    fn infer_arg(&mut self, arg: &Expr, expected: &Ty) -> TypeResult<Ty> {
+        if let Expr::Closure { params, body } = arg {
+            if let Ty::Fn { params: expected_params, ret } = self.apply_substitutions(expected) {
+                if expected_params.len() == params.len() {
+                    let param_tys: Vec<Ty> = expected_params
+                        .into_iter()
+                        .map(|ty| if ty.has_infer() { Ty::Any } else { ty })
+                        .collect();
+
+                    let mut scope = self.env.child();
+                    for (param, ty) in params.iter().zip(&param_tys) {
+                        scope.bind_var(param.clone(), ty.clone());
+                    }
+                    let old_env = std::mem::replace(&mut self.env, scope);
+                    let body_ty = self.check_block(body);
+                    self.env = old_env;
+                    let body_ty = body_ty?;
+
+                    // A closure run for its effects may end in any value
+                    let ret = if *ret == Ty::Unit { Ty::Unit } else { body_ty };
+                    return Ok(Ty::Fn {
+                        params: param_tys,
+                        ret: Box::new(ret),
+                    });
+                }
+            }
+        }
+        let ty = self.infer_expr(arg)?;
+        // A value whose type has a `display` method goes where IoData does,
+        // as its text: `io::println(point)` prints `Point::display(point)`
+        if self.apply_substitutions(expected) == iodata()
+            && self.record_shown(arg, &ty, &["display"])
+        {
+            return Ok(Ty::String);
+        }
+        self.record_iodata(arg, expected);
+        Ok(ty)
+    }
+
+    /// Record `expr`, if it is an interpolated string or a `format!` going
+    /// where `expected` is IoData, to be built as an iolist: its pieces are
+    /// written out as they are rather than copied into one String first.
+    /// Returns whether it was recorded.
+    fn record_iodata(&mut self, expr: &Expr, expected: &Ty) -> bool {
+        if matches!(expr, Expr::StringInterpolation(_) | Expr::Format { .. })
+            && self.apply_substitutions(expected) == iodata()
+        {
+            self.iodata_templates.insert(expr as *const Expr);
+            return true;
+        }
+        false
+    }
+
     /// Instantiate a generic enum with fresh inference variables.
     fn instantiate_enum(&mut self, info: &EnumInfo) -> (EnumInfo, HashMap<String, Ty>) {
         if info.type_params.is_empty() {
@@ -1036,13 +1554,13 @@ impl TypeChecker {
                     "unit variant '{}' takes no arguments, got {}",
                     variant,
                     exprs.len()
-                )));
+                )).with_code(ErrorCode::ArgumentCount));
             }
             (VariantInfoKind::Unit, EnumVariantArgs::Struct(_)) => {
                 self.error(TypeError::new(format!(
                     "unit variant '{}' does not take struct fields",
                     variant
-                )));
+                )).with_code(ErrorCode::VariantShape));
             }
             (VariantInfoKind::Tuple(expected_tys), EnumVariantArgs::Tuple(exprs)) => {
                 if exprs.len() != expected_tys.len() {
@@ -1051,7 +1569,7 @@ impl TypeChecker {
                         variant,
                         expected_tys.len(),
                         exprs.len()
-                    )));
+                    )).with_code(ErrorCode::ArgumentCount));
                 }
                 // Check and unify argument types
                 for (arg, expected_ty) in exprs.iter().zip(expected_tys.iter()) {
@@ -1070,13 +1588,13 @@ impl TypeChecker {
                 self.error(TypeError::new(format!(
                     "tuple variant '{}' requires arguments",
                     variant
-                )));
+                )).with_code(ErrorCode::VariantShape));
             }
             (VariantInfoKind::Tuple(_), EnumVariantArgs::Struct(_)) => {
                 self.error(TypeError::new(format!(
                     "tuple variant '{}' should use tuple syntax, not struct syntax",
                     variant
-                )));
+                )).with_code(ErrorCode::VariantShape));
             }
             (VariantInfoKind::Struct(expected_fields), EnumVariantArgs::Struct(field_exprs)) => {
                 // Check each provided field
@@ -1095,7 +1613,7 @@ impl TypeChecker {
                         self.error(TypeError::new(format!(
                             "unknown field '{}' in variant '{}'",
                             field_name, variant
-                        )));
+                        )).with_code(ErrorCode::UnknownField));
                     }
                 }
                 // Check for missing required fields
@@ -1104,7 +1622,7 @@ impl TypeChecker {
                         self.error(TypeError::new(format!(
                             "missing field '{}' in variant '{}'",
                             expected_name, variant
-                        )));
+                        )).with_code(ErrorCode::MissingField));
                     }
                 }
             }
@@ -1112,13 +1630,13 @@ impl TypeChecker {
                 self.error(TypeError::new(format!(
                     "struct variant '{}' requires fields",
                     variant
-                )));
+                )).with_code(ErrorCode::VariantShape));
             }
             (VariantInfoKind::Struct(_), EnumVariantArgs::Tuple(_)) => {
                 self.error(TypeError::new(format!(
                     "struct variant '{}' should use struct syntax {{ field: value }}, not tuple syntax",
                     variant
-                )));
+                )).with_code(ErrorCode::VariantShape));
             }
         }
         Ok(())
@@ -1157,6 +1675,12 @@ impl TypeChecker {
                     "Pid" => Ty::Pid,
                     "Ref" => Ty::Ref,
                     "Binary" => Ty::Binary,
+                    // `Map<K, V>` keeps its key and value types; a bare `Map` is untyped
+                    "Map" if type_args.len() == 2 => Ty::Named {
+                        name: name.clone(),
+                        module: None,
+                        args: type_args.iter().map(|t| self.ast_type_to_ty(t)).collect(),
+                    },
                     "Map" => Ty::RawMap,
                     "Any" => Ty::Any,
                     "IoList" => Ty::List(Box::new(Ty::Union(vec![
@@ -1164,6 +1688,8 @@ impl TypeChecker {
                         Ty::Binary,   // binary
                         Ty::List(Box::new(Ty::Any)),  // nested iolist
                     ]))),
+                    // A binary or an iolist
+                    "IoData" => Ty::Union(vec![Ty::Binary, Ty::List(Box::new(Ty::Any))]),
                     _ => {
                         // Check if it's a type alias
                         if let Some(alias_info) = self.env.type_aliases.get(name).cloned() {
@@ -1225,6 +1751,8 @@ impl TypeChecker {
 
     /// Type check a module.
     pub fn check_module(&mut self, module: &Module) -> TypeResult<()> {
+        self.current_source = module.source.clone();
+
         // First pass: collect all type definitions
         self.collect_types(module)?;
 
@@ -1237,11 +1765,12 @@ impl TypeChecker {
 
         // Second pass: collect all function signatures
         self.collect_functions(module)?;
+        self.check_clauses(module);
 
         // Third pass: type check function bodies
         for item in &module.items {
             if let Item::Function(func) = item {
-                self.check_function(func)?;
+                self.check_function(func).map_err(|e| self.in_function(e))?;
             }
             if let Item::Impl(impl_block) = item {
                 self.check_impl_block(impl_block)?;
@@ -1261,8 +1790,10 @@ impl TypeChecker {
     /// use statements that may reference them are processed.
     fn collect_extern_mods(&mut self, module: &Module) -> TypeResult<()> {
         for item in &module.items {
-            if let Item::ExternMod(extern_mod) = item {
-                self.collect_extern_mod(extern_mod, &extern_mod.name);
+            match item {
+                Item::ExternMod(extern_mod) => self.collect_extern_mod(extern_mod, &extern_mod.name),
+                Item::ExternBlock(block) => self.collect_extern_block(block),
+                _ => {}
             }
         }
         Ok(())
@@ -1280,13 +1811,17 @@ impl TypeChecker {
                         .collect();
                     // Check for #[record = "name"] attribute
                     let record_name = get_record_name(&s.attrs);
-                    self.env.structs.insert(
+                    if let Some(deprecation) = get_deprecation(&s.attrs) {
+                        self.deprecated.insert(s.name.clone(), deprecation);
+                    }
+                    Rc::make_mut(&mut self.env.structs).insert(
                         s.name.clone(),
                         StructInfo {
                             name: s.name.clone(),
                             type_params: s.type_params.clone(),
                             fields,
                             record_name,
+                            elixir_module: get_elixir_struct(&s.attrs),
                         },
                     );
                 }
@@ -1311,7 +1846,10 @@ impl TypeChecker {
                             (v.name.clone(), kind)
                         })
                         .collect();
-                    self.env.enums.insert(
+                    if let Some(deprecation) = get_deprecation(&e.attrs) {
+                        self.deprecated.insert(e.name.clone(), deprecation);
+                    }
+                    Rc::make_mut(&mut self.env.enums).insert(
                         e.name.clone(),
                         EnumInfo {
                             name: e.name.clone(),
@@ -1350,7 +1888,7 @@ impl TypeChecker {
                             }
                         })
                         .collect();
-                    self.env.traits.insert(
+                    Rc::make_mut(&mut self.env.traits).insert(
                         trait_def.name.clone(),
                         TraitInfo {
                             name: trait_def.name.clone(),
@@ -1366,7 +1904,7 @@ impl TypeChecker {
                         .map(|(name, ty)| (name.clone(), self.ast_type_to_ty(ty)))
                         .collect();
                     let methods = impl_def.methods.iter().map(|m| m.name.clone()).collect();
-                    self.env.trait_impls.insert(
+                    Rc::make_mut(&mut self.env.trait_impls).insert(
                         (impl_def.trait_name.clone(), impl_def.type_name.clone()),
                         TraitImplInfo {
                             trait_name: impl_def.trait_name.clone(),
@@ -1378,17 +1916,18 @@ impl TypeChecker {
                 }
                 Item::TraitDecl(decl) => {
                     // Record that this module implements the trait
-                    self.env.module_traits.push(decl.trait_name.clone());
+                    Rc::make_mut(&mut self.env.module_traits).push(decl.trait_name.clone());
                     // Store associated type bindings for Self::X resolution
                     for (name, ty) in &decl.type_bindings {
                         let resolved_ty = self.ast_type_to_ty(ty);
-                        self.env.associated_types.insert(name.clone(), resolved_ty);
+                        Rc::make_mut(&mut self.env.associated_types).insert(name.clone(), resolved_ty);
                     }
                 }
                 Item::ExternMod(extern_mod) => {
                     // Collect external function signatures from .dreamt stubs
                     self.collect_extern_mod(extern_mod, &extern_mod.name);
                 }
+                Item::ExternBlock(block) => self.collect_extern_block(block),
                 Item::Use(use_decl) => {
                     // Check if this is an import from an extern module
                     self.collect_use_decl(use_decl);
@@ -1396,7 +1935,7 @@ impl TypeChecker {
                 Item::TypeAlias(alias) => {
                     // Store type alias with its type parameters for generic alias support
                     let ty = self.ast_type_to_ty(&alias.ty);
-                    self.env.type_aliases.insert(
+                    Rc::make_mut(&mut self.env.type_aliases).insert(
                         alias.name.clone(),
                         TypeAliasInfo {
                             name: alias.name.clone(),
@@ -1429,12 +1968,11 @@ impl TypeChecker {
             .unwrap_or_else(|| module_path.to_string());
 
         // Store the Dream name -> BEAM name mapping
-        self.env
-            .extern_module_names
+        Rc::make_mut(&mut self.env.extern_module_names)
             .insert(module_path.to_string(), beam_module_name.clone());
 
         // Register this as a known extern module
-        self.env.extern_modules.insert(module_path.to_string());
+        Rc::make_mut(&mut self.env.extern_modules).insert(module_path.to_string());
 
         for item in &extern_mod.items {
             match item {
@@ -1443,49 +1981,7 @@ impl TypeChecker {
                     let nested_path = format!("{}.{}", module_path, nested.name);
                     self.collect_extern_mod(nested, &nested_path);
                 }
-                ExternItem::Function(func) => {
-                    // Extract #[name = "actual_fn_name"] attribute if present
-                    let beam_fn_name = func
-                        .attrs
-                        .iter()
-                        .find_map(|attr| {
-                            if attr.name == "name" {
-                                if let AttributeArgs::Eq(value) = &attr.args {
-                                    return Some(value.clone());
-                                }
-                            }
-                            None
-                        })
-                        .unwrap_or_else(|| func.name.clone());
-
-                    let params: Vec<(String, Ty)> = func
-                        .params
-                        .iter()
-                        .map(|(name, ty)| (name.clone(), self.ast_type_to_ty(ty)))
-                        .collect();
-                    let ret = self.ast_type_to_ty(&func.return_type);
-                    let info = FnInfo {
-                        name: func.name.clone(),
-                        type_params: func.type_params.clone(),
-                        params,
-                        ret,
-                    };
-                    let arity = info.params.len();
-
-                    // Store the function info
-                    self.env.extern_functions.insert(
-                        (module_path.to_string(), func.name.clone(), arity),
-                        info,
-                    );
-
-                    // Store the Dream name -> BEAM name mapping if different
-                    if beam_fn_name != func.name {
-                        self.env.extern_function_names.insert(
-                            (module_path.to_string(), func.name.clone(), arity),
-                            beam_fn_name,
-                        );
-                    }
-                }
+                ExternItem::Function(func) => self.collect_extern_fn(func, module_path),
                 ExternItem::Type(_) => {
                     // TODO: Handle opaque type declarations
                     // For now, we skip them - they're just markers
@@ -1494,12 +1990,92 @@ impl TypeChecker {
         }
     }
 
+    /// Collect the signatures of an `extern "erlang"` or `extern "elixir"`
+    /// block. Each function names its own module, which is called by the
+    /// last segment of its path. NIFs are module functions, collected by
+    /// [`Self::collect_functions`].
+    fn collect_extern_block(&mut self, block: &ExternBlock) {
+        if block.abi == "nif" {
+            return;
+        }
+        for (path, func) in &block.functions {
+            let module = ExternBlock::local_module_name(path).to_string();
+            Rc::make_mut(&mut self.env.extern_module_names)
+                .insert(module.clone(), block.beam_module_name(path));
+            Rc::make_mut(&mut self.env.extern_modules).insert(module.clone());
+            self.collect_extern_fn(func, &module);
+            Rc::make_mut(&mut self.env.declared_functions)
+                .insert((module, func.name.clone(), func.params.len()));
+        }
+    }
+
+    /// Register an Elixir module imported with `use elixir::Enum;` or
+    /// `use elixir::Phoenix::PubSub as PS;`, called by `local_name`.
+    fn add_elixir_module(&mut self, local_name: &str, segments: &[String]) {
+        let beam_name = ast::elixir_module_name(segments.iter().map(String::as_str));
+        Rc::make_mut(&mut self.env.extern_module_names)
+            .insert(local_name.to_string(), beam_name);
+        Rc::make_mut(&mut self.env.extern_modules).insert(local_name.to_string());
+    }
+
+    /// Record the signature of an extern function of `module_path`.
+    fn collect_extern_fn(&mut self, func: &ExternFn, module_path: &str) {
+        // Extract #[name = "actual_fn_name"] attribute if present
+        let beam_fn_name = func
+            .attrs
+            .iter()
+            .find_map(|attr| {
+                if attr.name == "name" {
+                    if let AttributeArgs::Eq(value) = &attr.args {
+                        return Some(value.clone());
+                    }
+                }
+                None
+            })
+            .unwrap_or_else(|| func.name.clone());
+
+        let info = self.extern_fn_to_info(func);
+        let arity = info.params.len();
+
+        // Store the function info
+        Rc::make_mut(&mut self.env.extern_functions).insert(
+            (module_path.to_string(), func.name.clone(), arity),
+            info,
+        );
+
+        // Store the Dream name -> BEAM name mapping if different
+        if beam_fn_name != func.name {
+            Rc::make_mut(&mut self.env.extern_function_names).insert(
+                (module_path.to_string(), func.name.clone(), arity),
+                beam_fn_name,
+            );
+        }
+    }
+
+    /// Convert an extern function declaration to FnInfo.
+    fn extern_fn_to_info(&self, func: &ExternFn) -> FnInfo {
+        let params = func
+            .params
+            .iter()
+            .map(|(name, ty)| (name.clone(), self.ast_type_to_ty(ty)))
+            .collect();
+        FnInfo {
+            name: func.name.clone(),
+            type_params: func.type_params.clone(),
+            params,
+            ret: self.ast_type_to_ty(&func.return_type),
+        }
+    }
+
     /// Known Dream stdlib modules that should NOT be treated as extern modules.
     /// These modules live under the dream:: namespace and have their own implementations.
     const STDLIB_MODULES: &'static [&'static str] = &[
         "io", "list", "enumerable", "iterator", "option", "result",
         "string", "map", "file", "timer", "display", "convert",
         "process", "genserver", "supervisor", "application", "logger",
+        "task", "ets", "port", "node", "code", "codec", "json", "regex",
+        "time", "random", "math", "agent", "stream", "crypto", "iodata",
+        "ops",
     ];
 
     /// Check if a module name is a Dream stdlib module.
@@ -1513,6 +2089,18 @@ impl TypeChecker {
     /// Note: Dream stdlib modules take priority over extern modules with the same name.
     fn collect_use_decl(&mut self, use_decl: &UseDecl) {
         match &use_decl.tree {
+            UseTree::Path { module, name, rename } if is_elixir_path(module) => {
+                let mut segments = module.segments[1..].to_vec();
+                segments.push(name.clone());
+                self.add_elixir_module(rename.as_ref().unwrap_or(name), &segments);
+            }
+            UseTree::Group { module, items } if is_elixir_path(module) => {
+                for item in items {
+                    let mut segments = module.segments[1..].to_vec();
+                    segments.push(item.name.clone());
+                    self.add_elixir_module(item.rename.as_ref().unwrap_or(&item.name), &segments);
+                }
+            }
             UseTree::Path { module, name, rename } => {
                 // Check if this is a module alias: `use erlang::std::erlang as erl`
                 // Build the full path from module.segments + name
@@ -1542,10 +2130,10 @@ impl TypeChecker {
                     if let Some(alias) = rename {
                         // Register the module alias: alias -> extern module name (e.g., "erl" -> "erlang")
                         // NOT the full path - we want to resolve to the actual extern module name
-                        self.env.extern_module_aliases.insert(alias.clone(), name.clone());
+                        Rc::make_mut(&mut self.env.extern_module_aliases).insert(alias.clone(), name.clone());
                         // Also add to extern_module_names if there's a BEAM name mapping
                         if let Some(beam_name) = self.env.extern_module_names.get(name).cloned() {
-                            self.env.extern_module_names.insert(alias.clone(), beam_name);
+                            Rc::make_mut(&mut self.env.extern_module_names).insert(alias.clone(), beam_name);
                         }
                     }
                     return;
@@ -1599,14 +2187,25 @@ impl TypeChecker {
                 Item::Function(func) => {
                     let info = self.function_to_info(func);
                     // Store with both simple name and module-qualified name
-                    self.env.functions.insert(func.name.clone(), info.clone());
+                    Rc::make_mut(&mut self.env.functions).insert(func.name.clone(), info.clone());
                     let qualified_name = format!("{}::{}", module.name, func.name);
-                    self.env.functions.insert(qualified_name, info);
+                    if let Some(deprecation) = get_deprecation(&func.attrs) {
+                        self.deprecated.insert(qualified_name.clone(), deprecation);
+                    }
+                    Rc::make_mut(&mut self.env.functions).insert(qualified_name, info);
+                }
+                Item::ExternBlock(block) if block.abi == "nif" => {
+                    for (_, func) in &block.functions {
+                        let info = self.extern_fn_to_info(func);
+                        Rc::make_mut(&mut self.env.functions).insert(func.name.clone(), info.clone());
+                        Rc::make_mut(&mut self.env.functions)
+                            .insert(format!("{}::{}", module.name, func.name), info);
+                    }
                 }
                 Item::Impl(impl_block) => {
                     for method in &impl_block.methods {
                         let info = self.function_to_info(method);
-                        self.env.methods.insert(
+                        Rc::make_mut(&mut self.env.methods).insert(
                             (impl_block.type_name.clone(), method.name.clone()),
                             info,
                         );
@@ -1618,6 +2217,42 @@ impl TypeChecker {
         Ok(())
     }
 
+    /// Warn about function clauses that never run, because an earlier
+    /// clause without a guard matches every argument they would.
+    fn check_clauses(&mut self, module: &Module) {
+        let mut earlier: HashMap<&str, Vec<&Function>> = HashMap::new();
+        for item in &module.items {
+            let Item::Function(func) = item else {
+                continue;
+            };
+            let clauses = earlier.entry(func.name.as_str()).or_default();
+            let covering = clauses
+                .iter()
+                .find(|clause| {
+                    clause.guard.is_none()
+                        && clause.params.len() == func.params.len()
+                        && clause
+                            .params
+                            .iter()
+                            .zip(&func.params)
+                            .all(|(c, f)| pattern_covers(&c.pattern, &f.pattern))
+                })
+                .map(|clause| clause.span.clone());
+            clauses.push(func);
+
+            if let Some(covering) = covering {
+                let mut warning = Warning::with_help(
+                    format!("this clause of `{}` can never match", func.name),
+                    "an earlier clause matches every argument this one would; move this one before it",
+                )
+                .with_code(ErrorCode::UnreachableClause)
+                .with_label(covering, "this clause matches first");
+                warning.span = Some(func.span.clone());
+                self.warn(warning);
+            }
+        }
+    }
+
     /// Convert a function AST to FnInfo.
     fn function_to_info(&self, func: &Function) -> FnInfo {
         let params = func
@@ -1653,6 +2288,8 @@ impl TypeChecker {
 
         // Set current function span for error reporting
         self.current_function_span = Some(func.span.clone());
+        self.deprecated_uses.clear();
+        let _item = ice::item(&func.span);
 
         // Set type parameter bounds for this function (for checking calls within generic functions)
         let old_type_param_bounds =
@@ -1683,7 +2320,7 @@ impl TypeChecker {
 
         // Check guard expression (if any) has type bool
         if let Some(ref guard) = func.guard {
-            let guard_ty = self.infer_expr(guard)?;
+            let guard_ty = self.infer_guard(guard)?;
             if !self.types_compatible(&guard_ty, &Ty::Bool) {
                 self.error_with_span(
                     format!(
@@ -1692,12 +2329,16 @@ impl TypeChecker {
                     ),
                     format!("expected bool, found {}", guard_ty),
                     func.span.clone(),
+                    ErrorCode::NonBoolCondition,
                 );
             }
         }
 
         let body_ty = self.check_block(&func.body)?;
         self.env = old_env;
+        if let Some(tail) = &func.body.expr {
+            self.record_iodata(tail, &ret_ty);
+        }
 
         // Check return type matches
         if !self.types_compatible(&body_ty, &ret_ty) {
@@ -1708,6 +2349,7 @@ impl TypeChecker {
                 ),
                 format!("expected {}, found {}", ret_ty, body_ty),
                 func.span.clone(),
+                ErrorCode::TypeMismatch,
             );
         }
 
@@ -1726,7 +2368,7 @@ impl TypeChecker {
                 self.error(TypeError::new(format!(
                     "trait '{}' not found",
                     impl_def.trait_name
-                )));
+                )).with_code(ErrorCode::UndefinedTrait));
                 return;
             }
         };
@@ -1753,7 +2395,7 @@ impl TypeChecker {
                     impl_def.trait_name, impl_def.type_name
                 ),
                 format!("missing required methods: {}", missing_methods.join(", ")),
-            ));
+            ).with_code(ErrorCode::IncompleteTraitImpl));
         }
 
         // Check associated types are bound
@@ -1777,16 +2419,23 @@ impl TypeChecker {
                     impl_def.trait_name, impl_def.type_name
                 ),
                 format!("missing associated types: {}", missing_types.join(", ")),
-            ));
+            ).with_code(ErrorCode::IncompleteTraitImpl));
         }
     }
 
     /// Type check an impl block.
     fn check_impl_block(&mut self, impl_block: &ImplBlock) -> TypeResult<()> {
-        for method in &impl_block.methods {
-            self.check_function(method)?;
-        }
-        Ok(())
+        let impl_type = self.ast_type_to_ty(&ast::Type::Named {
+            name: impl_block.type_name.clone(),
+            type_args: vec![],
+        });
+        let outer = self.current_impl_type.replace(impl_type);
+        let result = impl_block
+            .methods
+            .iter()
+            .try_for_each(|method| self.check_function(method).map_err(|e| self.in_function(e)));
+        self.current_impl_type = outer;
+        result
     }
 
     /// Type check a block, returning the type of the final expression.
@@ -1807,8 +2456,8 @@ impl TypeChecker {
     /// Type check a statement.
     fn check_stmt(&mut self, stmt: &Stmt) -> TypeResult<()> {
         match stmt {
-            Stmt::Let { pattern, ty, value, else_block } => {
-                let value_ty = self.infer_expr(value)?;
+            Stmt::Let { pattern, ty, value, else_block, span } => {
+                let mut value_ty = self.infer_expr(value)?;
 
                 // If there's a type annotation, check it matches
                 if let Some(ann_ty) = ty {
@@ -1818,6 +2467,14 @@ impl TypeChecker {
                             format!("type mismatch in let binding"),
                             format!("expected {}, found {}", expected, value_ty),
                         ));
+                    } else {
+                        self.check_coercion(&value_ty, &expected, span.clone());
+                        // A value coerced from `any` has the annotated type
+                        // from here on, so it is only warned about once, and
+                        // a string built as IoData is only IoData
+                        if value_ty == Ty::Any || self.record_iodata(value, &expected) {
+                            value_ty = expected;
+                        }
                     }
                 }
 
@@ -1831,8 +2488,10 @@ impl TypeChecker {
             }
             Stmt::Expr { expr, span } => {
                 let ty = self.infer_expr(expr)?;
+                // A `yield` gives back only the go-ahead for the next value
+                let is_yield = matches!(expr, Expr::Call { func, args, .. } if ast::yielded(func, args).is_some());
                 // Warn if a non-unit value is discarded (unless it's a block expression)
-                if !self.is_block_expr(expr) && !self.is_unit_like(&ty) {
+                if !self.is_block_expr(expr) && !self.is_unit_like(&ty) && !is_yield {
                     let expr_desc = self.describe_expr(expr);
                     let warning = if let Some(s) = span {
                         Warning::with_help_and_span(
@@ -1846,9 +2505,12 @@ impl TypeChecker {
                             "use `let _ = ...` to explicitly ignore the value",
                         )
                     };
-                    self.warn(warning);
+                    self.warn(warning.with_code(ErrorCode::UnusedValue));
                 }
             }
+            // Only left in modules that were never configured, where which
+            // way it goes isn't known
+            Stmt::Cfg { .. } => {}
         }
         Ok(())
     }
@@ -1942,6 +2604,23 @@ impl TypeChecker {
                     self.bind_pattern(p, &Ty::Any)?;
                 }
             }
+            Pattern::Enum { name, variant, fields: EnumPatternFields::Tuple(patterns) }
+                if name.is_empty() && self.env.get_struct(variant).is_some_and(|info| info.is_tuple()) =>
+            {
+                // A tuple struct pattern binds its fields at their types
+                let field_tys = self.get_enum_variant_types("", variant);
+                if patterns.len() != field_tys.len() {
+                    self.error(TypeError::new(format!(
+                        "tuple struct '{}' has {} fields, but the pattern has {}",
+                        variant,
+                        field_tys.len(),
+                        patterns.len()
+                    )).with_code(ErrorCode::VariantShape));
+                }
+                for (p, field_ty) in patterns.iter().zip(field_tys.iter()) {
+                    self.bind_pattern(p, field_ty)?;
+                }
+            }
             Pattern::Enum { name: _, variant: _, fields } => {
                 // For enum patterns, bind fields to Any for now
                 match fields {
@@ -1962,7 +2641,12 @@ impl TypeChecker {
                 // Bind variables in binary pattern segments
                 for seg in segments {
                     // Each segment value is a pattern (could be Ident, Wildcard, etc.)
-                    self.bind_pattern(&seg.value, &Ty::Int)?;
+                    let seg_ty = match seg.segment_type {
+                        BitSegmentType::Binary => Ty::Binary,
+                        BitSegmentType::Float => Ty::Float,
+                        BitSegmentType::Integer | BitSegmentType::Utf8 => Ty::Int,
+                    };
+                    self.bind_pattern(&seg.value, &seg_ty)?;
                 }
             }
             _ => {}
@@ -1981,12 +2665,38 @@ impl TypeChecker {
                 // Type check each expression part (any type is allowed)
                 for part in parts {
                     if let StringPart::Expr(e) = part {
-                        self.infer_expr(e)?;
+                        let ty = self.infer_expr(e)?;
+                        self.record_shown(e, &ty, &["display", "inspect"]);
                     }
                 }
                 // Result is always String
                 Ok(Ty::String)
             }
+            Expr::Format { parts, span } => {
+                for part in parts {
+                    match part {
+                        FormatPart::Literal(_) => {}
+                        FormatPart::Display(e) => {
+                            let ty = self.infer_expr(e)?;
+                            let ty = self.apply_substitutions(&ty);
+                            if let Ty::Fn { .. } = ty {
+                                let mut error = TypeError::with_span(
+                                    format!("`{{}}` can't display a value of type {}", ty),
+                                    span.clone(),
+                                );
+                                error.help = Some("use `{:?}` to show how the BEAM prints it".to_string());
+                                return Err(error);
+                            }
+                            self.record_shown(e, &ty, &["display", "inspect"]);
+                        }
+                        FormatPart::Debug(e) => {
+                            let ty = self.infer_expr(e)?;
+                            self.record_shown(e, &ty, &["inspect"]);
+                        }
+                    }
+                }
+                Ok(Ty::String)
+            }
             Expr::Atom(name) => Ok(Ty::AtomLiteral(name.clone())),
             Expr::Bool(_) => Ok(Ty::Bool),
             Expr::Unit => Ok(Ty::Unit),
@@ -1999,15 +2709,29 @@ impl TypeChecker {
                     // It's a function reference
                     Ok(Ty::Any) // TODO: proper function type
                 } else {
-                    self.error(TypeError::new(format!("undefined variable: {}", name)));
+                    let err = TypeError::new(format!("undefined variable: {}", name)).with_code(ErrorCode::UndefinedVariable);
+                    let names = self.value_names();
+                    let err = self.suggest_name(err, name, names.iter().map(String::as_str), false);
+                    self.error(err);
                     Ok(Ty::Error)
                 }
             }
 
             // Binary operations
-            Expr::Binary { op, left, right } => {
+            Expr::Binary { op, left, right, id } => {
                 let left_ty = self.infer_expr(left)?;
                 let right_ty = self.infer_expr(right)?;
+                if let Some((operator_impl, operand_ty)) = self.operator_impl(*op, &left_ty, &right_ty) {
+                    if self.in_guard {
+                        self.error(TypeError::with_help(
+                            format!("operator {} on {} can't be used in a guard", op, operand_ty),
+                            "it calls a trait impl, and a guard can't call functions",
+                        ).with_code(ErrorCode::InvalidOperand));
+                    }
+                    self.operator_impls.insert(id.get(), operator_impl);
+                    let right_ty = self.impl_operand(&right_ty);
+                    return self.check_overloaded_op(*op, &operand_ty, &right_ty);
+                }
                 self.check_binary_op(*op, &left_ty, &right_ty)
             }
 
@@ -2035,7 +2759,7 @@ impl TypeChecker {
                     self.error(TypeError::with_help(
                         "if condition must be bool",
                         format!("found {}", cond_ty),
-                    ));
+                    ).with_code(ErrorCode::NonBoolCondition));
                 }
 
                 let then_ty = self.check_block(then_block)?;
@@ -2138,17 +2862,35 @@ impl TypeChecker {
             // Map literal: %{key => value, ...}
             Expr::MapLiteral(pairs) => {
                 // Infer types for all keys and values
+                let mut key_tys = Vec::new();
+                let mut value_tys = Vec::new();
                 for (key, value) in pairs {
-                    self.infer_expr(key)?;
-                    self.infer_expr(value)?;
+                    let key_ty = self.infer_expr(key)?;
+                    let value_ty = self.infer_expr(value)?;
+                    key_tys.push(self.apply_substitutions(&key_ty));
+                    value_tys.push(self.apply_substitutions(&value_ty));
+                }
+                // A literal whose keys share one type and values share another
+                // is a `Map<K, V>`; mixed ones (record-like maps) stay untyped
+                let uniform = |tys: &[Ty]| {
+                    tys.first()
+                        .filter(|first| !first.has_infer() && tys.iter().all(|t| t == *first))
+                        .cloned()
+                };
+                match (uniform(&key_tys), uniform(&value_tys)) {
+                    (Some(key_ty), Some(value_ty)) => Ok(Ty::Named {
+                        name: "Map".to_string(),
+                        module: None,
+                        args: vec![key_ty, value_ty],
+                    }),
+                    _ => Ok(Ty::Any),
                 }
-                // Return map type (we don't have a more specific map type yet)
-                Ok(Ty::Any)
             }
 
             // Struct initialization
             Expr::StructInit { name, fields, base } => {
                 if let Some(info) = self.env.get_struct(name).cloned() {
+                    self.check_deprecated("struct", name, name);
                     let struct_ty = Ty::Named {
                         name: name.clone(),
                         module: None,
@@ -2177,15 +2919,21 @@ impl TypeChecker {
                                 ));
                             }
                         } else {
-                            self.error(TypeError::new(format!(
+                            let err = TypeError::new(format!(
                                 "struct '{}' has no field '{}'",
                                 name, field_name
-                            )));
+                            )).with_code(ErrorCode::UnknownField);
+                            let fields = info.fields.iter().map(|(n, _)| n.as_str());
+                            let err = self.suggest_name(err, field_name, fields, false);
+                            self.error(err);
                         }
                     }
                     Ok(struct_ty)
                 } else {
-                    self.error(TypeError::new(format!("undefined struct: {}", name)));
+                    let err = TypeError::new(format!("undefined struct: {}", name)).with_code(ErrorCode::UndefinedStruct);
+                    let structs = self.env.structs.keys().map(String::as_str);
+                    let err = self.suggest_name(err, name, structs, false);
+                    self.error(err);
                     Ok(Ty::Error)
                 }
             }
@@ -2195,16 +2943,21 @@ impl TypeChecker {
                 let variant_as_enum = variant.clone();
                 let enum_name = type_name.as_ref().unwrap_or(&variant_as_enum);
                 if let Some(info) = self.env.get_enum(enum_name).cloned() {
+                    self.check_deprecated("enum", enum_name, enum_name);
+
                     // Instantiate the generic enum with fresh inference vars
                     let (instantiated, subst) = self.instantiate_enum(&info);
 
                     if let Some((_, variant_kind)) = instantiated.variants.iter().find(|(v, _)| v == variant) {
                         self.check_variant_args(variant, variant_kind, args)?;
                     } else {
-                        self.error(TypeError::new(format!(
+                        let err = TypeError::new(format!(
                             "enum '{}' has no variant '{}'",
                             enum_name, variant
-                        )));
+                        )).with_code(ErrorCode::UndefinedVariant);
+                        let variants = info.variants.iter().map(|(v, _)| v.as_str());
+                        let err = self.suggest_name(err, variant, variants, false);
+                        self.error(err);
                     }
 
                     // Build the type arguments from substitutions
@@ -2225,13 +2978,28 @@ impl TypeChecker {
                         args: type_args,
                     })
                 } else {
+                    // A tuple struct constructor: Meters(3)
+                    if type_name.is_none() {
+                        if let Some(info) = self.env.get_struct(variant).filter(|info| info.is_tuple()).cloned() {
+                            self.check_deprecated("struct", variant, variant);
+                            let field_tys = info.fields.iter().map(|(_, t)| t.clone()).collect();
+                            self.check_variant_args(variant, &VariantInfoKind::Tuple(field_tys), args)?;
+                            return Ok(Ty::Named {
+                                name: variant.clone(),
+                                module: None,
+                                args: vec![],
+                            });
+                        }
+                    }
+
                     // Could be a variant without a type name (e.g., Some(x))
                     // Search all enums for this variant
-                    for (name, info) in &self.env.enums.clone() {
+                    for (name, info) in Rc::clone(&self.env.enums).iter() {
                         if let Some((_, _expected_kind)) =
                             info.variants.iter().find(|(v, _)| v == variant)
                         {
                             // Found the enum - instantiate it
+                            self.check_deprecated("enum", name, variant);
                             let (instantiated, subst) = self.instantiate_enum(info);
                             if let Some((_, variant_kind)) = instantiated.variants.iter().find(|(v, _)| v == variant) {
                                 self.check_variant_args(variant, variant_kind, args)?;
@@ -2256,7 +3024,14 @@ impl TypeChecker {
                             });
                         }
                     }
-                    self.error(TypeError::new(format!("undefined variant: {}", variant)));
+                    let err = TypeError::new(format!("undefined variant: {}", variant)).with_code(ErrorCode::UndefinedVariant);
+                    let variants = self
+                        .env
+                        .enums
+                        .values()
+                        .flat_map(|info| info.variants.iter().map(|(v, _)| v.as_str()));
+                    let err = self.suggest_name(err, variant, variants, false);
+                    self.error(err);
                     Ok(Ty::Error)
                 }
             }
@@ -2269,10 +3044,13 @@ impl TypeChecker {
                         if let Some((_, field_ty)) = info.fields.iter().find(|(n, _)| n == field) {
                             return Ok(field_ty.clone());
                         } else {
-                            self.error(TypeError::new(format!(
+                            let err = TypeError::new(format!(
                                 "struct '{}' has no field '{}'",
                                 name, field
-                            )));
+                            )).with_code(ErrorCode::UnknownField);
+                            let fields = info.fields.iter().map(|(n, _)| n.as_str());
+                            let err = self.suggest_name(err, field, fields, true);
+                            self.error(err);
                         }
                     }
                 }
@@ -2299,7 +3077,7 @@ impl TypeChecker {
                             self.error(TypeError::new(format!(
                                 "the `?` operator can only be applied to Result or Option, found {}",
                                 ty
-                            )));
+                            )).with_code(ErrorCode::InvalidTry));
                             Ok(Ty::Error)
                         }
                     }
@@ -2307,7 +3085,7 @@ impl TypeChecker {
                     self.error(TypeError::new(format!(
                         "the `?` operator can only be applied to Result or Option, found {}",
                         ty
-                    )));
+                    )).with_code(ErrorCode::InvalidTry));
                     Ok(Ty::Error)
                 }
             }
@@ -2386,12 +3164,12 @@ impl TypeChecker {
 
                     // Check guard if present
                     if let Some(guard) = &arm.guard {
-                        let guard_ty = self.infer_expr(guard)?;
+                        let guard_ty = self.infer_guard(guard)?;
                         if !self.types_compatible(&guard_ty, &Ty::Bool) {
                             self.error(TypeError::with_help(
                                 "receive guard must be bool",
                                 format!("found {}", guard_ty),
-                            ));
+                            ).with_code(ErrorCode::NonBoolCondition));
                         }
                     }
 
@@ -2427,7 +3205,7 @@ impl TypeChecker {
 
                     // Check argument types
                     for (arg, (_, param_ty)) in args.iter().zip(instantiated.params.iter()) {
-                        let arg_ty = self.infer_expr(arg)?;
+                        let arg_ty = self.infer_arg(arg, param_ty)?;
                         if self.unify(&arg_ty, param_ty).is_err()
                             && !self.types_compatible(&arg_ty, param_ty)
                         {
@@ -2461,6 +3239,9 @@ impl TypeChecker {
             Expr::QuoteItem(_) => Ok(Ty::Any),
             Expr::QuoteRepetition { .. } => Ok(Ty::Any),
 
+            // Reported by the cfg pass, which decides whether it is left in
+            Expr::CompileError { .. } => Ok(Ty::Any),
+
             // For loop expressions
             Expr::For {
                 clauses,
@@ -2494,7 +3275,7 @@ impl TypeChecker {
                                 self.error(TypeError::with_help(
                                     "for loop filter must be bool",
                                     format!("found {}", filter_ty),
-                                ));
+                                ).with_code(ErrorCode::NonBoolCondition));
                             }
                         }
                     }
@@ -2519,20 +3300,43 @@ impl TypeChecker {
 
     /// Check a binary operation and return the result type.
     fn check_binary_op(&mut self, op: BinOp, left: &Ty, right: &Ty) -> TypeResult<Ty> {
+        let floats = [left, right].map(|ty| self.apply_substitutions(ty) == Ty::Float);
         match op {
+            // Arithmetic on floats: `+`, `-` and `*` take two floats and give
+            // a float. ints and floats don't mix, and `/` and `%` stay integer
+            // division and remainder.
+            BinOp::Add | BinOp::Sub | BinOp::Mul if floats.contains(&true) => {
+                for (side, ty) in [("left", left), ("right", right)] {
+                    if !self.types_compatible(ty, &Ty::Float) {
+                        self.error(TypeError::with_help(
+                            format!("operator {} requires float operands", op),
+                            format!("{} operand is {}", side, ty),
+                        ).with_note("convert an int with `math::to_float`").with_code(ErrorCode::InvalidOperand));
+                    }
+                }
+                Ok(Ty::Float)
+            }
+
             // Arithmetic: int -> int -> int
             BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Mod => {
                 if !self.types_compatible(left, &Ty::Int) {
-                    self.error(TypeError::with_help(
+                    let mut err = TypeError::with_help(
                         format!("operator {} requires int operands", op),
                         format!("left operand is {}", left),
-                    ));
+                    ).with_code(ErrorCode::InvalidOperand);
+                    if op == BinOp::Add && *left == Ty::String {
+                        err = err.with_note("strings are joined with interpolation: \"{a}{b}\"");
+                    }
+                    if let (Ty::Named { name, .. }, Some((trait_name, _))) = (left, op.trait_method()) {
+                        err = err.with_note(format!("implement `ops::{}` for `{}` to use {} on it", trait_name, name, op));
+                    }
+                    self.error(err);
                 }
                 if !self.types_compatible(right, &Ty::Int) {
                     self.error(TypeError::with_help(
                         format!("operator {} requires int operands", op),
                         format!("right operand is {}", right),
-                    ));
+                    ).with_code(ErrorCode::InvalidOperand));
                 }
                 Ok(Ty::Int)
             }
@@ -2543,7 +3347,7 @@ impl TypeChecker {
                     self.error(TypeError::with_help(
                         "comparison operands must have the same type",
                         format!("left: {}, right: {}", left, right),
-                    ));
+                    ).with_code(ErrorCode::InvalidOperand));
                 }
                 Ok(Ty::Bool)
             }
@@ -2554,19 +3358,121 @@ impl TypeChecker {
                     self.error(TypeError::with_help(
                         format!("operator {} requires bool operands", op),
                         format!("left operand is {}", left),
-                    ));
+                    ).with_code(ErrorCode::InvalidOperand));
                 }
                 if !self.types_compatible(right, &Ty::Bool) {
                     self.error(TypeError::with_help(
                         format!("operator {} requires bool operands", op),
                         format!("right operand is {}", right),
-                    ));
+                    ).with_code(ErrorCode::InvalidOperand));
                 }
                 Ok(Ty::Bool)
             }
         }
     }
 
+    /// The `ops` trait impl `op` calls on a `left` and a `right`, if it
+    /// calls one, with the type of the operands. That is the left
+    /// operand's, or the right's when the left's is unknown, as a closure
+    /// parameter's is; the left then takes it.
+    fn operator_impl(&mut self, op: BinOp, left: &Ty, right: &Ty) -> Option<(OperatorImpl, Ty)> {
+        let (trait_name, _) = op.trait_method()?;
+        let left = self.impl_operand(left);
+        let operand = match left {
+            Ty::Infer(_) | Ty::Any => self.impl_operand(right),
+            _ => left.clone(),
+        };
+        let (Ty::Named { name, .. } | Ty::Var(name)) = &operand else {
+            return None;
+        };
+        let is_trait = |t: &String| t.rsplit("::").next() == Some(trait_name);
+        let operator_impl = match self.current_type_param_bounds.get(name) {
+            Some(bounds) if bounds.iter().any(is_trait) => OperatorImpl::Bound,
+            Some(_) => return None,
+            None if self.env.trait_impls.keys().any(|(t, type_name)| type_name == name && is_trait(t)) => {
+                OperatorImpl::Type(name.clone())
+            }
+            None => return None,
+        };
+        if matches!(left, Ty::Infer(_)) {
+            self.unify(&left, &operand).ok()?;
+        }
+        Some((operator_impl, operand))
+    }
+
+    /// `ty` as far as it has been inferred, with `Self` as the type of the
+    /// impl being checked.
+    fn impl_operand(&self, ty: &Ty) -> Ty {
+        match (self.apply_substitutions(ty), &self.current_impl_type) {
+            (Ty::Named { name, .. }, Some(impl_type)) if name == "Self" => impl_type.clone(),
+            (ty, _) => ty,
+        }
+    }
+
+    /// Infer the type of a guard.
+    fn infer_guard(&mut self, guard: &Expr) -> TypeResult<Ty> {
+        let outer = std::mem::replace(&mut self.in_guard, true);
+        let ty = self.infer_expr(guard);
+        self.in_guard = outer;
+        ty
+    }
+
+    /// Check an operator that calls a trait impl: both operands are the
+    /// implementing type, as the trait's methods take `Self`.
+    fn check_overloaded_op(&mut self, op: BinOp, left: &Ty, right: &Ty) -> TypeResult<Ty> {
+        if !self.types_compatible(right, left) {
+            self.error(TypeError::with_help(
+                format!("operator {} on {} takes another {}", op, left, left),
+                format!("right operand is {}", right),
+            ).with_code(ErrorCode::InvalidOperand));
+        }
+        match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul => Ok(left.clone()),
+            _ => Ok(Ty::Bool),
+        }
+    }
+
+    /// Record that `expr`, a `ty`, is shown through the first of `methods`
+    /// its type has, if it has any of them.
+    fn record_shown(&mut self, expr: &Expr, ty: &Ty, methods: &[&'static str]) -> bool {
+        let Ty::Named { name, .. } = self.apply_substitutions(ty) else {
+            return false;
+        };
+        let Some(method) = methods.iter().copied().find(|method| self.has_show_method(&name, method)) else {
+            return false;
+        };
+        self.shown_impls.insert(expr as *const Expr, (name, method));
+        true
+    }
+
+    /// Whether `type_name` has a `method(self)`, of its own, derived or from
+    /// a trait impl.
+    fn has_show_method(&self, type_name: &str, method: &str) -> bool {
+        self.env
+            .get_method(type_name, method)
+            .is_some_and(|info| info.params.len() == 1)
+            || self.env.trait_impls.values().any(|info| {
+                info.type_name == type_name && info.methods.iter().any(|m| m == method)
+            })
+    }
+
+    /// `expr` annotated, in a call to the method it is shown through if
+    /// inference recorded one.
+    fn annotate_shown(&mut self, expr: &Expr) -> Expr {
+        let annotated = self.annotate_expr(expr);
+        match self.shown_impls.get(&(expr as *const Expr)) {
+            Some((type_name, method)) => Expr::Call {
+                func: Box::new(Expr::Path {
+                    segments: vec![type_name.clone(), method.to_string()],
+                }),
+                type_args: vec![],
+                inferred_type_args: vec![],
+                args: vec![annotated],
+            },
+            None => annotated,
+        }
+    }
+
     /// Check a unary operation and return the result type.
     fn check_unary_op(&mut self, op: UnaryOp, ty: &Ty) -> TypeResult<Ty> {
         match op {
@@ -2575,7 +3481,7 @@ impl TypeChecker {
                     self.error(TypeError::with_help(
                         "negation requires int operand",
                         format!("found {}", ty),
-                    ));
+                    ).with_code(ErrorCode::InvalidOperand));
                 }
                 Ok(Ty::Int)
             }
@@ -2584,7 +3490,7 @@ impl TypeChecker {
                     self.error(TypeError::with_help(
                         "logical not requires bool operand",
                         format!("found {}", ty),
-                    ));
+                    ).with_code(ErrorCode::InvalidOperand));
                 }
                 Ok(Ty::Bool)
             }
@@ -2645,6 +3551,8 @@ impl TypeChecker {
                 };
 
                 if let Some(info) = info {
+                    self.check_deprecated("function", &resolved_name, name);
+
                     // Instantiate generic function
                     let instantiated = if !type_args.is_empty() {
                         // Explicit type arguments (turbofish syntax)
@@ -2661,12 +3569,12 @@ impl TypeChecker {
                             resolved_name,
                             instantiated.params.len(),
                             args.len()
-                        )));
+                        )).with_code(ErrorCode::ArgumentCount));
                     }
 
                     // Check argument types and unify
                     for (arg, (_, param_ty)) in args.iter().zip(instantiated.params.iter()) {
-                        let arg_ty = self.infer_expr(arg)?;
+                        let arg_ty = self.infer_arg(arg, param_ty)?;
                         // Try to unify, fall back to compatibility check
                         if self.unify(&arg_ty, param_ty).is_err()
                             && !self.types_compatible(&arg_ty, param_ty)
@@ -2675,6 +3583,8 @@ impl TypeChecker {
                                 format!("type mismatch in call to '{}'", resolved_name),
                                 format!("expected {}, found {}", param_ty, arg_ty),
                             ));
+                        } else {
+                            self.check_coercion(&arg_ty, param_ty, None);
                         }
                     }
 
@@ -2700,12 +3610,12 @@ impl TypeChecker {
                                     qualified_name,
                                     instantiated.params.len(),
                                     args.len()
-                                )));
+                                )).with_code(ErrorCode::ArgumentCount));
                             }
 
                             // Check argument types
                             for (arg, (_, param_ty)) in args.iter().zip(instantiated.params.iter()) {
-                                let arg_ty = self.infer_expr(arg)?;
+                                let arg_ty = self.infer_arg(arg, param_ty)?;
                                 if self.unify(&arg_ty, param_ty).is_err()
                                     && !self.types_compatible(&arg_ty, param_ty)
                                 {
@@ -2728,7 +3638,7 @@ impl TypeChecker {
 
                         // Check argument types
                         for (arg, (_, param_ty)) in args.iter().zip(instantiated.params.iter()) {
-                            let arg_ty = self.infer_expr(arg)?;
+                            let arg_ty = self.infer_arg(arg, param_ty)?;
                             if self.unify(&arg_ty, param_ty).is_err()
                                 && !self.types_compatible(&arg_ty, param_ty)
                             {
@@ -2749,6 +3659,7 @@ impl TypeChecker {
                     Ok(Ty::Any)
                 } else {
                     // Unknown function - could be external
+                    self.check_unknown_function(name);
                     for arg in args {
                         self.infer_expr(arg)?;
                     }
@@ -2762,12 +3673,19 @@ impl TypeChecker {
                     let func_name = &segments[1];
                     let qualified_name = format!("{}::{}", module, func_name);
 
+                    if module == "regex" {
+                        self.check_regex_literal(func_name, args);
+                    }
+
                     // Check if this is a stdlib module - these take priority over extern modules
-                    // with the same name (e.g., `logger` is both a stdlib wrapper and an extern module)
-                    if Self::is_stdlib_module(module) {
+                    // with the same name (e.g., `logger` is both a stdlib wrapper and an extern module),
+                    // unless the function is declared in an `extern "erlang"` block
+                    if Self::is_stdlib_module(module) && !self.env.is_declared_function(module, func_name, args.len()) {
                         // Try looking up as dream::{module}::{func}
                         let stdlib_qualified = format!("dream::{}::{}", module, func_name);
                         if let Some(info) = self.env.get_function(&stdlib_qualified).cloned() {
+                            self.check_deprecated("function", &stdlib_qualified, func_name);
+
                             let instantiated = if !type_args.is_empty() {
                                 self.instantiate_function_with_args(&info, type_args, &stdlib_qualified)?
                             } else {
@@ -2781,12 +3699,12 @@ impl TypeChecker {
                                     stdlib_qualified,
                                     instantiated.params.len(),
                                     args.len()
-                                )));
+                                )).with_code(ErrorCode::ArgumentCount));
                             }
 
                             // Check argument types
                             for (arg, (_, param_ty)) in args.iter().zip(instantiated.params.iter()) {
-                                let arg_ty = self.infer_expr(arg)?;
+                                let arg_ty = self.infer_arg(arg, param_ty)?;
                                 if self.unify(&arg_ty, param_ty).is_err()
                                     && !self.types_compatible(&arg_ty, param_ty)
                                 {
@@ -2811,7 +3729,7 @@ impl TypeChecker {
 
                             // Check argument types
                             for (arg, (_, param_ty)) in args.iter().zip(instantiated.params.iter()) {
-                                let arg_ty = self.infer_expr(arg)?;
+                                let arg_ty = self.infer_arg(arg, param_ty)?;
                                 if self.unify(&arg_ty, param_ty).is_err()
                                     && !self.types_compatible(&arg_ty, param_ty)
                                 {
@@ -2825,6 +3743,17 @@ impl TypeChecker {
                             return Ok(self.apply_substitutions(&instantiated.ret));
                         }
 
+                        let arities = self.env.declared_arities(module, func_name);
+                        if !arities.is_empty() {
+                            let arities: Vec<String> = arities.iter().map(|a| a.to_string()).collect();
+                            self.error(TypeError::new(format!(
+                                "function '{}' expects {} arguments, got {}",
+                                qualified_name,
+                                arities.join(" or "),
+                                arity
+                            )).with_code(ErrorCode::ArgumentCount));
+                        }
+
                         // Unknown extern function - treat as any
                         for arg in args {
                             self.infer_expr(arg)?;
@@ -2833,6 +3762,8 @@ impl TypeChecker {
                     }
 
                     if let Some(info) = self.env.get_function(&qualified_name).cloned() {
+                        self.check_deprecated("function", &qualified_name, func_name);
+
                         // Instantiate generic function
                         let instantiated = if !type_args.is_empty() {
                             // Explicit type arguments (turbofish syntax)
@@ -2849,12 +3780,12 @@ impl TypeChecker {
                                 qualified_name,
                                 instantiated.params.len(),
                                 args.len()
-                            )));
+                            )).with_code(ErrorCode::ArgumentCount));
                         }
 
                         // Check argument types and unify
                         for (arg, (_, param_ty)) in args.iter().zip(instantiated.params.iter()) {
-                            let arg_ty = self.infer_expr(arg)?;
+                            let arg_ty = self.infer_arg(arg, param_ty)?;
                             // Try to unify, fall back to compatibility check
                             if self.unify(&arg_ty, param_ty).is_err()
                                 && !self.types_compatible(&arg_ty, param_ty)
@@ -2863,6 +3794,8 @@ impl TypeChecker {
                                     format!("type mismatch in call to '{}'", qualified_name),
                                     format!("expected {}, found {}", param_ty, arg_ty),
                                 ));
+                            } else {
+                                self.check_coercion(&arg_ty, param_ty, None);
                             }
                         }
 
@@ -2903,7 +3836,7 @@ impl TypeChecker {
                 func_name,
                 info.type_params.len(),
                 type_args.len()
-            )));
+            )).with_code(ErrorCode::TypeArgumentCount));
             return Ok(info.clone());
         }
 
@@ -2923,7 +3856,7 @@ impl TypeChecker {
                         "type {} does not implement trait {}",
                         ty, missing_trait
                     ),
-                ));
+                ).with_code(ErrorCode::UnsatisfiedBound));
             }
 
             subst.insert(type_param.name.clone(), ty);
@@ -2955,12 +3888,12 @@ impl TypeChecker {
                     self.error(TypeError::new(format!(
                         "method '{}' expects {} arguments, got {}",
                         method, expected_args, args.len()
-                    )));
+                    )).with_code(ErrorCode::ArgumentCount));
                 }
 
                 // Check argument types and unify
                 for (arg, (_, param_ty)) in args.iter().zip(instantiated.params.iter().skip(1)) {
-                    let arg_ty = self.infer_expr(arg)?;
+                    let arg_ty = self.infer_arg(arg, param_ty)?;
                     if self.unify(&arg_ty, param_ty).is_err()
                         && !self.types_compatible(&arg_ty, param_ty)
                     {
@@ -2990,8 +3923,9 @@ impl TypeChecker {
         }
 
         let mut result_ty: Option<Ty> = None;
+        let with_step = ast::with_step(arms).is_some();
 
-        for arm in arms {
+        for (i, arm) in arms.iter().enumerate() {
             // Create new scope for arm
             let mut scope = self.env.child();
             std::mem::swap(&mut self.env, &mut scope);
@@ -3001,12 +3935,12 @@ impl TypeChecker {
 
             // Check guard if present
             if let Some(guard) = &arm.guard {
-                let guard_ty = self.infer_expr(guard)?;
+                let guard_ty = self.infer_guard(guard)?;
                 if !self.types_compatible(&guard_ty, &Ty::Bool) {
                     self.error(TypeError::with_help(
                         "match guard must be bool",
                         format!("found {}", guard_ty),
-                    ));
+                    ).with_code(ErrorCode::NonBoolCondition));
                 }
             }
 
@@ -3015,6 +3949,11 @@ impl TypeChecker {
 
             std::mem::swap(&mut self.env, &mut scope);
 
+            // The value a `with` step didn't match is checked below
+            if with_step && i == 1 {
+                continue;
+            }
+
             // Check all arms have compatible types
             if let Some(ref expected) = result_ty {
                 if !self.types_compatible(&body_ty, expected) {
@@ -3028,18 +3967,57 @@ impl TypeChecker {
             }
         }
 
-        // Check exhaustiveness
-        let missing = self.check_exhaustiveness(scrutinee_ty, arms);
+        if with_step {
+            if let Some(value_ty) = &result_ty {
+                self.check_with_failure(scrutinee_ty, value_ty);
+            }
+        }
+
+        // Check exhaustiveness, knowing the variants of `self` in an impl
+        let missing = match (scrutinee_ty, &self.current_impl_type) {
+            (Ty::Named { name, .. }, Some(impl_type)) if name == "Self" => {
+                self.check_exhaustiveness(impl_type, arms)
+            }
+            _ => self.check_exhaustiveness(scrutinee_ty, arms),
+        };
         if !missing.is_empty() {
-            self.error(TypeError::with_help(
+            // An error unless the `non_exhaustive` category is lowered
+            self.warn(Warning::with_help(
                 "non-exhaustive match",
                 format!("missing patterns: {}", missing.join(", ")),
-            ));
+            ).with_code(ErrorCode::NonExhaustiveMatch));
         }
 
         Ok(result_ty.unwrap_or(Ty::Unit))
     }
 
+    /// Check that a value a `with` step didn't match can be the value of the
+    /// whole block. An `Err` keeps its error and a `None` stays `None`, so
+    /// two `Result`s only need the same error type.
+    fn check_with_failure(&mut self, failure_ty: &Ty, value_ty: &Ty) {
+        let failure_ty = self.apply_substitutions(failure_ty);
+        let value_ty = self.apply_substitutions(value_ty);
+        let fits = match (&failure_ty, &value_ty) {
+            (
+                Ty::Named { name: n1, args: a1, .. },
+                Ty::Named { name: n2, args: a2, .. },
+            ) if n1 == n2 && (n1 == "Result" || n1 == "Option") => match (a1.get(1), a2.get(1)) {
+                (Some(e1), Some(e2)) => self.types_compatible(e1, e2),
+                _ => true,
+            },
+            _ => self.types_compatible(&failure_ty, &value_ty),
+        };
+        if !fits {
+            self.error(TypeError::with_help(
+                "`with` step can't end the block with its value",
+                format!(
+                    "a value that doesn't match ends the block, but it is {} and the block's value is {}",
+                    failure_ty, value_ty
+                ),
+            ).with_code(ErrorCode::TypeMismatch));
+        }
+    }
+
     /// Check if two types are compatible (for assignment, comparison, etc.).
     fn types_compatible(&self, ty1: &Ty, ty2: &Ty) -> bool {
         // Any is compatible with everything
@@ -3081,6 +4059,13 @@ impl TypeChecker {
             (Ty::Ref, Ty::Ref) => true,
             (Ty::RawMap, Ty::RawMap) => true,
 
+            // Strings are UTF-8 binaries, so binary functions take them
+            (Ty::String, Ty::Binary) | (Ty::Binary, Ty::String) => true,
+
+            (Ty::RawMap, Ty::Named { name, .. }) | (Ty::Named { name, .. }, Ty::RawMap) => {
+                name == "Map"
+            }
+
             // AtomLiteral is compatible with Atom
             (Ty::AtomLiteral(_), Ty::Atom) | (Ty::Atom, Ty::AtomLiteral(_)) => true,
 
@@ -3110,7 +4095,7 @@ impl TypeChecker {
                 Ty::Named { name: n1, args: a1, .. },
                 Ty::Named { name: n2, args: a2, .. },
             ) => {
-                n1 == n2
+                same_type_name(n1, n2)
                     && a1.len() == a2.len()
                     && a1.iter().zip(a2.iter()).all(|(t1, t2)| self.types_compatible(t1, t2))
             }
@@ -3287,6 +4272,9 @@ impl TypeChecker {
                 }
             }
         }
+        if let Some(info) = self.env.get_struct(variant_name).filter(|info| info.is_tuple()) {
+            return info.fields.iter().map(|(_, ty)| ty.clone()).collect();
+        }
         vec![]
     }
 
@@ -3309,9 +4297,14 @@ impl TypeChecker {
                             Constructor::Variant(name.clone(), vname.clone(), arity)
                         })
                         .collect()
-                } else if self.env.get_struct(name).is_some() {
-                    // It's a struct - single constructor
-                    vec![Constructor::Struct(name.clone())]
+                } else if let Some(info) = self.env.get_struct(name) {
+                    // It's a struct - single constructor. A tuple struct is
+                    // matched like a variant of the same name: Meters(m)
+                    if info.is_tuple() {
+                        vec![Constructor::Variant(name.clone(), name.clone(), info.fields.len())]
+                    } else {
+                        vec![Constructor::Struct(name.clone())]
+                    }
                 } else if name == "Result" {
                     // Built-in Result<T, E> enum: Ok(T) | Err(E)
                     vec![
@@ -3373,6 +4366,27 @@ impl TypeChecker {
                 return !has_full_coverage;
             }
 
+            // If some constructor appears in no row, the wildcard is useful
+            // for it exactly when it is for the rows that start with a
+            // wildcard. Splitting on every constructor instead would never
+            // end for a list, whose tail is another list.
+            let heads: Vec<&Constructor> = matrix
+                .rows
+                .iter()
+                .filter_map(|row| row.first())
+                .filter(|p| !p.is_wildcard())
+                .map(|p| &p.ctor)
+                .collect();
+            if !all_ctors.iter().all(|c| heads.iter().any(|h| self.constructors_match(h, c))) {
+                let mut default = PatternMatrix::new();
+                for row in &matrix.rows {
+                    if row.first().is_some_and(|p| p.is_wildcard()) {
+                        default.push_row(row[1..].to_vec());
+                    }
+                }
+                return self.is_useful(&default, rest);
+            }
+
             // For exhaustive types, check if any constructor is useful
             for ctor in &all_ctors {
                 let specialized_matrix = self.specialize_matrix(matrix, ctor, &first.ty);
@@ -3610,20 +4624,22 @@ impl TypeChecker {
 
     fn annotate_stmt(&mut self, stmt: &Stmt) -> Stmt {
         match stmt {
-            Stmt::Let { pattern, ty, value, else_block } => Stmt::Let {
+            Stmt::Let { pattern, ty, value, else_block, span } => Stmt::Let {
                 pattern: pattern.clone(),
                 ty: ty.clone(),
                 value: self.annotate_expr(value),
                 else_block: else_block.as_ref().map(|b| self.annotate_block(b)),
+                span: span.clone(),
             },
             Stmt::Expr { expr: e, span } => Stmt::Expr { expr: self.annotate_expr(e), span: span.clone() },
+            Stmt::Cfg { .. } => stmt.clone(),
         }
     }
 
     fn annotate_expr(&mut self, expr: &Expr) -> Expr {
         match expr {
             Expr::Call { func, type_args, args, .. } => {
-                let annotated_args: Vec<Expr> = args.iter().map(|a| self.annotate_expr(a)).collect();
+                let annotated_args: Vec<Expr> = args.iter().map(|a| self.annotate_shown(a)).collect();
 
                 // Check if this is a call to an extern module: module::func(args)
                 if let Expr::Path { segments } = func.as_ref() {
@@ -3632,8 +4648,12 @@ impl TypeChecker {
                         let func_name = &segments[1];
 
                         // Only transform to ExternCall if it's an extern module but NOT a stdlib module.
-                        // Stdlib modules (io, logger, etc.) have Dream wrapper implementations that should be called.
-                        if self.env.is_extern_module(module) && !Self::is_stdlib_module(module) {
+                        // Stdlib modules (io, logger, etc.) have Dream wrapper implementations that should be called,
+                        // except for functions declared in an `extern "erlang"` block.
+                        if self.env.is_extern_module(module)
+                            && (!Self::is_stdlib_module(module)
+                                || self.env.is_declared_function(module, func_name, args.len()))
+                        {
                             // Resolve module alias to full path for code generation
                             let resolved_module = self.env.resolve_module_alias(module).to_string();
 
@@ -3731,18 +4751,26 @@ impl TypeChecker {
                     receiver: Box::new(self.annotate_expr(receiver)),
                     method: method.clone(),
                     type_args: type_args.clone(),
-                    args: args.iter().map(|a| self.annotate_expr(a)).collect(),
+                    args: args.iter().map(|a| self.annotate_shown(a)).collect(),
                     resolved_module: resolved_module.clone(),
                     inferred_type_args: vec![], // TODO: implement method type inference
                 }
             }
 
             // Recursively annotate compound expressions
-            Expr::Binary { op, left, right } => Expr::Binary {
-                op: *op,
-                left: Box::new(self.annotate_expr(left)),
-                right: Box::new(self.annotate_expr(right)),
-            },
+            Expr::Binary { op, left, right, id } => {
+                let left = self.annotate_expr(left);
+                let right = self.annotate_expr(right);
+                match self.operator_impls.get(&id.get()) {
+                    Some(operator_impl) => operator_call(*op, operator_impl, left, right),
+                    None => Expr::Binary {
+                        op: *op,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        id: *id,
+                    },
+                }
+            }
 
             Expr::Unary { op, expr } => Expr::Unary {
                 op: *op,
@@ -3758,6 +4786,7 @@ impl TypeChecker {
             Expr::Match { expr, arms } => Expr::Match {
                 expr: Box::new(self.annotate_expr(expr)),
                 arms: arms.iter().map(|arm| MatchArm {
+                    attrs: arm.attrs.clone(),
                     pattern: arm.pattern.clone(),
                     guard: arm.guard.as_ref().map(|g| Box::new(self.annotate_expr(g))),
                     body: self.annotate_expr(&arm.body),
@@ -3821,6 +4850,7 @@ impl TypeChecker {
 
             Expr::Receive { arms, timeout } => Expr::Receive {
                 arms: arms.iter().map(|arm| MatchArm {
+                    attrs: arm.attrs.clone(),
                     pattern: arm.pattern.clone(),
                     guard: arm.guard.as_ref().map(|g| Box::new(self.annotate_expr(g))),
                     body: self.annotate_expr(&arm.body),
@@ -3880,18 +4910,55 @@ impl TypeChecker {
 
             // Simple expressions that don't need annotation
             Expr::Int(_) | Expr::String(_) | Expr::Charlist(_) | Expr::Atom(_)
-            | Expr::Bool(_) | Expr::Unit | Expr::Ident(_) | Expr::Path { .. } => expr.clone(),
+            | Expr::Bool(_) | Expr::Unit | Expr::Ident(_) | Expr::Path { .. }
+            | Expr::CompileError { .. } => expr.clone(),
+
+            // Built as IoData: the pieces in a list, literal text as a binary
+            Expr::StringInterpolation(parts) if self.iodata_templates.contains(&(expr as *const Expr)) => {
+                Expr::List(parts.iter().map(|part| match part {
+                    StringPart::Literal(s) => utf8_binary(s),
+                    StringPart::Expr(e) => display_call("to_string", self.annotate_shown(e)),
+                }).collect())
+            }
+
+            Expr::Format { parts, .. } if self.iodata_templates.contains(&(expr as *const Expr)) => {
+                Expr::List(parts.iter().map(|part| match part {
+                    FormatPart::Literal(s) => utf8_binary(s),
+                    FormatPart::Display(e) if matches!(**e, Expr::String(_)) => (**e).clone(),
+                    FormatPart::Display(e) => display_call("to_string", self.annotate_shown(e)),
+                    FormatPart::Debug(e) if self.shown_impls.contains_key(&(e.as_ref() as *const Expr)) => {
+                        display_call("to_string", self.annotate_shown(e))
+                    }
+                    FormatPart::Debug(e) => display_call("display_any", self.annotate_expr(e)),
+                }).collect())
+            }
 
             Expr::StringInterpolation(parts) => {
                 let annotated_parts = parts.iter().map(|part| {
                     match part {
                         StringPart::Literal(s) => StringPart::Literal(s.clone()),
-                        StringPart::Expr(e) => StringPart::Expr(Box::new(self.annotate_expr(e))),
+                        StringPart::Expr(e) => StringPart::Expr(Box::new(self.annotate_shown(e))),
                     }
                 }).collect();
                 Expr::StringInterpolation(annotated_parts)
             }
 
+            Expr::Format { parts, span } => Expr::Format {
+                parts: parts
+                    .iter()
+                    .map(|part| match part {
+                        FormatPart::Literal(s) => FormatPart::Literal(s.clone()),
+                        FormatPart::Display(e) => FormatPart::Display(Box::new(self.annotate_shown(e))),
+                        // Shown through `inspect`, the part is its text already
+                        FormatPart::Debug(e) if self.shown_impls.contains_key(&(e.as_ref() as *const Expr)) => {
+                            FormatPart::Display(Box::new(self.annotate_shown(e)))
+                        }
+                        FormatPart::Debug(e) => FormatPart::Debug(Box::new(self.annotate_expr(e))),
+                    })
+                    .collect(),
+                span: span.clone(),
+            },
+
             // Quote/Unquote - annotate inner expressions
             Expr::Quote(inner) => Expr::Quote(Box::new(self.annotate_expr(inner))),
             Expr::Unquote(inner) => Expr::Unquote(Box::new(self.annotate_expr(inner))),
@@ -3956,6 +5023,7 @@ impl TypeChecker {
             // For Result<(), E>: Erlang returns just 'ok' atom
             // :ok => Ok(())
             MatchArm {
+                attrs: vec![],
                 pattern: Pattern::Atom("ok".to_string()),
                 guard: None,
                 body: Expr::EnumVariant {
@@ -3968,6 +5036,7 @@ impl TypeChecker {
             // For Result<T, E>: Erlang returns {:ok, value}
             // (:ok, __val) => Ok(__val)
             MatchArm {
+                attrs: vec![],
                 pattern: Pattern::Tuple(vec![
                     Pattern::Atom("ok".to_string()),
                     Pattern::Ident("__ffi_val".to_string()),
@@ -3982,6 +5051,7 @@ impl TypeChecker {
         };
 
         let err_arm = MatchArm {
+            attrs: vec![],
             pattern: Pattern::Tuple(vec![
                 Pattern::Atom("error".to_string()),
                 Pattern::Ident("__ffi_err".to_string()),
@@ -4015,6 +5085,7 @@ impl TypeChecker {
         // :undefined => None
         // __val => Some(__val)
         let none_arm = MatchArm {
+            attrs: vec![],
             pattern: Pattern::Atom("undefined".to_string()),
             guard: None,
             body: Expr::EnumVariant {
@@ -4025,6 +5096,7 @@ impl TypeChecker {
         };
 
         let some_arm = MatchArm {
+            attrs: vec![],
             pattern: Pattern::Ident("__ffi_val".to_string()),
             guard: None,
             body: Expr::EnumVariant {
@@ -4136,6 +5208,9 @@ pub fn check_modules(modules: &[Module]) -> Vec<(String, TypeResult<Module>)> {
         checker.errors.clear();
         // Set current module for local function resolution
         checker.current_module = Some(module.name.clone());
+        checker.current_source = module.source.clone();
+        let _scope = ice::module(Phase::Typecheck, module);
+        checker.check_clauses(module);
 
         // Validate trait implementations for this module
         for item in &module.items {
@@ -4180,12 +5255,32 @@ pub struct TypeCheckResult {
     pub struct_info: HashMap<String, StructInfo>,
     /// Compiler warnings collected during type checking
     pub warnings: Vec<Warning>,
+    /// Signatures of module functions, keyed by qualified name
+    /// (`app::math::add`)
+    pub functions: HashMap<String, FnInfo>,
 }
 
 /// Type check multiple modules and return results with extern module name mappings.
 /// This is the preferred entry point when you need access to bindings metadata.
 pub fn check_modules_with_metadata(modules: &[Module]) -> TypeCheckResult {
-    let mut checker = TypeChecker::new();
+    check_modules_with_levels(modules, &WarningLevels::default())
+}
+
+/// [`check_modules_with_metadata`], reporting warnings at `levels`, as
+/// set by [`CompileOptions::warnings`](crate::config::CompileOptions).
+pub fn check_modules_with_levels(modules: &[Module], levels: &WarningLevels) -> TypeCheckResult {
+    check_modules_where(modules, levels, |_| true)
+}
+
+/// [`check_modules_with_levels`], checking the function bodies of only the
+/// modules `check` picks. The others still lend their definitions and
+/// signatures, but are left out of the results.
+pub fn check_modules_where(
+    modules: &[Module],
+    levels: &WarningLevels,
+    check: impl Fn(&Module) -> bool,
+) -> TypeCheckResult {
+    let mut checker = TypeChecker::with_warning_levels(levels.clone());
 
     // First pass: collect all extern mods from ALL modules
     // This ensures extern modules are registered before use statements are processed
@@ -4206,11 +5301,14 @@ pub fn check_modules_with_metadata(modules: &[Module]) -> TypeCheckResult {
 
     // Third pass: type check each module's function bodies
     let mut results = Vec::new();
-    for module in modules {
+    for module in modules.iter().filter(|module| check(module)) {
         // Clear errors before checking each module
         checker.errors.clear();
         // Set current module for local function resolution
         checker.current_module = Some(module.name.clone());
+        checker.current_source = module.source.clone();
+        let _scope = ice::module(Phase::Typecheck, module);
+        checker.check_clauses(module);
 
         // Validate trait implementations for this module
         for item in &module.items {
@@ -4241,10 +5339,14 @@ pub fn check_modules_with_metadata(modules: &[Module]) -> TypeCheckResult {
 
     TypeCheckResult {
         modules: results,
-        extern_module_names: checker.env.extern_module_names.clone(),
-        extern_function_names: checker.env.extern_function_names.clone(),
-        struct_info: checker.env.structs.clone(),
+        extern_module_names: Rc::unwrap_or_clone(checker.env.extern_module_names),
+        extern_function_names: Rc::unwrap_or_clone(checker.env.extern_function_names),
+        struct_info: Rc::unwrap_or_clone(checker.env.structs),
         warnings: checker.warnings,
+        functions: Rc::unwrap_or_clone(checker.env.functions)
+            .into_iter()
+            .filter(|(name, _)| name.contains("::"))
+            .collect(),
     }
 }
 
@@ -4259,6 +5361,7 @@ fn primitive_module(ty: &Ty) -> Option<&'static str> {
         Ty::String => Some("string"),
         Ty::List(_) => Some("enumerable"),
         Ty::RawMap => Some("map"),
+        Ty::Named { name, .. } if name == "Map" => Some("map"),
         // Result and Option types resolve to their stdlib modules
         Ty::Named { name, .. } if name == "Result" => Some("result"),
         Ty::Named { name, .. } if name == "Option" => Some("option"),
@@ -4273,13 +5376,17 @@ fn stdlib_method_return_type(module: &str, method: &str, recv_ty: &Ty) -> Ty {
     match module {
         "string" => match method {
             // String -> String methods
-            "trim" | "to_upper" | "to_lower" | "reverse" | "replace" | "slice" => Ty::String,
+            "trim" | "trim_left" | "trim_right" | "trim_chars" | "to_upper" | "to_lower"
+            | "capitalize" | "reverse" | "replace" | "slice" | "slice_from" | "byte_slice"
+            | "pad_left" | "pad_right" | "center" | "repeat" | "concat" => Ty::String,
             // String -> Int methods
-            "len" => Ty::Int,
+            "len" | "codepoint_len" | "byte_len" => Ty::Int,
             // String -> Bool methods
-            "contains" | "starts_with" | "ends_with" | "is_empty" => Ty::Bool,
+            "contains" | "starts_with" | "ends_with" | "is_empty" | "equal_ignore_case" => Ty::Bool,
             // String -> List methods
-            "split" | "chars" => Ty::List(Box::new(Ty::String)),
+            "split" | "chars" | "graphemes" | "codepoints" | "lines" | "words" => {
+                Ty::List(Box::new(Ty::String))
+            }
             _ => Ty::Any,
         },
         "enumerable" => match method {
@@ -4297,9 +5404,10 @@ fn stdlib_method_return_type(module: &str, method: &str, recv_ty: &Ty) -> Ty {
         },
         "map" => match method {
             // Map operations
-            "get" | "fetch" => Ty::Any,
-            "put" | "delete" | "merge" => Ty::RawMap,
-            "keys" | "values" => Ty::List(Box::new(Ty::Any)),
+            "get" | "get_or" | "fetch" | "fold" => Ty::Any,
+            // Map -> Map methods (preserve key and value types)
+            "put" | "update" | "delete" | "merge" | "filter" => recv_ty.clone(),
+            "keys" | "values" | "to_list" => Ty::List(Box::new(Ty::Any)),
             "size" => Ty::Int,
             "has_key" | "is_empty" => Ty::Bool,
             _ => Ty::Any,
@@ -4374,7 +5482,7 @@ impl MethodResolver {
 
     fn resolve_stmt(&mut self, stmt: &mut Stmt) {
         match stmt {
-            Stmt::Let { pattern, ty, value, else_block } => {
+            Stmt::Let { pattern, ty, value, else_block, .. } => {
                 self.resolve_expr(value);
 
                 // Resolve else block if present
@@ -4396,6 +5504,7 @@ impl MethodResolver {
             Stmt::Expr { expr, .. } => {
                 self.resolve_expr(expr);
             }
+            Stmt::Cfg { .. } => {}
         }
     }
 
@@ -4547,6 +5656,13 @@ impl MethodResolver {
                     }
                 }
             }
+            Expr::Format { parts, .. } => {
+                for part in parts {
+                    if let FormatPart::Display(e) | FormatPart::Debug(e) = part {
+                        self.resolve_expr(e);
+                    }
+                }
+            }
             // Leaf expressions - no children to resolve
             Expr::Int(_)
             | Expr::String(_)
@@ -4556,6 +5672,7 @@ impl MethodResolver {
             | Expr::Ident(_)
             | Expr::Path { .. }
             | Expr::Unit
+            | Expr::CompileError { .. }
             | Expr::Return(None) => {}
 
             // Quote/Unquote - resolve inner expressions
@@ -4604,7 +5721,7 @@ impl MethodResolver {
         match expr {
             Expr::String(_) => Ty::String,
             Expr::Charlist(_) => Ty::String,
-            Expr::StringInterpolation(_) => Ty::String,
+            Expr::StringInterpolation(_) | Expr::Format { .. } => Ty::String,
             Expr::Int(_) => Ty::Int,
             Expr::Bool(_) => Ty::Bool,
             Expr::Atom(name) => Ty::AtomLiteral(name.clone()),
@@ -4745,6 +5862,7 @@ impl MethodResolver {
                         Ty::Binary,
                         Ty::List(Box::new(Ty::Any)),
                     ]))),
+                    "IoData" => Ty::Union(vec![Ty::Binary, Ty::List(Box::new(Ty::Any))]),
                     _ => Ty::Named {
                         name: name.clone(),
                         module: None,
@@ -4784,6 +5902,485 @@ mod tests {
         check_module(&module)
     }
 
+    #[test]
+    fn test_child_scope() {
+        let mut env = TypeEnv::new();
+        env.bind_var("x".to_string(), Ty::Int);
+        env.add_extern_import("encode".to_string(), "jason".to_string(), "encode".to_string());
+
+        let mut child = env.child();
+        assert!(Rc::ptr_eq(&env.extern_imports, &child.extern_imports));
+        child.bind_var("y".to_string(), Ty::String);
+        child.add_extern_import("decode".to_string(), "jason".to_string(), "decode".to_string());
+
+        assert_eq!(child.get_var("x"), Some(&Ty::Int));
+        assert_eq!(child.get_var("y"), Some(&Ty::String));
+        assert_eq!(env.get_var("y"), None);
+        assert_eq!(env.get_var("never_bound_anywhere"), None);
+        assert!(child.get_extern_import("decode").is_some());
+        assert!(env.get_extern_import("decode").is_none());
+    }
+
+    #[test]
+    fn test_format() {
+        let result = parse_and_check(r#"
+            mod test {
+                fn greet(name: string, points: int) -> string {
+                    format!("{} has {:?} points", name, points)
+                }
+            }
+        "#);
+        assert!(result.is_ok());
+
+        let result = parse_and_check(r#"
+            mod test {
+                fn bad() -> string {
+                    let f = |x| { x + 1 };
+                    format!("{}", f)
+                }
+            }
+        "#);
+        assert!(result.unwrap_err().message.contains("can't display"));
+    }
+
+    #[test]
+    fn test_module_qualified_type_names() {
+        let iter = r#"
+            mod iterator {
+                pub struct Iter { state: any }
+                pub fn empty() -> Iter { Iter { state: [] } }
+            }
+        "#;
+        let file = r#"
+            mod file {
+                fn lines() -> Result<iterator::Iter, any> {
+                    Ok(iterator::empty())
+                }
+            }
+        "#;
+        let modules = [iter, file].map(|src| Parser::new(src).parse_module().expect("parse error"));
+        let result = check_modules_with_metadata(&modules);
+        for (name, checked) in &result.modules {
+            assert!(checked.is_ok(), "{}: {:?}", name, checked);
+        }
+
+        assert!(same_type_name("iterator::Iter", "Iter"));
+        assert!(same_type_name("Iter", "iterator::Iter"));
+        assert!(!same_type_name("iterator::Iter", "stream::Iter"));
+    }
+
+    #[test]
+    fn test_float_arithmetic() {
+        let result = parse_and_check(r#"
+            mod geometry {
+                fn between(min: float, max: float, t: float) -> float {
+                    min + (max - min) * t
+                }
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        // Floats returned by calls, as in math::to_radians
+        let result = parse_and_check(r#"
+            mod geometry {
+                extern mod math {
+                    fn pi() -> float;
+                    fn fdiv(a: any, b: any) -> float;
+                }
+
+                fn to_radians(degrees: float) -> float {
+                    math::fdiv(degrees, 180) * math::pi()
+                }
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let err = parse_and_check(r#"
+            mod geometry {
+                fn half(x: float) -> float {
+                    x / 2
+                }
+            }
+        "#).unwrap_err();
+        assert_eq!(err.message, "operator / requires int operands");
+
+        let err = parse_and_check(r#"
+            mod geometry {
+                fn grow(x: float) -> float {
+                    x * 2
+                }
+            }
+        "#).unwrap_err();
+        assert_eq!(err.message, "operator * requires float operands");
+        assert_eq!(err.help.as_deref(), Some("right operand is int"));
+    }
+
+    #[test]
+    fn test_function_clauses() {
+        let result = parse_and_check(r#"
+            mod test {
+                fn fact(0) -> int {
+                    1
+                }
+                fn fact(n) {
+                    n * fact(n - 1)
+                }
+            }
+        "#);
+        assert!(result.is_ok());
+
+        let source = r#"
+            mod test {
+                fn fact(n: int) -> int {
+                    n * fact(n - 1)
+                }
+                fn fact(0) {
+                    1
+                }
+            }
+        "#;
+        let modules = [Parser::new(source).parse_module().expect("parse error")];
+        let result = check_modules_with_levels(&modules, &WarningLevels::default());
+        assert!(result.modules[0].1.is_ok());
+        assert_eq!(result.warnings.len(), 1);
+        assert_eq!(result.warnings[0].code, Some(ErrorCode::UnreachableClause));
+        assert_eq!(result.warnings[0].message, "this clause of `fact` can never match");
+    }
+
+    #[test]
+    fn test_tuple_struct() {
+        let result = parse_and_check(r#"
+            mod test {
+                struct Cents(int);
+
+                fn add(a: Cents, b: Cents) -> Cents {
+                    match a {
+                        Cents(x) => {
+                            let Cents(y) = b;
+                            Cents(x + y)
+                        }
+                    }
+                }
+
+                fn total() -> Cents {
+                    add(Cents(100), Cents(250))
+                }
+            }
+        "#);
+        assert!(result.is_ok());
+
+        let result = parse_and_check(r#"
+            mod test {
+                struct Meters(float);
+
+                fn double(m: Meters) -> Meters {
+                    m
+                }
+
+                fn main() -> Meters {
+                    double(15)
+                }
+            }
+        "#);
+        assert!(result.is_err());
+
+        let result = parse_and_check(r#"
+            mod test {
+                struct Meters(float);
+
+                fn main() -> Meters {
+                    Meters("far")
+                }
+            }
+        "#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_operator_overloading() {
+        let source = r#"
+            mod money {
+                enum Ordering { Less, Equal, Greater }
+                trait Add { fn add(self, other: Self) -> Self; }
+                trait Ord { fn cmp(self, other: Self) -> Ordering; }
+
+                struct Money(int);
+
+                impl Add for Money {
+                    fn add(self, other: Money) -> Money {
+                        let Money(a) = self;
+                        let Money(b) = other;
+                        Money(a + b)
+                    }
+                }
+
+                impl Ord for Money {
+                    fn cmp(self, other: Money) -> Ordering {
+                        Ordering::Equal
+                    }
+                }
+
+                fn total(a: Money, b: Money) -> Money {
+                    a + b
+                }
+
+                fn cheaper(a: Money, b: Money) -> bool {
+                    a < b
+                }
+
+                fn sum(a: int, b: int) -> int {
+                    a + b
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let result = check_modules_with_metadata(&[module]);
+        let module = result.modules[0].1.as_ref().expect("type error");
+        let body = |name: &str| {
+            module.items.iter().find_map(|item| match item {
+                Item::Function(f) if f.name == name => f.body.expr.as_deref().cloned(),
+                _ => None,
+            }).unwrap()
+        };
+        assert!(matches!(
+            body("total"),
+            Expr::Call { func, .. } if *func == Expr::Path { segments: vec!["Money".into(), "add".into()] }
+        ));
+        assert!(matches!(
+            body("cheaper"),
+            Expr::Binary { op: BinOp::Eq, right, .. } if *right == Expr::Atom("less".into())
+        ));
+        assert!(matches!(body("sum"), Expr::Binary { op: BinOp::Add, .. }));
+
+        let err = parse_and_check(r#"
+            mod money {
+                trait Add { fn add(self, other: Self) -> Self; }
+                struct Money(int);
+                impl Add for Money {
+                    fn add(self, other: Money) -> Money { self }
+                }
+
+                fn total(a: Money) -> Money {
+                    a + 1
+                }
+            }
+        "#).unwrap_err();
+        assert_eq!(err.message, "operator + on Money takes another Money");
+
+        let err = parse_and_check(r#"
+            mod money {
+                struct Money(int);
+
+                fn double(a: Money) -> Money {
+                    a * a
+                }
+            }
+        "#).unwrap_err();
+        assert_eq!(err.annotations.notes, ["implement `ops::Mul` for `Money` to use * on it"]);
+    }
+
+    #[test]
+    fn test_operator_overloading_operand_types() {
+        let source = r#"
+            mod vectors {
+                trait Add { fn add(self, other: Self) -> Self; }
+                trait Eq { fn eq(self, other: Self) -> bool; }
+
+                struct V { x: int }
+
+                impl Add for V {
+                    fn add(self, other: V) -> V {
+                        V { x: self.x + other.x }
+                    }
+                }
+
+                impl V {
+                    fn double(self) -> V {
+                        self + self
+                    }
+                }
+
+                fn sum<T: Add>(a: T, b: T) -> T {
+                    a + b
+                }
+
+                fn shift(a: V) -> V {
+                    let f = |y| { y + a };
+                    f(a)
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let result = check_modules_with_metadata(&[module]);
+        let module = result.modules[0].1.as_ref().expect("type error");
+        let body = |name: &str| {
+            module.items.iter().find_map(|item| match item {
+                Item::Function(f) if f.name == name => Some(f.body.clone()),
+                Item::Impl(block) => block.methods.iter().find(|f| f.name == name).map(|f| f.body.clone()),
+                _ => None,
+            }).unwrap()
+        };
+        let add_call = Expr::Path { segments: vec!["V".into(), "add".into()] };
+
+        // `self` is the impl's type
+        assert!(matches!(body("double").expr.as_deref(), Some(Expr::Call { func, .. }) if **func == add_call));
+
+        // A bounded type parameter dispatches on the value
+        assert!(matches!(
+            body("sum").expr.as_deref(),
+            Some(Expr::MethodCall { method, args, .. }) if method == "add" && args.len() == 1
+        ));
+
+        // A closure parameter takes the other operand's type
+        let Some(Stmt::Let { value: Expr::Closure { body: closure, .. }, .. }) = body("shift").stmts.first().cloned() else {
+            panic!("expected the closure");
+        };
+        assert!(matches!(closure.expr.as_deref(), Some(Expr::Call { func, .. }) if **func == add_call));
+
+        let err = parse_and_check(r#"
+            mod vectors {
+                trait Eq { fn eq(self, other: Self) -> bool; }
+                struct V { x: int }
+                impl Eq for V {
+                    fn eq(self, other: V) -> bool { true }
+                }
+
+                fn same(a: V, b: V) -> bool {
+                    match a {
+                        v if v == b => true,
+                        _ => false,
+                    }
+                }
+            }
+        "#).unwrap_err();
+        assert_eq!(err.message, "operator == on V can't be used in a guard");
+    }
+
+    #[test]
+    fn test_shown_through_display() {
+        let source = r#"
+            mod colors {
+                trait Inspect { fn inspect(self) -> String; }
+
+                enum Color { Red, Green }
+
+                impl Color {
+                    fn display(self) -> String {
+                        match self {
+                            Color::Red => "red",
+                            Color::Green => "green",
+                        }
+                    }
+                }
+
+                impl Inspect for Color {
+                    fn inspect(self) -> String { "Color" }
+                }
+
+                fn label(text: IoData) -> IoData { text }
+
+                fn greeting(c: Color) -> String { "the {c} one" }
+
+                fn debug(c: Color) -> String { format!("{:?}", c) }
+
+                fn printed(c: Color) -> IoData { label(c) }
+
+                fn count(n: int) -> String { "{n} left" }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let result = check_modules_with_metadata(&[module]);
+        let module = result.modules[0].1.as_ref().expect("type error");
+        let body = |name: &str| {
+            module.items.iter().find_map(|item| match item {
+                Item::Function(f) if f.name == name => f.body.expr.as_deref().cloned(),
+                _ => None,
+            }).unwrap()
+        };
+        let shown_through = |expr: &Expr, method: &str| matches!(
+            expr,
+            Expr::Call { func, .. } if **func == Expr::Path { segments: vec!["Color".into(), method.into()] }
+        );
+
+        let Expr::StringInterpolation(parts) = body("greeting") else { panic!() };
+        assert!(matches!(&parts[1], StringPart::Expr(e) if shown_through(e, "display")));
+        let Expr::Format { parts, .. } = body("debug") else { panic!() };
+        assert!(matches!(&parts[0], FormatPart::Display(e) if shown_through(e, "inspect")));
+        let Expr::Call { args, .. } = body("printed") else { panic!() };
+        assert!(shown_through(&args[0], "display"));
+        let Expr::StringInterpolation(parts) = body("count") else { panic!() };
+        assert!(matches!(&parts[0], StringPart::Expr(e) if **e == Expr::Ident("n".into())));
+    }
+
+    #[test]
+    fn test_strings_built_where_iodata_goes_are_lists() {
+        let source = r#"
+            mod page {
+                struct User { name: String }
+
+                impl User {
+                    fn display(self) -> String { self.name }
+                }
+
+                fn write(text: IoData) -> IoData { text }
+
+                fn row(u: User) -> IoData { format!("<li>{}</li>", u) }
+
+                fn title(name: String) -> IoData { write("<h1>{name}</h1>") }
+
+                fn heading(name: String) -> IoData {
+                    let h: IoData = "<h2>{name}</h2>";
+                    h
+                }
+
+                fn label(name: String) -> String { "<b>{name}</b>" }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let result = check_modules_with_metadata(&[module]);
+        let module = result.modules[0].1.as_ref().expect("type error");
+        let function = |name: &str| {
+            module.items.iter().find_map(|item| match item {
+                Item::Function(f) if f.name == name => Some(f.body.clone()),
+                _ => None,
+            }).unwrap()
+        };
+        let text = |expr: &Expr| match expr {
+            Expr::BitString(segments) => segments.iter().map(|seg| match *seg.value {
+                Expr::Int(b) => b as u8,
+                _ => panic!("not a byte"),
+            }).collect::<Vec<u8>>(),
+            _ => panic!("not a binary: {:?}", expr),
+        };
+        let display_call = |expr: &Expr, function: &str| matches!(
+            expr,
+            Expr::Call { func, .. } if **func == Expr::Path { segments: vec!["display".into(), function.into()] }
+        );
+
+        // A template a function returns as IoData
+        let Some(Expr::List(pieces)) = function("row").expr.as_deref().cloned() else { panic!() };
+        assert_eq!(pieces.len(), 3);
+        assert_eq!(text(&pieces[0]), b"<li>");
+        let Expr::Call { args, .. } = &pieces[1] else { panic!() };
+        assert!(display_call(&pieces[1], "to_string"));
+        assert!(matches!(&args[0], Expr::Call { func, .. }
+            if **func == Expr::Path { segments: vec!["User".into(), "display".into()] }));
+        assert_eq!(text(&pieces[2]), b"</li>");
+
+        // An argument for an IoData parameter
+        let Some(Expr::Call { args, .. }) = function("title").expr.as_deref().cloned() else { panic!() };
+        let Expr::List(pieces) = &args[0] else { panic!() };
+        assert_eq!(text(&pieces[0]), b"<h1>");
+        assert!(display_call(&pieces[1], "to_string"));
+
+        // A binding annotated IoData
+        let Stmt::Let { value, .. } = &function("heading").stmts[0] else { panic!() };
+        assert!(matches!(value, Expr::List(_)));
+
+        // Anywhere else, still a String
+        assert!(matches!(function("label").expr.as_deref(), Some(Expr::StringInterpolation(_))));
+    }
+
     #[test]
     fn test_simple_function() {
         let result = parse_and_check(r#"
@@ -4903,6 +6500,111 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_closure_checked_against_parameter_type() {
+        let checked = |body: &str| {
+            parse_and_check(&format!(
+                r#"
+                mod test {{
+                    fn map<T, U>(items: [T], f: fn(T) -> U) -> [U] {{
+                        :lists::map(f, items)
+                    }}
+
+                    fn keep<T>(items: [T], pred: fn(T) -> bool) -> [T] {{
+                        :lists::filter(pred, items)
+                    }}
+
+                    fn test() -> [int] {{
+                        {}
+                    }}
+                }}
+                "#,
+                body
+            ))
+        };
+        assert!(checked("map([1, 2], |x| { x + 1 })").is_ok());
+        assert!(checked("keep([1, 2], |x| { x > 1 })").is_ok());
+        // `x` is a string, known from the list
+        assert!(checked("map([\"a\"], |x| { x + 1 })").is_err());
+        // The predicate must return bool
+        assert!(checked("keep([1, 2], |x| { x + 1 })").is_err());
+    }
+
+    #[test]
+    fn test_string_passes_as_binary() {
+        let result = parse_and_check(r#"
+            mod test {
+                extern mod erlang {
+                    fn byte_size(b: Binary) -> int;
+                }
+
+                fn test() -> int {
+                    :erlang::byte_size("héllo")
+                }
+            }
+        "#);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_map_key_and_value_types() {
+        let checked = |call: &str| {
+            parse_and_check(&format!(
+                r#"
+                mod test {{
+                    extern mod maps {{
+                        fn new() -> map;
+                    }}
+
+                    fn total(ages: Map<String, int>) -> int {{
+                        0
+                    }}
+
+                    fn test() -> int {{
+                        {}
+                    }}
+                }}
+                "#,
+                call
+            ))
+        };
+
+        assert!(checked(r#"total({"ada" => 36, "alan" => 41})"#).is_ok());
+        // Untyped and record-like maps are accepted as any `Map<K, V>`
+        assert!(checked("total(:maps::new())").is_ok());
+        assert!(checked(r#"total({name: "ada", age: 36})"#).is_ok());
+        assert!(checked(r#"total({"ada" => "thirty-six"})"#).is_err());
+    }
+
+    #[test]
+    fn test_with_block_failure_types() {
+        let checked = |body: &str| {
+            parse_and_check(&format!(
+                r#"
+                mod test {{
+                    fn parse(s: String) -> Result<int, String> {{
+                        Ok(1)
+                    }}
+
+                    fn lookup(n: int) -> Option<int> {{
+                        None
+                    }}
+
+                    fn test() -> Result<int, String> {{
+                        {}
+                    }}
+                }}
+                "#,
+                body
+            ))
+        };
+
+        // An `Err` ends the block whatever its `Ok` type was
+        assert!(checked(r#"with { n <- parse("1")?; m <- parse("2")?; Ok(n + m) }"#).is_ok());
+        // A `None` can't be the value of a block of `Result`s
+        assert!(checked(r#"with { n <- parse("1")?; Some(m) <- lookup(n); Ok(m) }"#).is_err());
+    }
+
     #[test]
     fn test_extern_function_arity_lookup() {
         // With arity-based lookup, calling with wrong arity finds no stub
@@ -4923,6 +6625,89 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_extern_erlang_block() {
+        let checked = |body: &str| {
+            parse_and_check(&format!(
+                r#"
+                mod test {{
+                    extern "erlang" {{
+                        fn lists::reverse<T>(list: [T]) -> [T];
+                        fn timer::sleep(ms: int) -> atom;
+                    }}
+
+                    fn test() -> [int] {{
+                        {}
+                    }}
+                }}
+                "#,
+                body
+            ))
+        };
+        assert!(checked("lists::reverse([1, 2, 3])").is_ok());
+        // Declared functions are Erlang's, not the stdlib module's
+        assert!(checked("timer::sleep(10); [1]").is_ok());
+        assert!(checked("lists::reverse(\"abc\")").is_err());
+        let err = checked("lists::reverse([1], [2])").unwrap_err();
+        assert_eq!(err.code, ErrorCode::ArgumentCount);
+        assert!(err.message.contains("'lists::reverse' expects 1 arguments, got 2"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_elixir_modules() {
+        let result = parse_and_check(r#"
+            mod test {
+                use elixir::Jason;
+
+                extern "elixir" {
+                    fn Enum::sum(list: [int]) -> int;
+                }
+
+                fn total() -> int {
+                    Jason::encode(1);
+                    Enum::sum([1, 2, 3])
+                }
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let result = parse_and_check(r#"
+            mod test {
+                extern "elixir" {
+                    fn Enum::sum(list: [int]) -> int;
+                }
+
+                fn total() -> string {
+                    Enum::sum([1, 2, 3])
+                }
+            }
+        "#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_nif_functions() {
+        let checked = |body: &str| {
+            parse_and_check(&format!(
+                r#"
+                mod test {{
+                    extern "nif" {{
+                        fn add(a: int, b: int) -> int;
+                    }}
+
+                    fn test() -> int {{
+                        {}
+                    }}
+                }}
+                "#,
+                body
+            ))
+        };
+        assert!(checked("add(1, 2)").is_ok());
+        assert!(checked("add(1, \"2\")").is_err());
+        assert!(checked("add(1)").is_err());
+    }
+
     #[test]
     fn test_extern_function_wrong_arg_type() {
         // Extern function with wrong argument type should error
@@ -5098,6 +6883,36 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_match_on_list_of_structs() {
+        let result = parse_and_check(r#"
+            mod test {
+                pub struct Job { id: int }
+
+                fn total(jobs: [Job], acc: int) -> int {
+                    match jobs {
+                        [] => acc,
+                        [job | rest] => total(rest, acc + job.id),
+                    }
+                }
+            }
+        "#);
+        assert!(result.is_ok());
+
+        let err = parse_and_check(r#"
+            mod test {
+                pub struct Job { id: int }
+
+                fn first(jobs: [Job]) -> int {
+                    match jobs {
+                        [job | _] => job.id,
+                    }
+                }
+            }
+        "#).unwrap_err();
+        assert_eq!(err.code, ErrorCode::NonExhaustiveMatch);
+    }
+
     #[test]
     fn test_union_type_value_matches_variant() {
         // :ok should be assignable to :ok | :error
@@ -5248,4 +7063,214 @@ mod tests {
         "#);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_function_signatures() {
+        let module = Parser::new(r#"
+            mod shapes {
+                pub fn area(w: int, h: int) -> int {
+                    w * h
+                }
+            }
+        "#)
+        .parse_module()
+        .expect("parse error");
+        let result = check_modules_with_metadata(&[module]);
+        assert_eq!(result.functions["shapes::area"].signature(), "fn area(w: int, h: int) -> int");
+        assert!(!result.functions.contains_key("area"));
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let source = r#"
+            mod shapes {
+                struct Point { x: int, y: int }
+
+                fn total(count: int) -> int {
+                    cont + 1
+                }
+
+                fn first(p: Point) -> int {
+                    p.xx
+                }
+
+                fn twice(n: int) -> int {
+                    n * 2
+                }
+
+                fn four(n: int) -> int {
+                    twce(twice(n))
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let mut checker = TypeChecker::new();
+        checker.current_module = Some(module.name.clone());
+        assert!(checker.check_module(&module).is_err());
+
+        let find = |code| checker.errors.iter().find(|e| e.code == code).unwrap();
+        let err = find(ErrorCode::UndefinedVariable);
+        assert_eq!(err.help.as_deref(), Some("did you mean `count`?"));
+        let start = source.find("cont +").unwrap();
+        assert_eq!(err.span.map(|s| s.offset()), Some(start));
+        assert_eq!(err.annotations.suggestions[0].span, start..start + 4);
+        assert_eq!(err.annotations.suggestions[0].replacement, "count");
+
+        let err = find(ErrorCode::UnknownField);
+        assert_eq!(err.help.as_deref(), Some("did you mean `x`?"));
+        assert_eq!(err.span.map(|s| s.offset()), Some(source.find("xx").unwrap()));
+
+        let warning = &checker.warnings[0];
+        assert_eq!(warning.code, Some(ErrorCode::UnknownFunction));
+        assert_eq!(warning.annotations.suggestions[0].replacement, "twice");
+        assert_eq!(warning.span, Some(source.find("twce").unwrap()..source.find("twce").unwrap() + 4));
+    }
+
+    #[test]
+    fn test_warning_levels() {
+        let source = r#"
+            mod app {
+                #[deprecated(note = "use `area` instead")]
+                fn size(w: int, h: int) -> int {
+                    w * h
+                }
+
+                fn pick(b: bool) -> int {
+                    match b {
+                        true => 1,
+                    }
+                }
+
+                fn main() -> int {
+                    let n: int = :legacy::timestamp();
+                    size(n, 2) + pick(true)
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let modules = [module];
+
+        let result = check_modules_with_levels(&modules, &WarningLevels::default());
+        let err = result.modules[0].1.as_ref().unwrap_err();
+        assert_eq!(err.code, ErrorCode::NonExhaustiveMatch);
+        assert!(err.annotations.notes.is_empty());
+        let codes: Vec<_> = result.warnings.iter().map(|w| w.code).collect();
+        assert_eq!(codes, [Some(ErrorCode::Deprecated)]);
+        let warning = &result.warnings[0];
+        assert_eq!(warning.message, "use of deprecated function `size`: use `area` instead");
+        let start = source.find("size(n").unwrap();
+        assert_eq!(warning.span, Some(start..start + 4));
+
+        let mut levels = WarningLevels::new();
+        levels.set("non_exhaustive", LintLevel::Warn).unwrap();
+        levels.set("dynamic", LintLevel::Warn).unwrap();
+        levels.set("deprecated", LintLevel::Deny).unwrap();
+        let result = check_modules_with_levels(&modules, &levels);
+        let err = result.modules[0].1.as_ref().unwrap_err();
+        assert_eq!(err.code, ErrorCode::Deprecated);
+        assert_eq!(err.annotations.notes, ["`deprecated` warnings are denied"]);
+        let codes: Vec<_> = result.warnings.iter().map(|w| w.code).collect();
+        assert_eq!(
+            codes,
+            [Some(ErrorCode::NonExhaustiveMatch), Some(ErrorCode::DynamicCoercion)]
+        );
+        assert_eq!(result.warnings[1].message, "implicit coercion from `any` to `int`");
+    }
+
+    #[test]
+    fn test_yield_is_not_an_unused_value() {
+        let source = r#"
+            mod numbers {
+                fn count(n: int) -> Any {
+                    gen {
+                        yield n;
+                        yield n + 1;
+                    }
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let result = check_modules_with_metadata(&[module]);
+        assert!(result.warnings.iter().all(|w| w.code != Some(ErrorCode::UnusedValue)), "{:?}", result.warnings);
+    }
+
+    #[test]
+    fn test_deprecated_suggestion() {
+        let source = r#"
+            mod app {
+                #[deprecated(note = "charlists are going away", suggestion = "to_binary")]
+                fn to_charlist(n: int) -> int {
+                    n
+                }
+
+                fn to_binary(n: int) -> int {
+                    n
+                }
+
+                fn main() -> int {
+                    to_charlist(1)
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let result = check_modules_with_levels(&[module], &WarningLevels::default());
+        let warning = &result.warnings[0];
+        assert_eq!(
+            warning.message,
+            "use of deprecated function `to_charlist`: charlists are going away"
+        );
+        let start = source.find("to_charlist(1)").unwrap();
+        let fix = &warning.annotations.suggestions[0];
+        assert_eq!(fix.span, start..start + "to_charlist".len());
+        assert_eq!(fix.replacement, "to_binary");
+        assert!(fix.is_machine_applicable());
+    }
+
+    #[test]
+    fn test_invalid_regex_literal() {
+        let source = r#"
+            mod dates {
+                fn year(date: String) -> Any {
+                    regex::find(regex::new("(\\d{{4}}-"), date)
+                }
+
+                fn day(pattern: String) -> Any {
+                    regex::compile(pattern)
+                }
+
+                fn month() -> Any {
+                    regex::compile("(?<month>\\d\\d)")
+                }
+            }
+        "#;
+        let module = Parser::new(source).parse_module().expect("parse error");
+        let mut checker = TypeChecker::new();
+        checker.current_module = Some(module.name.clone());
+        assert!(checker.check_module(&module).is_err());
+
+        let errors: Vec<_> = checker.errors.iter().filter(|e| e.code == ErrorCode::InvalidRegex).collect();
+        assert_eq!(errors.len(), 1);
+        let err = errors[0];
+        assert_eq!(err.message, "invalid regular expression: missing `)` to close this group");
+        assert_eq!(err.help.as_deref(), Some("at byte 0 of the pattern"));
+        let start = source.find("new(").unwrap();
+        assert_eq!(err.span.map(|s| s.offset()), Some(start));
+    }
+
+    #[test]
+    fn test_check_every_stdlib_module() {
+        // The stdlib is checked as one program when it is compiled, and a
+        // module that fails stops every other one from being built
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("stdlib");
+        let mut loader = crate::compiler::ModuleLoader::with_package("dream".to_string(), dir.clone());
+        loader.load_all_in_dir(&dir).expect("stdlib should load");
+        let modules = loader.into_modules();
+
+        let failures: Vec<_> = check_modules_with_metadata(&modules)
+            .modules
+            .into_iter()
+            .filter_map(|(name, result)| result.err().map(|e| format!("{}: {}", name, e.message)))
+            .collect();
+        assert!(failures.is_empty(), "stdlib modules should type check:\n{}", failures.join("\n"));
+    }
 }
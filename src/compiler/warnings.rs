@@ -0,0 +1,198 @@
+//! Warning categories and their levels.
+//!
+//! Every warning the type checker and the cfg pass report belongs to a
+//! [`WarningCategory`], found from its [`ErrorCode`]. Each category has a default [`LintLevel`];
+//! the `[warnings]` table of dream.toml and the `-A`/`-W`/`-D` flags of the
+//! commands that compile change it for the whole build, through
+//! [`CompileOptions::warnings`](crate::config::CompileOptions). An allowed
+//! warning is dropped, and a denied one is reported as an error.
+
+use super::diagnostics::{did_you_mean, ErrorCode};
+use super::error::{TypeError, Warning};
+use super::lint::LintLevel;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A kind of warning whose level can be set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WarningCategory {
+    /// Values computed and then thrown away
+    Unused,
+    /// Uses of items marked `#[deprecated]`
+    Deprecated,
+    /// `match` expressions missing some patterns
+    NonExhaustive,
+    /// Values of type `any` used where a concrete type is expected
+    Dynamic,
+    /// Calls to names that look like misspellings
+    Unresolved,
+    /// `#[cfg]` conditions testing something the compiler doesn't know of
+    UnexpectedCfgs,
+    /// Function clauses that earlier clauses leave nothing to match
+    Unreachable,
+}
+
+impl WarningCategory {
+    pub const ALL: [WarningCategory; 7] = [
+        WarningCategory::Unused,
+        WarningCategory::Deprecated,
+        WarningCategory::NonExhaustive,
+        WarningCategory::Dynamic,
+        WarningCategory::Unresolved,
+        WarningCategory::UnexpectedCfgs,
+        WarningCategory::Unreachable,
+    ];
+
+    /// The name used in flags and dream.toml.
+    pub fn name(self) -> &'static str {
+        match self {
+            WarningCategory::Unused => "unused",
+            WarningCategory::Deprecated => "deprecated",
+            WarningCategory::NonExhaustive => "non_exhaustive",
+            WarningCategory::Dynamic => "dynamic",
+            WarningCategory::Unresolved => "unresolved",
+            WarningCategory::UnexpectedCfgs => "unexpected_cfgs",
+            WarningCategory::Unreachable => "unreachable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<WarningCategory> {
+        WarningCategory::ALL.into_iter().find(|category| category.name() == name)
+    }
+
+    /// The category of diagnostics with `code`, if they can be warnings.
+    pub fn of(code: ErrorCode) -> Option<WarningCategory> {
+        match code {
+            ErrorCode::UnusedValue => Some(WarningCategory::Unused),
+            ErrorCode::Deprecated => Some(WarningCategory::Deprecated),
+            ErrorCode::NonExhaustiveMatch => Some(WarningCategory::NonExhaustive),
+            ErrorCode::DynamicCoercion => Some(WarningCategory::Dynamic),
+            ErrorCode::UnknownFunction => Some(WarningCategory::Unresolved),
+            ErrorCode::UnexpectedCfg => Some(WarningCategory::UnexpectedCfgs),
+            ErrorCode::UnreachableClause => Some(WarningCategory::Unreachable),
+            _ => None,
+        }
+    }
+
+    /// Non-exhaustive matches crash at runtime, so they are errors unless
+    /// allowed; coercions from `any` are everywhere in FFI code, so they
+    /// are only reported when asked for.
+    pub fn default_level(self) -> LintLevel {
+        match self {
+            WarningCategory::NonExhaustive => LintLevel::Deny,
+            WarningCategory::Dynamic => LintLevel::Allow,
+            _ => LintLevel::Warn,
+        }
+    }
+}
+
+impl fmt::Display for WarningCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The level of each warning category, starting from the defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarningLevels {
+    levels: HashMap<WarningCategory, LintLevel>,
+    /// Level for every category that would otherwise warn, from `-D warnings`
+    warnings: Option<LintLevel>,
+}
+
+impl WarningLevels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the level of the category called `name`, or with `warnings`, of
+    /// every category that would warn.
+    pub fn set(&mut self, name: &str, level: LintLevel) -> Result<(), String> {
+        if name == "warnings" {
+            self.warnings = Some(level);
+            return Ok(());
+        }
+        let category = WarningCategory::from_name(name).ok_or_else(|| {
+            let names = WarningCategory::ALL.iter().map(|c| c.name());
+            let mut message = format!("unknown warning category `{}`", name);
+            if let Some(candidate) = did_you_mean(name, names.clone()) {
+                message.push_str(&format!("; did you mean `{}`?", candidate));
+            }
+            let names: Vec<_> = names.collect();
+            message.push_str(&format!(" (expected one of {})", names.join(", ")));
+            message
+        })?;
+        self.levels.insert(category, level);
+        Ok(())
+    }
+
+    pub fn level(&self, category: WarningCategory) -> LintLevel {
+        let level = self
+            .levels
+            .get(&category)
+            .copied()
+            .unwrap_or(category.default_level());
+        match (level, self.warnings) {
+            (LintLevel::Warn, Some(warnings)) => warnings,
+            _ => level,
+        }
+    }
+
+    /// The level of a diagnostic with `code`. Codes outside every
+    /// category always warn.
+    pub fn level_of(&self, code: Option<ErrorCode>) -> LintLevel {
+        code.and_then(WarningCategory::of)
+            .map_or(LintLevel::Warn, |category| self.level(category))
+    }
+
+    /// Split `warnings` into those still reported as warnings and the
+    /// denied ones, as errors. Allowed warnings are dropped.
+    pub fn apply(&self, warnings: impl IntoIterator<Item = Warning>) -> (Vec<Warning>, Vec<TypeError>) {
+        let mut kept = Vec::new();
+        let mut denied = Vec::new();
+        for warning in warnings {
+            match self.level_of(warning.code) {
+                LintLevel::Allow => {}
+                LintLevel::Warn => kept.push(warning),
+                LintLevel::Deny => denied.push(warning.into_error()),
+            }
+        }
+        (kept, denied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_warning_levels() {
+        let mut levels = WarningLevels::new();
+        assert_eq!(levels.level(WarningCategory::Unused), LintLevel::Warn);
+        assert_eq!(levels.level(WarningCategory::NonExhaustive), LintLevel::Deny);
+        assert_eq!(levels.level(WarningCategory::Dynamic), LintLevel::Allow);
+        assert_eq!(levels.level_of(None), LintLevel::Warn);
+        assert_eq!(levels.level_of(Some(ErrorCode::UnusedValue)), LintLevel::Warn);
+
+        levels.set("warnings", LintLevel::Deny).unwrap();
+        levels.set("deprecated", LintLevel::Allow).unwrap();
+        assert_eq!(levels.level(WarningCategory::Unused), LintLevel::Deny);
+        assert_eq!(levels.level(WarningCategory::Deprecated), LintLevel::Allow);
+        assert_eq!(levels.level(WarningCategory::Dynamic), LintLevel::Allow);
+
+        levels.set("non_exhaustive", LintLevel::Warn).unwrap();
+        assert_eq!(levels.level(WarningCategory::NonExhaustive), LintLevel::Deny);
+
+        let (kept, denied) = levels.apply([
+            Warning::new("unexpected `cfg` condition name: `tset`").with_code(ErrorCode::UnexpectedCfg),
+            Warning::new("use of deprecated function `size`").with_code(ErrorCode::Deprecated),
+        ]);
+        assert!(kept.is_empty());
+        assert_eq!(denied.len(), 1);
+        assert_eq!(denied[0].code, ErrorCode::UnexpectedCfg);
+        assert_eq!(denied[0].annotations.notes, ["`unexpected_cfgs` warnings are denied"]);
+
+        let err = levels.set("unsued", LintLevel::Deny).unwrap_err();
+        assert!(err.starts_with("unknown warning category `unsued`; did you mean `unused`?"), "{}", err);
+    }
+}
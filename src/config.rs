@@ -2,11 +2,15 @@
 //!
 //! Handles parsing of `dream.toml` manifest files and project discovery.
 
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::compiler::{did_you_mean, EmitKind, LintLevel, WarningLevels};
+use crate::target::TargetLayout;
+
 /// Error type for configuration operations.
 #[derive(Debug)]
 pub struct ConfigError {
@@ -41,14 +45,209 @@ pub struct ProjectConfig {
     #[serde(default)]
     pub dependencies: HashMap<String, Dependency>,
     /// Feature flags for conditional compilation.
-    /// Each feature can depend on other features.
-    /// Example: `[features]\n json = []\n full = ["json", "async"]`
+    /// Each feature can depend on other features, and the `default` entry
+    /// lists the features enabled when none are requested explicitly.
+    /// Example: `[features]\n default = ["json"]\n json = []\n full = ["json", "async"]`
     #[serde(default)]
     pub features: HashMap<String, Vec<String>>,
+    /// Build profiles: `[profile.dev]`, `[profile.release]`, or any other
+    /// name selected with `--profile`.
+    #[serde(default)]
+    pub profile: HashMap<String, ProfileConfig>,
+    /// Lint levels for `dream lint`, by lint name: `allow`, `warn` or `deny`.
+    /// Example: `[lints]\n shadowing = "warn"\n unused_imports = "deny"`
+    #[serde(default)]
+    pub lints: HashMap<String, String>,
+    /// Levels for the compiler's warnings, by category, or `warnings` for
+    /// every category that would warn: `allow`, `warn` or `deny`.
+    /// Example: `[warnings]\n deprecated = "allow"\n unused = "deny"`
+    #[serde(default)]
+    pub warnings: HashMap<String, String>,
+    /// `[build]` settings that apply whatever the profile.
+    #[serde(default)]
+    pub build: BuildConfig,
+    /// Root of the workspace this package is a member of. Set when the
+    /// package is loaded through [`ProjectConfig::from_project_root`] or
+    /// [`Workspace::load`], never read from dream.toml.
+    #[serde(skip)]
+    pub workspace_root: Option<PathBuf>,
+}
+
+/// A `[profile.<name>]` section. Unset fields keep the profile's defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// 0 keeps erlc's defaults, 1 inlines small functions, 2 inlines more
+    pub opt_level: Option<u8>,
+    /// Keep debug info in .beam files
+    pub debug: Option<bool>,
+    /// Enable `cfg(debug_assertions)`
+    pub debug_assertions: Option<bool>,
+    /// Where .beam files go, relative to the project root
+    pub out_dir: Option<String>,
+}
+
+/// A build profile with every setting resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    pub opt_level: u8,
+    pub debug: bool,
+    pub debug_assertions: bool,
+    /// Output directory override, relative to the project root
+    pub out_dir: Option<PathBuf>,
+}
+
+impl Profile {
+    /// The default profile: debug info and assertions on, no inlining.
+    pub fn dev() -> Self {
+        Self {
+            name: "dev".to_string(),
+            opt_level: 0,
+            debug: true,
+            debug_assertions: true,
+            out_dir: None,
+        }
+    }
+
+    /// `--release`: optimized, without debug info or assertions.
+    pub fn release() -> Self {
+        Self {
+            name: "release".to_string(),
+            opt_level: 2,
+            debug: false,
+            debug_assertions: false,
+            out_dir: None,
+        }
+    }
+
+    /// Flags to pass to erlc for this profile.
+    pub fn erlc_flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if self.debug {
+            flags.push("+debug_info".to_string());
+        }
+        match self.opt_level {
+            0 => {}
+            1 => flags.push("+inline".to_string()),
+            _ => {
+                flags.push("+inline".to_string());
+                flags.push("+{inline_size,100}".to_string());
+            }
+        }
+        flags
+    }
+
+    fn apply(mut self, config: &ProfileConfig) -> Self {
+        if let Some(level) = config.opt_level {
+            self.opt_level = level;
+        }
+        if let Some(debug) = config.debug {
+            self.debug = debug;
+        }
+        if let Some(assertions) = config.debug_assertions {
+            self.debug_assertions = assertions;
+        }
+        if let Some(dir) = &config.out_dir {
+            self.out_dir = Some(PathBuf::from(dir));
+        }
+        self
+    }
+}
+
+/// The `[build]` section.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BuildConfig {
+    /// Runtime the code is for, tested by `cfg(target = "...")`: `beam`
+    /// (the default) or `atomvm`
+    #[serde(default)]
+    pub target: RuntimeTarget,
+    /// Cfg options set for every build, added to those from `--cfg`.
+    /// Example: `[build.cfg]\n tracing = true\n log_level = "debug"`
+    #[serde(default)]
+    pub cfg: HashMap<String, CfgValue>,
+    /// Names `#[cfg]` may test although they are only set now and then,
+    /// with `--cfg`, so that they aren't warned about.
+    /// Example: `[build]\n check-cfg = ["otel"]`
+    #[serde(default, rename = "check-cfg")]
+    pub check_cfg: Vec<String>,
+}
+
+/// The runtime code is compiled for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuntimeTarget {
+    /// The Erlang/OTP virtual machine
+    #[default]
+    Beam,
+    /// AtomVM, the BEAM for microcontrollers
+    AtomVm,
+    /// Dream's built-in VM, for `dream run --native`
+    Native,
+}
+
+impl RuntimeTarget {
+    pub const ALL: [RuntimeTarget; 3] = [RuntimeTarget::Beam, RuntimeTarget::AtomVm, RuntimeTarget::Native];
+
+    /// The name tested by `cfg(target = "...")`.
+    pub fn name(self) -> &'static str {
+        match self {
+            RuntimeTarget::Beam => "beam",
+            RuntimeTarget::AtomVm => "atomvm",
+            RuntimeTarget::Native => "native",
+        }
+    }
+}
+
+/// The value of a `[build.cfg]` entry.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum CfgValue {
+    /// `tracing = true` sets `cfg(tracing)`; `false` leaves it unset
+    Flag(bool),
+    /// `log_level = "debug"` sets `cfg(log_level = "debug")`
+    Value(String),
+    /// `backend = ["pg", "sqlite"]` sets the key to each value
+    Values(Vec<String>),
+}
+
+/// Cfg names the compiler sets itself, which can't be set by hand.
+pub const RESERVED_CFG_NAMES: [&str; 5] = ["test", "debug_assertions", "feature", "target", "otp_release"];
+
+/// Parse a `--cfg` flag: `name`, `name=value` or `name="value"`.
+pub fn parse_cfg(spec: &str) -> ConfigResult<(String, Option<String>)> {
+    let (name, value) = match spec.split_once('=') {
+        Some((name, value)) => {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            (name.trim(), Some(value.to_string()))
+        }
+        None => (spec.trim(), None),
+    };
+    check_cfg_name(name).map_err(|e| ConfigError::new(format!("--cfg {}: {}", spec, e)))?;
+    Ok((name.to_string(), value))
+}
+
+/// Why `name` can't be set as a cfg option, if it can't.
+fn check_cfg_name(name: &str) -> Result<(), String> {
+    let mut chars = name.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("`{}` is not a valid cfg name", name));
+    }
+    if RESERVED_CFG_NAMES.contains(&name) {
+        return Err(format!("`{}` is set by the compiler and can't be set by hand", name));
+    }
+    Ok(())
 }
 
 /// A dependency specification.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(untagged)]
 pub enum Dependency {
     /// Simple version string: `jason = "1.4"`
@@ -58,7 +257,7 @@ pub enum Dependency {
 }
 
 /// Detailed dependency specification.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct DependencySpec {
     /// Version requirement (for hex deps)
     pub version: Option<String>,
@@ -76,6 +275,10 @@ pub struct DependencySpec {
     /// Whether this is a hex.pm package (default: true if version specified)
     #[serde(default)]
     pub hex: bool,
+    /// Another member of the same workspace: `other = { workspace = true }`.
+    /// Resolved to that member's `path` when the workspace is loaded.
+    #[serde(default)]
+    pub workspace: bool,
 }
 
 impl Dependency {
@@ -105,6 +308,19 @@ impl Dependency {
         }
     }
 
+    /// Get the branch, tag or commit to check out for a git dependency.
+    /// `None` means the repository's default branch.
+    pub fn git_rev(&self) -> Option<&str> {
+        match self {
+            Dependency::Detailed(spec) => spec
+                .git_ref
+                .as_deref()
+                .or(spec.tag.as_deref())
+                .or(spec.branch.as_deref()),
+            _ => None,
+        }
+    }
+
     /// Get the path if this is a path dependency.
     pub fn path(&self) -> Option<&str> {
         match self {
@@ -112,6 +328,11 @@ impl Dependency {
             _ => None,
         }
     }
+
+    /// Check if this refers to another workspace member.
+    pub fn is_workspace(&self) -> bool {
+        matches!(self, Dependency::Detailed(spec) if spec.workspace)
+    }
 }
 
 /// Application configuration from dream.toml's [application] section.
@@ -124,6 +345,9 @@ pub struct ApplicationConfig {
     /// Static environment variables for the application.
     #[serde(default)]
     pub env: HashMap<String, toml::Value>,
+    /// OTP applications to start before this one (e.g. `["crypto", "ssl"]`).
+    #[serde(default)]
+    pub extra_applications: Vec<String>,
 }
 
 /// Package metadata from dream.toml.
@@ -131,6 +355,8 @@ pub struct ApplicationConfig {
 pub struct Package {
     pub name: String,
     pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
     #[serde(default = "default_src_dir")]
     pub src: String,
 }
@@ -139,16 +365,20 @@ fn default_src_dir() -> String {
     "src".to_string()
 }
 
+fn read_manifest<T: DeserializeOwned>(path: &Path) -> ConfigResult<T> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        ConfigError::new(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+
+    toml::from_str(&content).map_err(|e| {
+        ConfigError::new(format!("Failed to parse {}: {}", path.display(), e))
+    })
+}
+
 impl ProjectConfig {
     /// Load configuration from a dream.toml file.
     pub fn load(path: &Path) -> ConfigResult<Self> {
-        let content = fs::read_to_string(path).map_err(|e| {
-            ConfigError::new(format!("Failed to read {}: {}", path.display(), e))
-        })?;
-
-        toml::from_str(&content).map_err(|e| {
-            ConfigError::new(format!("Failed to parse {}: {}", path.display(), e))
-        })
+        read_manifest(path)
     }
 
     /// Find the project root by looking for dream.toml in current and parent directories.
@@ -168,25 +398,61 @@ impl ProjectConfig {
     }
 
     /// Load configuration from the project root.
+    ///
+    /// If the package is a member of a workspace, its `{ workspace = true }`
+    /// dependencies are resolved and it shares the workspace's build
+    /// directory and lockfile.
     pub fn from_project_root() -> ConfigResult<(PathBuf, Self)> {
         let root = Self::find_project_root().ok_or_else(|| {
             ConfigError::new("Could not find dream.toml in current or parent directories")
         })?;
 
         let config_path = root.join("dream.toml");
+        if Workspace::is_virtual(&config_path)? {
+            return Err(ConfigError::new(format!(
+                "{} is a workspace without a [package]; run the command in a member \
+                 directory or select one with `-p <member>`",
+                config_path.display()
+            )));
+        }
+
+        if let Some(workspace) = Workspace::find(&root)? {
+            if let Some(member) = workspace.member_at(&root) {
+                return Ok((root, member.config.clone()));
+            }
+        }
+
         let config = Self::load(&config_path)?;
+        if let Some((name, _)) = config.dependencies.iter().find(|(_, dep)| dep.is_workspace()) {
+            return Err(ConfigError::new(format!(
+                "dependency `{}` has `workspace = true`, but {} is not a workspace member",
+                name, config.package.name
+            )));
+        }
 
         Ok((root, config))
     }
 
+    /// The directory holding `_build`, `deps` and dream.lock: the workspace
+    /// root for a workspace member, otherwise the project root itself.
+    pub fn shared_root<'a>(&'a self, project_root: &'a Path) -> &'a Path {
+        self.workspace_root.as_deref().unwrap_or(project_root)
+    }
+
     /// Get the source directory path relative to project root.
     pub fn src_dir(&self, project_root: &Path) -> PathBuf {
         project_root.join(&self.package.src)
     }
 
-    /// Get the build directory path relative to project root.
+    /// Get the build directory path relative to project root. Workspace
+    /// members all build into the workspace's `_build`.
     pub fn build_dir(&self, project_root: &Path) -> PathBuf {
-        project_root.join("_build")
+        self.target_layout(project_root).root().to_path_buf()
+    }
+
+    /// The layout of the build directory.
+    pub fn target_layout(&self, project_root: &Path) -> TargetLayout {
+        TargetLayout::for_root(self.shared_root(project_root))
     }
 
     /// Get the BEAM output directory following Elixir's structure:
@@ -197,11 +463,17 @@ impl ProjectConfig {
 
     /// Get the BEAM output directory for a specific environment.
     pub fn beam_dir_for_env(&self, project_root: &Path, env: &str) -> PathBuf {
-        self.build_dir(project_root)
-            .join(env)
-            .join("lib")
-            .join(&self.package.name)
-            .join("ebin")
+        self.target_layout(project_root)
+            .beam_dir(env, &self.package.name)
+    }
+
+    /// Get the BEAM output directory for a build profile: its `out-dir`,
+    /// or `_build/{profile}/lib/{app}/ebin/`.
+    pub fn profile_beam_dir(&self, project_root: &Path, profile: &Profile) -> PathBuf {
+        match &profile.out_dir {
+            Some(dir) => project_root.join(dir),
+            None => self.beam_dir_for_env(project_root, &profile.name),
+        }
     }
 
     /// Check if this project is configured as an application.
@@ -219,6 +491,118 @@ impl ProjectConfig {
         })
     }
 
+    /// Get the module `dream run` calls `main` on: `main.dream` when the
+    /// project has one, otherwise the root module (`lib.dream`).
+    pub fn entry_module(&self, project_root: &Path) -> String {
+        if self.src_dir(project_root).join("main.dream").exists() {
+            self.qualify_module("main")
+        } else {
+            self.qualify_module(&self.package.name)
+        }
+    }
+
+    /// Names of the extra binaries in `src/bin/`, sorted.
+    pub fn bin_names(&self, project_root: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(self.src_dir(project_root).join("bin"))
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p.extension().is_some_and(|ext| ext == "dream"))
+                    .filter_map(|p| p.file_stem().and_then(|s| s.to_str()).map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    /// Get the BEAM module of binary `name` (`src/bin/name.dream`), e.g.
+    /// `dream::my_app::bin::tool`. `None` if there is no such binary.
+    pub fn bin_module(&self, project_root: &Path, name: &str) -> Option<String> {
+        self.bin_names(project_root)
+            .iter()
+            .any(|n| n == name)
+            .then(|| self.qualify_module(&format!("bin::{}", name)))
+    }
+
+    /// Get the fully qualified BEAM module of the application callback
+    /// module, e.g. `dream::my_app::my_app`.
+    pub fn application_beam_module(&self) -> Option<String> {
+        self.application_module().map(|m| self.qualify_module(&m))
+    }
+
+    /// Qualify a module path from dream.toml into its BEAM module name.
+    /// `server` and `my_app::server` both become `dream::my_app::server`.
+    pub fn qualify_module(&self, module: &str) -> String {
+        let package = &self.package.name;
+        if module.starts_with("dream::") {
+            module.to_string()
+        } else if module.starts_with(&format!("{}::", package)) {
+            format!("dream::{}", module)
+        } else {
+            format!("dream::{}::{}", package, module)
+        }
+    }
+
+    /// Applications that must be running before this one starts: kernel and
+    /// stdlib, `extra_applications` as listed, then dependencies sorted by
+    /// name so the `.app` file is the same on every build. This is not a
+    /// start order; OTP starts each application's own requirements before
+    /// it, whatever its place in the list.
+    pub fn required_applications(&self) -> Vec<String> {
+        let mut apps = vec!["kernel".to_string(), "stdlib".to_string()];
+        if let Some(app) = &self.application {
+            apps.extend(app.extra_applications.iter().cloned());
+        }
+        let mut deps: Vec<&String> = self.dependencies.keys().collect();
+        deps.sort();
+        apps.extend(deps.into_iter().cloned());
+
+        let mut seen = HashSet::new();
+        apps.retain(|a| seen.insert(a.clone()));
+        apps
+    }
+
+    /// Render the OTP `.app` resource file for this project.
+    /// `module_names` are the compiled modules, with or without the
+    /// `dream::` prefix.
+    pub fn app_resource(&self, module_names: &[String]) -> String {
+        let modules = module_names
+            .iter()
+            .map(|m| {
+                if m.starts_with("dream::") {
+                    format!("'{}'", m)
+                } else {
+                    format!("'dream::{}'", m)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        // Without a mod entry OTP treats the project as a library application
+        let mod_entry = match self.application_beam_module() {
+            Some(module) => format!("  {{mod, {{'{}', []}}}},\n", module),
+            None => String::new(),
+        };
+
+        format!(
+            r#"{{application, {name}, [
+  {{description, "A Dream application"}},
+  {{vsn, "{version}"}},
+{mod_entry}  {{modules, [{modules}]}},
+  {{registered, []}},
+  {{applications, [{applications}]}}
+]}}.
+"#,
+            name = self.package.name,
+            version = self.package.version,
+            mod_entry = mod_entry,
+            modules = modules,
+            applications = self.required_applications().join(", "),
+        )
+    }
+
     /// Get the application environment configuration.
     pub fn application_env(&self) -> HashMap<String, toml::Value> {
         self.application
@@ -252,12 +636,513 @@ impl ProjectConfig {
 
         enabled
     }
+
+    /// Features listed under `[features] default`.
+    pub fn default_features(&self) -> Vec<String> {
+        self.features.get("default").cloned().unwrap_or_default()
+    }
+
+    /// Every feature declared under `[features]`, sorted. `default` is not
+    /// a feature of its own.
+    pub fn declared_features(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .features
+            .keys()
+            .filter(|name| *name != "default")
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// The features `selection` turns on, with everything they imply.
+    /// Requesting a feature the manifest does not declare is an error, as is
+    /// a feature implying one that is not declared.
+    pub fn select_features(&self, selection: &FeatureSelection) -> ConfigResult<HashSet<String>> {
+        let declared = self.declared_features();
+        let undeclared = |name: &String| !declared.contains(name);
+
+        for (feature, implied) in &self.features {
+            if let Some(missing) = implied.iter().find(|name| undeclared(name)) {
+                return Err(ConfigError::new(format!(
+                    "feature `{}` enables `{}`, which is not declared in [features]",
+                    feature, missing
+                )));
+            }
+        }
+        if let Some(missing) = selection.features.iter().find(|name| undeclared(name)) {
+            let available = if declared.is_empty() {
+                "it declares no features".to_string()
+            } else {
+                format!("available: {}", declared.join(", "))
+            };
+            let suggestion = did_you_mean(missing, declared.iter().map(String::as_str))
+                .map(|feature| format!("; did you mean `{}`?", feature))
+                .unwrap_or_default();
+            return Err(ConfigError::new(format!(
+                "package `{}` has no feature `{}`{} ({})",
+                self.package.name, missing, suggestion, available
+            )));
+        }
+
+        let mut requested = if selection.all_features {
+            declared.clone()
+        } else if selection.no_default_features {
+            Vec::new()
+        } else {
+            self.default_features()
+        };
+        requested.extend(selection.features.iter().cloned());
+        Ok(self.resolve_features(&requested))
+    }
+
+    /// Resolve a build profile: `dev` and `release` start from their
+    /// built-in settings, any other name must have a `[profile.<name>]`
+    /// section and starts from `dev`. The manifest section overrides either.
+    pub fn profile(&self, name: &str) -> ConfigResult<Profile> {
+        let base = match name {
+            "dev" => Profile::dev(),
+            "release" => Profile::release(),
+            _ if self.profile.contains_key(name) => Profile {
+                name: name.to_string(),
+                ..Profile::dev()
+            },
+            _ => {
+                return Err(ConfigError::new(format!(
+                    "profile `{}` is not defined in dream.toml",
+                    name
+                )));
+            }
+        };
+        Ok(match self.profile.get(name) {
+            Some(config) => base.apply(config),
+            None => base,
+        })
+    }
+
+    /// Build compile options for this project from the manifest.
+    /// Enables the features `selection` asks for, with everything they
+    /// imply, applies the named build profile, and points the paths at the
+    /// project's source directory and the profile's (or, in test mode, the
+    /// `test`) output directory.
+    pub fn compile_options(
+        &self,
+        project_root: &Path,
+        selection: &FeatureSelection,
+        profile: &str,
+        test_mode: bool,
+    ) -> ConfigResult<CompileOptions> {
+        let profile = self.profile(profile)?;
+        let out_dir = if test_mode {
+            self.beam_dir_for_env(project_root, "test")
+        } else {
+            self.profile_beam_dir(project_root, &profile)
+        };
+
+        Ok(CompileOptions {
+            test_mode,
+            features: self.select_features(selection)?,
+            src_dir: Some(self.src_dir(project_root)),
+            out_dir: Some(out_dir),
+            debug_assertions: profile.debug_assertions,
+            erlc_flags: profile.erlc_flags(),
+            warnings: self.warning_levels()?,
+            cfg: self.cfg_options(selection)?,
+            target: self.build.target,
+            expected_cfg: Some(self.expected_cfg()?),
+            ..CompileOptions::default()
+        })
+    }
+
+    /// The cfg options set in `[build.cfg]` and by the `--cfg` flags in
+    /// `selection`.
+    pub fn cfg_options(&self, selection: &FeatureSelection) -> ConfigResult<HashSet<(String, Option<String>)>> {
+        let mut cfg = HashSet::new();
+        for (name, value) in &self.build.cfg {
+            check_cfg_name(name).map_err(|e| ConfigError::new(format!("dream.toml [build.cfg]: {}", e)))?;
+            match value {
+                CfgValue::Flag(true) => {
+                    cfg.insert((name.clone(), None));
+                }
+                CfgValue::Flag(false) => {}
+                CfgValue::Value(value) => {
+                    cfg.insert((name.clone(), Some(value.clone())));
+                }
+                CfgValue::Values(values) => {
+                    cfg.extend(values.iter().map(|value| (name.clone(), Some(value.clone()))));
+                }
+            }
+        }
+        for spec in &selection.cfg {
+            cfg.insert(parse_cfg(spec)?);
+        }
+        Ok(cfg)
+    }
+
+    /// The cfg names and features dream.toml declares.
+    pub fn expected_cfg(&self) -> ConfigResult<ExpectedCfg> {
+        for name in &self.build.check_cfg {
+            check_cfg_name(name).map_err(|e| ConfigError::new(format!("dream.toml [build] check-cfg: {}", e)))?;
+        }
+        Ok(ExpectedCfg {
+            names: self.build.cfg.keys().chain(&self.build.check_cfg).cloned().collect(),
+            features: self.features.keys().filter(|name| *name != "default").cloned().collect(),
+        })
+    }
+
+    /// The warning levels set in `[warnings]`.
+    pub fn warning_levels(&self) -> ConfigResult<WarningLevels> {
+        let mut configured: Vec<_> = self.warnings.iter().collect();
+        configured.sort();
+        let mut levels = WarningLevels::new();
+        for (name, level) in configured {
+            let result = match LintLevel::from_name(level) {
+                Some(level) => levels.set(name, level),
+                None => Err(format!(
+                    "invalid level `{}` for `{}` (expected allow, warn or deny)",
+                    level, name
+                )),
+            };
+            result.map_err(|e| ConfigError::new(format!("dream.toml [warnings]: {}", e)))?;
+        }
+        Ok(levels)
+    }
+}
+
+// =============================================================================
+// Workspaces
+// =============================================================================
+
+/// The `[workspace]` section of the dream.toml at the root of a repository
+/// holding several packages.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// Member directories relative to the workspace root. An entry ending
+    /// in `/*` takes every subdirectory that has a dream.toml.
+    #[serde(default)]
+    pub members: Vec<String>,
+    /// Directories to leave out of `members`
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// Just enough of a dream.toml to tell whether it declares a workspace.
+#[derive(Deserialize)]
+struct WorkspaceManifest {
+    workspace: Option<WorkspaceConfig>,
+    package: Option<toml::Value>,
+}
+
+/// One package of a workspace.
+#[derive(Debug, Clone)]
+pub struct WorkspaceMember {
+    /// Directory holding the member's dream.toml
+    pub root: PathBuf,
+    pub config: ProjectConfig,
+}
+
+impl WorkspaceMember {
+    pub fn name(&self) -> &str {
+        &self.config.package.name
+    }
+}
+
+/// A loaded workspace. Members build into one `_build` and share `deps`
+/// and dream.lock, all at the workspace root.
+#[derive(Debug, Clone)]
+pub struct Workspace {
+    pub root: PathBuf,
+    /// Members sorted by name
+    pub members: Vec<WorkspaceMember>,
+}
+
+impl Workspace {
+    /// Load the workspace declared by the dream.toml in `root`, or `None`
+    /// if it has no `[workspace]` section. A root that is also a package
+    /// is a member itself.
+    pub fn load(root: &Path) -> ConfigResult<Option<Self>> {
+        let manifest: WorkspaceManifest = read_manifest(&root.join("dream.toml"))?;
+        let Some(workspace) = manifest.workspace else {
+            return Ok(None);
+        };
+        let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+
+        let mut dirs = Vec::new();
+        if manifest.package.is_some() {
+            dirs.push(root.clone());
+        }
+        for pattern in &workspace.members {
+            if let Some(parent) = pattern.strip_suffix("/*") {
+                let parent = root.join(parent);
+                let entries = fs::read_dir(&parent).map_err(|e| {
+                    ConfigError::new(format!("Failed to read {}: {}", parent.display(), e))
+                })?;
+                let mut found: Vec<PathBuf> = entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|dir| dir.join("dream.toml").is_file())
+                    .collect();
+                found.sort();
+                dirs.extend(found);
+            } else {
+                let dir = root.join(pattern);
+                if !dir.join("dream.toml").is_file() {
+                    return Err(ConfigError::new(format!(
+                        "workspace member `{}` has no dream.toml",
+                        pattern
+                    )));
+                }
+                dirs.push(dir);
+            }
+        }
+        let excluded: Vec<PathBuf> = workspace.exclude.iter().map(|dir| root.join(dir)).collect();
+        dirs.retain(|dir| !excluded.contains(dir));
+
+        let mut members: Vec<WorkspaceMember> = Vec::new();
+        for dir in dirs {
+            if members.iter().any(|m| m.root == dir) {
+                continue;
+            }
+            let mut config = ProjectConfig::load(&dir.join("dream.toml"))?;
+            config.workspace_root = Some(root.clone());
+            if let Some(other) = members.iter().find(|m| m.name() == config.package.name) {
+                return Err(ConfigError::new(format!(
+                    "workspace members {} and {} are both named `{}`",
+                    other.root.display(),
+                    dir.display(),
+                    config.package.name
+                )));
+            }
+            members.push(WorkspaceMember { root: dir, config });
+        }
+        members.sort_by(|a, b| a.name().cmp(b.name()));
+
+        // Point `{ workspace = true }` dependencies at the member's directory
+        let roots: HashMap<String, PathBuf> = members
+            .iter()
+            .map(|m| (m.name().to_string(), m.root.clone()))
+            .collect();
+        for member in &mut members {
+            for (name, dep) in member.config.dependencies.iter_mut() {
+                let Dependency::Detailed(spec) = dep else {
+                    continue;
+                };
+                if !spec.workspace {
+                    continue;
+                }
+                let path = roots.get(name).ok_or_else(|| {
+                    ConfigError::new(format!(
+                        "{} depends on `{}` with `workspace = true`, but the workspace has no member `{}`",
+                        member.config.package.name, name, name
+                    ))
+                })?;
+                spec.path = Some(path.to_string_lossy().into_owned());
+            }
+        }
+
+        Ok(Some(Self { root, members }))
+    }
+
+    /// Find the workspace the package in `project_root` belongs to: the
+    /// nearest dream.toml at or above it with a `[workspace]` section, if
+    /// that workspace lists the package as a member.
+    pub fn find(project_root: &Path) -> ConfigResult<Option<Self>> {
+        let start = project_root
+            .canonicalize()
+            .unwrap_or_else(|_| project_root.to_path_buf());
+        for dir in start.ancestors() {
+            if !dir.join("dream.toml").is_file() {
+                continue;
+            }
+            if let Some(workspace) = Self::load(dir)? {
+                return Ok(workspace.member_at(&start).is_some().then_some(workspace));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether the dream.toml at `path` declares a workspace but no package.
+    pub fn is_virtual(path: &Path) -> ConfigResult<bool> {
+        let manifest: WorkspaceManifest = read_manifest(path)?;
+        Ok(manifest.workspace.is_some() && manifest.package.is_none())
+    }
+
+    /// Look up a member by package name.
+    pub fn member(&self, name: &str) -> ConfigResult<&WorkspaceMember> {
+        self.members.iter().find(|m| m.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = self.members.iter().map(WorkspaceMember::name).collect();
+            ConfigError::new(format!(
+                "workspace has no member `{}` (members: {})",
+                name,
+                names.join(", ")
+            ))
+        })
+    }
+
+    /// The member whose dream.toml is in `dir`.
+    pub fn member_at(&self, dir: &Path) -> Option<&WorkspaceMember> {
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        self.members.iter().find(|m| m.root == dir)
+    }
+
+    /// Names of the other members `member` depends on, sorted.
+    pub fn member_dependencies(&self, member: &WorkspaceMember) -> Vec<&str> {
+        let mut names: Vec<&str> = self
+            .members
+            .iter()
+            .filter(|other| {
+                member
+                    .config
+                    .dependencies
+                    .get(other.name())
+                    .is_some_and(|dep| Self::points_at(member, dep, other))
+            })
+            .map(WorkspaceMember::name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Whether `dep`, declared by `member`, is the member `other`.
+    fn points_at(member: &WorkspaceMember, dep: &Dependency, other: &WorkspaceMember) -> bool {
+        dep.is_workspace()
+            || dep.path().is_some_and(|path| {
+                let dir = member.root.join(path);
+                dir.canonicalize().unwrap_or(dir) == other.root
+            })
+    }
+
+    /// The members named in `names` and every member they depend on, each
+    /// after its dependencies. All members if `names` is empty.
+    pub fn build_order(&self, names: &[&str]) -> ConfigResult<Vec<&WorkspaceMember>> {
+        let selected: Vec<&WorkspaceMember> = if names.is_empty() {
+            self.members.iter().collect()
+        } else {
+            names.iter().map(|name| self.member(name)).collect::<ConfigResult<_>>()?
+        };
+
+        let mut order = Vec::new();
+        let mut visiting = Vec::new();
+        for member in selected {
+            self.visit(member, &mut visiting, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn visit<'a>(
+        &'a self,
+        member: &'a WorkspaceMember,
+        visiting: &mut Vec<&'a str>,
+        order: &mut Vec<&'a WorkspaceMember>,
+    ) -> ConfigResult<()> {
+        if order.iter().any(|m| m.name() == member.name()) {
+            return Ok(());
+        }
+        if let Some(start) = visiting.iter().position(|name| *name == member.name()) {
+            let mut cycle = visiting[start..].to_vec();
+            cycle.push(member.name());
+            return Err(ConfigError::new(format!(
+                "workspace members depend on each other: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        visiting.push(member.name());
+        for name in self.member_dependencies(member) {
+            self.visit(self.member(name)?, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(member);
+        Ok(())
+    }
+
+    /// Every member's dependencies other than the members themselves, to
+    /// be fetched once into the workspace's `deps` and locked in its
+    /// dream.lock. Relative paths are rewritten against the workspace root.
+    pub fn dependencies(&self) -> ConfigResult<HashMap<String, Dependency>> {
+        let mut merged: HashMap<String, (Dependency, &str)> = HashMap::new();
+        for member in &self.members {
+            for (name, dep) in &member.config.dependencies {
+                let is_member = self
+                    .members
+                    .iter()
+                    .any(|other| other.name() == name && Self::points_at(member, dep, other));
+                if is_member {
+                    continue;
+                }
+
+                let dep = self.rebase(member, dep);
+                match merged.get(name) {
+                    Some((existing, declared_by)) if *existing != dep => {
+                        return Err(ConfigError::new(format!(
+                            "dependency `{}` is declared differently by workspace members {} and {}",
+                            name,
+                            declared_by,
+                            member.name()
+                        )));
+                    }
+                    Some(_) => {}
+                    None => {
+                        merged.insert(name.clone(), (dep, member.name()));
+                    }
+                }
+            }
+        }
+        Ok(merged.into_iter().map(|(name, (dep, _))| (name, dep)).collect())
+    }
+
+    /// `dep` as declared by `member`, with a relative path made relative to
+    /// the workspace root instead.
+    fn rebase(&self, member: &WorkspaceMember, dep: &Dependency) -> Dependency {
+        let mut dep = dep.clone();
+        if let Dependency::Detailed(spec) = &mut dep {
+            if let Some(path) = spec.path.as_mut().filter(|path| Path::new(path.as_str()).is_relative()) {
+                let member_dir = member.root.strip_prefix(&self.root).unwrap_or(member.root.as_path());
+                *path = member_dir.join(&*path).to_string_lossy().into_owned();
+            }
+        }
+        dep
+    }
 }
 
 // =============================================================================
 // Compile Options
 // =============================================================================
 
+/// Features and cfg options asked for on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeatureSelection {
+    /// `--features a,b`: enabled on top of the defaults
+    pub features: Vec<String>,
+    /// `--no-default-features`: leave out `[features] default`
+    pub no_default_features: bool,
+    /// `--all-features`: enable every declared feature
+    pub all_features: bool,
+    /// `--cfg name` or `--cfg key="value"`, as given
+    pub cfg: Vec<String>,
+}
+
+impl FeatureSelection {
+    /// The default features plus `features`.
+    pub fn new<S: Into<String>>(features: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            features: features.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+/// What a project expects `#[cfg]` to test besides the names the compiler
+/// sets, for warning about conditions that are most likely typos.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpectedCfg {
+    /// Names from `[build.cfg]` and `check-cfg`, with any value
+    pub names: HashSet<String>,
+    /// Features declared in `[features]`
+    pub features: HashSet<String>,
+}
+
 /// Options for conditional compilation.
 /// Used to evaluate `#[cfg(...)]` attributes during code generation.
 #[derive(Debug, Clone, Default)]
@@ -266,6 +1151,39 @@ pub struct CompileOptions {
     pub test_mode: bool,
     /// Set of enabled features for `#[cfg(feature = "...")]`.
     pub features: HashSet<String>,
+    /// Directory holding the sources, when compiling a project.
+    pub src_dir: Option<PathBuf>,
+    /// Directory compiled .beam files are written to, when compiling a project.
+    pub out_dir: Option<PathBuf>,
+    /// Instrument functions and statements to count how often their lines
+    /// run, for `dream test --coverage`.
+    pub coverage: bool,
+    /// Give functions and statements a probe that can stop at a breakpoint
+    /// and show the variables in scope, for `dream debug`.
+    pub debug: bool,
+    /// Whether `cfg(debug_assertions)` holds, from the build profile.
+    pub debug_assertions: bool,
+    /// Extra erlc flags from the build profile.
+    pub erlc_flags: Vec<String>,
+    /// Stages to write to the output directory, from `--emit`. Empty means
+    /// the default: only .beam files.
+    pub emit: Vec<EmitKind>,
+    /// Level of each warning category, from dream.toml's `[warnings]` and
+    /// the `-A`/`-W`/`-D` flags.
+    pub warnings: WarningLevels,
+    /// Cfg options from `--cfg` and `[build.cfg]`: a name alone for
+    /// `cfg(name)`, or with a value for `cfg(name = "value")`.
+    pub cfg: HashSet<(String, Option<String>)>,
+    /// Runtime being compiled for, for `cfg(target = "...")`.
+    pub target: RuntimeTarget,
+    /// Major release of the Erlang/OTP installation, such as 26, for
+    /// `cfg(otp_release >= "26")`. When it isn't known, no `otp_release`
+    /// predicate holds.
+    pub otp_release: Option<u32>,
+    /// The project's cfg names and features. Without one, names set in
+    /// `cfg` are the only ones expected besides the built-in ones, and any
+    /// feature is.
+    pub expected_cfg: Option<ExpectedCfg>,
 }
 
 impl CompileOptions {
@@ -278,15 +1196,15 @@ impl CompileOptions {
     pub fn for_testing() -> Self {
         Self {
             test_mode: true,
-            features: HashSet::new(),
+            ..Self::default()
         }
     }
 
     /// Create compile options with specific features.
     pub fn with_features(features: HashSet<String>) -> Self {
         Self {
-            test_mode: false,
             features,
+            ..Self::default()
         }
     }
 
@@ -295,6 +1213,7 @@ impl CompileOptions {
         Self {
             test_mode: true,
             features,
+            ..Self::default()
         }
     }
 
@@ -302,6 +1221,59 @@ impl CompileOptions {
     pub fn has_feature(&self, feature: &str) -> bool {
         self.features.contains(feature)
     }
+
+    /// Check if the cfg option `name`, or `name = "value"`, is set.
+    pub fn has_cfg(&self, name: &str, value: Option<&str>) -> bool {
+        self.cfg.contains(&(name.to_string(), value.map(str::to_string)))
+    }
+
+    /// Whether the output of stage `kind` should be written.
+    pub fn emits(&self, kind: EmitKind) -> bool {
+        if self.emit.is_empty() {
+            kind == EmitKind::Beam
+        } else {
+            self.emit.contains(&kind)
+        }
+    }
+}
+
+// =============================================================================
+// Node Options
+// =============================================================================
+
+/// Distribution settings for the BEAM node started by `dream run`.
+#[derive(Debug, Clone, Default)]
+pub struct NodeOptions {
+    /// Short node name (`-sname`), e.g. `app` becomes `app@host`.
+    pub sname: Option<String>,
+    /// Fully qualified node name (`-name`), e.g. `app@host.example.com`.
+    pub name: Option<String>,
+    /// Magic cookie shared by nodes allowed to connect (`-setcookie`).
+    pub cookie: Option<String>,
+}
+
+impl NodeOptions {
+    /// Whether the node should start with distribution enabled.
+    pub fn is_distributed(&self) -> bool {
+        self.sname.is_some() || self.name.is_some()
+    }
+
+    /// Command-line arguments for `erl`.
+    pub fn erl_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(sname) = &self.sname {
+            args.push("-sname".to_string());
+            args.push(sname.clone());
+        } else if let Some(name) = &self.name {
+            args.push("-name".to_string());
+            args.push(name.clone());
+        }
+        if let Some(cookie) = &self.cookie {
+            args.push("-setcookie".to_string());
+            args.push(cookie.clone());
+        }
+        args
+    }
 }
 
 /// Generate a default dream.toml content for a new project.
@@ -320,7 +1292,7 @@ pub fn generate_main_dream(name: &str) -> String {
     format!(
         r#"// {} - A Dream project
 
-pub fn main() {{
+pub fn main() -> Atom {{
     :ok
 }}
 "#,
@@ -328,9 +1300,136 @@ pub fn main() {{
     )
 }
 
+/// Generate a default lib.dream content for a new library project.
+pub fn generate_lib_dream(name: &str) -> String {
+    format!(
+        r#"// {} - A Dream library
+
+/// Say hello.
+pub fn hello() -> Atom {{
+    :world
+}}
+"#,
+        name
+    )
+}
+
+/// Generate a starter test file for a new project.
+pub fn generate_tests_dream(name: &str, lib: bool) -> String {
+    let (import, call, expected) = if lib {
+        ("use crate::hello;", "hello()", ":world")
+    } else {
+        ("use crate::main::main;", "main()", ":ok")
+    };
+    format!(
+        r#"// Tests for {name}
+// Only compiled when running `dream test`
+
+{import}
+
+#[test]
+pub fn test_{fn_name}() -> Atom {{
+    match {call} {{
+        {expected} => :ok,
+        other => :erlang::error((:unexpected, other)),
+    }}
+}}
+"#,
+        name = name,
+        import = import,
+        fn_name = if lib { "hello" } else { "main" },
+        call = call,
+        expected = expected,
+    )
+}
+
+/// Generate a .gitignore for a new project.
+pub fn generate_gitignore() -> String {
+    "/_build/\n/deps/\n".to_string()
+}
+
+/// Files created by `dream new`, as (path relative to the project root, contents).
+pub fn project_template(name: &str, lib: bool) -> Vec<(&'static str, String)> {
+    let entry = if lib {
+        ("src/lib.dream", generate_lib_dream(name))
+    } else {
+        ("src/main.dream", generate_main_dream(name))
+    };
+    vec![
+        ("dream.toml", generate_dream_toml(name)),
+        entry,
+        ("src/tests.dream", generate_tests_dream(name, lib)),
+        (".gitignore", generate_gitignore()),
+    ]
+}
+
+/// Files created by `dream nif new`, as (path relative to the project root,
+/// contents): a Rustler crate in `native/<name>` and the module `src/<name>.dream`
+/// declaring its functions, which Rustler registers for that module's BEAM name.
+pub fn nif_template(package: &str, src: &str, name: &str) -> Vec<(String, String)> {
+    let cargo_toml = format!(
+        r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+crate-type = ["cdylib"]
+
+[dependencies]
+rustler = "0.34"
+"#,
+        name = name
+    );
+    let lib_rs = format!(
+        r#"#[rustler::nif]
+fn add(a: i64, b: i64) -> i64 {{
+    a + b
+}}
+
+rustler::init!("dream::{package}::{name}");
+"#,
+        package = package,
+        name = name
+    );
+    let module = format!(
+        r#"// Native functions of {name}, implemented in native/{name}
+
+extern "nif" {{
+    fn add(a: int, b: int) -> int;
+}}
+"#,
+        name = name
+    );
+    vec![
+        (format!("native/{}/Cargo.toml", name), cargo_toml),
+        (format!("native/{}/src/lib.rs", name), lib_rs),
+        (format!("native/{}/.gitignore", name), "/target/\n".to_string()),
+        (format!("{}/{}.dream", src, name), module),
+    ]
+}
+
+/// Check that a name can be used as a package name.
+/// Package names become module path segments, so they must be identifiers:
+/// a lowercase letter followed by lowercase letters, digits or underscores.
+pub fn validate_package_name(name: &str) -> ConfigResult<()> {
+    let mut chars = name.chars();
+    let valid = matches!(chars.next(), Some('a'..='z'))
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ConfigError::new(format!(
+            "invalid package name '{}': use lowercase letters, digits and underscores, starting with a letter",
+            name
+        )))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler::WarningCategory;
 
     #[test]
     fn test_parse_dream_toml() {
@@ -364,6 +1463,89 @@ src = "lib"
         assert!(content.contains("version = \"0.1.0\""));
     }
 
+    #[test]
+    fn test_project_template_binary() {
+        let files = project_template("my_app", false);
+        let paths: Vec<&str> = files.iter().map(|(p, _)| *p).collect();
+        assert_eq!(
+            paths,
+            vec!["dream.toml", "src/main.dream", "src/tests.dream", ".gitignore"]
+        );
+        let tests = &files[2].1;
+        assert!(tests.contains("use crate::main::main;"));
+        assert!(tests.contains("#[test]"));
+        assert!(files[3].1.contains("/_build/"));
+    }
+
+    #[test]
+    fn test_project_template_library() {
+        let files = project_template("my_lib", true);
+        assert_eq!(files[1].0, "src/lib.dream");
+        assert!(files[1].1.contains("pub fn hello()"));
+        assert!(files[2].1.contains("use crate::hello;"));
+    }
+
+    #[test]
+    fn test_nif_template() {
+        let files = nif_template("my_app", "src", "fast_math");
+        let paths: Vec<&str> = files.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "native/fast_math/Cargo.toml",
+                "native/fast_math/src/lib.rs",
+                "native/fast_math/.gitignore",
+                "src/fast_math.dream"
+            ]
+        );
+        assert!(files[0].1.contains("crate-type = [\"cdylib\"]"));
+        assert!(files[1].1.contains("rustler::init!(\"dream::my_app::fast_math\");"));
+        assert!(files[3].1.contains("extern \"nif\" {\n    fn add(a: int, b: int) -> int;\n}"));
+    }
+
+    #[test]
+    fn test_validate_package_name() {
+        assert!(validate_package_name("my_app").is_ok());
+        assert!(validate_package_name("app2").is_ok());
+        assert!(validate_package_name("MyApp").is_err());
+        assert!(validate_package_name("my-app").is_err());
+        assert!(validate_package_name("2app").is_err());
+        assert!(validate_package_name("").is_err());
+    }
+
+    #[test]
+    fn test_project_template_checks() {
+        use crate::compiler::{check_modules_with_metadata, ModuleLoader};
+
+        for lib in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            for (path, contents) in project_template("my_app", lib) {
+                let path = dir.path().join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+
+            let src = dir.path().join("src");
+            let mut loader = ModuleLoader::with_package("my_app".to_string(), src.clone());
+            loader.load_all_in_dir(&src).unwrap();
+            let result = check_modules_with_metadata(&loader.into_modules());
+            for (name, checked) in result.modules {
+                assert!(checked.is_ok(), "{} (lib: {}): {:?}", name, lib, checked.err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry_module() {
+        let dir = tempfile::tempdir().unwrap();
+        let config: ProjectConfig = toml::from_str(&generate_dream_toml("my_app")).unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        assert_eq!(config.entry_module(dir.path()), "dream::my_app::my_app");
+
+        fs::write(dir.path().join("src/main.dream"), generate_main_dream("my_app")).unwrap();
+        assert_eq!(config.entry_module(dir.path()), "dream::my_app::main");
+    }
+
     #[test]
     fn test_parse_application_config() {
         let content = r#"
@@ -399,6 +1581,81 @@ env = { port = 3000 }
         assert_eq!(config.application_module(), Some("my_app".to_string()));
     }
 
+    #[test]
+    fn test_application_beam_module_is_qualified() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+
+[application]
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        assert_eq!(
+            config.application_beam_module(),
+            Some("dream::my_app::my_app".to_string())
+        );
+        assert_eq!(config.qualify_module("server"), "dream::my_app::server");
+        assert_eq!(config.qualify_module("my_app::server"), "dream::my_app::server");
+        assert_eq!(config.qualify_module("dream::other"), "dream::other");
+    }
+
+    #[test]
+    fn test_required_applications_order() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+
+[application]
+extra_applications = ["crypto", "stdlib", "ssl"]
+
+[dependencies]
+ranch = "2.1"
+cowboy = "2.10"
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        assert_eq!(
+            config.required_applications(),
+            vec!["kernel", "stdlib", "crypto", "ssl", "cowboy", "ranch"]
+        );
+    }
+
+    #[test]
+    fn test_app_resource() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "1.2.0"
+
+[application]
+mod = "server"
+
+[dependencies]
+jason = "1.4"
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        let app = config.app_resource(&["my_app::server".to_string()]);
+        assert!(app.starts_with("{application, my_app, ["));
+        assert!(app.contains("{vsn, \"1.2.0\"}"));
+        assert!(app.contains("{mod, {'dream::my_app::server', []}}"));
+        assert!(app.contains("{modules, ['dream::my_app::server']}"));
+        assert!(app.contains("{applications, [kernel, stdlib, jason]}"));
+    }
+
+    #[test]
+    fn test_app_resource_for_library_has_no_mod() {
+        let content = r#"
+[package]
+name = "my_lib"
+version = "0.1.0"
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        let app = config.app_resource(&["dream::my_lib".to_string()]);
+        assert!(!app.contains("{mod,"));
+        assert!(app.contains("{applications, [kernel, stdlib]}"));
+    }
+
     #[test]
     fn test_no_application_section() {
         let content = r#"
@@ -505,4 +1762,403 @@ c = ["b"]
         assert!(opts.has_feature("json"));
         assert!(!opts.has_feature("async"));
     }
+
+    #[test]
+    fn test_bin_modules() {
+        let dir = tempfile::tempdir().unwrap();
+        let config: ProjectConfig = toml::from_str(&generate_dream_toml("my_app")).unwrap();
+        assert!(config.bin_names(dir.path()).is_empty());
+
+        let bin_dir = dir.path().join("src").join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::write(bin_dir.join("tool.dream"), "pub fn main() {}").unwrap();
+        fs::write(bin_dir.join("admin.dream"), "pub fn main() {}").unwrap();
+        fs::write(bin_dir.join("notes.txt"), "").unwrap();
+
+        assert_eq!(config.bin_names(dir.path()), vec!["admin", "tool"]);
+        assert_eq!(
+            config.bin_module(dir.path(), "tool").as_deref(),
+            Some("dream::my_app::bin::tool")
+        );
+        assert_eq!(config.bin_module(dir.path(), "missing"), None);
+    }
+
+    #[test]
+    fn test_compile_options_from_manifest() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+description = "An example"
+src = "lib"
+
+[features]
+default = ["json"]
+json = []
+async = []
+full = ["json", "async"]
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        assert_eq!(config.package.description.as_deref(), Some("An example"));
+        assert_eq!(config.default_features(), vec!["json"]);
+
+        let root = Path::new("/proj");
+        let opts = config.compile_options(root, &FeatureSelection::default(), "dev", false).unwrap();
+        assert!(!opts.test_mode);
+        assert!(opts.has_feature("json"));
+        assert!(!opts.has_feature("async"));
+        assert_eq!(opts.src_dir, Some(PathBuf::from("/proj/lib")));
+        assert_eq!(
+            opts.out_dir,
+            Some(PathBuf::from("/proj/_build/dev/lib/my_app/ebin"))
+        );
+
+        let opts = config.compile_options(root, &FeatureSelection::new(["async"]), "dev", true).unwrap();
+        assert!(opts.test_mode);
+        assert!(opts.has_feature("json"));
+        assert!(opts.has_feature("async"));
+        assert_eq!(
+            opts.out_dir,
+            Some(PathBuf::from("/proj/_build/test/lib/my_app/ebin"))
+        );
+    }
+
+    #[test]
+    fn test_compile_options_without_default_features() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+
+[features]
+json = []
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        assert!(config.default_features().is_empty());
+        let opts = config
+            .compile_options(Path::new("/proj"), &FeatureSelection::default(), "dev", false)
+            .unwrap();
+        assert!(opts.features.is_empty());
+    }
+
+    #[test]
+    fn test_profiles() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+
+[profile.release]
+debug = true
+out-dir = "dist"
+
+[profile.staging]
+opt-level = 1
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        let root = Path::new("/proj");
+        let none = FeatureSelection::default();
+
+        let dev = config.compile_options(root, &none, "dev", false).unwrap();
+        assert!(dev.debug_assertions);
+        assert_eq!(dev.erlc_flags, vec!["+debug_info"]);
+        assert_eq!(dev.out_dir, Some(PathBuf::from("/proj/_build/dev/lib/my_app/ebin")));
+
+        let release = config.compile_options(root, &none, "release", false).unwrap();
+        assert!(!release.debug_assertions);
+        assert_eq!(release.erlc_flags, vec!["+debug_info", "+inline", "+{inline_size,100}"]);
+        assert_eq!(release.out_dir, Some(PathBuf::from("/proj/dist")));
+
+        let staging = config.profile("staging").unwrap();
+        assert_eq!((staging.opt_level, staging.debug, staging.debug_assertions), (1, true, true));
+        let staging = config.compile_options(root, &none, "staging", false).unwrap();
+        assert_eq!(staging.out_dir, Some(PathBuf::from("/proj/_build/staging/lib/my_app/ebin")));
+
+        assert_eq!(
+            config.profile("bench").unwrap_err().to_string(),
+            "profile `bench` is not defined in dream.toml"
+        );
+    }
+
+    #[test]
+    fn test_warning_levels() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+
+[warnings]
+warnings = "deny"
+deprecated = "allow"
+non_exhaustive = "warn"
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        let opts = config.compile_options(Path::new("/proj"), &FeatureSelection::default(), "dev", false).unwrap();
+        assert_eq!(opts.warnings.level(WarningCategory::Unused), LintLevel::Deny);
+        assert_eq!(opts.warnings.level(WarningCategory::Deprecated), LintLevel::Allow);
+        assert_eq!(opts.warnings.level(WarningCategory::NonExhaustive), LintLevel::Deny);
+        assert_eq!(opts.warnings.level(WarningCategory::Dynamic), LintLevel::Allow);
+
+        let config: ProjectConfig = toml::from_str(&content.replace("\"warn\"", "\"never\"")).unwrap();
+        let err = config.warning_levels().unwrap_err();
+        assert_eq!(
+            err.message,
+            "dream.toml [warnings]: invalid level `never` for `non_exhaustive` (expected allow, warn or deny)"
+        );
+    }
+
+    #[test]
+    fn test_cfg_options() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+
+[build.cfg]
+tracing = true
+metrics = false
+log_level = "debug"
+backend = ["pg", "sqlite"]
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        let selection = FeatureSelection {
+            cfg: vec!["otel".to_string(), "region=\"eu\"".to_string()],
+            ..FeatureSelection::default()
+        };
+        let opts = config.compile_options(Path::new("/proj"), &selection, "dev", false).unwrap();
+        assert!(opts.has_cfg("tracing", None));
+        assert!(!opts.has_cfg("metrics", None));
+        assert!(opts.has_cfg("log_level", Some("debug")));
+        assert!(!opts.has_cfg("log_level", None));
+        assert!(opts.has_cfg("backend", Some("pg")) && opts.has_cfg("backend", Some("sqlite")));
+        assert!(opts.has_cfg("otel", None));
+        assert!(opts.has_cfg("region", Some("eu")));
+
+        assert_eq!(parse_cfg("mode=fast").unwrap(), ("mode".to_string(), Some("fast".to_string())));
+        assert!(parse_cfg("2fast").is_err());
+        let err = parse_cfg("test").unwrap_err();
+        assert_eq!(err.message, "--cfg test: `test` is set by the compiler and can't be set by hand");
+
+        let config: ProjectConfig = toml::from_str(&content.replace("tracing", "feature")).unwrap();
+        assert!(config.cfg_options(&FeatureSelection::default()).is_err());
+
+        assert_eq!(opts.target, RuntimeTarget::Beam);
+        let config: ProjectConfig = toml::from_str(&content.replace("[build.cfg]", "[build]\ntarget = \"atomvm\"\n\n[build.cfg]")).unwrap();
+        let opts = config.compile_options(Path::new("/proj"), &FeatureSelection::default(), "dev", false).unwrap();
+        assert_eq!(opts.target, RuntimeTarget::AtomVm);
+
+        let declared = "[features]\ndefault = [\"json\"]\njson = []\n\n[build]\ncheck-cfg = [\"otel\"]\n\n[build.cfg]";
+        let config: ProjectConfig = toml::from_str(&content.replace("[build.cfg]", declared)).unwrap();
+        let expected = config.expected_cfg().unwrap();
+        assert!(expected.names.contains("metrics") && expected.names.contains("otel"));
+        assert!(!expected.names.contains("region"));
+        assert_eq!(expected.features, HashSet::from(["json".to_string()]));
+        let config: ProjectConfig = toml::from_str(&content.replace("[build.cfg]", "[build]\ncheck-cfg = [\"test\"]\n\n[build.cfg]")).unwrap();
+        assert!(config.expected_cfg().is_err());
+    }
+
+    #[test]
+    fn test_select_features() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+
+[features]
+default = ["json"]
+json = []
+async = []
+full = ["json", "async"]
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        let enabled = |selection: FeatureSelection| {
+            let mut features: Vec<String> = config.select_features(&selection).unwrap().into_iter().collect();
+            features.sort();
+            features
+        };
+
+        assert_eq!(enabled(FeatureSelection::default()), vec!["json"]);
+        assert_eq!(
+            enabled(FeatureSelection {
+                no_default_features: true,
+                ..FeatureSelection::default()
+            }),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            enabled(FeatureSelection {
+                no_default_features: true,
+                ..FeatureSelection::new(["async"])
+            }),
+            vec!["async"]
+        );
+        assert_eq!(
+            enabled(FeatureSelection {
+                all_features: true,
+                ..FeatureSelection::default()
+            }),
+            vec!["async", "full", "json"]
+        );
+
+        let err = config.select_features(&FeatureSelection::new(["jsno"])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "package `my_app` has no feature `jsno`; did you mean `json`? (available: async, full, json)"
+        );
+
+        let err = config.select_features(&FeatureSelection::new(["xml"])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "package `my_app` has no feature `xml` (available: async, full, json)"
+        );
+    }
+
+    #[test]
+    fn test_select_features_rejects_undeclared_implied_feature() {
+        let content = r#"
+[package]
+name = "my_app"
+version = "0.1.0"
+
+[features]
+full = ["json"]
+"#;
+        let config: ProjectConfig = toml::from_str(content).unwrap();
+        let err = config.select_features(&FeatureSelection::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "feature `full` enables `json`, which is not declared in [features]"
+        );
+    }
+
+    fn write_package(dir: &Path, name: &str, dependencies: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            dir.join("dream.toml"),
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}",
+                name, dependencies
+            ),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_workspace_members() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        fs::write(
+            root.join("dream.toml"),
+            "[workspace]\nmembers = [\"apps/*\", \"tools/cli\"]\nexclude = [\"apps/scratch\"]\n",
+        )
+        .unwrap();
+        write_package(&root.join("apps/core"), "core", "jason = \"~> 1.4\"\n");
+        write_package(
+            &root.join("apps/web"),
+            "web",
+            "core = { workspace = true }\njason = \"~> 1.4\"\nlocal = { path = \"../../vendor/local\" }\n",
+        );
+        write_package(&root.join("apps/scratch"), "scratch", "");
+        write_package(&root.join("tools/cli"), "cli", "web = { path = \"../../apps/web\" }\n");
+        fs::create_dir_all(root.join("apps/docs")).unwrap();
+
+        assert!(Workspace::is_virtual(&root.join("dream.toml")).unwrap());
+        let workspace = Workspace::load(&root).unwrap().unwrap();
+        let names: Vec<&str> = workspace.members.iter().map(WorkspaceMember::name).collect();
+        assert_eq!(names, vec!["cli", "core", "web"]);
+
+        // Members share the workspace's build directory
+        let web = workspace.member("web").unwrap();
+        assert_eq!(web.config.workspace_root.as_deref(), Some(root.as_path()));
+        assert_eq!(web.config.build_dir(&web.root), root.join("_build"));
+        assert_eq!(
+            web.config.dependencies["core"].path(),
+            Some(root.join("apps/core").to_string_lossy().as_ref())
+        );
+
+        assert_eq!(workspace.member_dependencies(web), vec!["core"]);
+        let cli = workspace.member("cli").unwrap();
+        assert_eq!(workspace.member_dependencies(cli), vec!["web"]);
+        let order: Vec<&str> = workspace
+            .build_order(&["cli"])
+            .unwrap()
+            .into_iter()
+            .map(WorkspaceMember::name)
+            .collect();
+        assert_eq!(order, vec!["core", "web", "cli"]);
+        assert_eq!(workspace.build_order(&[]).unwrap().len(), 3);
+
+        // Other members are not fetched; relative paths move to the root
+        let deps = workspace.dependencies().unwrap();
+        let mut dep_names: Vec<&String> = deps.keys().collect();
+        dep_names.sort();
+        assert_eq!(dep_names, vec!["jason", "local"]);
+        assert_eq!(deps["local"].path(), Some("apps/web/../../vendor/local"));
+
+        let found = Workspace::find(&root.join("apps/web")).unwrap().unwrap();
+        assert_eq!(found.root, root);
+        assert!(Workspace::find(&root.join("apps/scratch")).unwrap().is_none());
+
+        assert_eq!(
+            workspace.member("api").unwrap_err().to_string(),
+            "workspace has no member `api` (members: cli, core, web)"
+        );
+    }
+
+    #[test]
+    fn test_workspace_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("dream.toml"), "[workspace]\nmembers = [\"a\", \"b\"]\n").unwrap();
+        write_package(&root.join("a"), "a", "b = { workspace = true }\n");
+        write_package(&root.join("b"), "b", "a = { workspace = true }\n");
+        let workspace = Workspace::load(root).unwrap().unwrap();
+        assert_eq!(
+            workspace.build_order(&["a"]).unwrap_err().to_string(),
+            "workspace members depend on each other: a -> b -> a"
+        );
+
+        write_package(&root.join("b"), "b", "jason = \"~> 1.4\"\nc = { workspace = true }\n");
+        let err = Workspace::load(root).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "b depends on `c` with `workspace = true`, but the workspace has no member `c`"
+        );
+
+        write_package(&root.join("a"), "a", "jason = \"~> 1.3\"\n");
+        write_package(&root.join("b"), "b", "jason = \"~> 1.4\"\n");
+        let workspace = Workspace::load(root).unwrap().unwrap();
+        assert_eq!(
+            workspace.dependencies().unwrap_err().to_string(),
+            "dependency `jason` is declared differently by workspace members a and b"
+        );
+    }
+
+    #[test]
+    fn test_node_options_default_is_not_distributed() {
+        let node = NodeOptions::default();
+        assert!(!node.is_distributed());
+        assert!(node.erl_args().is_empty());
+    }
+
+    #[test]
+    fn test_node_options_sname_and_cookie() {
+        let node = NodeOptions {
+            sname: Some("app".to_string()),
+            name: None,
+            cookie: Some("secret".to_string()),
+        };
+        assert!(node.is_distributed());
+        assert_eq!(node.erl_args(), vec!["-sname", "app", "-setcookie", "secret"]);
+    }
+
+    #[test]
+    fn test_node_options_long_name() {
+        let node = NodeOptions {
+            sname: None,
+            name: Some("app@host.example.com".to_string()),
+            cookie: None,
+        };
+        assert_eq!(node.erl_args(), vec!["-name", "app@host.example.com"]);
+    }
 }
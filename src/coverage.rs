@@ -0,0 +1,289 @@
+//! Line coverage for `dream test --coverage`.
+//!
+//! With [`CompileOptions::coverage`](crate::config::CompileOptions) set, the
+//! Core Erlang emitter bumps a counter in an ETS table each time a function
+//! or statement runs, keyed by module and source line. Once the tests have
+//! finished the runner prints every counter, and a [`CoverageReport`] turns
+//! them into a terminal summary, an LCOV file for CI dashboards and a
+//! browsable HTML page.
+
+use crate::markup::escape;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Execution counts for one instrumented module.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleCoverage {
+    /// Source file the lines refer to
+    pub source_path: Option<PathBuf>,
+    /// Times each instrumented line ran
+    pub lines: BTreeMap<usize, u64>,
+}
+
+impl ModuleCoverage {
+    /// Instrumented lines that ran at least once.
+    pub fn covered(&self) -> usize {
+        self.lines.values().filter(|&&count| count > 0).count()
+    }
+
+    /// Instrumented lines.
+    pub fn total(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Percentage of lines covered. A module with nothing to run counts
+    /// as fully covered.
+    pub fn percent(&self) -> f64 {
+        percent(self.covered(), self.total())
+    }
+}
+
+/// Coverage of every instrumented module, keyed by Dream module name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    pub modules: BTreeMap<String, ModuleCoverage>,
+}
+
+impl CoverageReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a module, so it shows up even if the runner never reports
+    /// on it.
+    pub fn add_module(&mut self, module: &str, source_path: Option<PathBuf>) {
+        self.modules
+            .entry(display_name(module).to_string())
+            .or_default()
+            .source_path = source_path;
+    }
+
+    /// Record a counter reported by the runner. `module` may be the BEAM
+    /// name (`dream::app::math`) or the Dream one (`app::math`).
+    pub fn record(&mut self, module: &str, line: usize, count: u64) {
+        *self
+            .modules
+            .entry(display_name(module).to_string())
+            .or_default()
+            .lines
+            .entry(line)
+            .or_default() += count;
+    }
+
+    pub fn covered(&self) -> usize {
+        self.modules.values().map(ModuleCoverage::covered).sum()
+    }
+
+    pub fn total(&self) -> usize {
+        self.modules.values().map(ModuleCoverage::total).sum()
+    }
+
+    pub fn percent(&self) -> f64 {
+        percent(self.covered(), self.total())
+    }
+
+    /// Per-module table for the terminal, with a total row.
+    pub fn summary(&self) -> String {
+        let width = self
+            .modules
+            .keys()
+            .map(String::len)
+            .chain(["Module".len(), "Total".len()])
+            .max()
+            .unwrap_or(0);
+
+        let mut out = String::new();
+        let _ = writeln!(out, "{:<width$}  {:>7}  {:>7}", "Module", "Lines", "Cover");
+        for (name, module) in &self.modules {
+            let _ = writeln!(
+                out,
+                "{:<width$}  {:>7}  {:>6.1}%",
+                name,
+                format!("{}/{}", module.covered(), module.total()),
+                module.percent()
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{:<width$}  {:>7}  {:>6.1}%",
+            "Total",
+            format!("{}/{}", self.covered(), self.total()),
+            self.percent()
+        );
+        out
+    }
+
+    /// The report in LCOV tracefile format. Source paths are made relative
+    /// to `root` where possible.
+    pub fn to_lcov(&self, root: &Path) -> String {
+        let mut out = String::new();
+        for module in self.modules.values() {
+            let Some(path) = &module.source_path else {
+                continue;
+            };
+            let path = path.strip_prefix(root).unwrap_or(path);
+            let _ = writeln!(out, "TN:");
+            let _ = writeln!(out, "SF:{}", path.display());
+            for (line, count) in &module.lines {
+                let _ = writeln!(out, "DA:{},{}", line, count);
+            }
+            let _ = writeln!(out, "LF:{}", module.total());
+            let _ = writeln!(out, "LH:{}", module.covered());
+            let _ = writeln!(out, "end_of_record");
+        }
+        out
+    }
+
+    /// A standalone HTML page: the summary table, then each module's source
+    /// with covered lines in green and missed lines in red.
+    pub fn to_html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Dream coverage</title>\n<style>\n\
+             body { font-family: sans-serif; margin: 2em; }\n\
+             table.summary td, table.summary th { padding: 0.2em 1em; text-align: left; }\n\
+             .source td { font-family: monospace; white-space: pre; padding: 0 0.5em; }\n\
+             .source td.num { color: #888; text-align: right; }\n\
+             .hit { background: #dfd; }\n\
+             .miss { background: #fdd; }\n\
+             </style>\n</head>\n<body>\n<h1>Dream coverage</h1>\n",
+        );
+
+        out.push_str("<table class=\"summary\">\n<tr><th>Module</th><th>Lines</th><th>Cover</th></tr>\n");
+        for (name, module) in &self.modules {
+            let _ = writeln!(
+                out,
+                "<tr><td><a href=\"#{id}\">{name}</a></td><td>{}/{}</td><td>{:.1}%</td></tr>",
+                module.covered(),
+                module.total(),
+                module.percent(),
+                id = anchor(name),
+                name = escape(name),
+            );
+        }
+        let _ = writeln!(
+            out,
+            "<tr><th>Total</th><th>{}/{}</th><th>{:.1}%</th></tr>\n</table>",
+            self.covered(),
+            self.total(),
+            self.percent()
+        );
+
+        for (name, module) in &self.modules {
+            let _ = writeln!(out, "<h2 id=\"{}\">{}</h2>", anchor(name), escape(name));
+            let source = module
+                .source_path
+                .as_ref()
+                .and_then(|path| fs::read_to_string(path).ok());
+            let Some(source) = source else {
+                out.push_str("<p>Source not available.</p>\n");
+                continue;
+            };
+            out.push_str("<table class=\"source\">\n");
+            for (i, text) in source.lines().enumerate() {
+                let line = i + 1;
+                let (class, count) = match module.lines.get(&line) {
+                    Some(0) => (" class=\"miss\"", "0".to_string()),
+                    Some(count) => (" class=\"hit\"", count.to_string()),
+                    None => ("", String::new()),
+                };
+                let _ = writeln!(
+                    out,
+                    "<tr{}><td class=\"num\">{}</td><td class=\"num\">{}</td><td>{}</td></tr>",
+                    class,
+                    line,
+                    count,
+                    escape(text)
+                );
+            }
+            out.push_str("</table>\n");
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Write `lcov.info` and `html/index.html` under `dir`.
+    pub fn write(&self, dir: &Path, root: &Path) -> io::Result<()> {
+        let html_dir = dir.join("html");
+        fs::create_dir_all(&html_dir)?;
+        fs::write(dir.join("lcov.info"), self.to_lcov(root))?;
+        fs::write(html_dir.join("index.html"), self.to_html())
+    }
+}
+
+fn display_name(module: &str) -> &str {
+    module.strip_prefix("dream::").unwrap_or(module)
+}
+
+fn percent(covered: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        covered as f64 * 100.0 / total as f64
+    }
+}
+
+fn anchor(name: &str) -> String {
+    name.replace("::", "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> CoverageReport {
+        let mut report = CoverageReport::new();
+        report.add_module("dream::app::math", Some(PathBuf::from("/proj/src/math.dream")));
+        report.record("dream::app::math", 2, 3);
+        report.record("dream::app::math", 3, 0);
+        report.record("dream::app::math", 7, 1);
+        report.record("app::util", 1, 0);
+        report
+    }
+
+    #[test]
+    fn test_counts() {
+        let report = report();
+        let math = &report.modules["app::math"];
+        assert_eq!((math.covered(), math.total()), (2, 3));
+        assert_eq!(report.modules["app::util"].percent(), 0.0);
+        assert_eq!((report.covered(), report.total()), (2, 4));
+        assert_eq!(report.percent(), 50.0);
+        assert_eq!(ModuleCoverage::default().percent(), 100.0);
+
+        let summary = report.summary();
+        assert!(summary.contains("app::math      2/3    66.7%"), "{}", summary);
+        assert!(summary.lines().last().unwrap().starts_with("Total"));
+    }
+
+    #[test]
+    fn test_lcov() {
+        let lcov = report().to_lcov(Path::new("/proj"));
+        assert_eq!(
+            lcov,
+            "TN:\nSF:src/math.dream\nDA:2,3\nDA:3,0\nDA:7,1\nLF:3\nLH:2\nend_of_record\n"
+        );
+    }
+
+    #[test]
+    fn test_html_escapes_source() {
+        let dir = std::env::temp_dir().join(format!("dream_cover_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("math.dream");
+        fs::write(&path, "fn lt(a: int, b: int) -> bool {\n    a < b\n}\n").unwrap();
+
+        let mut report = CoverageReport::new();
+        report.add_module("dream::app::math", Some(path));
+        report.record("dream::app::math", 2, 0);
+        let html = report.to_html();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(html.contains("<a href=\"#app-math\">app::math</a>"));
+        assert!(html.contains("<tr class=\"miss\"><td class=\"num\">2</td><td class=\"num\">0</td><td>    a &lt; b</td></tr>"));
+        assert!(html.contains("<tr><td class=\"num\">3</td><td class=\"num\"></td><td>}</td></tr>"));
+    }
+}
@@ -0,0 +1,884 @@
+//! The debugger behind `dream debug`.
+//!
+//! OTP's `int` interpreter steps through modules by re-running their
+//! abstract code, which modules compiled from Core Erlang don't carry.
+//! Instead, a debug build (`CompileOptions::debug`) calls a probe before
+//! every statement with the line and the Dream bindings in scope. The probe
+//! checks an ETS table of breakpoints and, when one is hit or the process is
+//! stepping, reports to a controller process and waits to be resumed.
+//!
+//! The controller runs in the same node as the program, started by
+//! [`harness_expr`]. It reads [`Command`]s as Erlang terms on stdin and
+//! reports [`Event`]s as marker lines on stdout, between the program's own
+//! output. [`Session`] drives it from Rust, and [`run_cli`] and [`serve_dap`]
+//! put a command prompt or a Debug Adapter Protocol server in front of it.
+
+use crate::erl::quote_atom;
+use crate::lsp::{read_message, write_message};
+use crate::testing::decode;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, ChildStdin, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// Prefix of the controller's protocol lines.
+const MARKER: &str = "##dream-debug##";
+
+/// Erlang expression that runs the debug controller, which starts the
+/// program on `run` and halts with its exit status. `program` is an
+/// expression evaluating to that status, e.g. a call to `main`.
+pub fn harness_expr(program: &str) -> String {
+    format!(
+        "ets:new(dream_debug, [named_table, public, set]), \
+         register(dream_debugger, self()), \
+         Debugger = self(), \
+         Hex = fun(Chars) -> binary:encode_hex(unicode:characters_to_binary(Chars)) end, \
+         Show = fun(Value) -> \
+             try 'dream::display':display_any(Value) \
+             catch _:_ -> io_lib:format(\"~tp\", [Value]) \
+             end \
+         end, \
+         Emit = fun(Format, Args) -> io:format(\"{marker} \" ++ Format ++ \"~n\", Args) end, \
+         spawn_link(fun ReadCommands() -> \
+             case io:read('') of \
+                 {{ok, Command}} -> Debugger ! {{command, Command}}, ReadCommands(); \
+                 {{error, _}} -> Debugger ! {{command, unreadable}}, ReadCommands(); \
+                 _ -> Debugger ! {{command, quit}} \
+             end \
+         end), \
+         Break = fun(Mod, Line) -> \
+             code:ensure_loaded(Mod), \
+             Probed = case erlang:function_exported(Mod, '__dream_debug_lines__', 0) of \
+                 true -> Mod:'__dream_debug_lines__'(); \
+                 false -> [] \
+             end, \
+             case [L || L <- Probed, L >= Line] of \
+                 [] -> 0; \
+                 Later -> \
+                     Actual = lists:min(Later), \
+                     ets:insert(dream_debug, {{{{Mod, Actual}}, true}}), \
+                     Actual \
+             end \
+         end, \
+         Main = fun() -> exit({{dream_exit, begin {program} end}}) end, \
+         Serve = fun Loop(Program, Stopped, NextId) -> \
+             receive \
+                 {{command, {{break, Mod, Line}}}} when is_atom(Mod), is_integer(Line) -> \
+                     Emit(\"breakpoint ~s ~b ~b\", [Mod, Line, Break(Mod, Line)]), \
+                     Loop(Program, Stopped, NextId); \
+                 {{command, {{clear, Mod, Line}}}} -> \
+                     ets:delete(dream_debug, {{Mod, Line}}), \
+                     Loop(Program, Stopped, NextId); \
+                 {{command, run}} when Program =:= none -> \
+                     {{Pid, _}} = spawn_monitor(Main), \
+                     Loop(Pid, Stopped, NextId); \
+                 {{command, run}} -> \
+                     Emit(\"error ~s\", [Hex(\"the program is already running\")]), \
+                     Loop(Program, Stopped, NextId); \
+                 {{command, {{Mode, Id}}}} when Mode =:= continue; Mode =:= step -> \
+                     case maps:take(Id, Stopped) of \
+                         {{Pid, Rest}} -> \
+                             Pid ! {{dream_debug_resume, Mode}}, \
+                             Loop(Program, Rest, NextId); \
+                         error -> \
+                             Emit(\"error ~s\", [Hex(io_lib:format(\"no stopped process ~p\", [Id]))]), \
+                             Loop(Program, Stopped, NextId) \
+                     end; \
+                 {{command, quit}} -> halt(0); \
+                 {{command, Other}} -> \
+                     Emit(\"error ~s\", [Hex(io_lib:format(\"unknown command: ~tp\", [Other]))]), \
+                     Loop(Program, Stopped, NextId); \
+                 {{stopped, Pid, Mod, Line, Bindings}} -> \
+                     Fields = [[$\\s, Hex(atom_to_list(Name)), $:, Hex(Show(Value))] || {{Name, Value}} <- Bindings], \
+                     Emit(\"stopped ~b ~w ~s ~b~s\", [NextId, Pid, Mod, Line, Fields]), \
+                     Loop(Program, Stopped#{{NextId => Pid}}, NextId + 1); \
+                 {{'DOWN', _, process, Pid, Reason}} when Pid =:= Program -> \
+                     Status = case Reason of \
+                         {{dream_exit, N}} when is_integer(N) -> N; \
+                         _ -> 1 \
+                     end, \
+                     Emit(\"exited ~b\", [Status]), \
+                     halt(Status) \
+             end \
+         end, \
+         Serve(none, #{{}}, 1).",
+        marker = MARKER,
+        program = program,
+    )
+}
+
+/// A request to the controller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Break at the first probed line at or after `line` of a BEAM module
+    Break { module: String, line: usize },
+    /// Remove the breakpoint at exactly `line`
+    Clear { module: String, line: usize },
+    /// Start the program
+    Run,
+    /// Resume the process of a stop
+    Continue(u64),
+    /// Resume the process of a stop until its next statement
+    Step(u64),
+    Quit,
+}
+
+impl Command {
+    /// The command as the Erlang term the controller reads, e.g.
+    /// `{break, 'dream::app', 12}.`
+    pub fn to_term(&self) -> String {
+        match self {
+            Command::Break { module, line } => format!("{{break, {}, {}}}.\n", quote_atom(module), line),
+            Command::Clear { module, line } => format!("{{clear, {}, {}}}.\n", quote_atom(module), line),
+            Command::Run => "run.\n".to_string(),
+            Command::Continue(id) => format!("{{continue, {}}}.\n", id),
+            Command::Step(id) => format!("{{step, {}}}.\n", id),
+            Command::Quit => "quit.\n".to_string(),
+        }
+    }
+}
+
+/// A process paused at a probe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stop {
+    /// Names the stop in `continue` and `step` commands
+    pub id: u64,
+    /// The paused process, e.g. `<0.91.0>`
+    pub pid: String,
+    /// BEAM module, e.g. `dream::app`
+    pub module: String,
+    pub line: usize,
+    /// Dream names in scope and their values, in binding order
+    pub bindings: Vec<(String, String)>,
+}
+
+/// What the controller reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A line the program printed
+    Output(String),
+    /// Answer to [`Command::Break`]: the line the breakpoint was placed on,
+    /// or `None` if the module has no statement at or after `requested`
+    Breakpoint { module: String, requested: usize, line: Option<usize> },
+    Stopped(Stop),
+    /// The program finished with this exit status
+    Exited(i32),
+    Error(String),
+}
+
+/// Turn a line of the node's stdout into an event.
+pub fn parse_event(line: &str) -> Event {
+    line.strip_prefix(MARKER)
+        .and_then(|rest| rest.strip_prefix(' '))
+        .and_then(parse_marker)
+        .unwrap_or_else(|| Event::Output(line.to_string()))
+}
+
+fn parse_marker(rest: &str) -> Option<Event> {
+    let mut fields = rest.split(' ');
+    let event = match fields.next()? {
+        "breakpoint" => {
+            let module = fields.next()?.to_string();
+            let requested = fields.next()?.parse().ok()?;
+            let line = fields.next()?.parse().ok()?;
+            Event::Breakpoint { module, requested, line: (line > 0).then_some(line) }
+        }
+        "stopped" => Event::Stopped(Stop {
+            id: fields.next()?.parse().ok()?,
+            pid: fields.next()?.to_string(),
+            module: fields.next()?.to_string(),
+            line: fields.next()?.parse().ok()?,
+            bindings: fields
+                .filter(|field| !field.is_empty())
+                .map(|field| {
+                    let (name, value) = field.split_once(':')?;
+                    Some((decode(name), decode(value)))
+                })
+                .collect::<Option<_>>()?,
+        }),
+        "exited" => Event::Exited(fields.next()?.parse().ok()?),
+        "error" => Event::Error(decode(fields.next()?)),
+        _ => return None,
+    };
+    Some(event)
+}
+
+/// The source file of each BEAM module in a debug build.
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<(PathBuf, String)>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `path` compiles into BEAM module `module`.
+    pub fn add(&mut self, path: PathBuf, module: impl Into<String>) {
+        self.files.push((path, module.into()));
+    }
+
+    /// The module compiled from `file`, given as a path or a trailing part
+    /// of one, e.g. `app.dream` or `src/app.dream`.
+    pub fn module_for(&self, file: &str) -> Option<&str> {
+        let wanted = Path::new(file);
+        let canonical = fs::canonicalize(wanted).ok();
+        self.files
+            .iter()
+            .find(|(path, _)| canonical.is_some() && fs::canonicalize(path).ok() == canonical)
+            .or_else(|| self.files.iter().find(|(path, _)| path.ends_with(wanted)))
+            .map(|(_, module)| module.as_str())
+    }
+
+    /// The source file of `module`.
+    pub fn path_for(&self, module: &str) -> Option<&Path> {
+        self.files
+            .iter()
+            .find(|(_, m)| m == module)
+            .map(|(path, _)| path.as_path())
+    }
+
+    /// `src/app.dream:12`, or the module name if its source is unknown.
+    pub fn location(&self, module: &str, line: usize) -> String {
+        match self.path_for(module) {
+            Some(path) => format!("{}:{}", path.display(), line),
+            None => format!("{}:{}", module, line),
+        }
+    }
+
+    /// Line `line` of `module`'s source, without its indentation.
+    pub fn source_line(&self, module: &str, line: usize) -> Option<String> {
+        let source = fs::read_to_string(self.path_for(module)?).ok()?;
+        source.lines().nth(line.checked_sub(1)?).map(|l| l.trim().to_string())
+    }
+}
+
+/// Split `file:line` into its parts.
+pub fn parse_location(spec: &str) -> Option<(&str, usize)> {
+    let (file, line) = spec.rsplit_once(':')?;
+    Some((file, line.parse().ok()?))
+}
+
+/// A BEAM node running a program under the controller.
+pub struct Session {
+    child: Child,
+    stdin: ChildStdin,
+}
+
+impl Session {
+    /// Start the controller in `erl`, which should already have the code
+    /// path of a debug build. Events go to `events`, converted by `wrap`.
+    /// The program's stdin is the controller's command stream, so it can't
+    /// read from the terminal.
+    pub fn start<T: Send + 'static>(
+        mut erl: process::Command,
+        program: &str,
+        events: Sender<T>,
+        wrap: fn(Event) -> T,
+    ) -> io::Result<Self> {
+        erl.arg("-noshell")
+            .arg("-eval")
+            .arg(harness_expr(program))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        let mut child = erl.spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if events.send(wrap(parse_event(&line))).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { child, stdin })
+    }
+
+    pub fn send(&mut self, command: &Command) -> io::Result<()> {
+        self.stdin.write_all(command.to_term().as_bytes())?;
+        self.stdin.flush()
+    }
+
+    /// Stop the node if it is still running and wait for it to exit.
+    pub fn finish(mut self) -> io::Result<ExitStatus> {
+        // The node has halted already if the program exited
+        let _ = self.send(&Command::Quit);
+        self.child.wait()
+    }
+}
+
+const CLI_HELP: &str = "\
+Commands:
+  break FILE:LINE   (b)  stop before the statement at or after LINE
+  clear FILE:LINE        remove a breakpoint
+  run               (r)  start the program
+  continue          (c)  resume until the next breakpoint
+  step              (s)  resume until the next statement
+  print [NAME]      (p)  show the bindings in scope, or one of them
+  where             (w)  show where the program is stopped
+  quit              (q)  stop the program and exit";
+
+/// Debug from a command prompt: read commands from `input` and report to
+/// `output` until the user quits or the program exits. Returns the
+/// program's exit status, if it finished.
+pub fn run_cli(
+    erl: process::Command,
+    program: &str,
+    sources: &SourceMap,
+    input: impl BufRead,
+    output: impl Write,
+) -> io::Result<Option<i32>> {
+    let (sender, events) = mpsc::channel();
+    let session = Session::start(erl, program, sender, std::convert::identity)?;
+    let mut cli = Cli {
+        session,
+        events,
+        sources,
+        output,
+        stops: VecDeque::new(),
+        breakpoints: Vec::new(),
+        running: false,
+        status: None,
+    };
+    writeln!(cli.output, "Type `help` for the commands, `run` to start.")?;
+    let result = cli.repl(input);
+    cli.session.finish()?;
+    result.map(|()| cli.status)
+}
+
+struct Cli<'a, W> {
+    session: Session,
+    events: Receiver<Event>,
+    sources: &'a SourceMap,
+    output: W,
+    /// Paused processes; the front one is the current stop
+    stops: VecDeque<Stop>,
+    /// (module, line) of each breakpoint set
+    breakpoints: Vec<(String, usize)>,
+    running: bool,
+    status: Option<i32>,
+}
+
+impl<W: Write> Cli<'_, W> {
+    fn repl(&mut self, mut input: impl BufRead) -> io::Result<()> {
+        while self.status.is_none() {
+            write!(self.output, "(dream-debug) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                break;
+            }
+            let mut words = line.split_whitespace();
+            let Some(command) = words.next() else { continue };
+            let argument = words.next();
+            match command {
+                "break" | "b" => self.set_breakpoint(argument)?,
+                "clear" => self.clear_breakpoint(argument)?,
+                "run" | "r" if self.running => writeln!(self.output, "The program is already running.")?,
+                "run" | "r" => {
+                    self.running = true;
+                    self.session.send(&Command::Run)?;
+                    self.wait_for_stop()?;
+                }
+                "continue" | "c" => self.resume(Command::Continue)?,
+                "step" | "s" | "next" | "n" => self.resume(Command::Step)?,
+                "print" | "p" => self.print(argument)?,
+                "where" | "w" => self.show_stop()?,
+                "help" | "h" | "?" => writeln!(self.output, "{}", CLI_HELP)?,
+                "quit" | "q" => break,
+                other => writeln!(self.output, "Unknown command `{}`. Type `help` for the commands.", other)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn set_breakpoint(&mut self, argument: Option<&str>) -> io::Result<()> {
+        let Some((file, line)) = argument.and_then(parse_location) else {
+            return writeln!(self.output, "Usage: break FILE:LINE");
+        };
+        let Some(module) = self.sources.module_for(file) else {
+            return writeln!(self.output, "No module of this project is compiled from {}.", file);
+        };
+        let module = module.to_string();
+        self.session.send(&Command::Break { module: module.clone(), line })?;
+        loop {
+            match self.next_event()? {
+                Some(Event::Breakpoint { line: Some(actual), .. }) => {
+                    self.breakpoints.push((module.clone(), actual));
+                    return writeln!(self.output, "Breakpoint at {}", self.sources.location(&module, actual));
+                }
+                Some(Event::Breakpoint { line: None, .. }) => {
+                    return writeln!(self.output, "No statement at or after line {} of {}.", line, file);
+                }
+                Some(_) => {}
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn clear_breakpoint(&mut self, argument: Option<&str>) -> io::Result<()> {
+        let Some((file, line)) = argument.and_then(parse_location) else {
+            return writeln!(self.output, "Usage: clear FILE:LINE");
+        };
+        let module = self.sources.module_for(file).map(str::to_string);
+        let index = self.breakpoints.iter().position(|(m, l)| Some(m) == module.as_ref() && *l == line);
+        match index {
+            Some(index) => {
+                let (module, line) = self.breakpoints.remove(index);
+                self.session.send(&Command::Clear { module, line })
+            }
+            None => writeln!(self.output, "No breakpoint at {}.", argument.unwrap_or_default()),
+        }
+    }
+
+    fn resume(&mut self, command: fn(u64) -> Command) -> io::Result<()> {
+        let Some(stop) = self.stops.pop_front() else {
+            let hint = if self.running { "No process is stopped." } else { "The program is not running; use `run`." };
+            return writeln!(self.output, "{}", hint);
+        };
+        self.session.send(&command(stop.id))?;
+        if self.stops.is_empty() {
+            self.wait_for_stop()
+        } else {
+            // Another process stopped while this one was current
+            self.show_stop()
+        }
+    }
+
+    fn print(&mut self, name: Option<&str>) -> io::Result<()> {
+        let Some(stop) = self.stops.front() else {
+            return writeln!(self.output, "No process is stopped.");
+        };
+        let bindings: Vec<&(String, String)> = stop
+            .bindings
+            .iter()
+            .filter(|(n, _)| name.is_none_or(|name| n == name))
+            .collect();
+        if bindings.is_empty() {
+            return match name {
+                Some(name) => writeln!(self.output, "No binding named `{}` here.", name),
+                None => writeln!(self.output, "No bindings in scope."),
+            };
+        }
+        for (name, value) in bindings {
+            writeln!(self.output, "{} = {}", name, value)?;
+        }
+        Ok(())
+    }
+
+    fn show_stop(&mut self) -> io::Result<()> {
+        let Some(stop) = self.stops.front() else {
+            return writeln!(self.output, "No process is stopped.");
+        };
+        writeln!(
+            self.output,
+            "Stopped in {} at {}",
+            stop.pid,
+            self.sources.location(&stop.module, stop.line)
+        )?;
+        if let Some(source) = self.sources.source_line(&stop.module, stop.line) {
+            writeln!(self.output, "{:>5} | {}", stop.line, source)?;
+        }
+        Ok(())
+    }
+
+    /// Report events until a process stops or the program exits.
+    fn wait_for_stop(&mut self) -> io::Result<()> {
+        while let Some(event) = self.next_event()? {
+            match event {
+                Event::Stopped(_) => return self.show_stop(),
+                Event::Exited(status) => {
+                    return writeln!(self.output, "The program exited with status {}.", status);
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The next event, after printing output and recording stops and the
+    /// exit status. `None` once the node is gone.
+    fn next_event(&mut self) -> io::Result<Option<Event>> {
+        let Ok(event) = self.events.recv() else {
+            self.status = self.status.or(Some(1));
+            return Ok(None);
+        };
+        match &event {
+            Event::Output(line) => writeln!(self.output, "{}", line)?,
+            Event::Error(message) => writeln!(self.output, "Error: {}", message)?,
+            Event::Stopped(stop) => self.stops.push_back(stop.clone()),
+            Event::Exited(status) => self.status = Some(*status),
+            Event::Breakpoint { .. } => {}
+        }
+        Ok(Some(event))
+    }
+}
+
+enum DapInput {
+    Client(Option<Value>),
+    Debugger(Event),
+}
+
+/// Serve the Debug Adapter Protocol on `reader` and `writer` until the
+/// client disconnects. The program starts when the client sends
+/// `configurationDone`, after its breakpoints are set.
+pub fn serve_dap(
+    mut reader: impl BufRead + Send + 'static,
+    writer: impl Write,
+    erl: process::Command,
+    program: &str,
+    sources: &SourceMap,
+) -> io::Result<()> {
+    let (sender, inbox) = mpsc::channel();
+    let session = Session::start(erl, program, sender.clone(), DapInput::Debugger)?;
+    thread::spawn(move || loop {
+        let message = read_message(&mut reader).ok().flatten();
+        let done = message.is_none();
+        if sender.send(DapInput::Client(message)).is_err() || done {
+            break;
+        }
+    });
+    let mut dap = Dap {
+        session,
+        inbox,
+        sources,
+        writer,
+        seq: 0,
+        deferred: VecDeque::new(),
+        threads: Vec::new(),
+        stops: HashMap::new(),
+        breakpoints: HashMap::new(),
+        running: false,
+    };
+    let result = dap.serve();
+    dap.session.finish()?;
+    result
+}
+
+struct Dap<'a, W> {
+    session: Session,
+    inbox: Receiver<DapInput>,
+    sources: &'a SourceMap,
+    writer: W,
+    seq: i64,
+    /// Requests that arrived while waiting on the controller
+    deferred: VecDeque<Value>,
+    /// Pid of each process that has stopped; thread ids are 1-based indexes
+    threads: Vec<String>,
+    /// The current stop of each paused thread
+    stops: HashMap<i64, Stop>,
+    /// Lines with breakpoints, by module
+    breakpoints: HashMap<String, Vec<usize>>,
+    running: bool,
+}
+
+impl<W: Write> Dap<'_, W> {
+    fn serve(&mut self) -> io::Result<()> {
+        loop {
+            let request = match self.deferred.pop_front() {
+                Some(request) => request,
+                None => match self.inbox.recv() {
+                    Ok(DapInput::Client(Some(request))) => request,
+                    Ok(DapInput::Client(None)) | Err(_) => return Ok(()),
+                    Ok(DapInput::Debugger(event)) => {
+                        self.on_event(event)?;
+                        continue;
+                    }
+                },
+            };
+            if !self.handle(&request)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Answer a request. Returns false once the client has disconnected.
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let args = &request["arguments"];
+        let thread = args["threadId"].as_i64().unwrap_or(0);
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                self.respond(request, json!({ "supportsConfigurationDoneRequest": true }))?;
+                self.event("initialized", json!({}))?;
+            }
+            "launch" | "attach" | "setExceptionBreakpoints" => self.respond(request, json!({}))?,
+            "setBreakpoints" => {
+                let body = self.set_breakpoints(args)?;
+                self.respond(request, body)?;
+            }
+            "configurationDone" => {
+                self.respond(request, json!({}))?;
+                if !self.running {
+                    self.running = true;
+                    self.session.send(&Command::Run)?;
+                }
+            }
+            "threads" => {
+                let mut threads: Vec<Value> = self
+                    .threads
+                    .iter()
+                    .enumerate()
+                    .map(|(i, pid)| json!({ "id": i + 1, "name": pid }))
+                    .collect();
+                if threads.is_empty() {
+                    threads.push(json!({ "id": 0, "name": "main" }));
+                }
+                self.respond(request, json!({ "threads": threads }))?;
+            }
+            "stackTrace" => {
+                let frames: Vec<Value> = self
+                    .stops
+                    .get(&thread)
+                    .map(|stop| {
+                        let mut frame = json!({
+                            "id": thread,
+                            "name": stop.module,
+                            "line": stop.line,
+                            "column": 1,
+                        });
+                        if let Some(path) = self.sources.path_for(&stop.module) {
+                            frame["source"] = json!({ "path": path });
+                        }
+                        frame
+                    })
+                    .into_iter()
+                    .collect();
+                let total = frames.len();
+                self.respond(request, json!({ "stackFrames": frames, "totalFrames": total }))?;
+            }
+            "scopes" => {
+                let frame = args["frameId"].as_i64().unwrap_or(0);
+                let scopes = json!([{ "name": "Locals", "variablesReference": frame, "expensive": false }]);
+                self.respond(request, json!({ "scopes": scopes }))?;
+            }
+            "variables" => {
+                let reference = args["variablesReference"].as_i64().unwrap_or(0);
+                let variables: Vec<Value> = self
+                    .stops
+                    .get(&reference)
+                    .map(|stop| stop.bindings.as_slice())
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|(name, value)| json!({ "name": name, "value": value, "variablesReference": 0 }))
+                    .collect();
+                self.respond(request, json!({ "variables": variables }))?;
+            }
+            "continue" => {
+                self.resume(thread, Command::Continue)?;
+                self.respond(request, json!({ "allThreadsContinued": false }))?;
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.resume(thread, Command::Step)?;
+                self.respond(request, json!({}))?;
+            }
+            "disconnect" | "terminate" => {
+                self.session.send(&Command::Quit).ok();
+                self.respond(request, json!({}))?;
+                return Ok(false);
+            }
+            command => self.fail(request, &format!("`{}` is not supported", command))?,
+        }
+        Ok(true)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> io::Result<Value> {
+        let requested: Vec<usize> = args["breakpoints"]
+            .as_array()
+            .map(|points| points.iter().filter_map(|p| p["line"].as_u64()).map(|l| l as usize).collect())
+            .unwrap_or_default();
+        let path = args["source"]["path"].as_str().unwrap_or_default();
+        let Some(module) = self.sources.module_for(path).map(str::to_string) else {
+            let unverified: Vec<Value> = requested
+                .iter()
+                .map(|line| json!({ "verified": false, "line": line, "message": "not a source file of this project" }))
+                .collect();
+            return Ok(json!({ "breakpoints": unverified }));
+        };
+
+        for line in self.breakpoints.remove(&module).unwrap_or_default() {
+            self.session.send(&Command::Clear { module: module.clone(), line })?;
+        }
+        let mut placed = Vec::new();
+        let mut set = Vec::new();
+        for &line in &requested {
+            self.session.send(&Command::Break { module: module.clone(), line })?;
+            let actual = self.wait_for_breakpoint()?;
+            if let Some(actual) = actual {
+                set.push(actual);
+            }
+            placed.push(match actual {
+                Some(actual) => json!({ "verified": true, "line": actual }),
+                None => json!({ "verified": false, "line": line, "message": "no statement at or after this line" }),
+            });
+        }
+        self.breakpoints.insert(module, set);
+        Ok(json!({ "breakpoints": placed }))
+    }
+
+    /// The controller's answer to a `break`, handling whatever arrives
+    /// before it.
+    fn wait_for_breakpoint(&mut self) -> io::Result<Option<usize>> {
+        loop {
+            match self.inbox.recv() {
+                Ok(DapInput::Debugger(Event::Breakpoint { line, .. })) => return Ok(line),
+                Ok(DapInput::Debugger(event)) => self.on_event(event)?,
+                Ok(DapInput::Client(Some(request))) => self.deferred.push_back(request),
+                Ok(DapInput::Client(None)) | Err(_) => return Ok(None),
+            }
+        }
+    }
+
+    fn resume(&mut self, thread: i64, command: fn(u64) -> Command) -> io::Result<()> {
+        match self.stops.remove(&thread) {
+            Some(stop) => self.session.send(&command(stop.id)),
+            None => Ok(()),
+        }
+    }
+
+    fn on_event(&mut self, event: Event) -> io::Result<()> {
+        match event {
+            Event::Output(line) => {
+                self.event("output", json!({ "category": "stdout", "output": format!("{}\n", line) }))
+            }
+            Event::Error(message) => {
+                self.event("output", json!({ "category": "stderr", "output": format!("{}\n", message) }))
+            }
+            Event::Stopped(stop) => {
+                let index = match self.threads.iter().position(|pid| *pid == stop.pid) {
+                    Some(index) => index + 1,
+                    None => {
+                        self.threads.push(stop.pid.clone());
+                        self.threads.len()
+                    }
+                };
+                let thread = index as i64;
+                let at_breakpoint = self
+                    .breakpoints
+                    .get(&stop.module)
+                    .is_some_and(|lines| lines.contains(&stop.line));
+                self.stops.insert(thread, stop);
+                let reason = if at_breakpoint { "breakpoint" } else { "step" };
+                self.event("stopped", json!({ "reason": reason, "threadId": thread, "allThreadsStopped": false }))
+            }
+            Event::Exited(status) => {
+                self.event("exited", json!({ "exitCode": status }))?;
+                self.event("terminated", json!({}))
+            }
+            Event::Breakpoint { .. } => Ok(()),
+        }
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> io::Result<()> {
+        let message = self.message("response", json!({
+            "request_seq": request["seq"],
+            "success": true,
+            "command": request["command"],
+            "body": body,
+        }));
+        write_message(&mut self.writer, &message)
+    }
+
+    fn fail(&mut self, request: &Value, error: &str) -> io::Result<()> {
+        let message = self.message("response", json!({
+            "request_seq": request["seq"],
+            "success": false,
+            "command": request["command"],
+            "message": error,
+        }));
+        write_message(&mut self.writer, &message)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let message = self.message("event", json!({ "event": event, "body": body }));
+        write_message(&mut self.writer, &message)
+    }
+
+    /// `fields` with the next sequence number and `type`.
+    fn message(&mut self, kind: &str, mut fields: Value) -> Value {
+        self.seq += 1;
+        fields["seq"] = json!(self.seq);
+        fields["type"] = json!(kind);
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::encode;
+
+    #[test]
+    fn test_command_terms() {
+        let brk = Command::Break { module: "dream::app".to_string(), line: 12 };
+        assert_eq!(brk.to_term(), "{break, 'dream::app', 12}.\n");
+        assert_eq!(Command::Run.to_term(), "run.\n");
+        assert_eq!(Command::Step(3).to_term(), "{step, 3}.\n");
+        assert_eq!(quote_atom("it's"), "'it\\'s'");
+    }
+
+    #[test]
+    fn test_parse_events() {
+        assert_eq!(
+            parse_event("##dream-debug## breakpoint dream::app 10 12"),
+            Event::Breakpoint { module: "dream::app".to_string(), requested: 10, line: Some(12) }
+        );
+        assert_eq!(
+            parse_event("##dream-debug## breakpoint dream::app 99 0"),
+            Event::Breakpoint { module: "dream::app".to_string(), requested: 99, line: None }
+        );
+        let line = format!(
+            "##dream-debug## stopped 1 <0.90.0> dream::app 4 {}:{} {}:{}",
+            encode("x"),
+            encode("1"),
+            encode("name"),
+            encode("\"joe\"")
+        );
+        assert_eq!(
+            parse_event(&line),
+            Event::Stopped(Stop {
+                id: 1,
+                pid: "<0.90.0>".to_string(),
+                module: "dream::app".to_string(),
+                line: 4,
+                bindings: vec![
+                    ("x".to_string(), "1".to_string()),
+                    ("name".to_string(), "\"joe\"".to_string()),
+                ],
+            })
+        );
+        assert_eq!(parse_event("##dream-debug## exited 0"), Event::Exited(0));
+        assert_eq!(parse_event(&format!("##dream-debug## error {}", encode("oops"))), Event::Error("oops".to_string()));
+        assert_eq!(parse_event("hello"), Event::Output("hello".to_string()));
+        assert_eq!(parse_event("##dream-debug## stopped x"), Event::Output("##dream-debug## stopped x".to_string()));
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut sources = SourceMap::new();
+        sources.add(PathBuf::from("/work/app/src/app.dream"), "dream::app");
+        sources.add(PathBuf::from("/work/app/src/app/db.dream"), "dream::app::db");
+        assert_eq!(sources.module_for("app.dream"), Some("dream::app"));
+        assert_eq!(sources.module_for("src/app/db.dream"), Some("dream::app::db"));
+        assert_eq!(sources.module_for("other.dream"), None);
+        assert_eq!(sources.location("dream::app", 3), "/work/app/src/app.dream:3");
+        assert_eq!(sources.location("dream::list", 3), "dream::list:3");
+        assert_eq!(parse_location("src/app.dream:12"), Some(("src/app.dream", 12)));
+        assert_eq!(parse_location("src/app.dream"), None);
+    }
+
+    #[test]
+    fn test_harness_expr() {
+        let expr = harness_expr("'dream::app':main()");
+        assert!(expr.contains("ets:new(dream_debug, [named_table, public, set])"));
+        assert!(expr.contains("register(dream_debugger, self())"));
+        assert!(expr.contains("exit({dream_exit, begin 'dream::app':main() end})"));
+        assert!(expr.contains("##dream-debug## "));
+        assert!(expr.ends_with("Serve(none, #{}, 1)."));
+    }
+}
@@ -1,12 +1,21 @@
 //! Dependency management for Dream projects.
 //!
 //! Handles fetching dependencies from hex.pm, git repositories, and local paths.
+//!
+//! Hex requirements (`"~> 1.4"`, `">= 2.0.0 and < 3.0.0"`) are resolved to the
+//! newest matching release through the hex.pm API, and downloaded tarballs are
+//! checked against the registry checksum before being unpacked into `deps/`.
 
 use crate::bindgen;
-use crate::config::{Dependency, ProjectConfig};
+use crate::config::{ConfigError, Dependency, ProjectConfig, Workspace};
+use crate::lockfile::{LockedPackage, Lockfile, Source, LOCKFILE_NAME};
+use crate::target::TargetLayout;
 use flate2::read::GzDecoder;
 use futures::future::join_all;
 use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
@@ -34,41 +43,106 @@ impl std::fmt::Display for DepsError {
 
 impl std::error::Error for DepsError {}
 
+impl From<ConfigError> for DepsError {
+    fn from(e: ConfigError) -> Self {
+        Self::new(e.message)
+    }
+}
+
 pub type DepsResult<T> = Result<T, DepsError>;
 
 /// Hex.pm repository base URL
 const HEX_REPO: &str = "https://repo.hex.pm";
 
+/// Hex.pm API base URL, used to list releases and their checksums
+const HEX_API: &str = "https://hex.pm/api";
+
+/// File in an unpacked hex package holding its metadata.config, as mix does
+const HEX_METADATA: &str = "hex_metadata.config";
+
+/// File in an unpacked hex package recording its version and tarball checksum
+const HEX_STAMP: &str = ".dream-hex";
+
+/// User agent sent to hex.pm
+const USER_AGENT: &str = concat!("dream/", env!("CARGO_PKG_VERSION"));
+
+/// A hex package after fetching.
+struct HexFetch {
+    version: String,
+    checksum: Option<String>,
+    requirements: Vec<(String, String)>,
+}
+
 /// Dependency manager for a Dream project.
 pub struct DepsManager {
+    /// Root the dependencies' relative paths are resolved against
     project_root: PathBuf,
-    config: ProjectConfig,
+    /// Where `deps`, `_build` and dream.lock live: the workspace root for
+    /// a workspace member, otherwise the project root
+    shared_root: PathBuf,
+    dependencies: HashMap<String, Dependency>,
     client: Client,
 }
 
 impl DepsManager {
-    /// Create a new dependency manager.
+    /// Create a new dependency manager. Other members of the package's
+    /// workspace are left out: they are built from source, not fetched.
     pub fn new(project_root: PathBuf, config: ProjectConfig) -> Self {
         Self {
+            shared_root: config.shared_root(&project_root).to_path_buf(),
             project_root,
-            config,
+            dependencies: config
+                .dependencies
+                .into_iter()
+                .filter(|(_, dep)| !dep.is_workspace())
+                .collect(),
             client: Client::new(),
         }
     }
 
+    /// Create a dependency manager for the dependencies of every member of
+    /// a workspace, sharing one `deps` directory and lockfile.
+    pub fn for_workspace(workspace: &Workspace) -> DepsResult<Self> {
+        Ok(Self {
+            project_root: workspace.root.clone(),
+            shared_root: workspace.root.clone(),
+            dependencies: workspace.dependencies()?,
+            client: Client::new(),
+        })
+    }
+
     /// Get the deps directory path.
     pub fn deps_dir(&self) -> PathBuf {
-        self.project_root.join("deps")
+        self.shared_root.join("deps")
     }
 
     /// Get the build directory for compiled dependencies.
     pub fn deps_build_dir(&self) -> PathBuf {
-        self.project_root.join("_build").join("dev").join("lib")
+        TargetLayout::for_root(&self.shared_root).lib_dir("dev")
     }
 
     /// Fetch all dependencies including transitive deps.
-    pub async fn fetch_all(&self) -> DepsResult<()> {
-        if self.config.dependencies.is_empty() {
+    ///
+    /// Versions and commits recorded in dream.lock are reused as long as they
+    /// still satisfy dream.toml, and the lockfile is rewritten with whatever
+    /// was resolved. With `locked`, a missing or outdated lockfile is an error.
+    pub async fn fetch_all(&self, locked: bool) -> DepsResult<()> {
+        let previous = Lockfile::read(&self.shared_root)?;
+        if locked {
+            match &previous {
+                Some(lock) => lock.verify_dependencies(&self.dependencies)?,
+                None => {
+                    return Err(DepsError::new(format!(
+                        "--locked was passed but {} does not exist",
+                        LOCKFILE_NAME
+                    )))
+                }
+            }
+        }
+        let old_lock = previous.clone().unwrap_or_default();
+        let mut lock = Lockfile::default();
+
+        if self.dependencies.is_empty() {
             println!("No dependencies to fetch.");
             return Ok(());
         }
@@ -79,11 +153,10 @@ impl DepsManager {
             DepsError::new(format!("Failed to create deps directory: {}", e))
         })?;
 
-        println!("Fetching {} dependencies...", self.config.dependencies.len());
+        println!("Fetching {} dependencies...", self.dependencies.len());
 
         // Collect initial hex dependencies to fetch
         let mut pending_hex_deps: Vec<(String, String)> = self
-            .config
             .dependencies
             .iter()
             .filter(|(_, dep)| dep.version().is_some() && !dep.is_git() && !dep.is_path())
@@ -97,7 +170,7 @@ impl DepsManager {
         while !pending_hex_deps.is_empty() {
             let fetch_futures: Vec<_> = pending_hex_deps
                 .iter()
-                .map(|(name, version)| self.fetch_hex_package(name, version))
+                .map(|(name, requirement)| self.fetch_hex_package(name, requirement, old_lock.get(name)))
                 .collect();
 
             let results = join_all(fetch_futures).await;
@@ -110,9 +183,11 @@ impl DepsManager {
                 fetched.insert(name.clone());
 
                 match result {
-                    Ok(requirements) => {
+                    Ok(fetch) => {
+                        lock.insert(LockedPackage::hex(name, &fetch.version, fetch.checksum));
+
                         // Add requirements we haven't seen yet
-                        for (req_name, req_version) in requirements {
+                        for (req_name, req_version) in fetch.requirements {
                             if !fetched.contains(&req_name) && !new_requirements.iter().any(|(n, _)| n == &req_name) {
                                 new_requirements.push((req_name, req_version));
                             }
@@ -129,34 +204,81 @@ impl DepsManager {
         }
 
         // Handle git dependencies sequentially (git clone isn't easily parallelized)
-        for (name, dep) in &self.config.dependencies {
+        for (name, dep) in &self.dependencies {
             if let Some(git_url) = dep.git_url() {
-                self.fetch_git_package(name, git_url, dep)?;
+                let locked_rev = old_lock
+                    .get(name)
+                    .filter(|l| l.satisfies(dep))
+                    .and_then(|l| l.rev.as_deref());
+                let commit = self.fetch_git_package(name, git_url, dep, locked_rev)?;
+                lock.insert(LockedPackage::git(name, git_url, &commit));
             }
         }
 
         // Handle path dependencies (just symlink or copy)
-        for (name, dep) in &self.config.dependencies {
+        for (name, dep) in &self.dependencies {
             if let Some(path) = dep.path() {
                 self.link_path_package(name, path)?;
+                lock.insert(LockedPackage::path(name, path));
             }
         }
 
+        if previous.as_ref() != Some(&lock) {
+            if locked {
+                return Err(DepsError::new(format!(
+                    "{} needs to be updated but --locked was passed",
+                    LOCKFILE_NAME
+                )));
+            }
+            lock.write(&self.shared_root)?;
+            println!("Updated {}.", LOCKFILE_NAME);
+        }
+
         println!("Dependencies fetched successfully.");
         Ok(())
     }
 
     /// Fetch a package from hex.pm and return its requirements.
-    async fn fetch_hex_package(&self, name: &str, version: &str) -> DepsResult<Vec<(String, String)>> {
+    /// `requirement` is resolved to the newest matching release first, unless
+    /// the lockfile pins a release that still satisfies it.
+    async fn fetch_hex_package(
+        &self,
+        name: &str,
+        requirement: &str,
+        locked: Option<&LockedPackage>,
+    ) -> DepsResult<HexFetch> {
         let deps_dir = self.deps_dir();
         let pkg_dir = deps_dir.join(name);
 
-        // Skip if already fetched (requirements already processed)
+        let pinned = locked
+            .filter(|l| l.source == Source::Hex)
+            .and_then(|l| Some((l.version.clone()?, l.checksum.clone())))
+            .filter(|(v, _)| HexVersion::parse(v).is_some_and(|v| requirement_matches(requirement, &v)));
+
+        // Already fetched: its requirements still need resolving
         if pkg_dir.exists() {
-            println!("  {} {} (cached)", name, version);
-            return Ok(Vec::new());
+            let stamp = read_hex_stamp(&pkg_dir);
+            let up_to_date = stamp.as_ref().is_some_and(|(have, _)| {
+                cached_hex_is_current(have, pinned.as_ref().map(|(want, _)| want.as_str()), requirement)
+            });
+            if let (true, Some((version, checksum))) = (up_to_date, stamp) {
+                println!("  {} {} (cached)", name, version);
+                let metadata = fs::read_to_string(pkg_dir.join(HEX_METADATA)).unwrap_or_default();
+                return Ok(HexFetch {
+                    version,
+                    checksum,
+                    requirements: self.parse_hex_requirements(&metadata),
+                });
+            }
+            fs::remove_dir_all(&pkg_dir).map_err(|e| {
+                DepsError::new(format!("Failed to remove stale copy of {}: {}", name, e))
+            })?;
         }
 
+        let (version, locked_checksum) = match pinned {
+            Some(pin) => pin,
+            None => (self.resolve_hex_version(name, requirement).await?, None),
+        };
         println!("  Fetching {} {}...", name, version);
 
         // Download the tarball
@@ -181,11 +303,101 @@ impl DepsManager {
             .await
             .map_err(|e| DepsError::new(format!("Failed to read response for {}: {}", name, e)))?;
 
+        // Verify the download against the checksum published by the registry
+        // and the one recorded in dream.lock
+        let checksum = sha256_hex(&tarball);
+        let registry_checksum = self.hex_release_checksum(name, &version).await?;
+        for expected in [registry_checksum, locked_checksum].into_iter().flatten() {
+            if !checksum.eq_ignore_ascii_case(&expected) {
+                return Err(DepsError::new(format!(
+                    "Checksum mismatch for {} {}: expected {}, got {}",
+                    name, version, expected, checksum
+                )));
+            }
+        }
+
         // Extract the package and get its requirements
         let requirements = self.extract_hex_tarball(name, &tarball)?;
+        fs::write(pkg_dir.join(HEX_STAMP), format!("{}\n{}\n", version, checksum)).map_err(|e| {
+            DepsError::new(format!("Failed to record version of {}: {}", name, e))
+        })?;
 
         println!("  {} {} fetched", name, version);
-        Ok(requirements)
+        Ok(HexFetch {
+            version,
+            checksum: Some(checksum),
+            requirements,
+        })
+    }
+
+    /// Resolve a requirement to a concrete hex release.
+    /// An exact version is used as is; anything else is matched against
+    /// the package's published releases, preferring the newest.
+    async fn resolve_hex_version(&self, name: &str, requirement: &str) -> DepsResult<String> {
+        if HexVersion::parse(requirement).is_some() {
+            return Ok(requirement.trim().to_string());
+        }
+
+        let url = format!("{}/packages/{}", HEX_API, name);
+        let package = self.hex_api_get(&url, name).await?.ok_or_else(|| {
+            DepsError::new(format!("Package {} not found on hex.pm", name))
+        })?;
+
+        let releases: Vec<String> = package["releases"]
+            .as_array()
+            .map(|releases| {
+                releases
+                    .iter()
+                    .filter_map(|r| r["version"].as_str().map(|v| v.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        select_version(requirement, &releases).ok_or_else(|| {
+            DepsError::new(format!(
+                "No release of {} matches requirement {}",
+                name, requirement
+            ))
+        })
+    }
+
+    /// Get the registry's SHA-256 checksum of a release tarball, if published.
+    async fn hex_release_checksum(&self, name: &str, version: &str) -> DepsResult<Option<String>> {
+        let url = format!("{}/packages/{}/releases/{}", HEX_API, name, version);
+        let release = self.hex_api_get(&url, name).await?;
+        Ok(release.and_then(|r| r["checksum"].as_str().map(|c| c.to_string())))
+    }
+
+    /// GET a hex.pm API resource as JSON. Returns `None` on 404.
+    async fn hex_api_get(&self, url: &str, name: &str) -> DepsResult<Option<serde_json::Value>> {
+        let response = self
+            .client
+            .get(url)
+            .header("user-agent", USER_AGENT)
+            .header("accept", "application/json")
+            .send()
+            .await
+            .map_err(|e| DepsError::new(format!("Failed to query hex.pm for {}: {}", name, e)))?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(DepsError::new(format!(
+                "Failed to query hex.pm for {}: HTTP {}",
+                name,
+                response.status()
+            )));
+        }
+
+        let body = response
+            .text()
+            .await
+            .map_err(|e| DepsError::new(format!("Failed to read hex.pm response for {}: {}", name, e)))?;
+
+        serde_json::from_str(&body)
+            .map(Some)
+            .map_err(|e| DepsError::new(format!("Invalid hex.pm response for {}: {}", name, e)))
     }
 
     /// Extract a hex package tarball and return its requirements.
@@ -202,6 +414,8 @@ impl DepsManager {
         // The hex tarball contains: VERSION, metadata.config, contents.tar.gz, CHECKSUM
         let mut archive = Archive::new(tarball);
 
+        let mut version_file: Option<Vec<u8>> = None;
+        let mut checksum_file: Option<String> = None;
         let mut contents_tar_gz: Option<Vec<u8>> = None;
         let mut metadata_config: Option<String> = None;
 
@@ -229,6 +443,18 @@ impl DepsManager {
                     DepsError::new(format!("Failed to read metadata.config for {}: {}", name, e))
                 })?;
                 metadata_config = Some(data);
+            } else if path_str == "VERSION" {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).map_err(|e| {
+                    DepsError::new(format!("Failed to read VERSION for {}: {}", name, e))
+                })?;
+                version_file = Some(data);
+            } else if path_str == "CHECKSUM" {
+                let mut data = String::new();
+                entry.read_to_string(&mut data).map_err(|e| {
+                    DepsError::new(format!("Failed to read CHECKSUM for {}: {}", name, e))
+                })?;
+                checksum_file = Some(data);
             }
         }
 
@@ -236,6 +462,19 @@ impl DepsManager {
             DepsError::new(format!("No contents.tar.gz found in {} tarball", name))
         })?;
 
+        // The inner checksum covers VERSION, metadata.config and contents.tar.gz
+        if let Some(expected) = checksum_file {
+            let mut hasher = Sha256::new();
+            hasher.update(version_file.as_deref().unwrap_or_default());
+            hasher.update(metadata_config.as_deref().unwrap_or_default());
+            hasher.update(&contents_data);
+            let actual = hex::encode(hasher.finalize());
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                let _ = fs::remove_dir_all(&temp_dir);
+                return Err(DepsError::new(format!("Corrupt tarball for {}: checksum mismatch", name)));
+            }
+        }
+
         // Extract contents.tar.gz to the package directory
         fs::create_dir_all(&pkg_dir).map_err(|e| {
             DepsError::new(format!("Failed to create package directory for {}: {}", name, e))
@@ -250,8 +489,11 @@ impl DepsManager {
         // Clean up temp directory
         let _ = fs::remove_dir_all(&temp_dir);
 
-        // Parse requirements from metadata.config
+        // Parse requirements from metadata.config, keeping it for cached runs
         let requirements = if let Some(metadata) = metadata_config {
+            fs::write(pkg_dir.join(HEX_METADATA), &metadata).map_err(|e| {
+                DepsError::new(format!("Failed to write metadata for {}: {}", name, e))
+            })?;
             self.parse_hex_requirements(&metadata)
         } else {
             Vec::new()
@@ -349,48 +591,65 @@ impl DepsManager {
     }
 
     /// Fetch a package from a git repository.
-    fn fetch_git_package(&self, name: &str, url: &str, dep: &Dependency) -> DepsResult<()> {
-        let deps_dir = self.deps_dir();
-        let pkg_dir = deps_dir.join(name);
-
-        // Skip if already fetched
-        if pkg_dir.exists() {
-            println!("  {} (git, cached)", name);
-            return Ok(());
+    ///
+    /// Repositories are mirrored once under `~/.dream/deps/git` and shared by
+    /// every project; `deps/<name>` is a local clone checked out at the
+    /// requested branch, tag or ref, or at the commit in dream.lock. Returns
+    /// the commit that was checked out.
+    fn fetch_git_package(
+        &self,
+        name: &str,
+        url: &str,
+        dep: &Dependency,
+        locked_rev: Option<&str>,
+    ) -> DepsResult<String> {
+        let pkg_dir = self.deps_dir().join(name);
+        let cache = git_cache_dir(&dream_home(), url);
+        let rev = locked_rev.or(dep.git_rev()).unwrap_or("HEAD");
+
+        // Tags, commits and locked revs never move; branches and the default branch do
+        let pinned = locked_rev.is_some()
+            || matches!(dep, Dependency::Detailed(spec) if spec.tag.is_some() || spec.git_ref.is_some());
+
+        // Mirror the repository, or refresh the mirror unless the rev is pinned
+        // and already present
+        if !cache.exists() {
+            println!("  Cloning {} from {}...", name, url);
+            if let Some(parent) = cache.parent() {
+                fs::create_dir_all(parent).map_err(|e| {
+                    DepsError::new(format!("Failed to create git cache for {}: {}", name, e))
+                })?;
+            }
+            run_git(name, None, &["clone", "--mirror", "--quiet", url, &cache.to_string_lossy()])?;
+        } else if !pinned || resolve_git_rev(name, &cache, rev).is_err() {
+            println!("  Updating {} from {}...", name, url);
+            // Offline is fine as long as the mirror already has the rev
+            if let Err(e) = run_git(name, Some(&cache), &["remote", "update", "--prune"]) {
+                eprintln!("  Warning: {}", e);
+            }
         }
+        let commit = resolve_git_rev(name, &cache, rev)?;
 
-        println!("  Cloning {} from {}...", name, url);
-
-        // Get branch/tag/ref if specified
-        let checkout_ref = if let Dependency::Detailed(spec) = dep {
-            spec.branch
-                .as_deref()
-                .or(spec.tag.as_deref())
-                .or(spec.git_ref.as_deref())
-        } else {
-            None
-        };
-
-        // Clone the repository
-        let mut cmd = std::process::Command::new("git");
-        cmd.arg("clone").arg("--depth").arg("1");
-
-        if let Some(ref_name) = checkout_ref {
-            cmd.arg("--branch").arg(ref_name);
+        // Reuse the existing checkout if it is already at the right commit
+        if pkg_dir.exists() {
+            if resolve_git_rev(name, &pkg_dir, "HEAD").ok().as_deref() == Some(commit.as_str()) {
+                println!("  {} (git, cached)", name);
+                return Ok(commit);
+            }
+            fs::remove_dir_all(&pkg_dir).map_err(|e| {
+                DepsError::new(format!("Failed to remove stale checkout of {}: {}", name, e))
+            })?;
         }
 
-        cmd.arg(url).arg(&pkg_dir);
-
-        let status = cmd.status().map_err(|e| {
-            DepsError::new(format!("Failed to clone {}: {}", name, e))
-        })?;
-
-        if !status.success() {
-            return Err(DepsError::new(format!("Git clone failed for {}", name)));
-        }
+        run_git(
+            name,
+            None,
+            &["clone", "--quiet", "--no-checkout", &cache.to_string_lossy(), &pkg_dir.to_string_lossy()],
+        )?;
+        run_git(name, Some(&pkg_dir), &["checkout", "--quiet", "--detach", &commit])?;
 
-        println!("  {} cloned", name);
-        Ok(())
+        println!("  {} checked out at {}", name, &commit[..commit.len().min(7)]);
+        Ok(commit)
     }
 
     /// Link a local path dependency.
@@ -398,12 +657,6 @@ impl DepsManager {
         let deps_dir = self.deps_dir();
         let pkg_dir = deps_dir.join(name);
 
-        // Skip if already linked
-        if pkg_dir.exists() {
-            println!("  {} (path, cached)", name);
-            return Ok(());
-        }
-
         // Resolve the path relative to project root
         let source_path = if Path::new(path).is_absolute() {
             PathBuf::from(path)
@@ -411,6 +664,20 @@ impl DepsManager {
             self.project_root.join(path)
         };
 
+        // Skip if already linked to the same place; relink if the path moved
+        if let Ok(target) = fs::read_link(&pkg_dir) {
+            if target == source_path {
+                println!("  {} (path, cached)", name);
+                return Ok(());
+            }
+            remove_link(&pkg_dir).map_err(|e| {
+                DepsError::new(format!("Failed to unlink {}: {}", name, e))
+            })?;
+        } else if pkg_dir.exists() {
+            println!("  {} (path, cached)", name);
+            return Ok(());
+        }
+
         if !source_path.exists() {
             return Err(DepsError::new(format!(
                 "Path dependency {} not found: {}",
@@ -675,6 +942,12 @@ impl DepsManager {
             return Ok(());
         }
 
+        // Prefer the package's own build through mix, which also writes its .app
+        if pkg_dir.join("mix.exs").exists() && self.compile_with_mix(name, &pkg_dir, &ebin_dir) {
+            println!("  {} compiled", name);
+            return Ok(());
+        }
+
         // Check if elixirc is available
         let elixirc_check = std::process::Command::new("elixirc")
             .arg("--version")
@@ -728,6 +1001,39 @@ impl DepsManager {
         Ok(())
     }
 
+    /// Build an Elixir dependency with `mix compile`, copying its ebin into
+    /// place. Returns false if mix is unavailable or the build fails, so the
+    /// caller can fall back to compiling the sources with elixirc.
+    fn compile_with_mix(&self, name: &str, pkg_dir: &Path, ebin_dir: &Path) -> bool {
+        println!("  Compiling {} (mix)...", name);
+
+        // Other deps are already compiled; make them visible instead of
+        // letting mix try to fetch them
+        let deps_dir = self.deps_dir();
+        let abs_deps_dir = deps_dir.canonicalize().unwrap_or(deps_dir);
+
+        let output = std::process::Command::new("mix")
+            .args(["compile", "--no-deps-check"])
+            .current_dir(pkg_dir)
+            .env("MIX_ENV", "prod")
+            .env("ERL_LIBS", &abs_deps_dir)
+            .output();
+
+        match output {
+            Ok(output) if output.status.success() => {}
+            _ => return false,
+        }
+
+        let mix_ebin = pkg_dir.join("_build/prod/lib").join(name).join("ebin");
+        if !mix_ebin.exists() {
+            return false;
+        }
+        if ebin_dir.exists() {
+            let _ = fs::remove_dir_all(ebin_dir);
+        }
+        Self::copy_dir_recursive(&mix_ebin, ebin_dir).is_ok()
+    }
+
     /// Generate a .app file for an Elixir dependency.
     fn generate_elixir_app_file(
         &self,
@@ -857,7 +1163,7 @@ impl DepsManager {
         }
 
         // Also check deps/ directory for hex packages with pre-built ebin
-        let deps_dir = self.project_root.join("deps");
+        let deps_dir = self.deps_dir();
         if let Ok(entries) = fs::read_dir(&deps_dir) {
             for entry in entries.flatten() {
                 let ebin = entry.path().join("ebin");
@@ -868,7 +1174,7 @@ impl DepsManager {
         }
 
         // Check path dependencies' own build directories
-        for (name, dep) in &self.config.dependencies {
+        for (name, dep) in &self.dependencies {
             if let Some(path) = dep.path() {
                 // Resolve the path relative to project root
                 let dep_root = if Path::new(path).is_absolute() {
@@ -878,12 +1184,7 @@ impl DepsManager {
                 };
 
                 // Path dependency's build output is in its own _build/dev/lib/<name>/ebin
-                let ebin = dep_root
-                    .join("_build")
-                    .join("dev")
-                    .join("lib")
-                    .join(name)
-                    .join("ebin");
+                let ebin = TargetLayout::for_root(&dep_root).beam_dir("dev", name);
                 if ebin.exists() {
                     paths.push(ebin);
                 }
@@ -896,7 +1197,7 @@ impl DepsManager {
     /// Get the bindings directory path.
     /// Bindings are stored in _build/bindings/
     pub fn bindings_dir(&self) -> PathBuf {
-        self.project_root.join("_build").join("bindings")
+        TargetLayout::for_root(&self.shared_root).bindings_dir()
     }
 
     /// Generate bindings for all dependencies.
@@ -992,7 +1293,7 @@ impl DepsManager {
         let file_paths: Vec<std::path::PathBuf> = erl_files.to_vec();
 
         // Call bindgen with the files
-        let result = bindgen::cmd_bindgen(&file_paths, Some(&output_file), Some(pkg_name));
+        let result = bindgen::cmd_bindgen(&file_paths, &[], false, Some(&output_file), Some(pkg_name));
 
         if result == std::process::ExitCode::SUCCESS {
             println!("  {} -> {}", pkg_name, output_file.display());
@@ -1021,7 +1322,7 @@ impl DepsManager {
         let file_paths: Vec<std::path::PathBuf> = ex_files.to_vec();
 
         // Call bindgen with the files
-        let result = crate::bindgen::cmd_bindgen(&file_paths, Some(&output_file), None);
+        let result = crate::bindgen::cmd_bindgen(&file_paths, &[], false, Some(&output_file), None);
 
         if result == std::process::ExitCode::SUCCESS {
             println!("  {} -> {} (Elixir)", pkg_name, output_file.display());
@@ -1126,3 +1427,369 @@ extern mod {} {{
         files
     }
 }
+
+// =============================================================================
+// Git Cache
+// =============================================================================
+
+/// Dream's per-user directory: `$DREAM_HOME`, or `~/.dream`.
+pub fn dream_home() -> PathBuf {
+    if let Some(home) = std::env::var_os("DREAM_HOME") {
+        return PathBuf::from(home);
+    }
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(".dream")
+}
+
+/// Mirror directory for a git URL: `<home>/deps/git/<repo>-<hash>`.
+/// The hash keeps forks with the same repository name apart.
+pub fn git_cache_dir(dream_home: &Path, url: &str) -> PathBuf {
+    let repo = url
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit(['/', ':'])
+        .next()
+        .filter(|s| !s.is_empty())
+        .unwrap_or("repo");
+    let hash = sha256_hex(url.as_bytes());
+    dream_home
+        .join("deps")
+        .join("git")
+        .join(format!("{}-{}", repo, &hash[..12]))
+}
+
+/// Read the version and checksum recorded when a hex package was unpacked.
+fn read_hex_stamp(pkg_dir: &Path) -> Option<(String, Option<String>)> {
+    let content = fs::read_to_string(pkg_dir.join(HEX_STAMP)).ok()?;
+    let mut lines = content.lines();
+    let version = lines.next()?.trim().to_string();
+    let checksum = lines.next().map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    Some((version, checksum))
+}
+
+/// Whether the unpacked release `have` can be kept: it is the release the
+/// lockfile pins, or with nothing pinned, it still meets `requirement`.
+fn cached_hex_is_current(have: &str, pinned: Option<&str>, requirement: &str) -> bool {
+    match pinned {
+        Some(want) => have == want,
+        None => HexVersion::parse(have).is_some_and(|v| requirement_matches(requirement, &v)),
+    }
+}
+
+/// Run git, in `dir` if given, returning its trimmed stdout.
+fn run_git(name: &str, dir: Option<&Path>, args: &[&str]) -> DepsResult<String> {
+    let mut cmd = std::process::Command::new("git");
+    if let Some(dir) = dir {
+        cmd.arg("-C").arg(dir);
+    }
+    let output = cmd
+        .args(args)
+        .output()
+        .map_err(|e| DepsError::new(format!("Failed to run git for {}: {}", name, e)))?;
+
+    if !output.status.success() {
+        return Err(DepsError::new(format!(
+            "git {} failed for {}:\n{}",
+            args.first().copied().unwrap_or_default(),
+            name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Resolve a branch, tag or ref to a commit hash in the repository at `dir`.
+fn resolve_git_rev(name: &str, dir: &Path, rev: &str) -> DepsResult<String> {
+    run_git(name, Some(dir), &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+        .map_err(|_| DepsError::new(format!("Unknown git revision '{}' for {}", rev, name)))
+}
+
+/// Remove a symlink created by `link_path_package`.
+fn remove_link(link: &Path) -> std::io::Result<()> {
+    #[cfg(windows)]
+    {
+        fs::remove_dir(link)
+    }
+    #[cfg(not(windows))]
+    {
+        fs::remove_file(link)
+    }
+}
+
+// =============================================================================
+// Hex Versions
+// =============================================================================
+
+/// SHA-256 of `data` as lowercase hex.
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+/// A release version as published on hex.pm, e.g. `1.4.2` or `2.0.0-rc.1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HexVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// Pre-release tag (`rc.1`), which sorts before the plain release.
+    pub pre: Option<String>,
+}
+
+impl HexVersion {
+    /// Parse a full `major.minor.patch[-pre][+build]` version.
+    pub fn parse(s: &str) -> Option<Self> {
+        match parse_partial(s)? {
+            (major, minor, Some(patch), pre) => Some(Self { major, minor, patch, pre }),
+            _ => None,
+        }
+    }
+}
+
+impl Ord for HexVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            })
+    }
+}
+
+impl PartialOrd for HexVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Parse `major.minor[.patch][-pre]`, leaving the patch out if absent.
+fn parse_partial(s: &str) -> Option<(u64, u64, Option<u64>, Option<String>)> {
+    let s = s.trim().split('+').next()?;
+    let (core, pre) = match s.split_once('-') {
+        Some((core, pre)) => (core, Some(pre.to_string())),
+        None => (s, None),
+    };
+    let mut parts = core.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = match parts.next() {
+        Some(p) => Some(p.parse().ok()?),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch, pre))
+}
+
+/// Check a version against a hex requirement.
+///
+/// Supports `==`, `!=`, `>`, `>=`, `<`, `<=` and `~>` joined with `and`/`or`.
+/// `~> 1.4` allows `>= 1.4.0 and < 2.0.0`; `~> 1.4.2` allows
+/// `>= 1.4.2 and < 1.5.0`. A bare version is exact when it has a patch
+/// component and treated like `~>` otherwise, so `jason = "1.4"` in
+/// dream.toml accepts any 1.x from 1.4 on.
+pub fn requirement_matches(requirement: &str, version: &HexVersion) -> bool {
+    requirement.split(" or ").any(|alternative| {
+        alternative
+            .split(" and ")
+            .all(|clause| clause_matches(clause.trim(), version))
+    })
+}
+
+fn clause_matches(clause: &str, version: &HexVersion) -> bool {
+    let (op, target) = ["~>", ">=", "<=", "==", "!=", ">", "<"]
+        .iter()
+        .find_map(|op| clause.strip_prefix(op).map(|rest| (*op, rest.trim())))
+        .unwrap_or(("", clause));
+
+    let Some((major, minor, patch, pre)) = parse_partial(target) else {
+        return false;
+    };
+    let bound = HexVersion { major, minor, patch: patch.unwrap_or(0), pre };
+
+    match op {
+        "" if patch.is_some() => *version == bound,
+        "~>" | "" => {
+            let upper = if patch.is_some() {
+                HexVersion { major, minor: minor + 1, patch: 0, pre: None }
+            } else {
+                HexVersion { major: major + 1, minor: 0, patch: 0, pre: None }
+            };
+            *version >= bound && *version < upper
+        }
+        "==" => *version == bound,
+        "!=" => *version != bound,
+        ">=" => *version >= bound,
+        "<=" => *version <= bound,
+        ">" => *version > bound,
+        "<" => *version < bound,
+        _ => false,
+    }
+}
+
+/// Pick the newest release matching `requirement`.
+/// Pre-releases are only considered when the requirement names one.
+pub fn select_version(requirement: &str, releases: &[String]) -> Option<String> {
+    let allow_pre = requirement.contains('-');
+    releases
+        .iter()
+        .filter_map(|r| HexVersion::parse(r).map(|v| (v, r)))
+        .filter(|(v, _)| allow_pre || v.pre.is_none())
+        .filter(|(v, _)| requirement_matches(requirement, v))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, r)| r.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> HexVersion {
+        HexVersion::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(v("1.4.2"), HexVersion { major: 1, minor: 4, patch: 2, pre: None });
+        assert_eq!(v("2.0.0-rc.1+build.5").pre.as_deref(), Some("rc.1"));
+        assert!(HexVersion::parse("1.4").is_none());
+        assert!(HexVersion::parse("~> 1.4").is_none());
+        assert!(HexVersion::parse("1.2.3.4").is_none());
+    }
+
+    #[test]
+    fn test_version_ordering() {
+        assert!(v("1.4.10") > v("1.4.9"));
+        assert!(v("2.0.0") > v("1.99.99"));
+        assert!(v("2.0.0-rc.1") < v("2.0.0"));
+    }
+
+    #[test]
+    fn test_pessimistic_requirement() {
+        assert!(requirement_matches("~> 1.4", &v("1.4.0")));
+        assert!(requirement_matches("~> 1.4", &v("1.9.3")));
+        assert!(!requirement_matches("~> 1.4", &v("2.0.0")));
+        assert!(!requirement_matches("~> 1.4", &v("1.3.9")));
+
+        assert!(requirement_matches("~> 1.4.2", &v("1.4.7")));
+        assert!(!requirement_matches("~> 1.4.2", &v("1.5.0")));
+        assert!(!requirement_matches("~> 1.4.2", &v("1.4.1")));
+    }
+
+    #[test]
+    fn test_compound_requirements() {
+        let req = ">= 2.0.0 and < 3.0.0";
+        assert!(requirement_matches(req, &v("2.5.1")));
+        assert!(!requirement_matches(req, &v("3.0.0")));
+
+        let req = "~> 1.0 or ~> 2.1";
+        assert!(requirement_matches(req, &v("1.2.0")));
+        assert!(requirement_matches(req, &v("2.3.0")));
+        assert!(!requirement_matches(req, &v("2.0.5")));
+    }
+
+    #[test]
+    fn test_bare_requirements() {
+        assert!(requirement_matches("1.4.4", &v("1.4.4")));
+        assert!(!requirement_matches("1.4.4", &v("1.4.5")));
+        assert!(requirement_matches("1.4", &v("1.6.0")));
+        assert!(requirement_matches("== 1.4.4", &v("1.4.4")));
+        assert!(!requirement_matches("!= 1.4.4", &v("1.4.4")));
+    }
+
+    #[test]
+    fn test_select_version() {
+        let releases: Vec<String> = ["1.3.0", "1.4.1", "1.4.4", "2.0.0-rc.0", "2.0.0"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(select_version("~> 1.4", &releases).as_deref(), Some("1.4.4"));
+        assert_eq!(select_version(">= 1.0.0", &releases).as_deref(), Some("2.0.0"));
+        assert_eq!(select_version("~> 2.0.0-rc", &releases).as_deref(), Some("2.0.0"));
+        assert_eq!(select_version("~> 3.0", &releases), None);
+        assert_eq!(select_version("< 2.0.0", &releases).as_deref(), Some("1.4.4"));
+    }
+
+    #[test]
+    fn test_cached_hex_is_current() {
+        assert!(cached_hex_is_current("1.4.4", Some("1.4.4"), "~> 1.4"));
+        assert!(!cached_hex_is_current("1.4.4", Some("1.5.0"), "~> 1.4"));
+        assert!(cached_hex_is_current("1.4.4", None, "~> 1.4"));
+        // The requirement was bumped past what is unpacked
+        assert!(!cached_hex_is_current("1.4.4", None, "~> 2.0"));
+        assert!(!cached_hex_is_current("garbage", None, "~> 1.4"));
+    }
+
+    #[test]
+    fn test_git_cache_dir() {
+        let home = Path::new("/home/me/.dream");
+        let dir = git_cache_dir(home, "https://github.com/acme/widgets.git");
+        assert!(dir.starts_with("/home/me/.dream/deps/git"));
+        let file_name = dir.file_name().unwrap().to_string_lossy().to_string();
+        assert!(file_name.starts_with("widgets-"), "{}", file_name);
+
+        // Same name, different fork
+        let fork = git_cache_dir(home, "git@github.com:someone/widgets.git");
+        assert_ne!(dir, fork);
+        assert_eq!(git_cache_dir(home, "https://github.com/acme/widgets.git"), dir);
+    }
+
+    #[test]
+    fn test_git_checkout_from_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let origin = tmp.path().join("origin");
+        fs::create_dir_all(&origin).unwrap();
+        let git = |args: &[&str]| run_git("widgets", Some(&origin), args).unwrap();
+        git(&["init", "--quiet"]);
+        git(&["-c", "user.name=t", "-c", "user.email=t@t", "commit", "--quiet", "--allow-empty", "-m", "one"]);
+        git(&["tag", "v1"]);
+        let v1 = git(&["rev-parse", "HEAD"]);
+        git(&["-c", "user.name=t", "-c", "user.email=t@t", "commit", "--quiet", "--allow-empty", "-m", "two"]);
+
+        let cache = git_cache_dir(tmp.path(), &origin.to_string_lossy());
+        fs::create_dir_all(cache.parent().unwrap()).unwrap();
+        run_git("widgets", None, &["clone", "--mirror", "--quiet", &origin.to_string_lossy(), &cache.to_string_lossy()])
+            .unwrap();
+        assert_eq!(resolve_git_rev("widgets", &cache, "v1").unwrap(), v1);
+        assert!(resolve_git_rev("widgets", &cache, "v2").is_err());
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_parse_hex_requirements() {
+        let config: ProjectConfig =
+            toml::from_str("[package]\nname = \"app\"\nversion = \"0.1.0\"\n").unwrap();
+        let manager = DepsManager::new(PathBuf::from("/tmp/app"), config);
+        let metadata = r#"{<<"name">>,<<"plug">>}.
+{<<"requirements">>,
+ [{<<"mime">>,
+   [{<<"app">>,<<"mime">>},
+    {<<"optional">>,false},
+    {<<"requirement">>,<<"~> 1.0 or ~> 2.0">>}]},
+  {<<"telemetry">>,
+   [{<<"app">>,<<"telemetry">>},
+    {<<"optional">>,false},
+    {<<"requirement">>,<<"~> 0.4.3 or ~> 1.0">>}]}]}.
+"#;
+        assert_eq!(
+            manager.parse_hex_requirements(metadata),
+            vec![
+                ("mime".to_string(), "~> 1.0 or ~> 2.0".to_string()),
+                ("telemetry".to_string(), "~> 0.4.3 or ~> 1.0".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,798 @@
+//! HTML documentation for `dream doc`.
+//!
+//! Each module of the project gets a page listing its public functions,
+//! types and traits. A declaration is shown the way `dream fmt` writes it,
+//! followed by the `///` comment above it rendered as markdown; a module's
+//! own description comes from the `//!` lines at the top of its file.
+//! Type names in declarations, and names in backticks in comments, link
+//! to the item they refer to. [`Site::write`] puts the pages under
+//! `_build/doc`, and [`serve`] serves them over HTTP.
+
+use crate::analysis::find_declaration;
+use crate::compiler::{format_declaration, is_test, Item, Module};
+use crate::markup::escape;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+
+/// What kind of item an [`ItemDoc`] documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Kind {
+    Struct,
+    Enum,
+    TypeAlias,
+    Trait,
+    Function,
+    Method,
+}
+
+impl Kind {
+    /// Start of the item's anchor, as in rustdoc: `struct.Point`.
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Struct => "struct",
+            Self::Enum => "enum",
+            Self::TypeAlias => "type",
+            Self::Trait => "trait",
+            Self::Function => "fn",
+            Self::Method => "method",
+        }
+    }
+
+    fn heading(self) -> &'static str {
+        match self {
+            Self::Struct => "Structs",
+            Self::Enum => "Enums",
+            Self::TypeAlias => "Type Aliases",
+            Self::Trait => "Traits",
+            Self::Function => "Functions",
+            Self::Method => "Methods",
+        }
+    }
+}
+
+/// One documented item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemDoc {
+    pub kind: Kind,
+    pub name: String,
+    /// The declaration as `dream fmt` writes it, without a body
+    pub declaration: String,
+    /// Markdown from the `///` comment above the item
+    pub doc: String,
+    /// Public methods, for a struct or enum with an `impl` block
+    pub methods: Vec<ItemDoc>,
+    /// Traits the type implements
+    pub traits: Vec<String>,
+}
+
+impl ItemDoc {
+    fn anchor(&self) -> String {
+        format!("{}.{}", self.kind.prefix(), self.name)
+    }
+}
+
+/// The documentation of one module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleDoc {
+    pub name: String,
+    /// Markdown from the `//!` lines at the top of the file
+    pub doc: String,
+    /// Items in source order
+    pub items: Vec<ItemDoc>,
+}
+
+impl ModuleDoc {
+    /// The public items of `module`, leaving out tests.
+    pub fn from_module(module: &Module) -> Self {
+        let source = module.source.as_deref().unwrap_or_default();
+        let declared_doc = |keyword: &str, name: &str| {
+            find_declaration(source, keyword, name).map_or_else(String::new, |span| doc_comment(source, span.start))
+        };
+        let item_doc = |kind: Kind, name: &str, item: &Item, doc: String| ItemDoc {
+            kind,
+            name: name.to_string(),
+            declaration: format_declaration(item),
+            doc,
+            methods: Vec::new(),
+            traits: Vec::new(),
+        };
+
+        let mut items = Vec::new();
+        for item in &module.items {
+            let documented = match item {
+                Item::Function(f) if f.is_pub && !is_test(&f.attrs) => {
+                    Some(item_doc(Kind::Function, &f.name, item, doc_comment(source, f.span.start)))
+                }
+                Item::Struct(s) if s.is_pub => Some(item_doc(Kind::Struct, &s.name, item, declared_doc("struct", &s.name))),
+                Item::Enum(e) if e.is_pub => Some(item_doc(Kind::Enum, &e.name, item, declared_doc("enum", &e.name))),
+                Item::TypeAlias(t) if t.is_pub => {
+                    Some(item_doc(Kind::TypeAlias, &t.name, item, declared_doc("type", &t.name)))
+                }
+                Item::Trait(t) => Some(item_doc(Kind::Trait, &t.name, item, declared_doc("trait", &t.name))),
+                _ => None,
+            };
+            items.extend(documented);
+        }
+
+        // Methods and trait implementations go with their type
+        for item in &module.items {
+            let (type_name, methods, trait_name) = match item {
+                Item::Impl(block) => (&block.type_name, &block.methods, None),
+                Item::TraitImpl(block) => (&block.type_name, &block.methods, Some(&block.trait_name)),
+                _ => continue,
+            };
+            let Some(owner) = items
+                .iter_mut()
+                .find(|i| matches!(i.kind, Kind::Struct | Kind::Enum) && i.name == *type_name)
+            else {
+                continue;
+            };
+            if let Some(trait_name) = trait_name {
+                owner.traits.push(trait_name.clone());
+                continue;
+            }
+            for method in methods.iter().filter(|m| m.is_pub) {
+                let method_item = Item::Function(method.clone());
+                owner.methods.push(ItemDoc {
+                    name: format!("{}.{}", type_name, method.name),
+                    ..item_doc(Kind::Method, &method.name, &method_item, doc_comment(source, method.span.start))
+                });
+            }
+        }
+
+        Self {
+            name: module.name.clone(),
+            doc: module_doc(source),
+            items,
+        }
+    }
+
+    /// The page's file name: `app.parser.html` for `app::parser`.
+    pub fn file_name(&self) -> String {
+        page_file(&self.name)
+    }
+}
+
+fn page_file(module: &str) -> String {
+    format!("{}.html", module.replace("::", "."))
+}
+
+/// The `///` comment directly above the line holding byte `offset` of
+/// `source`, past any attributes, without its markers.
+pub fn doc_comment(source: &str, offset: usize) -> String {
+    let line_start = source[..offset.min(source.len())].rfind('\n').map_or(0, |i| i + 1);
+    let mut lines = Vec::new();
+    for line in source[..line_start].lines().rev() {
+        let line = line.trim();
+        if let Some(text) = line.strip_prefix("///") {
+            lines.push(text.strip_prefix(' ').unwrap_or(text));
+        } else if !line.starts_with("#[") {
+            break;
+        }
+    }
+    lines.reverse();
+    lines.join("\n")
+}
+
+/// The `//!` lines opening `source`, without their markers.
+pub fn module_doc(source: &str) -> String {
+    let lines: Vec<&str> = source
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| line.is_empty())
+        .map_while(|line| line.strip_prefix("//!"))
+        .map(|text| text.strip_prefix(' ').unwrap_or(text))
+        .collect();
+    lines.join("\n")
+}
+
+/// Where each documented name is, for cross-links.
+#[derive(Debug, Default)]
+struct Links {
+    /// Item name to the modules defining it and its anchor there
+    items: HashMap<String, Vec<(String, String, Kind)>>,
+    modules: Vec<String>,
+}
+
+impl Links {
+    fn new(modules: &[ModuleDoc]) -> Self {
+        let mut links = Self::default();
+        for module in modules {
+            links.modules.push(module.name.clone());
+            for item in &module.items {
+                links
+                    .items
+                    .entry(item.name.clone())
+                    .or_default()
+                    .push((module.name.clone(), item.anchor(), item.kind));
+            }
+        }
+        for places in links.items.values_mut() {
+            places.sort();
+        }
+        links
+    }
+
+    /// The URL of what `path`, written in module `current`, names: a
+    /// module, or an item, looked for in `current` first. With `types`
+    /// set, functions are not linked.
+    fn resolve(&self, current: &str, path: &str, types: bool) -> Option<String> {
+        if let Some(module) = self.module(current, path) {
+            return Some(page_file(module));
+        }
+        let (module, name) = match path.rsplit_once("::") {
+            Some((prefix, name)) => (Some(self.module(current, prefix)?), name),
+            None => (None, path),
+        };
+        let places = self.items.get(name)?;
+        let (found, anchor, _) = places
+            .iter()
+            .filter(|(_, _, kind)| !types || *kind != Kind::Function)
+            .filter(|(m, _, _)| module.is_none_or(|module| m == module))
+            .min_by_key(|(m, _, _)| m != current)?;
+        Some(format!("{}#{}", page_file(found), anchor))
+    }
+
+    /// The module `path` names from `current`: itself, a submodule of
+    /// `current` or a sibling, or a module of the package.
+    fn module<'a>(&'a self, current: &str, path: &str) -> Option<&'a str> {
+        let parent = current.rsplit_once("::").map(|(parent, _)| parent);
+        let package = current.split("::").next().unwrap_or(current);
+        let path = path.strip_prefix("crate::").unwrap_or(path);
+        let candidates = [
+            Some(format!("{}::{}", current, path)),
+            parent.map(|parent| format!("{}::{}", parent, path)),
+            Some(format!("{}::{}", package, path)),
+            Some(path.to_string()),
+        ];
+        candidates
+            .into_iter()
+            .flatten()
+            .find_map(|name| self.modules.iter().find(|m| **m == name))
+            .map(String::as_str)
+    }
+}
+
+/// The documentation of a package.
+#[derive(Debug, Clone)]
+pub struct Site {
+    pub package: String,
+    pub modules: Vec<ModuleDoc>,
+}
+
+impl Site {
+    /// Documentation for `modules`, in name order.
+    pub fn new(package: impl Into<String>, modules: &[Module]) -> Self {
+        let mut modules: Vec<ModuleDoc> = modules.iter().map(ModuleDoc::from_module).collect();
+        modules.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            package: package.into(),
+            modules,
+        }
+    }
+
+    /// Write `index.html`, `style.css` and a page per module into `dir`.
+    /// Returns the path of the index.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("style.css"), STYLE)?;
+        let links = Links::new(&self.modules);
+        for module in &self.modules {
+            fs::write(dir.join(module.file_name()), self.module_page(module, &links))?;
+        }
+        let index = dir.join("index.html");
+        fs::write(&index, self.index_page(&links))?;
+        Ok(index)
+    }
+
+    fn page(&self, title: &str, body: &str) -> String {
+        let mut nav = String::new();
+        for module in &self.modules {
+            let _ = writeln!(nav, "<li><a href=\"{}\">{}</a></li>", module.file_name(), escape(&module.name));
+        }
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>{title}</title>\n<link rel=\"stylesheet\" href=\"style.css\">\n</head>\n<body>\n\
+             <nav>\n<h2><a href=\"index.html\">{package}</a></h2>\n<ul>\n{nav}</ul>\n</nav>\n\
+             <main>\n{body}</main>\n</body>\n</html>\n",
+            title = escape(title),
+            package = escape(&self.package),
+        )
+    }
+
+    fn index_page(&self, links: &Links) -> String {
+        let mut body = format!("<h1>{}</h1>\n<table class=\"summary\">\n", escape(&self.package));
+        for module in &self.modules {
+            let _ = writeln!(
+                body,
+                "<tr><td><a href=\"{}\">{}</a></td><td>{}</td></tr>",
+                module.file_name(),
+                escape(&module.name),
+                inline(summary(&module.doc), links, &module.name)
+            );
+        }
+        body.push_str("</table>\n");
+        self.page(&self.package, &body)
+    }
+
+    fn module_page(&self, module: &ModuleDoc, links: &Links) -> String {
+        let mut body = format!("<h1>Module <code>{}</code></h1>\n", escape(&module.name));
+        body.push_str(&markdown(&module.doc, links, &module.name));
+
+        let mut by_kind: BTreeMap<Kind, Vec<&ItemDoc>> = BTreeMap::new();
+        for item in &module.items {
+            by_kind.entry(item.kind).or_default().push(item);
+        }
+        for (kind, items) in &by_kind {
+            let _ = writeln!(body, "<h2>{}</h2>\n<table class=\"summary\">", kind.heading());
+            for item in items {
+                let _ = writeln!(
+                    body,
+                    "<tr><td><a href=\"#{}\">{}</a></td><td>{}</td></tr>",
+                    item.anchor(),
+                    escape(&item.name),
+                    inline(summary(&item.doc), links, &module.name)
+                );
+            }
+            body.push_str("</table>\n");
+        }
+        for items in by_kind.values() {
+            for item in items {
+                body.push_str(&item_section(item, links, &module.name));
+            }
+        }
+        self.page(&module.name, &body)
+    }
+}
+
+fn item_section(item: &ItemDoc, links: &Links, module: &str) -> String {
+    let mut out = format!(
+        "<section class=\"item\" id=\"{}\">\n<pre class=\"declaration\"><code>{}</code></pre>\n",
+        item.anchor(),
+        linked_declaration(&item.declaration, links, module)
+    );
+    out.push_str(&markdown(&item.doc, links, module));
+    if !item.traits.is_empty() {
+        let traits: Vec<String> = item
+            .traits
+            .iter()
+            .map(|name| match links.resolve(module, name, true) {
+                Some(url) => format!("<a href=\"{}\"><code>{}</code></a>", url, escape(name)),
+                None => format!("<code>{}</code>", escape(name)),
+            })
+            .collect();
+        let _ = writeln!(out, "<p class=\"traits\">Implements {}</p>", traits.join(", "));
+    }
+    if !item.methods.is_empty() {
+        out.push_str("<h3>Methods</h3>\n");
+        for method in &item.methods {
+            out.push_str(&item_section(method, links, module));
+        }
+    }
+    out.push_str("</section>\n");
+    out
+}
+
+/// `declaration`, escaped, with the type names in it linked.
+fn linked_declaration(declaration: &str, links: &Links, module: &str) -> String {
+    let mut out = String::new();
+    let mut rest = declaration;
+    while let Some(start) = rest.find(|c: char| c.is_alphabetic() || c == '_') {
+        out.push_str(&escape(&rest[..start]));
+        let path_len = path_len(&rest[start..]);
+        let path = &rest[start..start + path_len];
+        let is_type = path.rsplit("::").next().is_some_and(|name| name.starts_with(char::is_uppercase));
+        match links.resolve(module, path, true).filter(|_| is_type) {
+            Some(url) => {
+                let _ = write!(out, "<a href=\"{}\">{}</a>", url, escape(path));
+            }
+            None => out.push_str(&escape(path)),
+        }
+        rest = &rest[start + path_len..];
+    }
+    out.push_str(&escape(rest));
+    out
+}
+
+/// Length of the `a::b::c` path `text` starts with.
+fn path_len(text: &str) -> usize {
+    let ident = |s: &str| s.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(s.len());
+    let mut len = ident(text);
+    while let Some(next) = text[len..].strip_prefix("::") {
+        let segment = ident(next);
+        if segment == 0 {
+            break;
+        }
+        len += 2 + segment;
+    }
+    len
+}
+
+/// The first paragraph of a doc comment.
+fn summary(doc: &str) -> &str {
+    let end = doc.find("\n\n").unwrap_or(doc.len());
+    doc[..end].trim()
+}
+
+/// HTML for a doc comment: paragraphs, `#` headings, `-` and `1.` lists
+/// and fenced code, with inline code, emphasis and links.
+fn markdown(text: &str, links: &Links, module: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut list: Option<(&str, Vec<String>)> = None;
+    let mut code: Option<Vec<&str>> = None;
+
+    fn flush(out: &mut String, paragraph: &mut Vec<&str>, list: &mut Option<(&str, Vec<String>)>, links: &Links, module: &str) {
+        if !paragraph.is_empty() {
+            let _ = writeln!(out, "<p>{}</p>", inline(&paragraph.join("\n"), links, module));
+            paragraph.clear();
+        }
+        if let Some((tag, entries)) = list.take() {
+            let _ = writeln!(out, "<{}>", tag);
+            for entry in entries {
+                let _ = writeln!(out, "<li>{}</li>", inline(&entry, links, module));
+            }
+            let _ = writeln!(out, "</{}>", tag);
+        }
+    }
+
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if let Some(lines) = &mut code {
+            if trimmed.starts_with("```") {
+                let _ = writeln!(out, "{}</code></pre>", escape(&lines.join("\n")));
+                code = None;
+            } else {
+                lines.push(line);
+            }
+            continue;
+        }
+        if let Some(lang) = trimmed.strip_prefix("```") {
+            flush(&mut out, &mut paragraph, &mut list, links, module);
+            let lang = if lang.is_empty() { "dream" } else { lang };
+            let _ = write!(out, "<pre class=\"example\"><code class=\"language-{}\">", escape(lang));
+            code = Some(Vec::new());
+            continue;
+        }
+        if trimmed.is_empty() {
+            flush(&mut out, &mut paragraph, &mut list, links, module);
+            continue;
+        }
+        let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+        if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+            flush(&mut out, &mut paragraph, &mut list, links, module);
+            // Item headings are h2 and h3, so a comment's start at h4
+            let level = (hashes + 3).min(6);
+            let _ = writeln!(out, "<h{level}>{}</h{level}>", inline(trimmed[hashes..].trim(), links, module));
+            continue;
+        }
+        let bullet = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+            .map(|entry| ("ul", entry))
+            .or_else(|| {
+                let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+                let entry = trimmed[digits..].strip_prefix(". ").filter(|_| digits > 0)?;
+                Some(("ol", entry))
+            });
+        match bullet {
+            Some((tag, entry)) => match &mut list {
+                Some((open, entries)) if *open == tag => entries.push(entry.to_string()),
+                _ => {
+                    flush(&mut out, &mut paragraph, &mut list, links, module);
+                    list = Some((tag, vec![entry.to_string()]));
+                }
+            },
+            None => match &mut list {
+                // An indented line carries on the last entry
+                Some((_, entries)) if line.starts_with(' ') => {
+                    if let Some(last) = entries.last_mut() {
+                        last.push(' ');
+                        last.push_str(trimmed);
+                    }
+                }
+                _ => {
+                    if list.is_some() {
+                        flush(&mut out, &mut paragraph, &mut list, links, module);
+                    }
+                    paragraph.push(trimmed);
+                }
+            },
+        }
+    }
+    if let Some(lines) = code {
+        let _ = writeln!(out, "{}</code></pre>", escape(&lines.join("\n")));
+    }
+    flush(&mut out, &mut paragraph, &mut list, links, module);
+    out
+}
+
+/// HTML for inline markdown: `code`, **strong**, *emphasis* and
+/// [text](url). Code naming a documented item links to it.
+fn inline(text: &str, links: &Links, module: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['`', '*', '[']) {
+        out.push_str(&escape(&rest[..start]));
+        let after = &rest[start..];
+        let (html, used) = if let Some(code) = after.strip_prefix('`') {
+            match code.find('`') {
+                Some(end) => {
+                    let code = &code[..end];
+                    let html = match links.resolve(module, code, false) {
+                        Some(url) if path_len(code) == code.len() => {
+                            format!("<a href=\"{}\"><code>{}</code></a>", url, escape(code))
+                        }
+                        _ => format!("<code>{}</code>", escape(code)),
+                    };
+                    (html, end + 2)
+                }
+                None => (escape("`"), 1),
+            }
+        } else if let Some(strong) = after.strip_prefix("**") {
+            match strong.find("**") {
+                Some(end) if end > 0 => (format!("<strong>{}</strong>", inline(&strong[..end], links, module)), end + 4),
+                _ => ("**".to_string(), 2),
+            }
+        } else if let Some(em) = after.strip_prefix('*') {
+            match em.find('*') {
+                Some(end) if end > 0 && !em.starts_with(' ') => {
+                    (format!("<em>{}</em>", inline(&em[..end], links, module)), end + 2)
+                }
+                _ => ("*".to_string(), 1),
+            }
+        } else {
+            match link(after) {
+                Some((label, url, used)) => (
+                    format!("<a href=\"{}\">{}</a>", escape(url), inline(label, links, module)),
+                    used,
+                ),
+                None => ("[".to_string(), 1),
+            }
+        };
+        out.push_str(&html);
+        rest = &after[used..];
+    }
+    out.push_str(&escape(rest));
+    out
+}
+
+/// A `[label](url)` link at the start of `text`, and its length.
+fn link(text: &str) -> Option<(&str, &str, usize)> {
+    let label_end = text.find("](")?;
+    let label = &text[1..label_end];
+    let url_len = text[label_end + 2..].find(')')?;
+    let url = &text[label_end + 2..label_end + 2 + url_len];
+    (!label.contains('[') && !url.contains(char::is_whitespace)).then_some((label, url, label_end + 3 + url_len))
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 0; display: flex; line-height: 1.5; color: #222; }
+nav { width: 16em; min-height: 100vh; padding: 1em 1.5em; background: #f5f5f5; box-sizing: border-box; }
+nav ul { list-style: none; padding: 0; }
+main { padding: 1em 2em; max-width: 60em; }
+a { color: #3556a8; text-decoration: none; }
+a:hover { text-decoration: underline; }
+code, pre { font-family: monospace; }
+pre { background: #f5f5f5; padding: 0.7em 1em; overflow-x: auto; }
+pre.declaration { background: none; padding: 0; font-size: 1.05em; font-weight: bold; }
+table.summary td { padding: 0.2em 1em 0.2em 0; vertical-align: top; }
+section.item { margin: 2em 0; border-top: 1px solid #ddd; }
+section.item section.item { margin-left: 1.5em; border-top: none; }
+";
+
+/// Serve the files in `dir` at `http://127.0.0.1:<port>/` until the
+/// process is stopped.
+pub fn serve(dir: &Path, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    for stream in listener.incoming() {
+        // A client that goes away mid-request is no reason to stop
+        if let Ok(stream) = stream {
+            let _ = respond(stream, dir);
+        }
+    }
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, dir: &Path) -> io::Result<()> {
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let file = request
+        .strip_prefix("GET ")
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|target| request_path(dir, target));
+    let (status, content_type, body) = match file.and_then(|path| Some((fs::read(&path).ok()?, path))) {
+        Some((body, path)) => ("200 OK", content_type(&path), body),
+        None => ("404 Not Found", "text/plain", b"Not found\n".to_vec()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+/// The file in `dir` a request for `target` is for, or `None` if it would
+/// leave `dir`.
+fn request_path(dir: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next().unwrap_or_default().trim_start_matches('/');
+    let relative = Path::new(path);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+    let full = dir.join(relative);
+    Some(if path.is_empty() || full.is_dir() { full.join("index.html") } else { full })
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Parser;
+
+    const SOURCE: &str = "\
+//! Shapes and their areas.
+//!
+//! Start with `Point`.
+
+/// A point on the plane.
+#[derive(Debug)]
+pub struct Point {
+    x: int,
+    y: int,
+}
+
+impl Point {
+    /// The point at `x`, `y`.
+    pub fn new(x: int, y: int) -> Point {
+        Point { x: x, y: y }
+    }
+
+    fn private(self) -> int {
+        1
+    }
+}
+
+/// The area of a `w` by `h` rectangle.
+///
+/// # Example
+/// ```dream
+/// shapes::area(2, 3)   // 6
+/// ```
+pub fn area(w: int, h: int) -> int {
+    w * h
+}
+
+/// Where `p` is, as `(x, y)`. See [the guide](https://example.com).
+pub fn locate(p: Point) -> (int, int) {
+    (p.x, p.y)
+}
+
+fn hidden() -> int {
+    area(1, 1)
+}
+
+#[test]
+pub fn test_area() {
+    assert_eq!(area(2, 3), 6);
+}
+";
+
+    fn shapes() -> Module {
+        Parser::new(SOURCE).parse_file("app::shapes").unwrap()
+    }
+
+    #[test]
+    fn test_module_doc() {
+        let doc = ModuleDoc::from_module(&shapes());
+        assert_eq!(doc.doc, "Shapes and their areas.\n\nStart with `Point`.");
+        let names: Vec<(Kind, &str)> = doc.items.iter().map(|i| (i.kind, i.name.as_str())).collect();
+        assert_eq!(names, [(Kind::Struct, "Point"), (Kind::Function, "area"), (Kind::Function, "locate")]);
+
+        let point = &doc.items[0];
+        assert_eq!(point.doc, "A point on the plane.");
+        assert_eq!(point.methods.len(), 1);
+        assert_eq!(point.methods[0].anchor(), "method.Point.new");
+        assert_eq!(point.methods[0].declaration, "pub fn new(x: int, y: int) -> Point");
+        assert_eq!(point.methods[0].doc, "The point at `x`, `y`.");
+
+        let area = &doc.items[1];
+        assert_eq!(area.declaration, "pub fn area(w: int, h: int) -> int");
+        assert!(area.doc.starts_with("The area of a `w` by `h` rectangle.\n\n# Example\n```dream\n"));
+        assert_eq!(doc.file_name(), "app.shapes.html");
+    }
+
+    #[test]
+    fn test_doc_comment() {
+        let source = "/// One\n/// two\n#[inline]\npub fn f() {}\n// Not a doc\nfn g() {}\n";
+        assert_eq!(doc_comment(source, source.find("pub fn").unwrap()), "One\ntwo");
+        assert_eq!(doc_comment(source, source.find("fn g").unwrap()), "");
+        assert_eq!(module_doc("\n//! Top\n//!\n//! More\nfn f() {}\n"), "Top\n\nMore");
+    }
+
+    #[test]
+    fn test_links() {
+        let other = Parser::new("pub fn helper() -> int { 1 }\npub struct Point { z: int }\n")
+            .parse_file("app::other")
+            .unwrap();
+        let modules = [ModuleDoc::from_module(&shapes()), ModuleDoc::from_module(&other)];
+        let links = Links::new(&modules);
+
+        // The current module's own item wins
+        assert_eq!(links.resolve("app::shapes", "Point", true).as_deref(), Some("app.shapes.html#struct.Point"));
+        assert_eq!(links.resolve("app::other", "Point", true).as_deref(), Some("app.other.html#struct.Point"));
+        assert_eq!(links.resolve("app::shapes", "other::Point", true).as_deref(), Some("app.other.html#struct.Point"));
+        assert_eq!(links.resolve("app::shapes", "other", false).as_deref(), Some("app.other.html"));
+        assert_eq!(links.resolve("app::shapes", "other::helper", false).as_deref(), Some("app.other.html#fn.helper"));
+        assert_eq!(links.resolve("app::shapes", "other::helper", true), None);
+        assert_eq!(links.resolve("app::shapes", "Missing", false), None);
+
+        assert_eq!(
+            linked_declaration("pub fn locate(p: Point) -> (int, int)", &links, "app::shapes"),
+            "pub fn locate(p: <a href=\"app.shapes.html#struct.Point\">Point</a>) -&gt; (int, int)"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let links = Links::new(&[ModuleDoc::from_module(&shapes())]);
+        let html = markdown(
+            "Adds **two** *numbers*, see `area` and [docs](https://x.y).\n\n\
+             # Example\n```dream\nlet x = a < b;\n```\n- one\n- two\n  more\n\n1. first\n2. second",
+            &links,
+            "app::shapes",
+        );
+        assert_eq!(
+            html,
+            "<p>Adds <strong>two</strong> <em>numbers</em>, see \
+             <a href=\"app.shapes.html#fn.area\"><code>area</code></a> and <a href=\"https://x.y\">docs</a>.</p>\n\
+             <h4>Example</h4>\n\
+             <pre class=\"example\"><code class=\"language-dream\">let x = a &lt; b;</code></pre>\n\
+             <ul>\n<li>one</li>\n<li>two more</li>\n</ul>\n\
+             <ol>\n<li>first</li>\n<li>second</li>\n</ol>\n"
+        );
+        assert_eq!(inline("a * b and `x`", &links, "app::shapes"), "a * b and <code>x</code>");
+    }
+
+    #[test]
+    fn test_site_pages() {
+        let site = Site::new("app", &[shapes()]);
+        let links = Links::new(&site.modules);
+        let page = site.module_page(&site.modules[0], &links);
+        assert!(page.contains("<h1>Module <code>app::shapes</code></h1>"));
+        assert!(page.contains("<h2>Structs</h2>"));
+        assert!(page.contains("<section class=\"item\" id=\"fn.area\">"));
+        assert!(page.contains("<h3>Methods</h3>"));
+        assert!(!page.contains("private") && !page.contains("hidden") && !page.contains("test_area"));
+
+        let index = site.index_page(&links);
+        assert!(index.contains(
+            "<tr><td><a href=\"app.shapes.html\">app::shapes</a></td><td>Shapes and their areas.</td></tr>"
+        ));
+    }
+
+    #[test]
+    fn test_request_path() {
+        let dir = Path::new("/doc");
+        assert_eq!(request_path(dir, "/"), Some(PathBuf::from("/doc/index.html")));
+        assert_eq!(request_path(dir, "/app.html?x=1"), Some(PathBuf::from("/doc/app.html")));
+        assert_eq!(request_path(dir, "/../secret"), None);
+        assert_eq!(content_type(Path::new("style.css")), "text/css");
+    }
+}
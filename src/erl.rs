@@ -0,0 +1,24 @@
+//! Quoting for the Erlang source that the BEAM-side tools (`dream profile`,
+//! `dream trace`, `dream top` and `dream debug`) send to the node they run in.
+
+/// An Erlang string literal.
+pub(crate) fn erlang_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// A quoted Erlang atom.
+pub(crate) fn quote_atom(name: &str) -> String {
+    format!("'{}'", name.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(erlang_string(r#"C:\dir "x""#), r#""C:\\dir \"x\"""#);
+        assert_eq!(quote_atom("dream::app"), "'dream::app'");
+        assert_eq!(quote_atom("it's"), "'it\\'s'");
+    }
+}
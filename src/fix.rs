@@ -0,0 +1,114 @@
+//! Fixes for `dream fix`: the edits the compiler's warnings and the lints
+//! suggest that are certain to be right, such as removing an unused import
+//! or replacing a call to a deprecated function with the one its
+//! `#[deprecated(suggestion = "..")]` names.
+//!
+//! Guesses, like the "did you mean" of a misspelt name, are left for a
+//! person to apply from the diagnostic.
+
+use crate::analysis::Location;
+use crate::compiler::{LintDiagnostic, Suggestion, Warning};
+use crate::refactor::Edit;
+use std::collections::BTreeMap;
+
+/// The machine-applicable suggestions of `warnings` and `lints`, as edits
+/// by module, each module's in source order. Of edits that overlap only
+/// the first is kept; applying it and checking again finds the others if
+/// they still apply.
+pub fn fixes(warnings: &[Warning], lints: &[LintDiagnostic]) -> BTreeMap<String, Vec<Edit>> {
+    let warnings = warnings
+        .iter()
+        .filter_map(|warning| Some((warning.module.as_deref()?, &warning.annotations.suggestions)));
+    let lints = lints.iter().map(|lint| (lint.module.as_str(), &lint.suggestions));
+
+    let mut fixes: BTreeMap<String, Vec<Edit>> = BTreeMap::new();
+    for (module, suggestions) in warnings.chain(lints) {
+        for suggestion in suggestions.iter().filter(|s| s.is_machine_applicable()) {
+            fixes.entry(module.to_string()).or_default().push(edit(module, suggestion));
+        }
+    }
+    for edits in fixes.values_mut() {
+        edits.sort_by_key(|edit| (edit.location.span.start, edit.location.span.end));
+        let mut kept: Vec<Edit> = Vec::with_capacity(edits.len());
+        for edit in edits.drain(..) {
+            let span = &edit.location.span;
+            // Two insertions at the same place have no order to go in
+            let overlaps = kept.last().is_some_and(|last| {
+                span.start < last.location.span.end || span.start == last.location.span.start
+            });
+            if !overlaps {
+                kept.push(edit);
+            }
+        }
+        *edits = kept;
+    }
+    fixes
+}
+
+fn edit(module: &str, suggestion: &Suggestion) -> Edit {
+    Edit {
+        location: Location {
+            module: module.to_string(),
+            span: suggestion.span.clone(),
+        },
+        text: suggestion.replacement.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{check_modules_with_levels, LintLevels, Linter, Parser, WarningLevels};
+    use crate::refactor::apply;
+
+    #[test]
+    fn test_fixes() {
+        let source = "use std::io;\n\n\
+                      #[deprecated(suggestion = \"twice\")]\n\
+                      fn double(n: int) -> int {\n    n * 2\n}\n\n\
+                      fn twice(n: int) -> int {\n    n * 2\n}\n\n\
+                      fn main(n: int) -> int {\n    let _ = \"double\";\n    double(n) + double(1)\n}\n";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let modules = [module];
+        let checked = check_modules_with_levels(&modules, &WarningLevels::default());
+        let lints = Linter::new(&LintLevels::new()).lint_modules(&modules);
+
+        // Each use of `double` is replaced where it is written, and the
+        // one in the string is left alone
+        let fixes = fixes(&checked.warnings, &lints);
+        assert_eq!(fixes.keys().collect::<Vec<_>>(), ["app"]);
+        assert_eq!(
+            apply(source, &fixes["app"]),
+            "#[deprecated(suggestion = \"twice\")]\n\
+             fn double(n: int) -> int {\n    n * 2\n}\n\n\
+             fn twice(n: int) -> int {\n    n * 2\n}\n\n\
+             fn main(n: int) -> int {\n    let _ = \"double\";\n    twice(n) + twice(1)\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_overlapping_fixes() {
+        let suggestion = |span: std::ops::Range<usize>, replacement: &str| Suggestion {
+            span,
+            replacement: replacement.to_string(),
+            message: String::new(),
+            applicability: crate::compiler::Applicability::MachineApplicable,
+        };
+        let mut warning = Warning::new("w").in_module("app".to_string());
+        warning.annotations.suggestions = vec![
+            suggestion(4..8, "b"),
+            suggestion(0..2, "a"),
+            suggestion(6..10, "c"),
+            suggestion(8..8, "d"),
+            suggestion(8..8, "e"),
+        ];
+        let mut guess = warning.clone();
+        guess.annotations.suggestions[0].applicability = crate::compiler::Applicability::MaybeIncorrect;
+
+        let texts = |warnings: &[Warning]| -> Vec<String> {
+            fixes(warnings, &[])["app"].iter().map(|edit| edit.text.clone()).collect()
+        };
+        assert_eq!(texts(&[warning]), ["a", "b", "d"]);
+        assert_eq!(texts(&[guess]), ["a", "c"]);
+    }
+}
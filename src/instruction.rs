@@ -1,6 +1,6 @@
 //! Bytecode instructions for the VM.
 
-use crate::Pid;
+use crate::{OverflowPolicy, Pid, Priority};
 
 // ========== Bit Syntax Types ==========
 
@@ -132,6 +132,19 @@ pub enum SegmentSource {
     Reg(Register),
 }
 
+/// Options for spawning a process with `SpawnOpt`
+#[derive(Debug, Clone, Default)]
+pub struct SpawnOpts {
+    /// Link the child to the spawning process
+    pub link: bool,
+    /// Monitor the child from the spawning process
+    pub monitor: bool,
+    /// Label shown in process info
+    pub label: Option<String>,
+    /// Scheduling priority of the child
+    pub priority: Priority,
+}
+
 /// Bytecode instructions
 #[derive(Debug, Clone)]
 pub enum Instruction {
@@ -147,6 +160,20 @@ pub enum Instruction {
     /// Spawn a new process and atomically link to it
     SpawnLink { code: Vec<Instruction>, dest: Register },
 
+    /// Spawn a new process with options, atomically linking/monitoring it
+    /// Stores the child PID in dest, or {Pid, Ref} when monitoring
+    SpawnOpt {
+        code: Vec<Instruction>,
+        opts: SpawnOpts,
+        dest: Register,
+    },
+
+    /// Set this process's label
+    SetLabel { label: String },
+
+    /// Set this process's scheduling priority
+    SetPriority { priority: Priority },
+
     /// Send a message to a process
     Send { to: Source, msg: String },
 
@@ -196,6 +223,10 @@ pub enum Instruction {
     /// When false (default), abnormal exit signals kill this process
     TrapExit { enable: bool },
 
+    /// Bound this process's mailbox to `max` messages (0 removes the limit)
+    /// Sends to a full mailbox are handled according to `policy`
+    SetMailboxLimit { max: u32, policy: OverflowPolicy },
+
     // ========== Arithmetic & Logic ==========
     /// Load an immediate integer into a register
     LoadInt { value: i64, dest: Register },
@@ -644,6 +675,15 @@ pub enum Instruction {
         dest: Register,
     },
 
+    /// Send a message to a process every `interval` reductions until cancelled
+    /// Returns a timer reference in dest for CancelTimer/ReadTimer
+    SendInterval {
+        interval: u32,
+        to: Source,
+        msg: Register,
+        dest: Register,
+    },
+
     /// Start a timer that sends {:timeout, ref, msg} to self after delay
     /// Returns the timer reference in dest
     StartTimer { delay: u32, msg: Register, dest: Register },
@@ -697,6 +737,14 @@ pub enum Instruction {
     /// Returns {status, mailbox_len, links_count, monitors_count, trap_exit}
     ProcessInfo { pid: Register, dest: Register },
 
+    /// Get a single process info item as {item, value}, or :undefined
+    /// Items: message_queue_len, reductions, status, trap_exit, label, priority
+    ProcessInfoItem {
+        pid: Register,
+        item: String,
+        dest: Register,
+    },
+
     /// Get list of loaded module names
     ModuleList { dest: Register },
 
@@ -9,17 +9,42 @@
 //! - Process monitors (one-way crash notification)
 //! - Process registry for named processes
 //! - Receive with timeout
-//! - Cooperative scheduling with reduction budgets
+//! - Preemptive scheduling with per-process reduction budgets
 
+pub mod analysis;
+pub mod bench;
 pub mod bindgen;
+pub mod code_path;
 pub mod compiler;
 pub mod config;
+pub mod coverage;
+pub mod debugger;
 pub mod deps;
+pub mod doc;
+mod erl;
+pub mod fix;
+pub mod lockfile;
+pub mod lsp;
+mod markup;
+pub mod output;
+pub mod profile;
+pub mod refactor;
+pub mod sarif;
+pub mod target;
+pub mod test_report;
+pub mod testing;
+pub mod timings;
+pub mod toolchain;
+pub mod top;
+pub mod trace;
+pub mod watch;
 mod instruction;
 mod message;
 mod module;
+pub mod native;
 mod pid;
 mod process;
+mod reference;
 mod scheduler;
 mod value;
 
@@ -29,11 +54,14 @@ pub mod wasm;
 // Re-export public API
 pub use instruction::{
     BitSegment, BitType, Endianness, Instruction, Operand, Pattern, Register, SegmentSource,
-    Signedness, Source,
+    Signedness, Source, SpawnOpts,
 };
 pub use message::{Message, SystemMsg};
 pub use module::{FunctionDef, Module};
 pub use pid::Pid;
-pub use process::{CallFrame, Process, ProcessStatus, TryFrame};
-pub use scheduler::{Scheduler, StepResult};
+pub use process::{
+    CallFrame, MailboxLimit, OverflowPolicy, Priority, Process, ProcessStatus, TryFrame,
+};
+pub use reference::Ref;
+pub use scheduler::{Scheduler, StepResult, REDUCTIONS_PER_SLICE};
 pub use value::Value;
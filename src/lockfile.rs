@@ -0,0 +1,288 @@
+//! The `dream.lock` lockfile.
+//!
+//! `dream deps get` records the exact version and checksum of every hex
+//! package and the commit of every git dependency, transitive ones
+//! included. Later fetches reuse those instead of resolving requirements
+//! again, so every checkout of a project builds against the same tree.
+//! With `--locked`, any change the lockfile would need is an error.
+
+use crate::config::{Dependency, ProjectConfig};
+use crate::deps::{requirement_matches, DepsError, DepsResult, HexVersion};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File name of the lockfile, next to dream.toml.
+pub const LOCKFILE_NAME: &str = "dream.lock";
+
+/// Current lockfile format version.
+const FORMAT_VERSION: u32 = 1;
+
+const HEADER: &str = "# This file is generated by `dream deps get`. Do not edit it by hand.\n";
+
+/// Where a locked package comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Hex,
+    Git,
+    Path,
+}
+
+/// One resolved package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub source: Source,
+    /// Exact release (hex)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// SHA-256 of the release tarball (hex)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// Repository URL (git)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    /// Checked out commit (git)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// Path as written in dream.toml (path)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl LockedPackage {
+    /// A hex release.
+    pub fn hex(name: &str, version: &str, checksum: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            source: Source::Hex,
+            version: Some(version.to_string()),
+            checksum,
+            git: None,
+            rev: None,
+            path: None,
+        }
+    }
+
+    /// A git dependency checked out at `rev`.
+    pub fn git(name: &str, url: &str, rev: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            source: Source::Git,
+            version: None,
+            checksum: None,
+            git: Some(url.to_string()),
+            rev: Some(rev.to_string()),
+            path: None,
+        }
+    }
+
+    /// A local path dependency.
+    pub fn path(name: &str, path: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            source: Source::Path,
+            version: None,
+            checksum: None,
+            git: None,
+            rev: None,
+            path: Some(path.to_string()),
+        }
+    }
+
+    /// Whether this entry still satisfies the dream.toml declaration.
+    pub fn satisfies(&self, dep: &Dependency) -> bool {
+        if let Some(url) = dep.git_url() {
+            self.source == Source::Git && self.git.as_deref() == Some(url)
+        } else if let Some(path) = dep.path() {
+            self.source == Source::Path && self.path.as_deref() == Some(path)
+        } else {
+            let locked = self.version.as_deref().and_then(HexVersion::parse);
+            match (dep.version(), locked) {
+                (Some(req), Some(version)) => {
+                    self.source == Source::Hex && requirement_matches(req, &version)
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+/// The parsed contents of dream.lock.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    /// Packages sorted by name.
+    #[serde(default, rename = "package")]
+    pub packages: Vec<LockedPackage>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            packages: Vec::new(),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Path of the lockfile for a project.
+    pub fn path(project_root: &Path) -> PathBuf {
+        project_root.join(LOCKFILE_NAME)
+    }
+
+    /// Read a project's lockfile, or `None` if it has none yet.
+    pub fn read(project_root: &Path) -> DepsResult<Option<Self>> {
+        let path = Self::path(project_root);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| DepsError::new(format!("Failed to read {}: {}", path.display(), e)))?;
+        Self::parse(&content)
+            .map(Some)
+            .map_err(|e| DepsError::new(format!("Failed to parse {}: {}", path.display(), e)))
+    }
+
+    /// Parse lockfile contents.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let lock: Self = toml::from_str(content).map_err(|e| e.to_string())?;
+        if lock.version > FORMAT_VERSION {
+            return Err(format!(
+                "lockfile version {} is newer than this dream supports ({})",
+                lock.version, FORMAT_VERSION
+            ));
+        }
+        Ok(lock)
+    }
+
+    /// Render the lockfile as TOML.
+    pub fn to_toml(&self) -> String {
+        let body = toml::to_string(self).expect("lockfile is always serializable");
+        format!("{}{}", HEADER, body)
+    }
+
+    /// Write the lockfile into a project.
+    pub fn write(&self, project_root: &Path) -> DepsResult<()> {
+        let path = Self::path(project_root);
+        fs::write(&path, self.to_toml())
+            .map_err(|e| DepsError::new(format!("Failed to write {}: {}", path.display(), e)))
+    }
+
+    /// Look up a package by name.
+    pub fn get(&self, name: &str) -> Option<&LockedPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// Add or replace a package, keeping entries sorted.
+    pub fn insert(&mut self, package: LockedPackage) {
+        self.packages.retain(|p| p.name != package.name);
+        let at = self
+            .packages
+            .partition_point(|p| p.name < package.name);
+        self.packages.insert(at, package);
+    }
+
+    /// Check that every dependency in dream.toml is locked and that the
+    /// locked entry still satisfies its declaration.
+    pub fn verify(&self, config: &ProjectConfig) -> DepsResult<()> {
+        self.verify_dependencies(&config.dependencies)
+    }
+
+    /// [`Lockfile::verify`] against a set of dependencies, such as all of
+    /// a workspace's.
+    pub fn verify_dependencies(&self, dependencies: &HashMap<String, Dependency>) -> DepsResult<()> {
+        let mut names: Vec<&String> = dependencies.keys().collect();
+        names.sort();
+        for name in names {
+            let dep = &dependencies[name];
+            match self.get(name) {
+                None => {
+                    return Err(DepsError::new(format!(
+                        "{} is not in {}; run `dream deps get` to update it",
+                        name, LOCKFILE_NAME
+                    )))
+                }
+                Some(locked) if !locked.satisfies(dep) => {
+                    return Err(DepsError::new(format!(
+                        "{} in {} no longer matches dream.toml; run `dream deps get` to update it",
+                        name, LOCKFILE_NAME
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(deps: &str) -> ProjectConfig {
+        let content = format!(
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"\n\n[dependencies]\n{}",
+            deps
+        );
+        toml::from_str(&content).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut lock = Lockfile::default();
+        lock.insert(LockedPackage::hex("jason", "1.4.4", Some("abc123".to_string())));
+        lock.insert(LockedPackage::git("widgets", "https://example.com/w.git", "deadbeef"));
+        lock.insert(LockedPackage::path("utils", "../utils"));
+
+        let text = lock.to_toml();
+        assert!(text.starts_with("# This file is generated"));
+        assert!(text.contains("[[package]]"));
+        assert!(!text.contains("path = \"\""));
+
+        let parsed = Lockfile::parse(&text).unwrap();
+        assert_eq!(parsed, lock);
+        let names: Vec<&str> = parsed.packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["jason", "utils", "widgets"]);
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut lock = Lockfile::default();
+        lock.insert(LockedPackage::hex("jason", "1.4.3", None));
+        lock.insert(LockedPackage::hex("jason", "1.4.4", None));
+        assert_eq!(lock.packages.len(), 1);
+        assert_eq!(lock.get("jason").unwrap().version.as_deref(), Some("1.4.4"));
+    }
+
+    #[test]
+    fn test_rejects_newer_format() {
+        let err = Lockfile::parse("version = 99\n").unwrap_err();
+        assert!(err.contains("newer"), "{}", err);
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut lock = Lockfile::default();
+        lock.insert(LockedPackage::hex("jason", "1.4.4", None));
+        lock.insert(LockedPackage::git("widgets", "https://example.com/w.git", "deadbeef"));
+
+        let cfg = config("jason = \"~> 1.4\"\nwidgets = { git = \"https://example.com/w.git\" }\n");
+        assert!(lock.verify(&cfg).is_ok());
+
+        // Requirement bumped past the locked version
+        let cfg = config("jason = \"~> 1.5\"\n");
+        assert!(lock.verify(&cfg).unwrap_err().message.contains("no longer matches"));
+
+        // New dependency not yet locked
+        let cfg = config("plug = \"~> 1.0\"\n");
+        assert!(lock.verify(&cfg).unwrap_err().message.contains("not in dream.lock"));
+
+        // Source changed from hex to path
+        let cfg = config("jason = { path = \"../jason\" }\n");
+        assert!(lock.verify(&cfg).is_err());
+    }
+}
@@ -0,0 +1,1072 @@
+//! `dream lsp`: a Language Server Protocol server over stdio.
+//!
+//! The editor sends each edit to an open file, which the server applies to
+//! the file's [`Text`] and re-lexes only around, with a [`TokenCache`]. The
+//! parser then reads the whole file again from those tokens; only lexing
+//! is incremental. The server keeps every module of the project parsed, in a query [`Database`]
+//! that after each edit re-checks only the open files the edited module
+//! affects, following the same dependency graph that incremental builds
+//! use, and publishes their diagnostics. Definitions, hovers, document symbols and
+//! completions are answered by an [`Analysis`] of the project, renames by
+//! [`refactor::rename`], and formatting from [`format_source`]. Suggested
+//! fixes ride along in each published diagnostic's `data`, and code
+//! action requests turn the ones the editor sends back into quick fixes.
+
+use crate::analysis::{self, Analysis, Location, SymbolKind, is_ident_char};
+use crate::compiler::{
+    Database, Module, ModuleCheck, ModuleLoader, Parser, Text, TokenCache, format_source, ice, typeck::FnInfo,
+};
+use crate::config::{FeatureSelection, ProjectConfig};
+use crate::output::{Diagnostic, Level};
+use crate::refactor;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// JSON-RPC error code for requests the server does not implement.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for a request the server failed on.
+const INTERNAL_ERROR: i64 = -32603;
+/// LSP error code for requests that are valid but cannot be carried out.
+const REQUEST_FAILED: i64 = -32803;
+
+// LSP symbol and completion kinds
+const SYMBOL_METHOD: u8 = 6;
+const SYMBOL_ENUM: u8 = 10;
+const SYMBOL_INTERFACE: u8 = 11;
+const SYMBOL_FUNCTION: u8 = 12;
+const SYMBOL_STRUCT: u8 = 23;
+const SYMBOL_TYPE_PARAMETER: u8 = 26;
+const COMPLETION_FUNCTION: u8 = 3;
+const COMPLETION_MODULE: u8 = 9;
+
+/// Result of a request: the `result` value, or an error code and message.
+type Reply = Result<Value, (i64, String)>;
+
+/// Read one message. Returns `None` at the end of input.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length")
+    })?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Write one message with its header.
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Answer messages from `reader` until the client sends `exit`. `context`
+/// holds modules that the project is checked against without being part
+/// of it: the stdlib and FFI stubs.
+pub fn serve(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    context: Vec<Module>,
+) -> io::Result<()> {
+    let mut server = Server::new(context);
+    while let Some(message) = read_message(&mut reader)? {
+        if message["method"] == "exit" {
+            break;
+        }
+        // A panic in the compiler fails the request rather than the server
+        let replies = ice::catch(|| server.handle(&message)).unwrap_or_else(|error| {
+            let failed = message
+                .get("id")
+                .map(|id| response(id.clone(), Err((INTERNAL_ERROR, error.to_string()))));
+            // The client shows the server's stderr in its log
+            eprintln!("{:?}", miette::Report::new(*error));
+            failed.into_iter().collect()
+        });
+        for reply in replies {
+            write_message(&mut writer, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+/// A file open in the editor.
+struct Document {
+    /// The text, which the editor's edits are applied to
+    buffer: Text,
+    tokens: TokenCache,
+    /// The module the file holds
+    module: String,
+    /// Set while the text does not parse, in which case the last version
+    /// that did stands in for the module
+    parse_error: bool,
+}
+
+/// The state of one editor session.
+pub struct Server {
+    /// Project modules, as on disk or as last edited, and the function
+    /// signatures from the last check
+    analysis: Analysis,
+    src_dir: Option<PathBuf>,
+    /// Open files by URI
+    documents: HashMap<String, Document>,
+    /// The same modules, with their diagnostics as of the last check
+    queries: Database,
+}
+
+impl Server {
+    pub fn new(context: Vec<Module>) -> Self {
+        Self {
+            analysis: Analysis::new(context.clone()),
+            src_dir: None,
+            documents: HashMap::new(),
+            queries: Database::new(context),
+        }
+    }
+
+    /// Handle one request or notification, returning the messages to send
+    /// back: the response to a request, and any diagnostics to publish.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let id = message.get("id").cloned();
+
+        let result = match method {
+            "initialize" => Ok(self.initialize(params)),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/didOpen" => return self.did_open(params),
+            "textDocument/didChange" => return self.did_change(params),
+            "textDocument/didClose" => return self.did_close(params),
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/documentSymbol" => Ok(self.document_symbols(params)),
+            "textDocument/completion" => Ok(self.completion(params)),
+            "textDocument/formatting" => Ok(self.formatting(params)),
+            "textDocument/rename" => self.rename(params),
+            "textDocument/codeAction" => Ok(code_actions(params)),
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method `{}`", method))),
+        };
+
+        // Notifications get no response, not even an error
+        let Some(id) = id else {
+            return Vec::new();
+        };
+        vec![response(id, result)]
+    }
+
+    fn initialize(&mut self, params: &Value) -> Value {
+        let root = params["rootUri"]
+            .as_str()
+            .and_then(uri_to_path)
+            .or_else(|| params["rootPath"].as_str().map(PathBuf::from));
+        if let Some(root) = root {
+            self.load_project(&root);
+        }
+
+        json!({
+            "capabilities": {
+                // Only the edited range on each change
+                "textDocumentSync": 2,
+                "definitionProvider": true,
+                "hoverProvider": true,
+                "documentSymbolProvider": true,
+                "completionProvider": { "triggerCharacters": [":"] },
+                "documentFormattingProvider": true,
+                "renameProvider": true,
+                "codeActionProvider": { "codeActionKinds": ["quickfix"] },
+            },
+            "serverInfo": { "name": "dream", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    /// Parse every module of the project at `root`, if there is one.
+    fn load_project(&mut self, root: &Path) {
+        let Ok(config) = ProjectConfig::load(&root.join("dream.toml")) else {
+            return;
+        };
+        let src_dir = config.src_dir(root);
+        let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+        loader.add_bindings_dir(config.target_layout(root).bindings_dir());
+        // A file that does not parse is reported once it is opened
+        let _ = loader.load_all_in_dir(&src_dir);
+
+        for module in loader.into_modules() {
+            self.queries.set_module(module.clone());
+            self.analysis.insert(module);
+        }
+        self.queries.set_warning_levels(config.warning_levels().unwrap_or_default());
+        // The default features, with tests compiled in so that they are
+        // checked too
+        if let Ok(options) = config.compile_options(root, &FeatureSelection::default(), "dev", true) {
+            self.queries.set_options(options);
+        }
+        self.queries.set_package(Some(config.package.name.clone()));
+        self.analysis.set_package(Some(config.package.name));
+        self.src_dir = Some(src_dir);
+    }
+
+    fn did_open(&mut self, params: &Value) -> Vec<Value> {
+        let document = &params["textDocument"];
+        match (document["uri"].as_str(), document["text"].as_str()) {
+            (Some(uri), Some(text)) => {
+                let buffer = Text::new(text);
+                let tokens = TokenCache::new(&buffer);
+                self.update(uri, buffer, tokens)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Apply each change to `uri` in turn: a range and the text replacing
+    /// it, or without a range the whole new text.
+    fn did_change(&mut self, params: &Value) -> Vec<Value> {
+        let (Some(uri), Some(changes)) = (
+            params["textDocument"]["uri"].as_str(),
+            params["contentChanges"].as_array(),
+        ) else {
+            return Vec::new();
+        };
+        let (mut buffer, mut tokens) = self
+            .documents
+            .get_mut(uri)
+            .map(|document| (std::mem::take(&mut document.buffer), std::mem::take(&mut document.tokens)))
+            .unwrap_or_default();
+        for change in changes {
+            let Some(text) = change["text"].as_str() else {
+                continue;
+            };
+            let range = &change["range"];
+            if range.is_null() {
+                buffer = Text::new(text);
+                tokens = TokenCache::new(&buffer);
+                continue;
+            }
+            let start = offset_in(buffer.chunks(), &range["start"]);
+            let end = offset_in(buffer.chunks(), &range["end"]);
+            // A range past the end of the text is out of date; skip it
+            if let Some((start, end)) = start.zip(end).filter(|(start, end)| start <= end) {
+                buffer.replace(start..end, text);
+                tokens.edit(&buffer, start..end, text.len());
+            }
+        }
+        self.update(uri, buffer, tokens)
+    }
+
+    fn did_close(&mut self, params: &Value) -> Vec<Value> {
+        let Some(uri) = params["textDocument"]["uri"].as_str() else {
+            return Vec::new();
+        };
+        let Some(document) = self.documents.remove(uri) else {
+            return Vec::new();
+        };
+
+        // Unsaved edits are dropped, so go back to the file on disk
+        let mut replies = vec![publish(uri, &Text::default(), &[])];
+        let on_disk = uri_to_path(uri).and_then(|path| fs::read_to_string(path).ok());
+        if let Some(Ok(mut module)) =
+            on_disk.map(|text| Parser::new(&text).parse_file(&document.module))
+        {
+            module.source_path = uri_to_path(uri);
+            let name = module.name.clone();
+            self.queries.set_module(module.clone());
+            self.analysis.insert(module);
+            replies.extend(self.check(&name));
+        }
+        replies
+    }
+
+    /// Take the new text of `uri`, already lexed, and re-check.
+    fn update(&mut self, uri: &str, buffer: Text, tokens: TokenCache) -> Vec<Value> {
+        let name = self.module_name(uri);
+        let text = buffer.to_string();
+        let mut parser = Parser::with_tokens(&text, tokens.tokens().to_vec());
+        match parser.parse_file(&name) {
+            Ok(mut module) => {
+                module.source_path = uri_to_path(uri);
+                let name = module.name.clone();
+                self.queries.set_module(module.clone());
+                self.analysis.insert(module);
+                self.documents.insert(
+                    uri.to_string(),
+                    Document {
+                        buffer,
+                        tokens,
+                        module: name.clone(),
+                        parse_error: false,
+                    },
+                );
+                self.check(&name)
+            }
+            Err(_) => {
+                let diagnostics: Vec<Diagnostic> = parser
+                    .errors()
+                    .iter()
+                    .map(|e| Diagnostic::from_parse_error(&name, e, &text))
+                    .collect();
+                let reply = publish(uri, &buffer, &diagnostics);
+                self.documents.insert(
+                    uri.to_string(),
+                    Document {
+                        buffer,
+                        tokens,
+                        module: name,
+                        parse_error: true,
+                    },
+                );
+                vec![reply]
+            }
+        }
+    }
+
+    /// The module held by the file at `uri`.
+    fn module_name(&self, uri: &str) -> String {
+        if let Some(document) = self.documents.get(uri) {
+            return document.module.clone();
+        }
+        let Some(path) = uri_to_path(uri) else {
+            return "main".to_string();
+        };
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if let Some(module) = self
+            .analysis
+            .modules()
+            .find(|m| m.source_path.as_deref() == Some(canonical.as_path()))
+        {
+            return module.name.clone();
+        }
+        match (self.analysis.package(), &self.src_dir) {
+            (Some(package), Some(src_dir)) => {
+                ModuleLoader::with_package(package.to_string(), src_dir.clone())
+                    .derive_module_name(&path)
+            }
+            _ => ModuleLoader::new().derive_module_name(&path),
+        }
+    }
+
+    /// Bring the checks of the open files up to date, and publish
+    /// diagnostics for those that were out of date, and for the files of
+    /// the `edited` module, which may have had syntax errors showing.
+    fn check(&mut self, edited: &str) -> Vec<Value> {
+        let open: Vec<&str> = self
+            .documents
+            .values()
+            .filter(|document| !document.parse_error)
+            .map(|document| document.module.as_str())
+            .collect();
+        let mut checks: HashMap<String, Rc<ModuleCheck>> = self.queries.check(open).into_iter().collect();
+        if let Some(check) = self.queries.diagnostics(edited) {
+            checks.entry(edited.to_string()).or_insert(check);
+        }
+        self.analysis.set_functions(self.queries.functions().clone());
+
+        let mut replies = Vec::new();
+        for (uri, document) in &self.documents {
+            let Some(check) = checks.get(&document.module).filter(|_| !document.parse_error) else {
+                continue;
+            };
+            // The text the check ran on, as the document parsed
+            let text = self.analysis.module(&document.module).and_then(|m| m.source.as_deref());
+            let diagnostics: Vec<Diagnostic> = check
+                .warnings
+                .iter()
+                .map(|w| Diagnostic::from_warning(w, text))
+                .chain(check.errors.iter().map(|e| Diagnostic::from_type_error(&document.module, e, text)))
+                .collect();
+            replies.push(publish(uri, &document.buffer, &diagnostics));
+        }
+        replies
+    }
+
+    /// The open document of a position request and the byte offset of
+    /// the position in it.
+    fn position<'a>(&'a self, params: &Value) -> Option<(&'a Document, usize)> {
+        let document = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        let offset = offset_in(document.buffer.chunks(), &params["position"])?;
+        Some((document, offset))
+    }
+
+    /// The whole document, formatted, as a single edit. A document that
+    /// does not parse is left alone.
+    fn formatting(&self, params: &Value) -> Value {
+        let Some(document) = params["textDocument"]["uri"]
+            .as_str()
+            .and_then(|uri| self.documents.get(uri))
+        else {
+            return Value::Null;
+        };
+        let text = document.buffer.to_string();
+        match format_source(&text) {
+            Ok(formatted) if formatted == text => json!([]),
+            Ok(formatted) => json!([{ "range": range([text.as_str()], 0..text.len()), "newText": formatted }]),
+            Err(_) => Value::Null,
+        }
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let Some((document, offset)) = self.position(params) else {
+            return Value::Null;
+        };
+        self.analysis
+            .symbol_at(&document.module, offset)
+            .and_then(|symbol| self.analysis.definition(&symbol))
+            .and_then(|location| self.location(&location))
+            .unwrap_or(Value::Null)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let Some((document, offset)) = self.position(params) else {
+            return Value::Null;
+        };
+        match self.analysis.type_at(&document.module, offset) {
+            Some(contents) => json!({
+                "contents": { "kind": "markdown", "value": format!("```dream\n{}\n```", contents) },
+            }),
+            None => Value::Null,
+        }
+    }
+
+    fn document_symbols(&self, params: &Value) -> Value {
+        let Some(uri) = params["textDocument"]["uri"].as_str() else {
+            return Value::Null;
+        };
+        let Some(document) = self.documents.get(uri) else {
+            return Value::Null;
+        };
+        let symbols: Vec<Value> = self
+            .analysis
+            .document_symbols(&document.module)
+            .into_iter()
+            .map(|symbol| {
+                let kind = match symbol.kind {
+                    SymbolKind::Function => SYMBOL_FUNCTION,
+                    SymbolKind::Method => SYMBOL_METHOD,
+                    SymbolKind::Struct => SYMBOL_STRUCT,
+                    SymbolKind::Enum => SYMBOL_ENUM,
+                    SymbolKind::Trait => SYMBOL_INTERFACE,
+                    SymbolKind::TypeAlias => SYMBOL_TYPE_PARAMETER,
+                };
+                json!({
+                    "name": symbol.name,
+                    "kind": kind,
+                    "location": { "uri": uri, "range": range(document.buffer.chunks(), symbol.span) },
+                })
+            })
+            .collect();
+        json!(symbols)
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let Some((document, offset)) = self.position(params) else {
+            return json!([]);
+        };
+        let before = document.buffer.slice(0..offset);
+        let start = before
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !is_path_char(c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+
+        // `module::` completes the module's functions; anything else the
+        // current module's functions and the modules in reach
+        let items = match before[start..].rsplit_once("::") {
+            Some((path, _)) => match self.analysis.find_module(&document.module, path) {
+                Some(module) => self.function_completions(module, module.name == document.module),
+                None => Vec::new(),
+            },
+            None => {
+                let mut items = match self.analysis.module(&document.module) {
+                    Some(module) => self.function_completions(module, true),
+                    None => Vec::new(),
+                };
+                items.extend(self.module_completions());
+                items
+            }
+        };
+        json!(items)
+    }
+
+    fn function_completions(&self, module: &Module, private: bool) -> Vec<Value> {
+        analysis::functions(module)
+            .filter(|f| private || f.is_pub)
+            .map(|f| {
+                let detail = self.analysis.signature(&module.name, &f.name).map(FnInfo::signature);
+                json!({ "label": f.name, "kind": COMPLETION_FUNCTION, "detail": detail })
+            })
+            .collect()
+    }
+
+    /// Names of the project's and the stdlib's top-level modules, as code
+    /// refers to them.
+    fn module_completions(&self) -> Vec<Value> {
+        let names: BTreeSet<&str> = self
+            .analysis
+            .modules()
+            .filter_map(|module| match self.analysis.package() {
+                Some(package) => module.name.strip_prefix(package)?.strip_prefix("::"),
+                None => Some(module.name.as_str()),
+            })
+            .chain(
+                self.analysis
+                    .context()
+                    .iter()
+                    .filter_map(|m| m.name.strip_prefix("dream::")),
+            )
+            .map(|name| name.split("::").next().unwrap_or(name))
+            .collect();
+        names
+            .into_iter()
+            .map(|name| json!({ "label": name, "kind": COMPLETION_MODULE }))
+            .collect()
+    }
+
+    /// Rename the symbol at the position everywhere the project refers
+    /// to it, as a workspace edit.
+    fn rename(&self, params: &Value) -> Reply {
+        let symbol = self
+            .position(params)
+            .and_then(|(document, offset)| self.analysis.symbol_at(&document.module, offset))
+            .ok_or_else(|| (REQUEST_FAILED, "no function, type or variable to rename here".to_string()))?;
+        let new_name = params["newName"].as_str().unwrap_or_default();
+        let rename = refactor::rename(&self.analysis, &symbol, new_name).map_err(|e| (REQUEST_FAILED, e))?;
+
+        let mut changes = serde_json::Map::new();
+        for (module, edits) in &rename.edits {
+            let Some((uri, chunks)) = self.file(module) else {
+                continue;
+            };
+            let edits: Vec<Value> = edits
+                .iter()
+                .map(|edit| json!({ "range": range(chunks.iter().copied(), edit.location.span.clone()), "newText": edit.text }))
+                .collect();
+            changes.insert(uri, json!(edits));
+        }
+        Ok(json!({ "changes": changes }))
+    }
+
+    /// An LSP location, in the file holding the module and its current
+    /// text.
+    fn location(&self, location: &Location) -> Option<Value> {
+        let (uri, chunks) = self.file(&location.module)?;
+        Some(json!({ "uri": uri, "range": range(chunks, location.span.clone()) }))
+    }
+
+    /// The URI and current text of the file holding `module`, in chunks.
+    fn file(&self, module: &str) -> Option<(String, Vec<&str>)> {
+        if let Some((uri, document)) = self.documents.iter().find(|(_, d)| d.module == module) {
+            return Some((uri.clone(), document.buffer.chunks().collect()));
+        }
+        let module = self.analysis.module(module)?;
+        Some((
+            path_to_uri(module.source_path.as_deref()?),
+            vec![module.source.as_deref()?],
+        ))
+    }
+}
+
+fn response(id: Value, result: Reply) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    }
+}
+
+/// A `publishDiagnostics` notification replacing the diagnostics of `uri`,
+/// whose text is `text`.
+fn publish(uri: &str, text: &Text, diagnostics: &[Diagnostic]) -> Value {
+    let diagnostics: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            // Errors without a span of their own still point at a label
+            let span = diagnostic
+                .span
+                .as_ref()
+                .or_else(|| diagnostic.labels.first().map(|label| &label.span))
+                .map_or(0..0, |s| s.byte_start..s.byte_end);
+            let mut message = match &diagnostic.help {
+                Some(help) => format!("{}\nhelp: {}", diagnostic.message, help),
+                None => diagnostic.message.clone(),
+            };
+            for note in &diagnostic.notes {
+                message.push_str(&format!("\nnote: {}", note));
+            }
+            let mut published = json!({
+                "range": range(text.chunks(), span),
+                "severity": match diagnostic.level {
+                    Level::Error => 1,
+                    Level::Warning => 2,
+                },
+                "source": "dream",
+                "message": message,
+            });
+            if let Some(code) = &diagnostic.code {
+                published["code"] = json!(code);
+            }
+            if !diagnostic.suggestions.is_empty() {
+                let fixes: Vec<Value> = diagnostic
+                    .suggestions
+                    .iter()
+                    .map(|suggestion| {
+                        json!({
+                            "title": suggestion.message,
+                            "range": range(text.chunks(), suggestion.span.byte_start..suggestion.span.byte_end),
+                            "newText": suggestion.replacement,
+                        })
+                    })
+                    .collect();
+                published["data"] = json!({ "fixes": fixes });
+            }
+            if !diagnostic.labels.is_empty() {
+                let related: Vec<Value> = diagnostic
+                    .labels
+                    .iter()
+                    .map(|label| {
+                        json!({
+                            "location": {
+                                "uri": uri,
+                                "range": range(text.chunks(), label.span.byte_start..label.span.byte_end),
+                            },
+                            "message": label.message,
+                        })
+                    })
+                    .collect();
+                published["relatedInformation"] = json!(related);
+            }
+            published
+        })
+        .collect();
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// Quick fixes for the diagnostics of a `codeAction` request, from the
+/// fixes [`publish`] put in their `data`. The editor sends the diagnostics
+/// back as it got them, so nothing has to be remembered between the two.
+fn code_actions(params: &Value) -> Value {
+    let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+    let empty = Vec::new();
+    let diagnostics = params["context"]["diagnostics"].as_array().unwrap_or(&empty);
+    let actions: Vec<Value> = diagnostics
+        .iter()
+        .flat_map(|diagnostic| {
+            let fixes = diagnostic["data"]["fixes"].as_array().cloned().unwrap_or_default();
+            fixes.into_iter().map(move |fix| {
+                json!({
+                    "title": fix["title"],
+                    "kind": "quickfix",
+                    "diagnostics": [diagnostic],
+                    "isPreferred": true,
+                    "edit": {
+                        "changes": { uri: [{ "range": fix["range"], "newText": fix["newText"] }] },
+                    },
+                })
+            })
+        })
+        .collect();
+    json!(actions)
+}
+
+fn is_path_char(c: char) -> bool {
+    is_ident_char(c) || c == ':'
+}
+
+/// The LSP position of byte `offset` in `text`: a 0-based line and a
+/// column counted in UTF-16 code units.
+/// The LSP position of byte `offset` in the text made of `chunks`. An
+/// offset inside a character is taken back to its start, and one past the
+/// end to the end.
+fn position_in<'a>(chunks: impl IntoIterator<Item = &'a str>, offset: usize) -> Value {
+    let (mut start, mut line, mut character) = (0, 0, 0);
+    'chunks: for chunk in chunks {
+        for (i, c) in chunk.char_indices() {
+            if start + i + c.len_utf8() > offset {
+                break 'chunks;
+            }
+            if c == '\n' {
+                line += 1;
+                character = 0;
+            } else {
+                character += c.len_utf16();
+            }
+        }
+        start += chunk.len();
+    }
+    json!({ "line": line, "character": character })
+}
+
+fn range<'a>(chunks: impl IntoIterator<Item = &'a str> + Clone, span: Range<usize>) -> Value {
+    json!({ "start": position_in(chunks.clone(), span.start), "end": position_in(chunks, span.end) })
+}
+
+/// The byte offset of an LSP position in the text made of `chunks`.
+fn offset_in<'a>(chunks: impl IntoIterator<Item = &'a str>, position: &Value) -> Option<usize> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let (mut offset, mut current, mut units) = (0, 0, 0);
+    for chunk in chunks {
+        for (i, c) in chunk.char_indices() {
+            if current == line {
+                if units >= character || c == '\n' {
+                    return Some(offset + i);
+                }
+                units += c.len_utf16();
+            } else if c == '\n' {
+                current += 1;
+            }
+        }
+        offset += chunk.len();
+    }
+    (current == line).then_some(offset)
+}
+
+/// The path of a `file://` URI.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let escaped = (b == b'%')
+            .then(|| std::str::from_utf8(tail.get(..2)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // `file:///C:/x` on Windows
+    let path = match path.strip_prefix('/') {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => rest.to_string(),
+        _ => path,
+    };
+    Some(PathBuf::from(path))
+}
+
+/// The `file://` URI of `path`.
+fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for b in path.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~:".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", b));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "struct Point {\n    x: int,\n}\n\npub fn area(w: int, h: int) -> int {\n    w * h\n}\n\nfn twice() -> int {\n    area(2, 1)\n}\n";
+
+    fn open(server: &mut Server, uri: &str, text: &str) -> Vec<Value> {
+        server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": uri, "languageId": "dream", "version": 1, "text": text } },
+        }))
+    }
+
+    fn request(server: &mut Server, method: &str, params: Value) -> Value {
+        let replies = server
+            .handle(&json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params }));
+        assert_eq!(replies.len(), 1);
+        replies[0]["result"].clone()
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({
+            "textDocument": { "uri": "file:///app/src/shapes.dream" },
+            "position": { "line": line, "character": character },
+        })
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({ "id": 1 })).unwrap();
+        assert_eq!(out, b"Content-Length: 8\r\n\r\n{\"id\":1}");
+
+        let mut reader = io::Cursor::new(out);
+        assert_eq!(read_message(&mut reader).unwrap(), Some(json!({ "id": 1 })));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_positions() {
+        let text = "let é = 1;\nfoo()";
+        assert_eq!(position_in([text], 12), json!({ "line": 1, "character": 0 }));
+        assert_eq!(position_in([text], 9), json!({ "line": 0, "character": 8 }));
+        assert_eq!(position_in([text], 5), json!({ "line": 0, "character": 4 }));
+        assert_eq!(position_in(["let é", " = 1;\nf", "oo()"], 12), json!({ "line": 1, "character": 0 }));
+        assert_eq!(position_in([text], 100), json!({ "line": 1, "character": 5 }));
+        assert_eq!(
+            offset_in([text], &json!({ "line": 0, "character": 8 })),
+            Some(9)
+        );
+        assert_eq!(
+            offset_in([text], &json!({ "line": 1, "character": 3 })),
+            Some(15)
+        );
+        assert_eq!(offset_in([text], &json!({ "line": 5, "character": 0 })), None);
+    }
+
+    #[test]
+    fn test_uris() {
+        assert_eq!(
+            uri_to_path("file:///home/me/my%20app/a.dream"),
+            Some(PathBuf::from("/home/me/my app/a.dream"))
+        );
+        assert_eq!(
+            uri_to_path("file:///C:/src/a.dream"),
+            Some(PathBuf::from("C:/src/a.dream"))
+        );
+        assert_eq!(uri_to_path("untitled:1"), None);
+        assert_eq!(
+            path_to_uri(Path::new("/home/me/my app/a.dream")),
+            "file:///home/me/my%20app/a.dream"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut server = Server::new(Vec::new());
+        let replies = open(
+            &mut server,
+            "file:///app/src/shapes.dream",
+            "fn bad() -> int {\n    \"hello\"\n}\n",
+        );
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+        let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(diagnostics[0]["code"], "E0030");
+
+        let replies = open(
+            &mut server,
+            "file:///app/src/shapes.dream",
+            "fn broken( {\n",
+        );
+        assert_eq!(
+            replies[0]["params"]["diagnostics"][0]["range"]["start"]["line"],
+            0
+        );
+
+        // Every syntax error is published, not just the first
+        let replies = open(
+            &mut server,
+            "file:///app/src/shapes.dream",
+            "fn a() -> int { 1 2 }\nfn b( {}\n",
+        );
+        let diagnostics = replies[0]["params"]["diagnostics"].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[1]["range"]["start"]["line"], 1);
+
+        let replies = open(&mut server, "file:///app/src/shapes.dream", SOURCE);
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+        let uri = "file:///app/src/shapes.dream";
+        let mut server = Server::new(Vec::new());
+        open(&mut server, uri, SOURCE);
+        let change = |server: &mut Server, changes: Value| {
+            server.handle(&json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": { "textDocument": { "uri": uri, "version": 2 }, "contentChanges": changes },
+            }))
+        };
+        let edit = |start: (u64, u64), end: (u64, u64), text: &str| {
+            json!({
+                "range": {
+                    "start": { "line": start.0, "character": start.1 },
+                    "end": { "line": end.0, "character": end.1 },
+                },
+                "text": text,
+            })
+        };
+
+        // `w * h` becomes `"w"`, then the two edits of one change undo it
+        let replies = change(&mut server, json!([edit((5, 4), (5, 9), "\"w\"")]));
+        assert_eq!(replies[0]["params"]["diagnostics"][0]["code"], "E0030");
+        let replies = change(
+            &mut server,
+            json!([edit((5, 4), (5, 7), "w"), edit((5, 5), (5, 5), " * h")]),
+        );
+        assert_eq!(replies[0]["params"]["diagnostics"], json!([]));
+        let document = &server.documents[uri];
+        assert_eq!(document.buffer.to_string(), SOURCE);
+        assert_eq!(document.tokens, TokenCache::new(&Text::new(SOURCE)));
+
+        // A change without a range replaces the whole text
+        change(&mut server, json!([{ "text": "fn one() -> int {\n    1\n}\n" }]));
+        assert_eq!(server.documents[uri].buffer.to_string(), "fn one() -> int {\n    1\n}\n");
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut server = Server::new(Vec::new());
+        open(&mut server, "file:///app/src/shapes.dream", SOURCE);
+
+        // `area` in `area(2, 1)`
+        let definition = request(&mut server, "textDocument/definition", at(9, 5));
+        assert_eq!(definition["uri"], "file:///app/src/shapes.dream");
+        assert_eq!(
+            definition["range"]["start"],
+            json!({ "line": 4, "character": 4 })
+        );
+
+        let hover = request(&mut server, "textDocument/hover", at(9, 5));
+        assert_eq!(
+            hover["contents"]["value"],
+            "```dream\nfn area(w: int, h: int) -> int\n```"
+        );
+
+        let symbols = request(
+            &mut server,
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": "file:///app/src/shapes.dream" } }),
+        );
+        let names: Vec<&str> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Point", "area", "twice"]);
+        assert_eq!(
+            symbols[0]["location"]["range"]["start"],
+            json!({ "line": 0, "character": 0 })
+        );
+
+        let completions = request(&mut server, "textDocument/completion", at(9, 4));
+        let labels: Vec<&str> = completions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, ["area", "twice", "shapes"]);
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut server = Server::new(Vec::new());
+        let uri = "file:///app/src/shapes.dream";
+        open(&mut server, uri, SOURCE);
+
+        let mut params = at(9, 5);
+        params["newName"] = json!("surface");
+        let edit = request(&mut server, "textDocument/rename", params.clone());
+        let edits = edit["changes"][uri].as_array().unwrap();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[0]["range"]["start"], json!({ "line": 4, "character": 7 }));
+        assert_eq!(edits[1]["newText"], "surface");
+
+        params["newName"] = json!("twice");
+        let replies = server.handle(&json!({
+            "jsonrpc": "2.0", "id": 2, "method": "textDocument/rename", "params": params,
+        }));
+        assert_eq!(replies[0]["error"]["code"], REQUEST_FAILED);
+    }
+
+    #[test]
+    fn test_formatting() {
+        let mut server = Server::new(Vec::new());
+        let uri = "file:///app/src/shapes.dream";
+        let params = json!({ "textDocument": { "uri": uri }, "options": { "tabSize": 4 } });
+
+        open(&mut server, uri, "fn twice() -> int { area(2,1) }\n");
+        let edits = request(&mut server, "textDocument/formatting", params.clone());
+        assert_eq!(edits[0]["newText"], "fn twice() -> int {\n    area(2, 1)\n}\n");
+        assert_eq!(edits[0]["range"]["end"], json!({ "line": 1, "character": 0 }));
+
+        open(&mut server, uri, SOURCE);
+        assert_eq!(request(&mut server, "textDocument/formatting", params.clone()), json!([]));
+
+        open(&mut server, uri, "fn broken( {\n");
+        assert_eq!(request(&mut server, "textDocument/formatting", params), Value::Null);
+    }
+
+    #[test]
+    fn test_code_actions() {
+        let mut server = Server::new(Vec::new());
+        let uri = "file:///app/src/shapes.dream";
+        let replies = open(&mut server, uri, "fn twice(count: int) -> int {\n    cont * 2\n}\n");
+        let diagnostics = replies[0]["params"]["diagnostics"].clone();
+        assert_eq!(diagnostics[0]["data"]["fixes"][0]["newText"], "count");
+
+        let params = json!({
+            "textDocument": { "uri": uri },
+            "range": diagnostics[0]["range"],
+            "context": { "diagnostics": diagnostics },
+        });
+        let actions = request(&mut server, "textDocument/codeAction", params);
+        assert_eq!(actions[0]["kind"], "quickfix");
+        let edit = &actions[0]["edit"]["changes"][uri][0];
+        assert_eq!(edit["newText"], "count");
+        assert_eq!(
+            edit["range"],
+            json!({ "start": { "line": 1, "character": 4 }, "end": { "line": 1, "character": 8 } })
+        );
+    }
+
+    #[test]
+    fn test_unknown_request() {
+        let mut server = Server::new(Vec::new());
+        let replies =
+            server.handle(&json!({ "jsonrpc": "2.0", "id": 7, "method": "textDocument/codeLens" }));
+        assert_eq!(replies[0]["error"]["code"], METHOD_NOT_FOUND);
+        assert!(
+            server
+                .handle(&json!({ "jsonrpc": "2.0", "method": "$/cancelRequest" }))
+                .is_empty()
+        );
+    }
+}
@@ -1,24 +1,79 @@
 //! Dream CLI - Build and run Dream programs.
 
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, BufRead, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, ExitCode};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 use dream::{
+    analysis::{Analysis, Symbol},
     compiler::{
-        cfg, check_modules_with_metadata, expand_derives_with_registry, expand_quotes,
-        get_derive_macro_name, is_derive_macro, is_macro, resolve_stdlib_methods,
-        CompilerError, CompilerWarning, CoreErlangEmitter, GenericFunctionRegistry, Item, MacroRegistry,
-        Module, ModuleContext, ModuleLoader, Parser as DreamParser, SharedGenericRegistry,
+        affected_modules, cfg, check_modules_with_levels, Annotations, check_modules_with_metadata, dump_ast, dump_tokens, expand_derives_with_registry,
+        expand_quotes, format_source, get_derive_macro_name, is_derive_macro, is_macro, resolve_stdlib_methods,
+        EmitKind, ErrorCode, InternalCompilerError, ice,
+        CompilerError, CompilerWarning, CoreErlangEmitter, GenericFunctionRegistry, Item, Lint,
+        LintDiagnostic, LintLevel, LintLevels, Linter, LoadError, MacroRegistry, Module, ModuleContext,
+        ModuleLoader, Parser as DreamParser, SharedGenericRegistry,
+        TypeError, Warning, WarningLevels, order_by_dependency, typeck::StructInfo,
     },
-    config::{generate_dream_toml, generate_main_dream, ApplicationConfig, CompileOptions, ProjectConfig},
-    deps::DepsManager,
+    config::{
+        nif_template, parse_cfg, project_template, validate_package_name, ApplicationConfig, CompileOptions, ConfigError,
+        ConfigResult, FeatureSelection, NodeOptions, ProjectConfig, Workspace,
+    },
+    bench::{self, BenchEvent, BenchOptions},
+    bindgen,
+    code_path::CodePath,
+    coverage::CoverageReport,
+    debugger::{self, SourceMap},
+    doc,
+    deps::{DepsError, DepsManager},
+    fix,
+    lockfile::{Lockfile, LOCKFILE_NAME},
+    output::{Diagnostic, Message, MessageFormat, TestStatus},
+    profile::{self, Profile, Tool},
+    refactor,
+    sarif,
+    target::{self, TargetLayout},
+    watch::{self, WatchOptions},
+    test_report::{ReportFormat, TestReport},
+    timings::{self, Phase},
+    toolchain,
+    testing::{self, ResultParser, RunOptions, TestCase, TestEvent, TestFilter, TestOutcome},
+    top::{self, SortKey},
+    trace::{self, TraceOptions, TracePattern},
 };
-use std::collections::HashSet;
-use std::sync::{Arc, RwLock};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+/// `--message-format`, set once at startup.
+static MESSAGE_FORMAT: OnceLock<MessageFormat> = OnceLock::new();
+
+/// Set at startup when stdout carries a protocol (`dream debug --dap`),
+/// which progress output must stay out of.
+static PROTOCOL_STDOUT: OnceLock<bool> = OnceLock::new();
+
+/// Diagnostics reported so far, printed as one SARIF log at exit in
+/// `--message-format=sarif` mode.
+static SARIF_DIAGNOSTICS: Mutex<Vec<Diagnostic>> = Mutex::new(Vec::new());
+
+/// The `-A`/`-W`/`-D` warning flags, set once at startup and applied over
+/// each package's `[warnings]`.
+static WARNING_FLAGS: OnceLock<Vec<(LintLevel, String)>> = OnceLock::new();
+
+/// Progress output: `println!`, except that in JSON and SARIF modes, and
+/// under `dream debug --dap`, it goes to stderr so stdout carries nothing
+/// but the machine-readable output.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if machine_output() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
 
 #[derive(Parser)]
 #[command(name = "dream")]
@@ -26,6 +81,110 @@ use std::sync::{Arc, RwLock};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    /// Output format for build, check, lint and test: human, json or sarif
+    #[arg(long, global = true, alias = "output-format", value_name = "FMT", default_value = "human")]
+    message_format: MessageFormat,
+}
+
+/// Feature and cfg flags shared by the commands that compile the project.
+#[derive(Args, Clone, Default)]
+struct FeatureArgs {
+    /// Enable features for conditional compilation (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    features: Vec<String>,
+    /// Don't enable the features listed in `[features] default`
+    #[arg(long)]
+    no_default_features: bool,
+    /// Enable every feature declared in dream.toml
+    #[arg(long)]
+    all_features: bool,
+    /// Set a cfg option: `name` or `key="value"`
+    #[arg(long, value_name = "SPEC")]
+    cfg: Vec<String>,
+}
+
+impl From<FeatureArgs> for FeatureSelection {
+    fn from(args: FeatureArgs) -> Self {
+        Self {
+            features: args.features,
+            no_default_features: args.no_default_features,
+            all_features: args.all_features,
+            cfg: args.cfg,
+        }
+    }
+}
+
+/// Warning level flags shared by the commands that compile the project.
+#[derive(Args, Clone, Default)]
+struct WarningArgs {
+    /// Allow these warning categories (`warnings` for every one that would warn)
+    #[arg(short = 'A', long = "allow", value_name = "CATEGORY")]
+    allow: Vec<String>,
+    /// Warn on these warning categories
+    #[arg(short = 'W', long = "warn", value_name = "CATEGORY")]
+    warn: Vec<String>,
+    /// Fail on these warning categories (`warnings` for every one that would warn)
+    #[arg(short = 'D', long = "deny", value_name = "CATEGORY")]
+    deny: Vec<String>,
+}
+
+impl WarningArgs {
+    /// Each category with its level, allows first and denies last.
+    fn flags(&self) -> Vec<(LintLevel, String)> {
+        let allow = self.allow.iter().map(|name| (LintLevel::Allow, name.clone()));
+        let warn = self.warn.iter().map(|name| (LintLevel::Warn, name.clone()));
+        let deny = self.deny.iter().map(|name| (LintLevel::Deny, name.clone()));
+        allow.chain(warn).chain(deny).collect()
+    }
+}
+
+/// Build profile flags shared by build and run.
+#[derive(Args, Clone, Default)]
+struct ProfileArgs {
+    /// Build with the release profile
+    #[arg(long, conflicts_with = "profile")]
+    release: bool,
+    /// Build with a profile from dream.toml's [profile.<name>]
+    #[arg(long)]
+    profile: Option<String>,
+}
+
+impl ProfileArgs {
+    fn name(&self) -> &str {
+        match &self.profile {
+            Some(name) => name,
+            None if self.release => "release",
+            None => "dev",
+        }
+    }
+}
+
+/// Workspace member selection shared by build, test and check.
+#[derive(Args, Clone, Default)]
+struct PackageArgs {
+    /// Only these workspace members (and, when building, what they depend on)
+    #[arg(short = 'p', long = "package", value_name = "MEMBER", value_delimiter = ',')]
+    package: Vec<String>,
+    /// Every member of the workspace
+    #[arg(long, conflicts_with = "package")]
+    workspace: bool,
+}
+
+impl PackageArgs {
+    fn is_set(&self) -> bool {
+        self.workspace || !self.package.is_empty()
+    }
+}
+
+/// `--watch` flags shared by build, test and run.
+#[derive(Args, Clone, Copy)]
+struct WatchArgs {
+    /// Re-run whenever a source file changes
+    #[arg(long)]
+    watch: bool,
+    /// Clear the screen before each re-run
+    #[arg(long, requires = "watch")]
+    clear: bool,
 }
 
 #[derive(Subcommand)]
@@ -34,6 +193,9 @@ enum Commands {
     New {
         /// Name of the project
         name: String,
+        /// Create a library (src/lib.dream) instead of a binary (src/main.dream)
+        #[arg(long)]
+        lib: bool,
     },
     /// Build the project or a single file
     Build {
@@ -45,9 +207,25 @@ enum Commands {
         /// Output directory
         #[arg(long, short)]
         output: Option<PathBuf>,
-        /// Enable features for conditional compilation (comma-separated)
-        #[arg(long, value_delimiter = ',')]
-        features: Vec<String>,
+        /// Stages to write to the output directory (comma-separated): tokens, ast, typed-ast, core, beam
+        #[arg(long, value_delimiter = ',', value_parser = parse_emit_kind, value_name = "KINDS")]
+        emit: Vec<EmitKind>,
+        #[command(flatten)]
+        features: FeatureArgs,
+        #[command(flatten)]
+        warnings: WarningArgs,
+        /// Require dream.lock to be present and up to date
+        #[arg(long)]
+        locked: bool,
+        /// Report how long each compiler phase took, per module, and write it to _build/timings
+        #[arg(long)]
+        timings: bool,
+        #[command(flatten)]
+        profile: ProfileArgs,
+        #[command(flatten)]
+        packages: PackageArgs,
+        #[command(flatten)]
+        watch: WatchArgs,
     },
     /// Compile the project or a single file (alias for build)
     Compile {
@@ -59,9 +237,57 @@ enum Commands {
         /// Output directory
         #[arg(long, short)]
         output: Option<PathBuf>,
-        /// Enable features for conditional compilation (comma-separated)
-        #[arg(long, value_delimiter = ',')]
-        features: Vec<String>,
+        /// Stages to write to the output directory (comma-separated): tokens, ast, typed-ast, core, beam
+        #[arg(long, value_delimiter = ',', value_parser = parse_emit_kind, value_name = "KINDS")]
+        emit: Vec<EmitKind>,
+        #[command(flatten)]
+        features: FeatureArgs,
+        #[command(flatten)]
+        warnings: WarningArgs,
+        /// Require dream.lock to be present and up to date
+        #[arg(long)]
+        locked: bool,
+        /// Report how long each compiler phase took, per module, and write it to _build/timings
+        #[arg(long)]
+        timings: bool,
+        #[command(flatten)]
+        profile: ProfileArgs,
+        #[command(flatten)]
+        packages: PackageArgs,
+        #[command(flatten)]
+        watch: WatchArgs,
+    },
+    /// Compile files into an ebin directory, for build tools such as Mix and rebar3
+    ///
+    /// This interface is stable: with `--message-format json`, stdout has
+    /// one JSON message per line, and the exit status is 0 only when every
+    /// module compiled.
+    CompileFiles {
+        /// Source files to compile, with the modules they declare with `mod`
+        #[arg(required_unless_present = "print_stdlib")]
+        files: Vec<PathBuf>,
+        /// Directory to write .beam files to
+        #[arg(short = 'o', long, value_name = "DIR", required_unless_present = "print_stdlib")]
+        ebin: Option<PathBuf>,
+        /// Name modules as those of a package (`<package>::<path>`), with sources in --src
+        #[arg(long, requires = "src")]
+        package: Option<String>,
+        /// Source directory of --package
+        #[arg(long, value_name = "DIR", requires = "package")]
+        src: Option<PathBuf>,
+        /// Directory to search for bindings
+        #[arg(short = 'I', long = "include", value_name = "DIR")]
+        include: Vec<PathBuf>,
+        /// Directory of a dependency's compiled modules (`<app>/ebin`)
+        #[arg(long = "pa", value_name = "DIR")]
+        code_path: Vec<PathBuf>,
+        /// Print the directory of the compiled standard library, which must be on the code path, and exit
+        #[arg(long, conflicts_with_all = ["files", "ebin"])]
+        print_stdlib: bool,
+        #[command(flatten)]
+        features: FeatureArgs,
+        #[command(flatten)]
+        warnings: WarningArgs,
     },
     /// Build and run the project or a single file
     Run {
@@ -77,30 +303,251 @@ enum Commands {
         #[arg(long)]
         no_halt: bool,
         /// Start with interactive Erlang shell (like iex -S mix)
-        #[arg(short = 'S', long)]
+        #[arg(short = 'S', long, conflicts_with = "watch")]
         shell: bool,
         /// Environment: dev, test, prod (default: dev)
         #[arg(short, long, default_value = "dev")]
         env: String,
-        /// Enable features for conditional compilation (comma-separated)
-        #[arg(long, value_delimiter = ',')]
-        features: Vec<String>,
-        /// Arguments to pass to the function
+        #[command(flatten)]
+        features: FeatureArgs,
+        #[command(flatten)]
+        warnings: WarningArgs,
+        /// Start a distributed node with a short name (name@host)
+        #[arg(long, conflicts_with = "name")]
+        sname: Option<String>,
+        /// Start a distributed node with a fully qualified name (name@host.domain)
+        #[arg(long)]
+        name: Option<String>,
+        /// Magic cookie for connecting to other nodes
+        #[arg(long)]
+        cookie: Option<String>,
+        /// Run on the built-in Rust VM instead of the BEAM (no Erlang install needed)
+        #[arg(long, conflicts_with_all = ["shell", "no_halt", "sname", "name", "cookie"])]
+        native: bool,
+        /// Run the binary in src/bin/<NAME>.dream instead of the main module
+        #[arg(long, conflicts_with_all = ["file", "function", "native"])]
+        bin: Option<String>,
+        /// Arguments to pass to the function, as Erlang terms
         args: Vec<String>,
+        /// Arguments after `--` are passed to `main(args)` as a list of strings
+        #[arg(last = true)]
+        program_args: Vec<String>,
+        #[command(flatten)]
+        profile: ProfileArgs,
+        #[command(flatten)]
+        watch: WatchArgs,
     },
     /// Run tests
     Test {
-        /// Filter tests by name (substring match)
+        /// Only run tests whose name contains this (e.g. `parser::`)
+        filter: Option<String>,
+        #[command(flatten)]
+        features: FeatureArgs,
+        #[command(flatten)]
+        warnings: WarningArgs,
+        /// Only run tests tagged with one of these (comma-separated)
+        #[arg(long = "tag", value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Skip tests tagged with any of these (comma-separated)
+        #[arg(long = "exclude-tag", value_delimiter = ',')]
+        exclude_tags: Vec<String>,
+        /// Only run #[ignore] tests
+        #[arg(long, conflicts_with = "include_ignored")]
+        ignored: bool,
+        /// Run #[ignore] tests as well
+        #[arg(long)]
+        include_ignored: bool,
+        /// Number of tests to run at once (default: number of CPUs)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Per-test timeout in seconds, 0 for none
+        #[arg(long, default_value_t = testing::DEFAULT_TIMEOUT.as_secs())]
+        timeout: u64,
+        /// Report line coverage, writing LCOV and HTML to _build/cover
+        #[arg(long)]
+        coverage: bool,
+        /// Also write the results for CI to _build/test-results: junit, tap (comma-separated)
+        #[arg(long = "report", value_name = "FORMAT", value_delimiter = ',')]
+        reports: Vec<ReportFormat>,
+        #[command(flatten)]
+        packages: PackageArgs,
+        #[command(flatten)]
+        watch: WatchArgs,
+    },
+    /// Run the project's program under the debugger, with line breakpoints and stepping
+    Debug {
+        /// Serve the Debug Adapter Protocol on stdio, for editors, instead of a command prompt
+        #[arg(long)]
+        dap: bool,
+        /// Debug the binary in src/bin/<NAME>.dream instead of the main module
+        #[arg(long)]
+        bin: Option<String>,
+        #[command(flatten)]
+        features: FeatureArgs,
+        #[command(flatten)]
+        warnings: WarningArgs,
+        /// Arguments after `--` are passed to `main(args)` as a list of strings
+        #[arg(last = true)]
+        program_args: Vec<String>,
+    },
+    /// Parse and type check the project without generating code
+    Check {
+        #[command(flatten)]
+        features: FeatureArgs,
+        #[command(flatten)]
+        warnings: WarningArgs,
+        /// Check with cfg(test) enabled, including test modules
+        #[arg(long)]
+        tests: bool,
+        /// Report how long each compiler phase took, per module, and write it to _build/timings
+        #[arg(long)]
+        timings: bool,
+        #[command(flatten)]
+        packages: PackageArgs,
+    },
+    /// Type check the project and run lints over it
+    Lint {
+        #[command(flatten)]
+        features: FeatureArgs,
+        /// Lint with cfg(test) enabled, including test modules
+        #[arg(long)]
+        tests: bool,
+        /// Allow these lints or warning categories (`warnings` for every one that would warn)
+        #[arg(short = 'A', long = "allow", value_name = "LINT")]
+        allow: Vec<String>,
+        /// Warn on these lints or warning categories
+        #[arg(short = 'W', long = "warn", value_name = "LINT")]
+        warn: Vec<String>,
+        /// Fail on these lints or warning categories (`warnings` for every one that would warn)
+        #[arg(short = 'D', long = "deny", value_name = "LINT")]
+        deny: Vec<String>,
+        /// List the lints and their default levels
+        #[arg(long)]
+        list: bool,
+        #[command(flatten)]
+        packages: PackageArgs,
+    },
+    /// Apply the fixes the compiler and lints are sure of, editing sources in place
+    Fix {
+        #[command(flatten)]
+        features: FeatureArgs,
+        /// Fix with cfg(test) enabled, including test modules
+        #[arg(long)]
+        tests: bool,
+        /// Print the fixes as a diff instead of applying them
+        #[arg(long)]
+        dry_run: bool,
+        #[command(flatten)]
+        packages: PackageArgs,
+    },
+    /// Generate HTML documentation for the project
+    Doc {
+        #[command(flatten)]
+        features: FeatureArgs,
+        /// Serve the documentation on localhost after generating it
+        #[arg(long)]
+        serve: bool,
+        /// Port to serve on
+        #[arg(long, default_value_t = 8000, requires = "serve")]
+        port: u16,
+    },
+    /// Explain an error code, or list every code
+    Explain {
+        /// The code to explain, such as E0030
+        code: Option<String>,
+    },
+    /// Run benchmarks
+    Bench {
+        /// Only run benchmarks whose name contains this
         filter: Option<String>,
-        /// Enable features for conditional compilation (comma-separated)
-        #[arg(long, value_delimiter = ',')]
-        features: Vec<String>,
+        #[command(flatten)]
+        features: FeatureArgs,
+        /// Warmup time per benchmark in milliseconds
+        #[arg(long, default_value_t = 500)]
+        warmup: u64,
+        /// Number of timed samples per benchmark
+        #[arg(long, default_value_t = 100)]
+        samples: usize,
+    },
+    /// Profile the program, a function or the tests, reporting time per Dream function
+    Profile {
+        /// Zero-argument function to profile, e.g. `app::parser::parse_all` (default: the program's main)
+        function: Option<String>,
+        /// Profile the test suite instead
+        #[arg(long, conflicts_with = "function")]
+        tests: bool,
+        /// Profiler: eprof (own time per function, low overhead) or fprof (call graph, slower; the default with --flamegraph)
+        #[arg(long)]
+        tool: Option<Tool>,
+        /// Also write the call graph as a flamegraph SVG to this file
+        #[arg(long, value_name = "FILE")]
+        flamegraph: Option<PathBuf>,
+        /// Number of functions to list
+        #[arg(long, default_value_t = 30)]
+        limit: usize,
+        #[command(flatten)]
+        features: FeatureArgs,
+        #[command(flatten)]
+        warnings: WarningArgs,
+        /// Arguments after `--` are passed to `main(args)` as a list of strings
+        #[arg(last = true)]
+        program_args: Vec<String>,
+    },
+    /// Trace calls to Dream functions on a running node
+    Trace {
+        /// Functions to trace, e.g. `mymod::handle_*` or `app::server::handle_call/3`
+        pattern: String,
+        /// The node to trace, e.g. `app@host`
+        #[arg(long)]
+        node: String,
+        /// Magic cookie of the node
+        #[arg(long)]
+        cookie: Option<String>,
+        /// Also print return values and exceptions
+        #[arg(long)]
+        returns: bool,
+        /// Stop after this many calls
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+        /// Print at most this many calls per second, dropping the rest
+        #[arg(long)]
+        rate: Option<usize>,
+    },
+    /// Show a live table of the processes on a running node
+    Top {
+        /// The node to watch, e.g. `app@host`, or `app` on this host
+        #[arg(long)]
+        node: String,
+        /// Magic cookie of the node
+        #[arg(long)]
+        cookie: Option<String>,
+        /// Column to sort by: reductions, memory or queue
+        #[arg(long, default_value = "reductions")]
+        sort: SortKey,
+        /// Seconds between samples
+        #[arg(long, default_value_t = 2.0)]
+        interval: f64,
+    },
+    /// Remove build output
+    Clean {
+        /// Only remove the output of these packages (comma-separated)
+        #[arg(short = 'p', long = "package", value_name = "PACKAGE", value_delimiter = ',')]
+        package: Vec<String>,
+        /// Only remove the compiled standard library shared by all projects
+        #[arg(long, conflicts_with = "package")]
+        cache: bool,
     },
     /// Generate .dreamt type stubs from Erlang source files
     Bindgen {
         /// Erlang source files (.erl) to parse
-        #[arg(required = true)]
+        #[arg(required_unless_present = "include_lib")]
         files: Vec<PathBuf>,
+        /// Header from an OTP application, as `-include_lib` names it (e.g. kernel/include/file.hrl)
+        #[arg(long = "include-lib", value_name = "PATH")]
+        include_lib: Vec<String>,
+        /// Only generate structs and field accessors for `-record` definitions
+        #[arg(long)]
+        records: bool,
         /// Output file (default: stdout)
         #[arg(short, long)]
         output: Option<PathBuf>,
@@ -111,34 +558,218 @@ enum Commands {
     /// Show version information
     Version,
     /// Start an interactive Dream shell (REPL)
-    Shell,
+    Shell {
+        /// Directory to write the session's compiled code to, instead of
+        /// the system temp dir. The session works in a directory of its own
+        /// inside, removed when it ends
+        #[arg(long, value_name = "DIR")]
+        workdir: Option<PathBuf>,
+    },
+    /// Run the language server over stdio, for editors
+    Lsp,
+    /// Format source files in place
+    Fmt {
+        /// Files or directories to format (default: the project's source directory)
+        files: Vec<PathBuf>,
+        /// Only check formatting: list the files that would change and fail if any would
+        #[arg(long)]
+        check: bool,
+    },
+    /// Change code across the project, such as renaming a function
+    Refactor {
+        #[command(subcommand)]
+        action: RefactorAction,
+    },
     /// Manage dependencies
     Deps {
         #[command(subcommand)]
         action: DepsAction,
     },
+    /// Work with native functions (NIFs) written in Rust
+    Nif {
+        #[command(subcommand)]
+        action: NifAction,
+    },
 }
 
 #[derive(Subcommand)]
 enum DepsAction {
     /// Fetch all dependencies
-    Get,
+    Get {
+        /// Fail instead of updating dream.lock
+        #[arg(long)]
+        locked: bool,
+    },
     /// Compile all dependencies
     Compile,
     /// Generate bindings for dependencies
     Bindgen,
 }
 
-mod bindgen;
+#[derive(Subcommand)]
+enum RefactorAction {
+    /// Rename a function, type or variable everywhere it is used
+    Rename {
+        /// The current name, as `name` or `module::name`
+        old: String,
+        /// The new name
+        new: String,
+        /// Where the name is written, as `file:line:column`; needed for variables
+        #[arg(long)]
+        at: Option<String>,
+        /// Print the changes as a diff instead of making them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum NifAction {
+    /// Create a Rustler crate in native/<name> and the module declaring its functions
+    New {
+        /// Name of the crate and the module
+        name: String,
+    },
+}
+
 mod repl;
 
+impl Commands {
+    /// The warning level flags, for commands that take them.
+    fn warning_args(&self) -> Option<&WarningArgs> {
+        match self {
+            Commands::Build { warnings, .. }
+            | Commands::Compile { warnings, .. }
+            | Commands::CompileFiles { warnings, .. }
+            | Commands::Run { warnings, .. }
+            | Commands::Test { warnings, .. }
+            | Commands::Debug { warnings, .. }
+            | Commands::Profile { warnings, .. }
+            | Commands::Check { warnings, .. } => Some(warnings),
+            _ => None,
+        }
+    }
+
+    /// Whether `--timings` was given.
+    fn timings(&self) -> bool {
+        match self {
+            Commands::Build { timings, .. } | Commands::Compile { timings, .. } | Commands::Check { timings, .. } => {
+                *timings
+            }
+            _ => false,
+        }
+    }
+
+    /// The `--watch` flags, for commands that take them.
+    fn watch_args(&self) -> Option<WatchArgs> {
+        match self {
+            Commands::Build { watch, .. }
+            | Commands::Compile { watch, .. }
+            | Commands::Run { watch, .. }
+            | Commands::Test { watch, .. } => Some(*watch),
+            _ => None,
+        }
+    }
+}
+
 fn main() -> ExitCode {
     let cli = Cli::parse();
+    MESSAGE_FORMAT.get_or_init(|| cli.message_format);
+    WARNING_FLAGS.get_or_init(|| cli.command.warning_args().map(WarningArgs::flags).unwrap_or_default());
+    PROTOCOL_STDOUT.get_or_init(|| matches!(cli.command, Commands::Debug { dap: true, .. }));
+
+    if let Some(watch) = cli.command.watch_args().filter(|w| w.watch) {
+        return cmd_watch(&watch);
+    }
+
+    if cli.command.timings() {
+        timings::enable();
+    }
+    let status = match ice::catch(|| run(cli.command)) {
+        Ok(status) => status,
+        Err(error) => {
+            report_internal_error(*error);
+            // The status a panic would have exited with
+            ExitCode::from(101)
+        }
+    };
+    if let Some(timings) = timings::take() {
+        report_timings(&timings);
+    }
+    if MESSAGE_FORMAT.get() == Some(&MessageFormat::Sarif) {
+        print_sarif_log();
+    }
+    status
+}
+
+/// Print the `--timings` summary, and write the HTML page and trace to
+/// `_build/timings` (or `./_build/timings` outside a project).
+fn report_timings(timings: &timings::Timings) {
+    status!();
+    status!("{}", timings.summary(10).trim_end());
+    let layout = current_target_layout().unwrap_or_else(|_| TargetLayout::for_root(Path::new(".")));
+    match timings.write(&layout.timings_dir()) {
+        Ok(page) => status!("Timings written to {}", page.display()),
+        Err(e) => eprintln!("Warning: failed to write timings: {}", e),
+    }
+}
 
-    match cli.command {
-        Commands::New { name } => cmd_new(&name),
-        Commands::Build { file, target, output, features } | Commands::Compile { file, target, output, features } => {
-            cmd_build(file.as_deref(), &target, output.as_deref(), &features)
+/// Run a command other than a `--watch` loop.
+fn run(command: Commands) -> ExitCode {
+    match command {
+        Commands::New { name, lib } => cmd_new(&name, lib),
+        Commands::Build { file, target, output, emit: stages, features, locked, profile, packages, .. }
+        | Commands::Compile { file, target, output, emit: stages, features, locked, profile, packages, .. } => {
+            let features = features.into();
+            if file.is_some() && packages.is_set() {
+                eprintln!("Error: -p and --workspace cannot be used when building a single file");
+                return ExitCode::from(1);
+            }
+            let status = if file.is_some() {
+                cmd_build(file.as_deref(), &target, output.as_deref(), &stages, &features, profile.name(), locked)
+            } else {
+                let dependency_features = FeatureSelection::default();
+                with_members(&packages, |role| {
+                    // Features are named per package, so only the selected members get them
+                    let features = if role.selected { &features } else { &dependency_features };
+                    cmd_build(None, &target, output.as_deref(), &stages, features, profile.name(), locked)
+                })
+            };
+            emit(Message::BuildFinished {
+                success: status == ExitCode::SUCCESS,
+            });
+            status
+        }
+        Commands::CompileFiles {
+            files,
+            ebin,
+            package,
+            src,
+            include,
+            code_path,
+            print_stdlib,
+            features,
+            ..
+        } => {
+            if print_stdlib {
+                return match compile_stdlib() {
+                    Ok(dir) => {
+                        println!("{}", dir.display());
+                        ExitCode::SUCCESS
+                    }
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        ExitCode::from(1)
+                    }
+                };
+            }
+            let ebin = ebin.expect("clap requires --ebin without --print-stdlib");
+            let package = package.as_deref().zip(src.as_deref());
+            let status = cmd_compile_files(&files, &ebin, package, &include, &code_path, &features.into());
+            emit(Message::BuildFinished {
+                success: status == ExitCode::SUCCESS,
+            });
+            status
         }
         Commands::Run {
             file,
@@ -148,102 +779,595 @@ fn main() -> ExitCode {
             shell,
             env,
             features,
+            sname,
+            name,
+            cookie,
+            native,
+            bin,
             args,
-        } => cmd_run(file.as_deref(), function.as_deref(), eval, no_halt, shell, &env, &features, &args),
-        Commands::Test { filter, features } => cmd_test(filter.as_deref(), &features),
+            program_args,
+            profile,
+            ..
+        } => {
+            if native {
+                return cmd_run_native(file.as_deref(), function.as_deref(), &args);
+            }
+            let node = NodeOptions { sname, name, cookie };
+            let entry = RunEntry {
+                function: function.as_deref(),
+                bin: bin.as_deref(),
+                args: &args,
+                program_args: &program_args,
+            };
+            cmd_run(file.as_deref(), &entry, eval, no_halt, shell, &env, &features.into(), profile.name(), &node)
+        }
+        Commands::Test {
+            filter,
+            features,
+            tags,
+            exclude_tags,
+            ignored,
+            include_ignored,
+            jobs,
+            timeout,
+            coverage,
+            reports,
+            packages,
+            ..
+        } => {
+            let filter = TestFilter {
+                pattern: filter,
+                tags,
+                exclude_tags,
+                ignored,
+                include_ignored,
+            };
+            let defaults = RunOptions::default();
+            let run_options = RunOptions {
+                jobs: jobs.unwrap_or(defaults.jobs),
+                timeout: (timeout > 0).then(|| std::time::Duration::from_secs(timeout)),
+                cover: Vec::new(),
+            };
+            let features = features.into();
+            with_members(&packages, |role| {
+                // Tests load the members they depend on from their dev builds
+                if role.dependency {
+                    let status = cmd_build(None, "beam", None, &[], &FeatureSelection::default(), "dev", false);
+                    if status != ExitCode::SUCCESS {
+                        return status;
+                    }
+                }
+                if !role.selected {
+                    return ExitCode::SUCCESS;
+                }
+                cmd_test(&filter, run_options.clone(), &features, coverage, &reports)
+            })
+        }
+        Commands::Debug { dap, bin, features, program_args, .. } => {
+            cmd_debug(dap, bin.as_deref(), &features.into(), &program_args)
+        }
+        Commands::Check { features, tests, packages, .. } => {
+            let features = features.into();
+            let status = with_members(&packages, |role| {
+                if !role.selected {
+                    return ExitCode::SUCCESS;
+                }
+                cmd_check(&features, tests)
+            });
+            emit(Message::BuildFinished {
+                success: status == ExitCode::SUCCESS,
+            });
+            status
+        }
+        Commands::Doc { features, serve, port } => cmd_doc(&features.into(), serve.then_some(port)),
+        Commands::Lint {
+            features,
+            tests,
+            allow,
+            warn,
+            deny,
+            list,
+            packages,
+        } => {
+            if list {
+                return cmd_lint_list();
+            }
+            let features = features.into();
+            let flags = [(LintLevel::Allow, allow), (LintLevel::Warn, warn), (LintLevel::Deny, deny)];
+            let status = with_members(&packages, |role| {
+                if !role.selected {
+                    return ExitCode::SUCCESS;
+                }
+                cmd_lint(&features, tests, &flags)
+            });
+            emit(Message::BuildFinished {
+                success: status == ExitCode::SUCCESS,
+            });
+            status
+        }
+        Commands::Fix {
+            features,
+            tests,
+            dry_run,
+            packages,
+        } => {
+            let features = features.into();
+            with_members(&packages, |role| {
+                if !role.selected {
+                    return ExitCode::SUCCESS;
+                }
+                cmd_fix(&features, tests, dry_run)
+            })
+        }
+        Commands::Explain { code } => cmd_explain(code.as_deref()),
+        Commands::Bench {
+            filter,
+            features,
+            warmup,
+            samples,
+        } => {
+            let options = BenchOptions {
+                warmup: std::time::Duration::from_millis(warmup),
+                samples,
+                ..BenchOptions::default()
+            };
+            cmd_bench(filter.as_deref(), &options, &features.into())
+        }
+        Commands::Profile {
+            function,
+            tests,
+            tool,
+            flamegraph,
+            limit,
+            features,
+            program_args,
+            ..
+        } => {
+            let target = match (tests, function) {
+                (true, _) => ProfileTarget::Tests,
+                (false, Some(function)) => ProfileTarget::Function(function),
+                (false, None) => ProfileTarget::Main(program_args),
+            };
+            let tool = match (tool, &flamegraph) {
+                (Some(Tool::Eprof), Some(_)) => {
+                    eprintln!("Error: --flamegraph needs the call graph, which only fprof records");
+                    return ExitCode::from(1);
+                }
+                (Some(tool), _) => tool,
+                (None, Some(_)) => Tool::Fprof,
+                (None, None) => Tool::Eprof,
+            };
+            cmd_profile(&target, tool, flamegraph.as_deref(), limit, &features.into())
+        }
+        Commands::Trace { pattern, node, cookie, returns, limit, rate } => {
+            cmd_trace(&pattern, &node, cookie, &TraceOptions { returns, limit, rate })
+        }
+        Commands::Top { node, cookie, sort, interval } => cmd_top(&node, cookie, sort, interval),
+        Commands::Clean { package, cache } => cmd_clean(&package, cache),
         Commands::Bindgen {
             files,
+            include_lib,
+            records,
             output,
             module,
-        } => bindgen::cmd_bindgen(&files, output.as_deref(), module.as_deref()),
+        } => bindgen::cmd_bindgen(&files, &include_lib, records, output.as_deref(), module.as_deref()),
         Commands::Version => {
             println!("dream {}", env!("CARGO_PKG_VERSION"));
             ExitCode::SUCCESS
         }
-        Commands::Shell => {
+        Commands::Shell { workdir } => {
+            if !check_toolchain("start the shell") {
+                return ExitCode::from(1);
+            }
             // Compile stdlib first so it's available in the shell
             if let Err(e) = compile_stdlib() {
                 eprintln!("Warning: Failed to compile stdlib: {}", e);
             }
-            repl::run_shell()
+            repl::run_shell(project_code_paths(), workdir.as_deref())
         }
+        Commands::Lsp => cmd_lsp(),
+        Commands::Fmt { files, check } => cmd_fmt(&files, check),
+        Commands::Refactor { action } => cmd_refactor(action),
         Commands::Deps { action } => cmd_deps(action),
+        Commands::Nif { action } => cmd_nif(action),
     }
 }
 
-/// Create a new Dream project.
-fn cmd_new(name: &str) -> ExitCode {
-    let project_dir = Path::new(name);
-
-    if project_dir.exists() {
-        eprintln!("Error: directory '{}' already exists", name);
-        return ExitCode::from(1);
+/// Serve LSP requests on stdin and stdout until the editor exits.
+fn cmd_lsp() -> ExitCode {
+    let mut context = load_stub_modules();
+    context.extend(load_stdlib_modules());
+    match dream::lsp::serve(io::stdin().lock(), io::stdout(), context) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(1)
+        }
     }
+}
 
-    // Create directory structure
-    let src_dir = project_dir.join("src");
-    if let Err(e) = fs::create_dir_all(&src_dir) {
-        eprintln!("Error creating directories: {}", e);
-        return ExitCode::from(1);
+/// Format the given files, or every source file of the project, in place.
+fn cmd_fmt(paths: &[PathBuf], check: bool) -> ExitCode {
+    let mut files = Vec::new();
+    if paths.is_empty() {
+        let (project_root, config) = match ProjectConfig::from_project_root() {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(1);
+            }
+        };
+        files = collect_dream_files_recursive(&config.src_dir(&project_root));
+    } else {
+        for path in paths {
+            if path.is_dir() {
+                files.extend(collect_dream_files_recursive(path));
+            } else {
+                files.push(path.clone());
+            }
+        }
     }
+    files.sort();
 
-    // Write dream.toml
-    let toml_path = project_dir.join("dream.toml");
-    if let Err(e) = fs::write(&toml_path, generate_dream_toml(name)) {
-        eprintln!("Error writing dream.toml: {}", e);
-        return ExitCode::from(1);
+    let mut failed = false;
+    let mut unformatted = 0;
+    for path in &files {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("Error reading {}: {}", path.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                eprintln!("Error: {}: {}", path.display(), e);
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{}", path.display());
+            unformatted += 1;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("Error writing {}: {}", path.display(), e);
+            failed = true;
+        }
     }
 
-    // Write src/main.dream
-    let main_path = src_dir.join("main.dream");
-    if let Err(e) = fs::write(&main_path, generate_main_dream(name)) {
-        eprintln!("Error writing main.dream: {}", e);
-        return ExitCode::from(1);
+    if check && unformatted > 0 {
+        eprintln!(
+            "{} file{} would be reformatted; run `dream fmt` to fix",
+            unformatted,
+            if unformatted == 1 { "" } else { "s" }
+        );
+    }
+    if failed || unformatted > 0 {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
     }
-
-    println!("Created project '{}'", name);
-    println!();
-    println!("  cd {}", name);
-    println!("  dream build");
-    println!("  dream run");
-
-    ExitCode::SUCCESS
 }
 
-/// Handle dependency management commands.
-fn cmd_deps(action: DepsAction) -> ExitCode {
-    // Find project root and load config
-    let (project_root, config) = match ProjectConfig::from_project_root() {
+/// Rename a function, type or variable across the project, or with
+/// `--dry-run` print the diff the rename would make.
+fn cmd_refactor(action: RefactorAction) -> ExitCode {
+    let RefactorAction::Rename { old, new, at, dry_run } = action;
+    let (project_root, _config) = match ProjectConfig::from_project_root() {
         Ok(result) => result,
         Err(e) => {
             eprintln!("Error: {}", e);
             return ExitCode::from(1);
         }
     };
+    let mut context = load_stub_modules();
+    context.extend(load_stdlib_modules());
+    let analysis = match Analysis::load(&project_root, context) {
+        Ok(analysis) => analysis,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
 
-    let deps_manager = DepsManager::new(project_root, config);
-
-    match action {
-        DepsAction::Get => {
-            // Use tokio runtime for async deps fetching
-            let rt = match tokio::runtime::Runtime::new() {
-                Ok(rt) => rt,
-                Err(e) => {
-                    eprintln!("Error creating runtime: {}", e);
-                    return ExitCode::from(1);
-                }
-            };
+    let symbol = match &at {
+        Some(at) => rename_target_at(&analysis, at, &old),
+        None => rename_target(&analysis, &old),
+    };
+    let rename = match symbol.and_then(|symbol| refactor::rename(&analysis, &symbol, &new)) {
+        Ok(rename) => rename,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    for note in &rename.notes {
+        eprintln!("note: {}", note);
+    }
 
-            if let Err(e) = rt.block_on(deps_manager.fetch_all()) {
-                eprintln!("Error fetching dependencies: {}", e);
-                return ExitCode::from(1);
-            }
+    let root = project_root.canonicalize().unwrap_or(project_root);
+    let mut places = 0;
+    for (module, edits) in &rename.edits {
+        let Some(module) = analysis.module(module) else {
+            continue;
+        };
+        let (Some(path), Some(source)) = (module.source_path.as_deref(), module.source.as_deref()) else {
+            continue;
+        };
+        if dry_run {
+            let shown = path.strip_prefix(&root).unwrap_or(path);
+            print!("{}", refactor::diff(&shown.display().to_string(), source, edits));
+        } else if let Err(e) = fs::write(path, refactor::apply(source, edits)) {
+            eprintln!("Error writing {}: {}", path.display(), e);
+            return ExitCode::from(1);
+        }
+        places += edits.len();
+    }
 
-            // Auto-generate bindings after fetching
-            if let Err(e) = deps_manager.generate_bindings() {
-                eprintln!("Warning: Failed to generate bindings: {}", e);
-                // Don't fail the command, just warn
-            }
+    let files = rename.edits.len();
+    let summary = format!(
+        "`{}` to `{}` in {} place{} across {} file{}",
+        old,
+        new,
+        places,
+        if places == 1 { "" } else { "s" },
+        files,
+        if files == 1 { "" } else { "s" }
+    );
+    if dry_run {
+        // The diff alone goes to stdout, so it can be piped to `patch`
+        eprintln!("Would rename {}", summary);
+    } else {
+        status!("Renamed {}", summary);
+    }
+    ExitCode::SUCCESS
+}
+
+/// The symbol named `old` at `at`, a `file:line:column` position.
+fn rename_target_at(analysis: &Analysis, at: &str, old: &str) -> Result<Symbol, String> {
+    let invalid = || format!("expected `file:line:column` for --at, got `{}`", at);
+    let mut parts = at.rsplitn(3, ':');
+    let (Some(column), Some(line), Some(file)) = (parts.next(), parts.next(), parts.next()) else {
+        return Err(invalid());
+    };
+    let (Ok(line), Ok(column)) = (line.parse::<usize>(), column.parse::<usize>()) else {
+        return Err(invalid());
+    };
+
+    let path = Path::new(file)
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", file, e))?;
+    let module = analysis
+        .modules()
+        .find(|m| m.source_path.as_deref() == Some(path.as_path()))
+        .ok_or_else(|| format!("{} is not a module of the project", file))?;
+    let offset = module
+        .source
+        .as_deref()
+        .and_then(|source| refactor::offset_at(source, line, column))
+        .ok_or_else(|| format!("{} has no line {}, column {}", file, line, column))?;
+    let symbol = analysis
+        .symbol_at(&module.name, offset)
+        .ok_or_else(|| format!("no function, type or variable at {}", at))?;
+
+    let expected = old.rsplit("::").next().unwrap_or(old);
+    match &symbol {
+        Symbol::Item { name, .. } | Symbol::Local { name, .. } if name != expected => {
+            Err(format!("the name at {} is `{}`, not `{}`", at, name, old))
+        }
+        _ => Ok(symbol),
+    }
+}
+
+/// The function or type `old` names: `module::name`, or a name only one
+/// module of the project declares.
+fn rename_target(analysis: &Analysis, old: &str) -> Result<Symbol, String> {
+    let declared = |module: &Module, name: &str| {
+        let symbol = Symbol::Item {
+            module: module.name.clone(),
+            name: name.to_string(),
+        };
+        analysis.definition(&symbol).map(|_| symbol)
+    };
+    if let Some((path, name)) = old.rsplit_once("::") {
+        let package = analysis.package().unwrap_or_default();
+        let module = analysis
+            .find_module(package, path)
+            .ok_or_else(|| format!("no module `{}` in the project", path))?;
+        return declared(module, name)
+            .ok_or_else(|| format!("`{}` declares no function or type `{}`", module.name, name));
+    }
+
+    let mut found: Vec<Symbol> = analysis.modules().filter_map(|module| declared(module, old)).collect();
+    match found.len() {
+        0 => Err(format!(
+            "no function or type `{}` in the project; give --at to rename a variable",
+            old
+        )),
+        1 => Ok(found.remove(0)),
+        _ => {
+            let mut modules: Vec<String> = found
+                .iter()
+                .filter_map(|symbol| match symbol {
+                    Symbol::Item { module, .. } => Some(format!("`{}::{}`", module, old)),
+                    _ => None,
+                })
+                .collect();
+            modules.sort();
+            Err(format!(
+                "`{}` is declared in more than one module: {}; give the full path or --at",
+                old,
+                modules.join(", ")
+            ))
+        }
+    }
+}
+
+/// Create a new Dream project.
+fn cmd_new(name: &str, lib: bool) -> ExitCode {
+    let project_dir = Path::new(name);
+
+    // The package name is the last path component (`dream new apps/foo` -> foo)
+    let package = project_dir
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or(name);
+    if let Err(e) = validate_package_name(package) {
+        eprintln!("Error: {}", e);
+        return ExitCode::from(1);
+    }
+
+    if project_dir.exists() {
+        eprintln!("Error: directory '{}' already exists", name);
+        return ExitCode::from(1);
+    }
+
+    for (relative, contents) in project_template(package, lib) {
+        let path = project_dir.join(relative);
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                eprintln!("Error creating directories: {}", e);
+                return ExitCode::from(1);
+            }
+        }
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("Error writing {}: {}", relative, e);
+            return ExitCode::from(1);
+        }
+    }
+
+    let kind = if lib { "library" } else { "binary" };
+    println!("Created {} project '{}'", kind, package);
+    println!();
+    println!("  cd {}", name);
+    println!("  dream build");
+    if lib {
+        println!("  dream test");
+    } else {
+        println!("  dream run");
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Handle NIF commands.
+fn cmd_nif(action: NifAction) -> ExitCode {
+    let NifAction::New { name } = action;
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    // The name is both a crate and a module path segment
+    if validate_package_name(&name).is_err() {
+        eprintln!(
+            "Error: invalid NIF name '{}': use lowercase letters, digits and underscores, starting with a letter",
+            name
+        );
+        return ExitCode::from(1);
+    }
+
+    let package = &config.package.name;
+    let files = nif_template(package, &config.package.src, &name);
+    if let Some((relative, _)) = files.iter().find(|(relative, _)| project_root.join(relative).exists()) {
+        eprintln!("Error: '{}' already exists", relative);
+        return ExitCode::from(1);
+    }
+    for (relative, contents) in &files {
+        let path = project_root.join(relative);
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                eprintln!("Error creating directories: {}", e);
+                return ExitCode::from(1);
+            }
+        }
+        if let Err(e) = fs::write(&path, contents) {
+            eprintln!("Error writing {}: {}", relative, e);
+            return ExitCode::from(1);
+        }
+    }
+
+    // The module loads `priv/native/lib<name>.so` of its application
+    let priv_dir = config
+        .target_layout(&project_root)
+        .lib_dir("dev")
+        .join(package)
+        .join("priv")
+        .join("native");
+    let priv_dir = priv_dir.strip_prefix(&project_root).unwrap_or(&priv_dir);
+    println!("Created NIF crate native/{} and module {}/{}.dream", name, config.package.src, name);
+    println!();
+    println!("Build the library into the application's priv directory:");
+    println!();
+    println!("  cargo build --release --manifest-path native/{}/Cargo.toml", name);
+    println!("  mkdir -p {}", priv_dir.display());
+    println!(
+        "  cp native/{}/target/release/lib{}.{} {}/lib{}.so",
+        name,
+        name,
+        std::env::consts::DLL_EXTENSION,
+        priv_dir.display(),
+        name
+    );
+
+    ExitCode::SUCCESS
+}
+
+/// Handle dependency management commands.
+fn cmd_deps(action: DepsAction) -> ExitCode {
+    // Find project root and load config
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    // A workspace fetches every member's dependencies into one deps/
+    let deps_manager = match load_workspace(&config) {
+        Ok(Some(workspace)) => match DepsManager::for_workspace(&workspace) {
+            Ok(manager) => manager,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(1);
+            }
+        },
+        Ok(None) => DepsManager::new(project_root, config),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    match action {
+        DepsAction::Get { locked } => {
+            // Use tokio runtime for async deps fetching
+            let rt = match tokio::runtime::Runtime::new() {
+                Ok(rt) => rt,
+                Err(e) => {
+                    eprintln!("Error creating runtime: {}", e);
+                    return ExitCode::from(1);
+                }
+            };
+
+            if let Err(e) = rt.block_on(deps_manager.fetch_all(locked)) {
+                eprintln!("Error fetching dependencies: {}", e);
+                return ExitCode::from(1);
+            }
+
+            // Auto-generate bindings after fetching
+            if let Err(e) = deps_manager.generate_bindings() {
+                eprintln!("Warning: Failed to generate bindings: {}", e);
+                // Don't fail the command, just warn
+            }
 
             ExitCode::SUCCESS
         }
@@ -266,11 +1390,123 @@ fn cmd_deps(action: DepsAction) -> ExitCode {
     }
 }
 
+/// Code paths for a shell started inside a project: the project's compiled
+/// modules plus every fetched dependency. Empty outside a project.
+fn project_code_paths() -> Vec<PathBuf> {
+    let Ok((project_root, config)) = ProjectConfig::from_project_root() else {
+        return Vec::new();
+    };
+    let mut paths = vec![config.beam_dir(&project_root)];
+    paths.extend(DepsManager::new(project_root, config).dep_ebin_paths());
+    paths
+}
+
+/// The workspace a package's config says it belongs to, if any.
+fn load_workspace(config: &ProjectConfig) -> ConfigResult<Option<Workspace>> {
+    match &config.workspace_root {
+        Some(root) => Workspace::load(root),
+        None => Ok(None),
+    }
+}
+
+/// Why a workspace member takes part in a command.
+#[derive(Debug, Clone, Copy)]
+struct MemberRole {
+    /// Picked by `-p`/`--workspace`, or the package the command ran in
+    selected: bool,
+    /// Another member taking part depends on it
+    dependency: bool,
+}
+
+/// The workspace and the names of the members `args` picks. Without `-p`
+/// or `--workspace`, a member picks itself and a workspace root without a
+/// [package] picks every member. `None` outside a workspace.
+fn select_members(args: &PackageArgs) -> ConfigResult<Option<(Workspace, Vec<String>)>> {
+    let root = ProjectConfig::find_project_root();
+    let workspace = match &root {
+        Some(root) if Workspace::is_virtual(&root.join("dream.toml"))? => Workspace::load(root)?,
+        Some(root) => Workspace::find(root)?,
+        None => None,
+    };
+    let Some(workspace) = workspace else {
+        if args.is_set() {
+            return Err(ConfigError::new(
+                "-p and --workspace need a dream.toml with a [workspace] section",
+            ));
+        }
+        return Ok(None);
+    };
+
+    let current = root.as_deref().and_then(|root| workspace.member_at(root));
+    let selected = match current {
+        _ if !args.package.is_empty() => args.package.clone(),
+        Some(member) if !args.workspace => vec![member.name().to_string()],
+        _ => workspace.members.iter().map(|m| m.name().to_string()).collect(),
+    };
+    Ok(Some((workspace, selected)))
+}
+
+/// Run `command` for the packages `-p`/`--workspace` pick. In a workspace
+/// that is every picked member plus the members they depend on, each in
+/// its own directory and after its dependencies; the first failure stops
+/// the rest. Outside a workspace `command` runs once for the current
+/// package.
+fn with_members(args: &PackageArgs, mut command: impl FnMut(MemberRole) -> ExitCode) -> ExitCode {
+    let (workspace, selected) = match select_members(args) {
+        Ok(Some(selection)) => selection,
+        Ok(None) => {
+            return command(MemberRole {
+                selected: true,
+                dependency: false,
+            })
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let names: Vec<&str> = selected.iter().map(String::as_str).collect();
+    let order = match workspace.build_order(&names) {
+        Ok(order) => order,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let needed: HashSet<&str> = order
+        .iter()
+        .flat_map(|member| workspace.member_dependencies(member))
+        .collect();
+
+    for member in order {
+        if let Err(e) = std::env::set_current_dir(&member.root) {
+            eprintln!("Error: cannot enter {}: {}", member.root.display(), e);
+            return ExitCode::from(1);
+        }
+        let status = command(MemberRole {
+            selected: names.contains(&member.name()),
+            dependency: needed.contains(member.name()),
+        });
+        if status != ExitCode::SUCCESS {
+            return status;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
 /// Build the project or a standalone file.
-fn cmd_build(file: Option<&Path>, target: &str, output: Option<&Path>, features: &[String]) -> ExitCode {
+fn cmd_build(
+    file: Option<&Path>,
+    target: &str,
+    output: Option<&Path>,
+    stages: &[EmitKind],
+    features: &FeatureSelection,
+    profile: &str,
+    locked: bool,
+) -> ExitCode {
     // Determine if we're building a standalone file or a project
     if let Some(source_file) = file {
-        return build_standalone_file(source_file, target, output, features);
+        return build_standalone_file(source_file, target, output, stages, features, profile);
     }
 
     // Project mode: find project root and load config
@@ -282,9 +1518,34 @@ fn cmd_build(file: Option<&Path>, target: &str, output: Option<&Path>, features:
         }
     };
 
+    if locked {
+        let verified = Lockfile::read(config.shared_root(&project_root)).and_then(|lock| match lock {
+            Some(lock) => match load_workspace(&config)? {
+                // The lockfile is shared, so it covers every member
+                Some(workspace) => lock.verify_dependencies(&workspace.dependencies()?),
+                None => lock.verify(&config),
+            },
+            None => Err(DepsError::new(format!("{} does not exist", LOCKFILE_NAME))),
+        });
+        if let Err(e) = verified {
+            eprintln!("Error: --locked: {}", e);
+            return ExitCode::from(1);
+        }
+    }
+
+    // Features and paths come from dream.toml; CLI features add to the defaults
+    let mut compile_options = match project_compile_options(&config, &project_root, features, profile, false) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    compile_options.emit = stages.to_vec();
     let src_dir = config.src_dir(&project_root);
     let build_dir = output
         .map(|p| p.to_path_buf())
+        .or_else(|| compile_options.out_dir.clone())
         .unwrap_or_else(|| config.beam_dir(&project_root));
 
     // Create build directory
@@ -298,7 +1559,7 @@ fn cmd_build(file: Option<&Path>, target: &str, output: Option<&Path>, features:
         eprintln!("Warning: {}", e);
     }
 
-    println!("Compiling {}...", config.package.name);
+    status!("Compiling {}...", config.package.name);
 
     // Load all .dream files in src/ directory with package context
     // This enables Rust-style module naming (e.g., my_app::users::auth)
@@ -308,11 +1569,11 @@ fn cmd_build(file: Option<&Path>, target: &str, output: Option<&Path>, features:
     );
 
     // Add _build/bindings/ to search path for auto-generated dependency bindings
-    let bindings_dir = project_root.join("_build").join("bindings");
+    let bindings_dir = config.target_layout(&project_root).bindings_dir();
     loader.add_bindings_dir(bindings_dir);
 
     if let Err(e) = loader.load_all_in_dir(&src_dir) {
-        eprintln!("Error loading modules: {}", e);
+        report_load_error(&e);
         return ExitCode::from(1);
     }
 
@@ -320,10 +1581,6 @@ fn cmd_build(file: Option<&Path>, target: &str, output: Option<&Path>, features:
     let modules = loader.into_modules();
     let module_names: Vec<String> = modules.iter().map(|m| m.name.clone()).collect();
 
-    // Resolve features (CLI features + their dependencies from config)
-    let resolved_features = config.resolve_features(features);
-    let compile_options = CompileOptions::with_features(resolved_features);
-
     // Get dependency ebin paths for loading macros from dependencies
     let deps_manager = DepsManager::new(project_root.clone(), config.clone());
     let dep_ebin_paths = deps_manager.dep_ebin_paths();
@@ -343,7 +1600,7 @@ fn cmd_build(file: Option<&Path>, target: &str, output: Option<&Path>, features:
     );
 
     // Generate .app file if compilation succeeded
-    if result == ExitCode::SUCCESS && target == "beam" {
+    if result == ExitCode::SUCCESS && target == "beam" && compile_options.emits(EmitKind::Beam) {
         if let Err(e) = generate_app_file(&build_dir, &config, &module_names) {
             eprintln!("Warning: Failed to generate .app file: {}", e);
         }
@@ -353,7 +1610,14 @@ fn cmd_build(file: Option<&Path>, target: &str, output: Option<&Path>, features:
 }
 
 /// Build a standalone .dream file.
-fn build_standalone_file(source_file: &Path, target: &str, output: Option<&Path>, features: &[String]) -> ExitCode {
+fn build_standalone_file(
+    source_file: &Path,
+    target: &str,
+    output: Option<&Path>,
+    stages: &[EmitKind],
+    features: &FeatureSelection,
+    profile: &str,
+) -> ExitCode {
     if !source_file.exists() {
         eprintln!("Error: file not found: {}", source_file.display());
         return ExitCode::from(1);
@@ -363,9 +1627,18 @@ fn build_standalone_file(source_file: &Path, target: &str, output: Option<&Path>
     if let Some(project_root) = find_project_root(source_file) {
         if let Ok(config) = ProjectConfig::load(&project_root.join("dream.toml")) {
             // This is a project file - use project mode
+            let mut compile_options = match project_compile_options(&config, &project_root, features, profile, false) {
+                Ok(options) => options,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return ExitCode::from(1);
+                }
+            };
+            compile_options.emit = stages.to_vec();
             let src_dir = config.src_dir(&project_root);
             let build_dir = output
                 .map(|p| p.to_path_buf())
+                .or_else(|| compile_options.out_dir.clone())
                 .unwrap_or_else(|| config.beam_dir(&project_root));
 
             if let Err(e) = fs::create_dir_all(&build_dir) {
@@ -378,7 +1651,7 @@ fn build_standalone_file(source_file: &Path, target: &str, output: Option<&Path>
                 eprintln!("Warning: {}", e);
             }
 
-            println!("Compiling {}...", config.package.name);
+            status!("Compiling {}...", config.package.name);
 
             let mut loader = ModuleLoader::with_package(
                 config.package.name.clone(),
@@ -386,11 +1659,11 @@ fn build_standalone_file(source_file: &Path, target: &str, output: Option<&Path>
             );
 
             // Add _build/bindings/ to search path for auto-generated dependency bindings
-            let bindings_dir = project_root.join("_build").join("bindings");
+            let bindings_dir = config.target_layout(&project_root).bindings_dir();
             loader.add_bindings_dir(bindings_dir);
 
             if let Err(e) = loader.load_all_in_dir(&src_dir) {
-                eprintln!("Error loading modules: {}", e);
+                report_load_error(&e);
                 return ExitCode::from(1);
             }
 
@@ -398,10 +1671,6 @@ fn build_standalone_file(source_file: &Path, target: &str, output: Option<&Path>
             let modules = loader.into_modules();
             let module_names: Vec<String> = modules.iter().map(|m| m.name.clone()).collect();
 
-            // Resolve features
-            let resolved_features = config.resolve_features(features);
-            let compile_options = CompileOptions::with_features(resolved_features);
-
             // Get dependency ebin paths for loading macros from dependencies
             let deps_manager = DepsManager::new(project_root.clone(), config.clone());
             let dep_ebin_paths = deps_manager.dep_ebin_paths();
@@ -420,7 +1689,7 @@ fn build_standalone_file(source_file: &Path, target: &str, output: Option<&Path>
             );
 
             // Generate .app file if compilation succeeded
-            if result == ExitCode::SUCCESS && target == "beam" {
+            if result == ExitCode::SUCCESS && target == "beam" && compile_options.emits(EmitKind::Beam) {
                 if let Err(e) = generate_app_file(&build_dir, &config, &module_names) {
                     eprintln!("Warning: Failed to generate .app file: {}", e);
                 }
@@ -446,9 +1715,9 @@ fn build_standalone_file(source_file: &Path, target: &str, output: Option<&Path>
         return ExitCode::from(1);
     }
 
-    println!("Compiling {}...", source_file.display());
+    status!("Compiling {}...", source_file.display());
 
-    compile_and_emit(source_file, &build_dir, target, features)
+    compile_and_emit(source_file, &build_dir, target, stages, features)
 }
 
 /// Find the project root by looking for dream.toml in current and parent directories.
@@ -471,7 +1740,13 @@ fn find_project_root(start: &Path) -> Option<PathBuf> {
 }
 
 /// Compile source file(s) and emit to build directory.
-fn compile_and_emit(entry_file: &Path, build_dir: &Path, target: &str, features: &[String]) -> ExitCode {
+fn compile_and_emit(
+    entry_file: &Path,
+    build_dir: &Path,
+    target: &str,
+    stages: &[EmitKind],
+    features: &FeatureSelection,
+) -> ExitCode {
     // Load modules
     let mut loader = ModuleLoader::new();
     if let Err(e) = loader.load_project(entry_file) {
@@ -479,24 +1754,107 @@ fn compile_and_emit(entry_file: &Path, build_dir: &Path, target: &str, features:
         return ExitCode::from(1);
     }
 
-    // Create compile options from features (standalone files have no feature resolution)
-    let resolved_features: HashSet<String> = features.iter().cloned().collect();
-    let compile_options = CompileOptions::with_features(resolved_features);
+    let compile_options = match standalone_compile_options(stages, features) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
 
     // Standalone files don't have a package context or dependencies
     compile_modules_with_options(loader.into_modules(), build_dir, target, None, &compile_options, &[], &std::collections::HashSet::new())
 }
 
+/// Compile options for files outside a project: the features and cfg
+/// options as given, since there is no dream.toml to resolve them with.
+fn standalone_compile_options(stages: &[EmitKind], features: &FeatureSelection) -> ConfigResult<CompileOptions> {
+    let resolved_features: HashSet<String> = features.features.iter().cloned().collect();
+    let mut compile_options = CompileOptions {
+        emit: stages.to_vec(),
+        otp_release: otp_release(),
+        ..CompileOptions::with_features(resolved_features)
+    };
+    apply_warning_flags(&mut compile_options.warnings)?;
+    for spec in &features.cfg {
+        compile_options.cfg.insert(parse_cfg(spec)?);
+    }
+    Ok(compile_options)
+}
+
+/// Compile `files`, and the modules they declare with `mod`, into `ebin`,
+/// for a build tool such as Mix or rebar3. Modules are named by file, or
+/// as modules of `package` with sources in its directory. Bindings are
+/// looked for in `include`; `code_path` holds the dependencies' compiled
+/// modules, each in `<app>/ebin`.
+fn cmd_compile_files(
+    files: &[PathBuf],
+    ebin: &Path,
+    package: Option<(&str, &Path)>,
+    include: &[PathBuf],
+    code_path: &[PathBuf],
+    features: &FeatureSelection,
+) -> ExitCode {
+    let compile_options = match standalone_compile_options(&[], features) {
+        Ok(options) => CompileOptions {
+            out_dir: Some(ebin.to_path_buf()),
+            ..options
+        },
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    if let Err(e) = fs::create_dir_all(ebin) {
+        eprintln!("Error creating {}: {}", ebin.display(), e);
+        return ExitCode::from(1);
+    }
+    if let Err(e) = compile_stdlib() {
+        eprintln!("Warning: {}", e);
+    }
+
+    let mut loader = match package {
+        Some((name, src)) => ModuleLoader::with_package(name.to_string(), src.to_path_buf()),
+        None => ModuleLoader::new(),
+    };
+    for dir in include {
+        loader.add_bindings_dir(dir.clone());
+    }
+    for file in files {
+        if let Err(e) = loader.load(file) {
+            report_load_error(&e);
+            return ExitCode::from(1);
+        }
+    }
+
+    // Dependencies are named by their application directories
+    let dependencies: HashSet<String> = code_path
+        .iter()
+        .filter(|dir| dir.file_name().is_some_and(|name| name == "ebin"))
+        .filter_map(|dir| Some(dir.parent()?.file_name()?.to_str()?.to_string()))
+        .collect();
+    compile_modules_with_options(
+        loader.into_modules(),
+        ebin,
+        "beam",
+        package.map(|(name, _)| name),
+        &compile_options,
+        code_path,
+        &dependencies,
+    )
+}
+
 /// Compile modules to Core Erlang and optionally BEAM.
 fn compile_modules(
     modules: Vec<Module>,
     build_dir: &Path,
     target: &str,
     package_name: Option<&str>,
+    compile_options: &CompileOptions,
 ) -> ExitCode {
     // Use stdlib generics registry if available
     let stdlib_registry = load_stdlib_generics();
-    compile_modules_with_registry(modules, build_dir, target, stdlib_registry, package_name, &[])
+    compile_modules_with_registry(modules, build_dir, target, stdlib_registry, package_name, compile_options, &[])
 }
 
 /// Compile modules to Core Erlang and optionally BEAM, with compile options for cfg filtering.
@@ -530,6 +1888,7 @@ fn compile_modules_with_registry(
     target: &str,
     external_registry: Option<SharedGenericRegistry>,
     package_name: Option<&str>,
+    compile_options: &CompileOptions,
     dep_ebin_paths: &[PathBuf],
 ) -> ExitCode {
     if modules.is_empty() {
@@ -748,7 +2107,7 @@ fn compile_modules_with_registry(
                         } else {
                             format!("dream::{}", module.name)
                         };
-                        println!("  Compiled macro module {}.beam", beam_module_name);
+                        status!("  Compiled macro module {}.beam", beam_module_name);
 
                         // Register macros from this module and track for .macros file
                         for (derive_name, func_name) in get_macro_functions(module) {
@@ -796,9 +2155,10 @@ fn compile_modules_with_registry(
             None => ModuleContext::default(),
         };
 
-        let mut emitter = CoreErlangEmitter::with_registry_and_context(
+        let mut emitter = CoreErlangEmitter::with_all(
             generic_registry.clone(),
             module_context,
+            compile_options.clone(),
         );
         // Set extern module name mappings for #[name = "..."] attribute support
         emitter.set_extern_module_names(extern_module_names.clone());
@@ -832,24 +2192,21 @@ fn compile_modules_with_registry(
             return ExitCode::from(1);
         }
 
-        println!("  Compiled {}.core", &beam_module_name);
+        status!("  Compiled {}.core", &beam_module_name);
         core_files.push(core_file);
     }
 
     // If target is "core", we're done
     if target == "core" {
-        println!();
-        println!("Build complete. Core Erlang files in {}", build_dir.display());
+        status!();
+        status!("Build complete. Core Erlang files in {}", build_dir.display());
         return ExitCode::SUCCESS;
     }
 
     // For "beam" target, invoke erlc
     if target == "beam" {
         // Check if erlc is available
-        if !command_exists("erlc") {
-            eprintln!();
-            eprintln!("Warning: erlc not found in PATH");
-            eprintln!("Install Erlang/OTP to compile to BEAM bytecode.");
+        if !check_toolchain("compile to BEAM bytecode") {
             eprintln!();
             eprintln!("Core Erlang files are in {}", build_dir.display());
             eprintln!("You can compile manually with: erlc +from_core *.core");
@@ -857,7 +2214,7 @@ fn compile_modules_with_registry(
         }
 
         for core_file in &core_files {
-            let status = Command::new("erlc")
+            let status = toolchain::erlc()
                 .arg("+from_core")
                 .arg("-o")
                 .arg(build_dir)
@@ -867,7 +2224,7 @@ fn compile_modules_with_registry(
             match status {
                 Ok(s) if s.success() => {
                     let beam_name = core_file.file_stem().unwrap().to_string_lossy();
-                    println!("  Compiled {}.beam", beam_name);
+                    status!("  Compiled {}.beam", beam_name);
                     // Clean up intermediate .core file
                     let _ = fs::remove_file(core_file);
                 }
@@ -892,15 +2249,15 @@ fn compile_modules_with_registry(
         }
     }
 
-    println!();
-    println!("Build complete.");
+    status!();
+    status!("Build complete.");
 
     ExitCode::SUCCESS
 }
 
 /// Compile modules to Core Erlang and optionally BEAM, with registry and compile options.
 fn compile_modules_with_registry_and_options(
-    modules: Vec<Module>,
+    mut modules: Vec<Module>,
     build_dir: &Path,
     target: &str,
     external_registry: Option<SharedGenericRegistry>,
@@ -913,130 +2270,47 @@ fn compile_modules_with_registry_and_options(
         eprintln!("No modules to compile");
         return ExitCode::from(1);
     }
+    if !configure_modules(&mut modules, compile_options) {
+        eprintln!("\nCompilation failed due to previous errors.");
+        return ExitCode::from(1);
+    }
 
-    // Load stub modules for FFI type checking
-    let stub_modules = load_stub_modules();
-
-    // Load all stdlib modules for type checking
-    // This is needed even when compiling stdlib itself, because stdlib modules
-    // may depend on extern modules defined in other stdlib files
-    let stdlib_modules_full = load_stdlib_modules();
+    // Dump the front end's output before type checking, so it's there to
+    // look at when type checking fails
+    for module in &modules {
+        let name = beam_module_name(&module.name);
+        if compile_options.emits(EmitKind::Tokens) {
+            let tokens = module.source.as_deref().map(dump_tokens).unwrap_or_default();
+            if !write_emitted(build_dir, &name, EmitKind::Tokens, &tokens) {
+                return ExitCode::from(1);
+            }
+        }
+        if compile_options.emits(EmitKind::Ast) && !write_emitted(build_dir, &name, EmitKind::Ast, &dump_ast(module)) {
+            return ExitCode::from(1);
+        }
+    }
 
-    // Check if we're compiling stdlib itself (by checking if any module shares a name with stdlib)
-    let stdlib_module_names_raw: std::collections::HashSet<_> = stdlib_modules_full
-        .iter()
-        .map(|m| m.name.clone())
-        .collect();
+    // Type check all modules together (allows cross-module type references)
+    // This also annotates the AST with inferred type arguments
+    let Some(CheckedModules {
+        modules: annotated_modules,
+        extern_module_names,
+        struct_info,
+        ..
+    }) = type_check_modules(&modules, compile_options)
+    else {
+        eprintln!("\nCompilation failed due to type errors.");
+        return ExitCode::from(1);
+    };
 
-    let user_module_names: std::collections::HashSet<_> = modules.iter()
-        .map(|m| m.name.clone())
-        .collect();
+    // Use annotated modules for code generation, dependencies first so that
+    // generic functions are registered before the modules that call them
+    let mut modules = order_by_dependency(annotated_modules, package_name);
 
-    let is_compiling_stdlib = user_module_names.iter().any(|n| stdlib_module_names_raw.contains(n));
-
-    // When compiling stdlib itself, filter out modules being compiled to avoid duplicates
-    // but keep other stdlib modules for type checking (e.g., extern module definitions)
-    let stdlib_modules: Vec<Module> = if is_compiling_stdlib {
-        stdlib_modules_full
-            .into_iter()
-            .filter(|m| !user_module_names.contains(&m.name))
-            .collect()
-    } else {
-        stdlib_modules_full
-    };
-
-    // Combine user modules with stub modules and stdlib for type checking
-    let mut all_modules_for_typeck: Vec<Module> = stub_modules;
-    all_modules_for_typeck.extend(stdlib_modules.iter().cloned());
-    all_modules_for_typeck.extend(modules.iter().cloned());
-
-    // Type check all modules together (allows cross-module type references)
-    // This also annotates the AST with inferred type arguments
-    let mut has_errors = false;
-
-    let mut annotated_modules: Vec<Module> = Vec::new();
-    let type_check_result = check_modules_with_metadata(&all_modules_for_typeck);
-    let extern_module_names = type_check_result.extern_module_names.clone();
-    let struct_info = type_check_result.struct_info.clone();
-
-    // List of stdlib module names for filtering
-    let stdlib_module_names: std::collections::HashSet<_> = stdlib_modules.iter()
-        .map(|m| m.name.clone())
-        .collect();
-
-    // Display warnings (filter out stdlib warnings) using miette
-    for warning in &type_check_result.warnings {
-        let is_stdlib = warning.module.as_ref()
-            .map(|m| stdlib_module_names.contains(m))
-            .unwrap_or(false);
-        if !is_stdlib {
-            // Try to find source code for rich diagnostics
-            if let Some(module_name) = &warning.module {
-                if let Some(module) = modules.iter().find(|m| &m.name == module_name) {
-                    if let Some(ref source) = module.source {
-                        let compiler_warning = CompilerWarning::from_warning(module_name, source, warning.clone());
-                        eprintln!("{:?}", miette::Report::new(compiler_warning));
-                        continue;
-                    }
-                }
-            }
-            // Fallback: simple warning without source context
-            eprintln!("  warning: {}", warning.message);
-            if let Some(help) = &warning.help {
-                eprintln!("    help: {}", help);
-            }
-        }
-    }
-
-    for (module_name, result) in type_check_result.modules {
-        // Skip stub modules (they don't have function bodies)
-        if module_name.ends_with("_stubs") || module_name == "erlang" {
-            continue;
-        }
-
-        // Skip stdlib modules for error reporting (but still process them for annotation)
-        let is_stdlib = stdlib_module_names.contains(&module_name);
-
-        match result {
-            Ok(annotated) => {
-                // Only keep user modules, not stdlib modules that were added for type checking
-                if modules.iter().any(|m| m.name == module_name) {
-                    annotated_modules.push(annotated);
-                }
-            }
-            Err(e) => {
-                // Only report errors for user modules, not stdlib
-                if !is_stdlib {
-                    has_errors = true;
-                    // Find the module to get source for error display
-                    if let Some(module) = modules.iter().find(|m| m.name == module_name) {
-                        if let Some(ref source) = module.source {
-                            let err = CompilerError::type_error(&module_name, source, e);
-                            eprintln!("  Type error in {}:\n{:?}", module_name, miette::Report::new(err));
-                        } else {
-                            eprintln!("  Type error in {}: {:?}", module_name, miette::Report::new(e));
-                        }
-                    } else {
-                        // Module not found in user modules - this shouldn't happen
-                        eprintln!("  Type error in {}: {:?}", module_name, e);
-                    }
-                }
-            }
-        }
-    }
-
-    if has_errors {
-        eprintln!("\nCompilation failed due to type errors.");
-        return ExitCode::from(1);
-    }
-
-    // Use annotated modules for code generation
-    let mut modules = annotated_modules;
-
-    // Create a shared registry for cross-module generic functions
-    // Start with external (stdlib) generics if available
-    let generic_registry: SharedGenericRegistry = external_registry
-        .unwrap_or_else(|| Arc::new(RwLock::new(GenericFunctionRegistry::new())));
+    // Create a shared registry for cross-module generic functions
+    // Start with external (stdlib) generics if available
+    let generic_registry: SharedGenericRegistry = external_registry
+        .unwrap_or_else(|| Arc::new(RwLock::new(GenericFunctionRegistry::new())));
 
     // Collect local module short names for module resolution
     // These are the short names (e.g., "hello_handler") that can be referenced
@@ -1096,7 +2370,8 @@ fn compile_modules_with_registry_and_options(
                 let mut macro_module = module.clone();
                 if let Err(errors) = expand_derives_with_registry(&mut macro_module, &mut MacroRegistry::new()) {
                     for err in errors {
-                        eprintln!("Derive error in macro module {}: {}", module_name, err.message);
+                        let rendered = format!("Derive error in macro module {}: {}", module_name, err.message);
+                        report_error(module_name, &err.message, ErrorCode::MacroExpansion, rendered);
                     }
                     return ExitCode::from(1);
                 }
@@ -1125,7 +2400,7 @@ fn compile_modules_with_registry_and_options(
                         } else {
                             format!("dream::{}", module.name)
                         };
-                        println!("  Compiled macro module {}.beam", beam_module_name);
+                        status!("  Compiled macro module {}.beam", beam_module_name);
 
                         // Register macros from this module and track for .macros file
                         for (derive_name, func_name) in get_macro_functions(module) {
@@ -1142,43 +2417,86 @@ fn compile_modules_with_registry_and_options(
         }
     }
 
-    // Expand derive macros (e.g., #[derive(Debug, Clone)]) with macro registry
+    // Expand derive macros (e.g., #[derive(Debug, Clone)]) with macro registry.
+    // Keep going after a failure so every module's errors are reported.
+    let mut error_count = 0;
     for module in &mut modules {
-        if let Err(errors) = expand_derives_with_registry(module, &mut macro_registry) {
+        let name = module.name.clone();
+        let expanded = timings::time(Phase::Resolve, Some(&name), || {
+            expand_derives_with_registry(module, &mut macro_registry)
+        });
+        if let Err(errors) = expanded {
             for err in errors {
-                eprintln!("Derive error: {}", err.message);
+                let rendered = format!("Derive error in {}: {}", module.name, err.message);
+                report_error(&module.name, &err.message, ErrorCode::MacroExpansion, rendered);
+                error_count += 1;
             }
-            return ExitCode::from(1);
         }
     }
+    if error_count > 0 {
+        return compilation_failed(error_count);
+    }
 
-    // Expand quote expressions in all modules (quote { ... } -> tuple construction)
+    // Expand quote expressions in all modules (quote { ... } -> tuple construction),
+    // then resolve stdlib method calls (e.g., s.trim() -> string::trim(s))
     for module in &mut modules {
-        expand_quotes(module);
+        let name = module.name.clone();
+        timings::time(Phase::Resolve, Some(&name), || {
+            expand_quotes(module);
+            resolve_stdlib_methods(module);
+        });
     }
 
-    // Resolve stdlib method calls (e.g., s.trim() -> string::trim(s))
-    for module in &mut modules {
-        resolve_stdlib_methods(module);
+    if compile_options.emits(EmitKind::TypedAst) {
+        for module in &modules {
+            let name = beam_module_name(&module.name);
+            if !write_emitted(build_dir, &name, EmitKind::TypedAst, &dump_ast(module)) {
+                return ExitCode::from(1);
+            }
+        }
     }
 
-    // Compile each module to Core Erlang (with incremental compilation)
+    // With only front end stages requested there is nothing to generate
+    let keep_core = target == "core" || compile_options.emits(EmitKind::Core);
+    let run_erlc = target == "beam" && compile_options.emits(EmitKind::Beam);
+    if !compile_options.emit.is_empty() && !keep_core && !run_erlc {
+        status!();
+        status!("Build complete. Output in {}", build_dir.display());
+        return ExitCode::SUCCESS;
+    }
+
+    // Compile each module to Core Erlang (with incremental compilation).
+    // A module is recompiled when its source is newer than its .beam, or
+    // when a module it depends on is being recompiled. `--emit core` asks
+    // for every module's Core Erlang, so then all of them are.
+    let beam_file_for = |module: &Module| {
+        let name = beam_module_name(&module.name);
+        let beam_file = build_dir.join(format!("{}.beam", &name));
+        (name, beam_file)
+    };
+    let stale: HashSet<String> = modules
+        .iter()
+        .filter(|module| {
+            compile_options.emits(EmitKind::Core) || needs_recompilation(module, &beam_file_for(module).1)
+        })
+        .map(|module| module.name.clone())
+        .collect();
+    let stale = affected_modules(&modules, &stale, package_name);
+
     let mut core_files = Vec::new();
     let mut skipped_count = 0;
 
     for module in &modules {
-        // All Dream modules are prefixed with dream:: (like Elixir uses Elixir.)
-        let beam_module_name = if module.name.starts_with("dream::") {
-            module.name.clone()
-        } else {
-            format!("dream::{}", module.name)
-        };
-
-        let beam_file = build_dir.join(format!("{}.beam", &beam_module_name));
+        let (beam_module_name, beam_file) = beam_file_for(module);
 
         // Check if module needs recompilation
-        if !needs_recompilation(module, &beam_file) {
+        if !stale.contains(&module.name) {
             skipped_count += 1;
+            emit(Message::CompilerArtifact {
+                module: beam_module_name,
+                filename: beam_file,
+                fresh: true,
+            });
             continue;
         }
 
@@ -1201,11 +2519,13 @@ fn compile_modules_with_registry_and_options(
         // Set struct info for Erlang record compilation support
         emitter.set_struct_info(struct_info.clone());
 
-        let core_erlang = match emitter.emit_module(module) {
+        let core_erlang = match timings::time(Phase::Codegen, Some(&module.name), || emitter.emit_module(module)) {
             Ok(c) => c,
             Err(e) => {
-                eprintln!("Compile error in {}: {}", module.name, e);
-                return ExitCode::from(1);
+                let rendered = format!("Compile error in {}: {}", module.name, e);
+                report_error(&module.name, &e.to_string(), ErrorCode::CodegenFailed, rendered);
+                error_count += 1;
+                continue;
             }
         };
 
@@ -1221,28 +2541,36 @@ fn compile_modules_with_registry_and_options(
             return ExitCode::from(1);
         }
 
-        println!("  Compiled {}.core", &beam_module_name);
+        status!("  Compiled {}.core", &beam_module_name);
+        if keep_core {
+            emit(Message::CompilerArtifact {
+                module: beam_module_name,
+                filename: core_file.clone(),
+                fresh: false,
+            });
+        }
         core_files.push(core_file);
     }
 
-    // If target is "core", we're done
-    if target == "core" {
-        println!();
+    if error_count > 0 {
+        return compilation_failed(error_count);
+    }
+
+    // If only Core Erlang was asked for, we're done
+    if !run_erlc {
+        status!();
         if skipped_count > 0 {
-            println!("Build complete. {} module(s) up to date, {} recompiled.", skipped_count, core_files.len());
+            status!("Build complete. {} module(s) up to date, {} recompiled.", skipped_count, core_files.len());
         } else {
-            println!("Build complete. Core Erlang files in {}", build_dir.display());
+            status!("Build complete. Core Erlang files in {}", build_dir.display());
         }
         return ExitCode::SUCCESS;
     }
 
     // For "beam" target, invoke erlc
-    if target == "beam" && !core_files.is_empty() {
+    if !core_files.is_empty() {
         // Check if erlc is available
-        if !command_exists("erlc") {
-            eprintln!();
-            eprintln!("Warning: erlc not found in PATH");
-            eprintln!("Install Erlang/OTP to compile to BEAM bytecode.");
+        if !check_toolchain("compile to BEAM bytecode") {
             eprintln!();
             eprintln!("Core Erlang files are in {}", build_dir.display());
             eprintln!("You can compile manually with: erlc +from_core *.core");
@@ -1250,20 +2578,27 @@ fn compile_modules_with_registry_and_options(
         }
 
         // Batch compile all .core files in a single erlc invocation
-        let mut cmd = Command::new("erlc");
-        cmd.arg("+from_core").arg("-o").arg(build_dir);
+        let mut cmd = toolchain::erlc();
+        cmd.arg("+from_core").args(&compile_options.erlc_flags).arg("-o").arg(build_dir);
         for core_file in &core_files {
             cmd.arg(core_file);
         }
 
-        let status = cmd.status();
+        let status = timings::time(Phase::Erlc, None, || cmd.status());
         match status {
             Ok(s) if s.success() => {
                 for core_file in &core_files {
                     let beam_name = core_file.file_stem().unwrap().to_string_lossy();
-                    println!("  Compiled {}.beam", beam_name);
+                    status!("  Compiled {}.beam", beam_name);
+                    emit(Message::CompilerArtifact {
+                        module: beam_name.to_string(),
+                        filename: build_dir.join(format!("{}.beam", beam_name)),
+                        fresh: false,
+                    });
                     // Clean up intermediate .core file
-                    let _ = fs::remove_file(core_file);
+                    if !keep_core {
+                        let _ = fs::remove_file(core_file);
+                    }
                 }
             }
             Ok(s) => {
@@ -1286,131 +2621,431 @@ fn compile_modules_with_registry_and_options(
         }
     }
 
-    println!();
+    status!();
     if skipped_count > 0 && core_files.is_empty() {
-        println!("Build complete. All {} module(s) up to date.", skipped_count);
+        status!("Build complete. All {} module(s) up to date.", skipped_count);
     } else if skipped_count > 0 {
-        println!("Build complete. {} module(s) up to date, {} recompiled.", skipped_count, core_files.len());
+        status!("Build complete. {} module(s) up to date, {} recompiled.", skipped_count, core_files.len());
     } else {
-        println!("Build complete.");
+        status!("Build complete.");
     }
 
     ExitCode::SUCCESS
 }
 
-/// Generate an OTP .app file for the Dream application.
-fn generate_app_file(
-    build_dir: &Path,
-    config: &ProjectConfig,
-    module_names: &[String],
-) -> Result<(), String> {
-    // OTP application name is just the package name (no dream:: prefix)
-    let app_name = &config.package.name;
-    let version = &config.package.version;
+/// The name of a Dream module on the BEAM. All Dream modules are prefixed
+/// with `dream::`, like Elixir uses `Elixir.`.
+fn beam_module_name(name: &str) -> String {
+    if name.starts_with("dream::") {
+        name.to_string()
+    } else {
+        format!("dream::{}", name)
+    }
+}
+
+/// Write the `kind` stage of the BEAM module `module` to `build_dir`, for
+/// `--emit`. Returns false after reporting a failure.
+fn write_emitted(build_dir: &Path, module: &str, kind: EmitKind, contents: &str) -> bool {
+    let file = build_dir.join(format!("{}.{}", module, kind.extension()));
+    if let Err(e) = fs::write(&file, contents) {
+        eprintln!("Error writing {}: {}", file.display(), e);
+        return false;
+    }
+    status!("  Emitted {}.{}", module, kind.extension());
+    emit(Message::CompilerArtifact {
+        module: module.to_string(),
+        filename: file,
+        fresh: false,
+    });
+    true
+}
+
+/// Parse one `--emit` value.
+fn parse_emit_kind(name: &str) -> Result<EmitKind, String> {
+    EmitKind::from_name(name).ok_or_else(|| {
+        let names: Vec<_> = EmitKind::ALL.iter().map(|kind| kind.name()).collect();
+        format!("expected one of {}", names.join(", "))
+    })
+}
+
+/// Type checked user modules, with what code generation needs to know
+/// about them.
+struct CheckedModules {
+    /// User modules annotated with inferred types
+    modules: Vec<Module>,
+    extern_module_names: HashMap<String, String>,
+    struct_info: HashMap<String, StructInfo>,
+    /// Warnings about user modules
+    warnings: Vec<Warning>,
+}
 
-    // Format module list as Erlang atoms with dream:: prefix
-    // Module names are fully qualified (e.g., dream::http_api::hello_handler)
-    let modules_str = module_names
+/// Configure `modules` for `options` before they are type checked, and
+/// report the `#[cfg]` conditions that test something unknown and the
+/// `compile_error!`s left in. Returns `false` if there are any of those
+/// errors, or the warnings are denied.
+fn configure_modules(modules: &mut [Module], options: &CompileOptions) -> bool {
+    let mut warnings = Vec::new();
+    let mut denied = Vec::new();
+    for module in modules.iter_mut() {
+        let name = module.name.clone();
+        let configured = timings::time(Phase::Resolve, Some(&name), || cfg::configure_module(module, options));
+        let (kept, errors) = options.warnings.apply(configured.warnings);
+        warnings.extend(kept);
+        denied.extend(
+            errors
+                .into_iter()
+                .chain(configured.errors)
+                .map(|error| (module.name.clone(), error)),
+        );
+    }
+    for warning in &warnings {
+        report_warning(modules, warning);
+    }
+    let ok = denied.is_empty();
+    for (module_name, error) in denied {
+        report_type_error(modules, &module_name, error);
+    }
+    ok
+}
+
+/// Configured copies of `modules`, for finding the tests and benchmarks
+/// in them. Nothing is reported: compiling the modules does that.
+fn configured_copies(modules: &[Module], options: &CompileOptions) -> Vec<Module> {
+    modules
         .iter()
-        .map(|m| {
-            if m.starts_with("dream::") {
-                format!("'{}'", m)
-            } else {
-                format!("'dream::{}'", m)
-            }
+        .cloned()
+        .map(|mut module| {
+            cfg::configure_module(&mut module, options);
+            module
         })
-        .collect::<Vec<_>>()
-        .join(", ");
+        .collect()
+}
 
-    // Get dependency application names
-    let deps: Vec<String> = config
-        .dependencies
-        .keys()
-        .map(|k| k.clone())
-        .collect();
+/// Type check `modules` together with the stdlib and FFI stubs, reporting
+/// warnings and errors as it goes. Returns `None` if any user module has a
+/// type error.
+fn type_check_modules(modules: &[Module], options: &CompileOptions) -> Option<CheckedModules> {
+    check_user_modules(modules, options, true)
+}
+
+/// [`type_check_modules`], reporting the warnings only if `report_warnings`.
+fn check_user_modules(modules: &[Module], options: &CompileOptions, report_warnings: bool) -> Option<CheckedModules> {
+    let levels = &options.warnings;
+
+    // Load stub modules for FFI type checking
+    let stub_modules = load_stub_modules();
 
-    let deps_str = deps
+    // Load all stdlib modules for type checking
+    // This is needed even when compiling stdlib itself, because stdlib modules
+    // may depend on extern modules defined in other stdlib files
+    let mut stdlib_modules_full = load_stdlib_modules();
+    configure_stdlib(&mut stdlib_modules_full, options);
+
+    // Check if we're compiling stdlib itself (by checking if any module shares a name with stdlib)
+    let stdlib_module_names_raw: std::collections::HashSet<_> = stdlib_modules_full
         .iter()
-        .map(|d| d.as_str())
-        .collect::<Vec<_>>()
-        .join(", ");
+        .map(|m| m.name.clone())
+        .collect();
 
-    // Build the .app file content
-    // Standard OTP applications: kernel and stdlib
-    let applications = if deps_str.is_empty() {
-        "kernel, stdlib".to_string()
-    } else {
-        format!("kernel, stdlib, {}", deps_str)
-    };
+    let user_module_names: std::collections::HashSet<_> = modules.iter()
+        .map(|m| m.name.clone())
+        .collect();
 
-    // Build the mod entry if an application module is configured
-    let mod_entry = if let Some(ref app_config) = config.application {
-        if let Some(ref module) = app_config.module {
-            // Build fully qualified module name: dream::module
-            // The module name in config already includes the package prefix (e.g., "http_api::app")
-            let full_module = format!("dream::{}", module);
-            format!("  {{mod, {{'{}', []}}}},\n", full_module)
-        } else {
-            String::new()
-        }
+    let is_compiling_stdlib = user_module_names.iter().any(|n| stdlib_module_names_raw.contains(n));
+
+    // When compiling stdlib itself, filter out modules being compiled to avoid duplicates
+    // but keep other stdlib modules for type checking (e.g., extern module definitions)
+    let stdlib_modules: Vec<Module> = if is_compiling_stdlib {
+        stdlib_modules_full
+            .into_iter()
+            .filter(|m| !user_module_names.contains(&m.name))
+            .collect()
     } else {
-        String::new()
+        stdlib_modules_full
     };
 
-    let app_content = format!(
-        r#"{{application, {app_name}, [
-  {{description, "A Dream application"}},
-  {{vsn, "{version}"}},
-{mod_entry}  {{modules, [{modules}]}},
-  {{registered, []}},
-  {{applications, [{applications}]}}
-]}}.
-"#,
-        app_name = app_name,
-        version = version,
-        mod_entry = mod_entry,
-        modules = modules_str,
-        applications = applications,
-    );
-
-    let app_file = build_dir.join(format!("{}.app", app_name));
-    fs::write(&app_file, app_content)
-        .map_err(|e| format!("Failed to write {}: {}", app_file.display(), e))?;
+    // Combine user modules with stub modules and stdlib for type checking
+    let mut all_modules_for_typeck: Vec<Module> = stub_modules;
+    all_modules_for_typeck.extend(stdlib_modules.iter().cloned());
+    all_modules_for_typeck.extend(modules.iter().cloned());
 
-    println!("  Generated {}.app", app_name);
-    Ok(())
-}
+    // Type check all modules together (allows cross-module type references)
+    // This also annotates the AST with inferred type arguments
+    let mut has_errors = false;
 
-/// Macro metadata for serialization
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct MacroMetadata {
-    /// Derive name (e.g., "Serialize")
-    derive_name: String,
-    /// Module containing the macro (e.g., "dream::serde::serde")
-    module: String,
-    /// Function name (e.g., "serialize_derive")
-    function: String,
-}
+    let mut annotated_modules: Vec<Module> = Vec::new();
+    let type_check_result = timings::time(Phase::Typecheck, None, || {
+        check_modules_with_levels(&all_modules_for_typeck, levels)
+    });
+    let mut warnings = Vec::new();
 
-/// Macros file format
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct MacrosFile {
-    macros: Vec<MacroMetadata>,
-}
+    // List of stdlib module names for filtering
+    let stdlib_module_names: std::collections::HashSet<_> = stdlib_modules.iter()
+        .map(|m| m.name.clone())
+        .collect();
 
-/// Generate a .macros metadata file listing exported macros.
-fn generate_macros_file(
-    build_dir: &Path,
-    app_name: &str,
-    macros: &[(String, String, String)], // (derive_name, module, function)
-) -> Result<(), String> {
-    if macros.is_empty() {
-        return Ok(());
+    // Display warnings (filter out stdlib warnings) using miette
+    for warning in &type_check_result.warnings {
+        let is_stdlib = warning.module.as_ref()
+            .map(|m| stdlib_module_names.contains(m))
+            .unwrap_or(false);
+        if !is_stdlib {
+            if report_warnings {
+                report_warning(modules, warning);
+            }
+            warnings.push(warning.clone());
+        }
     }
 
-    let macros_file = MacrosFile {
-        macros: macros
-            .iter()
+    for (module_name, result) in type_check_result.modules {
+        // Skip stub modules (they don't have function bodies)
+        if module_name.ends_with("_stubs") || module_name == "erlang" {
+            continue;
+        }
+
+        // Skip stdlib modules for error reporting (but still process them for annotation)
+        let is_stdlib = stdlib_module_names.contains(&module_name);
+
+        match result {
+            Ok(annotated) => {
+                // Only keep user modules, not stdlib modules that were added for type checking
+                if modules.iter().any(|m| m.name == module_name) {
+                    annotated_modules.push(annotated);
+                }
+            }
+            Err(e) => {
+                // Only report errors for user modules, not stdlib
+                if !is_stdlib {
+                    has_errors = true;
+                    report_type_error(modules, &module_name, e);
+                }
+            }
+        }
+    }
+
+    if has_errors {
+        return None;
+    }
+
+    Some(CheckedModules {
+        modules: annotated_modules,
+        extern_module_names: type_check_result.extern_module_names,
+        struct_info: type_check_result.struct_info,
+        warnings,
+    })
+}
+
+/// The compile options for a package: its manifest's, with the warning
+/// flags applied over its `[warnings]`.
+fn project_compile_options(
+    config: &ProjectConfig,
+    project_root: &Path,
+    features: &FeatureSelection,
+    profile: &str,
+    test_mode: bool,
+) -> ConfigResult<CompileOptions> {
+    let mut options = config.compile_options(project_root, features, profile, test_mode)?;
+    apply_warning_flags(&mut options.warnings)?;
+    options.otp_release = otp_release();
+    Ok(options)
+}
+
+/// The major release of the Erlang/OTP installation, for `cfg(otp_release)`;
+/// `None` without a supported one.
+fn otp_release() -> Option<u32> {
+    toolchain::probe().ok().map(|toolchain| toolchain.otp_release)
+}
+
+/// Apply the `-A`/`-W`/`-D` flags to `levels`.
+fn apply_warning_flags(levels: &mut WarningLevels) -> ConfigResult<()> {
+    for (level, name) in WARNING_FLAGS.get().into_iter().flatten() {
+        levels.set(name, *level).map_err(ConfigError::new)?;
+    }
+    Ok(())
+}
+
+/// Whether `--message-format=json` was given.
+fn json_output() -> bool {
+    MESSAGE_FORMAT.get() == Some(&MessageFormat::Json)
+}
+
+/// Whether stdout is reserved for JSON messages, a SARIF log or the DAP.
+fn machine_output() -> bool {
+    MESSAGE_FORMAT.get().is_some_and(|format| *format != MessageFormat::Human)
+        || PROTOCOL_STDOUT.get() == Some(&true)
+}
+
+/// Print a JSON message on stdout. Does nothing in human mode, which
+/// reports the same thing in its own words.
+fn emit(message: Message) {
+    if json_output() {
+        println!("{}", message.to_json());
+    }
+}
+
+/// Report a diagnostic: `rendered` on stderr for people, or a
+/// `compiler-message` carrying it in JSON mode. In SARIF mode it is kept
+/// for the log as well as shown.
+fn report_diagnostic(diagnostic: Diagnostic, rendered: String) {
+    match MESSAGE_FORMAT.get() {
+        Some(MessageFormat::Json) => emit(Message::CompilerMessage {
+            diagnostic: Box::new(diagnostic.with_rendered(rendered)),
+        }),
+        Some(MessageFormat::Sarif) => {
+            eprintln!("{}", rendered);
+            SARIF_DIAGNOSTICS.lock().unwrap().push(diagnostic);
+        }
+        _ => eprintln!("{}", rendered),
+    }
+}
+
+/// Print every diagnostic reported as a SARIF log. Files are given
+/// relative to the current directory, which in CI is the repository root
+/// that code scanning expects them relative to.
+fn print_sarif_log() {
+    let diagnostics = std::mem::take(&mut *SARIF_DIAGNOSTICS.lock().unwrap());
+    let root = std::env::current_dir().unwrap_or_default();
+    println!("{:#}", sarif::sarif_log(&diagnostics, &root));
+}
+
+/// Report a type checker warning about one of `modules`, with source
+/// context when the module's source is at hand.
+fn report_warning(modules: &[Module], warning: &Warning) {
+    let module = warning
+        .module
+        .as_ref()
+        .and_then(|name| modules.iter().find(|m| &m.name == name));
+    let source = module.and_then(|m| m.source.as_deref());
+    let diagnostic = Diagnostic::from_warning(warning, source).with_file(module.and_then(|m| m.source_path.clone()));
+    let rendered = match (module, source) {
+        (Some(module), Some(source)) => {
+            let warning = CompilerWarning::from_warning(&module.name, source, warning.clone());
+            format!("{:?}", miette::Report::new(warning))
+        }
+        // Simple warning without source context
+        _ => match &warning.help {
+            Some(help) => format!("  warning: {}\n    help: {}", warning.message, help),
+            None => format!("  warning: {}", warning.message),
+        },
+    };
+    report_diagnostic(diagnostic, rendered);
+}
+
+/// Report a type error in `module_name`, one of `modules`.
+fn report_type_error(modules: &[Module], module_name: &str, error: TypeError) {
+    let module = modules.iter().find(|m| m.name == module_name);
+    let source = module.and_then(|m| m.source.as_deref());
+    let diagnostic = Diagnostic::from_type_error(module_name, &error, source)
+        .with_file(module.and_then(|m| m.source_path.clone()));
+    let rendered = match (module, source) {
+        (Some(_), Some(source)) => {
+            let error = CompilerError::type_error(module_name, source, error);
+            format!("  Type error in {}:\n{:?}", module_name, miette::Report::new(error))
+        }
+        (Some(_), None) => format!("  Type error in {}: {:?}", module_name, miette::Report::new(error)),
+        // Module not found in user modules - this shouldn't happen
+        (None, _) => format!("  Type error in {}: {:?}", module_name, error),
+    };
+    report_diagnostic(diagnostic, rendered);
+}
+
+/// Report an error that has no source location, such as a failed derive.
+fn report_error(module_name: &str, message: &str, code: ErrorCode, rendered: String) {
+    let diagnostic = Diagnostic::error(message).with_code(code.as_str()).in_module(module_name);
+    report_diagnostic(diagnostic, rendered);
+}
+
+/// Report a panic in the compiler, asking for a bug report.
+fn report_internal_error(error: InternalCompilerError) {
+    let diagnostic = Diagnostic::from_internal_error(&error);
+    let rendered = format!("{:?}", miette::Report::new(error));
+    report_diagnostic(diagnostic, rendered);
+}
+
+/// Report a module that could not be loaded, or each syntax error in a
+/// file that could not be parsed.
+fn report_load_error(error: &LoadError) {
+    let Some((parse_errors, source)) = error.parse_errors.as_deref() else {
+        let diagnostic = Diagnostic::error(error.message.clone())
+            .with_code(error.code.as_str())
+            .with_file(error.path.clone())
+            .with_help(error.help.clone());
+        let mut rendered = format!("Error loading modules [{}]: {}", error.code, error);
+        if let Some(help) = &error.help {
+            rendered.push_str(&format!("\nhelp: {}", help));
+        }
+        report_diagnostic(diagnostic, rendered);
+        return;
+    };
+
+    // Show each syntax error in its source, with its labels
+    let filename = error.path.as_ref().map_or_else(String::new, |p| p.display().to_string());
+    for parse_error in parse_errors {
+        let span = parse_error.span.offset()..parse_error.span.offset() + parse_error.span.len();
+        let diagnostic = Diagnostic::error(parse_error.message.clone())
+            .with_code(parse_error.code.as_str())
+            .with_file(error.path.clone())
+            .with_span(source, Some(span))
+            .with_help(parse_error.help.clone())
+            .with_annotations(Some(source.as_str()), &parse_error.annotations);
+        let report = CompilerError::parse(filename.clone(), source.as_str(), parse_error.clone());
+        report_diagnostic(diagnostic, format!("{:?}", miette::Report::new(report)));
+    }
+}
+
+/// Report that a build failed after `count` errors were printed.
+fn compilation_failed(count: usize) -> ExitCode {
+    let plural = if count == 1 { "" } else { "s" };
+    eprintln!("\nCompilation failed with {} error{}.", count, plural);
+    ExitCode::from(1)
+}
+
+/// Generate an OTP .app file for the Dream application.
+fn generate_app_file(
+    build_dir: &Path,
+    config: &ProjectConfig,
+    module_names: &[String],
+) -> Result<(), String> {
+    // OTP application name is just the package name (no dream:: prefix)
+    let app_name = &config.package.name;
+    let app_file = build_dir.join(format!("{}.app", app_name));
+    fs::write(&app_file, config.app_resource(module_names))
+        .map_err(|e| format!("Failed to write {}: {}", app_file.display(), e))?;
+
+    println!("  Generated {}.app", app_name);
+    Ok(())
+}
+
+/// Macro metadata for serialization
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct MacroMetadata {
+    /// Derive name (e.g., "Serialize")
+    derive_name: String,
+    /// Module containing the macro (e.g., "dream::serde::serde")
+    module: String,
+    /// Function name (e.g., "serialize_derive")
+    function: String,
+}
+
+/// Macros file format
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct MacrosFile {
+    macros: Vec<MacroMetadata>,
+}
+
+/// Generate a .macros metadata file listing exported macros.
+fn generate_macros_file(
+    build_dir: &Path,
+    app_name: &str,
+    macros: &[(String, String, String)], // (derive_name, module, function)
+) -> Result<(), String> {
+    if macros.is_empty() {
+        return Ok(());
+    }
+
+    let macros_file = MacrosFile {
+        macros: macros
+            .iter()
             .map(|(derive_name, module, function)| MacroMetadata {
                 derive_name: derive_name.clone(),
                 module: module.clone(),
@@ -1669,26 +3304,37 @@ fn load_stdlib_modules() -> Vec<Module> {
     loader.into_modules()
 }
 
-/// Get the stdlib output directory.
-fn stdlib_beam_dir() -> PathBuf {
-    // Use target/stdlib relative to executable for compiled stdlib .beam files
-    // This ensures stdlib is found regardless of working directory
-    if let Ok(exe_path) = std::env::current_exe() {
-        if let Some(exe_dir) = exe_path.parent() {
-            // Executable is at target/release/dream or target/debug/dream
-            // Stdlib should be at target/stdlib
-            let stdlib_path = exe_dir.join("../stdlib");
-            // Canonicalize to resolve .. and ensure consistent path comparison
-            if let Ok(canonical) = stdlib_path.canonicalize() {
-                return canonical;
-            }
-            // If canonicalize fails (dir doesn't exist yet), create it and return the path
-            let _ = fs::create_dir_all(&stdlib_path);
-            return stdlib_path.canonicalize().unwrap_or(stdlib_path);
-        }
+/// Features the stdlib is compiled with. The compiled stdlib is shared by
+/// every project, so it has all of them; a project sees the items behind a
+/// feature only when it enables that feature too (see [`configure_stdlib`]).
+const STDLIB_FEATURES: &[&str] = &["json"];
+
+/// Options the stdlib itself is compiled with.
+fn stdlib_compile_options() -> CompileOptions {
+    CompileOptions::with_features(STDLIB_FEATURES.iter().map(|f| f.to_string()).collect())
+}
+
+/// Drop the stdlib items a project's `options` configure out, such as the
+/// `json` module's functions when the `json` feature is off, so the type
+/// checker only knows about the ones the project enabled.
+fn configure_stdlib(stdlib: &mut [Module], options: &CompileOptions) {
+    for module in stdlib.iter_mut() {
+        cfg::configure_module(module, options);
+        module.items.retain(|item| match item {
+            Item::Function(func) => cfg::should_include(&func.attrs, options),
+            Item::Struct(def) => cfg::should_include(&def.attrs, options),
+            Item::Enum(def) => cfg::should_include(&def.attrs, options),
+            _ => true,
+        });
     }
-    // Fallback to relative path
-    PathBuf::from("target/stdlib")
+}
+
+/// Get the stdlib output directory, creating it if needed.
+fn stdlib_beam_dir() -> PathBuf {
+    let stdlib_path = TargetLayout::stdlib_dir();
+    let _ = fs::create_dir_all(&stdlib_path);
+    // Canonicalize to resolve .. and ensure consistent path comparison
+    stdlib_path.canonicalize().unwrap_or(stdlib_path)
 }
 
 /// Load stdlib modules and extract their generic functions into a registry.
@@ -1718,7 +3364,7 @@ fn load_stdlib_generics() -> Option<SharedGenericRegistry> {
     Some(registry)
 }
 
-/// Compile the stdlib to target/stdlib/ if needed.
+/// Compile the stdlib into the toolchain's stdlib directory if needed.
 fn compile_stdlib() -> Result<PathBuf, String> {
     let stdlib_dir = find_stdlib_dir().ok_or("Could not find stdlib directory")?;
     let output_dir = stdlib_beam_dir();
@@ -1756,14 +3402,23 @@ fn compile_stdlib() -> Result<PathBuf, String> {
     }
 
     if needs_compile {
+        toolchain::probe().map_err(|e| format!("{}; {}", e, e.help()))?;
+
         // Load all stdlib modules with "dream" as the package name
         let mut loader = ModuleLoader::with_package("dream".to_string(), stdlib_dir.clone());
         if let Err(e) = loader.load_all_in_dir(&stdlib_dir) {
             return Err(format!("Failed to load stdlib modules: {}", e));
         }
 
-        // Compile all stdlib modules
-        let result = compile_modules(loader.into_modules(), &output_dir, "beam", Some("dream"));
+        // Compile all stdlib modules, with every feature so the gated
+        // modules such as json are in the shared beams
+        let result = compile_modules(
+            loader.into_modules(),
+            &output_dir,
+            "beam",
+            Some("dream"),
+            &stdlib_compile_options(),
+        );
         if result != ExitCode::SUCCESS {
             return Err("Failed to compile stdlib".to_string());
         }
@@ -1825,17 +3480,86 @@ fn extract_module_name(source_file: &Path) -> Option<String> {
     }
 }
 
+/// Run a single source file on the built-in VM.
+fn cmd_run_native(file: Option<&Path>, function: Option<&str>, args: &[String]) -> ExitCode {
+    let Some(source_file) = file else {
+        eprintln!("Error: --native currently requires a source file (project mode is BEAM-only)");
+        return ExitCode::from(1);
+    };
+
+    let source = match fs::read_to_string(source_file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error reading {}: {}", source_file.display(), e);
+            return ExitCode::from(1);
+        }
+    };
+
+    // Files either wrap their code in `mod name { }` or are the module body
+    let compiled = if source.trim_start().starts_with("mod ") {
+        dream::compiler::compile(&source)
+    } else {
+        let stem = source_file
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("main");
+        dream::compiler::compile_file(&source, stem)
+    };
+    let module = match compiled {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Error compiling {}: {}", source_file.display(), e);
+            return ExitCode::from(1);
+        }
+    };
+
+    let module_name = module.name.clone();
+    let function = function.unwrap_or("main");
+    let values = args.iter().map(|a| dream::native::parse_arg(a)).collect();
+
+    match dream::native::run_function(vec![module], &module_name, function, values) {
+        Ok(run) if run.success() => {
+            println!("{:?}", run.result);
+            ExitCode::SUCCESS
+        }
+        Ok(run) => {
+            eprintln!("Error: {}:{} exited with {:?}", module_name, function, run.result);
+            ExitCode::from(1)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(1)
+        }
+    }
+}
+
+/// What `dream run` should call.
+struct RunEntry<'a> {
+    /// `--function`: call this function with `args` and print the result
+    function: Option<&'a str>,
+    /// `--bin`: run `main` in `src/bin/<name>.dream`
+    bin: Option<&'a str>,
+    /// Erlang term arguments for `--function`
+    args: &'a [String],
+    /// Arguments after `--`, passed to `main(args)`
+    program_args: &'a [String],
+}
+
 /// Build and run the project or a standalone file.
 fn cmd_run(
     file: Option<&Path>,
-    function: Option<&str>,
+    entry: &RunEntry,
     eval_mode: bool,
     no_halt: bool,
     shell_mode: bool,
     env: &str,
-    features: &[String],
-    args: &[String],
+    features: &FeatureSelection,
+    profile: &str,
+    node: &NodeOptions,
 ) -> ExitCode {
+    let function = entry.function;
+    let args = entry.args;
+
     // Compile stdlib first
     let stdlib_dir = match compile_stdlib() {
         Ok(dir) => Some(dir),
@@ -1864,7 +3588,7 @@ fn cmd_run(
         };
 
         // Build the standalone file
-        let build_result = cmd_build(Some(source_file), "beam", Some(&build_dir), features);
+        let build_result = cmd_build(Some(source_file), "beam", Some(&build_dir), &[], features, profile, false);
         if build_result != ExitCode::SUCCESS {
             return build_result;
         }
@@ -1874,13 +3598,7 @@ fn cmd_run(
 
         (build_dir, module_name, None)
     } else {
-        // Project mode
-        let build_result = cmd_build(None, "beam", None, features);
-        if build_result != ExitCode::SUCCESS {
-            return build_result;
-        }
-
-        // Find project root and load config
+        // Project mode: find project root and load config
         let (project_root, config) = match ProjectConfig::from_project_root() {
             Ok(result) => result,
             Err(e) => {
@@ -1889,35 +3607,49 @@ fn cmd_run(
             }
         };
 
-        let beam_dir = config.beam_dir_for_env(&project_root, env);
-        let app_config = config.application.clone();
-
-        // Determine module name: use application module or package name
-        // Module names are prefixed with dream:: and package:: (e.g., "dream::http_api::http_api")
-        let base_module = if let Some(ref app) = app_config {
-            app.module.clone().unwrap_or_else(|| config.package.name.clone())
-        } else {
-            // Use package name as the default module (corresponds to lib.dream)
-            config.package.name.clone()
+        // A profile other than dev builds into its own directory
+        let beam_dir = match config.profile(profile) {
+            Ok(p) if p.name != "dev" => config.profile_beam_dir(&project_root, &p),
+            Ok(_) => config.beam_dir_for_env(&project_root, env),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(1);
+            }
         };
 
-        // Build the full module path: dream::package::module
-        let package_name = &config.package.name;
-        let module_name = if base_module.starts_with("dream::") {
-            base_module
-        } else if base_module.starts_with(&format!("{}::", package_name)) {
-            format!("dream::{}", base_module)
+        let build_result = cmd_build(None, "beam", Some(&beam_dir), &[], features, profile, false);
+        if build_result != ExitCode::SUCCESS {
+            return build_result;
+        }
+
+        let app_config = config.application.clone();
+
+        // Determine module name: the requested binary, else the application
+        // module, else main.dream or lib.dream, prefixed with dream:: and
+        // package:: (e.g., "dream::http_api::http_api")
+        let module_name = if let Some(bin) = entry.bin {
+            match config.bin_module(&project_root, bin) {
+                Some(module) => module,
+                None => {
+                    eprintln!("Error: no binary named '{}' in src/bin/", bin);
+                    let names = config.bin_names(&project_root);
+                    if !names.is_empty() {
+                        eprintln!("Available binaries: {}", names.join(", "));
+                    }
+                    return ExitCode::from(1);
+                }
+            }
         } else {
-            format!("dream::{}::{}", package_name, base_module)
+            config
+                .application_beam_module()
+                .unwrap_or_else(|| config.entry_module(&project_root))
         };
 
         (beam_dir, module_name, app_config)
     };
 
     // Check if erl is available
-    if !command_exists("erl") {
-        eprintln!("Error: erl not found in PATH");
-        eprintln!("Install Erlang/OTP to run on the BEAM.");
+    if !check_toolchain("run on the BEAM") {
         return ExitCode::from(1);
     }
 
@@ -1927,7 +3659,8 @@ fn cmd_run(
     // 3. If application is configured and no function specified, use application mode
     // 4. If __script__ module exists and no function specified, run the script
     // 5. Otherwise, use eval mode with main()
-    let use_app_mode = !eval_mode && function.is_none() && app_config.is_some();
+    let program_mode = entry.bin.is_some() || !entry.program_args.is_empty();
+    let use_app_mode = !eval_mode && !program_mode && function.is_none() && app_config.is_some();
 
     // Check if __script__ module exists (for script files with top-level expressions)
     let script_beam = beam_dir.join("__script__.beam");
@@ -1951,7 +3684,10 @@ fn cmd_run(
     deps_dirs.extend(find_elixir_ebin_dirs());
 
     if use_app_mode {
-        run_application(&beam_dir, &module_name, &app_config.unwrap(), stdlib_dir.as_ref(), &deps_dirs, shell_mode)
+        run_application(&beam_dir, &module_name, &app_config.unwrap(), stdlib_dir.as_ref(), &deps_dirs, shell_mode, node)
+    } else if program_mode || (function.is_none() && !has_script_module && !no_halt) {
+        // Run as a program: main(args) decides the exit status
+        run_main(&beam_dir, &module_name, entry.program_args, stdlib_dir.as_ref(), &deps_dirs, node)
     } else if has_script_module && function.is_none() && !eval_mode {
         // Run script mode: execute __script__:__main__()
         run_function(
@@ -1962,10 +3698,11 @@ fn cmd_run(
             no_halt,
             stdlib_dir.as_ref(),
             &deps_dirs,
+            node,
         )
     } else {
         let func = function.unwrap_or("main");
-        run_function(&beam_dir, &module_name, func, args, no_halt, stdlib_dir.as_ref(), &deps_dirs)
+        run_function(&beam_dir, &module_name, func, args, no_halt, stdlib_dir.as_ref(), &deps_dirs, node)
     }
 }
 
@@ -1977,6 +3714,7 @@ fn run_application(
     stdlib_dir: Option<&PathBuf>,
     deps_dirs: &[PathBuf],
     shell_mode: bool,
+    node: &NodeOptions,
 ) -> ExitCode {
     // Get the OTP application name from config (not the module name)
     let app_name = if let Ok((_, config)) = ProjectConfig::from_project_root() {
@@ -2018,10 +3756,11 @@ fn run_application(
 
     // Start all dependencies and then our application using OTP
     // This reads the .app file's {applications, [...]} and {mod, {...}} entries
+    // ensure_all_started returns the applications it started, dependencies first
     eval_parts.push(format!(
         "case application:ensure_all_started({}) of \
-            {{ok, _Started}} -> ok; \
-            {{error, {{_App, Reason}}}} -> io:format(\"Failed to start: ~p~n\", [Reason]), halt(1) \
+            {{ok, Started}} -> io:format(\"Started ~p~n\", [Started]); \
+            {{error, {{App, Reason}}}} -> io:format(\"Failed to start ~p: ~p~n\", [App, Reason]), halt(1) \
         end",
         app_name
     ));
@@ -2035,25 +3774,18 @@ fn run_application(
 
     let eval_expr = eval_parts.join(", ") + ".";
 
-    let mut cmd = Command::new("erl");
-    cmd.arg("-pa").arg(beam_dir);
+    let mut cmd = toolchain::erl();
+    CodePath::project(beam_dir, stdlib_dir.map(PathBuf::as_path), deps_dirs).apply(&mut cmd);
 
-    // Add stdlib to code path if available
-    if let Some(stdlib) = stdlib_dir {
-        cmd.arg("-pa").arg(stdlib);
-    }
-
-    // Add deps ebin directories to code path
-    for dep_dir in deps_dirs {
-        cmd.arg("-pa").arg(dep_dir);
-    }
+    // Distribution: node name and cookie
+    cmd.args(node.erl_args());
 
     cmd.arg("-noshell").arg("-eval").arg(&eval_expr);
 
     // Save terminal state before running erl
     let saved_term = save_terminal_state();
 
-    // Run erl with proper signal handling - let erl handle Ctrl+C (BREAK menu)
+    // Run erl, stopping it gracefully if we are interrupted
     let status = run_with_signal_handling(cmd);
 
     // Restore terminal state after erl exits
@@ -2069,6 +3801,97 @@ fn run_application(
     }
 }
 
+/// Run a program: call `main(args)` (or `main()` if there is no `main/1`)
+/// with stdin and stdout attached to the terminal, and exit with its status.
+fn run_main(
+    beam_dir: &Path,
+    module_name: &str,
+    program_args: &[String],
+    stdlib_dir: Option<&PathBuf>,
+    deps_dirs: &[PathBuf],
+    node: &NodeOptions,
+) -> ExitCode {
+    let mut cmd = toolchain::erl();
+    CodePath::project(beam_dir, stdlib_dir.map(PathBuf::as_path), deps_dirs).apply(&mut cmd);
+
+    // Distribution: node name and cookie
+    cmd.args(node.erl_args());
+
+    cmd.arg("-noshell")
+        .arg("-eval")
+        .arg(main_eval_expr(module_name, program_args));
+
+    // Interactive programs read the terminal through the BEAM's stdin
+    cmd.stdin(std::process::Stdio::inherit())
+        .stdout(std::process::Stdio::inherit())
+        .stderr(std::process::Stdio::inherit());
+
+    let saved_term = save_terminal_state();
+    let status = run_with_signal_handling(cmd);
+    restore_terminal_state(saved_term);
+
+    match status {
+        Ok(s) if s.success() => ExitCode::SUCCESS,
+        Ok(s) => ExitCode::from(s.code().unwrap_or(1) as u8),
+        Err(e) => {
+            eprintln!("Error running erl: {}", e);
+            ExitCode::from(1)
+        }
+    }
+}
+
+/// Erlang expression that calls a program's `main` and halts with its exit
+/// status, rather than leaving a `-noshell` node running.
+fn main_eval_expr(module_name: &str, program_args: &[String]) -> String {
+    format!("Status = {}, halt(Status).", main_status_expr(module_name, program_args))
+}
+
+/// Erlang expression that calls a program's `main` and evaluates to its
+/// exit status. An integer result in 0..=255 is the status, and any other
+/// integer is reported and exits 1 rather than being truncated; `error` and
+/// `{error, Reason}` exit 1; anything else exits 0. An uncaught exception
+/// is printed and exits 1.
+fn main_status_expr(module_name: &str, program_args: &[String]) -> String {
+    let args: Vec<String> = program_args.iter().map(|a| erlang_binary(a)).collect();
+    format!(
+        "begin \
+             M = '{module}', code:ensure_loaded(M), \
+             try case erlang:function_exported(M, main, 1) of \
+                     true -> M:main([{args}]); \
+                     false -> M:main() \
+                 end of \
+                 N when is_integer(N), N >= 0, N =< 255 -> N; \
+                 N when is_integer(N) -> \
+                     io:format(standard_error, \"error: exit status ~p is outside 0..255~n\", [N]), 1; \
+                 error -> 1; \
+                 {{error, Reason}} -> io:format(standard_error, \"error: ~p~n\", [Reason]), 1; \
+                 _ -> 0 \
+             catch Class:Error:Stack -> \
+                 io:format(standard_error, \"** ~p: ~p~n~p~n\", [Class, Error, Stack]), 1 \
+             end \
+         end",
+        module = module_name,
+        args = args.join(", "),
+    )
+}
+
+/// A string as an Erlang UTF-8 binary literal.
+fn erlang_binary(s: &str) -> String {
+    let mut out = String::from("<<\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out.push_str("\"/utf8>>");
+    out
+}
+
 /// Run in eval mode - call a function and optionally exit.
 fn run_function(
     beam_dir: &Path,
@@ -2078,6 +3901,7 @@ fn run_function(
     no_halt: bool,
     stdlib_dir: Option<&PathBuf>,
     deps_dirs: &[PathBuf],
+    node: &NodeOptions,
 ) -> ExitCode {
     // Format arguments for Erlang
     let args_str = if args.is_empty() {
@@ -2106,25 +3930,18 @@ fn run_function(
         )
     };
 
-    let mut cmd = Command::new("erl");
-    cmd.arg("-pa").arg(beam_dir);
-
-    // Add stdlib to code path if available
-    if let Some(stdlib) = stdlib_dir {
-        cmd.arg("-pa").arg(stdlib);
-    }
+    let mut cmd = toolchain::erl();
+    CodePath::project(beam_dir, stdlib_dir.map(PathBuf::as_path), deps_dirs).apply(&mut cmd);
 
-    // Add deps ebin directories to code path
-    for dep_dir in deps_dirs {
-        cmd.arg("-pa").arg(dep_dir);
-    }
+    // Distribution: node name and cookie
+    cmd.args(node.erl_args());
 
     cmd.arg("-noshell").arg("-eval").arg(&eval_expr);
 
     // Save terminal state before running erl
     let saved_term = save_terminal_state();
 
-    // Run erl with proper signal handling - let erl handle Ctrl+C (BREAK menu)
+    // Run erl, stopping it gracefully if we are interrupted
     let status = run_with_signal_handling(cmd);
 
     // Restore terminal state after erl exits
@@ -2140,35 +3957,82 @@ fn run_function(
     }
 }
 
-/// Run a command while ignoring Ctrl+C in the parent process.
-/// This allows the child (erl) to handle SIGINT itself (showing BREAK menu).
-fn run_with_signal_handling(cmd: Command) -> io::Result<std::process::ExitStatus> {
+/// How long to wait for an interrupted node to run its applications' stop
+/// callbacks before killing it.
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Run erl until it exits, shutting it down gracefully if this process is
+/// interrupted (Ctrl+C or SIGTERM).
+///
+/// The interrupt is forwarded to the node as SIGTERM, on which the BEAM
+/// calls `init:stop()`: applications are stopped in reverse order, running
+/// their stop callbacks, and the node halts. If it has not within
+/// [`SHUTDOWN_TIMEOUT`], or a second interrupt arrives, it is killed.
+///
+/// erl is started with `+Bi` so that the terminal's Ctrl+C, which it gets
+/// as well, does not open its BREAK menu. On Windows, where there is no
+/// SIGTERM to forward, erl handles the console's Ctrl+C itself, and is
+/// killed if it is still running after the timeout.
+fn run_with_signal_handling(mut cmd: Command) -> io::Result<std::process::ExitStatus> {
     use tokio::runtime::Runtime;
 
+    #[cfg(unix)]
+    cmd.arg("+Bi");
+
     // Create a runtime for async signal handling
     let rt = Runtime::new()?;
 
     rt.block_on(async {
         let mut child = tokio::process::Command::from(cmd).spawn()?;
 
-        // Wait for child to exit, ignoring Ctrl+C in parent
-        // The child receives SIGINT directly from the terminal
-        loop {
-            tokio::select! {
-                // Ignore Ctrl+C - let the child handle it
-                _ = tokio::signal::ctrl_c() => {
-                    // Do nothing - erl will show BREAK menu
-                    continue;
-                }
-                // Child exited
-                status = child.wait() => {
-                    return status;
-                }
+        tokio::select! {
+            status = child.wait() => return status,
+            signal = shutdown_signal() => signal?,
+        }
+
+        #[cfg(unix)]
+        if let Some(pid) = child.id() {
+            let _ = Command::new("kill")
+                .arg("-TERM")
+                .arg(pid.to_string())
+                .status();
+        }
+        eprintln!("Stopping... (interrupt again to kill the node)");
+
+        tokio::select! {
+            status = child.wait() => return status,
+            _ = tokio::time::sleep(SHUTDOWN_TIMEOUT) => {
+                eprintln!(
+                    "Warning: the node did not stop within {}s, killing it",
+                    SHUTDOWN_TIMEOUT.as_secs()
+                );
             }
+            _ = shutdown_signal() => {}
         }
+        child.kill().await?;
+        child.wait().await
     })
 }
 
+/// Wait for a request to stop: Ctrl+C, or on Unix SIGTERM as well.
+#[cfg(unix)]
+async fn shutdown_signal() -> io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = interrupt.recv() => {}
+        _ = terminate.recv() => {}
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
+
 /// Reset the terminal to a sane state.
 /// This is necessary after running erl because it may leave the terminal in raw mode
 /// Save terminal state before running a subprocess that might modify it.
@@ -2201,6 +4065,10 @@ fn restore_terminal_state(saved: Option<String>) {
             .status();
     }
 
+    // erl on Windows can leave the console without line input and echo
+    #[cfg(windows)]
+    let _ = crossterm::terminal::disable_raw_mode();
+
     // Drain any pending input
     while crossterm::event::poll(std::time::Duration::ZERO).unwrap_or(false) {
         let _ = crossterm::event::read();
@@ -2231,13 +4099,17 @@ fn toml_to_erlang(value: &toml::Value) -> String {
     }
 }
 
-/// Check if a command exists in PATH.
-fn command_exists(cmd: &str) -> bool {
-    Command::new("which")
-        .arg(cmd)
-        .output()
-        .map(|o| o.status.success())
-        .unwrap_or(false)
+/// Check that a supported Erlang/OTP is installed, which is needed to
+/// `doing` ("run tests"), saying what to do about it if not.
+fn check_toolchain(doing: &str) -> bool {
+    match toolchain::probe() {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Erlang/OTP is needed to {}: {}.", doing, e.help());
+            false
+        }
+    }
 }
 
 /// Check if a module contains any macro functions.
@@ -2320,7 +4192,7 @@ fn compile_module_to_beam(
         .map_err(|e| format!("Error writing {}: {}", core_file.display(), e))?;
 
     // Compile to BEAM using erlc
-    let status = Command::new("erlc")
+    let status = toolchain::erlc()
         .arg("+from_core")
         .arg("-o")
         .arg(build_dir)
@@ -2363,9 +4235,11 @@ fn needs_recompilation(module: &Module, beam_file: &Path) -> bool {
     }
 }
 
-/// Run tests in the project.
-fn cmd_test(filter: Option<&str>, features: &[String]) -> ExitCode {
-    // Find project root and load config
+/// Check the project for errors: parse every module, drop the ones cfg
+/// excludes and type check the rest, without emitting Core Erlang or
+/// invoking erlc.
+fn cmd_check(features: &FeatureSelection, tests: bool) -> ExitCode {
+    let started = std::time::Instant::now();
     let (project_root, config) = match ProjectConfig::from_project_root() {
         Ok(result) => result,
         Err(e) => {
@@ -2374,16 +4248,539 @@ fn cmd_test(filter: Option<&str>, features: &[String]) -> ExitCode {
         }
     };
 
+    let compile_options = match project_compile_options(&config, &project_root, features, "dev", tests) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
     let src_dir = config.src_dir(&project_root);
-    let build_dir = config.beam_dir_for_env(&project_root, "test");
 
-    // Create build directory
-    if let Err(e) = fs::create_dir_all(&build_dir) {
-        eprintln!("Error creating build directory: {}", e);
+    let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+    loader.add_bindings_dir(config.target_layout(&project_root).bindings_dir());
+    if let Err(e) = loader.load_all_in_dir(&src_dir) {
+        report_load_error(&e);
+        return ExitCode::from(1);
+    }
+    let mut modules = loader.into_modules();
+    if !configure_modules(&mut modules, &compile_options) {
+        eprintln!("\nerror: could not check {} due to previous errors", config.package.name);
+        return ExitCode::from(1);
+    }
+    modules.retain(|m| cfg::should_include(&m.attrs, &compile_options));
+
+    let Some(checked) = type_check_modules(&modules, &compile_options) else {
+        eprintln!("\nerror: could not check {} due to type errors", config.package.name);
+        return ExitCode::from(1);
+    };
+
+    let warnings = match checked.warnings.len() {
+        0 => String::new(),
+        1 => " (1 warning)".to_string(),
+        n => format!(" ({} warnings)", n),
+    };
+    status!(
+        "Checked {} module{} of {} in {:.2}s{}",
+        checked.modules.len(),
+        if checked.modules.len() == 1 { "" } else { "s" },
+        config.package.name,
+        started.elapsed().as_secs_f64(),
+        warnings
+    );
+    ExitCode::SUCCESS
+}
+
+/// Write the project's documentation to `_build/doc`, then serve it on
+/// `port` if one is given.
+fn cmd_doc(features: &FeatureSelection, port: Option<u16>) -> ExitCode {
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    let compile_options = match project_compile_options(&config, &project_root, features, "dev", false) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let src_dir = config.src_dir(&project_root);
+
+    let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+    if let Err(e) = loader.load_all_in_dir(&src_dir) {
+        report_load_error(&e);
+        return ExitCode::from(1);
+    }
+    let mut modules = loader.into_modules();
+    if !configure_modules(&mut modules, &compile_options) {
+        eprintln!("\nerror: could not document {} due to previous errors", config.package.name);
+        return ExitCode::from(1);
+    }
+    modules.retain(|m| cfg::should_include(&m.attrs, &compile_options));
+
+    let doc_dir = config.target_layout(&project_root).doc_dir();
+    let index = match doc::Site::new(&config.package.name, &modules).write(&doc_dir) {
+        Ok(index) => index,
+        Err(e) => {
+            eprintln!("Error writing {}: {}", doc_dir.display(), e);
+            return ExitCode::from(1);
+        }
+    };
+    status!(
+        "Documented {} module{} of {} in {}",
+        modules.len(),
+        if modules.len() == 1 { "" } else { "s" },
+        config.package.name,
+        index.display()
+    );
+
+    let Some(port) = port else {
+        return ExitCode::SUCCESS;
+    };
+    status!("Serving documentation at http://127.0.0.1:{}/ (Ctrl-C to stop)", port);
+    match doc::serve(&doc_dir, port) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: could not serve on port {}: {}", port, e);
+            ExitCode::from(1)
+        }
+    }
+}
+
+/// The lint levels the `[lints]` table of dream.toml sets.
+fn configured_lint_levels(config: &ProjectConfig) -> Result<LintLevels, String> {
+    let mut levels = LintLevels::new();
+    let mut configured: Vec<_> = config.lints.iter().collect();
+    configured.sort();
+    for (name, level) in configured {
+        match LintLevel::from_name(level) {
+            Some(level) => levels.set(name, level)?,
+            None => return Err(format!("invalid level `{}` for lint `{}` (expected allow, warn or deny)", level, name)),
+        }
+    }
+    Ok(levels)
+}
+
+/// Type check the project and run the lints over it. Levels come from the
+/// defaults, then dream.toml's `[lints]`, then `flags` in order; flags
+/// naming a warning category set its level for type checking. Fails if
+/// the project doesn't type check or a deny-level lint fires.
+fn cmd_lint(features: &FeatureSelection, tests: bool, flags: &[(LintLevel, Vec<String>)]) -> ExitCode {
+    let started = std::time::Instant::now();
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    let mut levels = match configured_lint_levels(&config) {
+        Ok(levels) => levels,
+        Err(e) => {
+            eprintln!("Error: dream.toml [lints]: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let mut compile_options = match project_compile_options(&config, &project_root, features, "dev", tests) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    // The flags also name warning categories, so `-D warnings` covers both
+    for (level, names) in flags {
+        for name in names {
+            let warning = compile_options.warnings.set(name, *level);
+            if let Err(e) = levels.set(name, *level) {
+                if warning.is_err() {
+                    eprintln!("Error: {}; `dream lint --list` shows every lint", e);
+                    return ExitCode::from(1);
+                }
+            }
+        }
+    }
+    let src_dir = config.src_dir(&project_root);
+
+    let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+    loader.add_bindings_dir(config.target_layout(&project_root).bindings_dir());
+    if let Err(e) = loader.load_all_in_dir(&src_dir) {
+        report_load_error(&e);
+        return ExitCode::from(1);
+    }
+    let mut modules = loader.into_modules();
+    if !configure_modules(&mut modules, &compile_options) {
+        eprintln!("\nerror: could not lint {} due to previous errors", config.package.name);
+        return ExitCode::from(1);
+    }
+    modules.retain(|m| cfg::should_include(&m.attrs, &compile_options));
+
+    let Some(checked) = type_check_modules(&modules, &compile_options) else {
+        eprintln!("\nerror: could not lint {} due to type errors", config.package.name);
+        return ExitCode::from(1);
+    };
+
+    let findings = Linter::new(&levels)
+        .with_context(&load_stdlib_modules())
+        .lint_modules(&checked.modules);
+    let denied = findings.iter().filter(|d| d.level == LintLevel::Deny).count();
+    for finding in &findings {
+        report_lint(&modules, finding);
+    }
+
+    let warned = findings.len() - denied + checked.warnings.len();
+    let mut counts = Vec::new();
+    if denied > 0 {
+        counts.push(format!("{} error{}", denied, if denied == 1 { "" } else { "s" }));
+    }
+    if warned > 0 {
+        counts.push(format!("{} warning{}", warned, if warned == 1 { "" } else { "s" }));
+    }
+    if denied > 0 {
+        eprintln!("\nerror: could not lint {} due to {}", config.package.name, counts.join(" and "));
+        return ExitCode::from(1);
+    }
+    status!(
+        "Linted {} module{} of {} in {:.2}s{}",
+        checked.modules.len(),
+        if checked.modules.len() == 1 { "" } else { "s" },
+        config.package.name,
+        started.elapsed().as_secs_f64(),
+        if counts.is_empty() { String::new() } else { format!(" ({})", counts.join(", ")) }
+    );
+    ExitCode::SUCCESS
+}
+
+/// Report a lint that fired in one of `modules`.
+fn report_lint(modules: &[Module], finding: &LintDiagnostic) {
+    let module = modules.iter().find(|m| m.name == finding.module);
+    let source = module.and_then(|m| m.source.as_deref());
+    let (diagnostic, severity) = match finding.level {
+        LintLevel::Deny => (Diagnostic::error(finding.message.clone()), miette::Severity::Error),
+        _ => (Diagnostic::warning(finding.message.clone()), miette::Severity::Warning),
+    };
+    let mut diagnostic = diagnostic
+        .with_code(finding.lint.name())
+        .in_module(&finding.module)
+        .with_file(module.and_then(|m| m.source_path.clone()))
+        .with_help(finding.help.clone())
+        .with_annotations(
+            source,
+            &Annotations {
+                suggestions: finding.suggestions.clone(),
+                ..Annotations::default()
+            },
+        );
+    if let Some(source) = source {
+        diagnostic = diagnostic.with_span(source, finding.span.clone());
+    }
+
+    let mut report = miette::MietteDiagnostic::new(finding.message.clone())
+        .with_severity(severity)
+        .with_code(finding.lint.name());
+    if let Some(help) = &finding.help {
+        report = report.with_help(help.clone());
+    }
+    let rendered = match (source, &finding.span) {
+        (Some(source), Some(span)) => {
+            let report = report.with_label(miette::LabeledSpan::underline(span.clone()));
+            let named = miette::NamedSource::new(&finding.module, source.to_string());
+            format!("{:?}", miette::Report::new(report).with_source_code(named))
+        }
+        _ => format!("{:?}", miette::Report::new(report)),
+    };
+    report_diagnostic(diagnostic, rendered);
+}
+
+/// How many rounds of fixes `dream fix` applies at most: a fix can make
+/// room for another, which the next round finds.
+const FIX_ROUNDS: usize = 10;
+
+/// Type check and lint the project, and apply the machine-applicable
+/// suggestions of the warnings and lints to its sources, round after round
+/// until there are none. With `dry_run`, print the first round as a diff
+/// instead. Fails if the project doesn't type check.
+fn cmd_fix(features: &FeatureSelection, tests: bool, dry_run: bool) -> ExitCode {
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let levels = match configured_lint_levels(&config) {
+        Ok(levels) => levels,
+        Err(e) => {
+            eprintln!("Error: dream.toml [lints]: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let compile_options = match project_compile_options(&config, &project_root, features, "dev", tests) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let src_dir = config.src_dir(&project_root);
+    // Generated bindings are loaded too, but fixed by regenerating them
+    let sources = src_dir.canonicalize().unwrap_or_else(|_| src_dir.clone());
+    let root = project_root.canonicalize().unwrap_or_else(|_| project_root.clone());
+    let context = load_stdlib_modules();
+
+    let mut fixed = 0;
+    let mut files = HashSet::new();
+    for _ in 0..FIX_ROUNDS {
+        let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+        loader.add_bindings_dir(config.target_layout(&project_root).bindings_dir());
+        if let Err(e) = loader.load_all_in_dir(&src_dir) {
+            report_load_error(&e);
+            return ExitCode::from(1);
+        }
+        let mut modules = loader.into_modules();
+        if !configure_modules(&mut modules, &compile_options) {
+            eprintln!("\nerror: could not fix {} due to previous errors", config.package.name);
+            return ExitCode::from(1);
+        }
+        modules.retain(|m| cfg::should_include(&m.attrs, &compile_options));
+
+        let Some(checked) = check_user_modules(&modules, &compile_options, false) else {
+            eprintln!("\nerror: could not fix {} due to type errors", config.package.name);
+            return ExitCode::from(1);
+        };
+        let findings = Linter::new(&levels).with_context(&context).lint_modules(&checked.modules);
+
+        let mut changed = false;
+        for (name, edits) in fix::fixes(&checked.warnings, &findings) {
+            let Some(module) = modules.iter().find(|m| m.name == name) else {
+                continue;
+            };
+            let (Some(path), Some(source)) = (module.source_path.as_deref(), module.source.as_deref()) else {
+                continue;
+            };
+            let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+            let edits: Vec<_> = edits
+                .into_iter()
+                .filter(|edit| source.get(edit.location.span.clone()) != Some(edit.text.as_str()))
+                .collect();
+            if edits.is_empty() || !path.starts_with(&sources) {
+                continue;
+            }
+            if dry_run {
+                let shown = path.strip_prefix(&root).unwrap_or(&path);
+                print!("{}", refactor::diff(&shown.display().to_string(), source, &edits));
+            } else if let Err(e) = fs::write(&path, refactor::apply(source, &edits)) {
+                eprintln!("Error writing {}: {}", path.display(), e);
+                return ExitCode::from(1);
+            }
+            fixed += edits.len();
+            files.insert(path);
+            changed = true;
+        }
+        if dry_run || !changed {
+            break;
+        }
+    }
+
+    let summary = format!(
+        "{} problem{} in {} file{}",
+        fixed,
+        if fixed == 1 { "" } else { "s" },
+        files.len(),
+        if files.len() == 1 { "" } else { "s" }
+    );
+    if dry_run {
+        // The diff alone goes to stdout, so it can be piped to `patch`
+        eprintln!("Would fix {}", summary);
+    } else if fixed == 0 {
+        status!("Nothing to fix in {}", config.package.name);
+    } else {
+        status!("Fixed {}", summary);
+    }
+    ExitCode::SUCCESS
+}
+
+/// Print every lint with its default level, for `dream lint --list`.
+fn cmd_lint_list() -> ExitCode {
+    let width = Lint::ALL.iter().map(|lint| lint.name().len()).max().unwrap_or(0);
+    println!("{:width$}  {:5}  description", "name", "level", width = width);
+    for lint in Lint::ALL {
+        println!(
+            "{:width$}  {:5}  {}",
+            lint.name(),
+            lint.default_level().to_string(),
+            lint.description(),
+            width = width
+        );
+    }
+    println!("\n`warnings` names every lint that would warn, as in `dream lint -D warnings`.");
+    ExitCode::SUCCESS
+}
+
+/// Print the explanation of `code`, or with no code a table of all of them.
+fn cmd_explain(code: Option<&str>) -> ExitCode {
+    let Some(code) = code else {
+        for code in ErrorCode::ALL {
+            println!("{}  {}", code, code.explanation().title);
+        }
+        println!("\nRun `dream explain <CODE>` for details.");
+        return ExitCode::SUCCESS;
+    };
+    match ErrorCode::from_code(code) {
+        Some(code) => {
+            print!("{}", code.explain());
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("Error: no such error code `{}`", code);
+            eprintln!("Run `dream explain` to list every code.");
+            ExitCode::from(1)
+        }
+    }
+}
+
+/// Remove build output: the whole build directory, only what `packages`
+/// produced in it, or with `cache` only the compiled stdlib.
+fn cmd_clean(packages: &[String], cache: bool) -> ExitCode {
+    let dirs = if cache {
+        vec![TargetLayout::stdlib_dir()]
+    } else {
+        let layout = match current_target_layout() {
+            Ok(layout) => layout,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(1);
+            }
+        };
+        if packages.is_empty() {
+            vec![layout.root().to_path_buf()]
+        } else {
+            let mut dirs = Vec::new();
+            for package in packages {
+                match layout.app_dirs(package) {
+                    Ok(found) => dirs.extend(found),
+                    Err(e) => {
+                        eprintln!("Error: --package {}: {}", package, e);
+                        return ExitCode::from(1);
+                    }
+                }
+            }
+            // Never remove anything outside _build, whatever a name resolved to
+            if let Some(outside) = dirs.iter().find(|dir| !layout.contains(dir)) {
+                eprintln!("Error: {} is outside {}", outside.display(), layout.root().display());
+                return ExitCode::from(1);
+            }
+            dirs
+        }
+    };
+
+    let mut freed = 0;
+    let mut removed = 0;
+    for dir in dirs.iter().filter(|dir| dir.exists()) {
+        match target::remove_dir(dir) {
+            Ok(bytes) => {
+                println!("Removed {}", dir.display());
+                freed += bytes;
+                removed += 1;
+            }
+            Err(e) => {
+                eprintln!("Error: failed to remove {}: {}", dir.display(), e);
+                return ExitCode::from(1);
+            }
+        }
+    }
+
+    if removed == 0 {
+        println!("Nothing to clean.");
+    } else {
+        println!("Freed {}", target::format_size(freed));
+    }
+    ExitCode::SUCCESS
+}
+
+/// The build directory of the current package, or of the workspace when run
+/// at a workspace root without a [package].
+fn current_target_layout() -> ConfigResult<TargetLayout> {
+    let root = ProjectConfig::find_project_root().ok_or_else(|| {
+        ConfigError::new("Could not find dream.toml in current or parent directories")
+    })?;
+    if Workspace::is_virtual(&root.join("dream.toml"))? {
+        return Ok(TargetLayout::for_root(&root));
+    }
+    let (project_root, config) = ProjectConfig::from_project_root()?;
+    Ok(config.target_layout(&project_root))
+}
+
+/// Re-run the current command whenever a source file changes.
+fn cmd_watch(args: &WatchArgs) -> ExitCode {
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Error: cannot find the dream executable: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let root = ProjectConfig::find_project_root().unwrap_or_else(|| PathBuf::from("."));
+    let child_args = watch::child_args(std::env::args_os());
+    let options = WatchOptions {
+        clear: args.clear,
+        ..WatchOptions::default()
+    };
+
+    println!("[watching {} for changes]", root.display());
+    let result = watch::watch(&root, &options, || {
+        let mut cmd = Command::new(&exe);
+        cmd.args(&child_args);
+        cmd
+    });
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+    }
+    ExitCode::from(1)
+}
+
+/// Run tests in the project.
+fn cmd_test(
+    filter: &TestFilter,
+    mut run_options: RunOptions,
+    features: &FeatureSelection,
+    coverage: bool,
+    reports: &[ReportFormat],
+) -> ExitCode {
+    // Find project root and load config
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    let mut compile_options = match project_compile_options(&config, &project_root, features, "dev", true) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    compile_options.coverage = coverage;
+    let src_dir = config.src_dir(&project_root);
+    // Instrumented modules get their own build directory
+    let build_dir = config.beam_dir_for_env(&project_root, if coverage { "cover" } else { "test" });
+
+    // Create build directory
+    if let Err(e) = fs::create_dir_all(&build_dir) {
+        eprintln!("Error creating build directory: {}", e);
         return ExitCode::from(1);
     }
 
-    println!("Compiling {} in test mode...", config.package.name);
+    status!("Compiling {} in test mode...", config.package.name);
 
     // Load all .dream files in src/ directory with package context
     let mut loader = ModuleLoader::with_package(
@@ -2392,47 +4789,76 @@ fn cmd_test(filter: Option<&str>, features: &[String]) -> ExitCode {
     );
 
     // Add _build/bindings/ to search path for auto-generated dependency bindings
-    let bindings_dir = project_root.join("_build").join("bindings");
+    let bindings_dir = config.target_layout(&project_root).bindings_dir();
     loader.add_bindings_dir(bindings_dir);
 
     if let Err(e) = loader.load_all_in_dir(&src_dir) {
-        eprintln!("Error loading modules: {}", e);
+        report_load_error(&e);
         return ExitCode::from(1);
     }
 
-    let modules = loader.into_modules();
+    let mut modules = loader.into_modules();
 
-    // Discover test functions before compilation
-    let mut test_functions: Vec<(String, String)> = Vec::new(); // (module_name, function_name)
+    // Examples in doc comments run as tests too
+    let mut doctest_modules = Vec::new();
     for module in &modules {
-        for item in &module.items {
-            if let Item::Function(func) = item {
-                if cfg::is_test(&func.attrs) {
-                    // Apply filter if provided
-                    if let Some(pattern) = filter {
-                        if !func.name.contains(pattern) {
-                            continue;
-                        }
-                    }
-                    test_functions.push((module.name.clone(), func.name.clone()));
-                }
+        match testing::doctest_module(module, Some(&config.package.name)) {
+            Ok(Some(doctests)) => doctest_modules.push(doctests),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::from(1);
             }
         }
     }
+    modules.extend(doctest_modules);
+
+    // Discover test functions before compilation, in configured copies of
+    // the modules so that `cfg_attr` can mark tests. Compiling configures
+    // the modules themselves, and reports what is wrong with their `#[cfg]`s.
+    let configured = configured_copies(&modules, &compile_options);
+    let discovered = testing::discover(&configured, &compile_options);
+    let found = discovered.len();
+    let (tests, ignored) = filter.select(discovered.clone());
+
+    let layout = config.target_layout(&project_root);
+    let mut test_report = TestReport::new(config.package.name.clone());
+    for test in discovered {
+        if test.ignored && filter.matches(&test) && !tests.contains(&test) {
+            test_report.skip(test);
+        }
+    }
 
-    if test_functions.is_empty() {
-        println!();
-        if filter.is_some() {
-            println!("No tests match the filter.");
+    if tests.is_empty() {
+        status!();
+        if found == 0 {
+            status!("No tests found.");
+        } else if ignored > 0 {
+            status!("No tests to run ({} ignored).", ignored);
         } else {
-            println!("No tests found.");
+            status!("No tests match the filter.");
         }
+        emit(Message::TestSummary { passed: 0, failed: 0, ignored });
+        write_test_reports(reports, &test_report, &layout);
         return ExitCode::SUCCESS;
     }
 
-    // Resolve features (CLI features + their dependencies from config)
-    let resolved_features = config.resolve_features(features);
-    let compile_options = CompileOptions::for_testing_with_features(resolved_features);
+    // Cover the modules a normal build would include, not the tests
+    let mut cover_report = CoverageReport::new();
+    if coverage {
+        let build_options = CompileOptions {
+            test_mode: false,
+            ..compile_options.clone()
+        };
+        for module in &configured {
+            if cfg::should_include(&module.attrs, &build_options)
+                && !module.name.ends_with("::__doctests")
+            {
+                cover_report.add_module(&module.name, module.source_path.clone());
+                run_options.cover.push(TestCase::new(module.name.clone(), "").beam_module());
+            }
+        }
+    }
 
     // Get dependency ebin paths for loading macros from dependencies
     let deps_manager = DepsManager::new(project_root.clone(), config.clone());
@@ -2466,101 +4892,636 @@ fn cmd_test(filter: Option<&str>, features: &[String]) -> ExitCode {
     };
 
     // Check if erl is available
-    if !command_exists("erl") {
-        eprintln!("Error: erl not found in PATH");
-        eprintln!("Install Erlang/OTP to run tests.");
+    if !check_toolchain("run tests") {
         return ExitCode::from(1);
     }
 
     // Get deps ebin paths
     let mut deps_dirs: Vec<PathBuf> = {
-        let deps_manager = DepsManager::new(project_root, config);
+        let deps_manager = DepsManager::new(project_root.clone(), config.clone());
         deps_manager.dep_ebin_paths()
     };
 
     // Add Elixir stdlib paths if available
     deps_dirs.extend(find_elixir_ebin_dirs());
 
-    // Run tests
-    println!();
-    println!("Running {} test{}...", test_functions.len(), if test_functions.len() == 1 { "" } else { "s" });
-    println!();
-
-    let mut passed = 0;
-    let mut failed = 0;
-    let mut failures: Vec<(String, String, String)> = Vec::new(); // (module, function, error)
-
-    for (module_name, func_name) in &test_functions {
-        // Build the full module name with dream:: prefix
-        let beam_module = if module_name.starts_with("dream::") {
-            module_name.clone()
-        } else {
-            format!("dream::{}", module_name)
-        };
+    // Run every test in one node; the runner reports results on stdout
+    status!();
+    status!("Running {} test{}...", tests.len(), if tests.len() == 1 { "" } else { "s" });
+    status!();
 
-        // Build eval expression to run the test and catch any errors
-        let eval_expr = format!(
-            "try '{}':'{}'() of _ -> io:format(\"ok~n\"), halt(0) catch Class:Reason:Stack -> io:format(\"~p:~p~n~p~n\", [Class, Reason, Stack]), halt(1) end.",
-            beam_module, func_name
-        );
+    let mut cmd = toolchain::erl();
+    CodePath::project(&build_dir, stdlib_dir.as_deref(), &deps_dirs).apply(&mut cmd);
 
-        let mut cmd = Command::new("erl");
-        cmd.arg("-pa").arg(&build_dir);
+    cmd.arg("-noshell")
+        .arg("-eval")
+        .arg(testing::runner_expr(&tests, &run_options))
+        .stdout(std::process::Stdio::piped());
 
-        // Add stdlib to code path if available
-        if let Some(ref stdlib) = stdlib_dir {
-            cmd.arg("-pa").arg(stdlib);
+    let started = std::time::Instant::now();
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Error: failed to start erl: {}", e);
+            return ExitCode::from(1);
         }
+    };
 
-        // Add deps ebin directories to code path
-        for dep_dir in &deps_dirs {
-            cmd.arg("-pa").arg(dep_dir);
+    let mut passed = 0;
+    let mut failures: Vec<(TestCase, String, String)> = Vec::new(); // (test, output, error)
+    let mut report = |event: TestEvent| match event {
+        TestEvent::Finished { test, outcome: TestOutcome::Passed, output } => {
+            passed += 1;
+            status!("  {} {} ... ok", "\u{2713}", test.name());
+            emit(Message::Test {
+                name: test.name(),
+                status: TestStatus::Ok,
+                output: output.clone(),
+                error: None,
+            });
+            test_report.record(test, TestStatus::Ok, output, None);
         }
+        TestEvent::Finished { test, outcome: TestOutcome::Failed(error), output } => {
+            status!("  {} {} ... FAILED", "\u{2717}", test.name());
+            emit(Message::Test {
+                name: test.name(),
+                status: TestStatus::Failed,
+                output: output.clone(),
+                error: Some(error.clone()),
+            });
+            test_report.record(test.clone(), TestStatus::Failed, output.clone(), Some(error.clone()));
+            failures.push((test, output, error));
+        }
+        TestEvent::Finished { test, outcome: TestOutcome::TimedOut, output } => {
+            status!("  {} {} ... TIMEOUT", "\u{2717}", test.name());
+            let limit = run_options.timeout.map_or(0, |t| t.as_secs());
+            let error = format!("timed out after {}s", limit);
+            emit(Message::Test {
+                name: test.name(),
+                status: TestStatus::Timeout,
+                output: output.clone(),
+                error: Some(error.clone()),
+            });
+            test_report.record(test.clone(), TestStatus::Timeout, output.clone(), Some(error.clone()));
+            failures.push((test, output, error));
+        }
+        TestEvent::Output(line) => status!("{}", line),
+        TestEvent::Covered { module, line, count } => cover_report.record(&module, line, count),
+    };
 
-        cmd.arg("-noshell").arg("-eval").arg(&eval_expr);
-
-        let output = cmd.output();
-
-        match output {
-            Ok(out) => {
-                if out.status.success() {
-                    passed += 1;
-                    println!("  {} {}::{} ... ok", "\u{2713}", module_name, func_name);
-                } else {
-                    failed += 1;
-                    let stderr = String::from_utf8_lossy(&out.stdout).to_string();
-                    failures.push((module_name.clone(), func_name.clone(), stderr));
-                    println!("  {} {}::{} ... FAILED", "\u{2717}", module_name, func_name);
-                }
+    let mut parser = ResultParser::new(&tests);
+    if let Some(stdout) = child.stdout.take() {
+        for line in io::BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(event) = parser.feed(&line) {
+                report(event);
+            }
+        }
+    }
+    let _ = child.wait();
+    for event in parser.finish_all() {
+        report(event);
+    }
+    test_report.duration = started.elapsed();
+
+    // Print summary
+    status!();
+    failures.sort_by(|a, b| a.0.cmp(&b.0));
+    if !failures.is_empty() {
+        status!("Failures:");
+        status!();
+        for (test, output, error) in &failures {
+            status!("  {}", test.name());
+            for line in output.lines().chain(error.lines()) {
+                status!("    {}", line);
+            }
+            status!();
+        }
+    }
+
+    if coverage {
+        let cover_dir = layout.cover_dir();
+        status!("Coverage:");
+        status!();
+        for line in cover_report.summary().lines() {
+            status!("  {}", line);
+        }
+        status!();
+        match cover_report.write(&cover_dir, &project_root) {
+            Ok(()) => status!(
+                "Wrote {} and {}",
+                cover_dir.join("lcov.info").display(),
+                cover_dir.join("html").join("index.html").display()
+            ),
+            Err(e) => eprintln!("Warning: failed to write coverage report: {}", e),
+        }
+        status!();
+    }
+
+    write_test_reports(reports, &test_report, &layout);
+
+    let failed = failures.len();
+    let total = passed + failed;
+    emit(Message::TestSummary { passed, failed, ignored });
+    let ignored = if ignored > 0 {
+        format!(" {} ignored.", ignored)
+    } else {
+        String::new()
+    };
+    if failed == 0 {
+        status!("{} test{} passed.{}", total, if total == 1 { "" } else { "s" }, ignored);
+        ExitCode::SUCCESS
+    } else {
+        status!("{} passed, {} failed.{}", passed, failed, ignored);
+        ExitCode::from(1)
+    }
+}
+
+/// Write `report` to `_build/test-results/<package>.<ext>` in each of
+/// `formats`. A report that can't be written is a warning: the run's
+/// result stands.
+fn write_test_reports(formats: &[ReportFormat], report: &TestReport, layout: &TargetLayout) {
+    if formats.is_empty() {
+        return;
+    }
+    let dir = layout.test_results_dir();
+    if let Err(e) = fs::create_dir_all(&dir) {
+        eprintln!("Warning: failed to create {}: {}", dir.display(), e);
+        return;
+    }
+    for format in formats {
+        let path = dir.join(format!("{}.{}", report.package, format.extension()));
+        match fs::write(&path, report.render(*format)) {
+            Ok(()) => status!("Wrote {}", path.display()),
+            Err(e) => eprintln!("Warning: failed to write {}: {}", path.display(), e),
+        }
+    }
+    status!();
+}
+
+/// Run the project's `#[bench]` functions and print per-call timings.
+/// Build the project with debug probes and run its program under the
+/// debugger, from a command prompt or as a DAP server on stdio.
+fn cmd_debug(dap: bool, bin: Option<&str>, features: &FeatureSelection, program_args: &[String]) -> ExitCode {
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    let mut compile_options = match project_compile_options(&config, &project_root, features, "dev", false) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    compile_options.debug = true;
+    let src_dir = config.src_dir(&project_root);
+    // Instrumented modules get their own build directory
+    let build_dir = config.beam_dir_for_env(&project_root, "debug");
+    if let Err(e) = fs::create_dir_all(&build_dir) {
+        eprintln!("Error creating build directory: {}", e);
+        return ExitCode::from(1);
+    }
+
+    let module_name = match bin {
+        Some(bin) => match config.bin_module(&project_root, bin) {
+            Some(module) => module,
+            None => {
+                eprintln!("Error: no binary named '{}' in src/bin/", bin);
+                return ExitCode::from(1);
             }
+        },
+        None => config.entry_module(&project_root),
+    };
+
+    status!("Compiling {} for debugging...", config.package.name);
+
+    let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+    loader.add_bindings_dir(config.target_layout(&project_root).bindings_dir());
+    if let Err(e) = loader.load_all_in_dir(&src_dir) {
+        report_load_error(&e);
+        return ExitCode::from(1);
+    }
+    let modules = loader.into_modules();
+
+    let mut sources = SourceMap::new();
+    for module in &modules {
+        if let Some(path) = &module.source_path {
+            sources.add(path.clone(), config.qualify_module(&module.name));
+        }
+    }
+
+    let deps_manager = DepsManager::new(project_root.clone(), config.clone());
+    let mut deps_dirs = deps_manager.dep_ebin_paths();
+    let dependency_names: HashSet<String> = config.dependencies.keys().cloned().collect();
+    let result = compile_modules_with_options(
+        modules,
+        &build_dir,
+        "beam",
+        Some(&config.package.name),
+        &compile_options,
+        &deps_dirs,
+        &dependency_names,
+    );
+    if result != ExitCode::SUCCESS {
+        return result;
+    }
+
+    let stdlib_dir = match compile_stdlib() {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            None
+        }
+    };
+
+    if !check_toolchain("debug on the BEAM") {
+        return ExitCode::from(1);
+    }
+    deps_dirs.extend(find_elixir_ebin_dirs());
+
+    let mut erl = toolchain::erl();
+    CodePath::project(&build_dir, stdlib_dir.as_deref(), &deps_dirs).apply(&mut erl);
+    let program = main_status_expr(&module_name, program_args);
+
+    if dap {
+        return match debugger::serve_dap(io::BufReader::new(io::stdin()), io::stdout(), erl, &program, &sources) {
+            Ok(()) => ExitCode::SUCCESS,
             Err(e) => {
-                failed += 1;
-                failures.push((module_name.clone(), func_name.clone(), e.to_string()));
-                println!("  {} {}::{} ... FAILED ({})", "\u{2717}", module_name, func_name, e);
+                eprintln!("Error: {}", e);
+                ExitCode::from(1)
             }
+        };
+    }
+    match debugger::run_cli(erl, &program, &sources, io::stdin().lock(), io::stdout()) {
+        Ok(Some(status)) => ExitCode::from(status as u8),
+        Ok(None) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(1)
         }
     }
+}
 
-    // Print summary
+/// What `dream profile` runs.
+enum ProfileTarget {
+    /// The program's `main`, with these arguments
+    Main(Vec<String>),
+    /// A zero-argument function, e.g. `app::parser::parse_all`
+    Function(String),
+    /// Every `#[test]` function
+    Tests,
+}
+
+/// Build the project and run the target under a profiler, then print the
+/// functions that took the most time.
+fn cmd_profile(
+    target: &ProfileTarget,
+    tool: Tool,
+    flamegraph: Option<&Path>,
+    limit: usize,
+    features: &FeatureSelection,
+) -> ExitCode {
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    let tests = matches!(target, ProfileTarget::Tests);
+    let compile_options = match project_compile_options(&config, &project_root, features, "dev", tests) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let src_dir = config.src_dir(&project_root);
+    let build_dir = config.beam_dir_for_env(&project_root, if tests { "test" } else { "dev" });
+    if let Err(e) = fs::create_dir_all(&build_dir) {
+        eprintln!("Error creating build directory: {}", e);
+        return ExitCode::from(1);
+    }
+
+    status!("Compiling {} for profiling...", config.package.name);
+
+    let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+    loader.add_bindings_dir(config.target_layout(&project_root).bindings_dir());
+    if let Err(e) = loader.load_all_in_dir(&src_dir) {
+        report_load_error(&e);
+        return ExitCode::from(1);
+    }
+    let modules = loader.into_modules();
+
+    let program = match target {
+        ProfileTarget::Main(args) => main_status_expr(&config.entry_module(&project_root), args),
+        ProfileTarget::Function(path) => {
+            let (module, function) = match path.rsplit_once("::") {
+                Some((module, function)) => (config.qualify_module(module), function),
+                None => (config.entry_module(&project_root), path.as_str()),
+            };
+            profile::function_program(&module, function)
+        }
+        ProfileTarget::Tests => {
+            let configured = configured_copies(&modules, &compile_options);
+            let (tests, _) = TestFilter::default().select(testing::discover(&configured, &compile_options));
+            if tests.is_empty() {
+                status!("No tests found.");
+                return ExitCode::SUCCESS;
+            }
+            profile::tests_program(&tests)
+        }
+    };
+
+    let deps_manager = DepsManager::new(project_root.clone(), config.clone());
+    let dep_ebin_paths = deps_manager.dep_ebin_paths();
+    let dependency_names: HashSet<String> = config.dependencies.keys().cloned().collect();
+    let result = compile_modules_with_options(
+        modules,
+        &build_dir,
+        "beam",
+        Some(&config.package.name),
+        &compile_options,
+        &dep_ebin_paths,
+        &dependency_names,
+    );
+    if result != ExitCode::SUCCESS {
+        return result;
+    }
+
+    let stdlib_dir = match compile_stdlib() {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            None
+        }
+    };
+
+    if !check_toolchain("profile on the BEAM") {
+        return ExitCode::from(1);
+    }
+
+    // fprof's trace and analysis files
+    let scratch = config.target_layout(&project_root).profiler_dir();
+    if let Err(e) = fs::create_dir_all(&scratch) {
+        eprintln!("Error creating {}: {}", scratch.display(), e);
+        return ExitCode::from(1);
+    }
+    let mut preload = vec![build_dir.clone()];
+    preload.extend(stdlib_dir.clone());
+
+    status!();
+    status!("Profiling with {}...", if tool == Tool::Fprof { "fprof" } else { "eprof" });
+    status!();
+
+    let mut cmd = toolchain::erl();
+    let elixir_dirs = find_elixir_ebin_dirs();
+    CodePath::project(&build_dir, stdlib_dir.as_deref(), dep_ebin_paths.iter().chain(&elixir_dirs))
+        .apply(&mut cmd);
+    cmd.arg("-noshell")
+        .arg("-eval")
+        .arg(profile::harness_expr(tool, &program, &preload, &scratch))
+        .stdout(std::process::Stdio::piped());
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Error: failed to start erl: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let mut profile = Profile::new();
+    if let Some(stdout) = child.stdout.take() {
+        for line in io::BufReader::new(stdout).lines().map_while(Result::ok) {
+            if let Some(output) = profile.feed(&line) {
+                println!("{}", output);
+            }
+        }
+    }
+    let _ = child.wait();
+
+    let Some(status) = profile.status else {
+        eprintln!("Error: the profiler stopped before reporting");
+        return ExitCode::from(1);
+    };
     println!();
-    if !failures.is_empty() {
-        println!("Failures:");
+    println!("Total {:.3} ms in {} functions", profile.total_us() as f64 / 1000.0, profile.functions.len());
+    println!();
+    print!("{}", profile.report(limit));
+
+    if let Some(path) = flamegraph {
+        match profile.flamegraph() {
+            Some(svg) => match fs::write(path, svg) {
+                Ok(()) => {
+                    println!();
+                    println!("Flamegraph written to {}", path.display());
+                }
+                Err(e) => {
+                    eprintln!("Error writing {}: {}", path.display(), e);
+                    return ExitCode::from(1);
+                }
+            },
+            None => eprintln!("Warning: no calls were recorded, so there is no flamegraph"),
+        }
+    }
+    ExitCode::from(status as u8)
+}
+
+/// Trace calls matching `pattern` on a running node from a hidden node,
+/// until the limit is reached or the user stops it.
+fn cmd_trace(pattern: &str, node: &str, cookie: Option<String>, options: &TraceOptions) -> ExitCode {
+    let pattern = match TracePattern::parse(pattern) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    if !check_toolchain("trace a node") {
+        return ExitCode::from(1);
+    }
+
+    let mut cmd = toolchain::erl();
+    cmd.args(trace::node_options("trace", node, cookie).erl_args())
+        .arg("-hidden")
+        .arg("-noshell")
+        .arg("-eval")
+        .arg(trace::tracer_expr(node, &pattern, options));
+
+    match cmd.status() {
+        Ok(s) if s.success() => ExitCode::SUCCESS,
+        Ok(s) => ExitCode::from(s.code().unwrap_or(1) as u8),
+        Err(e) => {
+            eprintln!("Error running erl: {}", e);
+            ExitCode::from(1)
+        }
+    }
+}
+
+fn cmd_top(node: &str, cookie: Option<String>, sort: SortKey, interval: f64) -> ExitCode {
+    let interval = match std::time::Duration::try_from_secs_f64(interval) {
+        Ok(interval) if interval >= std::time::Duration::from_millis(100) => interval,
+        _ => {
+            eprintln!("Error: --interval must be at least 0.1 seconds");
+            return ExitCode::from(1);
+        }
+    };
+
+    if !check_toolchain("watch a node") {
+        return ExitCode::from(1);
+    }
+
+    let mut cmd = toolchain::erl();
+    cmd.args(trace::node_options("top", node, cookie).erl_args());
+
+    match top::run(cmd, node, sort, interval) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(1)
+        }
+    }
+}
+
+fn cmd_bench(filter: Option<&str>, options: &BenchOptions, features: &FeatureSelection) -> ExitCode {
+    let (project_root, config) = match ProjectConfig::from_project_root() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    // Benchmarks may live next to tests, so cfg(test) is on, but they are
+    // measured with the release profile in a build directory of their own
+    let compile_options = match project_compile_options(&config, &project_root, features, "release", true) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let src_dir = config.src_dir(&project_root);
+    let build_dir = config.beam_dir_for_env(&project_root, "bench");
+
+    if let Err(e) = fs::create_dir_all(&build_dir) {
+        eprintln!("Error creating build directory: {}", e);
+        return ExitCode::from(1);
+    }
+
+    println!("Compiling {} for benchmarks...", config.package.name);
+
+    let mut loader = ModuleLoader::with_package(config.package.name.clone(), src_dir.clone());
+    loader.add_bindings_dir(config.target_layout(&project_root).bindings_dir());
+    if let Err(e) = loader.load_all_in_dir(&src_dir) {
+        eprintln!("Error loading modules: {}", e);
+        return ExitCode::from(1);
+    }
+    let modules = loader.into_modules();
+
+    let filter = TestFilter {
+        pattern: filter.map(str::to_string),
+        ..TestFilter::default()
+    };
+    let configured = configured_copies(&modules, &compile_options);
+    let (benches, _) = filter.select(bench::discover(&configured, &compile_options));
+    if benches.is_empty() {
         println!();
-        for (module, func, error) in &failures {
-            println!("  {}::{}", module, func);
-            for line in error.lines() {
-                println!("    {}", line);
+        println!("No benchmarks found.");
+        return ExitCode::SUCCESS;
+    }
+
+    let deps_manager = DepsManager::new(project_root.clone(), config.clone());
+    let dep_ebin_paths = deps_manager.dep_ebin_paths();
+    let dependency_names: HashSet<String> = config.dependencies.keys().cloned().collect();
+
+    let result = compile_modules_with_options(
+        modules,
+        &build_dir,
+        "beam",
+        Some(&config.package.name),
+        &compile_options,
+        &dep_ebin_paths,
+        &dependency_names,
+    );
+    if result != ExitCode::SUCCESS {
+        return result;
+    }
+
+    let stdlib_dir = match compile_stdlib() {
+        Ok(dir) => Some(dir),
+        Err(e) => {
+            eprintln!("Warning: {}", e);
+            None
+        }
+    };
+
+    if !check_toolchain("run benchmarks") {
+        return ExitCode::from(1);
+    }
+
+    println!();
+    println!("Running {} benchmark{}...", benches.len(), if benches.len() == 1 { "" } else { "s" });
+    println!();
+
+    let mut cmd = toolchain::erl();
+    let elixir_dirs = find_elixir_ebin_dirs();
+    CodePath::project(&build_dir, stdlib_dir.as_deref(), dep_ebin_paths.iter().chain(&elixir_dirs))
+        .apply(&mut cmd);
+    cmd.arg("-noshell")
+        .arg("-eval")
+        .arg(bench::runner_expr(&benches, options))
+        .stdout(std::process::Stdio::piped());
+
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("Error: failed to start erl: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+
+    let width = benches.iter().map(|b| b.name().len()).max().unwrap_or(0);
+    let mut failed = 0;
+    if let Some(stdout) = child.stdout.take() {
+        for line in io::BufReader::new(stdout).lines().map_while(Result::ok) {
+            match bench::parse_line(&benches, &line) {
+                Some(BenchEvent::Measured { bench, stats }) => {
+                    println!(
+                        "  {:<width$}  mean {:>10}/op  median {:>10}/op  p99 {:>10}/op  ({} \u{d7} {} iters)",
+                        bench.name(),
+                        bench::format_ns(stats.mean),
+                        bench::format_ns(stats.median),
+                        bench::format_ns(stats.p99),
+                        stats.samples,
+                        stats.iterations,
+                    );
+                }
+                Some(BenchEvent::Failed { bench, message }) => {
+                    failed += 1;
+                    println!("  {:<width$}  FAILED", bench.name());
+                    for line in message.lines() {
+                        println!("    {}", line);
+                    }
+                }
+                Some(BenchEvent::Output(line)) => println!("{}", line),
+                None => {}
             }
-            println!();
         }
     }
+    let _ = child.wait();
 
-    let total = passed + failed;
+    println!();
     if failed == 0 {
-        println!("{} test{} passed.", total, if total == 1 { "" } else { "s" });
         ExitCode::SUCCESS
     } else {
-        println!("{} passed, {} failed.", passed, failed);
+        println!("{} benchmark{} failed.", failed, if failed == 1 { "" } else { "s" });
         ExitCode::from(1)
     }
 }
@@ -0,0 +1,33 @@
+//! Escaping for the HTML, SVG and XML the reports are written in: coverage
+//! and timing pages, `dream doc`, flame graphs and JUnit files.
+
+/// `text` with the characters HTML and XML give meaning to escaped, and
+/// those XML doesn't allow at all (most control characters) dropped. Safe
+/// in element content and in quoted attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a & \"b\"\u{1b}[0m"), "a &amp; &quot;b&quot;[0m");
+        assert_eq!(escape("<T>\tit's"), "&lt;T&gt;\tit&apos;s");
+    }
+}
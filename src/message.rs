@@ -1,6 +1,6 @@
 //! Message types for inter-process communication.
 
-use crate::{Pid, Value};
+use crate::{Pid, Ref, Value};
 
 /// Messages sent between processes
 #[derive(Debug, Clone)]
@@ -18,5 +18,5 @@ pub enum SystemMsg {
     /// When trap_exit is true, this becomes a message; otherwise it kills the process
     Exit(Pid, Value),
     /// A monitored process exited: {:DOWN, Ref, :process, Pid, Reason}
-    Down(u64, Pid, Value),
+    Down(Ref, Pid, Value),
 }
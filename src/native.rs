@@ -0,0 +1,194 @@
+//! Native execution of Dream programs on the built-in VM.
+//!
+//! `dream run --native` compiles source files to VM bytecode with
+//! [`Codegen`](crate::compiler::Codegen) and runs them on the Rust
+//! [`Scheduler`], so no Erlang/OTP install is needed. This supports the
+//! subset of Dream the VM codegen handles: processes, message passing,
+//! links, monitors and timers. Distribution and hot code loading are
+//! BEAM-only.
+//!
+//! Calls to the `process` and `timer` functions the scheduler implements
+//! itself, such as `process::spawn_with`, `process::set_mailbox_limit` and
+//! `timer::interval`, compile to single VM instructions. Their options,
+//! intervals and limits must be literals.
+//!
+//! Processes are preempted after [`crate::REDUCTIONS_PER_SLICE`]
+//! reductions, so a busy loop in one process cannot starve the others.
+//! They take turns on the calling thread rather than run in parallel.
+
+use crate::{Instruction, Module, Pid, ProcessStatus, Scheduler, StepResult, Value};
+
+/// Reductions each step may spend before timers are ticked.
+const STEP_BUDGET: u32 = 1000;
+
+/// Outcome of running a function to completion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeRun {
+    /// Return value of the entry function, or the exit reason if it crashed.
+    pub result: Value,
+    /// Final status of the entry process.
+    pub status: ProcessStatus,
+}
+
+impl NativeRun {
+    /// Whether the entry function returned normally.
+    pub fn success(&self) -> bool {
+        self.status == ProcessStatus::Done
+    }
+}
+
+/// Load `modules` into a fresh scheduler and run `module:function(args)`.
+///
+/// Runs until every process has finished or is blocked forever. Returns an
+/// error if the entry point does not exist.
+pub fn run_function(
+    modules: Vec<Module>,
+    module: &str,
+    function: &str,
+    args: Vec<Value>,
+) -> Result<NativeRun, String> {
+    let mut scheduler = Scheduler::new();
+    for m in modules {
+        scheduler.load_module(m)?;
+    }
+
+    let arity = args.len() as u8;
+    let exported = scheduler
+        .modules
+        .get(module)
+        .map(|m| m.is_exported(function, arity));
+    match exported {
+        None => return Err(format!("module '{}' not found", module)),
+        Some(false) => {
+            return Err(format!(
+                "function {}:{}/{} is not defined or not public",
+                module, function, arity
+            ))
+        }
+        Some(true) => {}
+    }
+
+    let entry = scheduler.spawn(vec![
+        Instruction::CallMFA {
+            module: module.to_string(),
+            function: function.to_string(),
+            arity,
+        },
+        Instruction::End,
+    ]);
+    if let Some(process) = scheduler.processes.get_mut(&entry) {
+        for (i, arg) in args.into_iter().enumerate() {
+            process.registers[i] = arg;
+        }
+    }
+
+    while scheduler.step(STEP_BUDGET) == StepResult::Busy {
+        // Output is printed as it is produced; don't let the buffer grow
+        scheduler.take_output();
+    }
+
+    Ok(outcome(&scheduler, entry))
+}
+
+fn outcome(scheduler: &Scheduler, entry: Pid) -> NativeRun {
+    let Some(process) = scheduler.processes.get(&entry) else {
+        return NativeRun {
+            result: Value::Atom("noproc".to_string()),
+            status: ProcessStatus::Crashed,
+        };
+    };
+    match process.status {
+        ProcessStatus::Done => NativeRun {
+            result: process.registers[0].clone(),
+            status: ProcessStatus::Done,
+        },
+        // Blocked in receive with nothing left that could wake it
+        ProcessStatus::Waiting | ProcessStatus::Ready => NativeRun {
+            result: Value::Atom("deadlock".to_string()),
+            status: process.status,
+        },
+        ProcessStatus::Crashed => NativeRun {
+            result: process.exit_reason.clone(),
+            status: ProcessStatus::Crashed,
+        },
+    }
+}
+
+/// Parse a command-line argument into a VM value.
+/// Integers and floats are numbers, `"quoted"` text is a string, and
+/// anything else (optionally written `:name`) is an atom.
+pub fn parse_arg(arg: &str) -> Value {
+    if let Ok(n) = arg.parse::<i64>() {
+        Value::Int(n)
+    } else if let Ok(f) = arg.parse::<f64>() {
+        Value::Float(f)
+    } else if arg.len() >= 2 && arg.starts_with('"') && arg.ends_with('"') {
+        Value::String(arg[1..arg.len() - 1].to_string())
+    } else {
+        Value::Atom(arg.trim_start_matches(':').to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::compile;
+
+    #[test]
+    fn test_run_function_returns_value() {
+        let module = compile(
+            r#"
+            mod math {
+                pub fn add(x: int, y: int) -> int {
+                    x + y
+                }
+            }
+        "#,
+        )
+        .unwrap();
+
+        let run = run_function(
+            vec![module],
+            "math",
+            "add",
+            vec![Value::Int(3), Value::Int(4)],
+        )
+        .unwrap();
+        assert!(run.success());
+        assert_eq!(run.result, Value::Int(7));
+    }
+
+    #[test]
+    fn test_run_function_missing_entry() {
+        let module = compile(
+            r#"
+            mod math {
+                pub fn add(x: int, y: int) -> int {
+                    x + y
+                }
+            }
+        "#,
+        )
+        .unwrap();
+
+        let err = run_function(vec![module], "math", "main", vec![]).unwrap_err();
+        assert!(err.contains("math:main/0"), "unexpected error: {}", err);
+
+        let err = run_function(vec![], "math", "main", vec![]).unwrap_err();
+        assert!(
+            err.contains("module 'math' not found"),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_parse_arg() {
+        assert_eq!(parse_arg("42"), Value::Int(42));
+        assert_eq!(parse_arg("-7"), Value::Int(-7));
+        assert_eq!(parse_arg("1.5"), Value::Float(1.5));
+        assert_eq!(parse_arg("\"hi\""), Value::String("hi".to_string()));
+        assert_eq!(parse_arg(":ok"), Value::Atom("ok".to_string()));
+        assert_eq!(parse_arg("ok"), Value::Atom("ok".to_string()));
+    }
+}
@@ -0,0 +1,442 @@
+//! Machine-readable output for `--message-format=json`.
+//!
+//! In JSON mode `dream build`, `dream check` and `dream test` print one
+//! JSON object per line on stdout and send their human-readable progress
+//! to stderr, so editor plugins and CI can read diagnostics, compiled
+//! artifacts and test results without scraping text. Every object has a
+//! `reason` field naming what it describes, as cargo's messages do.
+//!
+//! `--message-format=sarif` reports the same [`Diagnostic`]s as one SARIF
+//! log instead; see [`crate::sarif`].
+
+use crate::compiler::{Annotations, ErrorCode, InternalCompilerError, ParseError, TypeError, Warning};
+use serde::Serialize;
+use std::fmt;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// How the CLI reports results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Text for people, the default
+    #[default]
+    Human,
+    /// One JSON object per line on stdout
+    Json,
+    /// A SARIF log of the diagnostics on stdout, at the end
+    Sarif,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            _ => Err(format!(
+                "unknown message format `{}` (expected `human`, `json` or `sarif`)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MessageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Human => "human",
+            Self::Json => "json",
+            Self::Sarif => "sarif",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        })
+    }
+}
+
+/// Where in a source file a diagnostic points. Lines and columns start at
+/// 1; columns count characters, not bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiagnosticSpan {
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub line_start: usize,
+    pub column_start: usize,
+    pub line_end: usize,
+    pub column_end: usize,
+}
+
+impl DiagnosticSpan {
+    /// The span of the bytes `range` in `source`.
+    pub fn new(source: &str, range: Range<usize>) -> Self {
+        let (line_start, column_start) = line_column(source, range.start);
+        let (line_end, column_end) = line_column(source, range.end);
+        Self {
+            byte_start: range.start,
+            byte_end: range.end,
+            line_start,
+            column_start,
+            line_end,
+            column_end,
+        }
+    }
+}
+
+/// A span a diagnostic points at besides its main one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiagnosticLabel {
+    pub span: DiagnosticSpan,
+    pub message: String,
+}
+
+/// A fix a tool can apply: replace the text at `span` with `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiagnosticSuggestion {
+    pub span: DiagnosticSpan,
+    pub replacement: String,
+    pub message: String,
+    /// `machine-applicable` if `dream fix` applies it, else `maybe-incorrect`
+    pub applicability: &'static str,
+}
+
+/// The 1-based line and column of byte `offset` in `source`.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// An error or warning from the compiler.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    /// Stable code such as `E0030`, or the lint name for lints
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Dream module the diagnostic is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<DiagnosticSpan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub help: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<DiagnosticLabel>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<DiagnosticSuggestion>,
+    /// The diagnostic as it would have been printed in human mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rendered: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+            code: None,
+            module: None,
+            file: None,
+            span: None,
+            help: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            suggestions: Vec::new(),
+            rendered: None,
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Level::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Level::Warning, message)
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn in_module(mut self, module: impl Into<String>) -> Self {
+        self.module = Some(module.into());
+        self
+    }
+
+    pub fn with_file(mut self, file: Option<PathBuf>) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Point at the bytes `range` of `source`.
+    pub fn with_span(mut self, source: &str, range: Option<Range<usize>>) -> Self {
+        self.span = range.map(|range| DiagnosticSpan::new(source, range));
+        self
+    }
+
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    /// Add the notes of `annotations`, and given the source its labels
+    /// and suggestions.
+    pub fn with_annotations(mut self, source: Option<&str>, annotations: &Annotations) -> Self {
+        self.notes.extend(annotations.notes.iter().cloned());
+        if let Some(source) = source {
+            self.labels.extend(annotations.labels.iter().map(|label| DiagnosticLabel {
+                span: DiagnosticSpan::new(source, label.span.clone()),
+                message: label.message.clone(),
+            }));
+            self.suggestions
+                .extend(annotations.suggestions.iter().map(|suggestion| DiagnosticSuggestion {
+                    span: DiagnosticSpan::new(source, suggestion.span.clone()),
+                    replacement: suggestion.replacement.clone(),
+                    message: suggestion.message.clone(),
+                    applicability: suggestion.applicability.as_str(),
+                }));
+        }
+        self
+    }
+
+    pub fn with_rendered(mut self, rendered: impl Into<String>) -> Self {
+        self.rendered = Some(rendered.into());
+        self
+    }
+}
+
+impl Diagnostic {
+    /// A parse error in `module`, whose source is `source`.
+    pub fn from_parse_error(module: &str, error: &ParseError, source: &str) -> Self {
+        let span = error.span.offset()..error.span.offset() + error.span.len();
+        Self::error(error.message.clone())
+            .with_code(error.code.as_str())
+            .in_module(module)
+            .with_span(source, Some(span))
+            .with_help(error.help.clone())
+            .with_annotations(Some(source), &error.annotations)
+    }
+
+    /// A type error in `module`. The span is only filled in given the
+    /// module's source.
+    pub fn from_type_error(module: &str, error: &TypeError, source: Option<&str>) -> Self {
+        let diagnostic = Self::error(error.message.clone())
+            .with_code(error.code.as_str())
+            .in_module(module)
+            .with_help(error.help.clone())
+            .with_annotations(source, &error.annotations);
+        match source {
+            Some(source) => {
+                let span = error.span.map(|s| s.offset()..s.offset() + s.len());
+                diagnostic.with_span(source, span)
+            }
+            None => diagnostic,
+        }
+    }
+
+    /// A panic in the compiler, pointing at the item it was compiling.
+    pub fn from_internal_error(error: &InternalCompilerError) -> Self {
+        let mut diagnostic = Self::error(error.to_string())
+            .with_code(ErrorCode::InternalError.as_str())
+            .with_file(error.file.clone())
+            .with_help(Some(error.help()));
+        diagnostic.notes = error.notes();
+        if let Some(module) = &error.module {
+            diagnostic = diagnostic.in_module(module);
+        }
+        match error.source() {
+            Some(source) => diagnostic.with_span(source, error.span.clone()),
+            None => diagnostic,
+        }
+    }
+
+    /// A type checker warning. `source` is the source of the warning's
+    /// module, if known.
+    pub fn from_warning(warning: &Warning, source: Option<&str>) -> Self {
+        let mut diagnostic = Self::warning(warning.message.clone())
+            .with_help(warning.help.clone())
+            .with_annotations(source, &warning.annotations);
+        if let Some(code) = warning.code {
+            diagnostic = diagnostic.with_code(code.as_str());
+        }
+        if let Some(module) = &warning.module {
+            diagnostic = diagnostic.in_module(module);
+        }
+        match source {
+            Some(source) => diagnostic.with_span(source, warning.span.clone()),
+            None => diagnostic,
+        }
+    }
+}
+
+/// How a test ended, as reported in JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TestStatus {
+    Ok,
+    Failed,
+    Timeout,
+}
+
+/// One line of JSON output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum Message {
+    /// An error or warning
+    CompilerMessage { diagnostic: Box<Diagnostic> },
+    /// A module was compiled, or was already up to date (`fresh`)
+    CompilerArtifact {
+        /// BEAM module name, e.g. `dream::app::users`
+        module: String,
+        filename: PathBuf,
+        fresh: bool,
+    },
+    /// A build or check is over
+    BuildFinished { success: bool },
+    /// A test finished
+    Test {
+        name: String,
+        status: TestStatus,
+        /// What the test printed
+        #[serde(skip_serializing_if = "String::is_empty")]
+        output: String,
+        /// Why it failed
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// Every test has finished
+    TestSummary {
+        passed: usize,
+        failed: usize,
+        ignored: usize,
+    },
+}
+
+impl Message {
+    /// The message as one line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("messages are always serializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_format_from_str() {
+        assert_eq!("json".parse(), Ok(MessageFormat::Json));
+        assert_eq!("human".parse(), Ok(MessageFormat::Human));
+        assert_eq!("sarif".parse(), Ok(MessageFormat::Sarif));
+        assert!("xml".parse::<MessageFormat>().is_err());
+    }
+
+    #[test]
+    fn test_line_column() {
+        let source = "fn main() {\n    let é = 1;\n}\n";
+        assert_eq!(line_column(source, 0), (1, 1));
+        assert_eq!(line_column(source, 16), (2, 5));
+        // Columns count characters: `é` is two bytes
+        assert_eq!(line_column(source, 22), (2, 10));
+        assert_eq!(line_column(source, 1000), (4, 1));
+    }
+
+    #[test]
+    fn test_compiler_message_json() {
+        let diagnostic = Diagnostic::warning("unused variable `x`")
+            .in_module("app::math")
+            .with_file(Some(PathBuf::from("src/math.dream")))
+            .with_span("let x = 1;", Some(4..5))
+            .with_help(Some("prefix it with an underscore".to_string()));
+        let message = Message::CompilerMessage { diagnostic: Box::new(diagnostic) };
+        assert_eq!(
+            message.to_json(),
+            r#"{"reason":"compiler-message","diagnostic":{"level":"warning","message":"unused variable `x`","module":"app::math","file":"src/math.dream","span":{"byte_start":4,"byte_end":5,"line_start":1,"column_start":5,"line_end":1,"column_end":6},"help":"prefix it with an underscore"}}"#
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_code() {
+        let error = TypeError::new("undefined variable: y").with_code(ErrorCode::UndefinedVariable);
+        let diagnostic = Diagnostic::from_type_error("app", &error, None);
+        assert_eq!(diagnostic.code.as_deref(), Some("E0020"));
+        assert!(Message::CompilerMessage { diagnostic: Box::new(diagnostic) }.to_json().contains(r#""code":"E0020""#));
+
+        let warning = Diagnostic::from_warning(&Warning::new("unused"), None);
+        assert_eq!(warning.code, None);
+    }
+
+    #[test]
+    fn test_diagnostic_annotations() {
+        let source = "fn f() {\n    g(1\n}";
+        let error = crate::compiler::Parser::new(source).parse_file("app").unwrap_err();
+        let diagnostic = Diagnostic::from_parse_error("app", &error, source);
+        assert_eq!(diagnostic.labels.len(), 1);
+        assert_eq!(diagnostic.labels[0].message, "unclosed delimiter");
+        assert_eq!(diagnostic.labels[0].span.line_start, 2);
+        assert_eq!(diagnostic.labels[0].span.column_start, 6);
+        let json = Message::CompilerMessage { diagnostic: Box::new(diagnostic) }.to_json();
+        assert!(json.contains(r#""labels":[{"span":"#));
+        assert!(!json.contains("suggestions"));
+    }
+
+    #[test]
+    fn test_result_messages_json() {
+        let message = Message::Test {
+            name: "app::tests::test_add".to_string(),
+            status: TestStatus::Ok,
+            output: String::new(),
+            error: None,
+        };
+        assert_eq!(
+            message.to_json(),
+            r#"{"reason":"test","name":"app::tests::test_add","status":"ok"}"#
+        );
+        assert_eq!(
+            Message::TestSummary { passed: 2, failed: 1, ignored: 0 }.to_json(),
+            r#"{"reason":"test-summary","passed":2,"failed":1,"ignored":0}"#
+        );
+        let artifact = Message::CompilerArtifact {
+            module: "dream::app::math".to_string(),
+            filename: PathBuf::from("_build/dev/lib/app/ebin/dream::app::math.beam"),
+            fresh: true,
+        };
+        assert_eq!(
+            artifact.to_json(),
+            r#"{"reason":"compiler-artifact","module":"dream::app::math","filename":"_build/dev/lib/app/ebin/dream::app::math.beam","fresh":true}"#
+        );
+        assert_eq!(
+            Message::BuildFinished { success: true }.to_json(),
+            r#"{"reason":"build-finished","success":true}"#
+        );
+    }
+}
@@ -2,7 +2,7 @@
 
 use std::collections::{HashMap, VecDeque};
 
-use crate::{Instruction, Message, Pid, Value};
+use crate::{Instruction, Message, Pid, Ref, Value};
 
 /// A frame on the call stack, enabling cross-module calls.
 #[derive(Debug, Clone)]
@@ -41,16 +41,18 @@ pub struct Process {
     pub mailbox: VecDeque<Message>,
     pub links: Vec<Pid>,
     /// Monitors this process has set up: (ref, target_pid)
-    pub monitors: Vec<(u64, Pid)>,
+    pub monitors: Vec<(Ref, Pid)>,
     /// Processes that are monitoring this one: (ref, monitoring_pid)
-    pub monitored_by: Vec<(u64, Pid)>,
+    pub monitored_by: Vec<(Ref, Pid)>,
     pub status: ProcessStatus,
     /// Exit reason when process terminates (default: :normal for Done, :crashed for Crashed)
     pub exit_reason: Value,
     /// When true, exit signals from linked processes become messages instead of killing this process
     pub trap_exit: bool,
-    /// Remaining timeout (in reductions) when waiting for a message
-    pub timeout: Option<u32>,
+    /// Scheduler time (in reductions) at which a pending receive times out
+    pub timeout: Option<u64>,
+    /// Set by the scheduler when `timeout` expires; consumed by the receive
+    pub timed_out: bool,
     /// Call stack for function calls (stores return context)
     pub call_stack: Vec<CallFrame>,
     /// Data stack for saving/restoring values (Push/Pop)
@@ -63,6 +65,59 @@ pub struct Process {
     pub current_exception: Option<(Value, Value, Vec<String>)>,
     /// Binary match state: (binary bytes, current bit position)
     pub binary_match_state: Option<(Vec<u8>, usize)>,
+    /// Total reductions this process has executed
+    pub reductions: u64,
+    /// Bound on the mailbox size; `None` means unbounded
+    pub mailbox_limit: Option<MailboxLimit>,
+    /// Senders suspended because this process's mailbox was full
+    pub suspended_senders: Vec<Pid>,
+    /// Human-readable label for observability
+    pub label: Option<String>,
+    /// Scheduling priority
+    pub priority: Priority,
+}
+
+/// Scheduling priority. Ready high-priority processes run before all
+/// others; low is currently scheduled like normal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+/// What happens to a send when the receiver's mailbox is full. Timer
+/// messages have no sender, so every policy but `DropOldest` drops them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the message being sent
+    DropNewest,
+    /// Discard the oldest queued message to make room
+    DropOldest,
+    /// Exit the sender with {:mailbox_full, Pid}
+    CrashSender,
+    /// Block the sender until the receiver takes a message. A process
+    /// sending to its own full mailbox would wait forever, so it exits as
+    /// with `CrashSender` instead
+    SuspendSender,
+}
+
+/// Maximum mailbox size and what to do when it is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MailboxLimit {
+    pub max: usize,
+    pub policy: OverflowPolicy,
 }
 
 /// Process execution status
@@ -96,12 +151,18 @@ impl Process {
             exit_reason: Value::Atom("normal".to_string()),
             trap_exit: false,
             timeout: None,
+            timed_out: false,
             call_stack: Vec::new(),
             stack: Vec::new(),
             dictionary: HashMap::new(),
             try_stack: Vec::new(),
             current_exception: None,
             binary_match_state: None,
+            reductions: 0,
+            mailbox_limit: None,
+            suspended_senders: Vec::new(),
+            label: None,
+            priority: Priority::Normal,
         }
     }
 
@@ -122,12 +183,18 @@ impl Process {
             exit_reason: Value::Atom("normal".to_string()),
             trap_exit: false,
             timeout: None,
+            timed_out: false,
             call_stack: Vec::new(),
             stack: Vec::new(),
             dictionary: HashMap::new(),
             try_stack: Vec::new(),
             current_exception: None,
             binary_match_state: None,
+            reductions: 0,
+            mailbox_limit: None,
+            suspended_senders: Vec::new(),
+            label: None,
+            priority: Priority::Normal,
         }
     }
 }
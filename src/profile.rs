@@ -0,0 +1,551 @@
+//! Profiling for `dream profile`.
+//!
+//! The program, one function or the test suite runs in a BEAM node under
+//! one of OTP's profilers, and [`harness_expr`] reports what was measured
+//! as marker lines: calls and time per function and, with fprof, the time
+//! each function spent in each of its callees. [`Profile`] collects them
+//! under Dream names for [`Profile::report`] and [`Profile::flamegraph`].
+
+use crate::erl::erlang_string;
+use crate::markup::escape;
+use crate::testing::{decode, TestCase};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Prefix of the profiler's protocol lines.
+const MARKER: &str = "##dream-profile##";
+
+/// The profiler to run under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Tool {
+    /// Calls and own time per function, counted the way `eprof` does with
+    /// `call_time` breakpoints. Cheap enough for whole programs.
+    #[default]
+    Eprof,
+    /// `fprof`: traces every call, so it is much slower, but also records
+    /// accumulated time and who called whom, which a flamegraph needs.
+    Fprof,
+}
+
+impl FromStr for Tool {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eprof" => Ok(Self::Eprof),
+            "fprof" => Ok(Self::Fprof),
+            _ => Err(format!("unknown profiler `{}` (expected `eprof` or `fprof`)", s)),
+        }
+    }
+}
+
+/// Erlang expression that runs `program`, an expression evaluating to an
+/// exit status, under `tool`, reports the measurements and halts with the
+/// status.
+///
+/// Modules in `preload` directories are loaded first, as eprof only counts
+/// calls into modules loaded when profiling starts. fprof keeps its trace
+/// and analysis files in `scratch`.
+pub fn harness_expr(tool: Tool, program: &str, preload: &[PathBuf], scratch: &Path) -> String {
+    let dirs: Vec<String> = preload.iter().map(|dir| erlang_string(&dir.to_string_lossy())).collect();
+    let prelude = format!(
+        "Hex = fun(Chars) -> binary:encode_hex(unicode:characters_to_binary(Chars)) end, \
+         Name = fun({{M, F, A}}) -> io_lib:format(\"~tw:~tw/~b\", [M, F, A]); \
+             (Other) -> io_lib:format(\"~tw\", [Other]) \
+         end, \
+         Emit = fun(Format, Args) -> io:format(\"{marker} \" ++ Format ++ \"~n\", Args) end, \
+         Program = fun() -> {program} end, \
+         [code:ensure_loaded(list_to_atom(filename:basename(File, \".beam\"))) \
+             || Dir <- [{dirs}], File <- filelib:wildcard(filename:join(Dir, \"*.beam\"))], ",
+        marker = MARKER,
+        program = program,
+        dirs = dirs.join(", "),
+    );
+    let body = match tool {
+        Tool::Eprof => "erlang:trace_pattern({'_', '_', '_'}, true, [call_time]), \
+             {Pid, Ref} = spawn_monitor(fun() -> receive go -> exit({dream_exit, Program()}) end end), \
+             erlang:trace(Pid, true, [call, set_on_spawn]), \
+             Pid ! go, \
+             Status = receive \
+                 {'DOWN', Ref, process, Pid, {dream_exit, N}} when is_integer(N) -> N; \
+                 {'DOWN', Ref, process, Pid, _} -> 1 \
+             end, \
+             erlang:trace_pattern({'_', '_', '_'}, pause, [call_time]), \
+             lists:foreach(fun({M, _}) -> \
+                 Functions = try M:module_info(functions) catch _:_ -> [] end, \
+                 lists:foreach(fun({F, A}) -> \
+                     case erlang:trace_info({M, F, A}, call_time) of \
+                         {call_time, [_ | _] = Counts} -> \
+                             {Calls, Us} = lists:foldl(fun({_, C, S, U}, {C0, U0}) -> {C0 + C, U0 + S * 1000000 + U} end, {0, 0}, Counts), \
+                             Calls > 0 andalso Emit(\"fn ~s ~b ~b -\", [Hex(Name({M, F, A})), Calls, Us]); \
+                         _ -> ok \
+                     end \
+                 end, Functions) \
+             end, code:all_loaded()), \
+             erlang:trace_pattern({'_', '_', '_'}, false, [call_time]), "
+            .to_string(),
+        Tool::Fprof => {
+            let trace = erlang_string(&scratch.join("fprof.trace").to_string_lossy());
+            let analysis = erlang_string(&scratch.join("fprof.analysis").to_string_lossy());
+            format!(
+                "Status = fprof:apply(Program, [], [{{file, {trace}}}]), \
+                 fprof:profile([{{file, {trace}}}]), \
+                 fprof:analyse([{{dest, {analysis}}}, {{totals, false}}, {{details, true}}, {{callers, true}}]), \
+                 {{ok, Terms}} = file:consult({analysis}), \
+                 lists:foreach(fun \
+                     ({{Callers, {{Function, Calls, Acc, Own}}, Callees}}) when is_list(Callers), is_list(Callees) -> \
+                         Emit(\"fn ~s ~b ~b ~b\", [Hex(Name(Function)), Calls, round(Own * 1000), round(Acc * 1000)]), \
+                         [Emit(\"call ~s ~s ~b ~b\", [Hex(Name(Function)), Hex(Name(Callee)), C, round(A * 1000)]) \
+                             || {{Callee, C, A, _}} <- Callees]; \
+                     (_) -> ok \
+                 end, Terms), ",
+            )
+        }
+    };
+    format!("{}{}Emit(\"done ~b\", [Status]), halt(Status).", prelude, body)
+}
+
+/// Erlang expression that calls a zero-argument function and evaluates to
+/// 0, or prints the exception and evaluates to 1.
+pub fn function_program(beam_module: &str, function: &str) -> String {
+    format!(
+        "begin \
+             try '{}':'{}'(), 0 \
+             catch Class:Error:Stack -> \
+                 io:format(standard_error, \"** ~p: ~p~n~p~n\", [Class, Error, Stack]), 1 \
+             end \
+         end",
+        beam_module, function
+    )
+}
+
+/// Erlang expression that runs `tests` one after another, ignoring how they
+/// turn out, and evaluates to 0.
+pub fn tests_program(tests: &[TestCase]) -> String {
+    let tests: Vec<String> = tests
+        .iter()
+        .map(|t| format!("{{'{}', '{}'}}", t.beam_module(), t.function))
+        .collect();
+    format!(
+        "begin lists:foreach(fun({{M, F}}) -> catch M:F() end, [{}]), 0 end",
+        tests.join(", ")
+    )
+}
+
+/// Measurements of one function, summed over every process.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionTime {
+    /// Dream name, e.g. `app::parser::parse/1`, or the Erlang one for
+    /// functions outside Dream modules
+    pub name: String,
+    pub calls: u64,
+    /// Time in the function itself, in microseconds
+    pub own_us: u64,
+    /// Time including its callees; fprof only
+    pub acc_us: Option<u64>,
+}
+
+/// Time one function spent calling another; fprof only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallTime {
+    pub caller: String,
+    pub callee: String,
+    pub calls: u64,
+    pub acc_us: u64,
+}
+
+/// What the profiler reported.
+#[derive(Debug, Clone, Default)]
+pub struct Profile {
+    pub functions: Vec<FunctionTime>,
+    pub calls: Vec<CallTime>,
+    /// Exit status of the profiled program, once it has finished
+    pub status: Option<i32>,
+    index: HashMap<String, usize>,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take in one line of the node's output. Returns the line if it is the
+    /// program's own output rather than a measurement.
+    pub fn feed<'l>(&mut self, line: &'l str) -> Option<&'l str> {
+        let Some(rest) = line.strip_prefix(MARKER).and_then(|rest| rest.strip_prefix(' ')) else {
+            return Some(line);
+        };
+        let fields: Vec<&str> = rest.split(' ').collect();
+        match fields.as_slice() {
+            ["fn", name, calls, own, acc] => {
+                let name = dream_name(&decode(name));
+                if is_harness(&name) {
+                    return None;
+                }
+                let (Ok(calls), Ok(own)) = (calls.parse::<u64>(), own.parse::<u64>()) else {
+                    return Some(line);
+                };
+                let acc = acc.parse::<u64>().ok();
+                let index = *self.index.entry(name.clone()).or_insert_with(|| {
+                    self.functions.push(FunctionTime { name, calls: 0, own_us: 0, acc_us: None });
+                    self.functions.len() - 1
+                });
+                let function = &mut self.functions[index];
+                function.calls += calls;
+                function.own_us += own;
+                if let Some(acc) = acc {
+                    *function.acc_us.get_or_insert(0) += acc;
+                }
+            }
+            ["call", caller, callee, calls, acc] => {
+                let caller = dream_name(&decode(caller));
+                let callee = dream_name(&decode(callee));
+                if is_harness(&caller) || is_harness(&callee) {
+                    return None;
+                }
+                let (Ok(calls), Ok(acc_us)) = (calls.parse::<u64>(), acc.parse::<u64>()) else {
+                    return Some(line);
+                };
+                match self.calls.iter_mut().find(|c| c.caller == caller && c.callee == callee) {
+                    Some(call) => {
+                        call.calls += calls;
+                        call.acc_us += acc_us;
+                    }
+                    None => self.calls.push(CallTime { caller, callee, calls, acc_us }),
+                }
+            }
+            ["done", status] => self.status = status.parse().ok(),
+            _ => return Some(line),
+        }
+        None
+    }
+
+    /// Own time of every function together.
+    pub fn total_us(&self) -> u64 {
+        self.functions.iter().map(|f| f.own_us).sum()
+    }
+
+    /// The `limit` functions with the most own time, as a table.
+    pub fn report(&self, limit: usize) -> String {
+        let mut functions: Vec<&FunctionTime> = self.functions.iter().filter(|f| f.calls > 0).collect();
+        functions.sort_by(|a, b| b.own_us.cmp(&a.own_us).then_with(|| a.name.cmp(&b.name)));
+        let total = self.total_us().max(1) as f64;
+        let with_acc = functions.iter().any(|f| f.acc_us.is_some());
+
+        let mut out = String::new();
+        if with_acc {
+            out.push_str("    Own ms      %    Acc ms      Calls   µs/call  Function\n");
+        } else {
+            out.push_str("    Own ms      %      Calls   µs/call  Function\n");
+        }
+        for f in functions.iter().take(limit) {
+            let _ = write!(out, "{:>10.3} {:>6.2}", f.own_us as f64 / 1000.0, f.own_us as f64 * 100.0 / total);
+            if with_acc {
+                let _ = write!(out, " {:>9.3}", f.acc_us.unwrap_or(0) as f64 / 1000.0);
+            }
+            let _ = writeln!(
+                out,
+                " {:>10} {:>9.2}  {}",
+                f.calls,
+                f.own_us as f64 / f.calls as f64,
+                f.name
+            );
+        }
+        if functions.len() > limit {
+            let _ = writeln!(out, "... and {} more", functions.len() - limit);
+        }
+        out
+    }
+
+    /// The call graph as a flamegraph SVG, or `None` without one (eprof
+    /// doesn't record callers).
+    ///
+    /// fprof sums time per caller and callee rather than recording whole
+    /// stacks, so each function's callees are drawn in proportion to how
+    /// its time divides among them. Recursion is cut at the first repeat.
+    pub fn flamegraph(&self) -> Option<String> {
+        if self.calls.is_empty() {
+            return None;
+        }
+        let acc: HashMap<&str, u64> = self
+            .functions
+            .iter()
+            .map(|f| (f.name.as_str(), f.acc_us.unwrap_or(f.own_us)))
+            .collect();
+        let mut callees: HashMap<&str, Vec<&CallTime>> = HashMap::new();
+        for call in &self.calls {
+            if call.caller != call.callee {
+                callees.entry(call.caller.as_str()).or_default().push(call);
+            }
+        }
+        for list in callees.values_mut() {
+            list.sort_by(|a, b| a.callee.cmp(&b.callee));
+        }
+        let called: HashSet<&str> = self.calls.iter().map(|c| c.callee.as_str()).collect();
+        let mut roots: Vec<&str> = callees
+            .keys()
+            .copied()
+            .filter(|name| !called.contains(name))
+            .collect();
+        roots.sort();
+
+        let total: u64 = roots.iter().map(|r| acc.get(r).copied().unwrap_or(0)).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut graph = Flamegraph {
+            acc: &acc,
+            callees: &callees,
+            min_us: total as f64 / 2000.0,
+            frames: Vec::new(),
+            path: Vec::new(),
+        };
+        let mut x = 0.0;
+        for root in roots {
+            let width = acc.get(root).copied().unwrap_or(0) as f64;
+            graph.descend(root, x, width, 0);
+            x += width;
+        }
+        Some(render_svg(&graph.frames, total as f64))
+    }
+}
+
+/// Frames of the harness itself, which the report leaves out.
+fn is_harness(name: &str) -> bool {
+    name.starts_with("erl_eval:")
+        || name.starts_with("fprof:")
+        || matches!(name, "suspend" | "undefined")
+}
+
+struct Flamegraph<'a> {
+    acc: &'a HashMap<&'a str, u64>,
+    callees: &'a HashMap<&'a str, Vec<&'a CallTime>>,
+    /// Frames narrower than this are left out
+    min_us: f64,
+    /// (depth, start, width, name), in microseconds
+    frames: Vec<(usize, f64, f64, String)>,
+    path: Vec<&'a str>,
+}
+
+impl<'a> Flamegraph<'a> {
+    fn descend(&mut self, name: &'a str, x: f64, width: f64, depth: usize) {
+        if width < self.min_us || depth > 64 || self.path.contains(&name) {
+            return;
+        }
+        self.frames.push((depth, x, width, name.to_string()));
+        let graph: &'a HashMap<&'a str, Vec<&'a CallTime>> = self.callees;
+        let Some(callees) = graph.get(name) else { return };
+        let own_acc = self.acc.get(name).copied().unwrap_or(0).max(1) as f64;
+        let scale = width / own_acc;
+        self.path.push(name);
+        let mut child_x = x;
+        for call in callees.iter() {
+            let child_width = (call.acc_us as f64 * scale).min(x + width - child_x);
+            self.descend(&call.callee, child_x, child_width, depth + 1);
+            child_x += child_width;
+        }
+        self.path.pop();
+    }
+}
+
+const SVG_WIDTH: f64 = 1200.0;
+const FRAME_HEIGHT: f64 = 16.0;
+
+fn render_svg(frames: &[(usize, f64, f64, String)], total_us: f64) -> String {
+    let depth = frames.iter().map(|f| f.0).max().unwrap_or(0) + 1;
+    let height = depth as f64 * FRAME_HEIGHT + 40.0;
+    let scale = (SVG_WIDTH - 20.0) / total_us;
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, "<?xml version=\"1.0\" standalone=\"no\"?>");
+    let _ = writeln!(
+        svg,
+        "<svg version=\"1.1\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" \
+         xmlns=\"http://www.w3.org/2000/svg\" font-family=\"monospace\" font-size=\"12\">",
+        w = SVG_WIDTH,
+        h = height
+    );
+    let _ = writeln!(svg, "<rect x=\"0\" y=\"0\" width=\"100%\" height=\"100%\" fill=\"#fdfdf5\"/>");
+    let _ = writeln!(
+        svg,
+        "<text x=\"{}\" y=\"20\" text-anchor=\"middle\" font-size=\"15\">Flame Graph ({:.3} ms)</text>",
+        SVG_WIDTH / 2.0,
+        total_us / 1000.0
+    );
+    for (depth, x, width, name) in frames {
+        let px = 10.0 + x * scale;
+        let pw = width * scale;
+        let y = height - (*depth as f64 + 1.0) * FRAME_HEIGHT;
+        let name = escape(name);
+        let _ = write!(
+            svg,
+            "<g><title>{} ({:.3} ms, {:.2}%)</title><rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{}\" fill=\"{}\" rx=\"2\"/>",
+            name,
+            width / 1000.0,
+            width * 100.0 / total_us,
+            px,
+            y,
+            pw,
+            FRAME_HEIGHT - 1.0,
+            frame_color(&name)
+        );
+        // About 7px per character at this font size
+        let fits = ((pw - 6.0) / 7.0).max(0.0) as usize;
+        if fits >= 3 {
+            let label: String = if name.chars().count() > fits {
+                name.chars().take(fits - 2).chain("..".chars()).collect()
+            } else {
+                name.clone()
+            };
+            let _ = write!(svg, "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>", px + 3.0, y + 11.5, label);
+        }
+        let _ = writeln!(svg, "</g>");
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A warm color picked by the name, so a function keeps its color.
+fn frame_color(name: &str) -> String {
+    let hash = name.bytes().fold(2166136261u32, |h, b| (h ^ b as u32).wrapping_mul(16777619));
+    format!(
+        "rgb({},{},{})",
+        205 + hash % 50,
+        (hash >> 8) % 180 + 40,
+        (hash >> 16) % 55
+    )
+}
+
+/// The Dream name of a function fprof or eprof reports in Erlang form:
+/// `'dream::app::parser':parse/1` is `app::parser::parse/1`, and a closure,
+/// `'dream::app':'-main/0-fun-0-'/1`, is `app::main::{closure}/1`. Names
+/// outside Dream modules come back unquoted, e.g. `lists:foldl/3`.
+pub fn dream_name(erlang: &str) -> String {
+    let Some((module, rest)) = split_atom(erlang).and_then(|(module, rest)| Some((module, rest.strip_prefix(':')?)))
+    else {
+        return erlang.to_string();
+    };
+    let Some((function, arity)) = split_atom(rest).and_then(|(f, rest)| Some((f, rest.strip_prefix('/')?))) else {
+        return erlang.to_string();
+    };
+
+    let function = match function.strip_prefix('-').and_then(|f| f.split_once("-fun-")) {
+        Some((parent, _)) => {
+            let parent = parent.rsplit_once('/').map_or(parent, |(name, _)| name);
+            format!("{}::{{closure}}", parent)
+        }
+        None => function.to_string(),
+    };
+    match module.strip_prefix("dream::") {
+        Some(module) => format!("{}::{}/{}", module, function, arity),
+        None => format!("{}:{}/{}", module, function, arity),
+    }
+}
+
+/// An atom at the start of `s`, quoted or not, and what follows it.
+fn split_atom(s: &str) -> Option<(&str, &str)> {
+    match s.strip_prefix('\'') {
+        Some(quoted) => {
+            let end = quoted.find('\'')?;
+            Some((&quoted[..end], &quoted[end + 1..]))
+        }
+        None => {
+            let end = s.find([':', '/']).unwrap_or(s.len());
+            Some((&s[..end], &s[end..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::encode;
+
+    #[test]
+    fn test_dream_name() {
+        assert_eq!(dream_name("'dream::app::parser':parse/1"), "app::parser::parse/1");
+        assert_eq!(dream_name("'dream::app':'-main/0-fun-0-'/1"), "app::main::{closure}/1");
+        assert_eq!(dream_name("'dream::list':map/2"), "list::map/2");
+        assert_eq!(dream_name("lists:foldl/3"), "lists:foldl/3");
+        assert_eq!(dream_name("erlang:'++'/2"), "erlang:++/2");
+        assert_eq!(dream_name("garbage_collect"), "garbage_collect");
+    }
+
+    #[test]
+    fn test_feed_sums_processes() {
+        let mut profile = Profile::new();
+        let main = encode("'dream::app':main/0");
+        let fib = encode("'dream::app':fib/1");
+        assert_eq!(profile.feed(&format!("##dream-profile## fn {} 1 100 5000", main)), None);
+        assert_eq!(profile.feed(&format!("##dream-profile## fn {} 10 2000 4900", fib)), None);
+        assert_eq!(profile.feed(&format!("##dream-profile## fn {} 5 900 2000", fib)), None);
+        assert_eq!(profile.feed(&format!("##dream-profile## fn {} 3 50 60", encode("erl_eval:expr/5"))), None);
+        assert_eq!(profile.feed(&format!("##dream-profile## call {} {} 1 4900", main, fib)), None);
+        assert_eq!(profile.feed("hello"), Some("hello"));
+        assert_eq!(profile.feed("##dream-profile## done 0"), None);
+
+        assert_eq!(profile.status, Some(0));
+        assert_eq!(profile.functions.len(), 2);
+        assert_eq!(
+            profile.functions[1],
+            FunctionTime { name: "app::fib/1".to_string(), calls: 15, own_us: 2900, acc_us: Some(6900) }
+        );
+        assert_eq!(profile.calls.len(), 1);
+        assert_eq!(profile.total_us(), 3000);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut profile = Profile::new();
+        profile.feed(&format!("##dream-profile## fn {} 1 100 -", encode("'dream::app':main/0")));
+        profile.feed(&format!("##dream-profile## fn {} 4 300 -", encode("lists:map/2")));
+        profile.feed(&format!("##dream-profile## fn {} 2 200 -", encode("'dream::app':step/1")));
+        let report = profile.report(2);
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(!lines[0].contains("Acc"));
+        assert!(lines[1].ends_with("lists:map/2"));
+        assert!(lines[1].contains("50.00"));
+        assert!(lines[2].ends_with("app::step/1"));
+        assert_eq!(lines[3], "... and 1 more");
+    }
+
+    #[test]
+    fn test_flamegraph() {
+        let mut profile = Profile::new();
+        profile.feed(&format!("##dream-profile## fn {} 1 10 100", encode("'dream::app':main/0")));
+        profile.feed(&format!("##dream-profile## fn {} 1 60 60", encode("'dream::app':parse/1")));
+        profile.feed(&format!("##dream-profile## fn {} 1 30 30", encode("'dream::app':eval/1")));
+        assert_eq!(profile.flamegraph(), None);
+
+        let main = encode("'dream::app':main/0");
+        profile.feed(&format!("##dream-profile## call {} {} 1 60", main, encode("'dream::app':parse/1")));
+        profile.feed(&format!("##dream-profile## call {} {} 1 30", main, encode("'dream::app':eval/1")));
+        let svg = profile.flamegraph().unwrap();
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("<title>app::main/0 (0.100 ms, 100.00%)</title>"));
+        assert!(svg.contains("<title>app::parse/1 (0.060 ms, 60.00%)</title>"));
+        assert!(svg.contains("<title>app::eval/1 (0.030 ms, 30.00%)</title>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_harness_expr() {
+        let program = function_program("dream::app", "run");
+        let eprof = harness_expr(Tool::Eprof, &program, &[PathBuf::from("/b")], Path::new("/tmp"));
+        assert!(eprof.contains("Program = fun() -> begin try 'dream::app':'run'(), 0"));
+        assert!(eprof.contains("|| Dir <- [\"/b\"]"));
+        assert!(eprof.contains("[call_time]"));
+        assert!(eprof.ends_with("Emit(\"done ~b\", [Status]), halt(Status)."));
+
+        let fprof = harness_expr(Tool::Fprof, &program, &[], Path::new("/tmp"));
+        assert!(fprof.contains("fprof:apply(Program, [], [{file, \"/tmp/fprof.trace\"}])"));
+        assert!(fprof.contains("file:consult(\"/tmp/fprof.analysis\")"));
+
+        let tests = tests_program(&[TestCase::new("app::tests", "test_add")]);
+        assert_eq!(tests, "begin lists:foreach(fun({M, F}) -> catch M:F() end, [{'dream::app::tests', 'test_add'}]), 0 end");
+        assert_eq!("fprof".parse::<Tool>(), Ok(Tool::Fprof));
+        assert!("perf".parse::<Tool>().is_err());
+    }
+}
@@ -0,0 +1,405 @@
+//! Automated refactorings for `dream refactor` and the language server,
+//! built on [`Analysis`].
+//!
+//! A rename finds every reference to a function, type or variable and
+//! replaces the name at each, in its declaration, call sites and `use`
+//! declarations alike. Uses through an `as` alias keep the alias, and a
+//! struct field written as a shorthand for a renamed variable gets its
+//! value spelled out. It refuses a new name that is not an identifier of
+//! the same kind, or that already means something else where the old one
+//! is used.
+
+use crate::analysis::{Analysis, Location, Symbol, find_word};
+use crate::compiler::{Item, Token};
+use logos::Logos;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+/// A replacement of a span of a module's source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub location: Location,
+    pub text: String,
+}
+
+/// What renaming a symbol changes.
+#[derive(Debug, Default)]
+pub struct Rename {
+    /// Edits by module, each module's in source order
+    pub edits: BTreeMap<String, Vec<Edit>>,
+    /// What the rename cannot update, such as callers outside the project
+    pub notes: Vec<String>,
+}
+
+/// Rename `symbol` to `new_name` everywhere the project refers to it.
+pub fn rename(analysis: &Analysis, symbol: &Symbol, new_name: &str) -> Result<Rename, String> {
+    let old_name = match symbol {
+        Symbol::Module(name) => {
+            return Err(format!("`{}` is a module, and renaming modules is not supported", name));
+        }
+        Symbol::Item { name, .. } | Symbol::Local { name, .. } => name,
+    };
+    if let Symbol::Item { module, .. } = symbol {
+        if !analysis.modules().any(|m| m.name == *module) {
+            return Err(format!("`{}` is declared in `{}`, outside the project", old_name, module));
+        }
+    }
+    let mut rename = Rename::default();
+    if new_name == old_name {
+        return Ok(rename);
+    }
+    check_name(old_name, new_name)?;
+    check_conflicts(analysis, symbol, new_name)?;
+
+    for location in analysis.references(symbol) {
+        let source = analysis
+            .module(&location.module)
+            .and_then(|m| m.source.as_deref())
+            .unwrap_or_default();
+        // Uses through an alias keep it
+        if source.get(location.span.clone()) != Some(old_name.as_str()) {
+            continue;
+        }
+        let text = if analysis.is_field_shorthand(&location) {
+            format!("{}: {}", old_name, new_name)
+        } else {
+            new_name.to_string()
+        };
+        rename
+            .edits
+            .entry(location.module.clone())
+            .or_default()
+            .push(Edit { location, text });
+    }
+
+    if let Symbol::Item { module, name } = symbol {
+        if is_pub(analysis, module, name) {
+            rename.notes.push(format!(
+                "`{}::{}` is public: code outside the project that uses it, such as other packages and Erlang modules, is not updated",
+                module, name
+            ));
+        }
+    }
+    Ok(rename)
+}
+
+/// A new name must be a single identifier cased like the old one:
+/// capitalized for types, lowercase for functions and variables.
+fn check_name(old_name: &str, new_name: &str) -> Result<(), String> {
+    let tokens: Vec<_> = Token::lexer(new_name).collect();
+    let is_type = old_name.starts_with(char::is_uppercase);
+    match tokens.as_slice() {
+        [Ok(Token::Ident(_))] if !is_type => Ok(()),
+        [Ok(Token::TypeIdent(_))] if is_type => Ok(()),
+        [Ok(Token::Ident(_))] => Err(format!(
+            "`{}` names a type, so `{}` must start with a capital letter",
+            old_name, new_name
+        )),
+        [Ok(Token::TypeIdent(_))] => Err(format!(
+            "`{}` must start with a lowercase letter, like `{}`",
+            new_name, old_name
+        )),
+        _ => Err(format!("`{}` is not a valid name", new_name)),
+    }
+}
+
+/// Make sure the new name doesn't already mean something where the old
+/// one is used, which the rename would change the meaning of.
+fn check_conflicts(analysis: &Analysis, symbol: &Symbol, new_name: &str) -> Result<(), String> {
+    match symbol {
+        Symbol::Local {
+            module,
+            function,
+            name,
+        } => {
+            let text = analysis
+                .module(module)
+                .and_then(|m| m.source.as_deref())
+                .and_then(|source| source.get(function.clone()))
+                .unwrap_or_default();
+            let used = Token::lexer(text).any(|token| match token {
+                Ok(Token::Ident(ident)) => ident == new_name,
+                Ok(Token::String(raw)) => find_word(&raw, 0..raw.len(), new_name).is_some(),
+                _ => false,
+            });
+            if used {
+                return Err(format!(
+                    "`{}` is already used in the function that binds `{}`",
+                    new_name, name
+                ));
+            }
+        }
+        Symbol::Item { module, .. } => {
+            let mut modules: BTreeSet<String> = analysis
+                .references(symbol)
+                .into_iter()
+                .map(|location| location.module)
+                .collect();
+            modules.insert(module.clone());
+            for module in &modules {
+                let existing = analysis.resolve(module, new_name);
+                if let Some(existing) = existing.filter(|existing| existing != symbol) {
+                    let existing = match existing {
+                        Symbol::Module(name) => format!("module `{}`", name),
+                        Symbol::Item { module, name } => format!("`{}::{}`", module, name),
+                        Symbol::Local { name, .. } => format!("`{}`", name),
+                    };
+                    return Err(format!(
+                        "`{}` already refers to {} in `{}`",
+                        new_name, existing, module
+                    ));
+                }
+            }
+        }
+        Symbol::Module(_) => {}
+    }
+    Ok(())
+}
+
+fn is_pub(analysis: &Analysis, module: &str, name: &str) -> bool {
+    let Some(module) = analysis.module(module) else {
+        return false;
+    };
+    module.items.iter().any(|item| match item {
+        Item::Function(f) => f.name == name && f.is_pub,
+        Item::Struct(s) => s.name == name && s.is_pub,
+        Item::Enum(e) => e.name == name && e.is_pub,
+        Item::TypeAlias(t) => t.name == name && t.is_pub,
+        _ => false,
+    })
+}
+
+/// The byte offset of a 1-based `line` and `column`, counted in
+/// characters, in `source`.
+pub fn offset_at(source: &str, line: usize, column: usize) -> Option<usize> {
+    let start = match line {
+        0 => return None,
+        1 => 0,
+        _ => source.match_indices('\n').nth(line - 2)?.0 + 1,
+    };
+    let text = source[start..].split('\n').next().unwrap_or_default();
+    let column = column.checked_sub(1)?;
+    let offset = match text.char_indices().nth(column) {
+        Some((offset, _)) => offset,
+        // Just past the end of the line
+        None if column == text.chars().count() => text.len(),
+        None => return None,
+    };
+    Some(start + offset)
+}
+
+/// `source` with `edits`, in source order and not overlapping, applied.
+pub fn apply(source: &str, edits: &[Edit]) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut at = 0;
+    for edit in edits {
+        out.push_str(&source[at..edit.location.span.start]);
+        out.push_str(&edit.text);
+        at = edit.location.span.end;
+    }
+    out.push_str(&source[at..]);
+    out
+}
+
+/// A unified diff of applying `edits`, in source order and not
+/// overlapping, to `source`, the file at `path`. Each run of lines the
+/// edits change is a hunk of its own, without context.
+pub fn diff(path: &str, source: &str, edits: &[Edit]) -> String {
+    let line_start = |at: usize| source[..at].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = |at: usize| source[at..].find('\n').map_or(source.len(), |i| at + i + 1);
+
+    // The whole lines each edit changes, merged where they touch
+    let mut hunks: Vec<(std::ops::Range<usize>, Vec<&Edit>)> = Vec::new();
+    for edit in edits {
+        let span = &edit.location.span;
+        let start = line_start(span.start);
+        let before = &source[start..span.start];
+        // Stop where the edit does if the replaced lines end there
+        let whole = (before.is_empty() && edit.text.is_empty()) || format!("{}{}", before, edit.text).ends_with('\n');
+        let end = if line_start(span.end) == span.end && whole {
+            span.end
+        } else {
+            line_end(span.end)
+        };
+        match hunks.last_mut() {
+            Some((lines, edits)) if start <= lines.end => {
+                lines.end = lines.end.max(end);
+                edits.push(edit);
+            }
+            _ => hunks.push((start..end, vec![edit])),
+        }
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    // How many lines the hunks so far added, less those they removed
+    let mut shift = 0isize;
+    for (lines, edits) in hunks {
+        let mut edited = String::new();
+        let mut at = lines.start;
+        for edit in edits {
+            edited.push_str(&source[at..edit.location.span.start]);
+            edited.push_str(&edit.text);
+            at = edit.location.span.end;
+        }
+        edited.push_str(&source[at..lines.end]);
+
+        let old: Vec<&str> = source[lines.clone()].split_inclusive('\n').collect();
+        let new: Vec<&str> = edited.split_inclusive('\n').collect();
+        let first = source[..lines.start].matches('\n').count() + 1;
+        let new_first = first as isize + shift;
+        shift += new.len() as isize - old.len() as isize;
+        // An empty side starts at the line before, as in `diff -u`
+        let old_start = if old.is_empty() { first - 1 } else { first };
+        let new_start = if new.is_empty() { new_first - 1 } else { new_first };
+        let _ = writeln!(out, "@@ -{},{} +{},{} @@", old_start, old.len(), new_start, new.len());
+        for text in old {
+            let _ = writeln!(out, "-{}", text.strip_suffix('\n').unwrap_or(text));
+        }
+        for text in new {
+            let _ = writeln!(out, "+{}", text.strip_suffix('\n').unwrap_or(text));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Parser;
+
+    const SHAPES: &str = "use app::util::{double, triple as thrice};\n\npub struct Point {\n    x: int,\n}\n\nfn make(x: int) -> Point {\n    let p = Point { x };\n    p\n}\n\nfn grow(n: int) -> int {\n    thrice(double(n)) + util::double(\"{n}\")\n}\n";
+    const UTIL: &str = "pub fn double(n: int) -> int {\n    n * 2\n}\n\npub fn triple(n: int) -> int {\n    n * 3\n}\n";
+
+    fn analysis() -> Analysis {
+        let mut analysis = Analysis::new(Vec::new());
+        analysis.set_package(Some("app".to_string()));
+        analysis.insert(Parser::new(SHAPES).parse_file("app::shapes").unwrap());
+        analysis.insert(Parser::new(UTIL).parse_file("app::util").unwrap());
+        analysis
+    }
+
+    fn renamed(analysis: &Analysis, rename: &Rename, module: &str) -> String {
+        let source = analysis.module(module).unwrap().source.as_deref().unwrap();
+        apply(source, rename.edits.get(module).map_or(&[][..], Vec::as_slice))
+    }
+
+    #[test]
+    fn test_rename_function() {
+        let analysis = analysis();
+        let double = Symbol::Item {
+            module: "app::util".to_string(),
+            name: "double".to_string(),
+        };
+        let rename = super::rename(&analysis, &double, "twice").unwrap();
+        assert!(renamed(&analysis, &rename, "app::shapes").starts_with("use app::util::{twice, triple as thrice};"));
+        assert!(renamed(&analysis, &rename, "app::shapes").contains("thrice(twice(n)) + util::twice(\"{n}\")"));
+        assert!(renamed(&analysis, &rename, "app::util").starts_with("pub fn twice(n: int)"));
+        assert_eq!(rename.notes.len(), 1);
+
+        // Aliased uses keep the alias
+        let triple = Symbol::Item {
+            module: "app::util".to_string(),
+            name: "triple".to_string(),
+        };
+        let rename = super::rename(&analysis, &triple, "treble").unwrap();
+        assert!(renamed(&analysis, &rename, "app::shapes").contains("{double, treble as thrice}"));
+        assert!(renamed(&analysis, &rename, "app::shapes").contains("thrice(double(n))"));
+    }
+
+    #[test]
+    fn test_rename_variable() {
+        let analysis = analysis();
+        let x = analysis
+            .symbol_at("app::shapes", SHAPES.find("x }").unwrap())
+            .unwrap();
+        let rename = super::rename(&analysis, &x, "width").unwrap();
+        let shapes = renamed(&analysis, &rename, "app::shapes");
+        assert!(shapes.contains("fn make(width: int) -> Point {\n    let p = Point { x: width };"));
+        // The field declaration is left alone
+        assert!(shapes.contains("    x: int,\n"));
+
+        let n = analysis
+            .symbol_at("app::shapes", SHAPES.find("n: int").unwrap())
+            .unwrap();
+        let rename = super::rename(&analysis, &n, "count").unwrap();
+        assert!(renamed(&analysis, &rename, "app::shapes").contains("thrice(double(count)) + util::double(\"{count}\")"));
+    }
+
+    #[test]
+    fn test_rename_refusals() {
+        let analysis = analysis();
+        let point = Symbol::Item {
+            module: "app::shapes".to_string(),
+            name: "Point".to_string(),
+        };
+        assert!(super::rename(&analysis, &point, "point").is_err());
+        assert!(super::rename(&analysis, &point, "Spot").is_ok());
+
+        let double = Symbol::Item {
+            module: "app::util".to_string(),
+            name: "double".to_string(),
+        };
+        assert!(super::rename(&analysis, &double, "Twice").is_err());
+        assert!(super::rename(&analysis, &double, "fn").is_err());
+        assert!(super::rename(&analysis, &double, "two words").is_err());
+        // Already a function of the module
+        let error = super::rename(&analysis, &double, "triple").unwrap_err();
+        assert_eq!(error, "`triple` already refers to `app::util::triple` in `app::util`");
+        // Already a name in the function
+        let p = analysis
+            .symbol_at("app::shapes", SHAPES.find("p =").unwrap())
+            .unwrap();
+        assert!(super::rename(&analysis, &p, "x").is_err());
+        assert!(super::rename(&analysis, &Symbol::Module("app::util".to_string()), "utils").is_err());
+    }
+
+    #[test]
+    fn test_offset_at() {
+        let source = "let é = 1;\nfoo()";
+        assert_eq!(offset_at(source, 1, 1), Some(0));
+        assert_eq!(offset_at(source, 1, 7), Some(7));
+        assert_eq!(offset_at(source, 2, 1), Some(12));
+        assert_eq!(offset_at(source, 2, 6), Some(17));
+        assert_eq!(offset_at(source, 2, 7), None);
+        assert_eq!(offset_at(source, 3, 1), None);
+        assert_eq!(offset_at(source, 0, 1), None);
+    }
+
+    #[test]
+    fn test_diff() {
+        let source = "fn a() -> int {\n    b(1)\n}\n\nfn c() -> int { b(2) }\n";
+        let edit = |start: usize, text: &str| Edit {
+            location: Location {
+                module: "app".to_string(),
+                span: start..start + 1,
+            },
+            text: text.to_string(),
+        };
+        let b = source.match_indices("b(").map(|(i, _)| i).collect::<Vec<_>>();
+        let edits = [edit(b[0], "bee"), edit(b[1], "bee")];
+        assert_eq!(
+            diff("src/app.dream", source, &edits),
+            "--- a/src/app.dream\n+++ b/src/app.dream\n\
+             @@ -2,1 +2,1 @@\n-    b(1)\n+    bee(1)\n\
+             @@ -5,1 +5,1 @@\n-fn c() -> int { b(2) }\n+fn c() -> int { bee(2) }\n"
+        );
+
+        let line = source.find("    b(1)").unwrap();
+        let edits = [
+            Edit {
+                location: Location {
+                    module: "app".to_string(),
+                    span: line..line + "    b(1)\n".len(),
+                },
+                text: String::new(),
+            },
+            edit(b[1], "bee"),
+        ];
+        assert_eq!(
+            diff("src/app.dream", source, &edits),
+            "--- a/src/app.dream\n+++ b/src/app.dream\n\
+             @@ -2,1 +1,0 @@\n-    b(1)\n\
+             @@ -5,1 +4,1 @@\n-fn c() -> int { b(2) }\n+fn c() -> int { bee(2) }\n"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+//! Unique reference type.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Creation counter handing each VM instance a distinct `creation`, so
+/// refs from two schedulers in the same OS process never compare equal.
+static NEXT_CREATION: AtomicU32 = AtomicU32::new(1);
+
+/// Unique reference, created by `make_ref()`, monitors and timers.
+///
+/// A ref is unique within its node: `creation` identifies the VM instance
+/// that made it and `id` is that instance's counter. Refs are ordered by
+/// creation, then id, and print like Erlang refs: `#Ref<0.1.42>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ref {
+    pub creation: u32,
+    pub id: u64,
+}
+
+impl Ref {
+    pub fn new(creation: u32, id: u64) -> Self {
+        Self { creation, id }
+    }
+
+    /// Allocate a creation number for a new VM instance.
+    pub(crate) fn next_creation() -> u32 {
+        NEXT_CREATION.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl std::fmt::Display for Ref {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#Ref<0.{}.{}>", self.creation, self.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ref_display() {
+        assert_eq!(Ref::new(3, 42).to_string(), "#Ref<0.3.42>");
+    }
+
+    #[test]
+    fn test_ref_ordering() {
+        assert!(Ref::new(1, 5) < Ref::new(1, 6));
+        assert!(Ref::new(1, 99) < Ref::new(2, 0));
+        assert_eq!(Ref::new(2, 7), Ref::new(2, 7));
+    }
+
+    #[test]
+    fn test_next_creation_is_unique() {
+        let a = Ref::next_creation();
+        let b = Ref::next_creation();
+        assert_ne!(a, b);
+    }
+}
@@ -7,11 +7,13 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitCode, Stdio};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::{self, Receiver};
 use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use rustyline::completion::{Completer, Pair};
 use rustyline::error::ReadlineError;
@@ -21,9 +23,13 @@ use rustyline::validate::Validator;
 use rustyline::{Context, Editor, ExternalPrinter, Helper};
 
 use dream::compiler::{
-    check_modules, resolve_stdlib_methods, CompilerError, CoreErlangEmitter,
+    check_modules, configure_module, ice, resolve_stdlib_methods, CompilerError, CoreErlangEmitter,
     GenericFunctionRegistry, Item, ModuleContext, Parser,
 };
+use dream::code_path::CodePath;
+use dream::config::CompileOptions;
+use dream::target::TargetLayout;
+use dream::toolchain;
 use miette::{NamedSource, SourceSpan};
 use std::sync::{Arc, RwLock};
 
@@ -99,13 +105,19 @@ const RESULT_MARKER: &str = "\x00DREAM_RESULT\x00";
 
 /// Erlang eval server with introspection support
 /// Protocol:
-///   eval:<filename> - evaluate a Core Erlang file (temp module, purged after)
-///   bind:<name>:<filename> - evaluate and store result in process dictionary
-///   load:<filename> - load a Core Erlang file persistently
+///   eval:<filename> - evaluate a Core Erlang file (temp module, purged after);
+///     its `__eval__` returns the value and the value as `{:?}` shows it, and
+///     the text is sent hex-encoded so that it arrives as one line
+///   bind:<name>:<filename> - evaluate as eval: does and store the value in
+///     the process dictionary
+///   load:<filename> - load a Core Erlang file persistently; the previous
+///     version is soft-purged first, so loading fails with old_code_in_use
+///     while a process still runs the old code
 ///   call:<module>:<function> - call a function in a loaded module
 ///   exports:<module> - get exports for a loaded module (in-memory)
 ///   introspect:list_modules - list all dream:: modules
 ///   introspect:exports:<module> - get exports for a module (from .beam file)
+///   introspect:processes - print a table of live processes
 const EVAL_SERVER: &str = r#"
 Loop = fun Loop() ->
     case io:get_line("") of
@@ -125,6 +137,23 @@ Loop = fun Loop() ->
                     || Dir <- Paths])),
                     io:format("~s~nok:~w~n", [<<0, "DREAM_RESULT", 0>>, Mods]),
                     Loop();
+                "introspect:processes" ->
+                    io:format("~-14s ~-24s ~-8s ~6s ~12s~n", ["PID", "LABEL", "PRIORITY", "MSGS", "REDUCTIONS"]),
+                    lists:foreach(fun(P) ->
+                        case erlang:process_info(P, [registered_name, dictionary, priority, message_queue_len, reductions]) of
+                            [{registered_name, Reg}, {dictionary, Dict}, {priority, Prio}, {message_queue_len, Len}, {reductions, Reds}] ->
+                                Label = case lists:keyfind('$process_label', 1, Dict) of
+                                    {_, L} when is_binary(L); is_list(L) -> L;
+                                    {_, L} -> io_lib:format("~p", [L]);
+                                    false when Reg =:= [] -> "";
+                                    false -> atom_to_list(Reg)
+                                end,
+                                io:format("~-14s ~-24ts ~-8s ~6B ~12B~n", [pid_to_list(P), Label, Prio, Len, Reds]);
+                            _ -> ok
+                        end
+                    end, erlang:processes()),
+                    io:format("~s~nok:ok~n", [<<0, "DREAM_RESULT", 0>>]),
+                    Loop();
                 _ ->
                     case string:prefix(Cmd, "exports:") of
                         nomatch ->
@@ -148,10 +177,10 @@ Loop = fun Loop() ->
                                                                                 code:purge(ModName),
                                                                                 case code:load_binary(ModName, Filename, Binary) of
                                                                                     {module, ModName} ->
-                                                                                        Val = ModName:'__eval__'(),
+                                                                                        {_Val, Shown} = ModName:'__eval__'(),
                                                                                         code:purge(ModName),
                                                                                         code:delete(ModName),
-                                                                                        {ok, Val};
+                                                                                        {ok, Shown};
                                                                                     {error, What} ->
                                                                                         {error, {load_failed, What}}
                                                                                 end;
@@ -164,7 +193,7 @@ Loop = fun Loop() ->
                                                                     end,
                                                                     io:format("~s~n", [<<0, "DREAM_RESULT", 0>>]),
                                                                     case Result of
-                                                                        {ok, Value} -> io:format("ok:~p~n", [Value]);
+                                                                        {ok, Text} -> io:format("ok:~s~n", [binary:encode_hex(Text)]);
                                                                         {error, Err} -> io:format("err:~p~n", [Err])
                                                                     end,
                                                                     Loop()
@@ -179,11 +208,11 @@ Loop = fun Loop() ->
                                                                         code:purge(ModName),
                                                                         case code:load_binary(ModName, Filename, Binary) of
                                                                             {module, ModName} ->
-                                                                                Val = ModName:'__eval__'(),
+                                                                                {Val, Shown} = ModName:'__eval__'(),
                                                                                 code:purge(ModName),
                                                                                 code:delete(ModName),
                                                                                 erlang:put(BindKey, Val),
-                                                                                {ok, Val};
+                                                                                {ok, Shown};
                                                                             {error, What} ->
                                                                                 {error, {load_failed, What}}
                                                                         end;
@@ -196,7 +225,7 @@ Loop = fun Loop() ->
                                                             end,
                                                             io:format("~s~n", [<<0, "DREAM_RESULT", 0>>]),
                                                             case Result of
-                                                                {ok, Value} -> io:format("ok:~p~n", [Value]);
+                                                                {ok, Text} -> io:format("ok:~s~n", [binary:encode_hex(Text)]);
                                                                 {error, Err} -> io:format("err:~p~n", [Err])
                                                             end,
                                                             Loop()
@@ -223,12 +252,16 @@ Loop = fun Loop() ->
                                             Result = try
                                                 case compile:file(Filename, [from_core, binary, return_errors]) of
                                                     {ok, CompiledMod, Binary} ->
-                                                        code:purge(CompiledMod),
-                                                        case code:load_binary(CompiledMod, Filename, Binary) of
-                                                            {module, CompiledMod} ->
-                                                                {ok, CompiledMod};
-                                                            {error, What} ->
-                                                                {error, {load_failed, What}}
+                                                        case code:soft_purge(CompiledMod) of
+                                                            true ->
+                                                                case code:load_binary(CompiledMod, Filename, Binary) of
+                                                                    {module, CompiledMod} ->
+                                                                        {ok, CompiledMod};
+                                                                    {error, What} ->
+                                                                        {error, {load_failed, What}}
+                                                                end;
+                                                            false ->
+                                                                {error, {old_code_in_use, CompiledMod}}
                                                         end;
                                                     {error, Errors, _Warnings} ->
                                                         {error, {compile_failed, Errors}}
@@ -303,7 +336,7 @@ const KEYWORDS: &[&str] = &[
 ];
 
 /// REPL commands for completion
-const COMMANDS: &[&str] = &[":help", ":quit", ":q", ":clear", ":bindings", ":b", ":h", ":reload", ":edit", ":e", ":load"];
+const COMMANDS: &[&str] = &[":help", ":quit", ":q", ":clear", ":bindings", ":b", ":h", ":reload", ":edit", ":e", ":load", ":processes", ":ps"];
 
 /// Information about a module's exports
 #[derive(Clone, Debug, Default)]
@@ -528,6 +561,48 @@ impl ExternalPrinter for StdoutPrinter {
     }
 }
 
+/// The directory a session writes its Core Erlang and `:edit` buffer to.
+/// Each session makes its own, named after it, so that shells running at
+/// the same time don't write over each other's files, and removes it with
+/// everything in it when dropped, which unwinding from a panic does too.
+struct Workdir {
+    path: PathBuf,
+}
+
+impl Workdir {
+    /// Make a directory for this session in `base`, or in the system temp
+    /// dir.
+    fn create(base: Option<&Path>) -> std::io::Result<Self> {
+        let base = base.map(Path::to_path_buf).unwrap_or_else(std::env::temp_dir);
+        std::fs::create_dir_all(&base)?;
+        let path = base.join(format!("dream-repl-{}", session_id()));
+        std::fs::create_dir(&path)?;
+        Ok(Self { path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Workdir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// An id for a session: the process id, when the session started, and how
+/// many sessions this process started before it.
+fn session_id() -> String {
+    static SESSIONS: AtomicU64 = AtomicU64::new(0);
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis())
+        .unwrap_or_default();
+    let session = SESSIONS.fetch_add(1, Ordering::Relaxed);
+    format!("{}-{:x}-{}", std::process::id(), started, session)
+}
+
 /// REPL state
 struct ReplState {
     bindings: SharedBindings,
@@ -536,20 +611,24 @@ struct ReplState {
     beam_stdin: Option<std::process::ChildStdin>,
     /// Channel receiver for command results only
     result_rx: Option<Receiver<BeamResult>>,
-    stdlib_path: Option<String>,
-    temp_dir: std::path::PathBuf,
+    workdir: Workdir,
+    /// The session's own modules, the stdlib, then the code paths it was
+    /// started with (project beam files, deps, etc.)
+    code_path: CodePath,
     /// Last edited content from :edit command (for iterating)
     last_edit: Option<String>,
-    /// Extra code paths (for deps, project beam files, etc.)
-    extra_paths: Vec<std::path::PathBuf>,
     /// Application to start (if running with -S)
     app_name: Option<String>,
 }
 
 impl ReplState {
-    fn new() -> Self {
-        let stdlib_path = find_stdlib_path();
-        let temp_dir = std::env::temp_dir();
+    fn new(workdir: Workdir, code_paths: Vec<PathBuf>) -> Self {
+        let mut code_path = CodePath::new();
+        code_path.push(workdir.path());
+        if let Some(stdlib) = find_stdlib_path() {
+            code_path.push(stdlib);
+        }
+        code_path.extend(code_paths).push_user_paths();
 
         Self {
             bindings: Rc::new(RefCell::new(Vec::new())),
@@ -557,33 +636,25 @@ impl ReplState {
             beam_process: None,
             beam_stdin: None,
             result_rx: None,
-            stdlib_path,
-            temp_dir,
+            workdir,
+            code_path,
             last_edit: None,
-            extra_paths: Vec::new(),
             app_name: None,
         }
     }
 
-    fn with_app(app_name: String, beam_dir: std::path::PathBuf, deps_dirs: Vec<std::path::PathBuf>) -> Self {
-        let stdlib_path = find_stdlib_path();
-        let temp_dir = std::env::temp_dir();
-
-        let mut extra_paths = vec![beam_dir];
-        extra_paths.extend(deps_dirs);
+    fn with_app(
+        app_name: String,
+        beam_dir: std::path::PathBuf,
+        deps_dirs: Vec<std::path::PathBuf>,
+        workdir: Workdir,
+    ) -> Self {
+        let mut code_paths = vec![beam_dir];
+        code_paths.extend(deps_dirs);
 
-        Self {
-            bindings: Rc::new(RefCell::new(Vec::new())),
-            registry: Rc::new(RefCell::new(ModuleRegistry::new())),
-            beam_process: None,
-            beam_stdin: None,
-            result_rx: None,
-            stdlib_path,
-            temp_dir,
-            last_edit: None,
-            extra_paths,
-            app_name: Some(app_name),
-        }
+        let mut state = Self::new(workdir, code_paths);
+        state.app_name = Some(app_name);
+        state
     }
 
     /// Start the BEAM process if not already running
@@ -613,18 +684,9 @@ impl ReplState {
             format!("{}.", eval_code)
         };
 
-        let mut cmd = Command::new("erl");
+        let mut cmd = toolchain::erl();
         cmd.arg("-noshell");
-        cmd.arg("-pa").arg(&self.temp_dir);
-
-        if let Some(ref stdlib) = self.stdlib_path {
-            cmd.arg("-pa").arg(stdlib);
-        }
-
-        // Add extra paths (project beam files, deps, etc.)
-        for path in &self.extra_paths {
-            cmd.arg("-pa").arg(path);
-        }
+        self.code_path.apply(&mut cmd);
 
         cmd.arg("-eval").arg(&eval_code);
         cmd.stdin(Stdio::piped())
@@ -732,9 +794,21 @@ impl ReplState {
 
         // Parse
         let mut parser = Parser::new(&dream_source);
-        let modules = parser.parse_file_modules(&module_name).map_err(|e| {
+        let mut modules = parser.parse_file_modules(&module_name).map_err(|e| {
             format_repl_error(expr_source, expr_offset, &e.message, e.span, e.help.as_deref())
         })?;
+        for module in &mut modules {
+            let configured = configure_module(module, &CompileOptions::default());
+            if let Some(e) = configured.errors.into_iter().next() {
+                return Err(format_repl_error(
+                    expr_source,
+                    expr_offset,
+                    &e.message,
+                    e.span.unwrap_or_else(|| SourceSpan::new(0.into(), 0)),
+                    e.help.as_deref(),
+                ));
+            }
+        }
 
         if modules.is_empty() {
             return Err("No module parsed".to_string());
@@ -787,7 +861,7 @@ impl ReplState {
         let (module_name, core_erlang) = self.compile_expr(expr_source)?;
 
         // Write and evaluate
-        let core_file = self.temp_dir.join(format!("{}.core", module_name));
+        let core_file = self.workdir.path().join(format!("{}.core", module_name));
         std::fs::write(&core_file, &core_erlang)
             .map_err(|e| format!("Failed to write Core Erlang: {}", e))?;
 
@@ -796,7 +870,7 @@ impl ReplState {
 
         let _ = std::fs::remove_file(&core_file);
 
-        result.map(|v| format_dream_value(&v))
+        result.and_then(|text| decode_shown(&text))
     }
 
     /// Evaluate an expression and bind the result to a name
@@ -804,7 +878,7 @@ impl ReplState {
         let (module_name, core_erlang) = self.compile_expr(expr_source)?;
 
         // Write Core Erlang file
-        let core_file = self.temp_dir.join(format!("{}.core", module_name));
+        let core_file = self.workdir.path().join(format!("{}.core", module_name));
         std::fs::write(&core_file, &core_erlang)
             .map_err(|e| format!("Failed to write Core Erlang: {}", e))?;
 
@@ -819,7 +893,7 @@ impl ReplState {
             self.add_binding(name.to_string());
         }
 
-        result.map(|v| format_dream_value(&v))
+        result.and_then(|text| decode_shown(&text))
     }
 
     /// Generate Dream source code wrapping an expression
@@ -843,6 +917,8 @@ impl ReplState {
             source.push_str("    }\n\n");
         }
 
+        // `__eval__` returns the value and how the shell shows it, the way
+        // `{:?}` does, so a type's `Inspect` impl is found where its type is known
         source.push_str("    pub fn __eval__() -> any {\n");
 
         // Bind each variable by calling the extern function
@@ -853,10 +929,11 @@ impl ReplState {
             ));
         }
 
-        // Track where the user expression starts (after 8 spaces of indentation)
-        let expr_offset = source.len() + 8; // 8 spaces of indentation
+        source.push_str("        let __value = ");
+        let expr_offset = source.len();
 
-        source.push_str(&format!("        {}\n", expr_source));
+        source.push_str(&format!("{};\n", expr_source.trim_end().trim_end_matches(';')));
+        source.push_str("        (__value, format!(\"{:?}\", __value))\n");
         source.push_str("    }\n");
         source.push_str("}\n");
         (source, expr_offset)
@@ -969,23 +1046,10 @@ fn parse_export_tuple(s: &str) -> Option<(String, u8)> {
     Some((name.to_string(), arity))
 }
 
-/// Find stdlib path
-fn find_stdlib_path() -> Option<String> {
-    if let Ok(exe_path) = std::env::current_exe() {
-        if let Some(exe_dir) = exe_path.parent() {
-            let stdlib = exe_dir.join("../stdlib");
-            if stdlib.exists() {
-                return stdlib.canonicalize().ok().map(|p| p.to_string_lossy().into_owned());
-            }
-        }
-    }
-
-    let target_stdlib = std::path::Path::new("target/stdlib");
-    if target_stdlib.exists() {
-        return target_stdlib.canonicalize().ok().map(|p| p.to_string_lossy().into_owned());
-    }
-
-    None
+/// Find the compiled stdlib, if it has been built.
+fn find_stdlib_path() -> Option<PathBuf> {
+    let stdlib = TargetLayout::stdlib_dir();
+    stdlib.exists().then_some(stdlib)
 }
 
 fn capitalize_first(s: &str) -> String {
@@ -996,196 +1060,11 @@ fn capitalize_first(s: &str) -> String {
     }
 }
 
-/// Format Erlang value as Dream syntax
-fn format_dream_value(value: &str) -> String {
-    let value = value.trim();
-
-    if value == "none" {
-        return "None".to_string();
-    }
-    if let Some(inner) = value.strip_prefix("{some,").and_then(|s| s.strip_suffix('}')) {
-        return format!("Some({})", format_dream_value(inner.trim()));
-    }
-    if let Some(inner) = value.strip_prefix("{ok,").and_then(|s| s.strip_suffix('}')) {
-        return format!("Ok({})", format_dream_value(inner.trim()));
-    }
-    if let Some(inner) = value.strip_prefix("{error,").and_then(|s| s.strip_suffix('}')) {
-        return format!("Err({})", format_dream_value(inner.trim()));
-    }
-
-    if let Some(inner) = value.strip_prefix('\'').and_then(|s| s.strip_suffix('\'')) {
-        if inner == "true" || inner == "false" || inner == "ok" || inner == "error" {
-            return inner.to_string();
-        }
-        return format!(":{}", inner);
-    }
-
-    if let Some(inner) = value.strip_prefix("<<\"").and_then(|s| s.strip_suffix("\">>")) {
-        return format!("\"{}\"", inner);
-    }
-
-    if value.starts_with("#{") && value.contains("'__struct__'") {
-        if let Some(struct_start) = value.find("'__struct__' => '") {
-            let after_struct = &value[struct_start + 17..];
-            if let Some(struct_end) = after_struct.find('\'') {
-                let struct_path = &after_struct[..struct_end];
-                let struct_name = struct_path.split("::").last().unwrap_or(struct_path);
-
-                // Check for wrapped struct format: data => #{...}
-                if let Some(data_start) = value.find("data => ") {
-                    let after_data = &value[data_start + 8..];
-                    if let Some(data_content) = extract_map_content(after_data) {
-                        if struct_name == "Map" {
-                            if data_content == "#{}" {
-                                return "{}".to_string();
-                            }
-                            return format!("{{ {} }}", format_map_fields(&data_content));
-                        }
-                        if data_content == "#{}" {
-                            return format!("{}()", struct_name);
-                        }
-                        return format!("{}({})", struct_name, format_map_fields(&data_content));
-                    }
-                } else {
-                    // Direct struct format: #{field => val, '__struct__' => 'Type', ...}
-                    // Extract fields excluding __struct__
-                    let fields = format_struct_fields(value, struct_name);
-                    if fields.is_empty() {
-                        return format!("{} {{}}", struct_name);
-                    }
-                    return format!("{} {{ {} }}", struct_name, fields);
-                }
-            }
-        }
-    }
-
-    value.to_string()
-}
-
-fn extract_map_content(s: &str) -> Option<String> {
-    if !s.starts_with("#{") {
-        return None;
-    }
-    let mut depth = 0;
-    let mut end_idx = 0;
-    for (i, c) in s.chars().enumerate() {
-        match c {
-            '{' => depth += 1,
-            '}' => {
-                depth -= 1;
-                if depth == 0 {
-                    end_idx = i + 1;
-                    break;
-                }
-            }
-            _ => {}
-        }
-    }
-    if end_idx > 0 { Some(s[..end_idx].to_string()) } else { None }
-}
-
-fn format_map_fields(map_str: &str) -> String {
-    let inner = map_str.strip_prefix("#{").and_then(|s| s.strip_suffix('}'));
-    if let Some(inner) = inner {
-        if inner.is_empty() { return String::new(); }
-        inner.replace(" => ", ": ")
-    } else {
-        map_str.to_string()
-    }
-}
-
-/// Format struct fields from an Erlang map, excluding __struct__
-/// Input: #{name => <<"Sonny">>,'__struct__' => 'repl_edit::User',age => 45}
-/// Output: name: "Sonny", age: 45
-fn format_struct_fields(map_str: &str, _struct_name: &str) -> String {
-    let inner = map_str
-        .strip_prefix("#{")
-        .and_then(|s| s.strip_suffix('}'));
-
-    let Some(inner) = inner else {
-        return String::new();
-    };
-
-    if inner.is_empty() {
-        return String::new();
-    }
-
-    // Parse the key => value pairs, handling nested structures
-    // We need to handle Erlang binaries like <<"text">> specially
-    let mut fields = Vec::new();
-    let mut current = String::new();
-    let mut depth = 0;
-    let mut in_binary = false;
-    let chars: Vec<char> = inner.chars().collect();
-    let mut i = 0;
-
-    while i < chars.len() {
-        let c = chars[i];
-
-        // Check for binary start: <<
-        if c == '<' && i + 1 < chars.len() && chars[i + 1] == '<' {
-            in_binary = true;
-            current.push('<');
-            current.push('<');
-            i += 2;
-            continue;
-        }
-
-        // Check for binary end: >>
-        if c == '>' && i + 1 < chars.len() && chars[i + 1] == '>' && in_binary {
-            in_binary = false;
-            current.push('>');
-            current.push('>');
-            i += 2;
-            continue;
-        }
-
-        match c {
-            '{' | '[' | '(' => {
-                depth += 1;
-                current.push(c);
-            }
-            '}' | ']' | ')' => {
-                depth -= 1;
-                current.push(c);
-            }
-            ',' if depth == 0 && !in_binary => {
-                let field = current.trim();
-                if !field.is_empty() && !field.contains("'__struct__'") {
-                    fields.push(format_single_field(field));
-                }
-                current.clear();
-            }
-            _ => current.push(c),
-        }
-        i += 1;
-    }
-
-    // Don't forget the last field
-    let field = current.trim();
-    if !field.is_empty() && !field.contains("'__struct__'") {
-        fields.push(format_single_field(field));
-    }
-
-    fields.join(", ")
-}
-
-/// Format a single field: "name => <<"Sonny">>" -> "name: \"Sonny\""
-fn format_single_field(field: &str) -> String {
-    if let Some(arrow_pos) = field.find(" => ") {
-        let key = field[..arrow_pos].trim();
-        let value = field[arrow_pos + 4..].trim();
-
-        // Clean up atom keys (remove quotes)
-        let clean_key = key.trim_matches('\'');
-
-        // Format the value
-        let formatted_value = format_dream_value(value);
-
-        format!("{}: {}", clean_key, formatted_value)
-    } else {
-        field.to_string()
-    }
+/// The text of a result the eval server sent hex-encoded. Unlike
+/// `testing::decode`, trailing whitespace is part of the result.
+fn decode_shown(field: &str) -> Result<String, String> {
+    let bytes = hex::decode(field).map_err(|_| format!("malformed result: {}", field))?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
 }
 
 fn print_banner() {
@@ -1203,17 +1082,28 @@ fn print_help() {
     println!("  :reload         Reload module registry");
     println!("  :edit, :e       Open $EDITOR to write Dream code");
     println!("  :load <file>    Compile and load a .dream file");
+    println!("  :processes, :ps List live processes with labels and priorities");
     println!();
     println!("Enter Dream expressions to evaluate them.");
     println!("Use 'let x = expr' to create bindings.");
     println!("Press TAB for completion.");
 }
 
-/// Run the interactive shell
-pub fn run_shell() -> ExitCode {
+/// Run the interactive shell. `code_paths` are added to the BEAM code path,
+/// e.g. a project's compiled modules and its dependencies' ebin dirs.
+/// The session's files go in a directory of its own inside `workdir`, or
+/// inside the system temp dir.
+pub fn run_shell(code_paths: Vec<std::path::PathBuf>, workdir: Option<&Path>) -> ExitCode {
     print_banner();
 
-    let mut state = ReplState::new();
+    let workdir = match Workdir::create(workdir) {
+        Ok(workdir) => workdir,
+        Err(e) => {
+            eprintln!("Failed to create the session's work directory: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let mut state = ReplState::new(workdir, code_paths);
 
     // Create Editor first so we can get an ExternalPrinter for BEAM output
     let helper = ReplHelper::new(Rc::clone(&state.bindings), Rc::clone(&state.registry));
@@ -1300,6 +1190,12 @@ pub fn run_shell() -> ExitCode {
                             }
                             continue;
                         }
+                        ":processes" | ":ps" => {
+                            if let Err(e) = state.send_command("introspect:processes") {
+                                eprintln!("Error: {}", e);
+                            }
+                            continue;
+                        }
                         ":edit" | ":e" => {
                             match edit_and_eval(&mut state) {
                                 Ok(Some(result)) => println!("{}", result),
@@ -1328,7 +1224,7 @@ pub fn run_shell() -> ExitCode {
                     }
                 }
 
-                match parse_and_eval(&mut state, line) {
+                match eval_line(&mut state, line) {
                     Ok(result) => println!("{}", result),
                     Err(e) => eprintln!("Error: {}", e),
                 }
@@ -1361,7 +1257,14 @@ pub fn run_shell_with_app(
     println!("Starting application '{}'...", app_name);
     println!();
 
-    let mut state = ReplState::with_app(app_name.clone(), beam_dir, deps_dirs);
+    let workdir = match Workdir::create(None) {
+        Ok(workdir) => workdir,
+        Err(e) => {
+            eprintln!("Failed to create the session's work directory: {}", e);
+            return ExitCode::from(1);
+        }
+    };
+    let mut state = ReplState::with_app(app_name.clone(), beam_dir, deps_dirs, workdir);
 
     // Create Editor first so we can get an ExternalPrinter for BEAM output
     let helper = ReplHelper::new(Rc::clone(&state.bindings), Rc::clone(&state.registry));
@@ -1450,6 +1353,12 @@ pub fn run_shell_with_app(
                             }
                             continue;
                         }
+                        ":processes" | ":ps" => {
+                            if let Err(e) = state.send_command("introspect:processes") {
+                                eprintln!("Error: {}", e);
+                            }
+                            continue;
+                        }
                         ":edit" | ":e" => {
                             match edit_and_eval(&mut state) {
                                 Ok(Some(result)) => println!("{}", result),
@@ -1478,7 +1387,7 @@ pub fn run_shell_with_app(
                     }
                 }
 
-                match parse_and_eval(&mut state, line) {
+                match eval_line(&mut state, line) {
                     Ok(result) => println!("{}", result),
                     Err(e) => eprintln!("Error: {}", e),
                 }
@@ -1515,7 +1424,7 @@ mod repl_edit {
 /// Open a temp file in the user's editor and compile/run it
 fn edit_and_eval(state: &mut ReplState) -> Result<Option<String>, String> {
     // Create a temporary file with .dream extension
-    let temp_file = state.temp_dir.join("dream_repl_edit.dream");
+    let temp_file = state.workdir.path().join("dream_repl_edit.dream");
 
     // Use last edit if available, otherwise use template
     let initial_content = state.last_edit.as_deref().unwrap_or(EDIT_TEMPLATE);
@@ -1589,10 +1498,17 @@ fn compile_and_run_source(
 ) -> Result<Option<String>, String> {
     // Parse the source
     let mut parser = Parser::new(source);
-    let modules = parser.parse_file_modules(fallback_name).map_err(|e| {
+    let mut modules = parser.parse_file_modules(fallback_name).map_err(|e| {
         let err = CompilerError::parse(fallback_name, source, e);
         format!("{:?}", miette::Report::new(err))
     })?;
+    for module in &mut modules {
+        let configured = configure_module(module, &CompileOptions::default());
+        if let Some(e) = configured.errors.into_iter().next() {
+            let err = CompilerError::type_error(fallback_name, source, e);
+            return Err(format!("{:?}", miette::Report::new(err)));
+        }
+    }
 
     if modules.is_empty() {
         return Ok(None);
@@ -1628,7 +1544,7 @@ fn compile_and_run_source(
             .map_err(|e| format!("Codegen error: {}", e))?;
 
         // Write Core Erlang to temp file
-        let core_file = state.temp_dir.join(format!("{}.core", prefixed_module.name));
+        let core_file = state.workdir.path().join(format!("{}.core", prefixed_module.name));
         std::fs::write(&core_file, &core_erlang)
             .map_err(|e| format!("Failed to write Core Erlang: {}", e))?;
 
@@ -1661,6 +1577,13 @@ fn compile_and_run_source(
     Ok(Some(format!("Loaded: {}", loaded_modules.join(", "))))
 }
 
+/// Evaluate a line, reporting a panic in the compiler as its error rather
+/// than ending the session.
+fn eval_line(state: &mut ReplState, input: &str) -> Result<String, String> {
+    ice::catch(|| parse_and_eval(state, input))
+        .unwrap_or_else(|error| Err(format!("{:?}", miette::Report::new(*error))))
+}
+
 fn parse_and_eval(state: &mut ReplState, input: &str) -> Result<String, String> {
     let input = input.trim();
 
@@ -1673,13 +1596,15 @@ fn parse_and_eval(state: &mut ReplState, input: &str) -> Result<String, String>
 }
 
 fn parse_and_eval_let(state: &mut ReplState, input: &str) -> Result<String, String> {
-    let input = input.trim().strip_prefix("let ").unwrap();
+    let Some(input) = input.trim().strip_prefix("let ") else {
+        return Err("Expected a let statement".to_string());
+    };
     let eq_pos = input.find('=').ok_or("Expected '=' in let statement")?;
 
     let name = input[..eq_pos].trim().to_string();
     let expr_source = input[eq_pos + 1..].trim().to_string();
 
-    if name.is_empty() || !name.chars().next().unwrap().is_alphabetic() {
+    if !name.chars().next().is_some_and(char::is_alphabetic) {
         return Err("Invalid variable name".to_string());
     }
 
@@ -1783,43 +1708,35 @@ mod tests {
     }
 
     #[test]
-    fn test_format_dream_value_quoted_atoms() {
-        // Atoms come from Erlang with single quotes
-        assert_eq!(format_dream_value("'ok'"), "ok");
-        assert_eq!(format_dream_value("'error'"), "error");
-        assert_eq!(format_dream_value("'true'"), "true");
-        assert_eq!(format_dream_value("'false'"), "false");
-        assert_eq!(format_dream_value("'my_atom'"), ":my_atom");
-    }
-
-    #[test]
-    fn test_format_dream_value_numbers() {
-        assert_eq!(format_dream_value("42"), "42");
-        assert_eq!(format_dream_value("-123"), "-123");
-    }
-
-    #[test]
-    fn test_format_dream_value_strings() {
-        assert_eq!(format_dream_value("<<\"hello\">>"), "\"hello\"");
-    }
-
-    #[test]
-    fn test_format_dream_value_result_types() {
-        // {ok, value} and {error, value} are formatted as Ok/Err
-        assert_eq!(format_dream_value("{ok,42}"), "Ok(42)");
-        assert_eq!(format_dream_value("{error,42}"), "Err(42)");
+    fn test_decode_shown() {
+        assert_eq!(decode_shown("506F696E74207B20783A2031207D").unwrap(), "Point { x: 1 }");
+        assert_eq!(decode_shown("220A22").unwrap(), "\"\n\"");
+        assert_eq!(decode_shown("").unwrap(), "");
+        assert!(decode_shown("4").is_err());
     }
 
     #[test]
-    fn test_format_dream_value_option_types() {
-        assert_eq!(format_dream_value("none"), "None");
-        assert_eq!(format_dream_value("{some,42}"), "Some(42)");
+    fn test_generated_source_shows_value() {
+        let base = tempfile::TempDir::new().unwrap();
+        let state = ReplState::new(Workdir::create(Some(base.path())).unwrap(), vec![]);
+        let (source, offset) = state.generate_dream_source("__repl_0", "1 + 2;");
+        assert!(source[offset..].starts_with("1 + 2;\n"));
+        assert!(source.contains("(__value, format!(\"{:?}\", __value))"));
     }
 
     #[test]
-    fn test_format_dream_value_lists() {
-        // Lists pass through as-is
-        assert_eq!(format_dream_value("[1,2,3]"), "[1,2,3]");
-        assert_eq!(format_dream_value("[]"), "[]");
+    fn test_workdir() {
+        let base = tempfile::TempDir::new().unwrap();
+        let one = Workdir::create(Some(base.path())).unwrap();
+        let two = Workdir::create(Some(base.path())).unwrap();
+        assert_ne!(one.path(), two.path());
+        assert!(one.path().starts_with(base.path()));
+
+        let file = one.path().join("__repl_0.core");
+        std::fs::write(&file, "module '__repl_0' [] attributes [] end").unwrap();
+        let path = one.path().to_path_buf();
+        drop(one);
+        assert!(!path.exists());
+        assert!(two.path().is_dir());
     }
 }
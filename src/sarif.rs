@@ -0,0 +1,325 @@
+//! SARIF output for `--message-format=sarif`.
+//!
+//! SARIF is the format code scanning services, GitHub's among them, take
+//! findings in. In SARIF mode the CLI keeps every diagnostic it reports and
+//! prints them as a single SARIF 2.1.0 log on stdout once the command is
+//! done. The log is made from the same [`Diagnostic`]s as the JSON
+//! messages: each error code or lint becomes a rule carrying its
+//! explanation, labels become related locations, and suggestions become
+//! fixes.
+
+use crate::compiler::{ErrorCode, Lint, LintLevel};
+use crate::output::{Diagnostic, DiagnosticSpan, Level};
+use serde_json::{Value, json};
+use std::path::Path;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// Base id that relative file locations are resolved against
+const SRCROOT: &str = "%SRCROOT%";
+
+/// A SARIF log with one run holding `diagnostics`. Files under `root` are
+/// given relative to it, so code scanning can match them to the files of
+/// the repository.
+pub fn sarif_log(diagnostics: &[Diagnostic], root: &Path) -> Value {
+    let mut rules = Vec::new();
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| result(diagnostic, root, &mut rules))
+        .collect();
+    let rules: Vec<Value> = rules.iter().map(|id| rule(id)).collect();
+    json!({
+        "$schema": SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "dream",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "originalUriBaseIds": {
+                SRCROOT: { "uri": directory_uri(root) },
+            },
+            // Diagnostic columns count characters
+            "columnKind": "unicodeCodePoints",
+            "results": results,
+        }],
+    })
+}
+
+/// The result for one diagnostic. The help and notes go into the message,
+/// after it, as they would be printed. `rules` collects the ids of the
+/// rules the results refer to, in order of first use.
+fn result(diagnostic: &Diagnostic, root: &Path, rules: &mut Vec<String>) -> Value {
+    let mut text = diagnostic.message.clone();
+    if let Some(help) = &diagnostic.help {
+        text.push_str(&format!("\nhelp: {}", help));
+    }
+    for note in &diagnostic.notes {
+        text.push_str(&format!("\nnote: {}", note));
+    }
+    let mut result = json!({
+        "level": level(diagnostic.level),
+        "message": { "text": text },
+    });
+
+    if let Some(code) = &diagnostic.code {
+        let index = match rules.iter().position(|id| id == code) {
+            Some(index) => index,
+            None => {
+                rules.push(code.clone());
+                rules.len() - 1
+            }
+        };
+        result["ruleId"] = json!(code);
+        result["ruleIndex"] = json!(index);
+    }
+    if let Some(module) = &diagnostic.module {
+        result["locations"] = json!([{
+            "logicalLocations": [{ "fullyQualifiedName": module, "kind": "module" }],
+        }]);
+    }
+
+    let Some(file) = &diagnostic.file else {
+        return result;
+    };
+    let artifact = artifact_location(file, root);
+    let mut location = physical_location(&artifact, diagnostic.span.as_ref());
+    if let Some(logical) = result.pointer("/locations/0/logicalLocations") {
+        location["logicalLocations"] = logical.clone();
+    }
+    result["locations"] = json!([location]);
+
+    if !diagnostic.labels.is_empty() {
+        let related: Vec<Value> = diagnostic
+            .labels
+            .iter()
+            .enumerate()
+            .map(|(id, label)| {
+                let mut location = physical_location(&artifact, Some(&label.span));
+                location["id"] = json!(id);
+                location["message"] = json!({ "text": label.message });
+                location
+            })
+            .collect();
+        result["relatedLocations"] = json!(related);
+    }
+    if !diagnostic.suggestions.is_empty() {
+        let fixes: Vec<Value> = diagnostic
+            .suggestions
+            .iter()
+            .map(|suggestion| {
+                json!({
+                    "description": { "text": suggestion.message },
+                    "artifactChanges": [{
+                        "artifactLocation": artifact,
+                        "replacements": [{
+                            "deletedRegion": region(&suggestion.span),
+                            "insertedContent": { "text": suggestion.replacement },
+                        }],
+                    }],
+                })
+            })
+            .collect();
+        result["fixes"] = json!(fixes);
+    }
+    result
+}
+
+/// The rule for a diagnostic code: an error code with its explanation, or
+/// a lint with its description.
+fn rule(id: &str) -> Value {
+    if let Some(code) = ErrorCode::from_code(id) {
+        let explanation = code.explanation();
+        let default = if code.is_warning() {
+            Level::Warning
+        } else {
+            Level::Error
+        };
+        return json!({
+            "id": id,
+            "shortDescription": { "text": explanation.title },
+            "fullDescription": { "text": explanation.description },
+            "help": { "text": explanation.description, "markdown": code.explain() },
+            "defaultConfiguration": { "level": level(default) },
+        });
+    }
+    match Lint::from_name(id) {
+        Some(lint) => json!({
+            "id": id,
+            "shortDescription": { "text": lint.description() },
+            "defaultConfiguration": { "level": lint_level(lint) },
+        }),
+        None => json!({ "id": id }),
+    }
+}
+
+fn level(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warning => "warning",
+    }
+}
+
+fn lint_level(lint: Lint) -> &'static str {
+    match lint.default_level() {
+        LintLevel::Allow => "none",
+        LintLevel::Warn => "warning",
+        LintLevel::Deny => "error",
+    }
+}
+
+fn physical_location(artifact: &Value, span: Option<&DiagnosticSpan>) -> Value {
+    let mut location = json!({ "physicalLocation": { "artifactLocation": artifact } });
+    if let Some(span) = span {
+        location["physicalLocation"]["region"] = region(span);
+    }
+    location
+}
+
+fn region(span: &DiagnosticSpan) -> Value {
+    json!({
+        "startLine": span.line_start,
+        "startColumn": span.column_start,
+        "endLine": span.line_end,
+        "endColumn": span.column_end,
+    })
+}
+
+/// Where `file` is: relative to the source root if it is under `root` or
+/// already relative, and an absolute `file:` URI otherwise.
+fn artifact_location(file: &Path, root: &Path) -> Value {
+    let relative = if file.is_relative() {
+        Some(file)
+    } else {
+        file.strip_prefix(root).ok()
+    };
+    match relative {
+        Some(path) => json!({ "uri": slashes(path), "uriBaseId": SRCROOT }),
+        None => json!({ "uri": file_uri(file) }),
+    }
+}
+
+/// `path` with `/` between its components, as URIs have.
+fn slashes(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn file_uri(path: &Path) -> String {
+    let path = slashes(path);
+    if path.starts_with('/') {
+        format!("file://{}", path)
+    } else {
+        // A Windows path such as `C:/src`
+        format!("file:///{}", path)
+    }
+}
+
+/// A directory's URI ends in `/`, so relative URIs resolve inside it.
+fn directory_uri(path: &Path) -> String {
+    let uri = file_uri(path);
+    if uri.ends_with('/') {
+        uri
+    } else {
+        format!("{}/", uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_sarif_log() {
+        let source = "fn f() {\n    cont + 1\n}\n";
+        let mut error = Diagnostic::error("undefined variable: cont")
+            .with_code("E0020")
+            .in_module("app::math")
+            .with_file(Some(PathBuf::from("/proj/src/math.dream")))
+            .with_span(source, Some(13..17))
+            .with_help(Some("did you mean `count`?".to_string()));
+        error
+            .notes
+            .push("variables must be bound before use".to_string());
+        error.suggestions.push(crate::output::DiagnosticSuggestion {
+            span: DiagnosticSpan::new(source, 13..17),
+            replacement: "count".to_string(),
+            message: "replace with `count`".to_string(),
+            applicability: "maybe-incorrect",
+        });
+        let lint = Diagnostic::warning("variable `x` is never used")
+            .with_code("unused_variables")
+            .in_module("app::math");
+        let again = Diagnostic::error("undefined variable: y").with_code("E0020");
+
+        let log = sarif_log(&[error, lint, again], Path::new("/proj"));
+        assert_eq!(log["version"], "2.1.0");
+        let run = &log["runs"][0];
+        assert_eq!(
+            run["originalUriBaseIds"]["%SRCROOT%"]["uri"],
+            "file:///proj/"
+        );
+
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0]["id"], "E0020");
+        assert_eq!(rules[0]["shortDescription"]["text"], "undefined variable");
+        assert_eq!(rules[0]["defaultConfiguration"]["level"], "error");
+        assert_eq!(rules[1]["id"], "unused_variables");
+        assert_eq!(rules[1]["defaultConfiguration"]["level"], "warning");
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        let result = &results[0];
+        assert_eq!(result["ruleId"], "E0020");
+        assert_eq!(result["ruleIndex"], 0);
+        assert_eq!(result["level"], "error");
+        assert_eq!(
+            result["message"]["text"],
+            "undefined variable: cont\nhelp: did you mean `count`?\nnote: variables must be bound before use"
+        );
+        let location = &result["locations"][0];
+        assert_eq!(
+            location["physicalLocation"]["artifactLocation"]["uri"],
+            "src/math.dream"
+        );
+        assert_eq!(
+            location["physicalLocation"]["artifactLocation"]["uriBaseId"],
+            "%SRCROOT%"
+        );
+        let region = &location["physicalLocation"]["region"];
+        assert_eq!(
+            (region["startLine"].as_u64(), region["startColumn"].as_u64()),
+            (Some(2), Some(5))
+        );
+        assert_eq!(
+            location["logicalLocations"][0]["fullyQualifiedName"],
+            "app::math"
+        );
+        let replacement = &result["fixes"][0]["artifactChanges"][0]["replacements"][0];
+        assert_eq!(replacement["insertedContent"]["text"], "count");
+
+        // Without a file, the module is the only location
+        assert_eq!(results[1]["level"], "warning");
+        assert_eq!(results[1]["ruleIndex"], 1);
+        assert!(results[1]["locations"][0].get("physicalLocation").is_none());
+        assert_eq!(results[2]["ruleIndex"], 0);
+        assert!(results[2].get("locations").is_none());
+    }
+
+    #[test]
+    fn test_artifact_location() {
+        let root = Path::new("/proj");
+        assert_eq!(
+            artifact_location(Path::new("/elsewhere/lib.dream"), root),
+            json!({ "uri": "file:///elsewhere/lib.dream" })
+        );
+        assert_eq!(
+            artifact_location(Path::new("src/main.dream"), root),
+            json!({ "uri": "src/main.dream", "uriBaseId": "%SRCROOT%" })
+        );
+    }
+}
@@ -5,8 +5,8 @@ use std::collections::{HashMap, VecDeque};
 use num_bigint::BigInt;
 
 use crate::{
-    CallFrame, Instruction, Message, Module, Operand, Pattern, Pid, Process, ProcessStatus,
-    Register, Source, SystemMsg, TryFrame, Value,
+    CallFrame, Instruction, MailboxLimit, Message, Module, Operand, OverflowPolicy, Pattern, Pid,
+    Priority, Process, ProcessStatus, Ref, Register, Source, SystemMsg, TryFrame, Value,
 };
 
 /// Result of stepping the scheduler
@@ -53,16 +53,24 @@ fn log(msg: &str) {
 #[derive(Debug, Clone)]
 pub struct Timer {
     /// Unique timer reference
-    pub timer_ref: u64,
+    pub timer_ref: Ref,
     /// Process that will receive the message
     pub target: Pid,
     /// Message to send when timer fires
     pub message: Value,
-    /// Remaining reductions until timer fires
-    pub remaining: u32,
+    /// Scheduler time (in reductions) at which the timer fires
+    pub deadline: u64,
+    /// Re-arm period for interval timers; `None` for one-shot timers
+    pub interval: Option<u32>,
 }
 
+/// Reductions a process may run before it is preempted and requeued.
+pub const REDUCTIONS_PER_SLICE: u32 = 2000;
+
 /// The scheduler / VM state
+///
+/// Processes run one time slice at a time on the calling thread, taking
+/// turns in the order they became ready, so runs are deterministic.
 #[derive(Debug)]
 pub struct Scheduler {
     pub processes: HashMap<Pid, Process>,
@@ -74,10 +82,17 @@ pub struct Scheduler {
     pub modules: HashMap<String, Module>,
     /// Output buffer from print instructions
     pub output: Vec<String>,
-    /// Global reference counter for make_ref and timer_ref
+    /// Counter for refs made by this VM (make_ref, monitors, timers)
     pub next_ref: u64,
+    /// Distinguishes this VM's refs from those of other VM instances
+    pub creation: u32,
     /// Pending timers
     pub timers: Vec<Timer>,
+    /// Scheduler time in reductions. Advanced by the reductions processes
+    /// use, and shared by timers and receive timeouts.
+    pub clock: u64,
+    /// Mailbox limit given to newly spawned processes
+    pub default_mailbox_limit: Option<MailboxLimit>,
 }
 
 impl Scheduler {
@@ -90,7 +105,10 @@ impl Scheduler {
             modules: HashMap::new(),
             output: Vec::new(),
             next_ref: 0,
+            creation: Ref::next_creation(),
             timers: Vec::new(),
+            clock: 0,
+            default_mailbox_limit: None,
         }
     }
 
@@ -136,6 +154,13 @@ impl Scheduler {
         std::mem::take(&mut self.output)
     }
 
+    /// Create a new ref, unique within this node
+    pub fn make_ref(&mut self) -> Ref {
+        let r = Ref::new(self.creation, self.next_ref);
+        self.next_ref += 1;
+        r
+    }
+
     /// Spawn a root process (no parent)
     pub fn spawn(&mut self, code: Vec<Instruction>) -> Pid {
         self.spawn_with_parent(code, None)
@@ -146,38 +171,45 @@ impl Scheduler {
         let pid = Pid(self.next_pid);
         self.next_pid += 1;
 
-        let process = Process::new(pid, parent, code);
+        let mut process = Process::new(pid, parent, code);
+        process.mailbox_limit = self.default_mailbox_limit;
         self.processes.insert(pid, process);
         self.ready_queue.push_back(pid);
 
         pid
     }
 
-    /// Run up to `budget` reductions, returns whether there's more work
-    pub fn step(&mut self, budget: u32) -> StepResult {
-        let mut remaining = budget;
-
-        // Tick timeouts for waiting processes
-        self.tick_timeouts();
+    /// Take the next ready process. High-priority processes are taken
+    /// ahead of the rest of the queue.
+    fn next_ready(&mut self) -> Option<Pid> {
+        let high = self.ready_queue.iter().position(|p| {
+            self.processes
+                .get(p)
+                .is_some_and(|p| p.priority == Priority::High)
+        });
+        match high {
+            Some(i) => self.ready_queue.remove(i),
+            None => self.ready_queue.pop_front(),
+        }
+    }
 
-        // Tick timers and fire expired ones
-        self.tick_timers(budget);
+    /// Run up to `budget` reductions, a time slice of at most
+    /// [`REDUCTIONS_PER_SLICE`] per process in turn; returns whether
+    /// there's more work
+    pub fn step(&mut self, budget: u32) -> StepResult {
+        self.tick();
 
+        let mut remaining = budget;
         while remaining > 0 {
-            // Get next ready process
-            let Some(pid) = self.ready_queue.pop_front() else {
-                // No ready processes - check if any are waiting with timeouts or timers
-                if self.has_pending_timeouts() || !self.timers.is_empty() {
-                    return StepResult::Busy; // Keep ticking
-                }
-                return StepResult::Idle;
+            let Some(pid) = self.next_ready() else {
+                break;
             };
-
-            // Run it for up to `remaining` reductions
-            let used = self.run_process(pid, remaining);
-            remaining = remaining.saturating_sub(used);
+            let used = self.run_process(pid, remaining.min(REDUCTIONS_PER_SLICE));
+            remaining = remaining.saturating_sub(used.max(1));
         }
+        self.advance_clock((budget - remaining) as u64);
 
+        self.release_suspended_senders();
         if self.ready_queue.is_empty() && !self.has_pending_timeouts() && self.timers.is_empty() {
             StepResult::Idle
         } else {
@@ -185,6 +217,38 @@ impl Scheduler {
         }
     }
 
+    /// Deliver what is due at the current time: fire timers before expiring
+    /// receive timeouts, so a message due at the same time wins over the
+    /// `after`.
+    fn tick(&mut self) {
+        self.fire_timers();
+        self.expire_timeouts();
+        self.release_suspended_senders();
+    }
+
+    /// Move the clock on by the `elapsed` reductions. When nothing ran and
+    /// nothing is ready, skip ahead to the next timer or receive timeout
+    /// instead, since nothing else can happen before it.
+    fn advance_clock(&mut self, elapsed: u64) {
+        if elapsed > 0 {
+            self.clock += elapsed;
+        } else if self.ready_queue.is_empty()
+            && let Some(deadline) = self.next_deadline()
+        {
+            self.clock = self.clock.max(deadline);
+        }
+    }
+
+    /// The earliest time a timer fires or a receive times out
+    fn next_deadline(&self) -> Option<u64> {
+        let timeouts = self
+            .processes
+            .values()
+            .filter(|p| p.status == ProcessStatus::Waiting)
+            .filter_map(|p| p.timeout);
+        self.timers.iter().map(|t| t.deadline).chain(timeouts).min()
+    }
+
     /// Check if any waiting process has a pending timeout
     fn has_pending_timeouts(&self) -> bool {
         self.processes
@@ -192,17 +256,17 @@ impl Scheduler {
             .any(|p| p.status == ProcessStatus::Waiting && p.timeout.is_some())
     }
 
-    /// Decrement timeouts for waiting processes, wake those that expired
-    fn tick_timeouts(&mut self) {
+    /// Wake waiting processes whose receive timeout has passed
+    fn expire_timeouts(&mut self) {
+        let now = self.clock;
         let mut to_wake = Vec::new();
 
         for (pid, process) in &mut self.processes {
             if process.status == ProcessStatus::Waiting {
-                if let Some(ref mut t) = process.timeout {
-                    if *t > 0 {
-                        *t -= 1;
-                    }
-                    if *t == 0 {
+                if let Some(deadline) = process.timeout {
+                    if deadline <= now {
+                        process.timeout = None;
+                        process.timed_out = true;
                         process.status = ProcessStatus::Ready;
                         to_wake.push(*pid);
                     }
@@ -210,32 +274,77 @@ impl Scheduler {
             }
         }
 
+        // HashMap iteration order is arbitrary; wake in pid order
+        to_wake.sort_by_key(|p| p.0);
         for pid in to_wake {
             self.ready_queue.push_back(pid);
         }
     }
 
-    /// Tick timers and fire any that have expired
-    fn tick_timers(&mut self, elapsed: u32) {
-        let mut fired = Vec::new();
-        let mut i = 0;
+    /// Wake senders suspended on a full mailbox once the receiver has room
+    /// (or has exited). They retry their send when they next run.
+    fn release_suspended_senders(&mut self) {
+        let mut to_wake = Vec::new();
 
-        while i < self.timers.len() {
-            let timer = &mut self.timers[i];
-            timer.remaining = timer.remaining.saturating_sub(elapsed);
+        for process in self.processes.values_mut() {
+            if process.suspended_senders.is_empty() {
+                continue;
+            }
+            let has_room = match process.mailbox_limit {
+                Some(limit) => process.mailbox.len() < limit.max,
+                None => true,
+            };
+            let exited = matches!(process.status, ProcessStatus::Done | ProcessStatus::Crashed);
+            if has_room || exited {
+                to_wake.append(&mut process.suspended_senders);
+            }
+        }
 
-            if timer.remaining == 0 {
-                // Timer fired - collect it for processing
-                fired.push(self.timers.remove(i));
-            } else {
-                i += 1;
+        to_wake.sort_by_key(|p| p.0);
+        to_wake.dedup();
+        for pid in to_wake {
+            if let Some(p) = self.processes.get_mut(&pid) {
+                if p.status == ProcessStatus::Waiting {
+                    p.status = ProcessStatus::Ready;
+                    self.ready_queue.push_back(pid);
+                }
             }
         }
+    }
+
+    /// Fire all timers whose deadline has passed, in deadline order.
+    /// Interval timers are re-armed as long as their target is alive.
+    fn fire_timers(&mut self) {
+        let now = self.clock;
+        let (mut fired, pending): (Vec<Timer>, Vec<Timer>) = std::mem::take(&mut self.timers)
+            .into_iter()
+            .partition(|t| t.deadline <= now);
+        self.timers = pending;
+        fired.sort_by_key(|t| (t.deadline, t.timer_ref));
+
+        for mut timer in fired {
+            let Some(process) = self.processes.get_mut(&timer.target) else {
+                continue;
+            };
+            if matches!(process.status, ProcessStatus::Done | ProcessStatus::Crashed) {
+                continue;
+            }
+
+            // A timer has no sender to crash or suspend, so a full mailbox
+            // drops its message unless the policy makes room
+            let full = process
+                .mailbox_limit
+                .filter(|limit| process.mailbox.len() >= limit.max);
+            let deliver = match full {
+                Some(limit) if limit.policy == OverflowPolicy::DropOldest => {
+                    process.mailbox.pop_front();
+                    true
+                }
+                Some(_) => false,
+                None => true,
+            };
 
-        // Deliver messages for fired timers
-        for timer in fired {
-            if let Some(process) = self.processes.get_mut(&timer.target) {
-                // Send the message
+            if deliver {
                 let msg_str = format!("{:?}", timer.message);
                 process.mailbox.push_back(Message::User(msg_str));
 
@@ -245,9 +354,34 @@ impl Scheduler {
                     self.ready_queue.push_back(timer.target);
                 }
             }
+
+            if let Some(interval) = timer.interval {
+                // Catch up from the previous deadline so intervals don't drift,
+                // but never schedule in the past
+                timer.deadline = (timer.deadline + interval.max(1) as u64).max(now + 1);
+                self.timers.push(timer);
+            }
         }
     }
 
+    /// Register a timer and return its reference
+    fn add_timer(&mut self, target: Pid, message: Value, delay: u32, interval: Option<u32>) -> Ref {
+        let timer_ref = self.make_ref();
+        self.timers.push(Timer {
+            timer_ref,
+            target,
+            message,
+            deadline: self.clock + delay as u64,
+            interval,
+        });
+        timer_ref
+    }
+
+    /// Time left (in reductions) before a pending timer fires
+    fn timer_remaining(&self, timer: &Timer) -> i64 {
+        timer.deadline.saturating_sub(self.clock) as i64
+    }
+
     /// Run a single process for up to `budget` reductions
     /// Returns how many reductions were used
     fn run_process(&mut self, pid: Pid, budget: u32) -> u32 {
@@ -371,6 +505,10 @@ impl Scheduler {
             }
         }
 
+        if let Some(p) = self.processes.get_mut(&pid) {
+            p.reductions += used as u64;
+        }
+
         // If we used full budget but process isn't done, requeue it
         if used >= budget {
             if let Some(p) = self.processes.get(&pid) {
@@ -417,10 +555,92 @@ impl Scheduler {
                 ExecResult::Continue(1)
             }
 
+            Instruction::SpawnOpt { code, opts, dest } => {
+                let child_pid = self.spawn_with_parent(code, Some(pid));
+
+                if let Some(child) = self.processes.get_mut(&child_pid) {
+                    child.label = opts.label;
+                    child.priority = opts.priority;
+                    if opts.link {
+                        child.links.push(pid);
+                    }
+                }
+
+                let mut result = Value::Pid(child_pid);
+                if opts.monitor {
+                    let monitor_ref = self.make_ref();
+                    if let Some(child) = self.processes.get_mut(&child_pid) {
+                        child.monitored_by.push((monitor_ref, pid));
+                    }
+                    if let Some(p) = self.processes.get_mut(&pid) {
+                        p.monitors.push((monitor_ref, child_pid));
+                    }
+                    result = Value::Tuple(vec![result, Value::Ref(monitor_ref)]);
+                }
+
+                if let Some(parent) = self.processes.get_mut(&pid) {
+                    if opts.link {
+                        parent.links.push(child_pid);
+                    }
+                    parent.registers[dest.0 as usize] = result;
+                }
+
+                ExecResult::Continue(1)
+            }
+
+            Instruction::SetLabel { label } => {
+                if let Some(p) = self.processes.get_mut(&pid) {
+                    p.label = Some(label);
+                }
+                ExecResult::Continue(1)
+            }
+
+            Instruction::SetPriority { priority } => {
+                if let Some(p) = self.processes.get_mut(&pid) {
+                    p.priority = priority;
+                }
+                ExecResult::Continue(1)
+            }
+
             Instruction::Send { to, msg } => {
                 let target_pid = self.resolve_pid(pid, &to);
                 if let Some(target_pid) = target_pid {
                     if let Some(target) = self.processes.get_mut(&target_pid) {
+                        let alive =
+                            !matches!(target.status, ProcessStatus::Done | ProcessStatus::Crashed);
+                        match target.mailbox_limit {
+                            Some(limit) if alive && target.mailbox.len() >= limit.max => {
+                                match limit.policy {
+                                    OverflowPolicy::DropNewest => return ExecResult::Continue(1),
+                                    OverflowPolicy::DropOldest => {
+                                        target.mailbox.pop_front();
+                                    }
+                                    OverflowPolicy::CrashSender => {
+                                        return ExecResult::Exit(Value::Tuple(vec![
+                                            Value::Atom("mailbox_full".to_string()),
+                                            Value::Pid(target_pid),
+                                        ]));
+                                    }
+                                    // Nothing drains a process's own mailbox while it
+                                    // waits, so a self-send can't suspend
+                                    OverflowPolicy::SuspendSender if target_pid == pid => {
+                                        return ExecResult::Exit(Value::Tuple(vec![
+                                            Value::Atom("mailbox_full".to_string()),
+                                            Value::Pid(target_pid),
+                                        ]));
+                                    }
+                                    OverflowPolicy::SuspendSender => {
+                                        // Retry the send once the receiver makes room
+                                        if !target.suspended_senders.contains(&pid) {
+                                            target.suspended_senders.push(pid);
+                                        }
+                                        return ExecResult::Wait;
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+
                         target.mailbox.push_back(Message::User(msg));
                         // Wake up if waiting
                         if target.status == ProcessStatus::Waiting {
@@ -442,6 +662,7 @@ impl Scheduler {
                     let value = Self::message_to_value(msg);
                     process.registers[dest.0 as usize] = value;
                     process.timeout = None;
+                    process.timed_out = false;
                     ExecResult::Continue(1)
                 } else {
                     ExecResult::Wait
@@ -449,6 +670,7 @@ impl Scheduler {
             }
 
             Instruction::ReceiveTimeout { dest, timeout } => {
+                let now = self.clock;
                 let Some(process) = self.processes.get_mut(&pid) else {
                     return ExecResult::Crash;
                 };
@@ -458,16 +680,17 @@ impl Scheduler {
                     let value = Self::message_to_value(msg);
                     process.registers[dest.0 as usize] = value;
                     process.timeout = None;
+                    process.timed_out = false;
                     ExecResult::Continue(1)
-                } else if process.timeout == Some(0) {
+                } else if process.timed_out {
                     // Timeout expired
                     process.registers[dest.0 as usize] = Value::String("TIMEOUT".into());
-                    process.timeout = None;
+                    process.timed_out = false;
                     ExecResult::Continue(1)
                 } else {
-                    // Set or keep timeout, wait
+                    // Set or keep the deadline, wait
                     if process.timeout.is_none() {
-                        process.timeout = Some(timeout);
+                        process.timeout = Some(now + timeout as u64);
                     }
                     ExecResult::Wait
                 }
@@ -515,8 +738,7 @@ impl Scheduler {
                 };
 
                 // Generate a unique monitor reference
-                let monitor_ref = self.next_ref;
-                self.next_ref += 1;
+                let monitor_ref = self.make_ref();
 
                 // Add monitor on caller side
                 if let Some(p) = self.processes.get_mut(&pid) {
@@ -621,6 +843,20 @@ impl Scheduler {
                 ExecResult::Continue(1)
             }
 
+            Instruction::SetMailboxLimit { max, policy } => {
+                if let Some(p) = self.processes.get_mut(&pid) {
+                    p.mailbox_limit = if max == 0 {
+                        None
+                    } else {
+                        Some(MailboxLimit {
+                            max: max as usize,
+                            policy,
+                        })
+                    };
+                }
+                ExecResult::Continue(1)
+            }
+
             // ========== Arithmetic ==========
             Instruction::LoadInt { value, dest } => {
                 if let Some(p) = self.processes.get_mut(&pid) {
@@ -1129,6 +1365,8 @@ impl Scheduler {
                     child.registers[i] = arg;
                 }
 
+                child.mailbox_limit = self.default_mailbox_limit;
+
                 self.processes.insert(child_pid, child);
                 self.ready_queue.push_back(child_pid);
 
@@ -1194,6 +1432,8 @@ impl Scheduler {
                 // Establish bidirectional link
                 child.links.push(pid);
 
+                child.mailbox_limit = self.default_mailbox_limit;
+
                 self.processes.insert(child_pid, child);
                 self.ready_queue.push_back(child_pid);
 
@@ -1322,9 +1562,9 @@ impl Scheduler {
                 };
 
                 // Check for timeout first
-                if process.timeout == Some(0) {
+                if process.timed_out {
                     if let Some(p) = self.processes.get_mut(&pid) {
-                        p.timeout = None;
+                        p.timed_out = false;
                     }
                     return ExecResult::Jump(timeout_target, 1);
                 }
@@ -1343,6 +1583,7 @@ impl Scheduler {
                             if let Some(p) = self.processes.get_mut(&pid) {
                                 p.mailbox.remove(i);
                                 p.timeout = None;
+                                p.timed_out = false;
                                 for (reg, val) in bindings {
                                     p.registers[reg.0 as usize] = val;
                                 }
@@ -1352,10 +1593,18 @@ impl Scheduler {
                     }
                 }
 
-                // No match found - set timeout and wait
+                // No match found - set the deadline and wait. A message that
+                // doesn't match can wake the process after the deadline has
+                // passed, and then the receive times out here instead
+                let now = self.clock;
                 if let Some(p) = self.processes.get_mut(&pid) {
-                    if p.timeout.is_none() {
-                        p.timeout = timeout.clone();
+                    match p.timeout {
+                        Some(deadline) if deadline <= now => {
+                            p.timeout = None;
+                            return ExecResult::Jump(timeout_target, 1);
+                        }
+                        Some(_) => {}
+                        None => p.timeout = timeout.map(|t| now + t as u64),
                     }
                 }
                 ExecResult::Wait
@@ -2223,8 +2472,7 @@ impl Scheduler {
 
             // ========== References ==========
             Instruction::MakeRef { dest } => {
-                let ref_id = self.next_ref;
-                self.next_ref += 1;
+                let ref_id = self.make_ref();
                 let Some(process) = self.processes.get_mut(&pid) else {
                     return ExecResult::Crash;
                 };
@@ -2380,17 +2628,7 @@ impl Scheduler {
                 };
                 let message = process.registers[msg.0 as usize].clone();
 
-                // Create timer reference
-                let timer_ref = self.next_ref;
-                self.next_ref += 1;
-
-                // Add timer to queue
-                self.timers.push(Timer {
-                    timer_ref,
-                    target: target_pid,
-                    message,
-                    remaining: delay,
-                });
+                let timer_ref = self.add_timer(target_pid, message, delay, None);
 
                 // Store timer ref in dest register
                 let Some(process) = self.processes.get_mut(&pid) else {
@@ -2400,6 +2638,24 @@ impl Scheduler {
                 ExecResult::Continue(1)
             }
 
+            Instruction::SendInterval { interval, to, msg, dest } => {
+                let Some(process) = self.processes.get(&pid) else {
+                    return ExecResult::Crash;
+                };
+                let Some(target_pid) = self.resolve_pid(pid, &to) else {
+                    return ExecResult::Crash;
+                };
+                let message = process.registers[msg.0 as usize].clone();
+
+                let timer_ref = self.add_timer(target_pid, message, interval, Some(interval));
+
+                let Some(process) = self.processes.get_mut(&pid) else {
+                    return ExecResult::Crash;
+                };
+                process.registers[dest.0 as usize] = Value::Ref(timer_ref);
+                ExecResult::Continue(1)
+            }
+
             Instruction::StartTimer { delay, msg, dest } => {
                 let Some(process) = self.processes.get(&pid) else {
                     return ExecResult::Crash;
@@ -2407,8 +2663,7 @@ impl Scheduler {
                 let message_value = process.registers[msg.0 as usize].clone();
 
                 // Create timer reference
-                let timer_ref = self.next_ref;
-                self.next_ref += 1;
+                let timer_ref = self.make_ref();
 
                 // Build {:timeout, ref, msg} tuple
                 let timeout_msg = Value::Tuple(vec![
@@ -2422,7 +2677,8 @@ impl Scheduler {
                     timer_ref,
                     target: pid,
                     message: timeout_msg,
-                    remaining: delay,
+                    deadline: self.clock + delay as u64,
+                    interval: None,
                 });
 
                 // Store timer ref in dest register
@@ -2444,7 +2700,7 @@ impl Scheduler {
                 // Find and remove the timer
                 let result = if let Some(idx) = self.timers.iter().position(|t| t.timer_ref == ref_id) {
                     let timer = self.timers.remove(idx);
-                    Value::Int(timer.remaining as i64)
+                    Value::Int(self.timer_remaining(&timer))
                 } else {
                     // Timer already fired or doesn't exist
                     Value::Atom("ok".to_string())
@@ -2470,7 +2726,7 @@ impl Scheduler {
                     .timers
                     .iter()
                     .find(|t| t.timer_ref == ref_id)
-                    .map(|t| Value::Int(t.remaining as i64))
+                    .map(|t| Value::Int(self.timer_remaining(t)))
                     .unwrap_or(Value::Int(0));
 
                 let Some(process) = self.processes.get_mut(&pid) else {
@@ -2691,6 +2947,53 @@ impl Scheduler {
                 ExecResult::Continue(1)
             }
 
+            Instruction::ProcessInfoItem {
+                pid: target_pid,
+                item,
+                dest,
+            } => {
+                let Some(process) = self.processes.get(&pid) else {
+                    return ExecResult::Crash;
+                };
+                let target = match &process.registers[target_pid.0 as usize] {
+                    Value::Pid(p) => *p,
+                    _ => return ExecResult::Crash,
+                };
+                let value = match self.processes.get(&target) {
+                    Some(p) if !matches!(p.status, ProcessStatus::Done | ProcessStatus::Crashed) => {
+                        match item.as_str() {
+                            "message_queue_len" => Some(Value::Int(p.mailbox.len() as i64)),
+                            "reductions" => Some(Value::Int(p.reductions as i64)),
+                            "status" => Some(Value::Atom(
+                                if p.status == ProcessStatus::Waiting {
+                                    "waiting"
+                                } else {
+                                    "runnable"
+                                }
+                                .to_string(),
+                            )),
+                            "trap_exit" => Some(Value::Atom(p.trap_exit.to_string())),
+                            "label" => Some(match &p.label {
+                                Some(label) => Value::String(label.clone()),
+                                None => Value::Atom("undefined".to_string()),
+                            }),
+                            "priority" => Some(Value::Atom(p.priority.as_str().to_string())),
+                            _ => return ExecResult::Exit(Value::Atom("badarg".to_string())),
+                        }
+                    }
+                    _ => None,
+                };
+                let info = match value {
+                    Some(v) => Value::Tuple(vec![Value::Atom(item), v]),
+                    None => Value::Atom("undefined".to_string()),
+                };
+                let Some(process) = self.processes.get_mut(&pid) else {
+                    return ExecResult::Crash;
+                };
+                process.registers[dest.0 as usize] = info;
+                ExecResult::Continue(1)
+            }
+
             Instruction::ModuleList { dest } => {
                 let modules: Vec<Value> = self
                     .modules
@@ -7645,6 +7948,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_refs_unique_across_schedulers() {
+        let mut a = Scheduler::new();
+        let mut b = Scheduler::new();
+
+        // Both counters start at zero; the creation keeps the refs apart
+        let (ra, rb) = (a.make_ref(), b.make_ref());
+        assert_eq!(ra.id, rb.id);
+        assert_ne!(ra, rb);
+        assert_eq!(
+            format!("{:?}", Value::Ref(ra)),
+            format!("#Ref<0.{}.0>", a.creation)
+        );
+    }
+
     #[test]
     fn test_is_ref() {
         let mut scheduler = Scheduler::new();
@@ -8637,50 +8955,25 @@ mod tests {
         assert!(matches!(process.registers[6], Value::Ref(_)));
     }
 
-    // ========== Try/Catch/After Tests ==========
-
     #[test]
-    fn test_try_catch_basic() {
-        // Basic try/catch - throw is caught
+    fn test_send_interval_repeats() {
+        // SendInterval keeps delivering until the target exits
         let mut scheduler = Scheduler::new();
 
-        // try { throw(:error, :oops) } catch { handle }
         let program = vec![
-            // 0: Try with catch at 5
-            Instruction::Try {
-                catch_target: 5,
-                after_target: None,
-            },
-            // 1: Load :error class
             Instruction::LoadAtom {
-                name: "error".to_string(),
+                name: "tick".to_string(),
                 dest: Register(0),
             },
-            // 2: Load :oops reason
-            Instruction::LoadAtom {
-                name: "oops".to_string(),
+            Instruction::SendInterval {
+                interval: 10,
+                to: Source::Self_,
+                msg: Register(0),
                 dest: Register(1),
             },
-            // 3: Throw
-            Instruction::Throw {
-                class: Register(0),
-                reason: Register(1),
-            },
-            // 4: Should not reach here
-            Instruction::LoadInt {
-                value: 999,
-                dest: Register(2),
-            },
-            // 5: Catch handler - get exception
-            Instruction::GetException { dest: Register(3) },
-            // 6: Clear exception
-            Instruction::ClearException,
-            // 7: Mark that we caught it
-            Instruction::LoadAtom {
-                name: "caught".to_string(),
-                dest: Register(4),
-            },
-            // 8: End
+            Instruction::Receive { dest: Register(2) },
+            Instruction::Receive { dest: Register(3) },
+            Instruction::Receive { dest: Register(4) },
             Instruction::End,
         ];
 
@@ -8689,52 +8982,44 @@ mod tests {
 
         let process = scheduler.processes.get(&Pid(0)).unwrap();
         assert_eq!(process.status, ProcessStatus::Done);
-
-        // R2 should NOT be 999 (we skipped that instruction)
-        assert_ne!(process.registers[2], Value::Int(999));
-
-        // R4 should be :caught
-        assert_eq!(process.registers[4], Value::Atom("caught".to_string()));
-
-        // R3 should have the exception tuple {class, reason, stacktrace}
-        match &process.registers[3] {
-            Value::Tuple(elems) => {
-                assert_eq!(elems.len(), 3);
-                assert_eq!(elems[0], Value::Atom("error".to_string()));
-                assert_eq!(elems[1], Value::Atom("oops".to_string()));
+        assert!(matches!(process.registers[1], Value::Ref(_)));
+        for reg in 2..=4 {
+            match &process.registers[reg] {
+                Value::String(s) => assert!(s.contains("tick")),
+                other => panic!("Expected tick message, got {:?}", other),
             }
-            _ => panic!("Expected exception tuple, got {:?}", process.registers[3]),
         }
+
+        // Interval timers for exited processes are dropped
+        assert!(scheduler.timers.is_empty());
     }
 
     #[test]
-    fn test_try_no_exception() {
-        // Try block completes without exception
+    fn test_cancel_interval() {
+        // CancelTimer stops an interval timer after it has fired
         let mut scheduler = Scheduler::new();
 
         let program = vec![
-            // 0: Try with catch at 4
-            Instruction::Try {
-                catch_target: 4,
-                after_target: None,
-            },
-            // 1: Normal work (no throw)
-            Instruction::LoadInt {
-                value: 42,
+            Instruction::LoadAtom {
+                name: "tick".to_string(),
                 dest: Register(0),
             },
-            // 2: EndTry
-            Instruction::EndTry,
-            // 3: Jump to end (skip catch)
-            Instruction::Jump { target: 6 },
-            // 4: Catch handler (should not run)
-            Instruction::LoadAtom {
-                name: "caught".to_string(),
+            Instruction::SendInterval {
+                interval: 10,
+                to: Source::Self_,
+                msg: Register(0),
                 dest: Register(1),
             },
-            // 5: Jump to end
-            Instruction::Jump { target: 6 },
-            // 6: End
+            Instruction::Receive { dest: Register(2) },
+            Instruction::CancelTimer {
+                timer_ref: Register(1),
+                dest: Register(3),
+            },
+            // Nothing else should arrive
+            Instruction::ReceiveTimeout {
+                dest: Register(4),
+                timeout: 50,
+            },
             Instruction::End,
         ];
 
@@ -8743,42 +9028,254 @@ mod tests {
 
         let process = scheduler.processes.get(&Pid(0)).unwrap();
         assert_eq!(process.status, ProcessStatus::Done);
-
-        // R0 should be 42
-        assert_eq!(process.registers[0], Value::Int(42));
-
-        // R1 should NOT be :caught (catch didn't run)
-        assert_ne!(process.registers[1], Value::Atom("caught".to_string()));
+        assert!(matches!(process.registers[3], Value::Int(_)));
+        assert_eq!(process.registers[4], Value::String("TIMEOUT".to_string()));
+        assert!(scheduler.timers.is_empty());
     }
 
     #[test]
-    fn test_throw_without_catch() {
-        // Throw without handler crashes process
+    fn test_receive_timeout_uses_scheduler_clock() {
+        // Receive timeouts and timers share the scheduler clock:
+        // a timer due before the receive deadline is delivered first.
         let mut scheduler = Scheduler::new();
 
         let program = vec![
             Instruction::LoadAtom {
-                name: "throw".to_string(),
+                name: "ping".to_string(),
                 dest: Register(0),
             },
-            Instruction::LoadAtom {
-                name: "uncaught".to_string(),
+            Instruction::SendAfter {
+                delay: 20,
+                to: Source::Self_,
+                msg: Register(0),
                 dest: Register(1),
             },
-            Instruction::Throw {
-                class: Register(0),
-                reason: Register(1),
+            Instruction::ReceiveTimeout {
+                dest: Register(2),
+                timeout: 30,
             },
             Instruction::End,
         ];
 
         scheduler.spawn(program);
-        run_to_idle(&mut scheduler);
+        while scheduler.step(5) == StepResult::Busy {}
 
         let process = scheduler.processes.get(&Pid(0)).unwrap();
-        assert_eq!(process.status, ProcessStatus::Crashed);
+        match &process.registers[2] {
+            Value::String(s) => assert!(s.contains("ping")),
+            other => panic!("Expected timer message, got {:?}", other),
+        }
+    }
 
-        // Exit reason should be tuple of {class, reason}
+    #[test]
+    fn test_receive_timeout_expires_on_scheduler_clock() {
+        // Nothing else runs, so the clock skips straight to the deadline
+        // rather than stepping through the idle reductions
+        let mut scheduler = Scheduler::new();
+
+        let program = vec![
+            Instruction::ReceiveTimeout {
+                dest: Register(0),
+                timeout: 30,
+            },
+            Instruction::End,
+        ];
+
+        scheduler.spawn(program);
+        let mut steps = 0;
+        while scheduler.step(5) == StepResult::Busy {
+            steps += 1;
+        }
+
+        let process = scheduler.processes.get(&Pid(0)).unwrap();
+        assert_eq!(process.registers[0], Value::String("TIMEOUT".to_string()));
+        assert!(steps <= 3, "timeout took {} steps", steps);
+        assert!(scheduler.clock >= 30);
+    }
+
+    #[test]
+    fn test_receive_match_times_out_despite_unmatched_messages() {
+        // An interval wakes the process more often than the receive
+        // deadline, but none of its messages match
+        let mut scheduler = Scheduler::new();
+
+        let program = vec![
+            Instruction::LoadAtom {
+                name: "tick".to_string(),
+                dest: Register(0),
+            },
+            Instruction::SendInterval {
+                interval: 1,
+                to: Source::Self_,
+                msg: Register(0),
+                dest: Register(1),
+            },
+            Instruction::ReceiveMatch {
+                clauses: vec![(Pattern::Atom("never".to_string()), 4)],
+                timeout: Some(50),
+                timeout_target: 3,
+            },
+            Instruction::CancelTimer {
+                timer_ref: Register(1),
+                dest: Register(2),
+            },
+            Instruction::End,
+        ];
+
+        scheduler.spawn(program);
+        for _ in 0..1000 {
+            if scheduler.step(5) == StepResult::Idle {
+                break;
+            }
+        }
+
+        let process = scheduler.processes.get(&Pid(0)).unwrap();
+        assert_eq!(process.status, ProcessStatus::Done);
+        assert!(!process.mailbox.is_empty());
+    }
+
+    // ========== Try/Catch/After Tests ==========
+
+    #[test]
+    fn test_try_catch_basic() {
+        // Basic try/catch - throw is caught
+        let mut scheduler = Scheduler::new();
+
+        // try { throw(:error, :oops) } catch { handle }
+        let program = vec![
+            // 0: Try with catch at 5
+            Instruction::Try {
+                catch_target: 5,
+                after_target: None,
+            },
+            // 1: Load :error class
+            Instruction::LoadAtom {
+                name: "error".to_string(),
+                dest: Register(0),
+            },
+            // 2: Load :oops reason
+            Instruction::LoadAtom {
+                name: "oops".to_string(),
+                dest: Register(1),
+            },
+            // 3: Throw
+            Instruction::Throw {
+                class: Register(0),
+                reason: Register(1),
+            },
+            // 4: Should not reach here
+            Instruction::LoadInt {
+                value: 999,
+                dest: Register(2),
+            },
+            // 5: Catch handler - get exception
+            Instruction::GetException { dest: Register(3) },
+            // 6: Clear exception
+            Instruction::ClearException,
+            // 7: Mark that we caught it
+            Instruction::LoadAtom {
+                name: "caught".to_string(),
+                dest: Register(4),
+            },
+            // 8: End
+            Instruction::End,
+        ];
+
+        scheduler.spawn(program);
+        run_to_idle(&mut scheduler);
+
+        let process = scheduler.processes.get(&Pid(0)).unwrap();
+        assert_eq!(process.status, ProcessStatus::Done);
+
+        // R2 should NOT be 999 (we skipped that instruction)
+        assert_ne!(process.registers[2], Value::Int(999));
+
+        // R4 should be :caught
+        assert_eq!(process.registers[4], Value::Atom("caught".to_string()));
+
+        // R3 should have the exception tuple {class, reason, stacktrace}
+        match &process.registers[3] {
+            Value::Tuple(elems) => {
+                assert_eq!(elems.len(), 3);
+                assert_eq!(elems[0], Value::Atom("error".to_string()));
+                assert_eq!(elems[1], Value::Atom("oops".to_string()));
+            }
+            _ => panic!("Expected exception tuple, got {:?}", process.registers[3]),
+        }
+    }
+
+    #[test]
+    fn test_try_no_exception() {
+        // Try block completes without exception
+        let mut scheduler = Scheduler::new();
+
+        let program = vec![
+            // 0: Try with catch at 4
+            Instruction::Try {
+                catch_target: 4,
+                after_target: None,
+            },
+            // 1: Normal work (no throw)
+            Instruction::LoadInt {
+                value: 42,
+                dest: Register(0),
+            },
+            // 2: EndTry
+            Instruction::EndTry,
+            // 3: Jump to end (skip catch)
+            Instruction::Jump { target: 6 },
+            // 4: Catch handler (should not run)
+            Instruction::LoadAtom {
+                name: "caught".to_string(),
+                dest: Register(1),
+            },
+            // 5: Jump to end
+            Instruction::Jump { target: 6 },
+            // 6: End
+            Instruction::End,
+        ];
+
+        scheduler.spawn(program);
+        run_to_idle(&mut scheduler);
+
+        let process = scheduler.processes.get(&Pid(0)).unwrap();
+        assert_eq!(process.status, ProcessStatus::Done);
+
+        // R0 should be 42
+        assert_eq!(process.registers[0], Value::Int(42));
+
+        // R1 should NOT be :caught (catch didn't run)
+        assert_ne!(process.registers[1], Value::Atom("caught".to_string()));
+    }
+
+    #[test]
+    fn test_throw_without_catch() {
+        // Throw without handler crashes process
+        let mut scheduler = Scheduler::new();
+
+        let program = vec![
+            Instruction::LoadAtom {
+                name: "throw".to_string(),
+                dest: Register(0),
+            },
+            Instruction::LoadAtom {
+                name: "uncaught".to_string(),
+                dest: Register(1),
+            },
+            Instruction::Throw {
+                class: Register(0),
+                reason: Register(1),
+            },
+            Instruction::End,
+        ];
+
+        scheduler.spawn(program);
+        run_to_idle(&mut scheduler);
+
+        let process = scheduler.processes.get(&Pid(0)).unwrap();
+        assert_eq!(process.status, ProcessStatus::Crashed);
+
+        // Exit reason should be tuple of {class, reason}
         match &process.exit_reason {
             Value::Tuple(elems) => {
                 assert_eq!(elems.len(), 2);
@@ -10242,4 +10739,430 @@ mod tests {
         assert_eq!(process.registers[2], Value::Int(0x3456));
         assert_eq!(process.registers[3], Value::Int(0x78));
     }
+
+    // ========== Preemption Tests ==========
+
+    fn spinner() -> Vec<Instruction> {
+        vec![Instruction::Jump { target: 0 }]
+    }
+
+    #[test]
+    fn test_spinning_process_is_preempted() {
+        let mut scheduler = Scheduler::new();
+        let spin = scheduler.spawn(spinner());
+        let worker = scheduler.spawn(vec![Instruction::End]);
+
+        // One large step: the spinner only gets a slice before the worker runs
+        scheduler.step(REDUCTIONS_PER_SLICE * 5);
+
+        assert_eq!(scheduler.processes[&worker].status, ProcessStatus::Done);
+        assert_eq!(scheduler.processes[&spin].status, ProcessStatus::Ready);
+    }
+
+    #[test]
+    fn test_reductions_are_counted() {
+        let mut scheduler = Scheduler::new();
+        let spin = scheduler.spawn(spinner());
+
+        scheduler.step(5000);
+
+        assert_eq!(scheduler.processes[&spin].reductions, 5000);
+    }
+
+    #[test]
+    fn test_spinners_share_time_fairly() {
+        let mut scheduler = Scheduler::new();
+        let pids: Vec<Pid> = (0..3).map(|_| scheduler.spawn(spinner())).collect();
+
+        for _ in 0..30 {
+            scheduler.step(1000);
+        }
+
+        let counts: Vec<u64> = pids
+            .iter()
+            .map(|p| scheduler.processes[p].reductions)
+            .collect();
+        let min = *counts.iter().min().unwrap();
+        let max = *counts.iter().max().unwrap();
+        assert!(min > 0, "a spinner was starved: {:?}", counts);
+        assert!(
+            max - min <= REDUCTIONS_PER_SLICE as u64,
+            "unfair split: {:?}",
+            counts
+        );
+    }
+
+    // ========== Mailbox Limit Tests ==========
+
+    /// Receiver that bounds its mailbox, then reads up to three messages
+    fn bounded_receiver(policy: OverflowPolicy) -> Vec<Instruction> {
+        vec![
+            Instruction::SetMailboxLimit { max: 2, policy },
+            Instruction::Receive { dest: Register(1) },
+            Instruction::Receive { dest: Register(2) },
+            Instruction::ReceiveTimeout {
+                dest: Register(3),
+                timeout: 500,
+            },
+            Instruction::End,
+        ]
+    }
+
+    fn send_three(to: Pid) -> Vec<Instruction> {
+        ["one", "two", "three"]
+            .iter()
+            .map(|msg| Instruction::Send {
+                to: Source::Pid(to),
+                msg: msg.to_string(),
+            })
+            .chain(std::iter::once(Instruction::End))
+            .collect()
+    }
+
+    fn received(scheduler: &Scheduler, pid: Pid) -> Vec<Value> {
+        let process = &scheduler.processes[&pid];
+        (1..=3).map(|r| process.registers[r].clone()).collect()
+    }
+
+    fn strings(items: &[&str]) -> Vec<Value> {
+        items.iter().map(|s| Value::String(s.to_string())).collect()
+    }
+
+    #[test]
+    fn test_mailbox_limit_drop_newest() {
+        let mut scheduler = Scheduler::new();
+        let receiver = scheduler.spawn(bounded_receiver(OverflowPolicy::DropNewest));
+        let sender = scheduler.spawn(send_three(receiver));
+        run_to_idle(&mut scheduler);
+
+        assert_eq!(scheduler.processes[&sender].status, ProcessStatus::Done);
+        assert_eq!(
+            received(&scheduler, receiver),
+            strings(&["one", "two", "TIMEOUT"])
+        );
+    }
+
+    #[test]
+    fn test_mailbox_limit_drop_oldest() {
+        let mut scheduler = Scheduler::new();
+        let receiver = scheduler.spawn(bounded_receiver(OverflowPolicy::DropOldest));
+        let sender = scheduler.spawn(send_three(receiver));
+        run_to_idle(&mut scheduler);
+
+        assert_eq!(scheduler.processes[&sender].status, ProcessStatus::Done);
+        assert_eq!(
+            received(&scheduler, receiver),
+            strings(&["two", "three", "TIMEOUT"])
+        );
+    }
+
+    #[test]
+    fn test_mailbox_limit_crash_sender() {
+        let mut scheduler = Scheduler::new();
+        let receiver = scheduler.spawn(bounded_receiver(OverflowPolicy::CrashSender));
+        let sender = scheduler.spawn(send_three(receiver));
+        run_to_idle(&mut scheduler);
+
+        let process = &scheduler.processes[&sender];
+        assert_eq!(process.status, ProcessStatus::Crashed);
+        assert_eq!(
+            process.exit_reason,
+            Value::Tuple(vec![
+                Value::Atom("mailbox_full".to_string()),
+                Value::Pid(receiver),
+            ])
+        );
+        assert_eq!(
+            received(&scheduler, receiver),
+            strings(&["one", "two", "TIMEOUT"])
+        );
+    }
+
+    #[test]
+    fn test_mailbox_limit_suspend_sender() {
+        let mut scheduler = Scheduler::new();
+        let receiver = scheduler.spawn(bounded_receiver(OverflowPolicy::SuspendSender));
+        let sender = scheduler.spawn(send_three(receiver));
+
+        // The sender blocks on the third send until the receiver drains
+        scheduler.step(100);
+        assert_eq!(scheduler.processes[&sender].pc, 2);
+        assert_eq!(scheduler.processes[&receiver].mailbox.len(), 0);
+
+        run_to_idle(&mut scheduler);
+
+        assert_eq!(scheduler.processes[&sender].status, ProcessStatus::Done);
+        assert_eq!(
+            received(&scheduler, receiver),
+            strings(&["one", "two", "three"])
+        );
+    }
+
+    #[test]
+    fn test_mailbox_limit_suspend_sender_self_send_crashes() {
+        let mut scheduler = Scheduler::new();
+        let pid = scheduler.spawn(vec![
+            Instruction::SetMailboxLimit {
+                max: 1,
+                policy: OverflowPolicy::SuspendSender,
+            },
+            Instruction::Send {
+                to: Source::Self_,
+                msg: "one".to_string(),
+            },
+            Instruction::Send {
+                to: Source::Self_,
+                msg: "two".to_string(),
+            },
+            Instruction::End,
+        ]);
+        run_to_idle(&mut scheduler);
+
+        let process = &scheduler.processes[&pid];
+        assert_eq!(process.status, ProcessStatus::Crashed);
+        assert_eq!(
+            process.exit_reason,
+            Value::Tuple(vec![
+                Value::Atom("mailbox_full".to_string()),
+                Value::Pid(pid),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_mailbox_limit_applies_to_timer_messages() {
+        let mut scheduler = Scheduler::new();
+        let pid = scheduler.spawn(vec![
+            Instruction::SetMailboxLimit {
+                max: 2,
+                policy: OverflowPolicy::SuspendSender,
+            },
+            Instruction::LoadAtom {
+                name: "tick".to_string(),
+                dest: Register(0),
+            },
+            Instruction::SendInterval {
+                interval: 1,
+                to: Source::Self_,
+                msg: Register(0),
+                dest: Register(1),
+            },
+            Instruction::ReceiveMatch {
+                clauses: vec![],
+                timeout: Some(20),
+                timeout_target: 4,
+            },
+            Instruction::End,
+        ]);
+        run_to_idle(&mut scheduler);
+
+        let process = &scheduler.processes[&pid];
+        assert_eq!(process.status, ProcessStatus::Done);
+        assert_eq!(process.mailbox.len(), 2);
+    }
+
+    #[test]
+    fn test_default_mailbox_limit_applies_to_new_processes() {
+        let mut scheduler = Scheduler::new();
+        let limit = MailboxLimit {
+            max: 10,
+            policy: OverflowPolicy::DropNewest,
+        };
+        scheduler.default_mailbox_limit = Some(limit);
+        let pid = scheduler.spawn(vec![Instruction::End]);
+
+        assert_eq!(scheduler.processes[&pid].mailbox_limit, Some(limit));
+    }
+
+    #[test]
+    fn test_process_info_message_queue_len() {
+        let mut scheduler = Scheduler::new();
+
+        let program = vec![
+            Instruction::SelfPid { dest: Register(0) },
+            Instruction::Send {
+                to: Source::Self_,
+                msg: "a".to_string(),
+            },
+            Instruction::Send {
+                to: Source::Self_,
+                msg: "b".to_string(),
+            },
+            Instruction::ProcessInfoItem {
+                pid: Register(0),
+                item: "message_queue_len".to_string(),
+                dest: Register(1),
+            },
+            Instruction::End,
+        ];
+
+        scheduler.spawn(program);
+        run_to_idle(&mut scheduler);
+
+        let process = scheduler.processes.get(&Pid(0)).unwrap();
+        assert_eq!(
+            process.registers[1],
+            Value::Tuple(vec![
+                Value::Atom("message_queue_len".to_string()),
+                Value::Int(2),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_process_info_item_dead_process_is_undefined() {
+        let mut scheduler = Scheduler::new();
+        let dead = scheduler.spawn(vec![Instruction::End]);
+        run_to_idle(&mut scheduler);
+
+        let program = vec![
+            Instruction::Move {
+                source: Register(5),
+                dest: Register(0),
+            },
+            Instruction::ProcessInfoItem {
+                pid: Register(0),
+                item: "message_queue_len".to_string(),
+                dest: Register(1),
+            },
+            Instruction::End,
+        ];
+        let pid = scheduler.spawn(program);
+        scheduler.processes.get_mut(&pid).unwrap().registers[5] = Value::Pid(dead);
+        run_to_idle(&mut scheduler);
+
+        assert_eq!(
+            scheduler.processes[&pid].registers[1],
+            Value::Atom("undefined".to_string())
+        );
+    }
+
+    // ========== Spawn Option Tests ==========
+
+    #[test]
+    fn test_spawn_opt_links_monitors_and_labels() {
+        let mut scheduler = Scheduler::new();
+
+        let program = vec![
+            Instruction::SpawnOpt {
+                code: vec![Instruction::Work { amount: 1 }, Instruction::End],
+                opts: crate::SpawnOpts {
+                    link: true,
+                    monitor: true,
+                    label: Some("worker".to_string()),
+                    priority: Priority::High,
+                },
+                dest: Register(0),
+            },
+            Instruction::Receive { dest: Register(1) },
+            Instruction::End,
+        ];
+
+        let parent = scheduler.spawn(program);
+        scheduler.step(1);
+
+        let (child, monitor_ref) = match &scheduler.processes[&parent].registers[0] {
+            Value::Tuple(items) => match (&items[0], &items[1]) {
+                (Value::Pid(p), Value::Ref(r)) => (*p, *r),
+                other => panic!("Expected (pid, ref), got {:?}", other),
+            },
+            other => panic!("Expected tuple, got {:?}", other),
+        };
+
+        let child_proc = &scheduler.processes[&child];
+        assert_eq!(child_proc.label.as_deref(), Some("worker"));
+        assert_eq!(child_proc.priority, Priority::High);
+        assert!(child_proc.links.contains(&parent));
+        assert!(child_proc.monitored_by.contains(&(monitor_ref, parent)));
+        assert!(scheduler.processes[&parent].links.contains(&child));
+        assert!(scheduler.processes[&parent]
+            .monitors
+            .contains(&(monitor_ref, child)));
+
+        // The monitor delivers a DOWN message when the child exits
+        run_to_idle(&mut scheduler);
+        assert_eq!(scheduler.processes[&parent].status, ProcessStatus::Done);
+    }
+
+    #[test]
+    fn test_spawn_opt_without_monitor_returns_pid() {
+        let mut scheduler = Scheduler::new();
+
+        let program = vec![
+            Instruction::SpawnOpt {
+                code: vec![Instruction::End],
+                opts: crate::SpawnOpts::default(),
+                dest: Register(0),
+            },
+            Instruction::End,
+        ];
+
+        let parent = scheduler.spawn(program);
+        run_to_idle(&mut scheduler);
+
+        let Value::Pid(child) = scheduler.processes[&parent].registers[0] else {
+            panic!("Expected pid");
+        };
+        assert!(scheduler.processes[&child].links.is_empty());
+        assert_eq!(scheduler.processes[&child].priority, Priority::Normal);
+    }
+
+    #[test]
+    fn test_high_priority_process_runs_first() {
+        let mut scheduler = Scheduler::new();
+        let normal = scheduler.spawn(vec![Instruction::End]);
+        let high = scheduler.spawn(vec![Instruction::End]);
+        scheduler.processes.get_mut(&high).unwrap().priority = Priority::High;
+
+        // A budget of one reduction only lets one process run
+        scheduler.step(1);
+
+        assert_eq!(scheduler.processes[&high].status, ProcessStatus::Done);
+        assert_eq!(scheduler.processes[&normal].status, ProcessStatus::Ready);
+    }
+
+    #[test]
+    fn test_process_info_label_and_priority() {
+        let mut scheduler = Scheduler::new();
+
+        let program = vec![
+            Instruction::SelfPid { dest: Register(0) },
+            Instruction::SetLabel {
+                label: "cache".to_string(),
+            },
+            Instruction::SetPriority {
+                priority: Priority::Low,
+            },
+            Instruction::ProcessInfoItem {
+                pid: Register(0),
+                item: "label".to_string(),
+                dest: Register(1),
+            },
+            Instruction::ProcessInfoItem {
+                pid: Register(0),
+                item: "priority".to_string(),
+                dest: Register(2),
+            },
+            Instruction::End,
+        ];
+
+        scheduler.spawn(program);
+        run_to_idle(&mut scheduler);
+
+        let process = scheduler.processes.get(&Pid(0)).unwrap();
+        assert_eq!(
+            process.registers[1],
+            Value::Tuple(vec![
+                Value::Atom("label".to_string()),
+                Value::String("cache".to_string()),
+            ])
+        );
+        assert_eq!(
+            process.registers[2],
+            Value::Tuple(vec![
+                Value::Atom("priority".to_string()),
+                Value::Atom("low".to_string()),
+            ])
+        );
+    }
 }
@@ -0,0 +1,242 @@
+//! Where build output goes.
+//!
+//! Everything a project build produces lives under its `_build` directory
+//! (the workspace's, for a workspace member):
+//!
+//! ```text
+//! _build/
+//! ├── <profile>/lib/<app>/ebin/   compiled modules, one tree per profile
+//! │                               (dev, release, test, cover, bench, ...)
+//! ├── bindings/                   type stubs generated for dependencies
+//! ├── cover/                      coverage reports (lcov.info, html/)
+//! ├── profiler/                   trace files of `dream profile`
+//! ├── doc/                        generated documentation
+//! ├── test-results/               JUnit and TAP reports of `dream test`
+//! ├── timings/                    reports of `--timings`
+//! └── rel/<app>/                  assembled releases
+//! ```
+//!
+//! The standard library is compiled once per toolchain rather than per
+//! project, into [`TargetLayout::stdlib_dir`].
+
+use crate::config::{ConfigResult, validate_package_name};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the build directory, next to dream.toml.
+pub const BUILD_DIR: &str = "_build";
+
+/// The layout of one `_build` directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetLayout {
+    root: PathBuf,
+}
+
+impl TargetLayout {
+    /// The layout rooted at `build_dir`, usually `<project>/_build`.
+    pub fn new(build_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            root: build_dir.into(),
+        }
+    }
+
+    /// The layout of the `_build` directory in `root`.
+    pub fn for_root(root: &Path) -> Self {
+        Self::new(root.join(BUILD_DIR))
+    }
+
+    /// The `_build` directory itself.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Output of one profile: `_build/<profile>`.
+    pub fn profile_dir(&self, profile: &str) -> PathBuf {
+        self.root.join(profile)
+    }
+
+    /// Compiled applications of one profile: `_build/<profile>/lib`.
+    pub fn lib_dir(&self, profile: &str) -> PathBuf {
+        self.profile_dir(profile).join("lib")
+    }
+
+    /// An application's modules, following Elixir's structure:
+    /// `_build/<profile>/lib/<app>/ebin`.
+    pub fn beam_dir(&self, profile: &str, app: &str) -> PathBuf {
+        self.lib_dir(profile).join(app).join("ebin")
+    }
+
+    /// Type stubs generated from dependencies: `_build/bindings`.
+    pub fn bindings_dir(&self) -> PathBuf {
+        self.root.join("bindings")
+    }
+
+    /// Coverage reports: `_build/cover`.
+    pub fn cover_dir(&self) -> PathBuf {
+        self.root.join("cover")
+    }
+
+    /// Scratch files of `dream profile`: `_build/profiler`.
+    pub fn profiler_dir(&self) -> PathBuf {
+        self.root.join("profiler")
+    }
+
+    /// Generated documentation: `_build/doc`.
+    pub fn doc_dir(&self) -> PathBuf {
+        self.root.join("doc")
+    }
+
+    /// Reports of test runs: `_build/test-results`.
+    pub fn test_results_dir(&self) -> PathBuf {
+        self.root.join("test-results")
+    }
+
+    /// Reports of `--timings`: `_build/timings`.
+    pub fn timings_dir(&self) -> PathBuf {
+        self.root.join("timings")
+    }
+
+    /// An assembled release: `_build/rel/<app>`.
+    pub fn release_dir(&self, app: &str) -> PathBuf {
+        self.root.join("rel").join(app)
+    }
+
+    /// Every directory under `_build` holding output of `app`, for
+    /// removing one package from a shared build directory. `app` has to be
+    /// a package name, so that it can't name a path such as `..`.
+    pub fn app_dirs(&self, app: &str) -> ConfigResult<Vec<PathBuf>> {
+        validate_package_name(app)?;
+        let mut dirs: Vec<PathBuf> = fs::read_dir(&self.root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path().join("lib").join(app))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        dirs.push(self.release_dir(app));
+        dirs.retain(|dir| dir.exists());
+        dirs.sort();
+        Ok(dirs)
+    }
+
+    /// Whether `path` lies inside the `_build` directory, below it rather
+    /// than being it, once `..` and symlinks are resolved. A path that
+    /// doesn't exist is not inside.
+    pub fn contains(&self, path: &Path) -> bool {
+        match (self.root.canonicalize(), path.canonicalize()) {
+            (Ok(root), Ok(path)) => path != root && path.starts_with(root),
+            _ => false,
+        }
+    }
+
+    /// The toolchain's compiled standard library, shared by every project.
+    /// It sits next to the directory of the `dream` executable, so a cargo
+    /// build at `target/debug/dream` uses `target/stdlib`.
+    pub fn stdlib_dir() -> PathBuf {
+        let beside_exe = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join("..").join("stdlib")));
+        match beside_exe {
+            Some(dir) => dir.canonicalize().map(crate::toolchain::plain_path).unwrap_or(dir),
+            None => PathBuf::from("target").join("stdlib"),
+        }
+    }
+}
+
+/// Remove `dir` and everything in it, returning the bytes freed. A missing
+/// directory frees nothing.
+pub fn remove_dir(dir: &Path) -> io::Result<u64> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let size = dir_size(dir);
+    fs::remove_dir_all(dir)?;
+    Ok(size)
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// A byte count with a readable unit, e.g. `1.5 MiB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let layout = TargetLayout::new("/app/_build");
+        assert_eq!(layout.beam_dir("release", "shop"), Path::new("/app/_build/release/lib/shop/ebin"));
+        assert_eq!(layout.bindings_dir(), Path::new("/app/_build/bindings"));
+        assert_eq!(layout.doc_dir(), Path::new("/app/_build/doc"));
+        assert_eq!(layout.test_results_dir(), Path::new("/app/_build/test-results"));
+        assert_eq!(layout.release_dir("shop"), Path::new("/app/_build/rel/shop"));
+    }
+
+    #[test]
+    fn test_app_dirs_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = TargetLayout::new(dir.path());
+        fs::create_dir_all(layout.beam_dir("dev", "web")).unwrap();
+        fs::create_dir_all(layout.beam_dir("test", "web")).unwrap();
+        fs::create_dir_all(layout.beam_dir("dev", "core")).unwrap();
+        fs::create_dir_all(layout.bindings_dir()).unwrap();
+        fs::write(layout.beam_dir("dev", "web").join("web.beam"), [0u8; 100]).unwrap();
+
+        let dirs = layout.app_dirs("web").unwrap();
+        assert_eq!(dirs, vec![layout.lib_dir("dev").join("web"), layout.lib_dir("test").join("web")]);
+
+        assert_eq!(remove_dir(&dirs[0]).unwrap(), 100);
+        assert_eq!(remove_dir(&dirs[0]).unwrap(), 0);
+        assert!(layout.beam_dir("dev", "core").exists());
+    }
+
+    #[test]
+    fn test_app_dirs_stay_in_build_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let layout = TargetLayout::new(dir.path().join("_build"));
+        fs::create_dir_all(layout.beam_dir("dev", "web")).unwrap();
+        fs::write(dir.path().join("dream.toml"), "").unwrap();
+
+        for app in ["..", "../../..", "web/..", "/tmp", "..\\..", ""] {
+            assert!(layout.app_dirs(app).is_err(), "{:?} was accepted", app);
+        }
+        assert!(layout.contains(&layout.lib_dir("dev").join("web")));
+        assert!(!layout.contains(layout.root()));
+        assert!(!layout.contains(&layout.lib_dir("dev").join("../../..")));
+        assert!(!layout.contains(&layout.lib_dir("dev").join("missing")));
+        assert!(dir.path().join("dream.toml").exists());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -0,0 +1,297 @@
+//! Reports of a `dream test` run in formats CI systems read.
+//!
+//! `dream test --report junit` writes JUnit XML, which GitHub Actions,
+//! GitLab, Jenkins and most other CI systems show test by test, and
+//! `--report tap` writes the Test Anything Protocol (version 13). Reports
+//! go to [`TargetLayout::test_results_dir`](crate::target::TargetLayout::test_results_dir),
+//! one file per package.
+
+use crate::markup::escape;
+use crate::output::TestStatus;
+use crate::testing::TestCase;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A report format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// JUnit XML, as written by Surefire and read by most CI systems
+    Junit,
+    /// Test Anything Protocol, version 13
+    Tap,
+}
+
+impl ReportFormat {
+    /// Extension of the report file.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ReportFormat::Junit => "xml",
+            ReportFormat::Tap => "tap",
+        }
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "junit" => Ok(Self::Junit),
+            "tap" => Ok(Self::Tap),
+            _ => Err(format!(
+                "unknown report format `{}` (expected `junit` or `tap`)",
+                s
+            )),
+        }
+    }
+}
+
+/// One finished test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TestResult {
+    test: TestCase,
+    status: TestStatus,
+    /// What the test printed
+    output: String,
+    /// Why it failed
+    error: Option<String>,
+}
+
+/// The results of one package's test run, in the order tests finished.
+#[derive(Debug, Clone, Default)]
+pub struct TestReport {
+    /// The package, which names the run
+    pub package: String,
+    results: Vec<TestResult>,
+    /// `#[ignore]` tests that matched the filter but didn't run
+    skipped: Vec<TestCase>,
+    /// Wall-clock time of the whole run
+    pub duration: Duration,
+}
+
+impl TestReport {
+    pub fn new(package: impl Into<String>) -> Self {
+        Self {
+            package: package.into(),
+            ..Self::default()
+        }
+    }
+
+    pub fn record(&mut self, test: TestCase, status: TestStatus, output: String, error: Option<String>) {
+        self.results.push(TestResult {
+            test,
+            status,
+            output,
+            error,
+        });
+    }
+
+    pub fn skip(&mut self, test: TestCase) {
+        self.skipped.push(test);
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Junit => self.junit(),
+            ReportFormat::Tap => self.tap(),
+        }
+    }
+
+    /// JUnit XML with a `<testsuite>` for each Dream module, in name order.
+    /// Timeouts are failures of type `timeout`.
+    fn junit(&self) -> String {
+        let mut modules: Vec<&str> = self
+            .results
+            .iter()
+            .map(|result| &result.test)
+            .chain(&self.skipped)
+            .map(|test| test.module.as_str())
+            .collect();
+        modules.sort_unstable();
+        modules.dedup();
+
+        let failures = self.results.iter().filter(|r| r.status != TestStatus::Ok).count();
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            out,
+            "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\">",
+            escape(&self.package),
+            self.results.len() + self.skipped.len(),
+            failures,
+            self.skipped.len(),
+            self.duration.as_secs_f64()
+        );
+        for module in modules {
+            let results: Vec<&TestResult> = self.results.iter().filter(|r| r.test.module == module).collect();
+            let skipped: Vec<&TestCase> = self.skipped.iter().filter(|t| t.module == module).collect();
+            let failures = results.iter().filter(|r| r.status != TestStatus::Ok).count();
+            let _ = writeln!(
+                out,
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\">",
+                escape(module),
+                results.len() + skipped.len(),
+                failures,
+                skipped.len()
+            );
+            for result in results {
+                let head = testcase_head(&result.test);
+                let failure = match result.status {
+                    TestStatus::Ok => None,
+                    TestStatus::Failed => Some("failure"),
+                    TestStatus::Timeout => Some("timeout"),
+                };
+                if failure.is_none() && result.output.is_empty() {
+                    let _ = writeln!(out, "    {}/>", head);
+                    continue;
+                }
+                let _ = writeln!(out, "    {}>", head);
+                if let Some(kind) = failure {
+                    let error = result.error.as_deref().unwrap_or_default();
+                    let summary = error.lines().next().unwrap_or_default();
+                    let _ = writeln!(
+                        out,
+                        "      <failure type=\"{}\" message=\"{}\">{}</failure>",
+                        kind,
+                        escape(summary),
+                        escape(error)
+                    );
+                }
+                if !result.output.is_empty() {
+                    let _ = writeln!(out, "      <system-out>{}</system-out>", escape(&result.output));
+                }
+                out.push_str("    </testcase>\n");
+            }
+            for test in skipped {
+                let _ = writeln!(out, "    {}>", testcase_head(test));
+                out.push_str("      <skipped message=\"ignored\"/>\n");
+                out.push_str("    </testcase>\n");
+            }
+            out.push_str("  </testsuite>\n");
+        }
+        out.push_str("</testsuites>\n");
+        out
+    }
+
+    /// TAP, numbering tests in the order they finished. The failure and
+    /// the test's output go in a YAML block under a failing test.
+    fn tap(&self) -> String {
+        let mut out = String::from("TAP version 13\n");
+        let _ = writeln!(out, "1..{}", self.results.len() + self.skipped.len());
+        for (index, result) in self.results.iter().enumerate() {
+            let number = index + 1;
+            if result.status == TestStatus::Ok {
+                let _ = writeln!(out, "ok {} - {}", number, result.test.name());
+                continue;
+            }
+            let _ = writeln!(out, "not ok {} - {}", number, result.test.name());
+            out.push_str("  ---\n");
+            if result.status == TestStatus::Timeout {
+                out.push_str("  severity: timeout\n");
+            }
+            if let Some(error) = &result.error {
+                yaml_block(&mut out, "message", error);
+            }
+            if !result.output.is_empty() {
+                yaml_block(&mut out, "output", &result.output);
+            }
+            out.push_str("  ...\n");
+        }
+        for (index, test) in self.skipped.iter().enumerate() {
+            let number = self.results.len() + index + 1;
+            let _ = writeln!(out, "ok {} - {} # SKIP ignored", number, test.name());
+        }
+        out
+    }
+}
+
+/// `<testcase ...` for `test`, left open.
+fn testcase_head(test: &TestCase) -> String {
+    format!(
+        "<testcase name=\"{}\" classname=\"{}\"",
+        escape(&test.function),
+        escape(&test.module)
+    )
+}
+
+/// `key: |` and `text` as a literal block, in a TAP YAML block.
+fn yaml_block(out: &mut String, key: &str, text: &str) {
+    let _ = writeln!(out, "  {}: |", key);
+    for line in text.lines() {
+        let _ = writeln!(out, "    {}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> TestReport {
+        let mut report = TestReport::new("app");
+        report.duration = Duration::from_millis(1500);
+        report.record(TestCase::new("app::tests", "test_add"), TestStatus::Ok, String::new(), None);
+        report.record(
+            TestCase::new("app::tests", "test_parse"),
+            TestStatus::Failed,
+            "parsing <input>\n".to_string(),
+            Some("assert_eq!(x, 2) failed at app::tests:12:5\n  left:  1".to_string()),
+        );
+        report.record(
+            TestCase::new("app", "test_slow"),
+            TestStatus::Timeout,
+            String::new(),
+            Some("timed out after 60s".to_string()),
+        );
+        report.skip(TestCase::new("app::tests", "test_db"));
+        report
+    }
+
+    #[test]
+    fn test_junit() {
+        let xml = report().render(ReportFormat::Junit);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"app\" tests=\"4\" failures=\"2\" errors=\"0\" skipped=\"1\" time=\"1.500\">\n"));
+        assert!(xml.contains(
+            "  <testsuite name=\"app\" tests=\"1\" failures=\"1\" errors=\"0\" skipped=\"0\">\n    \
+             <testcase name=\"test_slow\" classname=\"app\">\n      \
+             <failure type=\"timeout\" message=\"timed out after 60s\">timed out after 60s</failure>\n"
+        ));
+        assert!(xml.contains("<testcase name=\"test_add\" classname=\"app::tests\"/>"));
+        assert!(xml.contains("message=\"assert_eq!(x, 2) failed at app::tests:12:5\">"));
+        assert!(xml.contains("<system-out>parsing &lt;input&gt;\n</system-out>"));
+        assert!(xml.contains("<testcase name=\"test_db\" classname=\"app::tests\">\n      <skipped message=\"ignored\"/>"));
+        assert!(xml.ends_with("  </testsuite>\n</testsuites>\n"));
+    }
+
+    #[test]
+    fn test_tap() {
+        let tap = report().render(ReportFormat::Tap);
+        assert_eq!(
+            tap,
+            "TAP version 13\n\
+             1..4\n\
+             ok 1 - app::tests::test_add\n\
+             not ok 2 - app::tests::test_parse\n  \
+             ---\n  \
+             message: |\n    \
+             assert_eq!(x, 2) failed at app::tests:12:5\n      \
+             left:  1\n  \
+             output: |\n    \
+             parsing <input>\n  \
+             ...\n\
+             not ok 3 - app::test_slow\n  \
+             ---\n  \
+             severity: timeout\n  \
+             message: |\n    \
+             timed out after 60s\n  \
+             ...\n\
+             ok 4 - app::tests::test_db # SKIP ignored\n"
+        );
+    }
+
+    #[test]
+    fn test_report_format() {
+        assert_eq!("junit".parse::<ReportFormat>(), Ok(ReportFormat::Junit));
+        assert_eq!(ReportFormat::Tap.extension(), "tap");
+        assert!("xunit".parse::<ReportFormat>().unwrap_err().contains("expected `junit` or `tap`"));
+    }
+}
@@ -0,0 +1,883 @@
+//! Test discovery and the BEAM-side test runner used by `dream test`.
+//!
+//! Every `#[test]` function that survives cfg evaluation becomes a
+//! [`TestCase`], and a [`TestFilter`] picks the ones to run by name, tag
+//! and `#[ignore]`. All selected tests run in a single BEAM node, several
+//! at a time, each in its own process so a crash, a stray linked exit or a
+//! hang only fails that test. The runner reports each result as a marker
+//! line on stdout, which [`ResultParser`] turns back into [`TestEvent`]s
+//! while the node runs.
+//!
+//! Code examples in doc comments are tests too: [`doctest_module`] turns a
+//! module's examples into a hidden `#[test]` module compiled alongside it.
+
+use crate::compiler::{cfg, Attribute, Item, Module, Parser};
+use crate::config::CompileOptions;
+use std::time::Duration;
+
+/// Prefix of the runner's protocol lines.
+const MARKER: &str = "##dream-test##";
+
+/// A `#[test]` function.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TestCase {
+    /// Dream module name, e.g. `my_app::tests`
+    pub module: String,
+    pub function: String,
+    /// Marked `#[ignore]`
+    pub ignored: bool,
+    /// Tags from `#[tag(...)]`
+    pub tags: Vec<String>,
+}
+
+impl TestCase {
+    pub fn new(module: impl Into<String>, function: impl Into<String>) -> Self {
+        Self {
+            module: module.into(),
+            function: function.into(),
+            ignored: false,
+            tags: Vec::new(),
+        }
+    }
+
+    /// The BEAM module the test compiles into.
+    pub fn beam_module(&self) -> String {
+        if self.module.starts_with("dream::") {
+            self.module.clone()
+        } else {
+            format!("dream::{}", self.module)
+        }
+    }
+
+    /// Display name, e.g. `my_app::tests::test_add`.
+    pub fn name(&self) -> String {
+        format!("{}::{}", self.module, self.function)
+    }
+}
+
+/// Find the test functions in `modules` that are compiled in under
+/// `options`, sorted by name.
+pub fn discover(modules: &[Module], options: &CompileOptions) -> Vec<TestCase> {
+    discover_marked(modules, options, cfg::is_test)
+}
+
+/// Like [`discover`], for functions carrying some other marker attribute
+/// (`#[bench]`, say).
+pub(crate) fn discover_marked(
+    modules: &[Module],
+    options: &CompileOptions,
+    marked: fn(&[Attribute]) -> bool,
+) -> Vec<TestCase> {
+    let mut tests: Vec<TestCase> = modules
+        .iter()
+        .filter(|m| cfg::should_include(&m.attrs, options))
+        .flat_map(|m| {
+            m.items.iter().filter_map(move |item| match item {
+                Item::Function(func)
+                    if marked(&func.attrs) && cfg::should_include(&func.attrs, options) =>
+                {
+                    Some(TestCase {
+                        ignored: cfg::is_ignored(&func.attrs),
+                        tags: cfg::test_tags(&func.attrs),
+                        ..TestCase::new(m.name.clone(), func.name.clone())
+                    })
+                }
+                _ => None,
+            })
+        })
+        .collect();
+    tests.sort();
+    tests
+}
+
+/// Which discovered tests to run.
+#[derive(Debug, Clone, Default)]
+pub struct TestFilter {
+    /// Substring of the full test name, e.g. `parser::` or `test_add`
+    pub pattern: Option<String>,
+    /// Only run tests with at least one of these tags
+    pub tags: Vec<String>,
+    /// Skip tests with any of these tags
+    pub exclude_tags: Vec<String>,
+    /// Run only the `#[ignore]` tests
+    pub ignored: bool,
+    /// Run `#[ignore]` tests along with the rest
+    pub include_ignored: bool,
+}
+
+impl TestFilter {
+    /// Whether a test matches the name and tag filters, regardless of
+    /// `#[ignore]`.
+    pub fn matches(&self, test: &TestCase) -> bool {
+        self.pattern
+            .as_deref()
+            .is_none_or(|pattern| test.name().contains(pattern))
+            && (self.tags.is_empty() || self.tags.iter().any(|tag| test.tags.contains(tag)))
+            && !self.exclude_tags.iter().any(|tag| test.tags.contains(tag))
+    }
+
+    /// Split `tests` into those to run and the number of matching tests
+    /// skipped because they are ignored.
+    pub fn select(&self, tests: Vec<TestCase>) -> (Vec<TestCase>, usize) {
+        let mut run = Vec::new();
+        let mut skipped = 0;
+        for test in tests.into_iter().filter(|t| self.matches(t)) {
+            let wanted = if self.ignored {
+                test.ignored
+            } else {
+                self.include_ignored || !test.ignored
+            };
+            if wanted {
+                run.push(test);
+            } else if test.ignored {
+                skipped += 1;
+            }
+        }
+        (run, skipped)
+    }
+}
+
+/// A fenced code example from a doc comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Doctest {
+    /// Line of the opening fence, 1-based
+    pub line: usize,
+    /// The item the doc comment documents, if it is on one
+    pub item: Option<String>,
+    pub code: String,
+    /// Compile the example but don't run it (```` ```no_run ````)
+    pub no_run: bool,
+}
+
+impl Doctest {
+    /// Name of the generated function, e.g. `doctest_add_12`.
+    pub fn function_name(&self) -> String {
+        match &self.item {
+            Some(item) => format!("doctest_{}_{}", item, self.line),
+            None => format!("doctest_{}", self.line),
+        }
+    }
+}
+
+/// Collect the code examples in the `///` and `//!` comments of `source`.
+///
+/// Fences without a language, or marked `dream` or `rust`, are examples.
+/// `ignore` skips one, `no_run` only compiles it, and any other language
+/// (`text`, `erlang`, ...) is not Dream code. As in rustdoc, a line
+/// starting with `# ` is part of the example but hidden from readers.
+pub fn extract_doctests(source: &str) -> Vec<Doctest> {
+    let mut doctests = Vec::new();
+    // (line, code lines, no_run, inner) of the fence being read
+    let mut open: Option<(usize, Vec<String>, bool, bool)> = None;
+    // Fences closed in the current comment, waiting for the item below it
+    let mut pending: Vec<Doctest> = Vec::new();
+    let mut skipping = false;
+
+    for (index, raw) in source.lines().enumerate() {
+        let trimmed = raw.trim_start();
+        let inner = trimmed.starts_with("//!");
+        let doc = trimmed
+            .strip_prefix("///")
+            .or_else(|| trimmed.strip_prefix("//!"));
+
+        let Some(doc) = doc else {
+            // First line after the comment: find what it documents
+            if !pending.is_empty() && !trimmed.is_empty() && !trimmed.starts_with("#[") {
+                let item = documented_item(trimmed);
+                for mut doctest in pending.drain(..) {
+                    doctest.item = item.clone();
+                    doctests.push(doctest);
+                }
+            }
+            continue;
+        };
+        let doc = doc.strip_prefix(' ').unwrap_or(doc);
+
+        if let Some(info) = doc.trim().strip_prefix("```") {
+            match open.take() {
+                Some((line, code, no_run, inner)) => {
+                    let doctest = Doctest {
+                        line,
+                        item: None,
+                        code: code.join("\n"),
+                        no_run,
+                    };
+                    // `//!` documents the module itself, not the next item
+                    if inner {
+                        doctests.push(doctest);
+                    } else {
+                        pending.push(doctest);
+                    }
+                }
+                None if skipping => skipping = false,
+                None => match info.trim() {
+                    "" | "dream" | "rust" => open = Some((index + 1, Vec::new(), false, inner)),
+                    "no_run" | "dream,no_run" | "rust,no_run" => {
+                        open = Some((index + 1, Vec::new(), true, inner))
+                    }
+                    _ => skipping = true,
+                },
+            }
+        } else if let Some((_, code, _, _)) = &mut open {
+            let line = doc.strip_prefix("# ").unwrap_or(if doc == "#" { "" } else { doc });
+            code.push(line.to_string());
+        }
+    }
+    doctests.extend(pending);
+    doctests
+}
+
+/// Name of the item declared on `line`, e.g. `add` for `pub fn add(...)`.
+fn documented_item(line: &str) -> Option<String> {
+    let mut words = line.split(|c: char| !(c.is_alphanumeric() || c == '_'));
+    words
+        .by_ref()
+        .find(|word| matches!(*word, "fn" | "struct" | "enum" | "trait" | "mod" | "type" | "const"))?;
+    words.find(|w| !w.is_empty()).map(|name| name.to_lowercase())
+}
+
+/// Build the hidden test module for the doctests in `module`.
+///
+/// The module is `<module>::__doctests` and imports the public functions of
+/// `module`. Each example becomes a `#[test]` function (or an uncalled one,
+/// for `no_run`), with its `use` lines lifted to the top of the module.
+/// Returns `None` if `module` has no examples, and an error naming the
+/// example's line if one does not parse.
+pub fn doctest_module(module: &Module, package: Option<&str>) -> Result<Option<Module>, String> {
+    let Some(source) = module.source.as_deref() else {
+        return Ok(None);
+    };
+    let doctests = extract_doctests(source);
+    if doctests.is_empty() {
+        return Ok(None);
+    }
+
+    let mut functions: Vec<&str> = module
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Function(func) if func.is_pub => Some(func.name.as_str()),
+            _ => None,
+        })
+        .collect();
+    functions.sort();
+    functions.dedup();
+
+    let mut imports = String::new();
+    if !functions.is_empty() {
+        let path = match package {
+            Some(package) if module.name == package => "crate".to_string(),
+            Some(package) => match module.name.strip_prefix(&format!("{}::", package)) {
+                Some(rest) => format!("crate::{}", rest),
+                None => module.name.clone(),
+            },
+            None => module.name.clone(),
+        };
+        imports.push_str(&format!("use {}::{{{}}};\n", path, functions.join(", ")));
+    }
+
+    let mut bodies = String::new();
+    // Where each example's function ends in `bodies`, to place parse errors
+    let mut ends = Vec::new();
+    for doctest in &doctests {
+        let (uses, code): (Vec<&str>, Vec<&str>) = doctest
+            .code
+            .lines()
+            .partition(|line| line.trim_start().starts_with("use "));
+        for line in uses {
+            imports.push_str(line.trim());
+            imports.push('\n');
+        }
+        let attr = if doctest.no_run { "" } else { "#[test]\n" };
+        bodies.push_str(&format!(
+            "{}pub fn {}() -> Atom {{\nlet _ = {{\n{}\n}};\n:ok\n}}\n\n",
+            attr,
+            doctest.function_name(),
+            code.join("\n"),
+        ));
+        ends.push(bodies.len());
+    }
+
+    let name = format!("{}::__doctests", module.name);
+    let generated = format!("{}\n{}", imports, bodies);
+    Parser::new(&generated)
+        .parse_file(&name)
+        .map(Some)
+        .map_err(|e| {
+            let offset = e.span.offset().saturating_sub(imports.len() + 1);
+            let at = ends.partition_point(|&end| end <= offset).min(doctests.len() - 1);
+            format!(
+                "doctest at {} line {}: {}",
+                module.name, doctests[at].line, e.message
+            )
+        })
+}
+
+/// How the runner schedules tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunOptions {
+    /// Tests running at the same time
+    pub jobs: usize,
+    /// Kill a test that runs longer than this
+    pub timeout: Option<Duration>,
+    /// BEAM modules compiled with coverage counters, whose line counts are
+    /// reported once every test has finished
+    pub cover: Vec<String>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            jobs: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            timeout: Some(DEFAULT_TIMEOUT),
+            cover: Vec::new(),
+        }
+    }
+}
+
+/// Per-test time limit unless `--timeout` says otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Erlang expression that runs `tests` and halts once all have reported.
+///
+/// Up to `options.jobs` tests run at once, each in its own process with a
+/// group leader that captures what it prints. A test passes if it returns
+/// anything but `false` or `{error, _}`, and fails if it returns one of
+/// those, raises, its process dies, or it outlives the timeout. Failed
+/// `assert!`-family checks are reported with their source text and values
+/// rather than as a raw exception. Each
+/// result is one line: `<marker> <status> <index> <output> <message>`,
+/// with output and message hex-encoded so they stay on that line.
+///
+/// With `options.cover` set, the runner creates the table the coverage
+/// counters live in before starting, and afterwards prints
+/// `<marker> cover <module> <line> <count>` for every instrumented line.
+pub fn runner_expr(tests: &[TestCase], options: &RunOptions) -> String {
+    let tests: Vec<String> = tests
+        .iter()
+        .enumerate()
+        .map(|(i, t)| format!("{{{}, '{}', '{}'}}", i, t.beam_module(), t.function))
+        .collect();
+    let timeout = match options.timeout {
+        Some(limit) => limit.as_millis().to_string(),
+        None => "infinity".to_string(),
+    };
+    let cover: Vec<String> = options.cover.iter().map(|m| format!("'{}'", m)).collect();
+
+    format!(
+        "Tests = [{tests}], \
+         Jobs = {jobs}, \
+         Timeout = {timeout}, \
+         Cover = [{cover}], \
+         Cover =:= [] orelse ets:new(dream_cover, [named_table, public, set]), \
+         ToBin = fun(Chars, Enc) -> \
+             case catch unicode:characters_to_binary(Chars, Enc) of \
+                 Bin when is_binary(Bin) -> Bin; \
+                 _ -> <<>> \
+             end \
+         end, \
+         Assertion = fun(Info, Stack) -> \
+             Where = case Stack of \
+                 [{{Mod, _, _, _}} | _] -> io_lib:format(\"~s:\", [Mod]); \
+                 _ -> \"\" \
+             end, \
+             Head = io_lib:format(\"~ts failed at ~s~b:~b\", \
+                 [maps:get(source, Info), Where, maps:get(line, Info), maps:get(column, Info)]), \
+             Message = case maps:find(message, Info) of \
+                 {{ok, Text}} when is_binary(Text) -> io_lib:format(\"~n  ~ts\", [Text]); \
+                 {{ok, Term}} -> io_lib:format(\"~n  ~tp\", [Term]); \
+                 error -> \"\" \
+             end, \
+             Values = [io_lib:format(\"~n  ~-7s~tp\", [[atom_to_list(Key), $:], maps:get(Key, Info)]) \
+                 || Key <- [left, right, value], maps:is_key(Key, Info)], \
+             [Head, Message | Values] \
+         end, \
+         Capture = fun Collect(Acc) -> \
+             receive \
+                 {{io_request, From, ReplyAs, Req}} -> \
+                     {{Reply, Out}} = case Req of \
+                         {{put_chars, Enc, Chars}} -> {{ok, ToBin(Chars, Enc)}}; \
+                         {{put_chars, Enc, M, F, A}} -> {{ok, ToBin(catch apply(M, F, A), Enc)}}; \
+                         {{put_chars, Chars}} -> {{ok, ToBin(Chars, latin1)}}; \
+                         _ -> {{{{error, enotsup}}, <<>>}} \
+                     end, \
+                     From ! {{io_reply, ReplyAs, Reply}}, \
+                     Collect([Out | Acc]); \
+                 {{output, Caller}} -> \
+                     Caller ! {{output, self(), iolist_to_binary(lists:reverse(Acc))}} \
+             end \
+         end, \
+         Start = fun(M, F) -> \
+             Leader = spawn(fun() -> Capture([]) end), \
+             {{Pid, MonRef}} = spawn_monitor(fun() -> \
+                 group_leader(Leader, self()), \
+                 exit(try M:F() of \
+                     false -> {{failed, <<\"returned false\">>}}; \
+                     {{error, E}} -> {{failed, ToBin(io_lib:format(\"returned {{error, ~tp}}\", [E]), unicode)}}; \
+                     _ -> passed \
+                 catch \
+                     error:{{assertion_failed, Info}}:Stack when is_map(Info) -> \
+                         {{failed, ToBin(Assertion(Info, Stack), unicode)}}; \
+                     Class:Reason:Stack -> \
+                     {{failed, ToBin(io_lib:format(\"~p: ~tp~n~tp\", [Class, Reason, Stack]), unicode)}} \
+                 end) \
+             end), \
+             Timer = case Timeout of \
+                 infinity -> none; \
+                 _ -> erlang:send_after(Timeout, self(), {{test_timeout, MonRef}}) \
+             end, \
+             {{MonRef, {{Pid, Leader, Timer}}}} \
+         end, \
+         Report = fun(Status, Index, Leader, Msg) -> \
+             Leader ! {{output, self()}}, \
+             Out = receive {{output, Leader, Captured}} -> Captured after 1000 -> <<>> end, \
+             exit(Leader, kill), \
+             io:format(\"{marker} ~s ~b ~s ~s~n\", \
+                 [Status, Index, binary:encode_hex(Out), binary:encode_hex(Msg)]) \
+         end, \
+         Loop = fun Run(Pending, Running) -> \
+             case {{Pending, maps:size(Running)}} of \
+                 {{[], 0}} -> ok; \
+                 {{[{{I, M, F}} | Rest], N}} when N < Jobs -> \
+                     {{Ref, Info}} = Start(M, F), \
+                     Run(Rest, Running#{{Ref => {{I, Info}}}}); \
+                 _ -> \
+                     receive \
+                         {{'DOWN', DownRef, process, _, Why}} when is_map_key(DownRef, Running) -> \
+                             {{{{I, {{_, Leader, Timer}}}}, Left}} = maps:take(DownRef, Running), \
+                             Timer =:= none orelse erlang:cancel_timer(Timer), \
+                             case Why of \
+                                 passed -> Report(\"passed\", I, Leader, <<>>); \
+                                 {{failed, Msg}} -> Report(\"failed\", I, Leader, Msg); \
+                                 Other -> Report(\"failed\", I, Leader, \
+                                     ToBin(io_lib:format(\"exited: ~tp\", [Other]), unicode)) \
+                             end, \
+                             Run(Pending, Left); \
+                         {{test_timeout, TimeoutRef}} when is_map_key(TimeoutRef, Running) -> \
+                             {{{{I, {{Pid, Leader, _}}}}, Left}} = maps:take(TimeoutRef, Running), \
+                             erlang:demonitor(TimeoutRef, [flush]), \
+                             exit(Pid, kill), \
+                             Report(\"timeout\", I, Leader, <<>>), \
+                             Run(Pending, Left) \
+                     end \
+             end \
+         end, \
+         Loop(Tests, #{{}}), \
+         lists:foreach(fun(M) -> \
+             code:ensure_loaded(M), \
+             Lines = case erlang:function_exported(M, '__dream_cover__', 0) of \
+                 true -> M:'__dream_cover__'(); \
+                 false -> [] \
+             end, \
+             lists:foreach(fun(L) -> \
+                 Count = case ets:lookup(dream_cover, {{M, L}}) of \
+                     [{{_, C}}] -> C; \
+                     [] -> 0 \
+                 end, \
+                 io:format(\"{marker} cover ~s ~b ~b~n\", [M, L, Count]) \
+             end, Lines) \
+         end, Cover), \
+         halt(0).",
+        tests = tests.join(", "),
+        jobs = options.jobs.max(1),
+        timeout = timeout,
+        cover = cover.join(", "),
+        marker = MARKER,
+    )
+}
+
+/// How a test ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    Passed,
+    /// Failure message: the exception and stack trace, or the bad return value
+    Failed(String),
+    /// Killed after running past the timeout
+    TimedOut,
+}
+
+/// Something the runner reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestEvent {
+    /// A test finished. `output` is whatever it printed.
+    Finished {
+        test: TestCase,
+        outcome: TestOutcome,
+        output: String,
+    },
+    /// A line printed outside any test.
+    Output(String),
+    /// How many times an instrumented line ran, with `--coverage`.
+    Covered {
+        /// BEAM module name
+        module: String,
+        line: usize,
+        count: u64,
+    },
+}
+
+/// Turns the runner's stdout back into events, one line at a time.
+pub struct ResultParser {
+    /// Tests by runner index, taken once reported
+    tests: Vec<Option<TestCase>>,
+}
+
+impl ResultParser {
+    pub fn new(tests: &[TestCase]) -> Self {
+        Self {
+            tests: tests.iter().cloned().map(Some).collect(),
+        }
+    }
+
+    /// Feed one line of runner output.
+    pub fn feed(&mut self, line: &str) -> Option<TestEvent> {
+        let Some(result) = line.strip_prefix(MARKER) else {
+            return Some(TestEvent::Output(line.to_string()));
+        };
+
+        let mut fields = result.split(' ').skip(1);
+        let status = fields.next()?;
+        if status == "cover" {
+            return Some(TestEvent::Covered {
+                module: fields.next()?.to_string(),
+                line: fields.next()?.parse().ok()?,
+                count: fields.next()?.parse().ok()?,
+            });
+        }
+        let index: usize = fields.next()?.parse().ok()?;
+        let output = decode(fields.next().unwrap_or(""));
+        let message = decode(fields.next().unwrap_or(""));
+        let test = self.tests.get_mut(index)?.take()?;
+
+        let outcome = match status {
+            "passed" => TestOutcome::Passed,
+            "timeout" => TestOutcome::TimedOut,
+            _ => TestOutcome::Failed(message),
+        };
+        Some(TestEvent::Finished {
+            test,
+            outcome,
+            output,
+        })
+    }
+
+    /// Called when the runner exits. Every test that has not reported yet
+    /// (because one of them halted the node, say) fails.
+    pub fn finish_all(&mut self) -> Vec<TestEvent> {
+        self.tests
+            .iter_mut()
+            .filter_map(Option::take)
+            .map(|test| TestEvent::Finished {
+                test,
+                outcome: TestOutcome::Failed("test runner exited before this test finished".to_string()),
+                output: String::new(),
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn decode(field: &str) -> String {
+    hex::decode(field)
+        .map(|bytes| String::from_utf8_lossy(&bytes).trim_end().to_string())
+        .unwrap_or_default()
+}
+
+/// A field as the BEAM side writes it, for tests of what reads them.
+#[cfg(test)]
+pub(crate) fn encode(text: &str) -> String {
+    hex::encode_upper(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(name: &str, source: &str) -> Module {
+        Parser::new(source).parse_file(name).unwrap()
+    }
+
+    #[test]
+    fn test_discover() {
+        let modules = vec![
+            module(
+                "app::tests",
+                r#"
+                #[test]
+                pub fn test_add() -> atom { :ok }
+                #[test]
+                #[cfg(feature = "slow")]
+                pub fn test_slow() -> atom { :ok }
+                #[test]
+                #[ignore]
+                #[tag("db")]
+                pub fn test_db() -> atom { :ok }
+                pub fn helper() -> int { 1 }
+                "#,
+            ),
+            module("app", "#[test]\npub fn test_root() -> atom { :ok }"),
+        ];
+
+        let options = CompileOptions::for_testing();
+        let tests = discover(&modules, &options);
+        let names: Vec<String> = tests.iter().map(TestCase::name).collect();
+        assert_eq!(names, vec!["app::test_root", "app::tests::test_add", "app::tests::test_db"]);
+        assert!(tests[2].ignored);
+        assert_eq!(tests[2].tags, vec!["db"]);
+
+        let mut options = CompileOptions::for_testing();
+        options.features.insert("slow".to_string());
+        assert_eq!(discover(&modules, &options).len(), 4);
+    }
+
+    #[test]
+    fn test_extract_doctests() {
+        let source = r#"//! Math helpers.
+//!
+//! ```
+//! assert_eq!(math::add(1, 2), 3)
+//! ```
+
+/// Add two numbers.
+///
+/// ```dream
+/// # let x = 1;
+/// assert_eq!(add(x, 2), 3)
+/// ```
+///
+/// ```text
+/// not dream code
+/// ```
+#[inline]
+pub fn add(x: int, y: int) -> int { x + y }
+
+/// ```no_run
+/// server::start()
+/// ```
+///
+/// ```ignore
+/// broken(
+/// ```
+pub struct Point { x: int }
+"#;
+        let doctests = extract_doctests(source);
+        assert_eq!(
+            doctests,
+            vec![
+                Doctest {
+                    line: 3,
+                    item: None,
+                    code: "assert_eq!(math::add(1, 2), 3)".to_string(),
+                    no_run: false,
+                },
+                Doctest {
+                    line: 9,
+                    item: Some("add".to_string()),
+                    code: "let x = 1;\nassert_eq!(add(x, 2), 3)".to_string(),
+                    no_run: false,
+                },
+                Doctest {
+                    line: 20,
+                    item: Some("point".to_string()),
+                    code: "server::start()".to_string(),
+                    no_run: true,
+                },
+            ]
+        );
+        assert_eq!(doctests[1].function_name(), "doctest_add_9");
+        assert_eq!(doctests[0].function_name(), "doctest_3");
+    }
+
+    #[test]
+    fn test_doctest_module() {
+        let source = r#"
+/// ```
+/// use app::util::double;
+/// assert_eq!(add(double(1), 1), 3)
+/// ```
+pub fn add(x: int, y: int) -> int { x + y }
+
+fn private() -> int { 0 }
+"#;
+        let module = Parser::new(source).parse_file("app::math").unwrap();
+        let doctests = doctest_module(&module, Some("app")).unwrap().unwrap();
+        assert_eq!(doctests.name, "app::math::__doctests");
+
+        let generated = doctests.source.as_deref().unwrap();
+        assert!(generated.starts_with("use crate::math::{add};\nuse app::util::double;\n"));
+        assert!(!generated.contains("private"));
+
+        let tests = discover(&[doctests], &CompileOptions::for_testing());
+        assert_eq!(tests, vec![TestCase::new("app::math::__doctests", "doctest_add_2")]);
+
+        let plain = Parser::new("pub fn f() -> int { 1 }").parse_file("app::plain").unwrap();
+        assert_eq!(doctest_module(&plain, Some("app")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_doctest_parse_error_names_line() {
+        let source = "/// ```\n/// let x = 1;\n/// ```\npub fn a() -> int { 1 }\n\n/// ```\n/// let = ;\n/// ```\npub fn b() -> int { 2 }\n";
+        let module = Parser::new(source).parse_file("app").unwrap();
+        let err = doctest_module(&module, Some("app")).unwrap_err();
+        assert!(err.starts_with("doctest at app line 6:"), "{}", err);
+    }
+
+    fn tagged(name: &str, tags: &[&str], ignored: bool) -> TestCase {
+        TestCase {
+            ignored,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            ..TestCase::new("app::parser", name)
+        }
+    }
+
+    #[test]
+    fn test_filter_by_name_and_tag() {
+        let tests = vec![
+            tagged("test_fast", &[], false),
+            tagged("test_slow", &["slow"], false),
+            TestCase::new("app::lexer", "test_tokens"),
+        ];
+
+        let filter = TestFilter {
+            pattern: Some("parser::".to_string()),
+            ..TestFilter::default()
+        };
+        let (run, _) = filter.select(tests.clone());
+        assert_eq!(run.len(), 2);
+
+        let filter = TestFilter {
+            tags: vec!["slow".to_string()],
+            ..TestFilter::default()
+        };
+        let (run, _) = filter.select(tests.clone());
+        assert_eq!(run, vec![tests[1].clone()]);
+
+        let filter = TestFilter {
+            exclude_tags: vec!["slow".to_string()],
+            ..TestFilter::default()
+        };
+        let (run, _) = filter.select(tests.clone());
+        assert_eq!(run.len(), 2);
+        assert!(run.iter().all(|t| t.function != "test_slow"));
+    }
+
+    #[test]
+    fn test_filter_ignored() {
+        let tests = vec![tagged("test_a", &[], false), tagged("test_b", &[], true)];
+
+        let (run, skipped) = TestFilter::default().select(tests.clone());
+        assert_eq!(run, vec![tests[0].clone()]);
+        assert_eq!(skipped, 1);
+
+        let filter = TestFilter {
+            ignored: true,
+            ..TestFilter::default()
+        };
+        let (run, skipped) = filter.select(tests.clone());
+        assert_eq!(run, vec![tests[1].clone()]);
+        assert_eq!(skipped, 0);
+
+        let filter = TestFilter {
+            include_ignored: true,
+            ..TestFilter::default()
+        };
+        assert_eq!(filter.select(tests).0.len(), 2);
+    }
+
+    #[test]
+    fn test_runner_expr() {
+        let tests = [TestCase::new("app::tests", "test_add")];
+        let options = RunOptions {
+            jobs: 4,
+            timeout: Some(Duration::from_secs(5)),
+            cover: Vec::new(),
+        };
+        let expr = runner_expr(&tests, &options);
+        assert!(expr.starts_with("Tests = [{0, 'dream::app::tests', 'test_add'}], Jobs = 4, Timeout = 5000,"));
+        assert!(expr.ends_with("halt(0)."));
+        assert!(expr.contains("error:{assertion_failed, Info}:Stack when is_map(Info)"));
+        assert!(!expr.contains('%'), "the expression is passed on one line");
+        assert!(expr.contains("Cover = [],"));
+
+        let options = RunOptions {
+            jobs: 0,
+            timeout: None,
+            cover: vec!["dream::app::math".to_string()],
+        };
+        let expr = runner_expr(&tests, &options);
+        assert!(expr.contains("Jobs = 1, Timeout = infinity, Cover = ['dream::app::math'],"));
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let tests = vec![
+            TestCase::new("app::tests", "test_a"),
+            TestCase::new("app::tests", "test_b"),
+            TestCase::new("app::tests", "test_c"),
+        ];
+        let mut parser = ResultParser::new(&tests);
+
+        // Results arrive in completion order, not test order
+        let stdout = format!(
+            "booting\n\
+             ##dream-test## failed 1 {} {}\n\
+             ##dream-test## passed 0 {} \n\
+             ##dream-test## timeout 2  ",
+            encode("about to fail\n"),
+            encode("error: {badmatch,2}\n[{m,f,0}]"),
+            encode("hello from a\n"),
+        );
+        let events: Vec<TestEvent> = stdout.lines().filter_map(|l| parser.feed(l)).collect();
+        assert!(parser.finish_all().is_empty());
+
+        assert_eq!(
+            events,
+            vec![
+                TestEvent::Output("booting".to_string()),
+                TestEvent::Finished {
+                    test: tests[1].clone(),
+                    outcome: TestOutcome::Failed("error: {badmatch,2}\n[{m,f,0}]".to_string()),
+                    output: "about to fail".to_string(),
+                },
+                TestEvent::Finished {
+                    test: tests[0].clone(),
+                    outcome: TestOutcome::Passed,
+                    output: "hello from a".to_string(),
+                },
+                TestEvent::Finished {
+                    test: tests[2].clone(),
+                    outcome: TestOutcome::TimedOut,
+                    output: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_runner_exit_fails_remaining_tests() {
+        let tests = vec![
+            TestCase::new("app::tests", "test_ok"),
+            TestCase::new("app::tests", "test_halts"),
+        ];
+        let mut parser = ResultParser::new(&tests);
+        assert!(parser.feed("##dream-test## passed 0  ").is_some());
+        // A second report for the same test is ignored
+        assert_eq!(parser.feed("##dream-test## passed 0  "), None);
+
+        assert_eq!(
+            parser.feed("##dream-test## cover dream::app::math 12 3"),
+            Some(TestEvent::Covered {
+                module: "dream::app::math".to_string(),
+                line: 12,
+                count: 3,
+            })
+        );
+
+        let events = parser.finish_all();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0],
+            TestEvent::Finished { test, outcome: TestOutcome::Failed(msg), .. }
+                if test.function == "test_halts" && msg.contains("exited before")
+        ));
+    }
+}
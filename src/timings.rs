@@ -0,0 +1,411 @@
+//! Self-profiling for `--timings`: how long each phase of the compiler
+//! took, and on which module, so a slow build can be pinned on Dream's
+//! front end or on erlc.
+//!
+//! Nothing is recorded until [`enable`]; from then on [`time`] measures
+//! each phase it wraps. The phases run in the module loader and in the
+//! build driver alike, so the recording is global rather than passed
+//! through both. [`take`] ends it, and the [`Timings`] it returns print as
+//! a summary, an HTML page and a trace-event file for `chrome://tracing`
+//! or Perfetto.
+
+use crate::compiler::ice;
+use crate::markup::escape;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A phase of compilation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Phase {
+    /// Turning source into tokens
+    Lex,
+    /// Turning tokens into a module
+    Parse,
+    /// Applying `#[cfg]`, expanding derives and quotes, and resolving
+    /// method calls to the functions they call
+    Resolve,
+    /// Type checking, over all modules at once
+    Typecheck,
+    /// Generating Core Erlang
+    Codegen,
+    /// Compiling Core Erlang to BEAM with `erlc`
+    Erlc,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 6] = [
+        Phase::Lex,
+        Phase::Parse,
+        Phase::Resolve,
+        Phase::Typecheck,
+        Phase::Codegen,
+        Phase::Erlc,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Lex => "lex",
+            Phase::Parse => "parse",
+            Phase::Resolve => "resolve",
+            Phase::Typecheck => "typecheck",
+            Phase::Codegen => "codegen",
+            Phase::Erlc => "erlc",
+        }
+    }
+}
+
+/// One run of a phase.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub phase: Phase,
+    /// The module it worked on, or `None` for a run over every module
+    pub module: Option<String>,
+    /// When it started, counted from when recording did
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// Everything recorded between [`enable`] and [`take`].
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub events: Vec<Event>,
+    /// How long recording went on, phases or not
+    pub total: Duration,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// When recording started, and the events so far.
+static RECORDING: Mutex<Option<(Instant, Vec<Event>)>> = Mutex::new(None);
+
+/// Start recording.
+pub fn enable() {
+    *RECORDING.lock().unwrap() = Some((Instant::now(), Vec::new()));
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Run `f`, recording how long it took as `phase` on `module` if recording.
+pub fn time<T>(phase: Phase, module: Option<&str>, f: impl FnOnce() -> T) -> T {
+    // Noted whether or not recording, for internal compiler errors
+    let _scope = ice::phase(phase, module);
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let started = Instant::now();
+    let result = f();
+    let duration = started.elapsed();
+    if let Some((epoch, events)) = RECORDING.lock().unwrap().as_mut() {
+        events.push(Event {
+            phase,
+            module: module.map(str::to_string),
+            start: started.saturating_duration_since(*epoch),
+            duration,
+        });
+    }
+    result
+}
+
+/// Stop recording and return what was recorded, or `None` if it never
+/// started.
+pub fn take() -> Option<Timings> {
+    ENABLED.store(false, Ordering::Relaxed);
+    let (epoch, events) = RECORDING.lock().unwrap().take()?;
+    Some(Timings {
+        events,
+        total: epoch.elapsed(),
+    })
+}
+
+impl Timings {
+    /// Time spent in each phase, in pipeline order, leaving out phases
+    /// that never ran.
+    pub fn by_phase(&self) -> Vec<(Phase, Duration)> {
+        Phase::ALL
+            .iter()
+            .map(|&phase| {
+                let spent: Duration = self.events.iter().filter(|e| e.phase == phase).map(|e| e.duration).sum();
+                (phase, spent)
+            })
+            .filter(|(phase, _)| self.events.iter().any(|e| e.phase == *phase))
+            .collect()
+    }
+
+    /// Time spent on each module in each phase, slowest module first. Runs
+    /// over every module, like type checking, count for none of them.
+    pub fn by_module(&self) -> Vec<(String, BTreeMap<Phase, Duration>)> {
+        let mut modules: BTreeMap<&str, BTreeMap<Phase, Duration>> = BTreeMap::new();
+        for event in &self.events {
+            if let Some(module) = &event.module {
+                *modules.entry(module.as_str()).or_default().entry(event.phase).or_default() += event.duration;
+            }
+        }
+        let mut modules: Vec<(String, BTreeMap<Phase, Duration>)> = modules
+            .into_iter()
+            .map(|(module, phases)| (module.to_string(), phases))
+            .collect();
+        modules.sort_by_key(|(_, phases)| std::cmp::Reverse(phases.values().sum::<Duration>()));
+        modules
+    }
+
+    /// Time outside any phase: loading the standard library, checking
+    /// whether modules are up to date, writing files and so on.
+    pub fn other(&self) -> Duration {
+        let phases: Duration = self.events.iter().map(|e| e.duration).sum();
+        self.total.saturating_sub(phases)
+    }
+
+    /// A table of the time in each phase, and the `slowest` modules.
+    pub fn summary(&self, slowest: usize) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Timings ({}):", seconds(self.total));
+        let phases = self.by_phase();
+        for (phase, spent) in &phases {
+            let _ = writeln!(
+                out,
+                "  {:10} {:>9} {:>6}",
+                phase.name(),
+                seconds(*spent),
+                share(*spent, self.total)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "  {:10} {:>9} {:>6}",
+            "other",
+            seconds(self.other()),
+            share(self.other(), self.total)
+        );
+
+        let modules = self.by_module();
+        if !modules.is_empty() {
+            let width = modules.iter().take(slowest).map(|(name, _)| name.len()).max().unwrap_or(0);
+            let _ = writeln!(out, "Slowest modules:");
+            for (name, phases) in modules.iter().take(slowest) {
+                let parts: Vec<String> = phases
+                    .iter()
+                    .map(|(phase, spent)| format!("{} {}", phase.name(), seconds(*spent)))
+                    .collect();
+                let spent: Duration = phases.values().sum();
+                let _ = writeln!(
+                    out,
+                    "  {:width$} {:>9}  ({})",
+                    name,
+                    seconds(spent),
+                    parts.join(", "),
+                    width = width
+                );
+            }
+        }
+        out
+    }
+
+    /// The events in the Trace Event Format, for `chrome://tracing` and
+    /// Perfetto: one lane per phase, each event named after its module.
+    pub fn to_trace_events(&self) -> String {
+        let lanes = Phase::ALL.iter().enumerate().map(|(i, phase)| {
+            json!({
+                "name": "thread_name",
+                "ph": "M",
+                "pid": 1,
+                "tid": i + 1,
+                "args": { "name": phase.name() },
+            })
+        });
+        let events = self.events.iter().map(|event| {
+            let lane = Phase::ALL.iter().position(|p| *p == event.phase).unwrap_or(0) + 1;
+            json!({
+                "name": event.module.as_deref().unwrap_or(event.phase.name()),
+                "cat": event.phase.name(),
+                "ph": "X",
+                "ts": event.start.as_micros() as u64,
+                "dur": event.duration.as_micros() as u64,
+                "pid": 1,
+                "tid": lane,
+            })
+        });
+        let trace: Vec<Value> = lanes.chain(events).collect();
+        json!({ "traceEvents": trace, "displayTimeUnit": "ms" }).to_string()
+    }
+
+    /// A standalone HTML page: the time in each phase as bars, then a
+    /// table of every module's time in each phase, slowest first.
+    pub fn to_html(&self) -> String {
+        let mut out = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Dream build timings</title>\n<style>\n\
+             body { font-family: sans-serif; margin: 2em; }\n\
+             td, th { padding: 0.2em 1em; text-align: left; }\n\
+             td.num, th.num { text-align: right; font-family: monospace; }\n\
+             .bar { background: #69c; height: 1em; }\n\
+             </style>\n</head>\n<body>\n<h1>Dream build timings</h1>\n",
+        );
+        let _ = writeln!(out, "<p>Total: {}</p>", seconds(self.total));
+
+        out.push_str("<table>\n<tr><th>Phase</th><th class=\"num\">Time</th><th class=\"num\">Share</th><th></th></tr>\n");
+        let mut phases: Vec<(&str, Duration)> = self.by_phase().into_iter().map(|(p, d)| (p.name(), d)).collect();
+        phases.push(("other", self.other()));
+        for (name, spent) in phases {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td>\
+                 <td style=\"width: 20em\"><div class=\"bar\" style=\"width: {:.1}%\"></div></td></tr>",
+                name,
+                seconds(spent),
+                share(spent, self.total),
+                fraction(spent, self.total) * 100.0
+            );
+        }
+        out.push_str("</table>\n");
+
+        let modules = self.by_module();
+        if !modules.is_empty() {
+            let columns: Vec<Phase> = Phase::ALL
+                .into_iter()
+                .filter(|phase| modules.iter().any(|(_, phases)| phases.contains_key(phase)))
+                .collect();
+            out.push_str("<h2>Modules</h2>\n<table>\n<tr><th>Module</th><th class=\"num\">Total</th>");
+            for phase in &columns {
+                let _ = write!(out, "<th class=\"num\">{}</th>", phase.name());
+            }
+            out.push_str("</tr>\n");
+            for (name, phases) in &modules {
+                let spent: Duration = phases.values().sum();
+                let _ = write!(out, "<tr><td>{}</td><td class=\"num\">{}</td>", escape(name), seconds(spent));
+                for phase in &columns {
+                    let cell = phases.get(phase).map(|d| seconds(*d)).unwrap_or_default();
+                    let _ = write!(out, "<td class=\"num\">{}</td>", cell);
+                }
+                out.push_str("</tr>\n");
+            }
+            out.push_str("</table>\n");
+        }
+
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+
+    /// Write `index.html` and `trace.json` under `dir`, returning the
+    /// page's path.
+    pub fn write(&self, dir: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+        fs::write(dir.join("trace.json"), self.to_trace_events())?;
+        let page = dir.join("index.html");
+        fs::write(&page, self.to_html())?;
+        Ok(page)
+    }
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}s", duration.as_secs_f64())
+}
+
+fn fraction(part: Duration, total: Duration) -> f64 {
+    if total.is_zero() {
+        0.0
+    } else {
+        part.as_secs_f64() / total.as_secs_f64()
+    }
+}
+
+fn share(part: Duration, total: Duration) -> String {
+    format!("{:.1}%", fraction(part, total) * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timings() -> Timings {
+        let event = |phase, module: Option<&str>, start: u64, millis: u64| Event {
+            phase,
+            module: module.map(str::to_string),
+            start: Duration::from_millis(start),
+            duration: Duration::from_millis(millis),
+        };
+        Timings {
+            events: vec![
+                event(Phase::Lex, Some("app::a"), 0, 10),
+                event(Phase::Parse, Some("app::a"), 10, 20),
+                event(Phase::Lex, Some("app::b"), 30, 5),
+                event(Phase::Parse, Some("app::b"), 35, 5),
+                event(Phase::Typecheck, None, 40, 100),
+                event(Phase::Codegen, Some("app::b"), 140, 60),
+                event(Phase::Erlc, None, 200, 300),
+            ],
+            total: Duration::from_millis(1000),
+        }
+    }
+
+    #[test]
+    fn test_totals() {
+        let timings = timings();
+        assert_eq!(
+            timings.by_phase(),
+            [
+                (Phase::Lex, Duration::from_millis(15)),
+                (Phase::Parse, Duration::from_millis(25)),
+                (Phase::Typecheck, Duration::from_millis(100)),
+                (Phase::Codegen, Duration::from_millis(60)),
+                (Phase::Erlc, Duration::from_millis(300)),
+            ]
+        );
+        let modules: Vec<_> = timings.by_module().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(modules, ["app::b", "app::a"]);
+        assert_eq!(timings.other(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            timings().summary(1),
+            "Timings (1.000s):\n  \
+             lex           0.015s   1.5%\n  \
+             parse         0.025s   2.5%\n  \
+             typecheck     0.100s  10.0%\n  \
+             codegen       0.060s   6.0%\n  \
+             erlc          0.300s  30.0%\n  \
+             other         0.500s  50.0%\n\
+             Slowest modules:\n  \
+             app::b    0.070s  (lex 0.005s, parse 0.005s, codegen 0.060s)\n"
+        );
+    }
+
+    #[test]
+    fn test_trace_events() {
+        let trace: Value = serde_json::from_str(&timings().to_trace_events()).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), Phase::ALL.len() + 7);
+        let erlc = &events[events.len() - 1];
+        assert_eq!(erlc["name"], "erlc");
+        assert_eq!(erlc["ts"], 200_000);
+        assert_eq!(erlc["dur"], 300_000);
+        assert_eq!(erlc["tid"], 6);
+    }
+
+    #[test]
+    fn test_recording() {
+        // Other tests load modules while this one records, so only look
+        // for its own events
+        let module = "timings::test_recording";
+        assert_eq!(time(Phase::Lex, Some(module), || 1), 1);
+        assert!(take().is_none());
+        enable();
+        assert_eq!(time(Phase::Parse, Some(module), || 2), 2);
+        let timings = take().unwrap();
+        let phases: Vec<Phase> = timings
+            .events
+            .iter()
+            .filter(|e| e.module.as_deref() == Some(module))
+            .map(|e| e.phase)
+            .collect();
+        assert_eq!(phases, [Phase::Parse]);
+        assert!(take().is_none());
+    }
+}
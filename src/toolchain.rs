@@ -0,0 +1,501 @@
+//! Finding the Erlang/OTP executables: `erl`, `erlc` and the rest.
+//!
+//! Each is looked for, in order:
+//!
+//! 1. beside `DREAM_ERL`, the path of an `erl` executable, which is used
+//!    as `erl` itself;
+//! 2. in the `bin` directory of `ERL_HOME`, an OTP installation;
+//! 3. on `PATH`, as `which` or `where` would;
+//! 4. on Windows, where the OTP installer does not add itself to `PATH`,
+//!    in the installations it records in the registry, then in those under
+//!    `Program Files`.
+//!
+//! Failing all of them, the bare name is run, so that a missing toolchain
+//! gets the operating system's own "not found".
+//!
+//! Commands that need the BEAM call [`probe`] before starting, which also
+//! checks that the OTP release is one Dream supports, so that a missing or
+//! old installation is reported as such up front rather than as `erlc`
+//! failing halfway through a build.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::UNIX_EPOCH;
+use thiserror::Error;
+
+/// The oldest OTP release the generated code runs on.
+pub const MINIMUM_OTP_RELEASE: u32 = 26;
+
+/// Where to get Erlang/OTP.
+const DOWNLOADS: &str = "https://www.erlang.org/downloads";
+
+/// An Erlang/OTP installation recent enough for Dream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toolchain {
+    pub erl: PathBuf,
+    pub erlc: PathBuf,
+    /// The major release, such as 26
+    pub otp_release: u32,
+}
+
+/// Why there is no usable [`Toolchain`].
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ToolchainError {
+    #[error("Erlang/OTP not found: no `{0}` on PATH, in $ERL_HOME/bin or beside $DREAM_ERL")]
+    NotFound(&'static str),
+    #[error("could not get the OTP release from {}: {detail}", .erl.display())]
+    Unusable { erl: PathBuf, detail: String },
+    #[error("OTP {found} found at {}, Dream requires >= {}", .erl.display(), MINIMUM_OTP_RELEASE)]
+    TooOld { erl: PathBuf, found: u32 },
+}
+
+impl ToolchainError {
+    /// What to do about it.
+    pub fn help(&self) -> String {
+        let install = format!(
+            "install OTP {} or later from {} or with your package manager, asdf or kerl",
+            MINIMUM_OTP_RELEASE, DOWNLOADS
+        );
+        match self {
+            ToolchainError::NotFound(_) => format!("{}, and put its `bin` on PATH or set ERL_HOME", install),
+            ToolchainError::Unusable { .. } => format!("check that `erl` starts, or {}", install),
+            ToolchainError::TooOld { .. } => {
+                format!("{}, or set DREAM_ERL to the `erl` of a newer one", install)
+            }
+        }
+    }
+}
+
+/// Find `erl` and `erlc`, and check the OTP release they belong to. This is
+/// done once per run, and the release is remembered between runs for as
+/// long as `erl` leads to the same file, so that it is not started just to
+/// ask.
+pub fn probe() -> Result<&'static Toolchain, ToolchainError> {
+    static PROBED: OnceLock<Result<Toolchain, ToolchainError>> = OnceLock::new();
+    PROBED.get_or_init(detect).as_ref().map_err(Clone::clone)
+}
+
+fn detect() -> Result<Toolchain, ToolchainError> {
+    let erl = find_executable("erl").ok_or(ToolchainError::NotFound("erl"))?;
+    let erlc = find_executable("erlc").ok_or(ToolchainError::NotFound("erlc"))?;
+    let launcher = resolve_erl(&erl);
+    let otp_release = match launcher.as_deref().and_then(cached_release) {
+        Some(release) => release,
+        None => {
+            let release = ask_release(&erl)?;
+            if let Some(launcher) = &launcher {
+                cache_release(launcher, release);
+            }
+            release
+        }
+    };
+    if otp_release < MINIMUM_OTP_RELEASE {
+        return Err(ToolchainError::TooOld { erl, found: otp_release });
+    }
+    Ok(Toolchain { erl, erlc, otp_release })
+}
+
+/// Ask `erl` for its OTP release.
+fn ask_release(erl: &Path) -> Result<u32, ToolchainError> {
+    let unusable = |detail: String| ToolchainError::Unusable {
+        erl: erl.to_path_buf(),
+        detail,
+    };
+    let output = Command::new(erl)
+        .args(["-noshell", "-eval", "io:put_chars(erlang:system_info(otp_release)), halt()."])
+        .output()
+        .map_err(|e| unusable(e.to_string()))?;
+    let printed = String::from_utf8_lossy(&output.stdout);
+    parse_release(&printed).ok_or_else(|| unusable(format!("it printed {:?}", printed.trim())))
+}
+
+/// The major release in what `erlang:system_info(otp_release)` returns:
+/// "26", or "R16B03" before release 17.
+fn parse_release(printed: &str) -> Option<u32> {
+    let printed = printed.trim();
+    let printed = printed.strip_prefix('R').unwrap_or(printed);
+    let end = printed.find(|c: char| !c.is_ascii_digit()).unwrap_or(printed.len());
+    printed[..end].parse().ok()
+}
+
+/// What an `erl` file is, going by its contents.
+#[derive(Debug, PartialEq, Eq)]
+enum ErlFile {
+    /// OTP's own `erl`: an executable, or the script that starts `erlexec`
+    Launcher,
+    /// A version manager's shim, with the manager it hands over to
+    Shim(String),
+}
+
+/// What `content`, the contents of an `erl`, is, if it can be told. A
+/// shim is a script that execs its manager, as asdf's
+/// `exec asdf exec "erl" "$@"` does.
+fn classify(content: &[u8]) -> Option<ErlFile> {
+    let Some(script) = content.strip_prefix(b"#!") else {
+        return Some(ErlFile::Launcher);
+    };
+    let script = String::from_utf8_lossy(script);
+    if script.contains("erlexec") || script.contains("dyn_erl") {
+        return Some(ErlFile::Launcher);
+    }
+    script.lines().find_map(|line| {
+        let program = line.trim().strip_prefix("exec ")?.split_whitespace().next()?;
+        Some(ErlFile::Shim(program.trim_matches('"').to_string()))
+    })
+}
+
+/// The OTP `erl` that running `erl` ends up in. The shims of asdf and
+/// mise stay the same file whichever OTP is selected, so a shim's manager
+/// is asked `which erl`; mise's shims are links to mise itself. `None`
+/// when that can't be found out, and the release is then not cached.
+fn resolve_erl(erl: &Path) -> Option<PathBuf> {
+    let real = plain_path(fs::canonicalize(erl).ok()?);
+    let manager = if real.file_stem().is_some_and(|stem| stem != "erl") {
+        real
+    } else {
+        match classify(&fs::read(&real).ok()?)? {
+            ErlFile::Launcher => return Some(real),
+            ErlFile::Shim(manager) => PathBuf::from(manager),
+        }
+    };
+    let output = Command::new(manager).args(["which", "erl"]).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let which = String::from_utf8_lossy(&output.stdout);
+    let launcher = plain_path(fs::canonicalize(which.trim()).ok()?);
+    (classify(&fs::read(&launcher).ok()?)? == ErlFile::Launcher).then_some(launcher)
+}
+
+/// The file the last probe is remembered in, in Dream's home directory:
+/// the path of the `erl` launcher, when it was last modified, and its
+/// release, a line each.
+fn cache_file() -> PathBuf {
+    crate::deps::dream_home().join("toolchain")
+}
+
+/// When `erl` was last modified, in seconds, to tell a reinstall apart.
+fn modified(erl: &Path) -> Option<u64> {
+    let modified = fs::metadata(erl).and_then(|metadata| metadata.modified()).ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+fn cached_release(erl: &Path) -> Option<u32> {
+    let cached = fs::read_to_string(cache_file()).ok()?;
+    read_cache(&cached, erl, modified(erl)?)
+}
+
+fn cache_release(erl: &Path, release: u32) {
+    let Some(modified) = modified(erl) else {
+        return;
+    };
+    let file = cache_file();
+    if let Some(dir) = file.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(file, format!("{}\n{}\n{}\n", erl.display(), modified, release));
+}
+
+/// The release in `cached` if it was recorded for `erl` as last modified
+/// at `modified`.
+fn read_cache(cached: &str, erl: &Path, modified: u64) -> Option<u32> {
+    let mut lines = cached.lines();
+    let path = lines.next()?;
+    let at: u64 = lines.next()?.parse().ok()?;
+    let release = lines.next()?.parse().ok()?;
+    (Path::new(path) == erl && at == modified).then_some(release)
+}
+
+/// A command running `erl`.
+pub fn erl() -> Command {
+    Command::new(executable("erl"))
+}
+
+/// A command running `erlc`.
+pub fn erlc() -> Command {
+    Command::new(executable("erlc"))
+}
+
+/// The path of the OTP executable `name`, or just `name` if it is nowhere
+/// to be found.
+pub fn executable(name: &str) -> PathBuf {
+    find_executable(name).unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// The path of the OTP executable `name`, if it can be found. It is looked
+/// for once, and the answer remembered.
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    static FOUND: OnceLock<Mutex<HashMap<String, Option<PathBuf>>>> = OnceLock::new();
+    let mut found = FOUND
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    found
+        .entry(name.to_string())
+        .or_insert_with(|| find(name, &Search::from_env()).or_else(|| installed(name)))
+        .clone()
+}
+
+/// `path` as other programs take it. On Windows, `canonicalize` makes
+/// `\\?\C:\...` paths, which `erl` does not understand; this makes them
+/// `C:\...` again. Elsewhere it is `path` unchanged.
+pub fn plain_path(path: PathBuf) -> PathBuf {
+    match path.to_str().and_then(|s| s.strip_prefix(r"\\?\")) {
+        Some(plain) if plain.as_bytes().get(1) == Some(&b':') => PathBuf::from(plain),
+        _ => path,
+    }
+}
+
+/// Where to look, as the environment says.
+struct Search {
+    dream_erl: Option<PathBuf>,
+    erl_home: Option<PathBuf>,
+    path: Option<OsString>,
+}
+
+impl Search {
+    fn from_env() -> Self {
+        Self {
+            dream_erl: env::var_os("DREAM_ERL").map(PathBuf::from),
+            erl_home: env::var_os("ERL_HOME").map(PathBuf::from),
+            path: env::var_os("PATH"),
+        }
+    }
+}
+
+/// The file name of executable `name` on this platform: `erl.exe` on
+/// Windows.
+fn file_name(name: &str) -> String {
+    format!("{}{}", name, env::consts::EXE_SUFFIX)
+}
+
+/// Look for `name` where the environment says to.
+fn find(name: &str, search: &Search) -> Option<PathBuf> {
+    let file = file_name(name);
+    if let Some(erl) = &search.dream_erl {
+        if name == "erl" {
+            return Some(erl.clone());
+        }
+        if let Some(found) = erl.parent().map(|dir| dir.join(&file)).filter(|path| path.is_file()) {
+            return Some(found);
+        }
+    }
+    if let Some(home) = &search.erl_home {
+        let found = home.join("bin").join(&file);
+        if found.is_file() {
+            return Some(found);
+        }
+    }
+    let path = search.path.as_ref()?;
+    env::split_paths(path).map(|dir| dir.join(&file)).find(|path| path.is_file())
+}
+
+/// Look for `name` in the OTP installations found outside the environment.
+fn installed(name: &str) -> Option<PathBuf> {
+    let file = file_name(name);
+    installations()
+        .into_iter()
+        .map(|dir| dir.join("bin").join(&file))
+        .find(|path| path.is_file())
+}
+
+/// The OTP installations the installer recorded in the registry, the
+/// latest recorded first, then those under `Program Files`.
+#[cfg(windows)]
+fn installations() -> Vec<PathBuf> {
+    let mut installs = Vec::new();
+    for key in [
+        r"HKLM\SOFTWARE\Ericsson\Erlang",
+        r"HKLM\SOFTWARE\WOW6432Node\Ericsson\Erlang",
+        r"HKCU\SOFTWARE\Ericsson\Erlang",
+    ] {
+        let Ok(output) = Command::new("reg").args(["query", key, "/s", "/ve"]).output() else {
+            continue;
+        };
+        installs.extend(registry_dirs(&String::from_utf8_lossy(&output.stdout)));
+    }
+    for var in ["ProgramFiles", "ProgramFiles(x86)"] {
+        let Some(entries) = env::var_os(var).and_then(|dir| std::fs::read_dir(dir).ok()) else {
+            continue;
+        };
+        let mut dirs: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|dir| is_otp_dir(dir))
+            .collect();
+        dirs.sort_by(|a, b| b.cmp(a));
+        installs.extend(dirs);
+    }
+    installs
+}
+
+#[cfg(not(windows))]
+fn installations() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+/// The directories in the output of `reg query <key> /s /ve`: the default
+/// value of each version's key, latest recorded first.
+#[cfg(any(windows, test))]
+fn registry_dirs(output: &str) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = output
+        .lines()
+        .filter_map(|line| line.split_once("REG_SZ"))
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .collect();
+    dirs.reverse();
+    dirs
+}
+
+/// Whether `dir`, in `Program Files`, looks like an OTP installation:
+/// `Erlang OTP`, or `erl-26.2` or `erl5.10.4` from older installers.
+#[cfg(any(windows, test))]
+fn is_otp_dir(dir: &Path) -> bool {
+    let name = dir.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    name.starts_with("Erlang OTP")
+        || name
+            .strip_prefix("erl")
+            .is_some_and(|version| version.starts_with(|c: char| c == '-' || c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn touch(dir: &Path, name: &str) -> PathBuf {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join(file_name(name));
+        std::fs::write(&path, "").unwrap();
+        path
+    }
+
+    #[test]
+    fn test_find() {
+        let root = TempDir::new().unwrap();
+        let on_path = root.path().join("usr").join("bin");
+        let home = root.path().join("otp");
+        let custom = root.path().join("custom");
+        touch(&on_path, "erl");
+        touch(&on_path, "erlc");
+        touch(&home.join("bin"), "erlc");
+        let erl = touch(&custom, "erl");
+        touch(&custom, "escript");
+
+        let mut search = Search {
+            dream_erl: None,
+            erl_home: None,
+            path: Some(env::join_paths([root.path().join("missing"), on_path.clone()]).unwrap()),
+        };
+        assert_eq!(find("erl", &search), Some(on_path.join(file_name("erl"))));
+        assert_eq!(find("dialyzer", &search), None);
+
+        search.erl_home = Some(home.clone());
+        assert_eq!(find("erlc", &search), Some(home.join("bin").join(file_name("erlc"))));
+        assert_eq!(find("erl", &search), Some(on_path.join(file_name("erl"))));
+
+        search.dream_erl = Some(erl.clone());
+        assert_eq!(find("erl", &search), Some(erl));
+        assert_eq!(find("escript", &search), Some(custom.join(file_name("escript"))));
+        assert_eq!(find("erlc", &search), Some(home.join("bin").join(file_name("erlc"))));
+    }
+
+    #[test]
+    fn test_windows_paths() {
+        let output = "\r\nHKEY_LOCAL_MACHINE\\SOFTWARE\\Ericsson\\Erlang\\13.2\r\n    (Default)    REG_SZ    C:\\Program Files\\erl-25.3\r\n\r\nHKEY_LOCAL_MACHINE\\SOFTWARE\\Ericsson\\Erlang\\14.2\r\n    (Default)    REG_SZ    C:\\Program Files\\Erlang OTP\r\n";
+        assert_eq!(
+            registry_dirs(output),
+            [PathBuf::from("C:\\Program Files\\Erlang OTP"), PathBuf::from("C:\\Program Files\\erl-25.3")]
+        );
+        assert!(is_otp_dir(Path::new("Erlang OTP")));
+        assert!(is_otp_dir(Path::new("erl5.10.4")));
+        assert!(!is_otp_dir(Path::new("erlang-tools")));
+        assert!(!is_otp_dir(Path::new("Git")));
+
+        assert_eq!(plain_path(PathBuf::from(r"\\?\C:\dream\stdlib")), PathBuf::from(r"C:\dream\stdlib"));
+        assert_eq!(plain_path(PathBuf::from(r"\\?\UNC\host\share")), PathBuf::from(r"\\?\UNC\host\share"));
+        assert_eq!(plain_path(PathBuf::from("/opt/dream/stdlib")), PathBuf::from("/opt/dream/stdlib"));
+    }
+
+    #[test]
+    fn test_classify() {
+        let otp = b"#!/bin/sh\nROOTDIR=\"/usr/lib/erlang\"\nexec \"$BINDIR/erlexec\" ${1+\"$@\"}\n";
+        assert_eq!(classify(otp), Some(ErlFile::Launcher));
+        assert_eq!(classify(b"\x7fELF\x02\x01"), Some(ErlFile::Launcher));
+        let asdf = b"#!/usr/bin/env bash\n# asdf-plugin: erlang 26.2\nexec /home/me/.asdf/bin/asdf exec \"erl\" \"$@\"\n";
+        assert_eq!(classify(asdf), Some(ErlFile::Shim("/home/me/.asdf/bin/asdf".to_string())));
+        assert_eq!(classify(b"#!/bin/sh\necho hello\n"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shim_switching_otp() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = TempDir::new().unwrap();
+        let script = |path: &Path, text: &str| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        let otp_26 = root.path().join("installs").join("26").join("bin").join("erl");
+        let otp_27 = root.path().join("installs").join("27").join("bin").join("erl");
+        script(&otp_26, "#!/bin/sh\nexec \"$BINDIR/erlexec\"\n");
+        script(&otp_27, "#!/bin/sh\nexec \"$BINDIR/erlexec\"\n");
+        // A manager answering `which erl` with the selected installation
+        let selected = root.path().join("selected");
+        let manager = root.path().join("bin").join("manager");
+        script(&manager, &format!("#!/bin/sh\ncat '{}'\n", selected.display()));
+        let shim = root.path().join("shims").join("erl");
+        script(&shim, &format!("#!/bin/sh\nexec {} exec \"erl\" \"$@\"\n", manager.display()));
+
+        std::fs::write(&selected, otp_26.to_string_lossy().as_bytes()).unwrap();
+        let launcher = resolve_erl(&shim).unwrap();
+        assert_eq!(launcher, otp_26.canonicalize().unwrap());
+        let cached = format!("{}\n{}\n26\n", launcher.display(), modified(&launcher).unwrap());
+
+        // Switching OTP leaves the shim as it was, but not what it leads to
+        std::fs::write(&selected, otp_27.to_string_lossy().as_bytes()).unwrap();
+        let launcher = resolve_erl(&shim).unwrap();
+        assert_eq!(launcher, otp_27.canonicalize().unwrap());
+        assert_eq!(read_cache(&cached, &launcher, modified(&launcher).unwrap()), None);
+
+        // mise links its shims to itself
+        let mise = root.path().join("bin").join("mise");
+        script(&mise, &format!("#!/bin/sh\ncat '{}'\n", selected.display()));
+        let link = root.path().join("mise-shims").join("erl");
+        std::fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::os::unix::fs::symlink(&mise, &link).unwrap();
+        assert_eq!(resolve_erl(&link), Some(otp_27.canonicalize().unwrap()));
+
+        assert_eq!(resolve_erl(&otp_26), Some(otp_26.canonicalize().unwrap()));
+    }
+
+    #[test]
+    fn test_release() {
+        assert_eq!(parse_release("26\n"), Some(26));
+        assert_eq!(parse_release("R16B03"), Some(16));
+        assert_eq!(parse_release("erl: command not found"), None);
+
+        let erl = Path::new("/usr/lib/erlang/bin/erl");
+        let cached = "/usr/lib/erlang/bin/erl\n1700000000\n27\n";
+        assert_eq!(read_cache(cached, erl, 1_700_000_000), Some(27));
+        assert_eq!(read_cache(cached, erl, 1_700_000_001), None);
+        assert_eq!(read_cache(cached, Path::new("/opt/otp/bin/erl"), 1_700_000_000), None);
+        assert_eq!(read_cache("", erl, 1_700_000_000), None);
+
+        let error = ToolchainError::TooOld {
+            erl: PathBuf::from("/usr/bin/erl"),
+            found: 24,
+        };
+        assert_eq!(error.to_string(), "OTP 24 found at /usr/bin/erl, Dream requires >= 26");
+        assert!(error.help().contains("DREAM_ERL"));
+    }
+}
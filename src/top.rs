@@ -0,0 +1,851 @@
+//! A live process table for `dream top`.
+//!
+//! A hidden node connects to the running one and answers commands on its
+//! stdin: `sample` reports every process with its reductions, memory,
+//! message queue length, registered name, label and current function, and
+//! `{detail, Pid}` reports one process with its current stacktrace. The
+//! answers are marker lines like the other BEAM-side tools print.
+//! [`Dashboard`] keeps the table sorted between samples and renders it;
+//! [`run`] drives both from the terminal.
+
+use crate::erl::erlang_string;
+use crate::profile::dream_name;
+use crate::target::format_size;
+use crate::testing::decode;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{self, Child, ChildStdin, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Prefix of the sampler's protocol lines.
+const MARKER: &str = "##dream-top##";
+
+/// How long to wait for the node to answer a command.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Keys for navigating the table, shown under the title.
+const TABLE_HELP: &str = "up/down select  enter stacktrace  r reductions  m memory  l queue  q quit";
+
+/// Keys for the process view.
+const DETAIL_HELP: &str = "esc back  q quit";
+
+/// The column the table is sorted by, largest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    /// Reductions since the previous sample, i.e. who is busy right now
+    #[default]
+    Reductions,
+    Memory,
+    Queue,
+}
+
+impl SortKey {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Reductions => "reductions",
+            Self::Memory => "memory",
+            Self::Queue => "message queue",
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reductions" => Ok(Self::Reductions),
+            "memory" => Ok(Self::Memory),
+            "queue" => Ok(Self::Queue),
+            _ => Err(format!("unknown sort key `{}` (expected `reductions`, `memory` or `queue`)", s)),
+        }
+    }
+}
+
+/// Erlang expression that connects to `node` and serves `sample` and
+/// `{detail, Pid}` commands read from stdin until it closes. A `node`
+/// without a host is looked for on this host.
+pub fn sampler_expr(node: &str) -> String {
+    format!(
+        "Requested = {node}, \
+         Target = case string:split(Requested, \"@\") of \
+             [_, _] -> list_to_atom(Requested); \
+             [Short] -> \
+                 [_, Host] = string:split(atom_to_list(node()), \"@\"), \
+                 list_to_atom(Short ++ \"@\" ++ Host) \
+         end, \
+         Hex = fun(Chars) -> binary:encode_hex(unicode:characters_to_binary(Chars)) end, \
+         Emit = fun(Format, Args) -> io:format(\"{marker} \" ++ Format ++ \"~n\", Args) end, \
+         Name = fun({{M, F, A}}) when is_list(A) -> io_lib:format(\"~tw:~tw/~b\", [M, F, length(A)]); \
+             ({{M, F, A}}) -> io_lib:format(\"~tw:~tw/~b\", [M, F, A]); \
+             (_) -> \"\" \
+         end, \
+         case net_kernel:connect_node(Target) of \
+             true -> Emit(\"connected ~s\", [Hex(atom_to_list(Target))]); \
+             _ -> Emit(\"error ~s\", [Hex(io_lib:format(\"could not connect to ~s\", [Target]))]), halt(1) \
+         end, \
+         Sample = fun() -> \
+             Processes = erpc:call(Target, fun() -> \
+                 [{{P, Info, try proc_lib:get_label(P) catch _:_ -> undefined end}} \
+                     || P <- erlang:processes(), \
+                        Info <- [erlang:process_info(P, [registered_name, current_function, reductions, memory, message_queue_len])], \
+                        is_list(Info)] \
+             end, 5000), \
+             lists:foreach(fun({{P, Info, Label}}) -> \
+                 Get = fun(Key) -> proplists:get_value(Key, Info) end, \
+                 Registered = case Get(registered_name) of [] -> \"\"; Reg -> atom_to_list(Reg) end, \
+                 LabelText = case Label of undefined -> \"\"; _ -> io_lib:format(\"~tp\", [Label]) end, \
+                 Emit(\"proc ~w ~b ~b ~b ~s ~s ~s\", [P, Get(reductions), Get(memory), Get(message_queue_len), \
+                     Hex(Registered), Hex(LabelText), Hex(Name(Get(current_function)))]) \
+             end, Processes) \
+         end, \
+         Where = fun(Location) -> \
+             case {{proplists:get_value(file, Location), proplists:get_value(line, Location)}} of \
+                 {{undefined, _}} -> \"\"; \
+                 {{File, undefined}} -> File; \
+                 {{File, Line}} -> io_lib:format(\"~ts:~b\", [File, Line]) \
+             end \
+         end, \
+         Detail = fun(PidText) -> \
+             Keys = [registered_name, initial_call, status, message_queue_len, memory, reductions, links, monitors, current_stacktrace], \
+             case erpc:call(Target, erlang, process_info, [list_to_pid(PidText), Keys], 5000) of \
+                 undefined -> Emit(\"gone\", []); \
+                 Info -> lists:foreach(fun \
+                     ({{current_stacktrace, Stack}}) -> \
+                         [Emit(\"frame ~s ~s\", [Hex(Name({{M, F, A}})), Hex(Where(Location))]) || {{M, F, A, Location}} <- Stack]; \
+                     ({{registered_name, []}}) -> ok; \
+                     ({{initial_call, MFA}}) -> Emit(\"info ~s ~s\", [Hex(\"initial_call\"), Hex(Name(MFA))]); \
+                     ({{Key, Value}}) when is_list(Value) -> Emit(\"info ~s ~s\", [Hex(atom_to_list(Key)), Hex(integer_to_list(length(Value)))]); \
+                     ({{Key, Value}}) -> Emit(\"info ~s ~s\", [Hex(atom_to_list(Key)), Hex(io_lib:format(\"~tw\", [Value]))]) \
+                 end, Info) \
+             end \
+         end, \
+         Answer = fun(Command) -> \
+             try Command() \
+             catch Class:Reason -> Emit(\"error ~s\", [Hex(io_lib:format(\"~p: ~tp\", [Class, Reason]))]) \
+             end, \
+             Emit(\"end\", []) \
+         end, \
+         Serve = fun Loop() -> \
+             case io:read('') of \
+                 {{ok, sample}} -> Answer(Sample), Loop(); \
+                 {{ok, {{detail, PidText}}}} -> Answer(fun() -> Detail(PidText) end), Loop(); \
+                 _ -> halt(0) \
+             end \
+         end, \
+         Serve().",
+        node = erlang_string(node),
+        marker = MARKER,
+    )
+}
+
+/// One row of the process table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Process {
+    pub pid: String,
+    /// Registered name, or empty
+    pub registered: String,
+    /// Label set with `proc_lib:set_label`, or empty
+    pub label: String,
+    /// Dream name of the function the process is running
+    pub current: String,
+    /// Reductions since the process started
+    pub reductions: u64,
+    /// Bytes, including the stack, heap and message queue
+    pub memory: u64,
+    pub queue: u64,
+}
+
+impl Process {
+    /// What to call the process: its registered name, else its label.
+    pub fn name(&self) -> &str {
+        if self.registered.is_empty() {
+            &self.label
+        } else {
+            &self.registered
+        }
+    }
+}
+
+/// One line from the sampler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Connected(String),
+    Process(Process),
+    /// A `process_info` item of the process asked about
+    Info(String, String),
+    /// A stack frame of the process asked about, innermost first, and its
+    /// source location if known
+    Frame(String, String),
+    /// The process asked about has exited
+    Gone,
+    Error(String),
+    /// The answer to a command is complete
+    End,
+    /// Anything the node printed that is not part of the protocol
+    Output(String),
+}
+
+pub fn parse_reply(line: &str) -> Reply {
+    let Some(rest) = line.strip_prefix(MARKER).and_then(|rest| rest.strip_prefix(' ')) else {
+        return Reply::Output(line.to_string());
+    };
+    let fields: Vec<&str> = rest.split(' ').collect();
+    match fields.as_slice() {
+        ["connected", node] => Reply::Connected(decode(node)),
+        ["proc", pid, reductions, memory, queue, registered, label, current] => {
+            let (Ok(reductions), Ok(memory), Ok(queue)) = (reductions.parse::<u64>(), memory.parse::<u64>(), queue.parse::<u64>()) else {
+                return Reply::Output(line.to_string());
+            };
+            let current = decode(current);
+            Reply::Process(Process {
+                pid: pid.to_string(),
+                registered: decode(registered),
+                label: one_line(&decode(label)),
+                current: if current.is_empty() { current } else { dream_name(&current) },
+                reductions,
+                memory,
+                queue,
+            })
+        }
+        ["info", key, value] => Reply::Info(decode(key), decode(value)),
+        ["frame", function, location] => Reply::Frame(dream_name(&decode(function)), decode(location)),
+        ["gone"] => Reply::Gone,
+        ["error", message] => Reply::Error(one_line(&decode(message))),
+        ["end"] => Reply::End,
+        _ => Reply::Output(line.to_string()),
+    }
+}
+
+/// A pretty-printed term squeezed onto one line.
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The process being looked at.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Detail {
+    pub pid: String,
+    pub info: Vec<(String, String)>,
+    pub stack: Vec<(String, String)>,
+    pub gone: bool,
+}
+
+/// What the terminal loop should do after a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Redraw,
+    /// Fetch the selected process's stacktrace
+    Inspect,
+    Quit,
+}
+
+/// Lines to draw, and which of them is the selected row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screen {
+    pub lines: Vec<String>,
+    pub highlight: Option<usize>,
+}
+
+/// The state of the table between samples.
+#[derive(Debug, Clone)]
+pub struct Dashboard {
+    pub node: String,
+    pub sort: SortKey,
+    /// Processes with their reductions since the previous sample, in order
+    rows: Vec<(Process, u64)>,
+    previous: HashMap<String, u64>,
+    selected: usize,
+    /// First row shown, kept so the selection stays on screen
+    scroll: usize,
+    pub detail: Option<Detail>,
+    pub error: Option<String>,
+}
+
+impl Dashboard {
+    pub fn new(node: impl Into<String>, sort: SortKey) -> Self {
+        Self {
+            node: node.into(),
+            sort,
+            rows: Vec::new(),
+            previous: HashMap::new(),
+            selected: 0,
+            scroll: 0,
+            detail: None,
+            error: None,
+        }
+    }
+
+    /// Take in a sample, keeping the same process selected if it is still
+    /// alive. A process new since the previous sample counts all of its
+    /// reductions as recent.
+    pub fn update(&mut self, replies: Vec<Reply>) {
+        let selected = self.selected_pid().map(str::to_string);
+        let mut processes = Vec::new();
+        self.error = None;
+        for reply in replies {
+            match reply {
+                Reply::Process(process) => processes.push(process),
+                Reply::Error(message) => self.error = Some(message),
+                _ => {}
+            }
+        }
+        // A failed sample keeps the previous table on screen
+        if processes.is_empty() && self.error.is_some() {
+            return;
+        }
+
+        self.rows = processes
+            .into_iter()
+            .map(|process| {
+                let recent = match self.previous.get(&process.pid) {
+                    Some(before) => process.reductions.saturating_sub(*before),
+                    None => process.reductions,
+                };
+                (process, recent)
+            })
+            .collect();
+        self.previous = self.rows.iter().map(|(p, _)| (p.pid.clone(), p.reductions)).collect();
+        self.sort_rows();
+        self.selected = selected
+            .and_then(|pid| self.rows.iter().position(|(p, _)| p.pid == pid))
+            .unwrap_or(0)
+            .min(self.rows.len().saturating_sub(1));
+    }
+
+    /// Take in the answer to a `detail` command.
+    pub fn show_detail(&mut self, pid: &str, replies: Vec<Reply>) {
+        let mut detail = Detail {
+            pid: pid.to_string(),
+            ..Detail::default()
+        };
+        for reply in replies {
+            match reply {
+                Reply::Info(key, value) => detail.info.push((key, value)),
+                Reply::Frame(function, location) => detail.stack.push((function, location)),
+                Reply::Gone => detail.gone = true,
+                Reply::Error(message) => self.error = Some(message),
+                _ => {}
+            }
+        }
+        self.detail = Some(detail);
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &Process> {
+        self.rows.iter().map(|(process, _)| process)
+    }
+
+    pub fn selected_pid(&self) -> Option<&str> {
+        self.rows.get(self.selected).map(|(process, _)| process.pid.as_str())
+    }
+
+    pub fn set_sort(&mut self, sort: SortKey) {
+        let selected = self.selected_pid().map(str::to_string);
+        self.sort = sort;
+        self.sort_rows();
+        self.selected = selected
+            .and_then(|pid| self.rows.iter().position(|(p, _)| p.pid == pid))
+            .unwrap_or(0);
+    }
+
+    fn sort_rows(&mut self) {
+        let sort = self.sort;
+        self.rows.sort_by_key(|(process, recent)| {
+            let key = match sort {
+                SortKey::Reductions => *recent,
+                SortKey::Memory => process.memory,
+                SortKey::Queue => process.queue,
+            };
+            std::cmp::Reverse(key)
+        });
+    }
+
+    /// Move the selection by `delta` rows, stopping at either end.
+    pub fn move_selection(&mut self, delta: isize) {
+        let last = self.rows.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(delta).min(last);
+    }
+
+    /// React to a key press. `page` is how many rows fit on screen.
+    pub fn key(&mut self, key: KeyEvent, page: usize) -> Action {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Action::Quit;
+        }
+        if self.detail.is_some() {
+            return match key.code {
+                KeyCode::Char('q') => Action::Quit,
+                KeyCode::Esc | KeyCode::Backspace | KeyCode::Left => {
+                    self.detail = None;
+                    Action::Redraw
+                }
+                _ => Action::None,
+            };
+        }
+        let page = page.max(1) as isize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+            KeyCode::Enter | KeyCode::Right if self.selected_pid().is_some() => return Action::Inspect,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-page),
+            KeyCode::PageDown => self.move_selection(page),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = self.rows.len().saturating_sub(1),
+            KeyCode::Char('r') => self.set_sort(SortKey::Reductions),
+            KeyCode::Char('m') => self.set_sort(SortKey::Memory),
+            KeyCode::Char('l') => self.set_sort(SortKey::Queue),
+            _ => return Action::None,
+        }
+        Action::Redraw
+    }
+
+    /// The screen for a terminal of `width` by `height` characters.
+    pub fn render(&mut self, width: usize, height: usize) -> Screen {
+        if let Some(detail) = &self.detail {
+            return self.render_detail(detail, width, height);
+        }
+        self.render_table(width, height)
+    }
+
+    fn title(&self, width: usize, summary: &str, help: &str) -> Vec<String> {
+        let status = match &self.error {
+            Some(error) => format!("Error: {}", error),
+            None => String::new(),
+        };
+        vec![
+            fit(&format!("dream top - {}  {}", self.node, summary), width),
+            fit(help, width),
+            fit(&status, width),
+        ]
+    }
+
+    fn render_table(&mut self, width: usize, height: usize) -> Screen {
+        let summary = format!("{} processes, by {}", self.rows.len(), self.sort.label());
+        let mut lines = self.title(width, &summary, TABLE_HELP);
+        let header = lines.len() + 1;
+        let visible = height.saturating_sub(header).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + visible {
+            self.scroll = self.selected + 1 - visible;
+        }
+
+        // PID, the two numeric columns and MSGQ are fixed; the rest is
+        // split between the name and the current function
+        let flexible = width.saturating_sub(16 + 12 + 11 + 7 + 4);
+        let name_width = (flexible / 3).max(4);
+        let current_width = flexible.saturating_sub(name_width + 1);
+        let row = |pid: &str, name: &str, current: &str, reductions: &str, memory: &str, queue: &str| {
+            let line = format!(
+                "{} {} {} {:>12} {:>11} {:>7}",
+                fit(pid, 16),
+                fit(name, name_width),
+                fit(current, current_width),
+                reductions,
+                memory,
+                queue
+            );
+            fit(&line, width)
+        };
+        lines.push(row("PID", "NAME", "CURRENT FUNCTION", "REDUCTIONS", "MEMORY", "MSGQ"));
+        for (process, recent) in self.rows.iter().skip(self.scroll).take(visible) {
+            lines.push(row(
+                &process.pid,
+                process.name(),
+                &process.current,
+                &recent.to_string(),
+                &format_size(process.memory),
+                &process.queue.to_string(),
+            ));
+        }
+        let highlight = (!self.rows.is_empty()).then(|| header + self.selected - self.scroll);
+        Screen { lines, highlight }
+    }
+
+    fn render_detail(&self, detail: &Detail, width: usize, height: usize) -> Screen {
+        let name = self
+            .rows
+            .iter()
+            .find(|(process, _)| process.pid == detail.pid)
+            .map_or("", |(process, _)| process.name());
+        let summary = format!("process {} {}", detail.pid, name);
+        let mut lines = self.title(width, &summary, DETAIL_HELP);
+        if detail.gone {
+            lines.push(fit("The process has exited.", width));
+            return Screen { lines, highlight: None };
+        }
+        for (key, value) in &detail.info {
+            let value = match key.as_str() {
+                "memory" => value.parse().map_or(value.clone(), format_size),
+                "initial_call" => dream_name(value),
+                _ => value.clone(),
+            };
+            lines.push(fit(&format!("  {:<18} {}", key.replace('_', " "), value), width));
+        }
+        lines.push(String::new());
+        lines.push(fit("Stacktrace:", width));
+        for (function, location) in &detail.stack {
+            lines.push(fit(&format!("  {:<48} {}", function, location), width));
+        }
+        lines.truncate(height);
+        Screen { lines, highlight: None }
+    }
+}
+
+/// `text` cut or padded to exactly `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    let count = text.chars().count();
+    if count > width {
+        let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+        if width > 0 {
+            cut.push('~');
+        }
+        cut
+    } else {
+        format!("{}{}", text, " ".repeat(width - count))
+    }
+}
+
+/// The sampling node, as a child process.
+struct Sampler {
+    child: Child,
+    stdin: ChildStdin,
+    replies: Receiver<Reply>,
+}
+
+impl Sampler {
+    fn start(mut erl: process::Command, node: &str) -> Result<(Self, String), String> {
+        erl.arg("-hidden")
+            .arg("-noshell")
+            .arg("-eval")
+            .arg(sampler_expr(node))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null());
+        let mut child = erl.spawn().map_err(|e| format!("could not run erl: {}", e))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(parse_reply(&line)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let sampler = Self { child, stdin, replies };
+        loop {
+            match sampler.replies.recv_timeout(REPLY_TIMEOUT) {
+                Ok(Reply::Connected(target)) => return Ok((sampler, target)),
+                Ok(Reply::Error(message)) => {
+                    sampler.finish();
+                    return Err(message);
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    sampler.finish();
+                    return Err(format!("could not connect to {}", node));
+                }
+            }
+        }
+    }
+
+    /// Send a command and collect the answer.
+    fn request(&mut self, command: &str) -> Result<Vec<Reply>, String> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| "the connection to the node was lost".to_string())?;
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        let mut replies = Vec::new();
+        loop {
+            match self.replies.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(Reply::End) => return Ok(replies),
+                Ok(reply) => replies.push(reply),
+                Err(RecvTimeoutError::Timeout) => return Err("the node did not answer".to_string()),
+                Err(RecvTimeoutError::Disconnected) => return Err("the connection to the node was lost".to_string()),
+            }
+        }
+    }
+
+    fn detail(&mut self, pid: &str) -> Result<Vec<Reply>, String> {
+        self.request(&format!("{{detail, {}}}.", erlang_string(pid)))
+    }
+
+    /// Close the node's stdin, which makes it halt, and wait for it.
+    fn finish(self) {
+        let Self { mut child, stdin, .. } = self;
+        drop(stdin);
+        let _ = child.wait();
+    }
+}
+
+/// Raw mode on the alternate screen, undone when dropped.
+struct Terminal;
+
+impl Terminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+
+    fn size() -> (usize, usize) {
+        terminal::size().map_or((80, 24), |(w, h)| (w as usize, h as usize))
+    }
+
+    fn draw(screen: &Screen) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        queue!(out, Clear(ClearType::All))?;
+        for (i, line) in screen.lines.iter().enumerate() {
+            queue!(out, cursor::MoveTo(0, i as u16))?;
+            if screen.highlight == Some(i) {
+                queue!(out, SetAttribute(Attribute::Reverse), Print(line), SetAttribute(Attribute::Reset))?;
+            } else {
+                queue!(out, Print(line))?;
+            }
+        }
+        out.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Show the processes of `node` until the user quits, sampling them every
+/// `interval`. `erl` carries the distribution flags to connect with.
+pub fn run(erl: process::Command, node: &str, sort: SortKey, interval: Duration) -> Result<(), String> {
+    let (mut sampler, target) = Sampler::start(erl, node)?;
+    let result = match Terminal::enter() {
+        Ok(terminal) => {
+            let result = watch(&mut sampler, Dashboard::new(target, sort), interval);
+            drop(terminal);
+            result
+        }
+        Err(e) => Err(format!("could not set up the terminal: {}", e)),
+    };
+    sampler.finish();
+    result
+}
+
+fn watch(sampler: &mut Sampler, mut dashboard: Dashboard, interval: Duration) -> Result<(), String> {
+    let terminal_error = |e: io::Error| format!("terminal error: {}", e);
+    let mut next_sample = Instant::now();
+    loop {
+        if Instant::now() >= next_sample {
+            dashboard.update(sampler.request("sample.")?);
+            if let Some(pid) = dashboard.detail.as_ref().map(|detail| detail.pid.clone()) {
+                let replies = sampler.detail(&pid)?;
+                dashboard.show_detail(&pid, replies);
+            }
+            let (width, height) = Terminal::size();
+            Terminal::draw(&dashboard.render(width, height)).map_err(terminal_error)?;
+            next_sample = Instant::now() + interval;
+        }
+
+        let wait = next_sample.saturating_duration_since(Instant::now());
+        if !event::poll(wait).map_err(terminal_error)? {
+            continue;
+        }
+        let (width, height) = Terminal::size();
+        let redraw = match event::read().map_err(terminal_error)? {
+            Event::Key(key) if key.kind != KeyEventKind::Release => {
+                match dashboard.key(key, height.saturating_sub(4)) {
+                    Action::Quit => return Ok(()),
+                    Action::Inspect => {
+                        if let Some(pid) = dashboard.selected_pid().map(str::to_string) {
+                            let replies = sampler.detail(&pid)?;
+                            dashboard.show_detail(&pid, replies);
+                        }
+                        true
+                    }
+                    Action::Redraw => true,
+                    Action::None => false,
+                }
+            }
+            Event::Resize(..) => true,
+            _ => false,
+        };
+        if redraw {
+            Terminal::draw(&dashboard.render(width, height)).map_err(terminal_error)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::encode;
+
+    fn process(pid: &str, reductions: u64, memory: u64, queue: u64) -> Process {
+        Process {
+            pid: pid.to_string(),
+            registered: String::new(),
+            label: String::new(),
+            current: String::new(),
+            reductions,
+            memory,
+            queue,
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_sampler_expr() {
+        let expr = sampler_expr("app@host");
+        assert!(expr.starts_with("Requested = \"app@host\", Target = case"));
+        assert!(expr.contains("io:format(\"##dream-top## \" ++ Format"));
+        assert!(expr.contains("Emit(\"connected ~s\""));
+        assert!(expr.contains("current_stacktrace"));
+        assert!(expr.ends_with("Serve()."));
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        let line = format!(
+            "{} proc <0.88.0> 1200 4096 3 {} {} {}",
+            MARKER,
+            encode("app_server"),
+            encode(""),
+            encode("'dream::app::server':loop/2")
+        );
+        let Reply::Process(p) = parse_reply(&line) else { panic!("expected a process") };
+        assert_eq!(p.pid, "<0.88.0>");
+        assert_eq!((p.reductions, p.memory, p.queue), (1200, 4096, 3));
+        assert_eq!(p.name(), "app_server");
+        assert_eq!(p.current, "app::server::loop/2");
+
+        let line = format!("{} proc <0.90.0> 1 2 0 {} {} {}", MARKER, encode(""), encode("{worker,\n 1}"), encode(""));
+        let Reply::Process(p) = parse_reply(&line) else { panic!("expected a process") };
+        assert_eq!(p.name(), "{worker, 1}");
+        assert_eq!(p.current, "");
+
+        let line = format!("{} frame {} {}", MARKER, encode("'dream::app':'-main/0-fun-0-'/1"), encode("src/app.erl:3"));
+        assert_eq!(
+            parse_reply(&line),
+            Reply::Frame("app::main::{closure}/1".to_string(), "src/app.erl:3".to_string())
+        );
+        assert_eq!(parse_reply(&format!("{} end", MARKER)), Reply::End);
+        assert_eq!(parse_reply(&format!("{} gone", MARKER)), Reply::Gone);
+        assert_eq!(parse_reply("hello"), Reply::Output("hello".to_string()));
+    }
+
+    #[test]
+    fn test_update_and_sort() {
+        let mut dashboard = Dashboard::new("app@host", SortKey::Reductions);
+        dashboard.update(vec![
+            Reply::Process(process("<0.1.0>", 1000, 10, 0)),
+            Reply::Process(process("<0.2.0>", 50, 500, 7)),
+        ]);
+        let order = |d: &Dashboard| d.rows().map(|p| p.pid.clone()).collect::<Vec<_>>();
+        assert_eq!(order(&dashboard), ["<0.1.0>", "<0.2.0>"]);
+
+        // Only reductions since the previous sample count
+        dashboard.move_selection(1);
+        dashboard.update(vec![
+            Reply::Process(process("<0.1.0>", 1010, 10, 0)),
+            Reply::Process(process("<0.2.0>", 150, 500, 7)),
+        ]);
+        assert_eq!(order(&dashboard), ["<0.2.0>", "<0.1.0>"]);
+        assert_eq!(dashboard.selected_pid(), Some("<0.2.0>"));
+
+        dashboard.set_sort(SortKey::Memory);
+        assert_eq!(order(&dashboard), ["<0.2.0>", "<0.1.0>"]);
+        dashboard.set_sort(SortKey::Queue);
+        assert_eq!(dashboard.selected_pid(), Some("<0.2.0>"));
+
+        // A failed sample keeps the table
+        dashboard.update(vec![Reply::Error("timeout".to_string())]);
+        assert_eq!(dashboard.rows().count(), 2);
+        assert_eq!(dashboard.error.as_deref(), Some("timeout"));
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut dashboard = Dashboard::new("app@host", SortKey::Reductions);
+        dashboard.update((1..=5).map(|i| Reply::Process(process(&format!("<0.{}.0>", i), 10 - i, 0, 0))).collect());
+        assert_eq!(dashboard.key(key(KeyCode::Down), 10), Action::Redraw);
+        assert_eq!(dashboard.selected_pid(), Some("<0.2.0>"));
+        dashboard.key(key(KeyCode::PageDown), 10);
+        assert_eq!(dashboard.selected_pid(), Some("<0.5.0>"));
+        dashboard.key(key(KeyCode::Up), 10);
+        assert_eq!(dashboard.key(key(KeyCode::Enter), 10), Action::Inspect);
+
+        dashboard.show_detail("<0.4.0>", vec![Reply::Gone]);
+        assert_eq!(dashboard.key(key(KeyCode::Down), 10), Action::None);
+        assert_eq!(dashboard.key(key(KeyCode::Esc), 10), Action::Redraw);
+        assert!(dashboard.detail.is_none());
+        assert_eq!(dashboard.key(key(KeyCode::Char('q')), 10), Action::Quit);
+        assert_eq!(dashboard.key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL), 10), Action::Quit);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut dashboard = Dashboard::new("app@host", SortKey::Reductions);
+        let mut server = process("<0.88.0>", 1200, 2048, 3);
+        server.registered = "app_server".to_string();
+        server.current = "app::server::loop/2".to_string();
+        dashboard.update(vec![Reply::Process(server), Reply::Process(process("<0.89.0>", 5, 10, 0))]);
+
+        let screen = dashboard.render(100, 10);
+        assert!(screen.lines.iter().all(|line| line.chars().count() == 100));
+        assert!(screen.lines[0].starts_with("dream top - app@host  2 processes, by reductions"));
+        assert!(screen.lines[3].starts_with("PID "));
+        assert!(screen.lines[4].starts_with("<0.88.0>         app_server"));
+        assert!(screen.lines[4].contains("app::server::loop/2"));
+        assert!(screen.lines[4].trim_end().ends_with("1200     2.0 KiB       3"));
+        assert_eq!(screen.highlight, Some(4));
+
+        // The selection scrolls into view
+        let screen = dashboard.render(100, 5);
+        assert_eq!(screen.lines.len(), 5);
+        dashboard.move_selection(1);
+        let screen = dashboard.render(100, 5);
+        assert!(screen.lines[4].starts_with("<0.89.0>"));
+
+        dashboard.show_detail(
+            "<0.88.0>",
+            vec![
+                Reply::Info("memory".to_string(), "2048".to_string()),
+                Reply::Info("initial_call".to_string(), "'dream::app::server':init/1".to_string()),
+                Reply::Frame("app::server::loop/2".to_string(), "src/server.dream:12".to_string()),
+            ],
+        );
+        let screen = dashboard.render(100, 20);
+        assert!(screen.lines[0].contains("process <0.88.0> app_server"));
+        assert_eq!(screen.lines[3].trim_end(), "  memory             2.0 KiB");
+        assert_eq!(screen.lines[4].trim_end(), "  initial call       app::server::init/1");
+        assert_eq!(screen.lines[6].trim_end(), "Stacktrace:");
+        assert!(screen.lines[7].contains("app::server::loop/2") && screen.lines[7].trim_end().ends_with("src/server.dream:12"));
+        assert_eq!(screen.highlight, None);
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("abc", 5), "abc  ");
+        assert_eq!(fit("abcdef", 4), "abc~");
+        assert_eq!(fit("abc", 0), "");
+    }
+
+    #[test]
+    fn test_sort_key_from_str() {
+        assert_eq!("memory".parse::<SortKey>(), Ok(SortKey::Memory));
+        assert!("cpu".parse::<SortKey>().is_err());
+    }
+}
@@ -0,0 +1,266 @@
+//! Live call tracing for `dream trace`.
+//!
+//! A hidden node connects to the running one and spawns a tracer there.
+//! Like `recon_trace`, the tracer sets match specs on the Dream functions a
+//! [`TracePattern`] picks out, turns call tracing on for every process and
+//! stops by itself after a number of calls, so a busy production node is
+//! never flooded. It formats each call and return value with the node's own
+//! `display` module, so they read as Dream values, and sends the lines back
+//! to be printed. If the tracing node goes away, the tracer cleans up.
+
+use crate::config::NodeOptions;
+use crate::erl::{erlang_string, quote_atom};
+
+/// Which functions to trace: `module::function`, where both parts may use
+/// `*` and `?` wildcards and the function may end in `/arity`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracePattern {
+    /// Module glob, e.g. `mymod` or `app::*`
+    pub module: String,
+    /// Function glob, e.g. `handle_*`
+    pub function: String,
+    pub arity: Option<u32>,
+}
+
+impl TracePattern {
+    /// Parse `mymod::handle_*` or `app::server::handle_call/3`.
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        let (module, function) = pattern
+            .rsplit_once("::")
+            .filter(|(module, function)| !module.is_empty() && !function.is_empty())
+            .ok_or_else(|| format!("expected `module::function` in `{}`", pattern))?;
+        let (function, arity) = match function.split_once('/') {
+            Some((function, arity)) => {
+                let arity = arity
+                    .parse()
+                    .map_err(|_| format!("invalid arity `{}` in `{}`", arity, pattern))?;
+                (function, Some(arity))
+            }
+            None => (function, None),
+        };
+        Ok(Self {
+            module: module.to_string(),
+            function: function.to_string(),
+            arity,
+        })
+    }
+
+    /// Regex matching the BEAM names of the modules. The module part may
+    /// leave out leading path segments, so `server` matches
+    /// `dream::app::server`.
+    pub fn module_regex(&self) -> String {
+        let module = self.module.strip_prefix("dream::").unwrap_or(&self.module);
+        format!("^dream::(.*::)?{}$", glob_regex(module))
+    }
+
+    pub fn function_regex(&self) -> String {
+        format!("^{}$", glob_regex(&self.function))
+    }
+}
+
+fn glob_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c if "\\^$.|+()[]{}".contains(c) => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    regex
+}
+
+/// How much to trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceOptions {
+    /// Also report return values and exceptions
+    pub returns: bool,
+    /// Stop after this many calls and returns
+    pub limit: usize,
+    /// Report at most this many each second, dropping the rest
+    pub rate: Option<usize>,
+}
+
+impl Default for TraceOptions {
+    fn default() -> Self {
+        Self {
+            returns: false,
+            limit: 100,
+            rate: None,
+        }
+    }
+}
+
+/// Distribution flags for a hidden node, named after its `role`, that
+/// connects to `target`: a short name if `target`'s host is one, a long
+/// name if it is fully qualified.
+pub fn node_options(role: &str, target: &str, cookie: Option<String>) -> NodeOptions {
+    let name = format!("dream_{}_{}", role, std::process::id());
+    let long = target.split_once('@').is_some_and(|(_, host)| host.contains('.'));
+    NodeOptions {
+        sname: (!long).then(|| name.clone()),
+        name: long.then_some(name),
+        cookie,
+    }
+}
+
+/// Erlang expression that traces calls matching `pattern` on `node`,
+/// printing one line per call until the limit is reached.
+pub fn tracer_expr(node: &str, pattern: &TracePattern, options: &TraceOptions) -> String {
+    let spec = if options.returns {
+        "[{'_', [], [{exception_trace}]}]"
+    } else {
+        "true"
+    };
+    format!(
+        "Target = {node}, \
+         Local = self(), \
+         ModuleRe = {module_re}, \
+         FunctionRe = {function_re}, \
+         Arity = {arity}, \
+         Limit = {limit}, \
+         Rate = {rate}, \
+         case net_kernel:connect_node(Target) of \
+             true -> ok; \
+             _ -> io:format(standard_error, \"Error: could not connect to ~s~n\", [Target]), halt(1) \
+         end, \
+         spawn(Target, fun() -> \
+             Name = fun(M, F) -> \
+                 Module = case atom_to_list(M) of \"dream::\" ++ Rest -> Rest; Other -> Other end, \
+                 Module ++ \"::\" ++ atom_to_list(F) \
+             end, \
+             Show = fun(Value) -> \
+                 try 'dream::display':display_any(Value) \
+                 catch _:_ -> io_lib:format(\"~tp\", [Value]) \
+                 end \
+             end, \
+             Matches = fun(Atom, Re) -> re:run(atom_to_list(Atom), Re, [unicode]) =/= nomatch end, \
+             MFAs = [{{M, F, A}} || {{M, _}} <- code:all_loaded(), Matches(M, ModuleRe), \
+                 {{F, A}} <- M:module_info(functions), Matches(F, FunctionRe), \
+                 Arity =:= any orelse Arity =:= A, \
+                 F =/= module_info, not lists:prefix(\"-\", atom_to_list(F)), \
+                 not lists:prefix(\"__\", atom_to_list(F))], \
+             Local ! {{matched, length(MFAs)}}, \
+             Stop = fun(Reason) -> \
+                 erlang:trace(all, false, [call]), \
+                 [erlang:trace_pattern(MFA, false, [local]) || MFA <- MFAs], \
+                 Local ! {{stopped, Reason}} \
+             end, \
+             Trace = fun Loop(Sent, Start, InWindow, Dropped) -> \
+                 Line = receive \
+                     {{'DOWN', _, process, Local, _}} -> stop; \
+                     {{trace, Pid, call, {{M, F, Args}}}} -> \
+                         io_lib:format(\"~w ~ts(~ts)\", [Pid, Name(M, F), lists:join(\", \", [Show(Arg) || Arg <- Args])]); \
+                     {{trace, Pid, return_from, {{M, F, _}}, Value}} -> \
+                         io_lib:format(\"~w ~ts -> ~ts\", [Pid, Name(M, F), Show(Value)]); \
+                     {{trace, Pid, exception_from, {{M, F, _}}, {{Class, Reason}}}} -> \
+                         io_lib:format(\"~w ~ts raised ~p: ~ts\", [Pid, Name(M, F), Class, Show(Reason)]); \
+                     _ -> skip \
+                 end, \
+                 Now = erlang:monotonic_time(millisecond), \
+                 {{Start1, InWindow1, Dropped1}} = case Now - Start >= 1000 of \
+                     true when Dropped > 0 -> \
+                         Local ! {{line, io_lib:format(\"(~b dropped over the rate limit)\", [Dropped])}}, \
+                         {{Now, 0, 0}}; \
+                     true -> {{Now, 0, 0}}; \
+                     false -> {{Start, InWindow, Dropped}} \
+                 end, \
+                 if \
+                     Line =:= stop -> Stop(disconnected); \
+                     Line =:= skip -> Loop(Sent, Start1, InWindow1, Dropped1); \
+                     Rate =/= none, InWindow1 >= Rate -> Loop(Sent, Start1, InWindow1, Dropped1 + 1); \
+                     Sent + 1 >= Limit -> Local ! {{line, Line}}, Stop(limit); \
+                     true -> Local ! {{line, Line}}, Loop(Sent + 1, Start1, InWindow1 + 1, Dropped1) \
+                 end \
+             end, \
+             case MFAs of \
+                 [] -> ok; \
+                 _ -> \
+                     erlang:monitor(process, Local), \
+                     [erlang:trace_pattern(MFA, {spec}, [local]) || MFA <- MFAs], \
+                     erlang:trace(all, true, [call, {{tracer, self()}}]), \
+                     Trace(0, erlang:monotonic_time(millisecond), 0, 0) \
+             end \
+         end), \
+         receive \
+             {{matched, 0}} -> \
+                 io:format(standard_error, \"Error: no function loaded on ~s matches {pattern}~n\", [Target]), \
+                 halt(1); \
+             {{matched, Count}} -> \
+                 io:format(\"Tracing ~b function~s on ~s, stopping after ~b~n\", \
+                     [Count, case Count of 1 -> \"\"; _ -> \"s\" end, Target, Limit]) \
+         after 10000 -> \
+             io:format(standard_error, \"Error: ~s did not answer~n\", [Target]), \
+             halt(1) \
+         end, \
+         Print = fun Follow() -> \
+             receive \
+                 {{line, Line}} -> io:format(\"~ts~n\", [Line]), Follow(); \
+                 {{stopped, limit}} -> io:format(\"Stopped after ~b.~n\", [Limit]), halt(0); \
+                 {{stopped, _}} -> halt(0) \
+             end \
+         end, \
+         Print().",
+        node = quote_atom(node),
+        module_re = erlang_string(&pattern.module_regex()),
+        function_re = erlang_string(&pattern.function_regex()),
+        arity = pattern.arity.map_or("any".to_string(), |a| a.to_string()),
+        limit = options.limit.max(1),
+        rate = options.rate.map_or("none".to_string(), |r| r.max(1).to_string()),
+        spec = spec,
+        pattern = format!("{}::{}", pattern.module, pattern.function).replace('~', "~~").replace('"', "\\\""),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pattern() {
+        let pattern = TracePattern::parse("mymod::handle_*").unwrap();
+        assert_eq!(pattern.module, "mymod");
+        assert_eq!(pattern.function, "handle_*");
+        assert_eq!(pattern.arity, None);
+        assert_eq!(pattern.module_regex(), "^dream::(.*::)?mymod$");
+        assert_eq!(pattern.function_regex(), "^handle_.*$");
+
+        let pattern = TracePattern::parse("dream::app::server::handle_call/3").unwrap();
+        assert_eq!(pattern.module_regex(), "^dream::(.*::)?app::server$");
+        assert_eq!(pattern.arity, Some(3));
+
+        assert!(TracePattern::parse("handle_call").is_err());
+        assert!(TracePattern::parse("mymod::").is_err());
+        assert!(TracePattern::parse("mymod::f/x").is_err());
+        assert_eq!(glob_regex("a.b?c"), "a\\.b.c");
+    }
+
+    #[test]
+    fn test_node_options() {
+        let short = node_options("trace", "app@host", None);
+        assert!(short.sname.unwrap().starts_with("dream_trace_"));
+        assert_eq!(short.name, None);
+        let long = node_options("trace", "app@host.example.com", Some("secret".to_string()));
+        assert!(long.sname.is_none() && long.name.is_some());
+        assert_eq!(long.cookie.as_deref(), Some("secret"));
+    }
+
+    #[test]
+    fn test_tracer_expr() {
+        let pattern = TracePattern::parse("mymod::handle_*").unwrap();
+        let expr = tracer_expr("app@host", &pattern, &TraceOptions::default());
+        assert!(expr.starts_with("Target = 'app@host', Local = self(), ModuleRe = \"^dream::(.*::)?mymod$\","));
+        assert!(expr.contains("Arity = any, Limit = 100, Rate = none,"));
+        assert!(expr.contains("erlang:trace_pattern(MFA, true, [local])"));
+        assert!(expr.ends_with("Print()."));
+
+        let options = TraceOptions { returns: true, limit: 5, rate: Some(10) };
+        let expr = tracer_expr("app@host", &pattern, &options);
+        assert!(expr.contains("Limit = 5, Rate = 10,"));
+        assert!(expr.contains("[{'_', [], [{exception_trace}]}]"));
+    }
+}
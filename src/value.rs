@@ -6,7 +6,7 @@ use std::hash::{Hash, Hasher};
 use num_bigint::BigInt;
 use num_traits::{ToPrimitive, Zero};
 
-use crate::Pid;
+use crate::{Pid, Ref};
 
 /// Runtime value stored in registers
 #[derive(Clone)]
@@ -20,7 +20,7 @@ pub enum Value {
     /// Process identifier
     Pid(Pid),
     /// Unique reference (for request/response correlation)
-    Ref(u64),
+    Ref(Ref),
     /// String (text data)
     String(String),
     /// Binary (raw byte array)
@@ -103,7 +103,7 @@ impl std::fmt::Debug for Value {
             Value::BigInt(n) => write!(f, "{}", n),
             Value::Float(x) => write!(f, "{}", x),
             Value::Pid(p) => write!(f, "Pid({})", p.0),
-            Value::Ref(r) => write!(f, "#Ref<{}>", r),
+            Value::Ref(r) => write!(f, "{}", r),
             Value::String(s) => write!(f, "{:?}", s),
             Value::Binary(bytes) => {
                 write!(f, "<<")?;
@@ -0,0 +1,183 @@
+//! `--watch` for `dream build`, `dream test` and `dream run`.
+//!
+//! The watcher re-runs the same `dream` command, minus `--watch`, as a
+//! child process whenever a source file changes. Running it as a child
+//! means a long-lived `dream run` can simply be killed and started again,
+//! and each rebuild goes through the normal incremental build, which only
+//! recompiles the modules affected by the change.
+
+use crate::target::BUILD_DIR;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// Flags that only concern the watcher and are dropped from the child's
+/// command line.
+const WATCH_FLAGS: &[&str] = &["--watch", "--clear"];
+
+/// How often the child is checked for having exited while waiting.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchOptions {
+    /// Quiet period after a change before re-running, so that saving
+    /// several files (or an editor's write-then-rename) triggers one run
+    pub debounce: Duration,
+    /// Clear the terminal before each run
+    pub clear: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(200),
+            clear: false,
+        }
+    }
+}
+
+/// The arguments to re-run this command with: everything after the program
+/// name, without the watcher's own flags. Arguments after `--` belong to
+/// the program being run and are kept as they are.
+pub fn child_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut passthrough = false;
+    args.into_iter()
+        .skip(1)
+        .filter(|arg| {
+            if passthrough {
+                return true;
+            }
+            if arg == "--" {
+                passthrough = true;
+                return true;
+            }
+            !WATCH_FLAGS.iter().any(|flag| arg == flag)
+        })
+        .collect()
+}
+
+/// Whether a change to `path` should trigger a re-run: Dream sources, type
+/// stubs and `dream.toml`, but nothing under `_build`.
+pub fn is_relevant(path: &Path) -> bool {
+    if path.components().any(|c| c.as_os_str() == BUILD_DIR) {
+        return false;
+    }
+    let is_source = path
+        .extension()
+        .is_some_and(|ext| ext == "dream" || ext == "dreamt");
+    is_source || path.file_name().is_some_and(|name| name == "dream.toml")
+}
+
+/// Run `command` now and again after every relevant change under `root`,
+/// killing the previous run if it is still going. Returns only if watching
+/// fails.
+pub fn watch(root: &Path, options: &WatchOptions, mut command: impl FnMut() -> Command) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(io::Error::other)?;
+    watcher
+        .watch(root, RecursiveMode::Recursive)
+        .map_err(io::Error::other)?;
+
+    loop {
+        if options.clear {
+            // Clear the screen and move the cursor home
+            print!("\x1b[2J\x1b[H");
+            let _ = io::stdout().flush();
+        }
+
+        let mut child = Some(command().spawn()?);
+        let changed = wait_for_change(&rx, &mut child, options.debounce)?;
+        if let Some(mut child) = child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+
+        println!();
+        match changed.as_slice() {
+            [path] => println!("[{} changed, restarting]", display(root, path)),
+            paths => println!("[{} files changed, restarting]", paths.len()),
+        }
+    }
+}
+
+/// Block until a relevant change arrives and the debounce period has
+/// passed without another, reporting when the child exits meanwhile.
+/// Returns the changed paths.
+fn wait_for_change(
+    rx: &Receiver<notify::Result<Event>>,
+    child: &mut Option<Child>,
+    debounce: Duration,
+) -> io::Result<Vec<PathBuf>> {
+    let mut changed: Vec<PathBuf> = Vec::new();
+    loop {
+        let timeout = if changed.is_empty() { POLL_INTERVAL } else { debounce };
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) => {
+                if matches!(event.kind, EventKind::Access(_)) {
+                    continue;
+                }
+                for path in event.paths.into_iter().filter(|p| is_relevant(p)) {
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+            }
+            Ok(Err(e)) => return Err(io::Error::other(e)),
+            Err(RecvTimeoutError::Timeout) if !changed.is_empty() => return Ok(changed),
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(status) = child.as_mut().and_then(|c| c.try_wait().ok().flatten()) {
+                    *child = None;
+                    println!();
+                    if status.success() {
+                        println!("[finished, watching for changes]");
+                    } else {
+                        println!("[failed ({}), watching for changes]", status);
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(io::Error::other("file watcher stopped"));
+            }
+        }
+    }
+}
+
+fn display(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).display().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_child_args() {
+        assert_eq!(
+            child_args(args(&["dream", "test", "--watch", "--clear", "parser::"])),
+            args(&["test", "parser::"])
+        );
+        // Everything after `--` belongs to the program
+        assert_eq!(
+            child_args(args(&["dream", "run", "--watch", "--", "--watch"])),
+            args(&["run", "--", "--watch"])
+        );
+    }
+
+    #[test]
+    fn test_is_relevant() {
+        assert!(is_relevant(Path::new("/app/src/main.dream")));
+        assert!(is_relevant(Path::new("/app/src/ffi.dreamt")));
+        assert!(is_relevant(Path::new("/app/dream.toml")));
+        assert!(!is_relevant(Path::new("/app/_build/bindings/x.dreamt")));
+        assert!(!is_relevant(Path::new("/app/src/main.dream.swp")));
+        assert!(!is_relevant(Path::new("/app/README.md")));
+    }
+}